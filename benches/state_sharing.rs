@@ -0,0 +1,94 @@
+//! Handing pool states from the registry to compute tasks: cloning every `Box<dyn ProtocolSim>`
+//! into the registry and again into each task's snapshot, as the binary used to, against moving
+//! them into `Arc`s once (`hub::BlockView::apply`) and sharing those.
+//!
+//! ```sh
+//! cargo bench --bench state_sharing
+//! ```
+//!
+//! The states are Uniswap v2, the cheapest to clone; a VM pool's clone copies its storage and
+//! bytecode, so the gap only widens for them.
+use std::{
+    collections::HashMap,
+    hint::black_box,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use alloy_primitives::U256;
+use liquidity_depth_cli::hub::BlockView;
+use tycho_simulation::{
+    evm::protocol::uniswap_v2::state::UniswapV2State,
+    protocol::{models::BlockUpdate, state::ProtocolSim},
+};
+
+/// Pools updated every block.
+const POOLS: usize = 2_000;
+/// Blocks per run.
+const BLOCKS: usize = 50;
+/// Compute tasks taking a snapshot of every state per block, e.g. both directions of a pair and a
+/// cross-check.
+const READERS: usize = 3;
+
+fn main() {
+    // Compile-checked by `cargo test`, which runs bench targets with no arguments; only run the
+    // timings under `cargo bench`.
+    if !std::env::args().any(|arg| arg == "--bench") {
+        return;
+    }
+
+    let cloned: Duration = run_cloned(blocks());
+    let shared: Duration = run_shared(blocks());
+    let per_block = |elapsed: Duration| elapsed.as_secs_f64() * 1e6 / BLOCKS as f64;
+    println!("{} pools, {} blocks, {} readers", POOLS, BLOCKS, READERS);
+    println!("cloned  {:>10.1} µs/block  {:>6} clones/block", per_block(cloned), POOLS * (1 + READERS));
+    println!("shared  {:>10.1} µs/block  {:>6} clones/block", per_block(shared), 0);
+    println!("shared takes {:.1}x less time", cloned.as_secs_f64() / shared.as_secs_f64());
+}
+
+/// A function to build the blocks of a run up front, so both strategies time only the handoff.
+fn blocks() -> Vec<BlockUpdate> {
+    (0..BLOCKS)
+        .map(|block| {
+            let states: HashMap<String, Box<dyn ProtocolSim>> = (0..POOLS)
+                .map(|pool| {
+                    let reserve: U256 = U256::from((block * POOLS + pool + 1) as u64);
+                    let state: Box<dyn ProtocolSim> = Box::new(UniswapV2State::new(reserve, reserve));
+                    (format!("0x{:040x}", pool), state)
+                })
+                .collect();
+            BlockUpdate { block_number: block as u64, states, new_pairs: HashMap::new(), removed_pairs: HashMap::new() }
+        })
+        .collect()
+}
+
+/// A function to time the old path: clone each update into the registry, then clone the registry
+/// into every reader's snapshot.
+fn run_cloned(blocks: Vec<BlockUpdate>) -> Duration {
+    let started: Instant = Instant::now();
+    let mut registry: HashMap<String, Box<dyn ProtocolSim>> = HashMap::new();
+    for block in blocks.iter() {
+        for (id, state) in block.states.iter() {
+            registry.insert(id.clone(), state.clone());
+        }
+        for _ in 0..READERS {
+            let snapshot: Vec<Box<dyn ProtocolSim>> = registry.values().map(|state| state.clone_box()).collect();
+            black_box(snapshot);
+        }
+    }
+    started.elapsed()
+}
+
+/// A function to time the shared path: move each update into the block view, then hand every
+/// reader the same `Arc`s.
+fn run_shared(blocks: Vec<BlockUpdate>) -> Duration {
+    let started: Instant = Instant::now();
+    let mut view: BlockView = BlockView::default();
+    for block in blocks.into_iter() {
+        view.apply(block);
+        for _ in 0..READERS {
+            let snapshot: Vec<Arc<dyn ProtocolSim>> = view.states.values().cloned().collect();
+            black_box(snapshot);
+        }
+    }
+    started.elapsed()
+}
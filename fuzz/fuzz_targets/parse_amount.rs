@@ -0,0 +1,26 @@
+#![no_main]
+
+use alloy_primitives::{utils::format_units, U256};
+use libfuzzer_sys::fuzz_target;
+use liquidity_depth_cli::amount::parse_amount;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&decimals, rest)) = data.split_first() else {
+        return;
+    };
+    // Real tokens stay well under 77 decimals, the most a U256 can hold a whole unit of.
+    let decimals: u8 = decimals % 78;
+    let Ok(input) = std::str::from_utf8(rest) else {
+        return;
+    };
+
+    // Must never panic, whatever the input.
+    let Ok(amount) = parse_amount(input, decimals) else {
+        return;
+    };
+
+    // Anything that parses must survive a round trip through the formatter.
+    let formatted: String = format_units(amount, decimals).expect("decimals < 78");
+    let reparsed: U256 = parse_amount(&formatted, decimals).expect("formatted amount must parse");
+    assert_eq!(amount, reparsed, "input {:?} formatted {:?}", input, formatted);
+});
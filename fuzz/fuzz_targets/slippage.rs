@@ -0,0 +1,67 @@
+#![no_main]
+
+use alloy_primitives::U256;
+use libfuzzer_sys::fuzz_target;
+use liquidity_depth_cli::binary_search::{
+    calc_slippage, check_slippage_under_target, check_slippage_vs_target_within_tolerance, Slippage,
+};
+use liquidity_depth_cli::error::DepthError;
+use num_rational::BigRational;
+
+/// How far apart two slippages must be, as f64s, for the f64 comparison to be trusted.
+const MARGIN: f64 = 1e-9;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 90 {
+        return;
+    }
+    let amount_in: U256 = U256::from_be_slice(&data[0..32]);
+    let amount_out: U256 = U256::from_be_slice(&data[32..64]);
+    let target: f64 = f64::from_be_bytes(data[64..72].try_into().unwrap());
+    let precision: f64 = f64::from_be_bytes(data[72..80].try_into().unwrap());
+    let spot_price: f64 = f64::from_be_bytes(data[80..88].try_into().unwrap());
+    let (decimals_in, decimals_out): (usize, usize) = (data[88] as usize % 37, data[89] as usize % 37);
+
+    // calc_slippage is a fraction of the output at spot, so never over 100% (an improvement can
+    // be any size), and the ratios never overflow.
+    let slippage: Slippage = match calc_slippage(amount_in, amount_out, decimals_in, decimals_out, spot_price) {
+        Ok(slippage) => {
+            assert!(*slippage.ratio() <= BigRational::from_integer(1.into()));
+            slippage
+        }
+        Err(DepthError::InvalidSpotPrice(_)) => {
+            assert!(!(spot_price.is_finite() && spot_price > 0.0));
+            return;
+        }
+        Err(e) => panic!("unexpected error {}", e),
+    };
+    let value: f64 = slippage.to_f64();
+
+    // Under-target: a target that doesn't convert has nothing under it, and an improvement is under
+    // the rest. Otherwise the exact answer agrees with f64 wherever f64 can tell them apart.
+    let under: bool = check_slippage_under_target(&slippage, target);
+    match Slippage::try_from(target) {
+        Err(_) => assert!(!under),
+        Ok(exact) => {
+            assert_eq!(under, slippage <= exact);
+            if slippage.is_improvement() {
+                assert!(under);
+            }
+            if (value - target).abs() > MARGIN * target.abs().max(1.0) {
+                assert_eq!(under, value <= target, "slippage {:?} target {}", slippage, target);
+            }
+        }
+    }
+
+    // Within tolerance: agrees with f64 wherever the distance is clearly on one side of it.
+    match check_slippage_vs_target_within_tolerance(&slippage, target, precision) {
+        Ok(within) => {
+            let distance: f64 = (value - target).abs();
+            if (distance - precision).abs() > MARGIN * precision.max(distance).max(1.0) {
+                assert_eq!(within, distance <= precision, "slippage {:?} target {} precision {}", slippage, target, precision);
+            }
+        }
+        Err(DepthError::InvalidSlippage(_)) => {}
+        Err(e) => panic!("unexpected error {}", e),
+    }
+});
@@ -0,0 +1,429 @@
+use alloy_primitives::U256;
+use serde::Serialize;
+use tycho_simulation::models::Token;
+use tracing::{debug, info, warn};
+use crate::{
+    amount::one_token,
+    binary_search::{
+        check_slippage_under_target, check_slippage_vs_target_within_tolerance, Simulator, Slippage,
+        DEFAULT_BRACKET_TVL_MULTIPLE, DEFAULT_MAX_ITERATIONS, MAX_BRACKETING_DOUBLINGS,
+    },
+    error::DepthError,
+    provider::{AmountOutProvider, Pool},
+};
+
+/// How many pieces an order is cut into when splitting it across pools.
+pub const DEFAULT_SPLIT_CHUNKS: u32 = 50;
+
+/// How many times the greedy split is refined at half the step before, so the final split is
+/// within `1 / (chunks * 2^REFINE_HALVINGS)` of the order of the optimum rather than a whole chunk.
+const REFINE_HALVINGS: u32 = 6;
+
+/// The most amounts moved between pools at any one step, a backstop; each move strictly adds
+/// output, so refining ends well before this on any concave curve.
+const MAX_REFINE_MOVES: u32 = 64;
+
+/// The share of a split order routed to one pool.
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolAllocation {
+    pub pool_id: String,
+    /// In token_in base units.
+    pub amount_in: String,
+    /// In token_out base units.
+    pub amount_out: String,
+}
+
+/// Depth of a pair across all of its pools, with the order split between them.
+#[derive(Debug, Clone)]
+pub struct AggregateDepth {
+    /// The largest total amount of token_in found whose split slippage is within tolerance of the
+    /// target, or the order as given to `aggregate_fill_against_reference`.
+    pub amount_in: U256,
+    /// What the split order returns in total.
+    pub amount_out: U256,
+    /// The slippage of the whole split order relative to the reference price.
+    pub slippage: Slippage,
+    /// How `amount_in` is split. Pools that get nothing are left out.
+    pub allocations: Vec<PoolAllocation>,
+    /// How many `get_amount_out` calls the search used, across all pools.
+    pub simulations: u32,
+    /// Whether the split order's slippage landed within the precision of the target. False if
+    /// the search ran out of iterations or the bracket collapsed first, in which case `amount_in`
+    /// is the largest amount found under the target. Always true for a fixed-size fill.
+    pub converged: bool,
+    /// How many bisection steps the search took after bracketing, zero for a fixed-size fill.
+    pub iterations: u32,
+}
+
+/// A function to find the best spot price of token_in in token_out among a pair's pools.
+///
+/// Args:
+/// - pools: (id, pool) for every pool of the pair, Tycho states or any other `Pool`
+/// - token_in: The token being sold
+/// - token_out: The token being bought
+///
+/// Returns:
+/// - The highest spot price among the pools that returned one. Pools that fail are logged and
+///   skipped; the error is only returned if none of them priced
+pub fn best_spot_price<'a, P: Into<Pool<'a>> + Copy>(
+    pools: &[(String, P)],
+    token_in: &Token,
+    token_out: &Token,
+) -> Result<f64, DepthError> {
+    let mut best: Option<f64> = None;
+    let mut last_error: Option<DepthError> = None;
+    for (id, pool) in pools.iter() {
+        match (*pool).into().spot(token_in, token_out) {
+            Ok(spot) => best = Some(best.map_or(spot, |b: f64| b.max(spot))),
+            Err(e) => {
+                warn!("{}: no spot price, leaving it out of the best price: {}", id, e);
+                last_error = Some(match e {
+                    DepthError::Simulation(reason) => DepthError::Simulation(format!("{}: {}", id, reason)),
+                    e => e,
+                });
+            }
+        }
+    }
+    best.ok_or(last_error.unwrap_or(DepthError::InvalidSpotPrice(0.0)))
+}
+
+/// A function to calculate total market depth at the target slippage across every pool of a pair.
+///
+/// Slippage is measured against the best spot price among the pools, so the result answers "how
+/// much can I sell before my average price is X% worse than the best quote in the market".
+///
+/// Args:
+/// - pools: (id, pool) for every pool of the pair, Tycho states or any other `Pool`
+/// - target_slippage: The slippage tolerance, as a decimal (e.g., 2% slippage = 0.02)
+/// - precision: How close to the target counts as exact, as a decimal
+/// - token_in: The token being sold
+/// - token_out: The token being bought
+/// - chunks: How finely orders are split, see `split_order`
+///
+/// Returns:
+/// - The total depth and how it's split across pools, searched with the default iteration cap and
+///   bracket cap, see `aggregate_depth_against_reference`
+pub fn aggregate_depth<'a, P: Into<Pool<'a>> + Copy>(
+    pools: &[(String, P)],
+    target_slippage: f64,
+    precision: f64,
+    token_in: &Token,
+    token_out: &Token,
+    chunks: u32,
+) -> Result<AggregateDepth, DepthError> {
+    let reference_price: f64 = best_spot_price(pools, token_in, token_out)?;
+    aggregate_depth_against_reference(
+        pools,
+        target_slippage,
+        precision,
+        token_in,
+        token_out,
+        chunks,
+        reference_price,
+        DEFAULT_MAX_ITERATIONS,
+        DEFAULT_BRACKET_TVL_MULTIPLE,
+    )
+}
+
+/// Like `aggregate_depth`, but against a caller-supplied reference price, e.g. a scenario-shifted
+/// best spot, and with the search bounded like a single pool's.
+///
+/// Args:
+/// - max_iterations: How many bisection steps to take before settling for the best amount under
+///   the target, see `DepthCalculator::with_max_iterations`
+/// - bracket_tvl_multiple: Bracketing stops at the sum of the pools' `bracket_cap`s
+///
+/// Returns:
+/// - The total depth and how it's split, `DepthError::InsufficientLiquidity` if the split order is
+///   still under the target at the pools' combined cap, or `DepthError::NotConverged` if
+///   bracketing never got over it
+#[allow(clippy::too_many_arguments)]
+pub fn aggregate_depth_against_reference<'a, P: Into<Pool<'a>> + Copy>(
+    pools: &[(String, P)],
+    target_slippage: f64,
+    precision: f64,
+    token_in: &Token,
+    token_out: &Token,
+    chunks: u32,
+    reference_price: f64,
+    max_iterations: u32,
+    bracket_tvl_multiple: f64,
+) -> Result<AggregateDepth, DepthError> {
+    let mut sims: Vec<Simulator> = pools
+        .iter()
+        .map(|(_, pool)| {
+            let pool: Pool = (*pool).into();
+            Simulator::new(pool, token_in, token_out, reference_price)
+        })
+        .collect::<Result<_, _>>()?;
+    if sims.is_empty() {
+        return Err(DepthError::Simulation("no pools to aggregate".to_string()));
+    }
+    // Uncapped if any pool is, since that pool alone could take the rest of the order.
+    let max_in: Option<U256> = pools.iter().try_fold(U256::ZERO, |sum, (_, pool)| {
+        let pool: Pool = (*pool).into();
+        pool.max_in(token_in, token_out, reference_price, bracket_tvl_multiple).map(|cap| sum.saturating_add(cap))
+    });
+    let simulations = |sims: &[Simulator]| -> u32 { sims.iter().map(|sim| sim.simulations()).sum() };
+
+    // Same shape as the single-pool search: double until over the target, then bisect.
+    let mut left: U256 = U256::ZERO;
+    let mut best: Option<(U256, Vec<U256>)> = None;
+    let one: U256 = one_token(token_in.decimals).ok_or(DepthError::UnsupportedDecimals(token_in.decimals))?;
+    let mut try_in: U256 = max_in.map_or(one, |max_in| one.min(max_in));
+    let mut doublings: u32 = 0;
+    let mut right: U256 = loop {
+        let (split, amount_out, slippage) = match split_and_price(&mut sims, try_in, chunks) {
+            Ok(priced) => priced,
+            // Past what the pools will fill between them; bisect under it.
+            Err(DepthError::Simulation(reason)) if !left.is_zero() => {
+                debug!("aggregate bracketing: try_in {} refused: {}", try_in, reason);
+                break try_in;
+            }
+            Err(e) => return Err(e),
+        };
+        debug!("aggregate bracketing: try_in {} try_out {} slippage {:?}", try_in, amount_out, slippage);
+        if check_slippage_vs_target_within_tolerance(&slippage, target_slippage, precision)? {
+            return finish(pools, &mut sims, try_in, split, chunks, true, 0);
+        }
+        if !check_slippage_under_target(&slippage, target_slippage) {
+            break try_in;
+        }
+        best = Some((try_in, split));
+        left = try_in;
+        doublings += 1;
+        if doublings > MAX_BRACKETING_DOUBLINGS {
+            return Err(DepthError::NotConverged { target_slippage, simulations: simulations(&sims) });
+        }
+        let doubled: U256 = try_in.checked_mul(U256::from(2u64)).ok_or(DepthError::Overflow)?;
+        try_in = match max_in {
+            Some(max_in) if try_in >= max_in => return Err(DepthError::InsufficientLiquidity { target_slippage, max_in }),
+            Some(max_in) => doubled.min(max_in),
+            None => doubled,
+        };
+    };
+
+    let mut iterations: u32 = 0;
+    while iterations < max_iterations && right - left > U256::from(1u64) {
+        try_in = (left + right) / U256::from(2u64);
+        iterations += 1;
+        let (split, amount_out, slippage) = match split_and_price(&mut sims, try_in, chunks) {
+            Ok(priced) => priced,
+            Err(DepthError::Simulation(reason)) if !left.is_zero() => {
+                debug!("aggregate bisecting: try_in {} refused: {}", try_in, reason);
+                right = try_in;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        debug!("aggregate bisecting: try_in {} try_out {} slippage {:?}", try_in, amount_out, slippage);
+        if check_slippage_vs_target_within_tolerance(&slippage, target_slippage, precision)? {
+            return finish(pools, &mut sims, try_in, split, chunks, true, iterations);
+        }
+        if check_slippage_under_target(&slippage, target_slippage) {
+            best = Some((try_in, split));
+            left = try_in;
+        } else {
+            right = try_in;
+        }
+    }
+    info!("aggregate: no convergence at {} slippage after {} iterations", target_slippage, iterations);
+
+    let (amount_in, split): (U256, Vec<U256>) = best.unwrap_or((U256::ZERO, vec![U256::ZERO; pools.len()]));
+    finish(pools, &mut sims, amount_in, split, chunks, false, iterations)
+}
+
+/// A function to price one fixed-size order split optimally across every pool of a pair, the
+/// aggregate counterpart of `calculate_slippage_for_amount`.
+///
+/// Args:
+/// - pools: (id, pool) for every pool of the pair, Tycho states or any other `Pool`
+/// - amount_in: The order, in token_in base units
+/// - token_in: The token being sold
+/// - token_out: The token being bought
+/// - chunks: How finely the order is split, see `split_order`
+/// - reference_price: What the order is priced against, e.g. the best spot
+///
+/// Returns:
+/// - The order's total output and slippage, and how it's split
+pub fn aggregate_fill_against_reference<'a, P: Into<Pool<'a>> + Copy>(
+    pools: &[(String, P)],
+    amount_in: U256,
+    token_in: &Token,
+    token_out: &Token,
+    chunks: u32,
+    reference_price: f64,
+) -> Result<AggregateDepth, DepthError> {
+    let mut sims: Vec<Simulator> = pools
+        .iter()
+        .map(|(_, pool)| {
+            let pool: Pool = (*pool).into();
+            Simulator::new(pool, token_in, token_out, reference_price)
+        })
+        .collect::<Result<_, _>>()?;
+    if sims.is_empty() {
+        return Err(DepthError::Simulation("no pools to aggregate".to_string()));
+    }
+    let (split, _) = split_order(&mut sims, amount_in, chunks)?;
+    finish(pools, &mut sims, amount_in, split, chunks, true, 0)
+}
+
+/// A function to split an order across pools greedily: the order is cut into `chunks` equal
+/// pieces and each piece goes to the pool that returns the most for it given what it already has.
+///
+/// For pools whose output is concave in the input (every AMM curve), this converges on the optimal
+/// split as `chunks` grows, because it keeps marginal prices across pools equal to within a chunk.
+/// That's close enough to steer the search; `finish` refines the split it settles on with
+/// `refine_split`, once, so a coarse `chunks` still reports close to the optimum.
+///
+/// Returns:
+/// - The amount routed to each pool (in the order of `sims`) and the total output
+fn split_order(sims: &mut [Simulator], amount_in: U256, chunks: u32) -> Result<(Vec<U256>, U256), DepthError> {
+    let chunks: U256 = U256::from(chunks.max(1));
+    let chunk: U256 = (amount_in / chunks).max(U256::from(1u64));
+    let mut allocated: Vec<U256> = vec![U256::ZERO; sims.len()];
+    let mut outputs: Vec<U256> = vec![U256::ZERO; sims.len()];
+    let mut remaining: U256 = amount_in;
+
+    while !remaining.is_zero() {
+        // The last piece takes the rounding remainder.
+        let piece: U256 = if remaining < chunk * U256::from(2u64) { remaining } else { chunk };
+        let mut pick: Option<(usize, U256, U256)> = None;
+        for (i, sim) in sims.iter_mut().enumerate() {
+            // A pool that can't fill more (e.g. past its limits) just stops taking pieces.
+            let Ok((out, _)) = sim.simulate(allocated[i] + piece) else {
+                continue;
+            };
+            let gain: U256 = out.saturating_sub(outputs[i]);
+            if pick.is_none_or(|(_, _, best_gain)| gain > best_gain) {
+                pick = Some((i, out, gain));
+            }
+        }
+        let (i, out, _) = pick.ok_or_else(|| {
+            DepthError::Simulation(format!("no pool can fill {} more of the order", remaining))
+        })?;
+        allocated[i] += piece;
+        outputs[i] = out;
+        remaining -= piece;
+    }
+
+    let total_out: U256 = outputs.iter().fold(U256::ZERO, |acc, out| acc + *out);
+    Ok((allocated, total_out))
+}
+
+/// A function to improve a split by moving amounts between pools: at each step size, starting
+/// from half a chunk, the amount goes from the pool that loses least by giving it up to the one
+/// that gains most by taking it, for as long as that adds output; then the step is halved.
+///
+/// This equalizes marginal prices to within the last step, the optimality condition for a split
+/// across concave curves. A move a pool can't simulate is simply not made.
+///
+/// Args:
+/// - sims: One simulator per pool
+/// - allocated: The amount routed to each pool, updated in place
+/// - outputs: What each pool returns for its amount, updated in place
+/// - chunk: The greedy split's piece size, where the steps start
+fn refine_split(sims: &mut [Simulator], allocated: &mut [U256], outputs: &mut [U256], chunk: U256) {
+    if sims.len() < 2 {
+        return;
+    }
+    let mut step: U256 = chunk / U256::from(2u64);
+    for _ in 0..REFINE_HALVINGS {
+        if step.is_zero() {
+            break;
+        }
+        for _ in 0..MAX_REFINE_MOVES {
+            // The cheapest pool to take `step` from and the best one to give it to.
+            let mut give: Option<(usize, U256, U256)> = None;
+            let mut take: Option<(usize, U256, U256)> = None;
+            for (i, sim) in sims.iter_mut().enumerate() {
+                if allocated[i] >= step {
+                    let less: U256 = allocated[i] - step;
+                    let out: Option<U256> =
+                        if less.is_zero() { Some(U256::ZERO) } else { sim.simulate(less).ok().map(|(out, _)| out) };
+                    if let Some(out) = out {
+                        let loss: U256 = outputs[i].saturating_sub(out);
+                        if give.is_none_or(|(_, _, least)| loss < least) {
+                            give = Some((i, out, loss));
+                        }
+                    }
+                }
+                if let Ok((out, _)) = sim.simulate(allocated[i] + step) {
+                    let gain: U256 = out.saturating_sub(outputs[i]);
+                    if take.is_none_or(|(_, _, most)| gain > most) {
+                        take = Some((i, out, gain));
+                    }
+                }
+            }
+            match (give, take) {
+                (Some((from, from_out, loss)), Some((to, to_out, gain))) if from != to && gain > loss => {
+                    allocated[from] -= step;
+                    outputs[from] = from_out;
+                    allocated[to] += step;
+                    outputs[to] = to_out;
+                }
+                _ => break,
+            }
+        }
+        step /= U256::from(2u64);
+    }
+}
+
+fn split_and_price(
+    sims: &mut [Simulator],
+    amount_in: U256,
+    chunks: u32,
+) -> Result<(Vec<U256>, U256, Slippage), DepthError> {
+    let (split, amount_out) = split_order(sims, amount_in, chunks)?;
+    let slippage: Slippage = sims[0].price(amount_in, amount_out)?;
+    Ok((split, amount_out, slippage))
+}
+
+/// A function to refine the split a search or fill settled on, see `refine_split`, and price it.
+///
+/// Refining only adds output, so the reported slippage is at most the greedy split's.
+fn finish<P>(
+    pools: &[(String, P)],
+    sims: &mut [Simulator],
+    amount_in: U256,
+    mut split: Vec<U256>,
+    chunks: u32,
+    converged: bool,
+    iterations: u32,
+) -> Result<AggregateDepth, DepthError> {
+    let mut outputs: Vec<U256> = split
+        .iter()
+        .zip(sims.iter())
+        .map(|(pool_in, sim)| sim.evaluated_output(*pool_in).unwrap_or(U256::ZERO))
+        .collect();
+    let chunk: U256 = (amount_in / U256::from(chunks.max(1))).max(U256::from(1u64));
+    if !amount_in.is_zero() {
+        refine_split(sims, &mut split, &mut outputs, chunk);
+    }
+    let amount_out: U256 = outputs.iter().fold(U256::ZERO, |acc, out| acc + *out);
+    let slippage: Slippage = if amount_in.is_zero() {
+        Slippage::new(U256::ZERO, U256::from(1u64))
+    } else {
+        sims[0].price(amount_in, amount_out)?
+    };
+    let allocations: Vec<PoolAllocation> = pools
+        .iter()
+        .zip(sims.iter())
+        .zip(split)
+        .filter(|(_, pool_in)| !pool_in.is_zero())
+        .map(|(((id, _), sim), pool_in)| PoolAllocation {
+            pool_id: id.clone(),
+            amount_in: pool_in.to_string(),
+            amount_out: sim.evaluated_output(pool_in).unwrap_or(U256::ZERO).to_string(),
+        })
+        .collect();
+    Ok(AggregateDepth {
+        amount_in,
+        amount_out,
+        slippage,
+        allocations,
+        simulations: sims.iter().map(|sim| sim.simulations()).sum(),
+        converged,
+        iterations,
+    })
+}
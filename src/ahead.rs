@@ -0,0 +1,334 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+use alloy_primitives::U256;
+use tokio::sync::mpsc;
+use tycho_common::Bytes;
+use tycho_simulation::{models::Token, protocol::state::ProtocolSim};
+use crate::{
+    measure::{measure_pool, BlockContext, MeasuredAhead, PoolInputs, PoolJob, PoolOutcome},
+    native::{native_token_price, NativePrice},
+    parallel::{map_limited, ConcurrencyLimits},
+    schedule::SampleSchedule,
+    stream::StreamBlock,
+    usd::token_usd_price,
+};
+
+/// A block read off the stream ahead of the loop under `backfill --parallel-blocks`, with its
+/// changed states already taken out and shared, so the blocks measured before it can see them.
+pub type AheadBlock = (StreamBlock, HashMap<String, Arc<dyn ProtocolSim>>);
+
+/// Pools measured ahead, by block and then (pair index, pool id).
+pub type MeasuredBlocks = HashMap<u64, HashMap<(usize, String), MeasuredAhead>>;
+
+/// A function to take the loop's next block: the oldest one read ahead, or else the next off the stream.
+pub async fn next_block(
+    ahead: &mut VecDeque<AheadBlock>,
+    block_rx: &mut mpsc::Receiver<StreamBlock>,
+) -> Option<AheadBlock> {
+    match ahead.pop_front() {
+        Some(block) => Some(block),
+        None => block_rx.recv().await.map(|block| (block, HashMap::new())),
+    }
+}
+
+/// A function to set a block read off the stream aside until the loop gets to it, its changed
+/// states shared so the blocks before it can be measured alongside it.
+///
+/// Args:
+/// - block: The block as streamed
+/// - pools: With `--pools`, the only pools measured, lowercased
+pub fn read_ahead(mut block: StreamBlock, pools: &[String]) -> AheadBlock {
+    if !pools.is_empty() {
+        block.update.new_pairs.retain(|id, _| pools.contains(&id.to_lowercase()));
+        block.update.states.retain(|id, _| pools.contains(&id.to_lowercase()));
+    }
+    let states: HashMap<String, Arc<dyn ProtocolSim>> =
+        std::mem::take(&mut block.update.states).into_iter().map(|(id, state)| (id, Arc::from(state))).collect();
+    (block, states)
+}
+
+/// One block measured ahead of its turn, with what its pools are measured with.
+struct BlockAhead {
+    block_number: u64,
+    /// (pair index, pool id, state), as the block will track them.
+    pools: Vec<(usize, String, Arc<dyn ProtocolSim>)>,
+    notional_amounts: Vec<Vec<Result<U256, String>>>,
+    base_usd: Vec<Option<f64>>,
+}
+
+/// A function to measure a block's pools together with those of the due blocks read ahead of it,
+/// for `backfill --parallel-blocks`. Each block sees its own copy of the pool states: the tracked
+/// ones with every update up to that block applied.
+///
+/// Pairs priced by a feed are left to the block loop, which fetches their prices block by block,
+/// as are pools first announced ahead. Reading ahead stops at a resync snapshot.
+///
+/// Args:
+/// - context: What the current block is measured with; the others differ in number and prices
+/// - tracked_pairs: The tracked pools' tokens as of the current block
+/// - tracked_states: The tracked pools' states as of the current block
+/// - ahead: The blocks read ahead, oldest first
+/// - schedule: Which of them are measured
+/// - usd: With --usd, the native and stablecoin addresses that price token_in
+/// - workers: The most pools measured at once, across all the blocks
+/// - concurrency: Per-protocol limits on simulations at once
+///
+/// Returns:
+/// - The measured pools by block and then (pair index, pool id); every block measured has an entry
+#[allow(clippy::too_many_arguments)]
+pub fn measure_ahead(
+    context: &BlockContext,
+    tracked_pairs: &HashMap<String, Vec<Token>>,
+    tracked_states: &HashMap<String, Arc<dyn ProtocolSim>>,
+    ahead: &VecDeque<AheadBlock>,
+    schedule: &SampleSchedule,
+    usd: Option<(&[Bytes], &[Bytes])>,
+    workers: usize,
+    concurrency: &ConcurrencyLimits,
+) -> MeasuredBlocks {
+    type States = HashMap<String, Arc<dyn ProtocolSim>>;
+    let block_ahead = |block_number: u64, tokens: &HashMap<String, Vec<Token>>, states: &States| {
+        let priced_pools: Vec<(&str, &[Token], &dyn ProtocolSim)> = tokens
+            .iter()
+            .filter_map(|(id, tokens)| states.get(id).map(|state| (id.as_str(), tokens.as_slice(), state.as_ref())))
+            .collect();
+        let native_price: Option<NativePrice> = usd.and_then(|(natives, stables)| {
+            native_token_price(priced_pools.iter().copied(), natives, stables)
+        });
+        let mut block: BlockAhead =
+            BlockAhead { block_number, pools: Vec::new(), notional_amounts: Vec::new(), base_usd: Vec::new() };
+        for (i, pair) in context.pairs.iter().enumerate() {
+            let usd_price: Option<f64> = usd.and_then(|(natives, stables)| {
+                token_usd_price(&pair.token_in, &priced_pools, natives, stables, native_price.as_ref())
+                    .map(|price| price.price)
+            });
+            block.notional_amounts.push(
+                context.notionals.iter().map(|notional| notional.amount_in(&pair.token_in, usd_price)).collect(),
+            );
+            block.base_usd.push(usd_price);
+            if pair.oracle.is_some() || pair.usd_oracle.is_some() {
+                continue;
+            }
+            let mut ids: Vec<&String> = tokens
+                .iter()
+                .filter(|(id, tokens)| *tokens == &pair.tokens && context.pool_protocols.contains_key(*id))
+                .map(|(id, _)| id)
+                .collect();
+            ids.sort_unstable();
+            for id in ids.into_iter() {
+                if let Some(state) = states.get(id) {
+                    block.pools.push((i, id.clone(), state.clone()));
+                }
+            }
+        }
+        block
+    };
+
+    let mut tokens: HashMap<String, Vec<Token>> = tracked_pairs.clone();
+    let mut states: States = tracked_states.clone();
+    let mut blocks: Vec<BlockAhead> = vec![block_ahead(context.block_number, &tokens, &states)];
+    for (next, arrived) in ahead.iter() {
+        // A resync drops pools the snapshot lacks, which the loop sorts out when it gets there.
+        if next.snapshot {
+            break;
+        }
+        for (id, pool) in next.update.new_pairs.iter() {
+            tokens.insert(id.clone(), pool.tokens.clone());
+        }
+        for id in next.update.removed_pairs.keys() {
+            tokens.remove(id);
+        }
+        states.extend(arrived.iter().map(|(id, state)| (id.clone(), state.clone())));
+        if schedule.is_due(next.update.block_number) {
+            blocks.push(block_ahead(next.update.block_number, &tokens, &states));
+        }
+    }
+
+    let no_marks: Vec<Option<f64>> = vec![None; context.pairs.len()];
+    let contexts: Vec<BlockContext> = blocks
+        .iter()
+        .map(|block| BlockContext {
+            block_number: block.block_number,
+            notional_amounts: &block.notional_amounts,
+            base_usd: &block.base_usd,
+            marks: &no_marks,
+            ..*context
+        })
+        .collect();
+    let jobs: Vec<(usize, PoolJob, &Arc<dyn ProtocolSim>)> = blocks
+        .iter()
+        .enumerate()
+        .flat_map(|(b, block)| {
+            block.pools.iter().map(move |(pair, id, state)| {
+                (b, PoolJob { pair: *pair, id, state: state.as_ref(), vm_state: None, replay: None }, state)
+            })
+        })
+        .collect();
+    let outcomes: Vec<anyhow::Result<PoolOutcome>> = map_limited(
+        &jobs,
+        workers,
+        |(_, job, _)| context.pool_protocols.get(job.id).and_then(|protocol| concurrency.get(protocol)),
+        |(b, job, _)| measure_pool(&contexts[*b], job).map(PoolOutcome::tag_protocol_owned),
+    );
+
+    let mut measured: MeasuredBlocks = blocks.iter().map(|block| (block.block_number, HashMap::new())).collect();
+    for ((b, job, state), outcome) in jobs.iter().zip(outcomes) {
+        // Measured again when its turn comes, so the error is reported in order.
+        let Ok(outcome) = outcome else { continue };
+        let block: &BlockAhead = &blocks[*b];
+        let inputs: PoolInputs = PoolInputs {
+            mark: None,
+            base_usd: block.base_usd[job.pair],
+            notional_amounts: block.notional_amounts[job.pair].clone(),
+        };
+        measured
+            .entry(block.block_number)
+            .or_default()
+            .insert((job.pair, job.id.clone()), MeasuredAhead { state: Arc::clone(state), inputs, outcome });
+    }
+    measured
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, time::SystemTime};
+    use num_bigint::BigUint;
+    use tycho_simulation::{
+        evm::protocol::uniswap_v2::state::UniswapV2State,
+        protocol::models::BlockUpdate,
+    };
+    use crate::{
+        binary_search::WarmStarts,
+        console::PlainRenderer,
+        display::DisplayNames,
+        measure::{MeasureSettings, TrackedPair},
+    };
+    use super::*;
+
+    const POOL: &str = "0x0000000000000000000000000000000000000abc";
+
+    fn block(block_number: u64, pools: &[&str]) -> StreamBlock {
+        let states: HashMap<String, Box<dyn ProtocolSim>> = pools
+            .iter()
+            .map(|id| {
+                let state: Box<dyn ProtocolSim> = Box::new(UniswapV2State::new(U256::from(1_000u64), U256::from(2_000u64)));
+                (id.to_string(), state)
+            })
+            .collect();
+        StreamBlock {
+            snapshot: false,
+            failover: None,
+            timestamp: SystemTime::UNIX_EPOCH,
+            update: BlockUpdate { block_number, states, new_pairs: HashMap::new(), removed_pairs: HashMap::new() },
+        }
+    }
+
+    /// A block that moves the pool to 1,000 WETH against `usdc` whole USDC.
+    fn moved(block_number: u64, usdc: u64, snapshot: bool) -> StreamBlock {
+        let state: Box<dyn ProtocolSim> =
+            Box::new(UniswapV2State::new(U256::from(10u64).pow(U256::from(21u64)), U256::from(usdc * 1_000_000)));
+        StreamBlock {
+            snapshot,
+            failover: None,
+            timestamp: SystemTime::UNIX_EPOCH,
+            update: BlockUpdate {
+                block_number,
+                states: HashMap::from([(POOL.to_string(), state)]),
+                new_pairs: HashMap::new(),
+                removed_pairs: HashMap::new(),
+            },
+        }
+    }
+
+    /// A function to measure the WETH/USDC pool at block 100, as of 3,000,000 USDC, together with
+    /// the blocks read ahead of it.
+    fn measure_at_100(ahead: &VecDeque<AheadBlock>) -> MeasuredBlocks {
+        let weth = Token::new("0x0000000000000000000000000000000000000001", 18, "WETH", BigUint::from(0u32));
+        let usdc = Token::new("0x0000000000000000000000000000000000000002", 6, "USDC", BigUint::from(0u32));
+        let pairs: Vec<TrackedPair> = vec![TrackedPair::new(weth, usdc, vec![0.01], None, &DisplayNames::default()).unwrap()];
+        let (_, states): AheadBlock = read_ahead(moved(100, 3_000_000, false), &[]);
+        let context: BlockContext = BlockContext {
+            settings: &MeasureSettings::default(),
+            names: &DisplayNames::default(),
+            renderer: &PlainRenderer,
+            pairs: &pairs,
+            pool_protocols: &HashMap::from([(POOL.to_string(), "uniswap_v2".to_string())]),
+            protocol_owned: &HashSet::new(),
+            block_number: 100,
+            warm_starts: &WarmStarts::default(),
+            notionals: &[],
+            notional_amounts: &[Vec::new()],
+            base_usd: &[None],
+            marks: &[None],
+            sample_curves: false,
+            chart: false,
+        };
+        let tracked_pairs: HashMap<String, Vec<Token>> = HashMap::from([(POOL.to_string(), pairs[0].tokens.clone())]);
+        let limits: ConcurrencyLimits = ConcurrencyLimits::new(&HashMap::new());
+        measure_ahead(&context, &tracked_pairs, &states, ahead, &SampleSchedule::new(1, 0), None, 2, &limits)
+    }
+
+    #[test]
+    fn reattaches_pools_measured_ahead_to_their_own_block_and_state() {
+        let ahead: VecDeque<AheadBlock> = VecDeque::from([
+            read_ahead(moved(101, 2_900_000, false), &[]),
+            read_ahead(moved(102, 2_800_000, false), &[]),
+        ]);
+        let measured: MeasuredBlocks = measure_at_100(&ahead);
+        let mut blocks: Vec<u64> = measured.keys().copied().collect();
+        blocks.sort_unstable();
+        assert_eq!(blocks, vec![100, 101, 102]);
+
+        let inputs: PoolInputs = PoolInputs { mark: None, base_usd: None, notional_amounts: Vec::new() };
+        let key: (usize, String) = (0, POOL.to_string());
+        for (i, (block, states)) in ahead.iter().enumerate() {
+            let pool: &MeasuredAhead = &measured[&block.update.block_number][&key];
+            assert!(pool.matches(&states[POOL], &inputs));
+            // Each block's outcome was measured on that block's own state, not a neighbour's.
+            let (_, other_states) = &ahead[1 - i];
+            assert!(!pool.matches(&other_states[POOL], &inputs));
+            assert_eq!(pool.outcome.records[0]["block_number"], serde_json::json!(block.update.block_number));
+        }
+        let amount = |block: u64| measured[&block][&key].outcome.records[0]["quote_amount"].clone();
+        assert_ne!(amount(100), amount(101));
+        assert_ne!(amount(101), amount(102));
+    }
+
+    #[test]
+    fn stops_reading_ahead_at_a_resync_snapshot() {
+        let ahead: VecDeque<AheadBlock> = VecDeque::from([
+            read_ahead(moved(101, 2_900_000, false), &[]),
+            read_ahead(moved(102, 2_800_000, true), &[]),
+            read_ahead(moved(103, 2_700_000, false), &[]),
+        ]);
+        let mut blocks: Vec<u64> = measure_at_100(&ahead).keys().copied().collect();
+        blocks.sort_unstable();
+        assert_eq!(blocks, vec![100, 101]);
+    }
+
+    #[test]
+    fn reads_ahead_only_the_pools_asked_for() {
+        let (kept, states): AheadBlock = read_ahead(block(100, &["0xAbC", "0xdef"]), &["0xabc".to_string()]);
+        assert!(kept.update.states.is_empty());
+        assert_eq!(states.keys().collect::<Vec<&String>>(), vec!["0xAbC"]);
+
+        let (_, states): AheadBlock = read_ahead(block(100, &["0xabc", "0xdef"]), &[]);
+        assert_eq!(states.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn takes_blocks_read_ahead_before_the_stream() {
+        let (block_tx, mut block_rx) = mpsc::channel::<StreamBlock>(4);
+        block_tx.send(block(102, &[])).await.unwrap();
+        let mut ahead: VecDeque<AheadBlock> = VecDeque::from([read_ahead(block(101, &["0xabc"]), &[])]);
+
+        let (first, states): AheadBlock = next_block(&mut ahead, &mut block_rx).await.unwrap();
+        assert_eq!((first.update.block_number, states.len()), (101, 1));
+        let (second, _): AheadBlock = next_block(&mut ahead, &mut block_rx).await.unwrap();
+        assert_eq!(second.update.block_number, 102);
+        drop(block_tx);
+        assert!(next_block(&mut ahead, &mut block_rx).await.is_none());
+    }
+}
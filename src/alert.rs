@@ -0,0 +1,293 @@
+use std::{
+    fmt,
+    io::Write,
+    process::{Command, Stdio},
+    str::FromStr,
+};
+use serde::Serialize;
+use crate::notional::Notional;
+
+/// A rule that fires when a pair's aggregate depth at a target stays below a threshold for some
+/// blocks in a row, written `PAIR@TARGET<THRESHOLD`, optionally followed by `for N` blocks, e.g.
+/// `WETH/USDC@0.02<500 for 3` (500 WETH) or `WETH/USDC@0.01<$1M`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertRule {
+    /// The pair's label, e.g. "WETH/USDC", compared case-insensitively.
+    pub pair: String,
+    /// The slippage target, as a decimal.
+    pub target_slippage: f64,
+    /// Whole tokens of the pair's token_in, or dollars (which need `--usd`).
+    pub below: Notional,
+    /// Consecutive blocks below the threshold before the rule fires.
+    pub blocks: usize,
+}
+
+impl AlertRule {
+    /// A function to check whether the rule covers a pair's aggregate at a target.
+    pub fn applies_to(&self, pair: &str, target_slippage: f64) -> bool {
+        self.pair.eq_ignore_ascii_case(pair) && (self.target_slippage - target_slippage).abs() < 1e-12
+    }
+
+    /// A function to compare one block's depth with the threshold.
+    ///
+    /// Args:
+    /// - base_tokens: Depth in whole tokens of token_in, None if the search failed
+    /// - usd_value: The same in dollars, None without a dollar price
+    ///
+    /// Returns:
+    /// - Whether it's below, or None if the block can't tell, which neither counts towards nor
+    ///   breaks a streak
+    pub fn is_breached(&self, base_tokens: Option<f64>, usd_value: Option<f64>) -> Option<bool> {
+        match &self.below {
+            Notional::Tokens(amount) => Some(base_tokens? < amount.parse::<f64>().ok()?),
+            Notional::Usd(usd) => Some(usd_value? < *usd),
+        }
+    }
+}
+
+impl FromStr for AlertRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage =
+            || format!("alert \"{}\" should be PAIR@TARGET<THRESHOLD [for N], e.g. WETH/USDC@0.02<500 for 3", s);
+        let (pair, rest) = s.split_once('@').ok_or_else(usage)?;
+        let (target, rest) = rest.split_once('<').ok_or_else(usage)?;
+        let (threshold, blocks) = match rest.split_once(" for ") {
+            Some((threshold, blocks)) => (threshold, Some(blocks)),
+            None => (rest, None),
+        };
+        let pair: &str = pair.trim();
+        if pair.is_empty() || !pair.contains('/') {
+            return Err(usage());
+        }
+        let target_slippage: f64 = target
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|target| *target > 0.0 && *target < 1.0)
+            .ok_or_else(|| format!("alert \"{}\" needs a slippage target between 0 and 1, e.g. 0.02", s))?;
+        let below: Notional = threshold.trim().parse().map_err(|e| format!("alert \"{}\": {}", s, e))?;
+        let blocks: usize = match blocks {
+            Some(blocks) => blocks
+                .trim()
+                .trim_end_matches("blocks")
+                .trim_end_matches("block")
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|blocks| *blocks > 0)
+                .ok_or_else(|| format!("alert \"{}\" needs a positive number of blocks after \"for\"", s))?,
+            None => 1,
+        };
+        Ok(AlertRule { pair: pair.to_string(), target_slippage, below, blocks })
+    }
+}
+
+impl fmt::Display for AlertRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}<{}", self.pair, self.target_slippage, self.below)?;
+        if self.blocks > 1 {
+            write!(f, " for {}", self.blocks)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    /// Depth has been below the threshold for the rule's blocks.
+    Firing,
+    /// Depth is back above the threshold after the rule fired.
+    Resolved,
+}
+
+/// A rule firing or resolving, as notifiers get it and the result records carry it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Alert {
+    pub kind: AlertKind,
+    /// The rule as written.
+    pub rule: String,
+    pub pair: String,
+    pub target_slippage: f64,
+    pub block_number: u64,
+    /// The first block of the streak below the threshold.
+    pub since_block: u64,
+    /// The block's depth, e.g. "412.5000 WETH ($1242180)".
+    pub depth: String,
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            AlertKind::Firing => write!(
+                f,
+                "ALERT {}: depth at {:.2}% is {} at #{}, below the threshold since #{}",
+                self.rule,
+                self.target_slippage * 100.0,
+                self.depth,
+                self.block_number,
+                self.since_block
+            ),
+            AlertKind::Resolved => write!(
+                f,
+                "resolved {}: depth at {:.2}% is back to {} at #{}, after dropping at #{}",
+                self.rule,
+                self.target_slippage * 100.0,
+                self.depth,
+                self.block_number,
+                self.since_block
+            ),
+        }
+    }
+}
+
+/// Where alerts go besides the console and the result records. Implement it to page through
+/// anything; notifying happens on the block loop, so an implementation should give up quickly.
+pub trait Notifier: Send + Sync {
+    /// A short name for logs, e.g. "command".
+    fn name(&self) -> &str;
+
+    /// A function to deliver one alert.
+    fn notify(&self, alert: &Alert) -> Result<(), String>;
+
+    /// A function to offer a measured block's aggregate depth, for notifiers that post summaries
+    /// as well as alerts. Ignored unless implemented.
+    fn block_summary(&self, _summary: &str) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Runs a program for every alert with the alert as JSON on its stdin, e.g. a script that pages
+/// through an incident tool's CLI. Written as the command line, split on whitespace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandNotifier {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl FromStr for CommandNotifier {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace().map(str::to_string);
+        let program: String = words.next().ok_or_else(|| "the alert command is empty".to_string())?;
+        Ok(CommandNotifier { program, args: words.collect() })
+    }
+}
+
+impl Notifier for CommandNotifier {
+    fn name(&self) -> &str {
+        "command"
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), String> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("{}: {}", self.program, e))?;
+        let json: Vec<u8> = serde_json::to_vec(alert).map_err(|e| e.to_string())?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&json).map_err(|e| format!("{}: {}", self.program, e))?;
+        }
+        let status = child.wait().map_err(|e| format!("{}: {}", self.program, e))?;
+        if !status.success() {
+            return Err(format!("{} exited with {}", self.program, status));
+        }
+        Ok(())
+    }
+}
+
+/// Where a rule stands: how many blocks in a row it's been breached, from which block, and
+/// whether it has fired.
+#[derive(Debug, Clone, Copy, Default)]
+struct Streak {
+    blocks: usize,
+    since_block: u64,
+    firing: bool,
+}
+
+/// Evaluates alert rules block by block. A rule fires once when its streak reaches its blocks and
+/// resolves once when depth recovers, rather than on every block in between.
+#[derive(Debug, Clone, Default)]
+pub struct AlertMonitor {
+    rules: Vec<AlertRule>,
+    streaks: Vec<Streak>,
+}
+
+impl AlertMonitor {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        let streaks: Vec<Streak> = vec![Streak::default(); rules.len()];
+        Self { rules, streaks }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// A function to feed one block's aggregate depth for a pair and target to the rules that cover
+    /// it.
+    ///
+    /// Args:
+    /// - pair: The pair's label
+    /// - target_slippage: The target, as a decimal
+    /// - block_number: The block measured
+    /// - base_tokens: Depth in whole tokens of token_in, None if the search failed
+    /// - usd_value: The same in dollars, if known
+    /// - base: token_in's symbol, for the alert text
+    ///
+    /// Returns:
+    /// - Every rule that fired or resolved on this block
+    pub fn observe(
+        &mut self,
+        pair: &str,
+        target_slippage: f64,
+        block_number: u64,
+        base_tokens: Option<f64>,
+        usd_value: Option<f64>,
+        base: &str,
+    ) -> Vec<Alert> {
+        let depth: String = match (base_tokens, usd_value) {
+            (Some(tokens), Some(usd)) => format!("{:.4} {} (${:.0})", tokens, base, usd),
+            (Some(tokens), None) => format!("{:.4} {}", tokens, base),
+            (None, _) => "unknown".to_string(),
+        };
+        let mut alerts: Vec<Alert> = Vec::new();
+        for (rule, streak) in self.rules.iter().zip(self.streaks.iter_mut()) {
+            if !rule.applies_to(pair, target_slippage) {
+                continue;
+            }
+            let alert = |kind: AlertKind, since_block: u64| Alert {
+                kind,
+                rule: rule.to_string(),
+                pair: pair.to_string(),
+                target_slippage,
+                block_number,
+                since_block,
+                depth: depth.clone(),
+            };
+            match rule.is_breached(base_tokens, usd_value) {
+                Some(true) => {
+                    if streak.blocks == 0 {
+                        streak.since_block = block_number;
+                    }
+                    streak.blocks += 1;
+                    if streak.blocks >= rule.blocks && !streak.firing {
+                        streak.firing = true;
+                        alerts.push(alert(AlertKind::Firing, streak.since_block));
+                    }
+                }
+                Some(false) => {
+                    if streak.firing {
+                        alerts.push(alert(AlertKind::Resolved, streak.since_block));
+                    }
+                    *streak = Streak::default();
+                }
+                None => {}
+            }
+        }
+        alerts
+    }
+}
@@ -0,0 +1,114 @@
+use std::fmt;
+use alloy_primitives::U256;
+
+/// The most decimals a token can have and still fit one whole token in 256 bits: 10^77 does,
+/// 10^78 doesn't.
+pub const MAX_DECIMALS: usize = 77;
+
+#[derive(Debug, PartialEq)]
+pub enum AmountError {
+    Empty,
+    InvalidCharacter { input: String, character: char },
+    /// More fractional digits than the token has decimals; we don't round silently.
+    TooManyDecimals { input: String, decimals: u8 },
+    Overflow { input: String },
+}
+
+impl fmt::Display for AmountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AmountError::Empty => write!(f, "empty amount"),
+            AmountError::InvalidCharacter { input, character } => {
+                write!(f, "invalid character '{}' in amount \"{}\"", character, input)
+            }
+            AmountError::TooManyDecimals { input, decimals } => {
+                write!(f, "amount \"{}\" has more than {} decimal places", input, decimals)
+            }
+            AmountError::Overflow { input } => write!(f, "amount \"{}\" doesn't fit in 256 bits", input),
+        }
+    }
+}
+
+impl std::error::Error for AmountError {}
+
+/// A function to parse a human-readable token amount, e.g. "1.5" or "1_000", into base units.
+///
+/// Underscores are allowed as digit separators. Either side of the decimal point may be empty
+/// (".5", "5."), but not both. Extra fractional digits are only an error if they aren't zero.
+///
+/// Args:
+/// - input: The amount in whole tokens
+/// - decimals: The token's decimals
+///
+/// Returns:
+/// - The amount in base units, e.g. 1_500_000 for "1.5" USDC
+pub fn parse_amount(input: &str, decimals: u8) -> Result<U256, AmountError> {
+    let trimmed: &str = input.trim();
+    let (whole, fraction): (&str, &str) = trimmed.split_once('.').unwrap_or((trimmed, ""));
+    if !whole.chars().chain(fraction.chars()).any(|c| c.is_ascii_digit()) {
+        return Err(AmountError::Empty);
+    }
+    // Trailing zeros don't change the value, so "5.0" is fine even for a 0-decimal token.
+    let fraction: &str = fraction.trim_end_matches(['0', '_']);
+
+    let mut digits: Vec<u8> = Vec::with_capacity(whole.len() + decimals as usize);
+    let mut fraction_digits: usize = 0;
+    for (part, is_fraction) in [(whole, false), (fraction, true)] {
+        for c in part.chars() {
+            match c {
+                '0'..='9' => {
+                    digits.push(c as u8 - b'0');
+                    if is_fraction {
+                        fraction_digits += 1;
+                    }
+                }
+                '_' => {}
+                _ => return Err(AmountError::InvalidCharacter { input: input.to_string(), character: c }),
+            }
+        }
+    }
+    if fraction_digits > decimals as usize {
+        return Err(AmountError::TooManyDecimals { input: input.to_string(), decimals });
+    }
+    // Pad the fraction out to the token's decimals, so the digits read as base units.
+    digits.resize(digits.len() + decimals as usize - fraction_digits, 0);
+
+    let ten: U256 = U256::from(10u64);
+    let overflow = || AmountError::Overflow { input: input.to_string() };
+    digits.into_iter().try_fold(U256::ZERO, |acc, digit| {
+        acc.checked_mul(ten)
+            .and_then(|acc| acc.checked_add(U256::from(digit)))
+            .ok_or_else(overflow)
+    })
+}
+
+/// A function to get one whole token in base units, e.g. 1_000_000 for USDC, 1 for a 0-decimal
+/// token.
+///
+/// Returns:
+/// - 10^decimals, or None past `MAX_DECIMALS`
+pub fn one_token(decimals: usize) -> Option<U256> {
+    (decimals <= MAX_DECIMALS).then(|| U256::from(10u64).pow(U256::from(decimals)))
+}
+
+/// A function to format base units as whole tokens, the inverse of `parse_amount`: "1.5" for
+/// 1_500_000 USDC, "12" for 12 units of a 0-decimal token, with no trailing fractional zeros.
+///
+/// Unlike `alloy_primitives::utils::format_units`, any number of decimals works, and a whole amount
+/// isn't written with a ".0" it doesn't have.
+///
+/// Args:
+/// - amount: The amount in base units
+/// - decimals: The token's decimals
+pub fn format_amount(amount: U256, decimals: usize) -> String {
+    let digits: String = amount.to_string();
+    // Pad so there's at least one whole digit in front of the fraction.
+    let padded: String = format!("{:0>width$}", digits, width = decimals + 1);
+    let (whole, fraction): (&str, &str) = padded.split_at(padded.len() - decimals);
+    let fraction: &str = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{}.{}", whole, fraction)
+    }
+}
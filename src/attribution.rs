@@ -0,0 +1,66 @@
+use serde::Serialize;
+use tycho_simulation::protocol::state::ProtocolSim;
+use crate::{aggregate::PoolAllocation, error::guard_simulation};
+
+/// Measured slippage split into what the pool's fee takes and what moving the price takes.
+///
+/// The two compound rather than add: `1 - slippage = (1 - fee) * (1 - impact)`, so a 0.3% pool
+/// that fills 1.3% below the reference lost 0.3% to the fee and about 1.0% to impact. Against an
+/// oracle mark, `impact` also carries any gap between the pool's spot and the mark.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SlippageAttribution {
+    /// The fee as a decimal, e.g. 0.003.
+    pub fee: f64,
+    /// What's left once the fee is taken out, as a decimal. Negative for price improvement.
+    pub impact: f64,
+}
+
+impl SlippageAttribution {
+    /// A function to split a fill's slippage into its fee and price-impact components.
+    ///
+    /// Args:
+    /// - slippage: The fill against the reference as a decimal, see `Leg::signed_slippage`
+    /// - fee: The fee charged on the fill as a decimal
+    ///
+    /// Returns:
+    /// - The split, or None if the fee isn't in [0, 1)
+    pub fn split(slippage: f64, fee: f64) -> Option<Self> {
+        if !(0.0..1.0).contains(&fee) || !slippage.is_finite() {
+            return None;
+        }
+        Some(Self { fee, impact: 1.0 - (1.0 - slippage) / (1.0 - fee) })
+    }
+}
+
+/// A function to read a pool's fee.
+///
+/// `ProtocolSim::fee` isn't implemented for every protocol (some VM pools panic), so a pool
+/// without a usable fee simply goes unattributed.
+///
+/// Returns:
+/// - The fee as a decimal, or None if the pool can't say or reports something outside [0, 1)
+pub fn pool_fee(state: &dyn ProtocolSim) -> Option<f64> {
+    guard_simulation(|| Ok::<f64, ()>(state.fee()))
+        .ok()
+        .filter(|fee| (0.0..1.0).contains(fee))
+}
+
+/// A function to find the fee a split order pays overall: each pool's fee weighted by the share of
+/// the order routed to it.
+///
+/// Args:
+/// - allocations: How the order was split, see `AggregateDepth::allocations`
+/// - fee_of: Looks up a pool's fee by id
+///
+/// Returns:
+/// - The weighted fee, or None if nothing was routed or any pool's fee is unknown
+pub fn routed_fee(allocations: &[PoolAllocation], fee_of: impl Fn(&str) -> Option<f64>) -> Option<f64> {
+    let mut total: f64 = 0.0;
+    let mut weighted: f64 = 0.0;
+    for allocation in allocations.iter() {
+        let amount_in: f64 = allocation.amount_in.parse().ok()?;
+        total += amount_in;
+        weighted += amount_in * fee_of(&allocation.pool_id)?;
+    }
+    (total > 0.0).then(|| weighted / total)
+}
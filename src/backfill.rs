@@ -0,0 +1,294 @@
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use alloy_primitives::U256;
+use tokio::sync::mpsc;
+use tycho_common::{models::Chain, Bytes};
+use tycho_simulation::models::Token;
+use crate::{
+    amount::format_amount,
+    recording::{read_block, recorded_blocks, RecordedBlock},
+    stream::StreamBlock,
+};
+
+/// The most blocks `backfill --parallel-blocks` measures at once. Each one in flight holds its own
+/// view of the pool states and its results until it's written, so this bounds memory as well.
+pub const MAX_PARALLEL_BLOCKS: usize = 32;
+
+/// One measured block of a `DepthSeries`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeriesPoint {
+    pub block_number: u64,
+    /// Aggregate depth in token_in base units, None if the block's search failed.
+    pub amount_in: Option<U256>,
+    /// The same in dollars, with `--usd`.
+    pub usd_value: Option<f64>,
+}
+
+/// One pair's aggregate depth at one slippage target, block by block over a backfilled range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepthSeries {
+    pub pair: String,
+    /// The token sold, which the depth is counted in.
+    pub base: String,
+    pub decimals: usize,
+    pub target_slippage: f64,
+    pub points: Vec<SeriesPoint>,
+}
+
+impl DepthSeries {
+    /// A function to find the shallowest and deepest measured blocks.
+    ///
+    /// Returns:
+    /// - (shallowest, deepest), or None if every block failed
+    pub fn extremes(&self) -> Option<(&SeriesPoint, &SeriesPoint)> {
+        let measured = || self.points.iter().filter(|point| point.amount_in.is_some());
+        Some((measured().min_by_key(|point| point.amount_in)?, measured().max_by_key(|point| point.amount_in)?))
+    }
+}
+
+impl fmt::Display for DepthSeries {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let amount = |point: &SeriesPoint| match point.amount_in {
+            Some(amount) => format!("{} {}", format_amount(amount, self.decimals), self.base),
+            None => "failed".to_string(),
+        };
+        writeln!(f, "{} at {:.2}% over {} blocks", self.pair, self.target_slippage * 100.0, self.points.len())?;
+        for point in self.points.iter() {
+            match point.usd_value {
+                Some(usd) => writeln!(f, "   #{:<12} {:>28}  ${:.0}", point.block_number, amount(point), usd)?,
+                None => writeln!(f, "   #{:<12} {:>28}", point.block_number, amount(point))?,
+            }
+        }
+        if let Some((low, high)) = self.extremes() {
+            let (low_block, high_block): (u64, u64) = (low.block_number, high.block_number);
+            writeln!(f, "   low {} at #{}, high {} at #{}", amount(low), low_block, amount(high), high_block)?;
+        }
+        Ok(())
+    }
+}
+
+/// Every pair's depth series for a backfill, in the order they were first measured.
+#[derive(Debug, Clone, Default)]
+pub struct BackfillSeries {
+    series: Vec<DepthSeries>,
+}
+
+impl BackfillSeries {
+    /// A function to add one block's aggregate depth for a pair and target.
+    ///
+    /// Args:
+    /// - pair: The pair's label
+    /// - base: The sold token's symbol
+    /// - decimals: The sold token's decimals
+    /// - target_slippage: The target, as a decimal
+    /// - point: The block's depth
+    pub fn push(&mut self, pair: &str, base: &str, decimals: usize, target_slippage: f64, point: SeriesPoint) {
+        let position: Option<usize> = self
+            .series
+            .iter()
+            .position(|s| s.pair == pair && s.base == base && s.target_slippage == target_slippage);
+        match position {
+            Some(i) => self.series[i].points.push(point),
+            None => self.series.push(DepthSeries {
+                pair: pair.to_string(),
+                base: base.to_string(),
+                decimals,
+                target_slippage,
+                points: vec![point],
+            }),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &DepthSeries> {
+        self.series.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.series.is_empty()
+    }
+}
+
+/// A recording that `backfill` or `replay` reads in place of the stream.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub dir: PathBuf,
+    /// A backfill's first and last block to measure; a replay measures every block.
+    pub range: Option<(u64, u64)>,
+}
+
+impl Replay {
+    pub fn new(dir: &Path, range: Option<(u64, u64)>) -> anyhow::Result<Self> {
+        if let Some((from_block, to_block)) = range.filter(|(from_block, to_block)| from_block > to_block) {
+            anyhow::bail!("--from-block {} is after --to-block {}", from_block, to_block);
+        }
+        Ok(Self { dir: dir.to_path_buf(), range })
+    }
+
+    /// A function to tell whether a block only builds up state: recorded before a backfill's range.
+    pub fn is_before_range(&self, block_number: u64) -> bool {
+        self.range.is_some_and(|(from_block, _)| block_number < from_block)
+    }
+
+    /// A function to list the chain's recorded blocks, oldest first, up to the end of a backfill's
+    /// range. A recording that ends before the range starts is an error; one that starts after it
+    /// is measured from its first block, with a warning.
+    ///
+    /// Returns:
+    /// - (block number, path) for each block, as `recorded_blocks` lists them
+    pub fn blocks(&self, chain: Chain) -> anyhow::Result<Vec<(u64, PathBuf)>> {
+        let blocks: Vec<(u64, PathBuf)> =
+            recorded_blocks(&self.dir, &chain.to_string(), self.range.map(|(_, to_block)| to_block))
+                .map_err(|e| anyhow::anyhow!("{}: {}", self.dir.display(), e))?;
+        if let Some((from_block, _)) = self.range {
+            let (first, last): (u64, u64) = (blocks[0].0, blocks[blocks.len() - 1].0);
+            if last < from_block {
+                anyhow::bail!(
+                    "the {} recording in {} ends at #{}, before --from-block {}",
+                    chain,
+                    self.dir.display(),
+                    last,
+                    from_block
+                );
+            }
+            if first > from_block {
+                tracing::warn!("the {} recording starts at #{}, after --from-block {}", chain, first, from_block);
+            }
+        }
+        Ok(blocks)
+    }
+}
+
+/// A function to gather the tokens of every pool a recording announces. A recording brings its
+/// own tokens, since the live token list may have dropped some.
+///
+/// Args:
+/// - blocks: (block number, path) as `Replay::blocks` lists them
+pub fn recording_tokens(blocks: &[(u64, PathBuf)]) -> anyhow::Result<HashMap<Bytes, Token>> {
+    let mut tokens: HashMap<Bytes, Token> = HashMap::new();
+    for (_, path) in blocks.iter() {
+        let block: RecordedBlock = read_block(path).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+        tokens.extend(block.tokens().map(|token| (token.address.clone(), token)));
+    }
+    Ok(tokens)
+}
+
+/// A function to feed a recording's blocks to the block loop in place of the stream, oldest first.
+/// Files are read on a thread of their own; one that can't be read ends the replay early, logged.
+/// Blocks keep their recorded time; ones recorded without it get the file's modification time.
+///
+/// Args:
+/// - blocks: (block number, path) as `recorded_blocks` lists them
+/// - chain: The recording's chain
+///
+/// Returns:
+/// - The receiving end; it closes after the last block
+pub fn replay_recording(blocks: Vec<(u64, PathBuf)>, chain: Chain) -> mpsc::Receiver<StreamBlock> {
+    let (block_tx, block_rx) = mpsc::channel::<StreamBlock>(64);
+    std::thread::spawn(move || {
+        for (_, path) in blocks.into_iter() {
+            let replayed: Result<StreamBlock, String> = read_block(&path).map_err(|e| e.to_string()).and_then(|block| {
+                let snapshot: bool = block.snapshot;
+                let timestamp: SystemTime = match block.timestamp {
+                    Some(secs) => UNIX_EPOCH + Duration::from_secs(secs),
+                    None => fs::metadata(&path).and_then(|metadata| metadata.modified()).map_err(|e| e.to_string())?,
+                };
+                Ok(StreamBlock { snapshot, failover: None, timestamp, update: block.into_update(chain)? })
+            });
+            match replayed {
+                Ok(block) => {
+                    if block_tx.blocking_send(block).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("replay stopped at {}: {}", path.display(), e);
+                    return;
+                }
+            }
+        }
+    });
+    block_rx
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use crate::recording::write_block;
+    use super::*;
+
+    const POOL: &str = "0x0000000000000000000000000000000000000abc";
+
+    fn recorded(block_number: u64) -> RecordedBlock {
+        serde_json::from_value(serde_json::json!({
+            "block_number": block_number,
+            "snapshot": block_number == 100,
+            "timestamp": 1_700_000_000 + 12 * (block_number - 100),
+            "new_pairs": [{
+                "id": POOL,
+                "protocol_system": "uniswap_v2",
+                "protocol_type_name": "uniswap_v2_pool",
+                "tokens": [
+                    { "address": "0x0000000000000000000000000000000000000001", "symbol": "WETH", "decimals": 18 },
+                    { "address": "0x0000000000000000000000000000000000000002", "symbol": "USDC", "decimals": 6 },
+                ],
+            }],
+            "removed_pairs": [],
+            "states": {
+                POOL: { "kind": "uniswap_v2", "reserve0": "1000000000000000000000", "reserve1": "3000000000000" },
+            },
+        }))
+        .unwrap()
+    }
+
+    fn recording(name: &str) -> PathBuf {
+        let dir: PathBuf = env::temp_dir().join(format!("liquidity-depth-backfill-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for block_number in [100, 101, 102] {
+            write_block(&dir, "ethereum", &recorded(block_number)).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn checks_the_range_against_the_recording() {
+        let dir: PathBuf = recording("range");
+        assert!(Replay::new(&dir, Some((102, 101))).unwrap_err().to_string().contains("is after --to-block"));
+
+        let replay: Replay = Replay::new(&dir, Some((101, 101))).unwrap();
+        let blocks: Vec<u64> = replay.blocks(Chain::Ethereum).unwrap().into_iter().map(|(block, _)| block).collect();
+        assert_eq!(blocks, vec![100, 101]);
+        assert!(replay.is_before_range(100) && !replay.is_before_range(101));
+        let late: Replay = Replay::new(&dir, Some((200, 300))).unwrap();
+        assert!(late.blocks(Chain::Ethereum).unwrap_err().to_string().contains("ends at #102, before --from-block 200"));
+        assert!(!Replay::new(&dir, None).unwrap().is_before_range(0));
+
+        let tokens: HashMap<Bytes, Token> = recording_tokens(&replay.blocks(Chain::Ethereum).unwrap()).unwrap();
+        let mut symbols: Vec<&str> = tokens.values().map(|token| token.symbol.as_str()).collect();
+        symbols.sort_unstable();
+        assert_eq!(symbols, vec!["USDC", "WETH"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn replays_every_block_in_order_and_then_closes() {
+        let dir: PathBuf = recording("replay");
+        let blocks: Vec<(u64, PathBuf)> = Replay::new(&dir, None).unwrap().blocks(Chain::Ethereum).unwrap();
+        let mut block_rx: mpsc::Receiver<StreamBlock> = replay_recording(blocks, Chain::Ethereum);
+
+        let mut replayed: Vec<(u64, bool, SystemTime)> = Vec::new();
+        while let Some(block) = block_rx.recv().await {
+            assert_eq!(block.update.states.len(), 1);
+            replayed.push((block.update.block_number, block.snapshot, block.timestamp));
+        }
+        let at = |secs: u64| UNIX_EPOCH + Duration::from_secs(secs);
+        assert_eq!(
+            replayed,
+            vec![(100, true, at(1_700_000_000)), (101, false, at(1_700_000_012)), (102, false, at(1_700_000_024))]
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,291 +1,1012 @@
-use clap::Parser;
 use std::{
-    time::Instant,
-    ops::{Div, Mul},
-    fmt::{self, Debug},
+    collections::{HashMap, HashSet},
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
 };
-use num_bigint::BigUint;
-use alloy_primitives::{utils::format_units, U256};
-use tycho_simulation::{
-    models::Token,
-    protocol::{
-        state::ProtocolSim,
-    },
-    evm::protocol::u256_num::{u256_to_biguint, biguint_to_u256},
+use clap::{Parser, Subcommand};
+use liquidity_depth_cli::{
+    alert::{AlertRule, CommandNotifier, Notifier},
+    backfill::{recording_tokens, replay_recording, Replay, MAX_PARALLEL_BLOCKS},
+    binary_search::*,
+    chain::{default_tycho_url, native_symbol, parse_supported_chain},
+    chart::FigureFormat,
+    checkpoint::Checkpoint,
+    compare::{comparison_pair, ProtocolSet},
+    config::{ChainList, Config, ConfigProblem, EndpointList, OutputConfig, PairConfig, DEFAULT_TVL_MIN},
+    crosscheck::DEFAULT_DISAGREEMENT_BPS,
+    display::DisplayNames,
+    endpoints::{parse_endpoint_list, DEFAULT_STALL_TIMEOUT_SECS},
+    http::DEFAULT_LISTEN_ADDR,
+    html::HtmlReport,
+    hub::StateHub,
+    labels::{Label, Labels},
+    measure::{MeasureSettings, PairSpec, TrackedPair},
+    notional::Notional,
+    orderbook::{DEFAULT_BOOK_LEVELS, DEFAULT_BOOK_STEP_BPS},
+    parallel::{default_workers, ConcurrencyLimits},
+    oracle::OracleConfig,
+    protocols::{protocol_filter, ProtocolFilter},
+    rpc::RpcHandler,
+    run::{shutdown_signal, BlockLoop, OutputFormat, RunMode, RunSettings},
+    scenario::PriceShift,
+    schedule::SampleSchedule,
+    serve::{serve_http, serve_stdio},
+    sink::{chain_path, Compression, Partition},
+    stability::DEFAULT_STABILITY_WINDOW,
+    stream::{load_tokens, spawn_protocol_stream, spawn_vm_stream, stream_hub, StreamBlock, StreamSettings},
+    supervisor::RestartCounter,
+    token_id::TokenId,
+    webhook::WebhookNotifier,
 };
-use tracing::{info, error, warn, debug};
+use tycho_common::{models::Chain, Bytes};
+use num_bigint::ToBigUint;
+use tycho_simulation::models::Token;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tokio::{net::TcpListener, sync::mpsc};
+
+/// Measures how much of a token can be sold into a pool before slippage reaches a target, for every
+/// pool of each tracked pair, streamed block by block from Tycho.
+#[derive(Parser)]
+#[command(name = "binary_search", version, subcommand_negates_reqs = true)]
+struct Cli {
+    /// Read chain, endpoints, pairs, slippage targets and outputs from a TOML or YAML file; flags
+    /// given on the command line win over the file
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// The chain to stream [default: unichain]. Several, comma-separated or repeated (e.g.
+    /// ethereum,base,unichain), are streamed side by side, each with its own pools and files
+    #[arg(long, value_parser = parse_supported_chain, value_delimiter = ',')]
+    chain: Vec<Chain>,
+    /// A pair to track, as TOKEN_IN/TOKEN_OUT, e.g. "WETH/USDC"; repeat for several pairs
+    #[arg(long, value_name = "IN/OUT", value_parser = parse_pair)]
+    pair: Vec<(TokenId, TokenId)>,
+    /// Token to sell: a symbol ("WETH"), an address ("0x4200…0006"), or chain-prefixed ("base:WETH")
+    #[arg(long, value_name = "TOKEN", required_unless_present_any = ["config", "pair"], requires = "token_out")]
+    token_in: Option<TokenId>,
+    /// Token to buy, in the same format as --token-in
+    #[arg(long, value_name = "TOKEN", required_unless_present_any = ["config", "pair"], requires = "token_in")]
+    token_out: Option<TokenId>,
+    /// Target slippages as decimals, comma-separated, e.g. 0.02 for 2% [default: 0.02]
+    #[arg(long, value_delimiter = ',', value_parser = parse_slippage)]
+    slippage: Option<Vec<f64>>,
+    /// How close to the target counts as converged, as a decimal, e.g. 0.0001 for 0.01% [default: 0.0001]
+    #[arg(long, value_parser = parse_precision)]
+    precision: Option<f64>,
+    /// Loosen --precision tenfold for pools under ~128 tokens deep and tighten it tenfold for pools
+    /// over ~1M tokens deep, judged from the bracketing
+    #[arg(long)]
+    adaptive_precision: bool,
+    /// How many blocks of each pair's aggregate depth the coefficient of variation (the stability
+    /// metric) covers [default: 20]
+    #[arg(long, value_name = "BLOCKS")]
+    stability_window: Option<usize>,
+    /// Bisection steps per pool and target before settling for the best bracket found, which is
+    /// then reported with converged: false [default: 64]
+    #[arg(long, value_name = "N")]
+    max_iterations: Option<u32>,
+    /// Give up bracketing pools that aren't Uniswap v2-style at this multiple of their TVL, and
+    /// report the depth found so far as pool-limited [default: 10]
+    #[arg(long, value_name = "MULTIPLE", value_parser = parse_tvl_multiple)]
+    bracket_tvl_multiple: Option<f64>,
+    /// What to do when a pool won't quote a trade large enough to reach the target, e.g. past a
+    /// gas limit or its ticks: "report" the largest it quotes as a lower bound flagged
+    /// pool-limited, or "fail" the pool [default: report]
+    #[arg(long, value_name = "POLICY")]
+    pool_limits: Option<PoolLimitPolicy>,
+    /// Search every block from scratch instead of starting from where each pool converged on the
+    /// previous block
+    #[arg(long)]
+    cold_start: bool,
+    /// Include each depth's final get_amount_out call (amount_in, amount_out and gas, in base
+    /// units) in the JSON report and result records, to re-verify against another simulator
+    #[arg(long)]
+    sim_inputs: bool,
+    /// Also give depth in dollars, pricing each pair's token_in from its deepest stablecoin pool
+    /// (or native pool times the native price), or from the pair's usd_price feed in the config
+    #[arg(long)]
+    usd: bool,
+    /// Only track pools with at least this much TVL, in ETH [default: 500]
+    #[arg(long, value_name = "ETH", value_parser = parse_tvl)]
+    tvl_min: Option<f64>,
+    /// Stop tracking a pool only once its TVL falls below this, in ETH, so pools near --tvl-min
+    /// don't flicker in and out of the universe; at most --tvl-min [default: --tvl-min]
+    #[arg(long, value_name = "ETH", value_parser = parse_tvl)]
+    tvl_remove: Option<f64>,
+    /// Only stream and measure these protocol systems, comma-separated (e.g.
+    /// uniswap_v3,uniswap_v4), to isolate a venue's contribution to depth [default: all]
+    #[arg(long, value_name = "SYSTEM", value_delimiter = ',')]
+    protocols: Vec<String>,
+    /// Never stream or measure these protocol systems, comma-separated (e.g. vm:curve)
+    #[arg(long, value_name = "SYSTEM", value_delimiter = ',')]
+    exclude_protocols: Vec<String>,
+    /// Only stream and measure these pools, by component id, comma-separated, e.g. one canonical
+    /// pool instead of the whole market. Replaces the TVL filter
+    #[arg(long, value_name = "POOL", value_delimiter = ',')]
+    pools: Vec<String>,
+    /// Pool ids holding protocol-owned or locked liquidity, comma-separated; aggregates are also
+    /// reported without them. Added to the config's protocol_owned
+    #[arg(long, value_name = "POOL", value_delimiter = ',')]
+    protocol_owned: Vec<String>,
+    /// A free-form tag for every record and summary the run writes, e.g.
+    /// experiment=fee-change-study; repeatable, and overrides a config label with the same key
+    #[arg(long = "label", value_name = "KEY=VALUE")]
+    labels: Vec<Label>,
+    /// Also measure each pair's aggregate depth over two named sets of protocols, against the same
+    /// block and reference, and report the difference; give it twice, e.g. with-v4=uniswap_v3,uniswap_v4
+    /// and without-v4=!uniswap_v4 (a ! leaves a protocol out)
+    #[arg(long, value_name = "NAME=FILTER")]
+    compare_protocols: Vec<ProtocolSet>,
+    /// Alert when a pair's aggregate depth at a target stays below a threshold, e.g.
+    /// "WETH/USDC@0.02<500 for 3" (500 WETH, 3 blocks in a row) or "WETH/USDC@0.01<$1M" (with
+    /// --usd); repeatable. Alerts go to the console, --results and --alert-command
+    #[arg(long = "alert", value_name = "RULE")]
+    alerts: Vec<AlertRule>,
+    /// Run this command whenever an alert fires or resolves, with the alert as JSON on stdin
+    #[arg(long, value_name = "COMMAND")]
+    alert_command: Option<CommandNotifier>,
+    /// Stop after this many blocks; 0 runs until interrupted. Ignored with --soak [default: 5]
+    #[arg(long)]
+    blocks: Option<usize>,
+    /// Run for this many seconds while sampling RSS and tracked-map sizes, then print a soak report
+    #[arg(long, value_name = "SECONDS")]
+    soak: Option<u64>,
+    /// Seconds between soak samples
+    #[arg(long, value_name = "SECONDS", default_value_t = 30)]
+    soak_interval: u64,
+    /// Shift the reference price by a hypothetical market move, e.g. "+5%" or "-2.5%"
+    #[arg(long, value_name = "SHIFT", allow_hyphen_values = true)]
+    price_shift: Option<PriceShift>,
+    /// Write a reproducibility bundle (run settings, tokens, pools, state hashes, results) here
+    #[arg(long, value_name = "PATH")]
+    bundle: Option<PathBuf>,
+    /// Stream every result as JSON lines to this file as it's produced, flushed after every block
+    #[arg(long, value_name = "PATH")]
+    results: Option<PathBuf>,
+    /// Also append one row per block, pool and slippage target to this CSV file, for pandas or a
+    /// spreadsheet
+    #[arg(long, value_name = "PATH")]
+    csv: Option<PathBuf>,
+    /// Also upsert every block's results into this SQLite database (tables blocks, pools, depths,
+    /// exclusions and aggregates), for a queryable local history
+    #[arg(long, value_name = "PATH")]
+    sqlite: Option<PathBuf>,
+    /// Also record every streamed block's pools and states under DIR/<chain>/, one JSON file per
+    /// block, for `backfill`. Only Uniswap v2-style states can be recorded
+    #[arg(long, value_name = "DIR")]
+    record: Option<PathBuf>,
+    /// Save the last measured block and the tracked pools here after every block; a run started
+    /// with the same file skips blocks it already measured and records any it missed as a gap
+    #[arg(long, value_name = "PATH")]
+    checkpoint: Option<PathBuf>,
+    /// Compress file sinks: none, gzip or zstd [default: by extension, .gz or .zst]
+    #[arg(long, value_name = "CODEC")]
+    compress: Option<Compression>,
+    /// Split the --results and --csv files into one file per pair, chain or protocol, e.g.
+    /// depth.csv to depth-weth-usdc.csv, or runs/{partition}.csv to runs/weth-usdc.csv
+    #[arg(long, value_name = "KEY")]
+    partition_by: Option<Partition>,
+    /// Print the full pool table every N blocks and only changed rows in between [default: 1]
+    #[arg(long, value_name = "N")]
+    full_every: Option<u64>,
+    /// Threads measuring pools side by side each block [default: one per core]
+    #[arg(long, value_name = "N")]
+    workers: Option<usize>,
+    /// Only measure one block in N (pool states are still updated every block), e.g. to watch a
+    /// fast chain without simulating every block
+    #[arg(long, value_name = "N")]
+    every_n_blocks: Option<u64>,
+    /// With --every-n-blocks, measure at a random phase within each N blocks rather than at
+    /// multiples of N, so instances started together don't hit the endpoint on the same blocks
+    #[arg(long)]
+    sample_jitter: bool,
+    /// Fail over to the next Tycho endpoint (from a comma-separated TYCHO_URL or a list in the
+    /// config's [endpoints]) when the current one sends no block for this long [default: 60]
+    #[arg(long, value_name = "SECS")]
+    stall_timeout: Option<u64>,
+    /// ASCII-only, fixed-width console output for terminals and log collectors that mangle Unicode
+    #[arg(long)]
+    plain: bool,
+    /// text; json for one JSON document per block on stdout; ndjson for every result record as a
+    /// line of JSON on stdout as soon as it's measured (human-readable output then goes to stderr);
+    /// or parquet for every block's depth rows as Parquet files under --parquet-dir
+    #[arg(long, value_name = "FORMAT", default_value = "text")]
+    output: OutputFormat,
+    /// With --output parquet, write the files under DIR, partitioned as
+    /// date=YYYY-MM-DD/chain=<chain>/pair=<pair>/ [default: parquet]
+    #[arg(long, value_name = "DIR")]
+    parquet_dir: Option<PathBuf>,
+    /// Also sample each pool's full price-impact curve at this many log-spaced sizes into the bundle
+    /// (and, with --chart, for the chart)
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    curve_samples: usize,
+    /// Plot each pair's slippage-by-size curves, one per pool, under its results
+    #[arg(long, conflicts_with_all = ["coarse", "notional", "impact_table", "impact_sizes"])]
+    chart: bool,
+    /// Write each pair's pool curves and its aggregate depth over the run's blocks as figures into
+    /// DIR/<chain>/, rewritten every block. Needs a build with `--features charts`
+    #[arg(long, value_name = "DIR")]
+    charts_dir: Option<PathBuf>,
+    /// svg or png, for --charts-dir
+    #[arg(long, value_name = "FORMAT", default_value = "svg")]
+    chart_format: FigureFormat,
+    /// Estimate depth from two probes instead of a full search (~10% accuracy), for scanning many pairs
+    #[arg(long, conflicts_with_all = ["both_directions", "two_sided"])]
+    coarse: bool,
+    /// Also measure the reverse leg, e.g. how much USDC can be sold into ETH
+    #[arg(long)]
+    both_directions: bool,
+    /// Skip the depth search and report the slippage of selling this much token_in, per pool and
+    /// split across the pair's pools: whole tokens (e.g. 100) or dollars (e.g. $1M, priced as for --usd)
+    #[arg(long, value_name = "AMOUNT", conflicts_with_all = ["coarse", "both_directions", "two_sided"])]
+    notional: Option<Notional>,
+    /// Report slippage at a ladder of trade sizes, per pool and split across the pair's pools, as
+    /// a table: $10k, $100k, $1M and $10M unless --impact-sizes or the config says otherwise
+    #[arg(long, conflicts_with_all = ["coarse", "both_directions", "two_sided", "notional"])]
+    impact_table: bool,
+    /// Trade sizes for --impact-table, comma-separated and written as for --notional, e.g.
+    /// '$50k,$500k,$5M' or '10,100'; implies --impact-table
+    #[arg(long, value_name = "SIZES", value_delimiter = ',', conflicts_with_all = ["coarse", "both_directions", "two_sided", "notional"])]
+    impact_sizes: Vec<Notional>,
+    /// Solve for the token_in each pool needs to return exactly this much token_out, in whole
+    /// tokens (e.g. 1000000 to receive 1,000,000 USDC), within the pair's largest slippage target
+    #[arg(
+        long,
+        value_name = "AMOUNT",
+        conflicts_with_all = ["coarse", "both_directions", "two_sided", "notional", "impact_table", "impact_sizes"]
+    )]
+    exact_output: Option<String>,
+    /// Depth within ±target of mid, like order-book depth: measure both legs against the same mid
+    /// price and report the bid side, the ask side and their sum
+    #[arg(long)]
+    two_sided: bool,
+    /// Also measure depth along each pair's deepest two-hop route through any token the tracked
+    /// pools connect it by (e.g. WETH/DAI via USDC), for pairs with thin direct liquidity
+    #[arg(long)]
+    two_hop: bool,
+    /// Flag each pair whose cheapest and richest pools' spots are more than this many basis points
+    /// apart, with the size tradable between them before their prices meet again
+    #[arg(long, value_name = "BPS")]
+    arb_bps: Option<f64>,
+    /// Instead of tracking pairs, answer JSON requests from scripts: one per line on stdin
+    /// ("depth", "quote" or "curve"), each answered with one line on stdout from the live stream
+    #[arg(long)]
+    stdio: bool,
+    /// Also stream this VM protocol system (e.g. "vm:uniswap_v3") and compare its pools'
+    /// get_amount_out against the native states with the same id; repeat for several systems
+    #[arg(long, value_name = "VM_SYSTEM")]
+    cross_check: Vec<String>,
+    /// Flag native/VM outputs further apart than this, in basis points
+    #[arg(long, value_name = "BPS", default_value_t = DEFAULT_DISAGREEMENT_BPS)]
+    cross_check_bps: u32,
+    /// For Uniswap v3/v4 pools, also show liquidity within ±0.1/0.5/1/2% of spot, in token_out
+    #[arg(long)]
+    price_ranges: bool,
+    /// Start long runs (--blocks 0 or --soak) without confirming the pre-flight report
+    #[arg(long, short = 'y')]
+    yes: bool,
+    /// Show a live dashboard instead of console lines: each pair's aggregate depth at every target
+    /// with a sparkline of recent blocks, the selected pair's pools with their spot and depth, and
+    /// how far behind the stream the run is. Redrawn every block; ↑/↓ pick a pair and q quits.
+    /// Warnings go to logs/binary_search.log, and long runs start without the pre-flight prompt
+    #[arg(long, conflicts_with = "stdio")]
+    tui: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-pub struct Slippage {
-    pub num: U256,
-    pub den: U256,
+#[derive(Subcommand)]
+enum Command {
+    /// Estimate depth after a notional of liquidity is withdrawn proportionally across the pair's
+    /// pools, e.g. when LP incentives end, then exit
+    Unwind {
+        /// Liquidity to remove, in whole units of the output token
+        #[arg(long, value_parser = parse_unwind_notional)]
+        notional: f64,
+        /// Slippage targets for the before/after depth curve
+        #[arg(long, value_delimiter = ',', default_values_t = [0.005, 0.01, 0.02, 0.05])]
+        targets: Vec<f64>,
+    },
+    /// Turn each pair's pools into a CEX-style ladder of cumulative size at price levels either side
+    /// of mid on the first block, then exit
+    Orderbook {
+        /// Distance between price levels, in basis points
+        #[arg(long, value_name = "BPS", default_value_t = DEFAULT_BOOK_STEP_BPS)]
+        step_bps: u32,
+        /// Price levels on each side
+        #[arg(long, default_value_t = DEFAULT_BOOK_LEVELS)]
+        levels: usize,
+        /// Also write the ladders to this file: CSV for .csv (.csv.gz, .csv.zst), JSON lines otherwise
+        #[arg(long, value_name = "PATH")]
+        export: Option<PathBuf>,
+    },
+    /// Measure a past block range from a --record recording instead of the live stream, which
+    /// only starts at the chain head, then print each pair's depth over the range. Blocks before
+    /// --from-block only build up state; --blocks is ignored
+    Backfill {
+        /// First block to measure
+        #[arg(long, value_name = "BLOCK")]
+        from_block: u64,
+        /// Last block to measure
+        #[arg(long, value_name = "BLOCK")]
+        to_block: u64,
+        /// The directory --record wrote
+        #[arg(long, value_name = "DIR")]
+        recording: PathBuf,
+        /// Measure up to N blocks at once, each against its own copy of the pool states, and still
+        /// write them in block order. Every block in flight is held in memory until it's written,
+        /// so N is at most 32. Searches start cold, since a warm start ties a block to the one before
+        #[arg(long, value_name = "N", default_value_t = 1, value_parser = parse_parallel_blocks)]
+        parallel_blocks: usize,
+    },
+    /// Feed every block of a --record recording back through the engine, in order and as if
+    /// streamed, e.g. to debug a search that didn't converge or to check the math against a
+    /// known run; the same recording and flags always give the same records. --blocks is ignored
+    Replay {
+        /// The directory --record wrote
+        dir: PathBuf,
+    },
+    /// Run until interrupted, ignoring --blocks. SIGINT or SIGTERM finishes the block in
+    /// progress, flushes every sink and prints the run summary
+    Monitor,
+    /// Instead of tracking pairs, serve the live stream over HTTP until interrupted: GET
+    /// /depth?pair=WETH-USDC&slippage=0.02, /quote?pair=…&amount=100, /curve?pair=…&samples=20 and
+    /// /pools?pair=…, each answered with JSON as for --stdio; with --sqlite, also
+    /// /depth/history?pair=…&from=…&to=…&resolution=1h from the history another run wrote there
+    Serve {
+        /// Address to listen on
+        #[arg(long, value_name = "ADDR", default_value = DEFAULT_LISTEN_ADDR)]
+        listen: String,
+    },
+    /// Measure --blocks blocks as usual, then summarise them per pair as a standalone HTML page
+    /// with tables and charts, for sharing; or, with --from-sqlite, summarise a history --sqlite
+    /// already wrote, without streaming
+    Report {
+        /// Where to write the page; with several chains, each writes its own
+        #[arg(long, value_name = "PATH", default_value = "depth-report.html")]
+        out: PathBuf,
+        /// Summarise this --sqlite database instead of measuring blocks
+        #[arg(long, value_name = "PATH")]
+        from_sqlite: Option<PathBuf>,
+        /// The page's heading
+        #[arg(long, default_value = "Liquidity depth report")]
+        title: String,
+    },
+    /// Work with config files
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
 }
 
-impl Slippage {
-    pub fn new(num: U256, den: U256) -> Self {
-        Self { num, den }
-    }
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Check a config file's syntax, token identifiers, ranges, endpoints, sinks and cross-field
+    /// consistency without connecting anywhere, then exit
+    Validate {
+        /// The TOML or YAML file to check
+        path: PathBuf,
+    },
 }
 
-impl std::fmt::Debug for Slippage {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Slippage {{ num: {}, den: {} }}", self.num, self.den)
+/// A function to print every problem in a config file as "path:line:column: message", like a
+/// compiler.
+fn validate_config(path: &Path) -> anyhow::Result<()> {
+    let problems: Vec<ConfigProblem> =
+        Config::check_file(path).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+    for problem in problems.iter() {
+        println!("{}", problem.describe(path));
+    }
+    if !problems.is_empty() {
+        anyhow::bail!("{} problem(s) in {}", problems.len(), path.display());
     }
+    println!("{}: ok", path.display());
+    Ok(())
 }
 
-#[derive(Debug)]
-pub enum SlippageError {
-    Overflow,
+/// A function to write `report --from-sqlite`'s page, without connecting anywhere.
+fn write_sqlite_report(path: &Path, out: &Path, title: &str) -> anyhow::Result<()> {
+    let report: HtmlReport =
+        HtmlReport::from_sqlite(path, title).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+    if report.is_empty() {
+        anyhow::bail!("{} has no measured pairs to report on", path.display());
+    }
+    fs::write(out, report.render()).map_err(|e| anyhow::anyhow!("{}: {}", out.display(), e))?;
+    println!("wrote {}", out.display());
+    Ok(())
 }
 
-/// A function to calculate the slippage between a counterfactual and spot price.
-/// 
-/// Args:
-/// - counterfactual: The counterfactual price, i.e., the simulated output
-/// - spot: The spot price
-/// 
-/// Returns:
-/// - The slippage as a U256, or an error for overflows
-pub fn calc_slippage (
-    counterfactual: &U256,
-    spot: &U256,
-) -> Result<Slippage, SlippageError> {
-    let slip_num: U256 = counterfactual
-        .checked_sub(*spot)
-        .ok_or(SlippageError::Overflow)?;
-
-    let slip_den: U256 = *spot;
-
-    let slippage: Slippage = Slippage::new(slip_num, slip_den);
+fn parse_pair(s: &str) -> Result<(TokenId, TokenId), String> {
+    let (token_in, token_out) = s
+        .split_once('/')
+        .ok_or_else(|| format!("\"{}\" isn't a pair, expected TOKEN_IN/TOKEN_OUT, e.g. WETH/USDC", s))?;
+    let parse = |raw: &str| raw.trim().parse::<TokenId>().map_err(|e| e.to_string());
+    Ok((parse(token_in)?, parse(token_out)?))
+}
 
+fn parse_slippage(s: &str) -> Result<f64, String> {
+    let slippage: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    if !(slippage > 0.0 && slippage < 1.0) {
+        return Err(format!("{} is outside of (0, 1); pass a decimal, e.g. 0.02 for 2%", slippage));
+    }
     Ok(slippage)
 }
 
-/// A function to check if a given slippage is under a target size, expressed as a decimal.
-/// 
-/// Args:
-/// - slippage: The slippage to check
-/// - target_slippage: The target slippage, expressed as a decimal, e.g., 0.02 for 2%
-/// 
-/// Returns:
-/// - True if the slippage is <= the target, false otherwise
+fn parse_precision(s: &str) -> Result<f64, String> {
+    let precision: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    if !(precision > 0.0 && precision < 1.0) {
+        return Err(format!("{} is outside of (0, 1); pass a decimal, e.g. 0.0001 for 0.01%", precision));
+    }
+    Ok(precision)
+}
 
-pub fn check_slippage_under_target(
-    slippage: &Slippage,
-    target_slippage: f64,
-) -> bool {
-    // Set precision to 1,000,000 for this.
-    let scale: f64 = 1_000_000.0; 
+fn parse_tvl(s: &str) -> Result<f64, String> {
+    let tvl: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    if !(tvl.is_finite() && tvl >= 0.0) {
+        return Err(format!("{} must be a non-negative number of ETH", tvl));
+    }
+    Ok(tvl)
+}
 
-    // Decompose our precision into two ints
-    let targ_num: U256 = U256::from((target_slippage * scale).round() as u128);
-    let targ_den: U256 = U256::from(scale);
+fn parse_tvl_multiple(s: &str) -> Result<f64, String> {
+    let multiple: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    if !(multiple.is_finite() && multiple > 0.0) {
+        return Err(format!("{} must be a positive multiple of the pool's TVL", multiple));
+    }
+    Ok(multiple)
+}
 
-    &slippage.num * &targ_den <= &slippage.den * &targ_num
+fn parse_unwind_notional(s: &str) -> Result<f64, String> {
+    let notional: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    if !(notional.is_finite() && notional >= 0.0) {
+        return Err(format!("{} must be a non-negative amount of the output token", notional));
+    }
+    Ok(notional)
 }
 
-/// A function to check if the slippage is within a given tolerance of the target slippage.
-/// 
-/// Args:
-/// - slippage: The slippage to check
-/// - target_slippage: The target slippage, expressed as a decimal, e.g., 0.02 for 2%
-/// - precision: The precision of the tolerance, expressed as a decimal, e.g., 0.0001 for 0.01%
-/// 
-/// slippage.num   targ_num    prec_num
-/// ------------ - -------- <= --------
-/// slippage.den   targ_den    prec_den
-///
-/// slippage.num * targ_den - targ_num * slippage.den     prec_num
-/// ------------------------------------------------- <=  --------
-///              slippage.den * targ_den                  prec_den
-/// 
-/// prec_den * (slippage.num * targ_den - targ_num * slippage.den) <= prec_num * slippage.den * targ_den
-/// 
-/// But here I need the absolute value of the difference, so I call the difference "abs_diff" and ensure it's positive
-/// with an if/else statement.
-/// 
-/// prec_den * |abs_diff| <= prec_num * slippage.den * targ_den
-/// 
-/// Returns: true if the slippage is within { tolerance } of the target slippage, false otherwise
-pub fn check_slippage_vs_target_within_tolerance(
-    slippage: &Slippage,
-    target_slippage: f64,
-    precision: f64,
-) -> Result<bool, SlippageError> {
-    // Set precision to 1 billion for this.
-    let scale: f64 = 1_000_000_000.0; 
+/// A function to parse --parallel-blocks, which can't be zero or hold more blocks than memory allows.
+fn parse_parallel_blocks(s: &str) -> Result<usize, String> {
+    let blocks: usize = s.parse().map_err(|e| format!("{}", e))?;
+    if !(1..=MAX_PARALLEL_BLOCKS).contains(&blocks) {
+        return Err(format!("{} must be between 1 and {} blocks", blocks, MAX_PARALLEL_BLOCKS));
+    }
+    Ok(blocks)
+}
 
-    // Decompose our target slippage into two ints  
-    let targ_num: U256 = U256::from((target_slippage * scale).round() as u128);
-    let targ_den: U256 = U256::from(scale);
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    // Warnings by default, so reconnect attempts show up without RUST_LOG. On stderr, so they never
+    // interleave with JSON on stdout, or in a file while the dashboard has the terminal.
+    let writer: BoxMakeWriter = if cli.tui {
+        BoxMakeWriter::new(tracing_appender::rolling::never("logs", "binary_search.log"))
+    } else {
+        BoxMakeWriter::new(io::stderr)
+    };
+    tracing_subscriber::fmt()
+        .with_writer(writer)
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("warn")),
+        )
+        .init();
+    if let Some(Command::Config { command: ConfigCommand::Validate { path } }) = &cli.command {
+        return validate_config(path);
+    }
+    if let Some(Command::Report { out, from_sqlite: Some(path), title }) = &cli.command {
+        return write_sqlite_report(path, out, title);
+    }
 
-    // Decompose our precision into two ints
-    let prec_num: U256 = U256::from((precision * scale).round() as u128);
-    let prec_den: U256 = U256::from(scale);
+    // ── settings: CLI flags win over the config file, which wins over defaults ──
+    let config: Config = match &cli.config {
+        Some(path) => Config::load(path).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?,
+        None => Config::default(),
+    };
+    let mut chains: Vec<Chain> = if cli.chain.is_empty() { config.chains()? } else { cli.chain.clone() };
+    if chains.is_empty() {
+        chains.push(Chain::Unichain);
+    }
+    // Repeats anywhere in the list, e.g. `--chain base,ethereum,base`, keeping the first.
+    let mut seen: HashSet<Chain> = HashSet::new();
+    chains.retain(|chain| seen.insert(*chain));
+    if cli.tui {
+        if chains.len() > 1 {
+            anyhow::bail!("--tui shows one chain; pass a single --chain");
+        }
+        if matches!(cli.output, OutputFormat::Json | OutputFormat::Ndjson) {
+            anyhow::bail!("--tui draws on the terminal, so stdout can't also carry --output json");
+        }
+        if !matches!(cli.command, None | Some(Command::Monitor | Command::Replay { .. })) {
+            anyhow::bail!("--tui is for runs that measure block after block: plain runs, monitor and replay");
+        }
+    }
+    if chains.len() > 1 {
+        if cli.stdio || matches!(cli.command, Some(Command::Serve { .. })) {
+            anyhow::bail!("--stdio and serve answer from one chain; pass a single --chain");
+        }
+        if env::var("TYCHO_URL").is_ok() {
+            anyhow::bail!("TYCHO_URL names one chain's endpoints; with several chains, set [endpoints] per chain in the config");
+        }
+    }
+    // Arbitrum and BSC have no hosted endpoint, so say so before anything starts rather than
+    // halfway through another chain's setup.
+    if env::var("TYCHO_URL").is_err() {
+        for chain in chains.iter() {
+            if config.endpoint_urls(chain).is_none() && default_tycho_url(chain).is_none() {
+                anyhow::bail!(
+                    "{} has no hosted Tycho endpoint; give one under [endpoints] in the config, or in TYCHO_URL",
+                    chain
+                );
+            }
+        }
+    }
+    // Each chain has its own stream, pools and sinks; they run side by side until all are done, or
+    // until one fails.
+    futures::future::try_join_all(chains.iter().map(|chain| run_chain(&cli, &config, *chain, &chains))).await?;
+    Ok(())
+}
 
-    let abs_diff: U256 = if
-        slippage.num
-        .checked_mul(targ_den).ok_or(SlippageError::Overflow)?
-            >
-        targ_num
-        .checked_mul(slippage.den).ok_or(SlippageError::Overflow)? {
-            slippage.num
-            .checked_mul(targ_den).ok_or(SlippageError::Overflow)?
-                -
-            targ_num
-            .checked_mul(slippage.den).ok_or(SlippageError::Overflow)?
+/// A function to track one chain's pairs for the run: its own stream, token list, pools and sinks.
+///
+/// Args:
+/// - cli, config: The run's settings
+/// - chain: The chain to stream
+/// - chains: Every chain of the run. Pairs prefixed with another chain are left to that chain's
+///   run, and with more than one, records and console lines are told apart by chain and each chain
+///   writes its own files
+async fn run_chain(cli: &Cli, config: &Config, chain: Chain, chains: &[Chain]) -> anyhow::Result<()> {
+    let multi_chain: bool = chains.len() > 1;
+    // --pair (or --token-in/--token-out) picks pairs on the command line; otherwise every pair in
+    // the config is tracked. Picking one of the config's pairs keeps its targets.
+    let mut cli_pairs: Vec<(TokenId, TokenId)> = cli.pair.clone();
+    if let (Some(token_in), Some(token_out)) = (&cli.token_in, &cli.token_out) {
+        cli_pairs.push((token_in.clone(), token_out.clone()));
+    }
+    let mut pair_specs: Vec<PairSpec> = Vec::new();
+    if cli_pairs.is_empty() {
+        for pair in config.pairs.iter() {
+            let (token_in, token_out) = pair.token_ids()?;
+            pair_specs.push(PairSpec {
+                token_in,
+                token_out,
+                slippages: config.resolve_targets(Some(pair), cli.slippage.as_deref())?,
+                reference: pair.reference.clone(),
+                usd_price: pair.usd_price.clone(),
+            });
+        }
+    } else {
+        for (token_in, token_out) in cli_pairs.into_iter() {
+            let configured: Option<&PairConfig> = config
+                .pairs
+                .iter()
+                .find(|pair| pair.token_ids().is_ok_and(|ids| ids == (token_in.clone(), token_out.clone())));
+            let slippages: Vec<f64> = config.resolve_targets(configured, cli.slippage.as_deref())?;
+            let reference: Option<OracleConfig> = configured.and_then(|pair| pair.reference.clone());
+            let usd_price: Option<OracleConfig> = configured.and_then(|pair| pair.usd_price.clone());
+            pair_specs.push(PairSpec { token_in, token_out, slippages, reference, usd_price });
+        }
+    }
+    if pair_specs.is_empty() && !cli.stdio {
+        anyhow::bail!("no pair to track: pass --pair or --token-in and --token-out, or add a [[pairs]] entry to the config");
+    }
+    let precision: f64 = cli.precision.or(config.precision).unwrap_or(DEFAULT_PRECISION);
+    // Targets are compared as exact ratios; one that isn't a slippage would fail in every pool, so
+    // fail once, before connecting.
+    for value in pair_specs.iter().flat_map(|spec| spec.slippages.iter()).chain([&precision]) {
+        Slippage::try_from(*value)?;
+    }
+    let usd: bool = cli.usd || config.usd;
+    // --notional prices one trade size and the impact table a ladder of them; both replace the
+    // depth search.
+    let impact_table: bool = cli.notional.is_none()
+        && cli.exact_output.is_none()
+        && (cli.impact_table || !cli.impact_sizes.is_empty() || config.impact_table || config.impact_sizes.is_some());
+    let notionals: Vec<Notional> = match &cli.notional {
+        Some(notional) => vec![notional.clone()],
+        None if !cli.impact_sizes.is_empty() => cli.impact_sizes.clone(),
+        None if impact_table => config.impact_sizes()?,
+        None => Vec::new(),
+    };
+    if impact_table && (cli.coarse || cli.both_directions || cli.two_sided) {
+        anyhow::bail!("the config's impact table can't be combined with --coarse, --both-directions or --two-sided");
+    }
+    let adaptive_precision: bool = cli.adaptive_precision || config.adaptive_precision;
+    let max_iterations: u32 = cli.max_iterations.or(config.max_iterations).unwrap_or(DEFAULT_MAX_ITERATIONS);
+    let sim_inputs: bool = cli.sim_inputs || config.sim_inputs;
+    let two_hop: bool = cli.two_hop || config.two_hop;
+    let arb_bps: Option<f64> = cli.arb_bps.or(config.arb_bps);
+    if arb_bps.is_some_and(|bps| !(bps.is_finite() && bps >= 0.0)) {
+        anyhow::bail!("--arb-bps must be a non-negative number of basis points");
+    }
+    let bracket_tvl_multiple: f64 =
+        cli.bracket_tvl_multiple.or(config.bracket_tvl_multiple).unwrap_or(DEFAULT_BRACKET_TVL_MULTIPLE);
+    let pool_limits: PoolLimitPolicy = cli.pool_limits.or(config.pool_limit_policy()?).unwrap_or_default();
+    let stability_window: usize =
+        cli.stability_window.or(config.stability_window).unwrap_or(DEFAULT_STABILITY_WINDOW);
+    let parallel_blocks: usize = match &cli.command {
+        Some(Command::Backfill { parallel_blocks, .. }) => *parallel_blocks,
+        _ => 1,
+    };
+    // Blocks measured side by side can't start from each other's results.
+    let warm_start: bool = !(cli.cold_start || config.cold_start) && parallel_blocks == 1;
+    let tvl_min: f64 = cli.tvl_min.or(config.tvl_min).unwrap_or(DEFAULT_TVL_MIN);
+    let tvl_remove: f64 = cli.tvl_remove.or(config.tvl_remove).unwrap_or(tvl_min);
+    if tvl_remove > tvl_min {
+        anyhow::bail!(
+            "--tvl-remove {} is above --tvl-min {}: pools would be dropped as soon as they're added",
+            tvl_remove,
+            tvl_min
+        );
+    }
+    let pools: Vec<String> = if cli.pools.is_empty() { config.pools.clone() } else { cli.pools.clone() };
+    let pools: Vec<String> = pools.iter().map(|id| id.trim().to_lowercase()).filter(|id| !id.is_empty()).collect();
+    let protocol_owned: HashSet<String> =
+        cli.protocol_owned.iter().chain(config.protocol_owned.iter()).map(|id| id.to_lowercase()).collect();
+    let labels: Labels = Labels::merge(config.labels().into_iter().chain(cli.labels.iter().cloned()));
+    let protocols: ProtocolFilter = protocol_filter(
+        if cli.protocols.is_empty() { &config.protocols } else { &cli.protocols },
+        if cli.exclude_protocols.is_empty() { &config.exclude_protocols } else { &cli.exclude_protocols },
+    )
+    .map_err(|e| anyhow::anyhow!("--protocols/--exclude-protocols: {}", e))?;
+    let protocol_sets: Option<[ProtocolSet; 2]> = if cli.compare_protocols.is_empty() {
+        config.protocol_sets()?
+    } else {
+        Some(comparison_pair(&cli.compare_protocols).map_err(|e| anyhow::anyhow!("--compare-protocols: {}", e))?)
+    };
+    let alert_rules: Vec<AlertRule> = if cli.alerts.is_empty() { config.alert_rules()? } else { cli.alerts.clone() };
+    let alert_command: Option<CommandNotifier> = match &cli.alert_command {
+        Some(command) => Some(command.clone()),
+        None => config.alert_command.as_deref().map(str::parse).transpose().map_err(|e: String| anyhow::anyhow!(e))?,
+    };
+    let mut notifiers: Vec<Box<dyn Notifier>> =
+        alert_command.into_iter().map(|command| Box::new(command) as Box<dyn Notifier>).collect();
+    for webhook in config.notifiers.iter() {
+        let notifier: WebhookNotifier =
+            WebhookNotifier::new(webhook.clone()).map_err(|e| anyhow::anyhow!("[[notifiers]]: {}", e))?;
+        notifiers.push(Box::new(notifier));
+    }
+    let usd_priced: bool = usd || pair_specs.iter().any(|spec| spec.usd_price.is_some());
+    if alert_rules.iter().any(|rule| matches!(rule.below, Notional::Usd(_))) && !usd_priced {
+        anyhow::bail!("an alert with a dollar threshold needs --usd (or usd = true in the config)");
+    }
+    let max_blocks: usize = match cli.command {
+        Some(Command::Monitor | Command::Backfill { .. } | Command::Replay { .. }) => 0,
+        _ => cli.blocks.or(config.blocks).unwrap_or(5),
+    };
+    let long_run: bool = max_blocks == 0 || cli.soak.is_some();
+    let full_every: u64 = cli.full_every.or(config.output.full_every).unwrap_or(1);
+    let workers: usize = cli.workers.or(config.workers).unwrap_or_else(default_workers);
+    let concurrency: ConcurrencyLimits = ConcurrencyLimits::new(&config.concurrency);
+    let every_n_blocks: u64 = cli.every_n_blocks.or(config.every_n_blocks).unwrap_or(1);
+    let schedule: SampleSchedule = if cli.sample_jitter || config.sample_jitter {
+        SampleSchedule::jittered(every_n_blocks)
+    } else {
+        SampleSchedule::new(every_n_blocks, 0)
+    };
+    let plain: bool = cli.plain || config.output.plain;
+    let bundle_path: Option<PathBuf> = cli.bundle.clone().or_else(|| config.output.bundle.clone());
+    let results_path: Option<PathBuf> = cli.results.clone().or_else(|| config.output.results.clone());
+    let csv_path: Option<PathBuf> = cli.csv.clone().or_else(|| config.output.csv.clone());
+    let sqlite_path: Option<PathBuf> = cli.sqlite.clone().or_else(|| config.output.sqlite.clone());
+    let compression: Option<Compression> = match cli.compress {
+        Some(compression) => Some(compression),
+        None => config.output.compression()?,
+    };
+    let partition: Option<Partition> = match cli.partition_by {
+        Some(partition) => Some(partition),
+        None => config.output.partition()?,
+    };
+    // Side by side, chains would share every file; each gets its own unless the files are already
+    // split by chain.
+    let per_chain = |path: PathBuf, partitioned: bool| -> PathBuf {
+        if multi_chain && !(partitioned && partition == Some(Partition::Chain)) {
+            chain_path(&path, &chain.to_string())
         } else {
-            targ_num
-            .checked_mul(slippage.den).ok_or(SlippageError::Overflow)?
-                -
-            slippage.num
-            .checked_mul(targ_den).ok_or(SlippageError::Overflow)?
+            path
+        }
+    };
+    let bundle_path: Option<PathBuf> = bundle_path.map(|path| per_chain(path, false));
+    let results_path: Option<PathBuf> = results_path.map(|path| per_chain(path, true));
+    let csv_path: Option<PathBuf> = csv_path.map(|path| per_chain(path, true));
+    let sqlite_path: Option<PathBuf> = sqlite_path.map(|path| per_chain(path, false));
+    let checkpoint_path: Option<PathBuf> =
+        cli.checkpoint.clone().or_else(|| config.output.checkpoint.clone()).map(|path| per_chain(path, false));
+    // Recordings are already kept apart by chain, see `recording::block_path`.
+    let record_dir: Option<PathBuf> = cli.record.clone().or_else(|| config.output.record.clone());
+    let charts_dir: Option<PathBuf> =
+        cli.charts_dir.clone().or_else(|| config.output.charts_dir.clone()).map(|dir| dir.join(chain.to_string()));
+    // Partitioned by chain inside, see `columnar::partition_dir`.
+    let parquet_dir: PathBuf =
+        cli.parquet_dir.clone().or_else(|| config.output.parquet_dir.clone()).unwrap_or_else(|| PathBuf::from("parquet"));
+    #[cfg(not(feature = "charts"))]
+    if charts_dir.is_some() {
+        anyhow::bail!("--charts-dir needs a build with the charts feature: cargo build --features charts");
+    }
+    let replay: Option<Replay> = match &cli.command {
+        Some(Command::Backfill { from_block, to_block, recording, .. }) => {
+            Some(Replay::new(recording, Some((*from_block, *to_block)))?)
+        }
+        Some(Command::Replay { dir }) => Some(Replay::new(dir, None)?),
+        _ => None,
     };
-    
-    let lhs: U256 = prec_den.checked_mul(abs_diff).ok_or(SlippageError::Overflow)?;
-    let rhs: U256 = prec_num
-        .checked_mul(slippage.den).ok_or(SlippageError::Overflow)?
-        .checked_mul(targ_den).ok_or(SlippageError::Overflow)?;
+    if replay.is_some() {
+        if !cli.cross_check.is_empty() {
+            anyhow::bail!("--cross-check compares against a second live stream, which a recording doesn't have");
+        }
+        if record_dir.is_some() {
+            anyhow::bail!("--record would only copy the recording being read");
+        }
+        if checkpoint_path.is_some() {
+            anyhow::bail!("--checkpoint resumes a live run; a recording is read from its start every time");
+        }
+        if cli.sample_jitter || config.sample_jitter {
+            anyhow::bail!("--sample-jitter picks a random phase, so reading a recording twice wouldn't measure the same blocks");
+        }
+        if pair_specs.iter().any(|spec| spec.reference.is_some() || spec.usd_price.is_some()) {
+            tracing::warn!("price feeds are fetched live, so a recording's results depend on when it's read");
+        }
+    }
 
-    Ok(lhs <= rhs)
-}
+    let checkpoint: Option<Checkpoint> = match &checkpoint_path {
+        Some(path) => Checkpoint::load(path).map_err(|e| anyhow::anyhow!("--checkpoint {}: {}", path.display(), e))?,
+        None => None,
+    };
+    if let (Some(checkpoint), Some(path)) = (&checkpoint, &checkpoint_path) {
+        if checkpoint.chain != chain.to_string() {
+            anyhow::bail!("--checkpoint {} is for {}, not {}", path.display(), checkpoint.chain, chain);
+        }
+    }
 
-pub fn main() {
-    println!("Hello, world!");
+    let smallest_target: f64 = pair_specs
+        .iter()
+        .flat_map(|spec| spec.slippages.iter().copied())
+        .fold(f64::INFINITY, f64::min);
+    if precision >= smallest_target {
+        anyhow::bail!(
+            "precision {} must be smaller than every slippage target (smallest is {}), or every amount counts as converged",
+            precision,
+            smallest_target
+        );
+    }
+    if cli.curve_samples > 0 && bundle_path.is_none() && !cli.chart && charts_dir.is_none() {
+        anyhow::bail!("--curve-samples writes into the bundle; pass --bundle or set output.bundle in the config");
+    }
+    for id in pair_specs.iter().flat_map(|spec| [&spec.token_in, &spec.token_out]) {
+        id.check_chain(chains)
+            .map_err(|e| anyhow::anyhow!("{} (pass --chain to switch chains)", e))?;
+    }
+    pair_specs.retain(|spec| [&spec.token_in, &spec.token_out].iter().all(|id| id.check_chain(&[chain]).is_ok()));
+    if pair_specs.is_empty() && !cli.stdio {
+        anyhow::bail!("no pair to track on {}: every pair is prefixed with another chain", chain);
+    }
+    // Several endpoints are failed over in order of preference, see `Endpoints`.
+    let tycho_urls: Vec<String> = match env::var("TYCHO_URL") {
+        Ok(urls) => parse_endpoint_list(&urls),
+        Err(_) => match config.endpoint_urls(&chain) {
+            Some(urls) => urls.to_vec(),
+            None => vec![default_tycho_url(&chain)
+                .ok_or_else(|| anyhow::anyhow!("no default Tycho endpoint for {}, set TYCHO_URL", chain))?],
+        },
+    };
+    if tycho_urls.is_empty() {
+        anyhow::bail!("TYCHO_URL lists no endpoints");
+    }
+    let stall_timeout: Duration = Duration::from_secs(
+        cli.stall_timeout.or(config.stall_timeout_secs).unwrap_or(DEFAULT_STALL_TIMEOUT_SECS),
+    );
+    // Every setting as this chain's run resolved it, flags and defaults folded in, for the bundle.
+    let mut protocol_owned_ids: Vec<String> = protocol_owned.iter().cloned().collect();
+    protocol_owned_ids.sort_unstable();
+    let resolved_config: Config = Config {
+        chain: Some(ChainList::One(chain.to_string())),
+        endpoints: HashMap::from([(chain.to_string(), EndpointList::Many(tycho_urls.clone()))]),
+        precision: Some(precision),
+        adaptive_precision,
+        stability_window: Some(stability_window),
+        max_iterations: Some(max_iterations),
+        bracket_tvl_multiple: Some(bracket_tvl_multiple),
+        pool_limits: Some(pool_limits.to_string()),
+        cold_start: !warm_start,
+        sim_inputs,
+        usd,
+        impact_table,
+        impact_sizes: impact_table.then(|| notionals.iter().map(Notional::to_string).collect()),
+        two_hop,
+        arb_bps,
+        tvl_min: Some(tvl_min),
+        tvl_remove: Some(tvl_remove),
+        pools: pools.clone(),
+        protocol_owned: protocol_owned_ids,
+        protocols: if cli.protocols.is_empty() { config.protocols.clone() } else { cli.protocols.clone() },
+        exclude_protocols: if cli.exclude_protocols.is_empty() {
+            config.exclude_protocols.clone()
+        } else {
+            cli.exclude_protocols.clone()
+        },
+        compare_protocols: protocol_sets.as_ref().map(|sets| sets.iter().map(ProtocolSet::to_string).collect()),
+        alerts: alert_rules.iter().map(AlertRule::to_string).collect(),
+        alert_command: match &cli.alert_command {
+            Some(command) => Some(format!("{} {}", command.program, command.args.join(" ")).trim_end().to_string()),
+            None => config.alert_command.clone(),
+        },
+        notifiers: config.notifiers.clone(),
+        blocks: Some(max_blocks),
+        workers: Some(workers),
+        concurrency: config.concurrency.clone(),
+        every_n_blocks: Some(every_n_blocks),
+        sample_jitter: cli.sample_jitter || config.sample_jitter,
+        stall_timeout_secs: Some(stall_timeout.as_secs()),
+        labels: config
+            .labels
+            .clone()
+            .into_iter()
+            .chain(cli.labels.iter().map(|label| (label.key.clone(), label.value.clone())))
+            .collect(),
+        output: OutputConfig {
+            bundle: bundle_path.clone(),
+            plain,
+            full_every: Some(full_every),
+            results: results_path.clone(),
+            csv: csv_path.clone(),
+            sqlite: sqlite_path.clone(),
+            record: record_dir.clone(),
+            checkpoint: checkpoint_path.clone(),
+            parquet_dir: (cli.output == OutputFormat::Parquet).then(|| parquet_dir.clone()),
+            charts_dir: charts_dir.clone(),
+            compress: compression.map(|compression| compression.to_string()),
+            partition_by: partition.map(|partition| partition.to_string()),
+        },
+        // Resolved into each pair's own targets.
+        ladders: HashMap::new(),
+        slippages: None,
+        pairs: pair_specs
+            .iter()
+            .map(|spec| PairConfig {
+                token_in: spec.token_in.to_string(),
+                token_out: spec.token_out.to_string(),
+                ladder: None,
+                slippages: Some(spec.slippages.clone()),
+                reference: spec.reference.clone(),
+                usd_price: spec.usd_price.clone(),
+            })
+            .collect(),
+        display: config.display.clone(),
+    };
+    let tycho_api_key =
+        env::var("TYCHO_API_KEY").unwrap_or_else(|_| String::from("sampletoken"));
+
+    // The recording's blocks, oldest first, up to the end of a backfill's range.
+    let recorded: Option<Vec<(u64, PathBuf)>> = replay.as_ref().map(|replay| replay.blocks(chain)).transpose()?;
+    // load full token list once, from the first endpoint that answers; a recording brings the
+    // tokens of its pools instead, since the live list may have dropped some
+    let mut tokens: HashMap<Bytes, Token> = match &recorded {
+        Some(blocks) => recording_tokens(blocks)?,
+        None => load_tokens(&tycho_urls, &tycho_api_key, chain).await,
+    };
+    // Native ETH pools (e.g. Uniswap v4) quote against the zero address, which isn't a token.
+    let native_eth = Token::new(
+        "0x0000000000000000000000000000000000000000",
+        18,
+        native_symbol(&chain),
+        10_000.to_biguint().unwrap()
+    );
+    tokens.entry(native_eth.address.clone()).or_insert(native_eth);
+
+    let names: DisplayNames = config.display_names(chain)?;
+    let pairs: Vec<TrackedPair> =
+        pair_specs.into_iter().map(|spec| spec.resolve(chain, &tokens, &names)).collect::<anyhow::Result<_>>()?;
+
+    // ── the protocol stream, or the recording read in its place ───────────────
+    // The stream runs in its own supervised task, so a panic while decoding a block restarts it
+    // (with a fresh snapshot) instead of killing or hanging the run.
+    let stream_restarts: RestartCounter = RestartCounter::default();
+    let stream: StreamSettings = StreamSettings {
+        tycho_urls: tycho_urls.clone(),
+        stall_timeout,
+        tycho_api_key: tycho_api_key.clone(),
+        chain,
+        tvl_min,
+        tvl_remove,
+        pools: pools.clone(),
+        tokens: tokens.clone(),
+        protocols,
+    };
+    if cli.stdio || matches!(cli.command, Some(Command::Serve { .. })) {
+        let mut handler: RpcHandler = RpcHandler::new(chain, tokens, precision);
+        if let Some(path) = sqlite_path.clone() {
+            handler = handler.with_history(path);
+        }
+        let hub: StateHub = stream_hub(stream, stream_restarts);
+        if let Some(Command::Serve { listen }) = &cli.command {
+            let listener: TcpListener =
+                TcpListener::bind(listen).await.map_err(|e| anyhow::anyhow!("can't listen on {}: {}", listen, e))?;
+            return serve_http(listener, hub, handler, shutdown_signal()).await;
+        }
+        return serve_stdio(hub, handler).await;
+    }
+    let block_rx: mpsc::Receiver<StreamBlock> = match recorded {
+        Some(blocks) => replay_recording(blocks, chain),
+        None => spawn_protocol_stream(stream.clone(), stream_restarts.clone()),
+    };
+    // VM twins of native pools, streamed separately since a pool id can only hold one state per stream.
+    let vm_rx: Option<mpsc::Receiver<StreamBlock>> = (!cli.cross_check.is_empty())
+        .then(|| spawn_vm_stream(stream, stream_restarts.clone(), cli.cross_check.clone()));
+
+    let settings: RunSettings = RunSettings {
+        chain,
+        multi_chain,
+        mode: match &cli.command {
+            Some(Command::Unwind { notional, targets }) => RunMode::Unwind { notional: *notional, targets: targets.clone() },
+            Some(Command::Orderbook { step_bps, levels, export }) => RunMode::Orderbook {
+                step_bps: *step_bps,
+                levels: *levels,
+                export: export.clone().map(|path| per_chain(path, false)),
+            },
+            _ => RunMode::Measure,
+        },
+        measure: MeasureSettings {
+            precision,
+            max_iterations,
+            bracket_tvl_multiple,
+            pool_limits,
+            adaptive_precision,
+            sim_inputs,
+            cross_check_bps: cli.cross_check_bps,
+            price_shift: cli.price_shift,
+            exact_output: cli.exact_output.clone(),
+            coarse: cli.coarse,
+            both_directions: cli.both_directions,
+            two_sided: cli.two_sided,
+            curve_samples: cli.curve_samples,
+            price_ranges: cli.price_ranges,
+        },
+        names,
+        output: cli.output,
+        plain,
+        tui: cli.tui,
+        usd,
+        impact_table,
+        notionals,
+        two_hop,
+        arb_bps,
+        stability_window,
+        parallel_blocks,
+        warm_start,
+        pools,
+        protocol_owned,
+        labels,
+        protocol_sets,
+        alert_rules,
+        notifiers,
+        max_blocks,
+        preflight: long_run && !cli.tui && matches!(cli.command, None | Some(Command::Monitor)),
+        yes: cli.yes,
+        soak: cli.soak.map(|secs| (Duration::from_secs(secs), Duration::from_secs(cli.soak_interval))),
+        full_every,
+        workers,
+        concurrency,
+        schedule,
+        chart: cli.chart,
+        charts_dir,
+        chart_format: cli.chart_format,
+        bundle_path,
+        results_path,
+        csv_path,
+        sqlite_path,
+        parquet_dir,
+        compression,
+        partition,
+        checkpoint_path,
+        checkpoint,
+        record_dir,
+        replay,
+        report: match &cli.command {
+            Some(Command::Report { out, title, .. }) => Some((title.clone(), per_chain(out.clone(), false))),
+            _ => None,
+        },
+        resolved_config,
+        endpoint: tycho_urls[0].clone(),
+    };
+    // Stopping on a signal still goes through the summary and sink flushes.
+    BlockLoop::new(settings, pairs)?.run(block_rx, vm_rx, stream_restarts, shutdown_signal()).await
 }
-
-// Function to calculate the output amount for a given slippage tolerance.
-// 
-// Args:
-// - slippage: The slippage tolerance, as a decimal (e.g., 2% slippage = 0.02)
-// - precision: The precision of the tolerance, i.e., the the range within which we consider the slippage to be exact
-// - state: a Tycho-Simulation "state." Typically this will come from a BlockUpdate.states.
-// 
-// Returns:
-// - The output amount for the given slippage tolerance
-// pub fn calculate_output_for_slippage_tolerance(
-//     target_slippage_float: f64, // TODO: make this a Slippage struct
-//     precision: f64,
-//     state: &Box<dyn ProtocolSim>,
-//     token_in: &Token,
-//     token_out: &Token,
-// ) -> U256 {
-//     let mut left: U256 = U256::from(0);
-//     let mut right: U256 = U256::from(1_000_000_000u64);
-//     // let mut try_in: U256 = (left + right) / U256::from(2);
-//     let mut try_in: U256 = U256::from(10_u64).pow(U256::from(18_u64)); // i.e., 1 ETH
-
-//     // We pick an arbitrary scalar. TODO: will need to test this for edge cases in token amounts,
-//     // but for now let's get this working for ETH/USDC.
-//     let scale: u128 = 1_000; 
-
-//     // Decompose our target_slippage into two ints
-//     let targ_num: U256 = U256::from((target_slippage_float * scale as f64).round() as u128);
-//     let targ_den: U256 = U256::from(scale);
-//     let target_slippage: Slippage = Slippage::new(targ_num, targ_den);
-
-//     // Decompose our precision into two ints
-//     let prec_num: U256 = U256::from((precision * scale as f64).round() as u128);
-//     let prec_den: U256 = U256::from(scale);
-
-//     // Get the spot price
-//     let spot_price_float: f64 = state.spot_price(&token_in, &token_out).expect("Failed to get spot price");
-//     let spot_num: U256 = U256::from((spot_price_float * scale as f64).round() as u128);
-//     let spot_den: U256 = U256::from(scale);
-
-//     info!("Initial value assignments --------");
-//     info!("left: {:?}", left);
-//     info!("right: {:?}", right);
-//     info!("try_in: {:?}", try_in);
-//     info!("scale: {:?}", scale);
-//     info!("targ_num: {:?}", targ_num);
-//     info!("targ_den: {:?}", targ_den);
-//     info!("prec_num: {:?}", prec_num);
-//     info!("spot_price_float: {:?}", spot_price_float);
-//     info!("spot_num: {:?}", spot_num);
-//     info!("spot_den: {:?}", spot_den);
-//     info!("target_slippage_float: {:?}", target_slippage_float);
-//     info!("target_slippage: {:?}", target_slippage);
-//     info!("precision: {:?}", precision);
-//     info!("--------------------------------");
-    
-//     // First we need to double the amount in until we exceed our target slippage    
-//     let mut more_than_slippage_target: bool = false;
-//     while !more_than_slippage_target {
-//         info!("About to try to get amount out.");
-//         info!("try_in: {:?}", try_in);
-//         info!("token_in: {:?}", token_in);
-//         info!("token_out: {:?}", token_out);
-
-//         let try_out_biguint: BigUint = state.clone()
-//                 .get_amount_out(u256_to_biguint(try_in), &token_in, &token_out)
-//                 .unwrap()
-//                 .amount;
-
-//         info!("try_out_biguint: {:?}", try_out_biguint);
-
-//         let try_out: U256 = biguint_to_u256(&try_out_biguint);
-
-//         let slippage: Slippage = calc_slippage(spot_num, spot_den, try_out, try_in);
-        
-//         info!("printing slippage ---- \n Try_out: {:?} of type {:?}\n Slippage: {:?}", try_out, std::any::type_name_of_val(&try_out), slippage);
-
-//         let under_target: bool = check_slippage_under_target(
-//             &slippage,
-//             target_slippage_float,
-//             );
-        
-//         if under_target {
-//             left = try_in;
-//             try_in = try_in * U256::from(2_u32);
-//             info!("hit the under_target block");
-//         } else {
-//             more_than_slippage_target = true;
-//             right = try_in;
-//             info!("set more than slippage target to true");
-//         };
-//     }
-
-//     // We now know that the amount in is greater than our target slippage threshold.
-//     try_in = (left + right) / U256::from(2);
-
-//     let mut slippage_within_tolerance: bool = false;
-//     // Now we binary search for the exact amount in.
-//     while !slippage_within_tolerance {
-//         let try_out: U256 = biguint_to_u256(
-//             &state
-//             .get_amount_out(u256_to_biguint(try_in), &token_in, &token_out)
-//             .unwrap()
-//             .amount)
-//         ;
-
-//         let slippage: Slippage = calc_slippage(spot_num, spot_den, try_out, try_in);
-
-//         let valid: bool = check_slippage_vs_target_within_tolerance(
-//             &slippage,
-//             target_slippage_float,
-//             precision);
-
-//         if valid {
-//             slippage_within_tolerance = true;
-//         } else {
-//             if &slippage.num * &target_slippage.den < &target_slippage.num * &slippage.den {
-//                 left = try_in;
-//                 try_in = (left + right) / U256::from(2);
-//             } else if &slippage.num * &target_slippage.den > &target_slippage.num * &slippage.den {
-//                 right = try_in;
-//                 try_in = (left + right) / U256::from(2);
-//             } else {
-//                 panic!("This state should be unreachable.")
-//             }
-//         }
-//     }
-
-//     try_in
-// }
-
-// NOTES
-// If I'm trying to get the number of USDC out for 1 ETH:
-// BASE TOKEN = ETH
-// QUOTE TOKEN = USDC
-// AMOUNT IN = 1 ETH
-// AMOUNT OUT = 2700 USDC
\ No newline at end of file
@@ -0,0 +1,1045 @@
+use std::{collections::{BTreeMap, HashMap}, time::{Duration, Instant}};
+use alloy_primitives::U256;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+use tycho_simulation::{
+    models::Token,
+    protocol::state::ProtocolSim,
+    evm::protocol::{
+        u256_num::{u256_to_biguint, u256_to_f64, biguint_to_u256},
+        uniswap_v2::state::UniswapV2State,
+    },
+};
+use tycho_common::Bytes;
+use tracing::{debug, info};
+use crate::{
+    amount::one_token,
+    error::{guard_simulation, DepthError},
+    provider::{AmountOutProvider, Pool},
+};
+
+/// How close to a target a search has to get, as a decimal (0.01%), unless told otherwise.
+pub const DEFAULT_PRECISION: f64 = 0.0001;
+/// With adaptive precision, pools the bracketing crosses the largest target within this many
+/// doublings of one whole token_in (i.e. under 128 tokens) count as small.
+pub const SMALL_POOL_DOUBLINGS: u32 = 7;
+/// With adaptive precision, pools that take more doublings than this (over ~1M tokens) count as large.
+pub const LARGE_POOL_DOUBLINGS: u32 = 20;
+/// Bracketing gives up after this many doublings of one whole token_in, rather than walk a pool
+/// whose price barely moves all the way to a U256 overflow.
+pub const MAX_BRACKETING_DOUBLINGS: u32 = 128;
+/// Bracketing in a Uniswap v2-style pool stops at this multiple of its token_in reserve. Selling a
+/// hundred times the reserve fills at under a hundredth of spot, i.e. over 99% slippage, which is
+/// past any target worth measuring.
+pub const V2_RESERVE_MULTIPLE: u64 = 100;
+/// Bracketing in other pools stops at this multiple of the pool's TVL in token_in, as far as
+/// `get_limits` reports it.
+pub const DEFAULT_BRACKET_TVL_MULTIPLE: f64 = 10.0;
+/// How much adaptive precision loosens the tolerance for small pools and tightens it for large ones.
+pub const ADAPTIVE_PRECISION_FACTOR: f64 = 10.0;
+/// Bisection steps a search may take per target before it settles for the best bracket found, e.g.
+/// when the target sits on a tick boundary the tolerance can't be met across.
+pub const DEFAULT_MAX_ITERATIONS: u32 = 64;
+/// A warm-started search also tries this fraction of the hint (1/64, about 1.6%) past it, toward the
+/// target, so a pool that barely moved is bracketed in two simulations.
+pub const WARM_START_NUDGE: u64 = 64;
+/// A search that runs into the largest trade a pool will quote narrows in on it to within this
+/// fraction (1/1024, about 0.1%) before reporting depth as at least what's under it.
+pub const CEILING_RESOLUTION: u64 = 1_024;
+/// What to do with a depth the pool cut short, see `SearchResult::limit`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PoolLimitPolicy {
+    /// Report the largest amount the pool would quote as a lower bound, flagged as pool-limited.
+    #[default]
+    Report,
+    /// Fail the target with `DepthError::PoolLimited`, as if it couldn't be measured.
+    Fail,
+}
+
+impl PoolLimitPolicy {
+    /// A function to apply the policy to a finished search.
+    ///
+    /// Returns:
+    /// - The error to report instead of `result`, if the policy rejects it
+    pub fn rejects(&self, result: &SearchResult) -> Option<DepthError> {
+        match (self, &result.limit) {
+            (PoolLimitPolicy::Fail, Some(reason)) => {
+                Some(DepthError::PoolLimited { amount_in: result.amount_in, reason: reason.clone() })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for PoolLimitPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PoolLimitPolicy::Report => write!(f, "report"),
+            PoolLimitPolicy::Fail => write!(f, "fail"),
+        }
+    }
+}
+
+impl std::str::FromStr for PoolLimitPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "report" => Ok(PoolLimitPolicy::Report),
+            "fail" => Ok(PoolLimitPolicy::Fail),
+            _ => Err(format!("unknown pool limit policy \"{}\", expected one of: report, fail", s)),
+        }
+    }
+}
+
+/// A slippage as an exact, signed ratio: the share of the output at the reference a fill fell
+/// short by, negative for price improvement.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Slippage(BigRational);
+
+impl Slippage {
+    /// A function to make the slippage `num / den` of a fill worse than the reference.
+    pub fn new(num: U256, den: U256) -> Self {
+        Self(BigRational::new(to_bigint(num), to_bigint(den)))
+    }
+
+    /// A function to make the slippage of a fill that beat the reference by `num / den`.
+    pub fn improvement(num: U256, den: U256) -> Self {
+        Self(-BigRational::new(to_bigint(num), to_bigint(den)))
+    }
+
+    /// The slippage as a ratio, e.g. 1/100 for 1%.
+    pub fn ratio(&self) -> &BigRational {
+        &self.0
+    }
+
+    /// True for price improvement: the fill came out better than the reference.
+    pub fn is_improvement(&self) -> bool {
+        self.0.is_negative()
+    }
+
+    /// Lossy, for display only.
+    pub fn to_f64(&self) -> f64 {
+        self.0.to_f64().unwrap_or(f64::NAN)
+    }
+
+    /// A function to convert a decimal slippage to the ratio it's written as, exactly: 0.003 is
+    /// 3/1000, not the binary fraction nearest to it nor a rounding of it at some scale.
+    ///
+    /// Args:
+    /// - value: The slippage as a decimal, e.g. 0.003 for 0.3%
+    ///
+    /// Returns:
+    /// - The ratio, or an error if the value isn't finite or is negative
+    pub fn from_decimal(value: f64) -> Result<Self, DepthError> {
+        if !value.is_finite() || value < 0.0 {
+            return Err(DepthError::InvalidSlippage(value));
+        }
+        // `Display` writes the shortest decimal that reads back as the same f64, and never in
+        // exponent form, e.g. "0.0000001" for 1e-7.
+        let written: String = value.to_string();
+        let (whole, fraction): (&str, &str) = written.split_once('.').unwrap_or((written.as_str(), ""));
+        let digits: BigInt = format!("{}{}", whole, fraction).parse().map_err(|_| DepthError::InvalidSlippage(value))?;
+        Ok(Self(BigRational::new(digits, BigInt::from(10u32).pow(fraction.len() as u32))))
+    }
+}
+
+impl TryFrom<f64> for Slippage {
+    type Error = DepthError;
+
+    fn try_from(value: f64) -> Result<Self, Self::Error> {
+        Slippage::from_decimal(value)
+    }
+}
+
+impl std::fmt::Debug for Slippage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Slippage({})", self.0)
+    }
+}
+
+/// A function to turn an amount into an integer the ratios can be built from.
+fn to_bigint(amount: U256) -> BigInt {
+    BigInt::from(u256_to_biguint(amount))
+}
+
+/// A function to bring a non-negative integer back to an amount, if it fits in 256 bits.
+fn to_u256(value: &BigInt) -> Option<U256> {
+    value.to_biguint().filter(|value| value.bits() <= 256).map(|value| biguint_to_u256(&value))
+}
+
+/// A function to calculate the slippage of a fill: how far its execution price falls short of the
+/// spot price, once both amounts are in whole tokens.
+///
+/// ```text
+///                  amount_out / 10^dec_out
+/// execution  =  --------------------------
+///                  amount_in / 10^dec_in
+///
+/// slippage   =  1 - execution / spot
+/// ```
+///
+/// so selling 1 WETH for 2,970 USDC against a spot of 3,000 is 1% whatever the decimals. A fill
+/// better than spot, which some routes and shifted references give, is negative slippage, and one
+/// that returns nothing is 100%.
+///
+/// Args:
+/// - amount_in: What was sold, in token_in base units
+/// - amount_out: What came back, in token_out base units
+/// - decimals_in: token_in's decimals, e.g. 18 for WETH
+/// - decimals_out: token_out's decimals, e.g. 6 for USDC
+/// - spot_price: Price of one whole token_in in whole token_out
+///
+/// Returns:
+/// - The slippage as an exact ratio, or an error for an invalid spot price
+pub fn calc_slippage(
+    amount_in: U256,
+    amount_out: U256,
+    decimals_in: usize,
+    decimals_out: usize,
+    spot_price: f64,
+) -> Result<Slippage, DepthError> {
+    PriceScale::new(decimals_in, decimals_out, spot_price)?.slippage(amount_in, amount_out)
+}
+
+/// A reference price in base units, to compare execution prices with it exactly. See
+/// `calc_slippage`.
+#[derive(Debug, Clone)]
+pub(crate) struct PriceScale {
+    /// token_out base units one token_in base unit is worth at the reference: the price, exactly
+    /// as the f64 holds it, times 10^(dec_out - dec_in).
+    rate: BigRational,
+}
+
+impl PriceScale {
+    pub(crate) fn new(decimals_in: usize, decimals_out: usize, reference_price: f64) -> Result<Self, DepthError> {
+        let price: BigRational = BigRational::from_float(reference_price)
+            .filter(|price| price.is_positive())
+            .ok_or(DepthError::InvalidSpotPrice(reference_price))?;
+        let ten: BigInt = BigInt::from(10u32);
+        let rate: BigRational = if decimals_out >= decimals_in {
+            price * ten.pow((decimals_out - decimals_in) as u32)
+        } else {
+            price / ten.pow((decimals_in - decimals_out) as u32)
+        };
+        Ok(Self { rate })
+    }
+
+    /// A function to price a fill against the reference, see `calc_slippage`.
+    pub(crate) fn slippage(&self, amount_in: U256, amount_out: U256) -> Result<Slippage, DepthError> {
+        // Both in token_out base units: what the fill would return at the reference, and what it did.
+        let at_reference: BigRational = &self.rate * to_bigint(amount_in);
+        let executed: BigRational = BigRational::from_integer(to_bigint(amount_out));
+        Ok(Slippage(if executed.is_zero() {
+            // Nothing comes out: as bad as it gets.
+            BigRational::one()
+        } else if at_reference.is_zero() {
+            // Nothing went in, so there's no price to fall short of.
+            BigRational::zero()
+        } else {
+            (&at_reference - executed) / at_reference
+        }))
+    }
+
+    /// A function to find what `amount_out` would cost at the reference, rounded down.
+    pub(crate) fn input_at_reference(&self, amount_out: U256) -> Result<U256, DepthError> {
+        let input: BigRational = BigRational::from_integer(to_bigint(amount_out)) / &self.rate;
+        to_u256(&input.floor().to_integer()).ok_or(DepthError::Overflow)
+    }
+}
+
+/// A function to check if a given slippage is under a target size, expressed as a decimal.
+/// 
+/// Args:
+/// - slippage: The slippage to check
+/// - target_slippage: The target slippage, expressed as a decimal, e.g., 0.02 for 2%
+/// 
+/// Returns:
+/// - True if the slippage is <= the target, false otherwise. Price improvement is under any target.
+pub fn check_slippage_under_target(
+    slippage: &Slippage,
+    target_slippage: f64,
+) -> bool {
+    match Slippage::from_decimal(target_slippage) {
+        Ok(target) => *slippage <= target,
+        // Nothing is under a target that isn't a slippage.
+        Err(_) => false,
+    }
+}
+
+/// A function to check if the slippage is within a given tolerance of the target slippage.
+/// 
+/// Args:
+/// - slippage: The slippage to check
+/// - target_slippage: The target slippage, expressed as a decimal, e.g., 0.02 for 2%
+/// - precision: The precision of the tolerance, expressed as a decimal, e.g., 0.0001 for 0.01%
+/// 
+/// |slippage - target| <= precision
+///
+/// All three are exact ratios, so there's nothing to cross-multiply or overflow, and a negative
+/// slippage is simply further from the target.
+///
+/// Returns: true if the slippage is within { tolerance } of the target slippage, false otherwise
+pub fn check_slippage_vs_target_within_tolerance(
+    slippage: &Slippage,
+    target_slippage: f64,
+    precision: f64,
+) -> Result<bool, DepthError> {
+    let target: Slippage = Slippage::from_decimal(target_slippage)?;
+    let tolerance: Slippage = Slippage::from_decimal(precision)?;
+    Ok(slippage.within(&target, &tolerance))
+}
+
+impl Slippage {
+    /// A function to check `|self - target| <= tolerance`, for callers that convert the target and
+    /// tolerance once rather than on every check.
+    pub(crate) fn within(&self, target: &Slippage, tolerance: &Slippage) -> bool {
+        (&self.0 - &target.0).abs() <= tolerance.0
+    }
+}
+
+/// The outcome of a depth search for one pool and direction.
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    /// The largest amount of token_in found whose slippage is within tolerance of the target, or
+    /// the amount as given to `calculate_slippage_for_amount`.
+    pub amount_in: U256,
+    /// What the pool returns for `amount_in`.
+    pub amount_out: U256,
+    /// The slippage of that swap relative to the reference price.
+    pub slippage: Slippage,
+    /// How many `get_amount_out` calls the search used.
+    pub simulations: u32,
+    /// Wall-clock time the search took, including every simulation.
+    pub elapsed: Duration,
+    /// Bisection steps this target took; zero for single simulations.
+    pub iterations: u32,
+    /// False if the search stopped short of the tolerance, at the iteration cap or because the
+    /// bracket collapsed, and `amount_in` is the largest amount found under the target.
+    pub converged: bool,
+    /// The gas `get_amount_out` reported for `amount_in`; zero if it wasn't simulated, e.g. a depth
+    /// of zero.
+    pub gas: U256,
+    /// Why the pool stopped the search short of the target, e.g. it runs out of initialized ticks
+    /// past some size or is still under the target at the bracketing cap. `amount_in` is then only
+    /// a lower bound on depth, the largest amount the pool would quote, and `converged` is false.
+    pub limit: Option<String>,
+}
+
+/// A function to calculate the amount of token_in that can be swapped before slippage relative to
+/// the pool's spot price reaches the target.
+///
+/// Args:
+/// - target_slippage: The slippage tolerance, as a decimal (e.g., 2% slippage = 0.02)
+/// - precision: How close to the target counts as exact, as a decimal (e.g., 0.0001 for 0.01%)
+/// - state: a Tycho-Simulation "state." Typically this will come from a BlockUpdate.states.
+/// - token_in: The token being sold
+/// - token_out: The token being bought
+///
+/// Returns:
+/// - The converged amount in, its output, and its slippage
+pub fn calculate_output_for_slippage_tolerance(
+    target_slippage: f64,
+    precision: f64,
+    state: &dyn ProtocolSim,
+    token_in: &Token,
+    token_out: &Token,
+) -> Result<SearchResult, DepthError> {
+    let spot_price: f64 = guard_simulation(|| state.spot_price(token_in, token_out))?;
+
+    calculate_output_for_reference_price(target_slippage, precision, state, token_in, token_out, spot_price)
+}
+
+/// A function to price one fixed-size swap against a reference, the inverse of the depth search:
+/// the amount is given and the slippage is the answer, so it takes a single simulation.
+///
+/// Args:
+/// - amount_in: The amount of token_in to sell, in base units
+/// - state: The pool's ProtocolSim state
+/// - token_in: The token being sold
+/// - token_out: The token being bought
+/// - reference_price: As for `calculate_output_for_reference_price`
+///
+/// Returns:
+/// - The swap's output and slippage, with `amount_in` as given
+pub fn calculate_slippage_for_amount(
+    amount_in: U256,
+    state: &dyn ProtocolSim,
+    token_in: &Token,
+    token_out: &Token,
+    reference_price: f64,
+) -> Result<SearchResult, DepthError> {
+    slippage_for_amount(amount_in, Pool::Tycho(state), token_in, token_out, reference_price)
+}
+
+/// `calculate_slippage_for_amount` over any `Pool`.
+pub(crate) fn slippage_for_amount(
+    amount_in: U256,
+    pool: Pool,
+    token_in: &Token,
+    token_out: &Token,
+    reference_price: f64,
+) -> Result<SearchResult, DepthError> {
+    let mut sim: Simulator = Simulator::new(pool, token_in, token_out, reference_price)?;
+    let (amount_out, slippage) = sim.simulate(amount_in)?;
+    Ok(sim.result(amount_in, amount_out, slippage))
+}
+
+/// A function to find the input that receives an exact output, the exact-output counterpart of
+/// `calculate_slippage_for_amount`: e.g. how much ETH it takes to receive 1,000,000 USDC.
+///
+/// Pools only quote exact input, so this runs the search the other way round: double the input
+/// until `get_amount_out` reaches the target, then bisect down to the smallest input that still
+/// does.
+///
+/// Args:
+/// - amount_out: The output wanted, in token_out base units
+/// - max_slippage: The slippage constraint, as a decimal; the search gives up once a fill that
+///   still falls short is already past it, since more input only costs more
+/// - precision: How far above the smallest input the answer may be, as a decimal
+/// - state: The pool's ProtocolSim state
+/// - token_in: The token being sold
+/// - token_out: The token being bought
+/// - reference_price: As for `calculate_output_for_reference_price`
+///
+/// Returns:
+/// - The fill, with `amount_in` the input required and `amount_out` what it returns (at least the
+///   target), or `DepthError::OutputOutOfReach` if the output can't be had within `max_slippage`
+#[allow(clippy::too_many_arguments)]
+pub fn calculate_input_for_output(
+    amount_out: U256,
+    max_slippage: f64,
+    precision: f64,
+    state: &dyn ProtocolSim,
+    token_in: &Token,
+    token_out: &Token,
+    reference_price: f64,
+) -> Result<SearchResult, DepthError> {
+    input_for_output(amount_out, max_slippage, precision, Pool::Tycho(state), token_in, token_out, reference_price)
+}
+
+/// `calculate_input_for_output` over any `Pool`.
+pub(crate) fn input_for_output(
+    amount_out: U256,
+    max_slippage: f64,
+    precision: f64,
+    pool: Pool,
+    token_in: &Token,
+    token_out: &Token,
+    reference_price: f64,
+) -> Result<SearchResult, DepthError> {
+    let mut sim: Simulator = Simulator::new(pool, token_in, token_out, reference_price)?;
+    let out_of_reach = || DepthError::OutputOutOfReach { amount_out, max_slippage };
+
+    // Bracket: the largest input known to fall short, and one that reaches the target. Starting
+    // from what the output costs at the reference, which only falls short by the slippage.
+    let mut short_in: U256 = U256::ZERO;
+    let mut try_in: U256 = sim.scale.input_at_reference(amount_out)?.max(U256::from(1u64));
+    let mut doublings: u32 = 0;
+    let (mut enough_in, mut enough_out, mut enough_slippage) = loop {
+        let (try_out, slippage) = sim.simulate(try_in)?;
+        debug!("bracketing: try_in {} try_out {} slippage {:?}", try_in, try_out, slippage);
+        if try_out >= amount_out {
+            break (try_in, try_out, slippage);
+        }
+        if !check_slippage_under_target(&slippage, max_slippage) {
+            return Err(out_of_reach());
+        }
+        short_in = try_in;
+        doublings += 1;
+        if doublings > MAX_BRACKETING_DOUBLINGS {
+            return Err(out_of_reach());
+        }
+        try_in = try_in.checked_mul(U256::from(2u64)).ok_or(DepthError::Overflow)?;
+    };
+
+    // Bisect until the bracket is within `precision` of the answer, rounding up so the output is
+    // always reached.
+    let prec: BigRational = Slippage::from_decimal(precision)?.ratio().clone();
+    let tolerance = |amount_in: U256| -> U256 {
+        to_u256(&(&prec * to_bigint(amount_in)).floor().to_integer()).unwrap_or(U256::MAX)
+    };
+    while enough_in - short_in > tolerance(enough_in).max(U256::from(1u64)) {
+        let try_in: U256 = short_in + (enough_in - short_in) / U256::from(2u64);
+        let (try_out, slippage) = sim.simulate(try_in)?;
+        debug!("bisecting: try_in {} try_out {} slippage {:?}", try_in, try_out, slippage);
+        if try_out >= amount_out {
+            (enough_in, enough_out, enough_slippage) = (try_in, try_out, slippage);
+        } else {
+            short_in = try_in;
+        }
+    }
+    if !check_slippage_under_target(&enough_slippage, max_slippage) {
+        return Err(out_of_reach());
+    }
+    Ok(sim.result(enough_in, enough_out, enough_slippage))
+}
+
+/// Like `calculate_output_for_slippage_tolerance`, but measures slippage against a caller-supplied
+/// reference price instead of the pool's spot, e.g. a scenario-shifted spot.
+///
+/// Args:
+/// - reference_price: Price of token_in in token_out, in whole-token units (same as `spot_price`)
+///
+/// Slippage is how far the fill's execution price falls short of the reference price, in whole
+/// tokens, as `calc_slippage` defines it.
+pub fn calculate_output_for_reference_price(
+    target_slippage: f64,
+    precision: f64,
+    state: &dyn ProtocolSim,
+    token_in: &Token,
+    token_out: &Token,
+    reference_price: f64,
+) -> Result<SearchResult, DepthError> {
+    let mut results: Vec<SearchResult> = calculate_outputs_for_reference_price(
+        &[target_slippage],
+        precision,
+        state,
+        token_in,
+        token_out,
+        reference_price,
+    )?;
+    Ok(results.remove(0))
+}
+
+/// A function to calculate depth at several slippage targets in one pass, e.g. 0.1%, 0.5%, 1% and
+/// 2% for the same pool.
+///
+/// The exponential bracketing runs once, up to the largest target, and every `get_amount_out`
+/// result is kept so each target's binary search starts from the tightest bracket already known.
+///
+/// Args:
+/// - targets: The slippage tolerances, as decimals, in any order
+/// - precision: How close to a target counts as exact, as a decimal
+/// - state: The pool state
+/// - token_in: The token being sold
+/// - token_out: The token being bought
+///
+/// Returns:
+/// - One result per target, in the order of `targets`. `simulations` and `elapsed` are cumulative
+///   up to the point that target converged, since evaluations are shared.
+pub fn calculate_outputs_for_slippage_targets(
+    targets: &[f64],
+    precision: f64,
+    state: &dyn ProtocolSim,
+    token_in: &Token,
+    token_out: &Token,
+) -> Result<Vec<SearchResult>, DepthError> {
+    let spot_price: f64 = guard_simulation(|| state.spot_price(token_in, token_out))?;
+
+    calculate_outputs_for_reference_price(targets, precision, state, token_in, token_out, spot_price)
+}
+
+/// Like `calculate_outputs_for_slippage_targets`, but against a caller-supplied reference price.
+/// See `calculate_output_for_reference_price` for how slippage is measured.
+pub fn calculate_outputs_for_reference_price(
+    targets: &[f64],
+    precision: f64,
+    state: &dyn ProtocolSim,
+    token_in: &Token,
+    token_out: &Token,
+    reference_price: f64,
+) -> Result<Vec<SearchResult>, DepthError> {
+    search_targets(
+        targets,
+        &[],
+        precision,
+        DEFAULT_MAX_ITERATIONS,
+        DEFAULT_BRACKET_TVL_MULTIPLE,
+        false,
+        Pool::Tycho(state),
+        token_in,
+        token_out,
+        reference_price,
+    )
+}
+
+/// Like `calculate_outputs_for_reference_price`, but `precision` is adapted to the pool's size once
+/// bracketing has found it, see `adaptive_precision`. Small pools stop sooner; large pools, whose
+/// headline numbers matter most, get a tighter tolerance.
+pub fn calculate_outputs_with_adaptive_precision(
+    targets: &[f64],
+    precision: f64,
+    state: &dyn ProtocolSim,
+    token_in: &Token,
+    token_out: &Token,
+    reference_price: f64,
+) -> Result<Vec<SearchResult>, DepthError> {
+    search_targets(
+        targets,
+        &[],
+        precision,
+        DEFAULT_MAX_ITERATIONS,
+        DEFAULT_BRACKET_TVL_MULTIPLE,
+        true,
+        Pool::Tycho(state),
+        token_in,
+        token_out,
+        reference_price,
+    )
+}
+
+/// Like `calculate_outputs_for_reference_price`, but starting each target's search from a hint,
+/// typically the input it converged to on the previous block, instead of bracketing up from one
+/// whole token_in.
+///
+/// Liquidity barely moves between blocks, so a hint and a nudge past it usually bracket the target
+/// straight away and the bisection starts narrow. If the pool did move, the search widens from
+/// there as usual. Hints are only used when every target has one; otherwise this is the cold search.
+///
+/// Args:
+/// - hints: One per target, in the order of `targets`; None or zero for no hint
+/// - max_iterations: Bisection steps per target before settling for the best bracket found, see
+///   `SearchResult::converged`
+/// - bracket_tvl_multiple: Where bracketing gives up in pools that aren't Uniswap v2-style, as a
+///   multiple of the pool's TVL, see `bracket_cap`
+/// - adaptive: Adapt `precision` to the pool's size, as `calculate_outputs_with_adaptive_precision`
+///   does, judged from the hints rather than the bracketing
+/// - The rest as for `calculate_outputs_for_reference_price`
+///
+/// Returns:
+/// - One result per target, in the order of `targets`
+#[allow(clippy::too_many_arguments)]
+pub fn calculate_outputs_from_hints(
+    targets: &[f64],
+    hints: &[Option<U256>],
+    precision: f64,
+    max_iterations: u32,
+    bracket_tvl_multiple: f64,
+    adaptive: bool,
+    state: &dyn ProtocolSim,
+    token_in: &Token,
+    token_out: &Token,
+    reference_price: f64,
+) -> Result<Vec<SearchResult>, DepthError> {
+    search_targets(
+        targets,
+        hints,
+        precision,
+        max_iterations,
+        bracket_tvl_multiple,
+        adaptive,
+        Pool::Tycho(state),
+        token_in,
+        token_out,
+        reference_price,
+    )
+}
+
+/// Where each search converged, per pool, token sold and slippage target, to warm-start the next
+/// block's search with `calculate_outputs_from_hints`.
+#[derive(Debug, Default, Clone)]
+pub struct WarmStarts {
+    inputs: HashMap<(String, Bytes, u64), U256>,
+}
+
+impl WarmStarts {
+    /// A function to look up the hints for one pool and direction.
+    ///
+    /// Returns:
+    /// - One hint per target, in the order of `targets`
+    pub fn hints(&self, pool_id: &str, token_in: &Bytes, targets: &[f64]) -> Vec<Option<U256>> {
+        targets
+            .iter()
+            .map(|target| self.inputs.get(&(pool_id.to_string(), token_in.clone(), target.to_bits())).copied())
+            .collect()
+    }
+
+    /// A function to remember where one pool and direction converged, in the order of `targets`.
+    pub fn record(&mut self, pool_id: &str, token_in: &Bytes, targets: &[f64], results: &[SearchResult]) {
+        for (target, result) in targets.iter().zip(results.iter()) {
+            self.inputs.insert((pool_id.to_string(), token_in.clone(), target.to_bits()), result.amount_in);
+        }
+    }
+
+    /// A function to drop the hints of pools that are no longer tracked.
+    pub fn retain(&mut self, keep: impl Fn(&str) -> bool) {
+        self.inputs.retain(|(pool_id, _, _), _| keep(pool_id));
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
+
+/// A function to adapt the search tolerance to the size of the pool.
+///
+/// Size is read off the bracketing: how many doublings from one whole token_in it took to get past
+/// the largest target. In a pool that's only a few tokens deep, sub-bps precision buys dust and
+/// costs simulations; in a pool millions of tokens deep, a bps is a lot of size.
+///
+/// Args:
+/// - precision: The tolerance asked for, as a decimal
+/// - bracket_in: The first bracketing amount over the largest target
+/// - one_token: One whole token_in, where bracketing starts
+/// - smallest_target: The smallest slippage target; a loosened tolerance stays a tenth of it at most
+///
+/// Returns:
+/// - The tolerance to search with
+pub fn adaptive_precision(precision: f64, bracket_in: U256, one_token: U256, smallest_target: f64) -> f64 {
+    let doublings: u32 = (bracket_in / one_token.max(U256::from(1u64))).bit_len().saturating_sub(1) as u32;
+    if doublings < SMALL_POOL_DOUBLINGS {
+        (precision * ADAPTIVE_PRECISION_FACTOR).min(smallest_target / 10.0).max(precision)
+    } else if doublings > LARGE_POOL_DOUBLINGS {
+        precision / ADAPTIVE_PRECISION_FACTOR
+    } else {
+        precision
+    }
+}
+
+/// A function to find the largest input worth bracketing up to in a pool, so the doubling stops at a
+/// size the pool could plausibly fill instead of overflowing or tripping up the simulation.
+///
+/// Uniswap v2-style pools are capped from their reserves, see `V2_RESERVE_MULTIPLE`. Other pools
+/// are valued from `get_limits`, both sides in token_in at the reference price, and capped at
+/// `tvl_multiple` times that.
+///
+/// Args:
+/// - state: The pool state
+/// - token_in: The token sold
+/// - token_out: The token bought
+/// - reference_price: Price of token_in in token_out, in whole-token units
+/// - tvl_multiple: How many times the pool's TVL to bracket up to in pools that aren't v2-style
+///
+/// Returns:
+/// - The cap in token_in base units, or None if the pool reports no usable limits, in which case
+///   only `MAX_BRACKETING_DOUBLINGS` stops the bracketing
+pub fn bracket_cap(
+    state: &dyn ProtocolSim,
+    token_in: &Token,
+    token_out: &Token,
+    reference_price: f64,
+    tvl_multiple: f64,
+) -> Option<U256> {
+    if let Some(v2) = state.as_any().downcast_ref::<UniswapV2State>() {
+        // Uniswap sorts pool tokens by address, so reserve0 belongs to the lower address.
+        let reserve_in: U256 = if token_in.address < token_out.address { v2.reserve0 } else { v2.reserve1 };
+        return Some(reserve_in.saturating_mul(U256::from(V2_RESERVE_MULTIPLE))).filter(|cap| !cap.is_zero());
+    }
+    let (max_in, max_out) = guard_simulation(|| state.get_limits(token_in.address.clone(), token_out.address.clone())).ok()?;
+    let tvl_in: f64 = u256_to_f64(biguint_to_u256(&max_in)) / 10f64.powi(token_in.decimals as i32)
+        + u256_to_f64(biguint_to_u256(&max_out)) / 10f64.powi(token_out.decimals as i32) / reference_price;
+    let cap: f64 = tvl_in * tvl_multiple * 10f64.powi(token_in.decimals as i32);
+    // `as` saturates, so a pool too large to count in u128 is capped at u128::MAX.
+    (cap.is_finite() && cap >= 1.0).then(|| U256::from(cap as u128))
+}
+
+/// The search behind every `calculate_outputs_*` function, over any `Pool`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn search_targets(
+    targets: &[f64],
+    hints: &[Option<U256>],
+    precision: f64,
+    max_iterations: u32,
+    bracket_tvl_multiple: f64,
+    adaptive: bool,
+    pool: Pool,
+    token_in: &Token,
+    token_out: &Token,
+    reference_price: f64,
+) -> Result<Vec<SearchResult>, DepthError> {
+    let mut sim: Simulator = Simulator::new(pool, token_in, token_out, reference_price)?;
+    sim.max_in = pool.max_in(token_in, token_out, reference_price, bracket_tvl_multiple);
+    let max_target: f64 = match targets.iter().copied().reduce(f64::max) {
+        Some(max_target) => max_target,
+        None => return Ok(Vec::new()),
+    };
+    let one_token: U256 = one_token(token_in.decimals).ok_or(DepthError::UnsupportedDecimals(token_in.decimals))?;
+
+    let hints: Option<Vec<U256>> = (hints.len() == targets.len())
+        .then(|| hints.iter().map(|hint| hint.filter(|hint| !hint.is_zero())).collect())
+        .flatten();
+    // Each hint and a nudge past it toward its target; the search bisects between them, or widens
+    // from them if the pool moved.
+    let warm_in: U256 = match hints {
+        Some(hints) => {
+            let mut bracket_in: U256 = U256::ZERO;
+            for (&target, &hint) in targets.iter().zip(hints.iter()) {
+                // A pool whose ceiling fell under last block's depth is searched afresh.
+                let slippage: Slippage = match sim.simulate(hint) {
+                    Ok((_, slippage)) => slippage,
+                    Err(DepthError::Simulation(_)) => continue,
+                    Err(e) => return Err(e),
+                };
+                let nudge: U256 = (hint / U256::from(WARM_START_NUDGE)).max(U256::from(1u64));
+                let neighbour: U256 = if check_slippage_vs_target_within_tolerance(&slippage, target, precision)? {
+                    hint
+                } else if check_slippage_under_target(&slippage, target) {
+                    hint.saturating_add(nudge)
+                } else {
+                    hint.saturating_sub(nudge).max(U256::from(1u64))
+                };
+                match sim.simulate(neighbour) {
+                    Ok((neighbour_out, neighbour_slippage)) => debug!(
+                        "warm start: hint {} neighbour {} out {} slippage {:?}",
+                        hint, neighbour, neighbour_out, neighbour_slippage
+                    ),
+                    // The hint went through, so the nudge past it is the pool's ceiling.
+                    Err(DepthError::Simulation(reason)) => debug!("warm start: neighbour {} refused: {}", neighbour, reason),
+                    Err(e) => return Err(e),
+                }
+                bracket_in = bracket_in.max(hint).max(neighbour);
+            }
+            bracket_in
+        }
+        None => U256::ZERO,
+    };
+    let bracket_in: U256 = if !warm_in.is_zero() {
+        warm_in
+    } else {
+        // Without hints, or if the pool refused every one, we need to double the amount in until we
+        // exceed the largest target slippage.
+        let mut try_in: U256 = sim.max_in.map_or(one_token, |max_in| one_token.min(max_in));
+        let mut doublings: u32 = 0;
+        loop {
+            let (try_out, slippage) = match sim.simulate(try_in) {
+                Ok(evaluation) => evaluation,
+                // Past the largest trade the pool will quote; each target's search narrows in
+                // under it.
+                Err(DepthError::Simulation(_)) if doublings > 0 => break,
+                Err(e) => return Err(e),
+            };
+            debug!("bracketing: try_in {} try_out {} slippage {:?}", try_in, try_out, slippage);
+            if check_slippage_vs_target_within_tolerance(&slippage, max_target, precision)?
+                || !check_slippage_under_target(&slippage, max_target)
+            {
+                break;
+            }
+            doublings += 1;
+            if doublings > MAX_BRACKETING_DOUBLINGS {
+                return Err(DepthError::NotConverged { target_slippage: max_target, simulations: sim.simulations() });
+            }
+            try_in = match sim.double(try_in, max_target) {
+                Ok(doubled) => doubled,
+                // Still under the largest target at the cap, which the searches report.
+                Err(DepthError::InsufficientLiquidity { .. }) => break,
+                Err(e) => return Err(e),
+            };
+        }
+        try_in
+    };
+    let precision: f64 = if adaptive {
+        let smallest_target: f64 = targets.iter().copied().fold(f64::INFINITY, f64::min);
+        let adapted: f64 = adaptive_precision(precision, bracket_in, one_token, smallest_target);
+        debug!("adaptive precision: {} -> {} (bracket {})", precision, adapted, bracket_in);
+        adapted
+    } else {
+        precision
+    };
+
+    // Smallest target first, so its bisection points tighten the brackets of the larger ones.
+    let mut order: Vec<usize> = (0..targets.len()).collect();
+    order.sort_by(|a, b| targets[*a].total_cmp(&targets[*b]));
+    let mut results: Vec<Option<SearchResult>> = vec![None; targets.len()];
+    for i in order.into_iter() {
+        results[i] = Some(sim.search(targets[i], precision, one_token, max_iterations)?);
+    }
+    Ok(results.into_iter().flatten().collect())
+}
+
+/// Runs `get_amount_out` for the search and prices the result against the reference. Every
+/// evaluation is kept so searches for several targets can share them.
+pub(crate) struct Simulator<'a> {
+    pool: Pool<'a>,
+    token_in: &'a Token,
+    token_out: &'a Token,
+    scale: PriceScale,
+    simulations: u32,
+    started: Instant,
+    evaluated: BTreeMap<U256, (U256, Slippage)>,
+    /// The gas each evaluated amount reported.
+    gas: HashMap<U256, U256>,
+    /// Where doubling stops, see `bracket_cap`.
+    max_in: Option<U256>,
+    /// The smallest amount the pool failed to quote, and why.
+    ceiling: Option<(U256, String)>,
+}
+
+impl<'a> Simulator<'a> {
+    /// See `calculate_output_for_reference_price` for how fills are priced against the reference.
+    pub(crate) fn new(
+        pool: impl Into<Pool<'a>>,
+        token_in: &'a Token,
+        token_out: &'a Token,
+        reference_price: f64,
+    ) -> Result<Self, DepthError> {
+        Ok(Self {
+            pool: pool.into(),
+            token_in,
+            token_out,
+            scale: PriceScale::new(token_in.decimals, token_out.decimals, reference_price)?,
+            simulations: 0,
+            started: Instant::now(),
+            evaluated: BTreeMap::new(),
+            gas: HashMap::new(),
+            max_in: None,
+            ceiling: None,
+        })
+    }
+
+    pub(crate) fn simulate(&mut self, amount_in: U256) -> Result<(U256, Slippage), DepthError> {
+        if let Some(evaluation) = self.evaluated.get(&amount_in) {
+            return Ok(evaluation.clone());
+        }
+        self.simulations += 1;
+        let (amount_out, gas) = match self.pool.quote(u256_to_biguint(amount_in), self.token_in, self.token_out) {
+            Ok(quote) => quote,
+            Err(DepthError::Simulation(reason)) => {
+                if self.ceiling.as_ref().is_none_or(|(ceiling, _)| amount_in < *ceiling) {
+                    self.ceiling = Some((amount_in, reason.clone()));
+                }
+                return Err(DepthError::Simulation(reason));
+            }
+            Err(e) => return Err(e),
+        };
+        let amount_out: U256 = biguint_to_u256(&amount_out);
+        self.gas.insert(amount_in, biguint_to_u256(&gas));
+
+        let slippage: Slippage = self.price(amount_in, amount_out)?;
+        self.evaluated.insert(amount_in, (amount_out, slippage.clone()));
+        Ok((amount_out, slippage))
+    }
+
+    /// A function to price a fill against the reference, e.g. an order split across pools.
+    pub(crate) fn price(&self, amount_in: U256, amount_out: U256) -> Result<Slippage, DepthError> {
+        self.scale.slippage(amount_in, amount_out)
+    }
+
+    pub(crate) fn simulations(&self) -> u32 {
+        self.simulations
+    }
+
+    /// A function to double an amount that's still under `target_slippage`, up to the cap.
+    ///
+    /// Returns:
+    /// - The next amount to try, or `DepthError::InsufficientLiquidity` if `amount_in` already is
+    ///   the cap
+    fn double(&self, amount_in: U256, target_slippage: f64) -> Result<U256, DepthError> {
+        let doubled: U256 = amount_in.checked_mul(U256::from(2u64)).ok_or(DepthError::Overflow)?;
+        match self.max_in {
+            Some(max_in) if amount_in >= max_in => {
+                Err(DepthError::InsufficientLiquidity { target_slippage, max_in })
+            }
+            Some(max_in) => Ok(doubled.min(max_in)),
+            None => Ok(doubled),
+        }
+    }
+
+    pub(crate) fn evaluated_output(&self, amount_in: U256) -> Option<U256> {
+        self.evaluated.get(&amount_in).map(|(amount_out, _)| *amount_out)
+    }
+
+    /// A function to binary search one target, starting from the tightest bracket among the
+    /// amounts already evaluated, for at most `max_iterations` new amounts.
+    fn search(
+        &mut self,
+        target_slippage: f64,
+        precision: f64,
+        one_token: U256,
+        max_iterations: u32,
+    ) -> Result<SearchResult, DepthError> {
+        let target: Slippage = Slippage::from_decimal(target_slippage)?;
+        let tolerance: Slippage = Slippage::from_decimal(precision)?;
+        let mut iterations: u32 = 0;
+        loop {
+            // The largest amount under the target and the smallest one over it, or an amount
+            // that's already within tolerance.
+            let mut left: Option<(U256, U256, Slippage)> = None;
+            let mut right: Option<U256> = None;
+            for (amount_in, (amount_out, slippage)) in self.evaluated.iter() {
+                if slippage.within(&target, &tolerance) {
+                    return Ok(SearchResult { iterations, ..self.result(*amount_in, *amount_out, slippage.clone()) });
+                }
+                if *slippage <= target {
+                    left = Some((*amount_in, *amount_out, slippage.clone()));
+                } else if right.is_none() {
+                    right = Some(*amount_in);
+                }
+            }
+            let left_in: U256 = left.as_ref().map(|(amount_in, _, _)| *amount_in).unwrap_or(U256::ZERO);
+            // The best we can do is the largest amount under the target. If there's none, even
+            // the smallest trade is over the target (e.g. the pool fee exceeds it) and the depth
+            // is zero.
+            let best = || SearchResult {
+                iterations,
+                converged: false,
+                ..match &left {
+                    Some((amount_in, amount_out, slippage)) => self.result(*amount_in, *amount_out, slippage.clone()),
+                    None => self.result(U256::ZERO, U256::ZERO, Slippage::new(U256::ZERO, U256::from(1u64))),
+                }
+            };
+            let limited = |reason: &str| SearchResult { limit: Some(reason.to_string()), ..best() };
+            // A size the pool refused to quote bounds the bracket when nothing under it is over the
+            // target yet: depth is at least what the pool will take in one trade.
+            let ceiling: Option<(U256, String)> = self
+                .ceiling
+                .clone()
+                .filter(|(ceiling, _)| *ceiling > left_in && right.is_none_or(|right| *ceiling < right));
+            if iterations >= max_iterations {
+                info!("no convergence at {} slippage after {} iterations", target_slippage, iterations);
+                return Ok(match &ceiling {
+                    Some((_, reason)) => limited(reason),
+                    None => best(),
+                });
+            }
+
+            let try_in: U256 = match (right, &ceiling) {
+                // Close enough under the ceiling to report.
+                (_, Some((ceiling, reason)))
+                    if *ceiling <= left_in + (left_in / U256::from(CEILING_RESOLUTION)).max(U256::from(1u64)) =>
+                {
+                    info!("pool-limited at {} slippage: quotes {} but not {}: {}", target_slippage, left_in, ceiling, reason);
+                    return Ok(limited(reason));
+                }
+                (_, Some((ceiling, _))) => (left_in + *ceiling) / U256::from(2u64),
+                // The target sits between two adjacent base units (e.g. a tick boundary).
+                (Some(right), None) if right <= left_in + U256::from(1u64) => {
+                    info!("bracket collapsed at {} without reaching the tolerance", left_in);
+                    return Ok(best());
+                }
+                (Some(right), None) => (left_in + right) / U256::from(2u64),
+                // Nothing evaluated is over this target yet, so keep doubling.
+                (None, None) if left_in.is_zero() => self.max_in.map_or(one_token, |max_in| one_token.min(max_in)),
+                (None, None) => match self.double(left_in, target_slippage) {
+                    Ok(doubled) => doubled,
+                    Err(DepthError::InsufficientLiquidity { .. }) => {
+                        info!("pool-limited at {} slippage: still under it at the bracketing cap {}", target_slippage, left_in);
+                        return Ok(limited("still under the target at the bracketing cap"));
+                    }
+                    Err(e) => return Err(e),
+                },
+            };
+            match self.simulate(try_in) {
+                Ok((try_out, slippage)) => {
+                    debug!("bisecting: try_in {} try_out {} slippage {:?}", try_in, try_out, slippage)
+                }
+                // A smaller amount went through, so this is the pool's ceiling; the next pass
+                // narrows in under it.
+                Err(DepthError::Simulation(reason)) if !left_in.is_zero() => {
+                    debug!("bisecting: try_in {} refused: {}", try_in, reason)
+                }
+                Err(e) => return Err(e),
+            }
+            iterations += 1;
+        }
+    }
+
+    pub(crate) fn result(&self, amount_in: U256, amount_out: U256, slippage: Slippage) -> SearchResult {
+        SearchResult {
+            amount_in,
+            amount_out,
+            slippage,
+            simulations: self.simulations,
+            elapsed: self.started.elapsed(),
+            iterations: 0,
+            converged: true,
+            gas: self.gas.get(&amount_in).copied().unwrap_or_default(),
+            limit: None,
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tycho_simulation::{
+    models::Token,
+    protocol::{models::ProtocolComponent, state::ProtocolSim},
+};
+use crate::{config::Config, labels::Labels};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BundleManifest {
+    pub tool_version: String,
+    pub created_at_unix: u64,
+    pub chain: String,
+    pub tycho_url: String,
+    /// The exact command line, so a colleague can re-run with the same flags.
+    pub args: Vec<String>,
+    /// Every setting the run used, from flags, the config file and defaults alike; also written
+    /// as `config.toml`, so `--config config.toml` re-runs the analysis without the original files.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<Config>,
+    /// The run's `--label`s, the same as on every result record.
+    pub labels: Labels,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenRecord {
+    pub address: String,
+    pub symbol: String,
+    pub decimals: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolRecord {
+    pub id: String,
+    pub protocol_system: String,
+    pub tokens: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StateHashRecord {
+    pub block_number: u64,
+    pub pool_id: String,
+    pub sha256: String,
+}
+
+/// Everything needed to audit or re-run an analysis: the resolved run settings, token metadata,
+/// the pool universe, a fingerprint of every state the results were computed from, and the
+/// results themselves.
+pub struct RunBundle {
+    manifest: BundleManifest,
+    tokens: BTreeMap<String, TokenRecord>,
+    pools: BTreeMap<String, PoolRecord>,
+    state_hashes: Vec<StateHashRecord>,
+    results: Vec<serde_json::Value>,
+}
+
+impl RunBundle {
+    pub fn new(chain: &str, tycho_url: &str) -> Self {
+        let created_at_unix: u64 = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Self {
+            manifest: BundleManifest {
+                tool_version: env!("CARGO_PKG_VERSION").to_string(),
+                created_at_unix,
+                chain: chain.to_string(),
+                tycho_url: tycho_url.to_string(),
+                args: std::env::args().collect(),
+                config: None,
+                labels: Labels::default(),
+            },
+            tokens: BTreeMap::new(),
+            pools: BTreeMap::new(),
+            state_hashes: Vec::new(),
+            results: Vec::new(),
+        }
+    }
+
+    /// Tag the bundle with the run's labels.
+    pub fn with_labels(mut self, labels: Labels) -> Self {
+        self.manifest.labels = labels;
+        self
+    }
+
+    /// Record the settings the run resolved, see `BundleManifest::config`.
+    pub fn with_config(mut self, config: &Config) -> Self {
+        self.manifest.config = Some(config.clone());
+        self
+    }
+
+    pub fn add_token(&mut self, token: &Token) {
+        let address: String = token.address.to_string();
+        self.tokens.entry(address.clone()).or_insert_with(|| TokenRecord {
+            address,
+            symbol: token.symbol.clone(),
+            decimals: token.decimals,
+        });
+    }
+
+    /// A function to record a pool and the metadata of its tokens.
+    pub fn add_pool(&mut self, id: &str, component: &ProtocolComponent) {
+        for token in component.tokens.iter() {
+            self.add_token(token);
+        }
+        self.pools.insert(
+            id.to_string(),
+            PoolRecord {
+                id: id.to_string(),
+                protocol_system: component.protocol_system.clone(),
+                tokens: component.tokens.iter().map(|t| t.address.to_string()).collect(),
+            },
+        );
+    }
+
+    /// A function to fingerprint the state a result was computed from.
+    ///
+    /// States aren't serializable, so this hashes their `Debug` rendering, made canonical first
+    /// (see `canonical_debug`) since maps inside states print in a different order every run. That's
+    /// enough to tell whether a replayed state matches the original, not to reconstruct it.
+    pub fn add_state(&mut self, block_number: u64, pool_id: &str, state: &dyn ProtocolSim) {
+        let digest = Sha256::digest(canonical_debug(&format!("{:?}", state)).as_bytes());
+        self.state_hashes.push(StateHashRecord {
+            block_number,
+            pool_id: pool_id.to_string(),
+            sha256: format!("{:x}", digest),
+        });
+    }
+
+    pub fn add_result(&mut self, result: impl Serialize) -> io::Result<()> {
+        self.results.push(serde_json::to_value(result)?);
+        Ok(())
+    }
+
+    /// A function to write the bundle as a tar archive.
+    ///
+    /// The archive is zstd-compressed unless the path ends in a bare `.tar`.
+    ///
+    /// Args:
+    /// - path: Where to write the bundle, e.g. `out.tar.zst`
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path: &Path = path.as_ref();
+        let file: File = File::create(path)?;
+        if path.extension().is_some_and(|ext| ext == "tar") {
+            self.write_tar(file)?;
+        } else {
+            let encoder = zstd::stream::write::Encoder::new(file, 0)?;
+            self.write_tar(encoder)?.finish()?;
+        }
+        Ok(())
+    }
+
+    fn write_tar<W: Write>(&self, writer: W) -> io::Result<W> {
+        let mut builder: tar::Builder<W> = tar::Builder::new(writer);
+        let tokens: Vec<&TokenRecord> = self.tokens.values().collect();
+        let pools: Vec<&PoolRecord> = self.pools.values().collect();
+
+        self.append(&mut builder, "manifest.json", &serde_json::to_vec_pretty(&self.manifest)?)?;
+        if let Some(config) = &self.manifest.config {
+            let config: String = toml::to_string(config).map_err(io::Error::other)?;
+            self.append(&mut builder, "config.toml", config.as_bytes())?;
+        }
+        self.append(&mut builder, "tokens.json", &serde_json::to_vec_pretty(&tokens)?)?;
+        self.append(&mut builder, "pools.json", &serde_json::to_vec_pretty(&pools)?)?;
+        self.append(&mut builder, "state_hashes.jsonl", &to_jsonl(&self.state_hashes)?)?;
+        self.append(&mut builder, "results.jsonl", &to_jsonl(&self.results)?)?;
+
+        builder.into_inner()
+    }
+
+    fn append<W: Write>(&self, builder: &mut tar::Builder<W>, name: &str, data: &[u8]) -> io::Result<()> {
+        let mut header: tar::Header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(self.manifest.created_at_unix);
+        header.set_cksum();
+        builder.append_data(&mut header, name, data)
+    }
+}
+
+fn to_jsonl<T: Serialize>(records: &[T]) -> io::Result<Vec<u8>> {
+    let mut out: Vec<u8> = Vec::new();
+    for record in records.iter() {
+        serde_json::to_writer(&mut out, record)?;
+        out.push(b'\n');
+    }
+    Ok(out)
+}
+
+/// A function to make a `Debug` rendering independent of hash map order: the entries of every
+/// `{ ... }` group, maps, sets and struct fields alike, are sorted, at every depth. Lists and
+/// tuples keep their order, and nothing inside string literals is touched.
+///
+/// Args:
+/// - rendered: e.g. `Pool { ticks: {3: 1, -5: 2} }`
+///
+/// Returns:
+/// - e.g. `Pool { ticks: {-5: 2, 3: 1} }`
+pub fn canonical_debug(rendered: &str) -> String {
+    let chars: Vec<char> = rendered.chars().collect();
+    let mut at: usize = 0;
+    let mut out: String = String::with_capacity(rendered.len());
+    while at < chars.len() {
+        out.push_str(&canonical_item(&chars, &mut at, None));
+    }
+    out
+}
+
+/// A function to render from `at` up to (not including) `until` at the same depth, or to the end,
+/// sorting the entries of every `{ ... }` group met on the way.
+fn canonical_item(chars: &[char], at: &mut usize, until: Option<char>) -> String {
+    let mut out: String = String::new();
+    while let Some(&c) = chars.get(*at) {
+        if Some(c) == until || (until.is_some() && matches!(c, ',' | '}' | ']' | ')')) {
+            break;
+        }
+        *at += 1;
+        match c {
+            '"' => {
+                out.push(c);
+                while let Some(&c) = chars.get(*at) {
+                    *at += 1;
+                    out.push(c);
+                    if c == '\\' {
+                        if let Some(&escaped) = chars.get(*at) {
+                            *at += 1;
+                            out.push(escaped);
+                        }
+                    } else if c == '"' {
+                        break;
+                    }
+                }
+            }
+            '{' | '[' | '(' => {
+                let close: char = match c {
+                    '{' => '}',
+                    '[' => ']',
+                    _ => ')',
+                };
+                let mut entries: Vec<String> = Vec::new();
+                while chars.get(*at).is_some_and(|&c| c != close) {
+                    entries.push(canonical_item(chars, at, Some(',')).trim().to_string());
+                    if chars.get(*at) == Some(&',') {
+                        *at += 1;
+                    } else if chars.get(*at) != Some(&close) {
+                        // A stray closing bracket; keep it rather than loop on it.
+                        entries.push(chars[*at].to_string());
+                        *at += 1;
+                    }
+                }
+                *at += 1;
+                entries.retain(|entry| !entry.is_empty());
+                if c == '{' {
+                    entries.sort_unstable();
+                }
+                out.push(c);
+                out.push_str(&entries.join(", "));
+                out.push(close);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
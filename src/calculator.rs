@@ -0,0 +1,202 @@
+use alloy_primitives::U256;
+use tycho_simulation::{models::Token, protocol::state::ProtocolSim};
+use crate::{
+    binary_search::{
+        input_for_output, search_targets, slippage_for_amount, SearchResult,
+        DEFAULT_BRACKET_TVL_MULTIPLE, DEFAULT_MAX_ITERATIONS, DEFAULT_PRECISION,
+    },
+    error::DepthError,
+    estimate::{estimate_depth_in, DepthEstimate},
+    provider::{AmountOutProvider, Pool},
+};
+
+/// The depth engine for one pool and direction, for embedding in other Rust projects instead of
+/// running the CLI:
+///
+/// ```ignore
+/// let depth: SearchResult = DepthCalculator::new(&state, &weth, &usdc).depth_at(0.01)?;
+/// println!("{} WETH for {} USDC", format_amount(depth.amount_in, 18), format_amount(depth.amount_out, 6));
+/// ```
+///
+/// Slippage is measured against the pool's spot price unless `with_reference_price` sets another,
+/// and searches stop within `DEFAULT_PRECISION` of the target unless `with_precision` says otherwise.
+/// `from_provider` runs the same search over quotes that don't come from Tycho.
+#[derive(Clone, Copy)]
+pub struct DepthCalculator<'a> {
+    pool: Pool<'a>,
+    token_in: &'a Token,
+    token_out: &'a Token,
+    reference_price: Option<f64>,
+    precision: f64,
+    max_iterations: u32,
+    bracket_tvl_multiple: f64,
+    adaptive_precision: bool,
+}
+
+impl<'a> DepthCalculator<'a> {
+    /// Selling token_in for token_out in `state`'s pool, with the CLI's defaults.
+    pub fn new(state: &'a dyn ProtocolSim, token_in: &'a Token, token_out: &'a Token) -> Self {
+        Self::from_pool(Pool::Tycho(state), token_in, token_out)
+    }
+
+    /// Selling token_in for token_out at the quotes of any `AmountOutProvider`, with the CLI's
+    /// defaults.
+    pub fn from_provider(provider: &'a dyn AmountOutProvider, token_in: &'a Token, token_out: &'a Token) -> Self {
+        Self::from_pool(Pool::Provider(provider), token_in, token_out)
+    }
+
+    fn from_pool(pool: Pool<'a>, token_in: &'a Token, token_out: &'a Token) -> Self {
+        Self {
+            pool,
+            token_in,
+            token_out,
+            reference_price: None,
+            precision: DEFAULT_PRECISION,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            bracket_tvl_multiple: DEFAULT_BRACKET_TVL_MULTIPLE,
+            adaptive_precision: false,
+        }
+    }
+
+    /// Measure slippage against this price of token_in in token_out, in whole tokens, instead of the
+    /// pool's spot, e.g. an oracle mark or a shifted spot.
+    pub fn with_reference_price(self, reference_price: f64) -> Self {
+        Self { reference_price: Some(reference_price), ..self }
+    }
+
+    /// Stop searching within this much of the target, as a decimal (e.g. 0.0001 for 0.01%).
+    pub fn with_precision(self, precision: f64) -> Self {
+        Self { precision, ..self }
+    }
+
+    /// Settle for the best bracket after this many bisection steps per target.
+    pub fn with_max_iterations(self, max_iterations: u32) -> Self {
+        Self { max_iterations, ..self }
+    }
+
+    /// Give up bracketing pools that aren't Uniswap v2-style at this multiple of their TVL, see
+    /// `binary_search::bracket_cap`.
+    pub fn with_bracket_tvl_multiple(self, bracket_tvl_multiple: f64) -> Self {
+        Self { bracket_tvl_multiple, ..self }
+    }
+
+    /// Adapt the precision to the pool's size, see `binary_search::adaptive_precision`.
+    pub fn with_adaptive_precision(self, adaptive_precision: bool) -> Self {
+        Self { adaptive_precision, ..self }
+    }
+
+    /// The same pool the other way round, selling token_out for token_in. A reference price is
+    /// directional, so it's dropped in favor of the reversed pool's spot.
+    pub fn reversed(self) -> Self {
+        Self { token_in: self.token_out, token_out: self.token_in, reference_price: None, ..self }
+    }
+
+    /// A function to get the price slippage is measured against.
+    ///
+    /// Returns:
+    /// - The reference price if one was set, otherwise the pool's spot price of token_in in token_out
+    pub fn reference_price(&self) -> Result<f64, DepthError> {
+        match self.reference_price {
+            Some(reference_price) => Ok(reference_price),
+            None => self.pool.spot(self.token_in, self.token_out),
+        }
+    }
+
+    /// A function to find how much token_in can be sold before slippage reaches the target.
+    ///
+    /// Args:
+    /// - slippage: The slippage tolerance, as a decimal (e.g., 2% slippage = 0.02)
+    ///
+    /// Returns:
+    /// - The converged amount in, its output, and its slippage
+    pub fn depth_at(&self, slippage: f64) -> Result<SearchResult, DepthError> {
+        let mut depths: Vec<SearchResult> = self.depths_at(&[slippage])?;
+        Ok(depths.remove(0))
+    }
+
+    /// A function to find depth at several slippage targets in one pass, sharing simulations
+    /// between them.
+    ///
+    /// Args:
+    /// - slippages: The slippage tolerances, as decimals, in any order
+    ///
+    /// Returns:
+    /// - One result per target, in the order of `slippages`
+    pub fn depths_at(&self, slippages: &[f64]) -> Result<Vec<SearchResult>, DepthError> {
+        self.depths_from_hints(slippages, &[])
+    }
+
+    /// Like `depths_at`, but starting each target's search from a hint, typically where it
+    /// converged on the previous block, see `binary_search::calculate_outputs_from_hints`.
+    ///
+    /// Args:
+    /// - slippages: The slippage tolerances, as decimals, in any order
+    /// - hints: One per target, in the order of `slippages`; None or zero for no hint
+    ///
+    /// Returns:
+    /// - One result per target, in the order of `slippages`
+    pub fn depths_from_hints(&self, slippages: &[f64], hints: &[Option<U256>]) -> Result<Vec<SearchResult>, DepthError> {
+        search_targets(
+            slippages,
+            hints,
+            self.precision,
+            self.max_iterations,
+            self.bracket_tvl_multiple,
+            self.adaptive_precision,
+            self.pool,
+            self.token_in,
+            self.token_out,
+            self.reference_price()?,
+        )
+    }
+
+    /// A function to find depth at the target slippage in both directions, each against its own
+    /// spot unless a reference price was set, in which case the reverse leg uses its inverse.
+    ///
+    /// Returns:
+    /// - (forward, reverse): selling token_in for token_out, then token_out for token_in
+    pub fn depth_both_ways(&self, slippage: f64) -> Result<(SearchResult, SearchResult), DepthError> {
+        let reversed: DepthCalculator = match self.reference_price {
+            Some(reference_price) => self.reversed().with_reference_price(1.0 / reference_price),
+            None => self.reversed(),
+        };
+        Ok((self.depth_at(slippage)?, reversed.depth_at(slippage)?))
+    }
+
+    /// A function to price one fixed-size sale of token_in, in a single simulation.
+    ///
+    /// Args:
+    /// - amount_in: The amount of token_in to sell, in base units
+    ///
+    /// Returns:
+    /// - The swap's output and slippage, with `amount_in` as given
+    pub fn slippage_for(&self, amount_in: U256) -> Result<SearchResult, DepthError> {
+        slippage_for_amount(amount_in, self.pool, self.token_in, self.token_out, self.reference_price()?)
+    }
+
+    /// A function to find the input that receives an exact output.
+    ///
+    /// Args:
+    /// - amount_out: The output wanted, in token_out base units
+    /// - max_slippage: The slippage past which the output counts as out of reach, as a decimal
+    ///
+    /// Returns:
+    /// - The fill, or `DepthError::OutputOutOfReach`
+    pub fn input_for_output(&self, amount_out: U256, max_slippage: f64) -> Result<SearchResult, DepthError> {
+        input_for_output(
+            amount_out,
+            max_slippage,
+            self.precision,
+            self.pool,
+            self.token_in,
+            self.token_out,
+            self.reference_price()?,
+        )
+    }
+
+    /// A function to estimate depth at the target slippage from two probes instead of a search,
+    /// see `estimate::estimate_depth`.
+    pub fn estimate_at(&self, slippage: f64) -> Result<DepthEstimate, DepthError> {
+        estimate_depth_in(slippage, self.pool, self.token_in, self.token_out, self.reference_price()?)
+    }
+}
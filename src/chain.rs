@@ -0,0 +1,71 @@
+use tycho_common::{models::Chain, Bytes};
+
+/// Chains with registered exchanges. Not every one has a hosted Tycho endpoint, see
+/// `default_tycho_url`.
+pub const SUPPORTED_CHAINS: [Chain; 5] = [Chain::Ethereum, Chain::Base, Chain::Unichain, Chain::Arbitrum, Chain::Bsc];
+
+/// A function to look up the hosted Tycho endpoint for a chain.
+///
+/// Returns:
+/// - The endpoint, or None for chains without a hosted one (Arbitrum, BSC), which need one from
+///   `[endpoints]` or `TYCHO_URL`
+pub fn default_tycho_url(chain: &Chain) -> Option<String> {
+    match chain {
+        Chain::Ethereum => Some("tycho-beta.propellerheads.xyz".to_string()),
+        Chain::Base => Some("tycho-base-beta.propellerheads.xyz".to_string()),
+        Chain::Unichain => Some("tycho-unichain-beta.propellerheads.xyz".to_string()),
+        _ => None,
+    }
+}
+
+/// A function to parse a chain name for the CLI, listing the supported chains on failure.
+pub fn parse_supported_chain(name: &str) -> Result<Chain, String> {
+    let supported: Vec<String> = SUPPORTED_CHAINS.iter().map(|c| c.to_string()).collect();
+    name.to_lowercase()
+        .parse::<Chain>()
+        .ok()
+        .filter(|chain| SUPPORTED_CHAINS.contains(chain))
+        .ok_or_else(|| format!("unsupported chain \"{}\", expected one of: {}", name, supported.join(", ")))
+}
+
+/// A function to name a chain's native token, which pools holding it directly list as the zero
+/// address, e.g. "ETH" or "BNB".
+pub fn native_symbol(chain: &Chain) -> &'static str {
+    match chain {
+        Chain::Bsc => "BNB",
+        _ => "ETH",
+    }
+}
+
+/// A function to look up the wrapped native token of a chain, e.g. WETH.
+pub fn wrapped_native_address(chain: &Chain) -> Option<Bytes> {
+    match chain {
+        Chain::Ethereum => Some(Bytes::from("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")),
+        Chain::Base | Chain::Unichain => Some(Bytes::from("0x4200000000000000000000000000000000000006")),
+        Chain::Arbitrum => Some(Bytes::from("0x82aF49447D8a07e3bd95BD0d56f35241523fBab1")),
+        Chain::Bsc => Some(Bytes::from("0xbb4CdB9CBd36B01bD1cBaEBF2De08d9173bc095c")), // WBNB
+        _ => None,
+    }
+}
+
+/// A function to list the dollar stablecoins of a chain that native token prices are read against.
+pub fn stablecoin_addresses(chain: &Chain) -> Vec<Bytes> {
+    let addresses: &[&str] = match chain {
+        Chain::Ethereum => &[
+            "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48", // USDC
+            "0xdAC17F958D2ee523a2206206994597C13D831ec7", // USDT
+        ],
+        Chain::Base => &["0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913"], // USDC
+        Chain::Unichain => &["0x078D782b760474a361dDA0AF3839290b0EF57AD6"], // USDC
+        Chain::Arbitrum => &[
+            "0xaf88d065e77c8cC2239327C5EDb3A432268e5831", // USDC
+            "0xFd086bC7CD5C481DCC9C85ebE478A1C0b69FCbb9", // USDT
+        ],
+        Chain::Bsc => &[
+            "0x55d398326f99059fF775485246999027B3197955", // USDT
+            "0x8AC76a51cc950d9822D68b83fE1Ad97B32Cd580d", // USDC
+        ],
+        _ => &[],
+    };
+    addresses.iter().map(|address| Bytes::from(*address)).collect()
+}
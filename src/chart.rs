@@ -0,0 +1,209 @@
+use std::{fmt, str::FromStr};
+use crate::curve::DepthCurve;
+
+/// How many sizes each pool's curve is sampled at for `--chart`, unless `--curve-samples` says.
+pub const DEFAULT_CHART_SAMPLES: usize = 24;
+
+/// The plot's size in characters, not counting the axis labels.
+const CHART_WIDTH: usize = 60;
+const CHART_HEIGHT: usize = 12;
+
+/// The characters a chart is drawn with, so each console renderer can pick its own.
+#[derive(Debug, Clone, Copy)]
+pub struct ChartGlyphs {
+    /// One per series, reused if there are more series than markers.
+    pub markers: &'static [char],
+    pub vertical: char,
+    pub horizontal: char,
+    pub corner: char,
+}
+
+pub const UNICODE_GLYPHS: ChartGlyphs =
+    ChartGlyphs { markers: &['●', '○', '◆', '◇', '▲', '△', '■', '□'], vertical: '│', horizontal: '─', corner: '└' };
+
+pub const ASCII_GLYPHS: ChartGlyphs =
+    ChartGlyphs { markers: &['*', 'o', '+', 'x', '#', '@', '%', '&'], vertical: '|', horizontal: '-', corner: '+' };
+
+/// One pool's curve: (whole tokens sold, slippage as a decimal), smallest size first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartSeries {
+    pub label: String,
+    pub points: Vec<(f64, f64)>,
+}
+
+/// The slippage-vs-size curves of a pair's pools on one text plot, for `--chart`: sizes on a log
+/// scale across, slippage up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepthChart {
+    /// e.g. "WETH/USDC"
+    pub pair: String,
+    /// The token sold, which sizes are in.
+    pub base: String,
+    pub series: Vec<ChartSeries>,
+}
+
+impl DepthChart {
+    pub fn new(pair: &str, base: &str) -> Self {
+        Self { pair: pair.to_string(), base: base.to_string(), series: Vec::new() }
+    }
+
+    /// A function to add a pool's sampled curve. Curves without a point are left out.
+    ///
+    /// Args:
+    /// - label: What the legend calls it, e.g. the pool id
+    /// - curve: The sampled curve
+    /// - decimals: token_in's decimals, to turn sizes into whole tokens
+    pub fn add_curve(&mut self, label: &str, curve: &DepthCurve, decimals: usize) {
+        let scale: f64 = 10f64.powi(decimals as i32);
+        let points: Vec<(f64, f64)> = curve
+            .points
+            .iter()
+            .filter_map(|point| Some((point.amount_in.parse::<f64>().ok()? / scale, point.slippage)))
+            .filter(|(size, slippage)| *size > 0.0 && slippage.is_finite())
+            .collect();
+        if !points.is_empty() {
+            self.series.push(ChartSeries { label: label.to_string(), points });
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.series.is_empty()
+    }
+
+    /// A function to draw the plot, its axes and a legend, one string per line.
+    ///
+    /// Each series is drawn as a line through its samples, interpolated column by column; where
+    /// two cross, the later one shows.
+    ///
+    /// Args:
+    /// - glyphs: The characters to draw with
+    /// - ellipsis: Ends a label cut short in the legend
+    /// - indent: Starts every line
+    pub fn lines(&self, glyphs: &ChartGlyphs, ellipsis: &str, indent: &str) -> Vec<String> {
+        let points = || self.series.iter().flat_map(|series| series.points.iter());
+        let (Some(smallest), Some(largest)) =
+            (points().map(|(size, _)| *size).reduce(f64::min), points().map(|(size, _)| *size).reduce(f64::max))
+        else {
+            return Vec::new();
+        };
+        let lowest: f64 = points().map(|(_, slippage)| *slippage).fold(0.0, f64::min);
+        let highest: f64 = points().map(|(_, slippage)| *slippage).fold(lowest, f64::max);
+        let (x_lo, x_hi): (f64, f64) = (smallest.log10(), largest.log10());
+        let column = |size: f64| -> f64 {
+            if x_hi > x_lo {
+                (size.log10() - x_lo) / (x_hi - x_lo) * (CHART_WIDTH - 1) as f64
+            } else {
+                0.0
+            }
+        };
+        let row = |slippage: f64| -> usize {
+            let height: f64 = if highest > lowest { (slippage - lowest) / (highest - lowest) } else { 0.0 };
+            (height * (CHART_HEIGHT - 1) as f64).round() as usize
+        };
+
+        let mut grid: Vec<Vec<char>> = vec![vec![' '; CHART_WIDTH]; CHART_HEIGHT];
+        for (i, series) in self.series.iter().enumerate() {
+            let marker: char = glyphs.markers[i % glyphs.markers.len()];
+            let mut plot = |x: usize, slippage: f64| grid[CHART_HEIGHT - 1 - row(slippage)][x] = marker;
+            if let [(size, slippage)] = series.points.as_slice() {
+                plot(column(*size).round() as usize, *slippage);
+            }
+            for pair in series.points.windows(2) {
+                let ((from_size, from), (to_size, to)) = (pair[0], pair[1]);
+                let (start, end): (f64, f64) = (column(from_size), column(to_size));
+                for x in start.round() as usize..=end.round() as usize {
+                    let t: f64 = if end > start { ((x as f64 - start) / (end - start)).clamp(0.0, 1.0) } else { 1.0 };
+                    plot(x, from + (to - from) * t);
+                }
+            }
+        }
+
+        let percent = |slippage: f64| format!("{:.2}%", slippage * 100.0);
+        let label_width: usize = percent(highest).len().max(percent(lowest).len());
+        let mut lines: Vec<String> = Vec::with_capacity(CHART_HEIGHT + 4);
+        for (i, cells) in grid.iter().enumerate() {
+            let label: String = match i {
+                0 => percent(highest),
+                i if i == CHART_HEIGHT / 2 => percent((highest + lowest) / 2.0),
+                i if i == CHART_HEIGHT - 1 => percent(lowest),
+                _ => String::new(),
+            };
+            let cells: String = cells.iter().collect();
+            lines.push(format!("{}{:>width$} {}{}", indent, label, glyphs.vertical, cells.trim_end(), width = label_width));
+        }
+        let axis: String = std::iter::repeat_n(glyphs.horizontal, CHART_WIDTH).collect();
+        lines.push(format!("{}{:>width$} {}{}", indent, "", glyphs.corner, axis, width = label_width));
+        let (left, right): (String, String) = (compact(smallest), format!("{} {}", compact(largest), self.base));
+        lines.push(format!(
+            "{}{:>width$}  {}{:>gap$}",
+            indent,
+            "",
+            left,
+            right,
+            width = label_width,
+            gap = CHART_WIDTH.saturating_sub(left.len()),
+        ));
+        let legend: Vec<String> = self
+            .series
+            .iter()
+            .enumerate()
+            .map(|(i, series)| {
+                // Pool ids run to 66 characters; the first few tell them apart.
+                let label: String = match series.label.char_indices().nth(10) {
+                    Some((end, _)) => format!("{}{}", &series.label[..end], ellipsis),
+                    None => series.label.clone(),
+                };
+                format!("{} {}", glyphs.markers[i % glyphs.markers.len()], label)
+            })
+            .collect();
+        lines.push(format!("{}{:>width$}  {}", indent, "", legend.join("  "), width = label_width));
+        lines
+    }
+}
+
+/// A function to write a size briefly for an axis, e.g. 0.01, 250, 12.5k, 1M.
+pub(crate) fn compact(size: f64) -> String {
+    let (value, suffix): (f64, &str) = match size {
+        s if s >= 1e9 => (s / 1e9, "B"),
+        s if s >= 1e6 => (s / 1e6, "M"),
+        s if s >= 1e3 => (s / 1e3, "k"),
+        s => (s, ""),
+    };
+    let digits: String = format!("{:.3}", value);
+    format!("{}{}", digits.trim_end_matches('0').trim_end_matches('.'), suffix)
+}
+
+/// What `--charts-dir` writes its figures as.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FigureFormat {
+    #[default]
+    Svg,
+    Png,
+}
+
+impl FigureFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            FigureFormat::Svg => "svg",
+            FigureFormat::Png => "png",
+        }
+    }
+}
+
+impl FromStr for FigureFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "svg" => Ok(FigureFormat::Svg),
+            "png" => Ok(FigureFormat::Png),
+            other => Err(format!("unknown chart format \"{}\", expected svg or png", other)),
+        }
+    }
+}
+
+impl fmt::Display for FigureFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
@@ -0,0 +1,67 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use serde::{Deserialize, Serialize};
+
+/// Where a long run got to: its last measured block and the pools it was tracking, saved after
+/// every block so a restarted run neither measures a block twice nor leaves a gap unmarked.
+///
+/// Tycho's stream always starts at the chain head, so blocks missed while a run was down can't be
+/// fetched again; `gap` says which they were.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub chain: String,
+    pub block_number: u64,
+    /// Tracked pool ids, sorted.
+    pub pools: Vec<String>,
+}
+
+impl Checkpoint {
+    /// A function to read a checkpoint.
+    ///
+    /// Returns:
+    /// - The checkpoint, None if there's no file yet, or an error if the file can't be read or parsed
+    pub fn load(path: &Path) -> io::Result<Option<Self>> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// A function to write the checkpoint. It goes to a temporary file that's renamed over the old
+    /// one, so a crash mid-write leaves the previous checkpoint intact.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut temporary: PathBuf = path.to_path_buf().into_os_string().into();
+        temporary.as_mut_os_string().push(".tmp");
+        fs::write(&temporary, serde_json::to_vec(self)?)?;
+        fs::rename(&temporary, path)
+    }
+
+    /// A function to find the blocks missed between the checkpoint and the first block after a
+    /// restart.
+    ///
+    /// Returns:
+    /// - The first and last missed block, or None if the run picks up right where it stopped (or
+    ///   before, in which case blocks up to the checkpoint are skipped)
+    pub fn gap(&self, first_block: u64) -> Option<(u64, u64)> {
+        (first_block > self.block_number + 1).then(|| (self.block_number + 1, first_block - 1))
+    }
+
+    /// A function to compare the checkpoint's pools with those tracked after the restart.
+    ///
+    /// Returns:
+    /// - (pools gone since, pools new since), each sorted
+    pub fn pool_changes<'a>(&'a self, tracked: impl IntoIterator<Item = &'a str>) -> (Vec<&'a str>, Vec<&'a str>) {
+        let mut tracked: Vec<&str> = tracked.into_iter().collect();
+        tracked.sort_unstable();
+        let gone: Vec<&str> =
+            self.pools.iter().map(String::as_str).filter(|id| tracked.binary_search(id).is_err()).collect();
+        let new: Vec<&str> = tracked
+            .into_iter()
+            .filter(|id| self.pools.binary_search_by(|pool| pool.as_str().cmp(id)).is_err())
+            .collect();
+        (gone, new)
+    }
+}
@@ -0,0 +1,250 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use arrow_array::{
+    ArrayRef, BooleanArray, Float64Array, RecordBatch, StringArray, TimestampMillisecondArray, UInt32Array,
+    UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::{
+    arrow::ArrowWriter,
+    basic::{Compression, ZstdLevel},
+    file::properties::WriterProperties,
+};
+use crate::{
+    report::{BlockReport, DepthRow},
+    sink::partition_slug,
+};
+
+/// Blocks per Parquet file before it's closed and the next one started, so a crash loses at most
+/// this many blocks of a partition (about three hours of mainnet).
+pub const PARQUET_BLOCKS_PER_FILE: u64 = 1_000;
+
+/// The columns of every `--output parquet` file, in order. The same rows as `--csv`, minus `chain`
+/// and `pair`, which are partition keys, plus `measured_at`. Columns are only ever added at the end,
+/// so months of files can be read as one table.
+pub fn depth_schema() -> Schema {
+    let text = |name: &str, nullable: bool| Field::new(name, DataType::Utf8, nullable);
+    let number = |name: &str| Field::new(name, DataType::Float64, true);
+    Schema::new(vec![
+        Field::new("block_number", DataType::UInt64, false),
+        Field::new("measured_at", DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())), false),
+        number("native_price"),
+        text("pool_id", false),
+        text("protocol", false),
+        text("base", false),
+        text("quote", false),
+        text("status", false),
+        number("spot_price"),
+        number("reference_price"),
+        number("target_slippage"),
+        text("action", true),
+        text("notional", true),
+        Field::new("approximate", DataType::Boolean, false),
+        // Exact, in base units.
+        text("base_amount", true),
+        text("quote_amount", true),
+        number("usd_value"),
+        // Whole tokens, as floats for analysis.
+        number("base_tokens"),
+        number("quote_tokens"),
+        number("quote_usd_value"),
+        number("slippage"),
+        number("fee_slippage"),
+        number("impact_slippage"),
+        text("error", true),
+        Field::new("stale_since_block", DataType::UInt64, true),
+        Field::new("converged", DataType::Boolean, true),
+        text("pool_limited", true),
+        Field::new("iterations", DataType::UInt32, true),
+        number("elapsed_ms"),
+        Field::new("protocol_owned", DataType::Boolean, false),
+        Field::new("reorg", DataType::Boolean, false),
+        text("labels", false),
+    ])
+}
+
+/// A function to lay out one partition's directory, Hive-style, so DuckDB
+/// (`read_parquet('dir/**/*.parquet', hive_partitioning = true)`) and Spark pick up the keys.
+///
+/// Args:
+/// - dir: The dataset's root
+/// - date: The UTC date the rows were measured, e.g. "2026-10-16"
+/// - chain, pair: e.g. "ethereum", "WETH/USDC"
+///
+/// Returns:
+/// - e.g. `dir/date=2026-10-16/chain=ethereum/pair=weth-usdc`
+pub fn partition_dir(dir: &Path, date: &str, chain: &str, pair: &str) -> PathBuf {
+    dir.join(format!("date={}", date))
+        .join(format!("chain={}", partition_slug(chain)))
+        .join(format!("pair={}", partition_slug(pair)))
+}
+
+/// A function to turn a time into its UTC calendar date, e.g. "2026-10-16".
+pub fn utc_date(time: SystemTime) -> String {
+    let days: i64 = time.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs() as i64) / 86_400;
+    // Days since 1970-01-01 to a proleptic Gregorian date, counting from 0000-03-01 so leap days
+    // fall at the end of each year.
+    let shifted: i64 = days + 719_468;
+    let era: i64 = shifted.div_euclid(146_097);
+    let day_of_era: i64 = shifted.rem_euclid(146_097);
+    let year_of_era: i64 = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year: i64 = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index: i64 = (5 * day_of_year + 2) / 153;
+    let day: i64 = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month: i64 = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year: i64 = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// One partition's file being written.
+struct OpenFile {
+    writer: ArrowWriter<File>,
+    date: String,
+    /// Written under a `.tmp` name and renamed on close, so readers globbing `*.parquet` never see
+    /// a file without its footer.
+    temporary: PathBuf,
+    path: PathBuf,
+    blocks: u64,
+}
+
+impl OpenFile {
+    fn close(self) -> io::Result<()> {
+        self.writer.close().map_err(io::Error::other)?;
+        fs::rename(&self.temporary, &self.path)
+    }
+}
+
+/// Writes every block's depth rows as zstd-compressed Parquet, one file per partition of the block's
+/// UTC date, chain and pair, each holding up to `PARQUET_BLOCKS_PER_FILE` blocks and named after its first,
+/// e.g. `date=2026-10-16/chain=ethereum/pair=weth-usdc/part-000021000000.parquet`.
+///
+/// Parquet can't be appended to, so a partition's rows are only readable once its file is closed:
+/// when it's full, when the date changes, or when the run ends. Like the CSV, it's append-only;
+/// rows a reorg superseded stay, and the replacing block's rows have `reorg` set.
+pub struct ParquetSink {
+    dir: PathBuf,
+    chain: String,
+    schema: SchemaRef,
+    /// Open files by pair slug.
+    files: HashMap<String, OpenFile>,
+}
+
+impl ParquetSink {
+    pub fn new(dir: &Path, chain: &str) -> Self {
+        Self { dir: dir.to_path_buf(), chain: chain.to_string(), schema: Arc::new(depth_schema()), files: HashMap::new() }
+    }
+
+    /// A function to write one block's rows, see `BlockReport::rows`.
+    ///
+    /// Args:
+    /// - report: The block
+    /// - measured_at: The block's timestamp, for `measured_at` and the date partition
+    pub fn write_block(&mut self, report: &BlockReport, measured_at: SystemTime) -> io::Result<()> {
+        let date: String = utc_date(measured_at);
+        let mut by_pair: Vec<(String, Vec<DepthRow>)> = Vec::new();
+        for row in report.rows() {
+            match by_pair.iter_mut().find(|(pair, _)| *pair == row.pair) {
+                Some((_, rows)) => rows.push(row),
+                None => by_pair.push((row.pair.clone(), vec![row])),
+            }
+        }
+        let millis: i64 = measured_at.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as i64);
+        for (pair, rows) in by_pair.iter() {
+            let slug: String = partition_slug(pair);
+            let batch: RecordBatch = self.batch(rows, millis)?;
+            if self.files.get(&slug).is_some_and(|file| file.date != date) {
+                self.files.remove(&slug).expect("just checked").close()?;
+            }
+            let file: &mut OpenFile = match self.files.entry(slug.clone()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let dir: PathBuf = partition_dir(&self.dir, &date, &self.chain, pair);
+                    fs::create_dir_all(&dir)?;
+                    let path: PathBuf = dir.join(format!("part-{:012}.parquet", report.block_number));
+                    let temporary: PathBuf = dir.join(format!("part-{:012}.parquet.tmp", report.block_number));
+                    let properties: WriterProperties =
+                        WriterProperties::builder().set_compression(Compression::ZSTD(ZstdLevel::default())).build();
+                    let writer: ArrowWriter<File> =
+                        ArrowWriter::try_new(File::create(&temporary)?, self.schema.clone(), Some(properties))
+                            .map_err(io::Error::other)?;
+                    entry.insert(OpenFile { writer, date: date.clone(), temporary, path, blocks: 0 })
+                }
+            };
+            file.writer.write(&batch).map_err(io::Error::other)?;
+            file.blocks += 1;
+            if file.blocks >= PARQUET_BLOCKS_PER_FILE {
+                self.files.remove(&slug).expect("just written").close()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A function to close every open file, making the last rows readable. Called at the end of a
+    /// run; dropping the sink does it too, ignoring errors.
+    pub fn close(&mut self) -> io::Result<()> {
+        for (_, file) in self.files.drain() {
+            file.close()?;
+        }
+        Ok(())
+    }
+
+    fn batch(&self, rows: &[DepthRow], measured_at: i64) -> io::Result<RecordBatch> {
+        let text = |column: fn(&DepthRow) -> Option<&str>| -> ArrayRef {
+            Arc::new(rows.iter().map(column).collect::<StringArray>())
+        };
+        let number = |column: fn(&DepthRow) -> Option<f64>| -> ArrayRef {
+            Arc::new(rows.iter().map(column).collect::<Float64Array>())
+        };
+        let flag = |column: fn(&DepthRow) -> Option<bool>| -> ArrayRef {
+            Arc::new(rows.iter().map(column).collect::<BooleanArray>())
+        };
+        let tokens = |amount: &Option<String>| amount.as_deref().and_then(|tokens| tokens.parse::<f64>().ok());
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(rows.iter().map(|row| row.block_number).collect::<UInt64Array>()),
+            Arc::new(TimestampMillisecondArray::from(vec![measured_at; rows.len()]).with_timezone("UTC")),
+            number(|row| row.native_price),
+            text(|row| Some(&row.pool_id)),
+            text(|row| Some(&row.protocol)),
+            text(|row| Some(&row.base)),
+            text(|row| Some(&row.quote)),
+            Arc::new(rows.iter().map(|row| Some(row.status.to_string())).collect::<StringArray>()),
+            number(|row| row.spot_price),
+            number(|row| row.reference_price),
+            number(|row| row.target_slippage),
+            Arc::new(rows.iter().map(|row| row.action.map(|action| action.to_string())).collect::<StringArray>()),
+            text(|row| row.notional.as_deref()),
+            flag(|row| Some(row.approximate)),
+            text(|row| row.base_amount.as_deref()),
+            text(|row| row.quote_amount.as_deref()),
+            number(|row| row.usd_value),
+            Arc::new(rows.iter().map(|row| tokens(&row.base_tokens)).collect::<Float64Array>()),
+            Arc::new(rows.iter().map(|row| tokens(&row.quote_tokens)).collect::<Float64Array>()),
+            number(|row| row.quote_usd_value),
+            number(|row| row.slippage),
+            number(|row| row.fee_slippage),
+            number(|row| row.impact_slippage),
+            text(|row| row.error.as_deref()),
+            Arc::new(rows.iter().map(|row| row.stale_since_block).collect::<UInt64Array>()),
+            flag(|row| row.converged),
+            text(|row| row.pool_limited.as_deref()),
+            Arc::new(rows.iter().map(|row| row.iterations).collect::<UInt32Array>()),
+            number(|row| row.elapsed_ms),
+            flag(|row| Some(row.protocol_owned)),
+            flag(|row| Some(row.reorg)),
+            text(|row| Some(&row.labels)),
+        ];
+        RecordBatch::try_new(self.schema.clone(), columns).map_err(io::Error::other)
+    }
+}
+
+impl Drop for ParquetSink {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
@@ -0,0 +1,54 @@
+use std::{fmt, str::FromStr};
+use crate::protocols::ProtocolFilter;
+
+/// A named filter over protocol systems, for comparing a pair's depth with and without a venue,
+/// written `NAME=FILTER` as `--compare-protocols` takes it.
+///
+/// The filter is a `ProtocolFilter`, e.g. `with-v4=uniswap_v2,uniswap_v3,uniswap_v4` or
+/// `without-v4=!uniswap_v4`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolSet {
+    pub name: String,
+    pub filter: ProtocolFilter,
+}
+
+impl ProtocolSet {
+    /// A function to check whether a pool of this protocol system belongs to the set.
+    pub fn matches(&self, protocol: &str) -> bool {
+        self.filter.matches(protocol)
+    }
+}
+
+impl FromStr for ProtocolSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, filter)) = s.split_once('=') else {
+            return Err(format!("protocol set \"{}\" should be NAME=FILTER, e.g. without-v4=!uniswap_v4", s));
+        };
+        let name: &str = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(format!("protocol set \"{}\" needs a name without spaces before the =", s));
+        }
+        let filter: ProtocolFilter = filter.parse().map_err(|_| format!("protocol set \"{}\" has a ! without a protocol", s))?;
+        Ok(ProtocolSet { name: name.to_string(), filter })
+    }
+}
+
+impl fmt::Display for ProtocolSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.filter)
+    }
+}
+
+/// A function to check that protocol sets make a comparison: exactly two, named differently.
+///
+/// Returns:
+/// - The two sets, in the order given
+pub fn comparison_pair(sets: &[ProtocolSet]) -> Result<[ProtocolSet; 2], String> {
+    match sets {
+        [a, b] if a.name == b.name => Err(format!("both protocol sets are named \"{}\"", a.name)),
+        [a, b] => Ok([a.clone(), b.clone()]),
+        _ => Err(format!("a comparison takes exactly two protocol sets, not {}", sets.len())),
+    }
+}
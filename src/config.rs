@@ -0,0 +1,1227 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    fs,
+    path::{Path, PathBuf},
+};
+use serde::{Deserialize, Serialize};
+use tycho_common::{models::Chain, Bytes};
+use crate::{
+    alert::{AlertRule, CommandNotifier},
+    binary_search::PoolLimitPolicy,
+    chain::parse_supported_chain,
+    compare::{comparison_pair, ProtocolSet},
+    protocols::protocol_filter,
+    display::{DisplayNames, TokenDisplay},
+    labels::{check_key, Label},
+    notional::{Notional, DEFAULT_IMPACT_SIZES},
+    oracle::OracleConfig,
+    sink::{Compression, Partition},
+    token_id::{TokenId, TokenIdError, TokenRef},
+    webhook::WebhookConfig,
+};
+
+/// TVL a pool needs to be tracked, in ETH, when neither `--tvl-min` nor the config sets one.
+pub const DEFAULT_TVL_MIN: f64 = 500.0;
+
+/// Slippage targets used when neither the pair nor the config sets any, e.g., the 2% depth.
+pub const DEFAULT_SLIPPAGE_TARGETS: [f64; 1] = [0.02];
+
+/// The top-level config file, in TOML or YAML. Every setting is optional and the matching CLI flag
+/// wins when both are given.
+///
+/// Example:
+///
+/// ```toml
+/// chain = "base"
+/// precision = 0.0001
+/// tvl_min = 100
+///
+/// [endpoints]
+/// base = "tycho-base-beta.propellerheads.xyz"
+///
+/// [output]
+/// bundle = "runs/base.tar.zst"
+/// results = "runs/base.jsonl.zst"
+/// plain = true
+///
+/// [concurrency]
+/// "vm:curve" = 2
+///
+/// [ladders]
+/// tight = [0.0005, 0.001, 0.002]
+/// wide = [0.01, 0.02, 0.05]
+///
+/// [[pairs]]
+/// token_in = "WETH"
+/// token_out = "USDC"
+/// ladder = "tight"
+///
+/// [[pairs]]
+/// token_in = "PEPE"
+/// token_out = "WETH"
+/// ladder = "wide"
+///
+/// [display]
+/// "0xd9aAEc86B65D86f6A7B5B1b0c42FFA531710b6CA" = { symbol = "Bridged USDC (Base)" }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The chain to stream, e.g. "unichain", or a list to stream side by side.
+    #[serde(default)]
+    pub chain: Option<ChainList>,
+    /// Tycho endpoints by chain name, overriding the hosted defaults. A list is failed over in order
+    /// of preference, see `Endpoints`.
+    #[serde(default)]
+    pub endpoints: HashMap<String, EndpointList>,
+    /// How close to a target counts as converged, as a decimal.
+    #[serde(default)]
+    pub precision: Option<f64>,
+    /// Adapt the precision to each pool's size, like `--adaptive-precision`.
+    #[serde(default)]
+    pub adaptive_precision: bool,
+    /// Blocks the depth stability metric covers, like `--stability-window`.
+    #[serde(default)]
+    pub stability_window: Option<usize>,
+    /// Bisection steps per pool and target, like `--max-iterations`.
+    #[serde(default)]
+    pub max_iterations: Option<u32>,
+    /// Where bracketing gives up in pools that aren't Uniswap v2-style, as a multiple of their TVL,
+    /// like `--bracket-tvl-multiple`.
+    #[serde(default)]
+    pub bracket_tvl_multiple: Option<f64>,
+    /// "report" or "fail" for depth a pool cuts short, like `--pool-limits`.
+    #[serde(default)]
+    pub pool_limits: Option<String>,
+    /// Search every block from scratch, like `--cold-start`.
+    #[serde(default)]
+    pub cold_start: bool,
+    /// Include each depth's final simulation inputs and gas, like `--sim-inputs`.
+    #[serde(default)]
+    pub sim_inputs: bool,
+    /// Also give depth in dollars, like `--usd`.
+    #[serde(default)]
+    pub usd: bool,
+    /// Report a price-impact table, like `--impact-table`.
+    #[serde(default)]
+    pub impact_table: bool,
+    /// Trade sizes for the price-impact table, e.g. ["$10k", "$1M", "50"]; implies `impact_table`.
+    #[serde(default)]
+    pub impact_sizes: Option<Vec<String>>,
+    /// Also measure depth along each pair's deepest two-hop route, like `--two-hop`.
+    #[serde(default)]
+    pub two_hop: bool,
+    /// Flag pools of a pair whose spots diverge by more than this, in basis points, like `--arb-bps`.
+    #[serde(default)]
+    pub arb_bps: Option<f64>,
+    /// Only track pools with at least this much TVL, in ETH.
+    #[serde(default)]
+    pub tvl_min: Option<f64>,
+    /// Stop tracking a pool once its TVL falls below this, in ETH, like `--tvl-remove`.
+    #[serde(default)]
+    pub tvl_remove: Option<f64>,
+    /// Only stream and measure these pools, by component id, like `--pools`.
+    #[serde(default)]
+    pub pools: Vec<String>,
+    /// Pool ids holding protocol-owned or locked liquidity, e.g. a DAO's own pool, like
+    /// `--protocol-owned`. Aggregates are also reported without them.
+    #[serde(default)]
+    pub protocol_owned: Vec<String>,
+    /// Only stream and measure these protocol systems, e.g. ["uniswap_v3", "uniswap_v4"], like
+    /// `--protocols`.
+    #[serde(default)]
+    pub protocols: Vec<String>,
+    /// Never stream or measure these protocol systems, e.g. ["vm:curve"], like
+    /// `--exclude-protocols`.
+    #[serde(default)]
+    pub exclude_protocols: Vec<String>,
+    /// Two protocol sets to compare each pair's aggregate depth under, e.g.
+    /// ["with-v4=uniswap_v2,uniswap_v3,uniswap_v4", "without-v4=!uniswap_v4"], like
+    /// `--compare-protocols`.
+    #[serde(default)]
+    pub compare_protocols: Option<Vec<String>>,
+    /// Alert rules, e.g. ["WETH/USDC@0.02<500 for 3"], like `--alert`. See `AlertRule`.
+    #[serde(default)]
+    pub alerts: Vec<String>,
+    /// Run this command for every alert with the alert as JSON on stdin, like `--alert-command`.
+    #[serde(default)]
+    pub alert_command: Option<String>,
+    /// Slack, Discord or Telegram webhooks for alerts and, optionally, block summaries. Their URLs
+    /// are credentials, so they're left out wherever a config is written back, e.g. into a bundle.
+    #[serde(default, skip_serializing)]
+    pub notifiers: Vec<WebhookConfig>,
+    /// Stop after this many blocks; 0 runs until interrupted.
+    #[serde(default)]
+    pub blocks: Option<usize>,
+    /// Threads measuring pools side by side each block, like `--workers`.
+    #[serde(default)]
+    pub workers: Option<usize>,
+    /// The most pools of a protocol system measured at once, e.g. `"vm:curve" = 2` to keep the VM
+    /// engine from thrashing. Protocols not listed use every worker.
+    #[serde(default)]
+    pub concurrency: HashMap<String, usize>,
+    /// Only measure one block in this many, like `--every-n-blocks`.
+    #[serde(default)]
+    pub every_n_blocks: Option<u64>,
+    /// Measure at a random phase within each `every_n_blocks`, like `--sample-jitter`.
+    #[serde(default)]
+    pub sample_jitter: bool,
+    /// Fail over to the next endpoint after this many seconds without a block, like
+    /// `--stall-timeout`.
+    #[serde(default)]
+    pub stall_timeout_secs: Option<u64>,
+    /// Tags for every record the run writes, e.g. `experiment = "fee-change-study"`, like
+    /// `--label`, which overrides a key set here.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Where results go besides the console.
+    #[serde(default)]
+    pub output: OutputConfig,
+    /// Named slippage ladders that pairs can reference instead of repeating target lists.
+    #[serde(default)]
+    pub ladders: HashMap<String, Vec<f64>>,
+    /// Slippage targets for pairs that don't set a ladder or their own targets.
+    #[serde(default)]
+    pub slippages: Option<Vec<f64>>,
+    /// The pairs to measure.
+    #[serde(default)]
+    pub pairs: Vec<PairConfig>,
+    /// How tokens are named in reports, by address (optionally chain-prefixed). See `DisplayNames`.
+    #[serde(default)]
+    pub display: HashMap<String, TokenDisplay>,
+}
+
+/// One Tycho endpoint for a chain, or several to fail over between, primary first.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum EndpointList {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl EndpointList {
+    pub fn urls(&self) -> &[String] {
+        match self {
+            EndpointList::One(url) => std::slice::from_ref(url),
+            EndpointList::Many(urls) => urls,
+        }
+    }
+}
+
+/// One chain to stream, or several to run side by side, e.g. `chain = ["ethereum", "base"]`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ChainList {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl ChainList {
+    pub fn names(&self) -> &[String] {
+        match self {
+            ChainList::One(name) => std::slice::from_ref(name),
+            ChainList::Many(names) => names,
+        }
+    }
+}
+
+/// Output sinks.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    /// Write a reproducibility bundle here, like `--bundle`.
+    #[serde(default)]
+    pub bundle: Option<PathBuf>,
+    /// ASCII-only console output, like `--plain`.
+    #[serde(default)]
+    pub plain: bool,
+    /// Print the full pool table every N blocks, like `--full-every`.
+    #[serde(default)]
+    pub full_every: Option<u64>,
+    /// Stream every result as JSON lines to this file, like `--results`.
+    #[serde(default)]
+    pub results: Option<PathBuf>,
+    /// Append one row per pool and slippage target to this CSV file, like `--csv`.
+    #[serde(default)]
+    pub csv: Option<PathBuf>,
+    /// Upsert every block's results into this SQLite database, like `--sqlite`.
+    #[serde(default)]
+    pub sqlite: Option<PathBuf>,
+    /// Record every streamed block under this directory, like `--record`.
+    #[serde(default)]
+    pub record: Option<PathBuf>,
+    /// Save where the run got to here after every block, and resume from it, like `--checkpoint`.
+    #[serde(default)]
+    pub checkpoint: Option<PathBuf>,
+    /// Write `--output parquet` files under this directory, like `--parquet-dir`.
+    #[serde(default)]
+    pub parquet_dir: Option<PathBuf>,
+    /// Write each pair's figures into this directory every block, like `--charts-dir`.
+    #[serde(default)]
+    pub charts_dir: Option<PathBuf>,
+    /// "none", "gzip" or "zstd" for file sinks, like `--compress`. Defaults to the file extension.
+    #[serde(default)]
+    pub compress: Option<String>,
+    /// "pair", "chain" or "protocol" to split the results and CSV files, like `--partition-by`.
+    #[serde(default)]
+    pub partition_by: Option<String>,
+}
+
+impl OutputConfig {
+    /// A function to parse the configured compression, if any.
+    pub fn compression(&self) -> Result<Option<Compression>, ConfigError> {
+        self.compress
+            .as_deref()
+            .map(|raw| raw.parse::<Compression>())
+            .transpose()
+            .map_err(|reason| ConfigError::InvalidSetting { setting: "output.compress".to_string(), reason })
+    }
+
+    /// A function to parse how file sinks are partitioned, if they are.
+    pub fn partition(&self) -> Result<Option<Partition>, ConfigError> {
+        self.partition_by
+            .as_deref()
+            .map(|raw| raw.parse::<Partition>())
+            .transpose()
+            .map_err(|reason| ConfigError::InvalidSetting { setting: "output.partition_by".to_string(), reason })
+    }
+}
+
+/// A single tracked pair in the config.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PairConfig {
+    /// A token identifier, optionally chain-prefixed, e.g. "WETH" or "base:0x8335…". See `TokenId`.
+    pub token_in: String,
+    pub token_out: String,
+    /// Name of a ladder in `[ladders]`.
+    #[serde(default)]
+    pub ladder: Option<String>,
+    /// Explicit slippage targets for this pair only. Mutually exclusive with `ladder`.
+    #[serde(default)]
+    pub slippages: Option<Vec<f64>>,
+    /// Measure slippage against this HTTP price source instead of pool spot.
+    #[serde(default)]
+    pub reference: Option<OracleConfig>,
+    /// Dollar price of token_in from this HTTP price source instead of the pools. Implies `usd`.
+    #[serde(default)]
+    pub usd_price: Option<OracleConfig>,
+}
+
+impl PairConfig {
+    /// A short human label for the pair, e.g. "WETH/USDC".
+    pub fn label(&self) -> String {
+        format!("{}/{}", self.token_in, self.token_out)
+    }
+
+    /// A function to parse both token identifiers.
+    pub fn token_ids(&self) -> Result<(TokenId, TokenId), ConfigError> {
+        let parse = |raw: &str| {
+            raw.parse::<TokenId>()
+                .map_err(|error| ConfigError::Token { pair: self.label(), error })
+        };
+        Ok((parse(&self.token_in)?, parse(&self.token_out)?))
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    ParseYaml(serde_yaml::Error),
+    Chain(String),
+    InvalidSetting { setting: String, reason: String },
+    UnknownLadder { pair: String, ladder: String },
+    LadderAndSlippages { pair: String },
+    EmptyTargets { source: String },
+    InvalidTarget { source: String, target: f64 },
+    Token { pair: String, error: TokenIdError },
+    /// A syntax or schema error whose place in the file is given separately, see `ConfigProblem`.
+    Syntax(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "failed to read config: {}", e),
+            ConfigError::Parse(e) => write!(f, "failed to parse config: {}", e),
+            ConfigError::ParseYaml(e) => write!(f, "failed to parse config: {}", e),
+            ConfigError::Chain(e) => write!(f, "{}", e),
+            ConfigError::InvalidSetting { setting, reason } => write!(f, "`{}` {}", setting, reason),
+            ConfigError::UnknownLadder { pair, ladder } => {
+                write!(f, "pair {} references unknown ladder \"{}\"", pair, ladder)
+            }
+            ConfigError::LadderAndSlippages { pair } => {
+                write!(f, "pair {} sets both `ladder` and `slippages`, pick one", pair)
+            }
+            ConfigError::EmptyTargets { source } => {
+                write!(f, "{} has no slippage targets", source)
+            }
+            ConfigError::InvalidTarget { source, target } => {
+                write!(f, "{} has slippage target {} outside of (0, 1)", source, target)
+            }
+            ConfigError::Token { pair, error } => write!(f, "pair {}: {}", pair, error),
+            ConfigError::Syntax(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// A validation error and the setting it's about, e.g. "pairs[1].ladder", so it can be traced back
+/// to the file with `locate_setting`.
+#[derive(Debug)]
+pub struct ConfigProblem {
+    pub setting: String,
+    pub error: ConfigError,
+    /// Where in the file the setting is, once `check_str` has looked.
+    pub location: Option<Location>,
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl ConfigProblem {
+    /// A function to write the problem the way a compiler would, e.g.
+    /// "depth.toml:12:1: ladder \"tight\" has slippage target 2 outside of (0, 1)".
+    pub fn describe(&self, path: &Path) -> String {
+        match &self.location {
+            Some(location) => format!("{}:{}: {}", path.display(), location, self.error),
+            None => format!("{}: {}", path.display(), self.error),
+        }
+    }
+}
+
+/// A place in a config file, 1-based, written "line:column".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+impl Location {
+    /// A function to find the line and column of a byte offset into `raw`.
+    fn of_offset(raw: &str, offset: usize) -> Self {
+        let before: &str = raw.get(..offset).unwrap_or(raw);
+        let line_start: usize = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        Self { line: before.matches('\n').count() + 1, column: before[line_start..].chars().count() + 1 }
+    }
+}
+
+impl Config {
+    /// A function to read and validate a config file.
+    ///
+    /// Args:
+    /// - path: Path to a config file; `.yaml` and `.yml` are read as YAML, anything else as TOML
+    ///
+    /// Returns:
+    /// - The parsed config, or an error if it can't be read, parsed, or fails validation
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path: &Path = path.as_ref();
+        let raw: String = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml") | Some("yml") => Self::from_yaml_str(&raw),
+            _ => Self::from_toml_str(&raw),
+        }
+    }
+
+    /// A function to check a config file without connecting anywhere, for `config validate`.
+    ///
+    /// Args:
+    /// - path: Path to a config file, as for `load`
+    ///
+    /// Returns:
+    /// - Every problem, located as far as possible; an error only if the file can't be read
+    pub fn check_file(path: impl AsRef<Path>) -> Result<Vec<ConfigProblem>, ConfigError> {
+        let path: &Path = path.as_ref();
+        let raw: String = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        let yaml: bool = matches!(path.extension().and_then(|ext| ext.to_str()), Some("yaml") | Some("yml"));
+        Ok(Self::check_str(&raw, yaml))
+    }
+
+    /// A function to check a config's text, as `check_file` does.
+    ///
+    /// Args:
+    /// - raw: The config file's contents
+    /// - yaml: Whether `raw` is YAML rather than TOML
+    ///
+    /// Returns:
+    /// - Every problem with where it is in the file, in file order. A syntax error or unknown key
+    ///   stops parsing, so it's the only problem reported, at the place the parser gave up.
+    pub fn check_str(raw: &str, yaml: bool) -> Vec<ConfigProblem> {
+        let parsed: Result<Config, ConfigProblem> = if yaml {
+            serde_yaml::from_str(raw).map_err(|e| {
+                let location: Option<Location> =
+                    e.location().map(|location| Location { line: location.line(), column: location.column() });
+                // The message ends in " at line 3 column 5" when the error has a place.
+                let written: String = e.to_string();
+                let message: &str = match e.location() {
+                    Some(_) => written.rsplit_once(" at line ").map_or(written.as_str(), |(message, _)| message),
+                    None => &written,
+                };
+                ConfigProblem { setting: String::new(), error: ConfigError::Syntax(message.to_string()), location }
+            })
+        } else {
+            toml::from_str(raw).map_err(|e| ConfigProblem {
+                setting: String::new(),
+                error: ConfigError::Syntax(e.message().to_string()),
+                location: e.span().map(|span| Location::of_offset(raw, span.start)),
+            })
+        };
+        let config: Config = match parsed {
+            Ok(config) => config,
+            Err(problem) => return vec![problem],
+        };
+        let mut problems: Vec<ConfigProblem> = config.problems();
+        for problem in problems.iter_mut() {
+            problem.location = locate_setting(raw, yaml, &problem.setting);
+        }
+        problems.sort_by_key(|problem| problem.location.map_or((usize::MAX, 0), |location| (location.line, location.column)));
+        problems
+    }
+
+    /// A function to parse and validate a config from a TOML string.
+    pub fn from_toml_str(raw: &str) -> Result<Self, ConfigError> {
+        let config: Config = toml::from_str(raw).map_err(ConfigError::Parse)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// A function to parse and validate a config from a YAML string.
+    pub fn from_yaml_str(raw: &str) -> Result<Self, ConfigError> {
+        let config: Config = serde_yaml::from_str(raw).map_err(ConfigError::ParseYaml)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// A function to check that every ladder is well-formed and every pair's targets resolve.
+    ///
+    /// Returns:
+    /// - The first problem found, see `problems` for all of them
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        match self.problems().into_iter().next() {
+            Some(problem) => Err(problem.error),
+            None => Ok(()),
+        }
+    }
+
+    /// A function to check every setting and how they fit together, without touching the network.
+    ///
+    /// Returns:
+    /// - Every problem found, in file order as far as possible; empty if the config is valid
+    pub fn problems(&self) -> Vec<ConfigProblem> {
+        let mut problems: Vec<ConfigProblem> = Vec::new();
+        let mut report =
+            |setting: String, error: ConfigError| problems.push(ConfigProblem { setting, error, location: None });
+        let invalid = |setting: &str, reason: String| ConfigError::InvalidSetting { setting: setting.to_string(), reason };
+
+        let chains: Vec<Chain> = match self.chains() {
+            Ok(chains) => chains,
+            Err(error) => {
+                report("chain".to_string(), error);
+                Vec::new()
+            }
+        };
+        if self.chain.as_ref().is_some_and(|chain| chain.names().is_empty()) {
+            report("chain".to_string(), invalid("chain", "no chains listed".to_string()));
+        }
+        let mut names: Vec<&String> = self.endpoints.keys().collect();
+        names.sort();
+        for name in names.into_iter() {
+            let setting: String = format!("endpoints.{}", name);
+            if let Err(error) = parse_supported_chain(name) {
+                report(setting.clone(), ConfigError::Chain(error));
+            }
+            let urls: &[String] = self.endpoints[name].urls();
+            if urls.is_empty() {
+                report(setting.clone(), invalid(&setting, "no endpoints listed".to_string()));
+            }
+            for url in urls.iter() {
+                if url.is_empty() || url.contains(char::is_whitespace) {
+                    report(setting.clone(), invalid(&setting, format!("\"{}\" isn't a host name", url)));
+                } else if url.contains("://") {
+                    report(
+                        setting.clone(),
+                        invalid(&setting, format!("\"{}\" has a scheme; give the host only, e.g. tycho-beta.propellerheads.xyz", url)),
+                    );
+                }
+            }
+        }
+        if let Some(precision) = self.precision {
+            if !(precision > 0.0 && precision < 1.0) {
+                report("precision".to_string(), invalid("precision", format!("{} is outside of (0, 1)", precision)));
+            }
+        }
+        if let Some(multiple) = self.bracket_tvl_multiple {
+            if !(multiple.is_finite() && multiple > 0.0) {
+                report(
+                    "bracket_tvl_multiple".to_string(),
+                    invalid("bracket_tvl_multiple", format!("{} must be a positive multiple of the pool's TVL", multiple)),
+                );
+            }
+        }
+        let mut limited: Vec<(&String, &usize)> = self.concurrency.iter().collect();
+        limited.sort();
+        for (protocol, limit) in limited.into_iter() {
+            if *limit == 0 {
+                let setting: String = format!("concurrency.{}", protocol);
+                report(setting.clone(), invalid(&setting, "must allow at least one simulation at a time".to_string()));
+            }
+        }
+        let pool_ids = self.pools.iter().enumerate().map(|(i, id)| (format!("pools[{}]", i), id));
+        let owned_ids = self.protocol_owned.iter().enumerate().map(|(i, id)| (format!("protocol_owned[{}]", i), id));
+        for (setting, id) in pool_ids.chain(owned_ids) {
+            let hex: Option<&str> = id.strip_prefix("0x");
+            if !hex.is_some_and(|hex| !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit())) {
+                report(setting.clone(), invalid(&setting, format!("\"{}\" isn't a 0x-prefixed pool id", id)));
+            }
+        }
+        if let Some(bps) = self.arb_bps {
+            if !(bps.is_finite() && bps >= 0.0) {
+                report("arb_bps".to_string(), invalid("arb_bps", format!("{} must be a non-negative number of basis points", bps)));
+            }
+        }
+        for (setting, tvl) in [("tvl_min", self.tvl_min), ("tvl_remove", self.tvl_remove)] {
+            if let Some(tvl) = tvl {
+                if !(tvl.is_finite() && tvl >= 0.0) {
+                    report(setting.to_string(), invalid(setting, format!("{} must be a non-negative number of ETH", tvl)));
+                }
+            }
+        }
+        if let Some(tvl_remove) = self.tvl_remove {
+            let tvl_min: f64 = self.tvl_min.unwrap_or(DEFAULT_TVL_MIN);
+            if tvl_remove > tvl_min {
+                report("tvl_remove".to_string(), invalid("tvl_remove", format!("{} is above tvl_min {}", tvl_remove, tvl_min)));
+            }
+        }
+
+        for key in self.labels.keys() {
+            if let Err(reason) = check_key(key) {
+                let setting: String = format!("labels.{}", key);
+                report(setting.clone(), invalid(&setting, reason));
+            }
+        }
+        if let Err(reason) = protocol_filter(&self.protocols, &self.exclude_protocols) {
+            report("protocols".to_string(), invalid("protocols", reason));
+        }
+        if let Err(error) = self.protocol_sets() {
+            report("compare_protocols".to_string(), error);
+        }
+        if let Err(error) = self.pool_limit_policy() {
+            report("pool_limits".to_string(), error);
+        }
+        if let Err(error) = self.alert_rules() {
+            report("alerts".to_string(), error);
+        }
+        if let Some(Err(reason)) = self.alert_command.as_deref().map(str::parse::<CommandNotifier>) {
+            report("alert_command".to_string(), invalid("alert_command", reason));
+        }
+        for (i, notifier) in self.notifiers.iter().enumerate() {
+            if let Err(reason) = notifier.validate() {
+                let setting: String = format!("notifiers[{}]", i);
+                report(setting.clone(), invalid(&setting, reason));
+            }
+        }
+        if let Err(error) = self.output.compression() {
+            report("output.compress".to_string(), error);
+        }
+        if let Err(error) = self.output.partition() {
+            report("output.partition_by".to_string(), error);
+        }
+        // Two sinks writing the same file would interleave garbage; a missing directory fails at
+        // the first block instead of up front.
+        let mut sink_paths: HashSet<&Path> = HashSet::new();
+        let sinks: [(&str, &Option<PathBuf>); 4] = [
+            ("output.bundle", &self.output.bundle),
+            ("output.results", &self.output.results),
+            ("output.csv", &self.output.csv),
+            ("output.sqlite", &self.output.sqlite),
+        ];
+        for (setting, path) in sinks.into_iter() {
+            let Some(path) = path.as_deref() else { continue };
+            if !sink_paths.insert(path) {
+                report(setting.to_string(), invalid(setting, format!("{} is already used by another sink", path.display())));
+            }
+            if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                if !parent.is_dir() {
+                    report(setting.to_string(), invalid(setting, format!("directory {} doesn't exist", parent.display())));
+                }
+            }
+        }
+
+        let mut ladders: Vec<(&String, &Vec<f64>)> = self.ladders.iter().collect();
+        ladders.sort_by_key(|(name, _)| *name);
+        for (name, targets) in ladders.into_iter() {
+            if let Err(error) = validate_targets(&format!("ladder \"{}\"", name), targets) {
+                report(format!("ladders.{}", name), error);
+            }
+        }
+        if let Some(targets) = &self.slippages {
+            if let Err(error) = validate_targets("top-level `slippages`", targets) {
+                report("slippages".to_string(), error);
+            }
+        }
+
+        let mut seen: Vec<(TokenId, TokenId)> = Vec::new();
+        for (i, pair) in self.pairs.iter().enumerate() {
+            match self.slippage_targets(pair) {
+                Ok(targets) => {
+                    // Every amount would count as converged.
+                    let smallest: f64 = targets.first().copied().unwrap_or(f64::INFINITY);
+                    if let Some(precision) = self.precision.filter(|precision| *precision >= smallest) {
+                        report(
+                            format!("pairs[{}]", i),
+                            invalid(
+                                "precision",
+                                format!("{} must be smaller than pair {}'s smallest target {}", precision, pair.label(), smallest),
+                            ),
+                        );
+                    }
+                }
+                Err(error) => {
+                    let field: &str = if pair.ladder.is_some() { "ladder" } else { "slippages" };
+                    report(format!("pairs[{}].{}", i, field), error);
+                }
+            }
+            match pair.token_ids() {
+                Ok(ids) => {
+                    for (field, id) in [("token_in", &ids.0), ("token_out", &ids.1)] {
+                        if !chains.is_empty() {
+                            if let Err(error) = id.check_chain(&chains) {
+                                report(format!("pairs[{}].{}", i, field), ConfigError::Token { pair: pair.label(), error });
+                            }
+                        }
+                    }
+                    if ids.0 == ids.1 {
+                        report(format!("pairs[{}]", i), invalid("pairs", format!("{} pairs a token with itself", pair.label())));
+                    }
+                    if seen.contains(&ids) {
+                        report(format!("pairs[{}]", i), invalid("pairs", format!("{} is listed twice", pair.label())));
+                    }
+                    seen.push(ids);
+                }
+                Err(error) => {
+                    let field: &str = if pair.token_in.parse::<TokenId>().is_err() { "token_in" } else { "token_out" };
+                    report(format!("pairs[{}].{}", i, field), error);
+                }
+            }
+            let oracles = [("reference", &pair.reference), ("usd_price", &pair.usd_price)];
+            for (key, reference) in oracles.into_iter().filter_map(|(key, oracle)| Some((key, oracle.as_ref()?))) {
+                let setting: String = format!("pairs[{}].{}", i, key);
+                if !(reference.url.starts_with("http://") || reference.url.starts_with("https://")) {
+                    report(setting.clone(), invalid(&setting, format!("url \"{}\" must be http:// or https://", reference.url)));
+                }
+                for pointer in std::iter::once(&reference.price_pointer).chain(reference.timestamp_pointer.as_ref()) {
+                    if !pointer.starts_with('/') {
+                        report(setting.clone(), invalid(&setting, format!("\"{}\" isn't a JSON pointer, e.g. /price", pointer)));
+                    }
+                }
+                if reference.cache_secs > reference.max_age_secs {
+                    report(
+                        setting.clone(),
+                        invalid(
+                            &setting,
+                            format!(
+                                "caches marks for {}s, longer than the {}s max_age_secs allows",
+                                reference.cache_secs, reference.max_age_secs
+                            ),
+                        ),
+                    );
+                }
+            }
+        }
+
+        if let Err(error) = self.impact_sizes() {
+            report("impact_sizes".to_string(), error);
+        }
+
+        let mut displayed: Vec<(&String, &TokenDisplay)> = self.display.iter().collect();
+        displayed.sort_by_key(|(key, _)| *key);
+        for (key, display) in displayed.into_iter() {
+            let setting: String = format!("display.{}", key);
+            match display_address(key) {
+                Ok((prefix, _)) => {
+                    if let Some(prefix) = prefix {
+                        if !chains.is_empty() && !chains.contains(&prefix) {
+                            let streamed: Vec<String> = chains.iter().map(|chain| chain.to_string()).collect();
+                            report(setting.clone(), invalid(&setting, format!("is for {}, but the config streams {}", prefix, streamed.join(", "))));
+                        }
+                    }
+                }
+                Err(reason) => report(setting.clone(), invalid(&setting, reason)),
+            }
+            for (field, value) in [("symbol", &display.symbol), ("name", &display.name)] {
+                if value.as_deref().is_some_and(|value| value.trim().is_empty()) {
+                    report(setting.clone(), invalid(&setting, format!("`{}` is empty", field)));
+                }
+            }
+        }
+        problems
+    }
+
+    /// A function to parse the price-impact table's trade sizes.
+    ///
+    /// Returns:
+    /// - The sizes as listed, `DEFAULT_IMPACT_SIZES` if none are, or an error for an empty list or
+    ///   a size that doesn't parse
+    pub fn impact_sizes(&self) -> Result<Vec<Notional>, ConfigError> {
+        let invalid = |reason: String| ConfigError::InvalidSetting { setting: "impact_sizes".to_string(), reason };
+        let Some(sizes) = &self.impact_sizes else {
+            return Ok(DEFAULT_IMPACT_SIZES.to_vec());
+        };
+        if sizes.is_empty() {
+            return Err(invalid("is empty; leave it out for the default $10k, $100k, $1M, $10M".to_string()));
+        }
+        sizes.iter().map(|size| size.parse::<Notional>().map_err(invalid)).collect()
+    }
+
+    /// A function to parse what to do with depth a pool cuts short, if configured.
+    pub fn pool_limit_policy(&self) -> Result<Option<PoolLimitPolicy>, ConfigError> {
+        self.pool_limits
+            .as_deref()
+            .map(|raw| raw.parse::<PoolLimitPolicy>())
+            .transpose()
+            .map_err(|reason| ConfigError::InvalidSetting { setting: "pool_limits".to_string(), reason })
+    }
+
+    /// A function to parse the alert rules.
+    pub fn alert_rules(&self) -> Result<Vec<AlertRule>, ConfigError> {
+        self.alerts
+            .iter()
+            .map(|rule| rule.parse::<AlertRule>())
+            .collect::<Result<_, _>>()
+            .map_err(|reason| ConfigError::InvalidSetting { setting: "alerts".to_string(), reason })
+    }
+
+    /// A function to parse the protocol sets to compare, if configured.
+    pub fn protocol_sets(&self) -> Result<Option<[ProtocolSet; 2]>, ConfigError> {
+        let invalid = |reason: String| ConfigError::InvalidSetting { setting: "compare_protocols".to_string(), reason };
+        let Some(sets) = &self.compare_protocols else {
+            return Ok(None);
+        };
+        let sets: Vec<ProtocolSet> = sets.iter().map(|set| set.parse::<ProtocolSet>()).collect::<Result<_, _>>().map_err(invalid)?;
+        comparison_pair(&sets).map(Some).map_err(invalid)
+    }
+
+    /// A function to list the configured labels, for merging under the command line's.
+    pub fn labels(&self) -> Vec<Label> {
+        self.labels.iter().map(|(key, value)| Label { key: key.clone(), value: value.clone() }).collect()
+    }
+
+    /// A function to collect the display overrides that apply to a chain.
+    ///
+    /// Args:
+    /// - chain: The chain being streamed; entries prefixed with another chain are left out
+    ///
+    /// Returns:
+    /// - The overrides by address, or an error for a key that isn't a token address
+    pub fn display_names(&self, chain: Chain) -> Result<DisplayNames, ConfigError> {
+        let mut by_address: HashMap<Bytes, TokenDisplay> = HashMap::new();
+        for (key, display) in self.display.iter() {
+            let (prefix, address): (Option<Chain>, Bytes) = display_address(key).map_err(|reason| {
+                ConfigError::InvalidSetting { setting: format!("display.{}", key), reason }
+            })?;
+            if prefix.is_none_or(|prefix| prefix == chain) {
+                by_address.insert(address, display.clone());
+            }
+        }
+        Ok(DisplayNames::new(by_address))
+    }
+
+    /// A function to parse the configured chains, in order and without repeats.
+    ///
+    /// Returns:
+    /// - The chains; empty if the config doesn't set any
+    pub fn chains(&self) -> Result<Vec<Chain>, ConfigError> {
+        let mut chains: Vec<Chain> = Vec::new();
+        for name in self.chain.iter().flat_map(|chain| chain.names()) {
+            let chain: Chain = parse_supported_chain(name).map_err(ConfigError::Chain)?;
+            if !chains.contains(&chain) {
+                chains.push(chain);
+            }
+        }
+        Ok(chains)
+    }
+
+    /// A function to look up the configured Tycho endpoints for a chain, primary first, if any.
+    pub fn endpoint_urls(&self, chain: &Chain) -> Option<&[String]> {
+        self.endpoints
+            .iter()
+            .find(|(name, _)| parse_supported_chain(name).as_ref() == Ok(chain))
+            .map(|(_, urls)| urls.urls())
+            .filter(|urls| !urls.is_empty())
+    }
+
+    /// A function to check that every chain-prefixed token names one of the active chains.
+    ///
+    /// Args:
+    /// - active: The chains this run is connected to
+    pub fn check_chains(&self, active: &[Chain]) -> Result<(), ConfigError> {
+        for pair in self.pairs.iter() {
+            let (token_in, token_out) = pair.token_ids()?;
+            for id in [token_in, token_out].iter() {
+                id.check_chain(active)
+                    .map_err(|error| ConfigError::Token { pair: pair.label(), error })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A function to resolve the slippage targets for a pair from the config alone, see
+    /// `resolve_targets`.
+    pub fn slippage_targets(&self, pair: &PairConfig) -> Result<Vec<f64>, ConfigError> {
+        self.resolve_targets(Some(pair), None)
+    }
+
+    /// A function to resolve the slippage targets for a pair.
+    ///
+    /// Precedence is `--slippage`, then the pair's own `slippages`, then its `ladder`, then the
+    /// top-level `slippages`, then `DEFAULT_SLIPPAGE_TARGETS`.
+    ///
+    /// Args:
+    /// - pair: The pair's config entry, if it has one
+    /// - cli: Targets given with `--slippage`, if any
+    ///
+    /// Returns:
+    /// - The targets sorted ascending with duplicates removed, or an error for an unknown ladder, a
+    ///   pair setting both `ladder` and `slippages`, or targets outside of (0, 1)
+    pub fn resolve_targets(&self, pair: Option<&PairConfig>, cli: Option<&[f64]>) -> Result<Vec<f64>, ConfigError> {
+        let (source, mut targets): (String, Vec<f64>) = match (cli, pair) {
+            (_, Some(pair)) if pair.ladder.is_some() && pair.slippages.is_some() => {
+                return Err(ConfigError::LadderAndSlippages { pair: pair.label() });
+            }
+            (Some(cli), _) => ("--slippage".to_string(), cli.to_vec()),
+            (None, Some(pair @ PairConfig { slippages: Some(own), .. })) => (format!("pair {}", pair.label()), own.clone()),
+            (None, Some(pair @ PairConfig { ladder: Some(ladder), .. })) => {
+                let targets: &Vec<f64> = self
+                    .ladders
+                    .get(ladder)
+                    .ok_or_else(|| ConfigError::UnknownLadder { pair: pair.label(), ladder: ladder.clone() })?;
+                (format!("ladder \"{}\"", ladder), targets.clone())
+            }
+            (None, _) => match &self.slippages {
+                Some(targets) => ("top-level `slippages`".to_string(), targets.clone()),
+                None => ("the default".to_string(), DEFAULT_SLIPPAGE_TARGETS.to_vec()),
+            },
+        };
+        validate_targets(&source, &targets)?;
+
+        targets.sort_by(|a, b| a.total_cmp(b));
+        targets.dedup();
+        Ok(targets)
+    }
+}
+
+/// A function to parse a `[display]` key, which must be an address so overrides never depend on
+/// symbol lookup.
+fn display_address(key: &str) -> Result<(Option<Chain>, Bytes), String> {
+    let id: TokenId = key.parse::<TokenId>().map_err(|e| e.to_string())?;
+    match id.token {
+        TokenRef::Address(address) => Ok((id.chain, address)),
+        _ => Err("isn't a token address; display overrides are keyed by address, not symbol".to_string()),
+    }
+}
+
+fn validate_targets(source: &str, targets: &[f64]) -> Result<(), ConfigError> {
+    if targets.is_empty() {
+        return Err(ConfigError::EmptyTargets { source: source.to_string() });
+    }
+    for target in targets.iter() {
+        if !(*target > 0.0 && *target < 1.0) {
+            return Err(ConfigError::InvalidTarget { source: source.to_string(), target: *target });
+        }
+    }
+    Ok(())
+}
+
+/// A function to find where a setting is written, for error messages that point into the file.
+///
+/// This reads the text rather than a parse tree, so it understands the plain layouts the config
+/// docs use (`[table]`/`[[pairs]]` in TOML, block mappings and `- ` lists in YAML) and gives up on
+/// anything fancier.
+///
+/// Args:
+/// - raw: The config file's contents
+/// - yaml: Whether `raw` is YAML rather than TOML
+/// - setting: A `ConfigProblem::setting`, e.g. "precision", "output.csv" or "pairs[1].ladder"
+///
+/// Returns:
+/// - The line and column the setting's key starts at, or its table header or list item if the key
+///   itself isn't written out, or None if it can't be found
+pub fn locate_setting(raw: &str, yaml: bool, setting: &str) -> Option<Location> {
+    // "pairs[1].ladder" -> ("pairs", Some(1), Some("ladder")); "output.csv" -> ("output", None, Some("csv"))
+    let (head, key): (&str, Option<&str>) = match setting.split_once('.') {
+        Some((head, key)) => (head, Some(key)),
+        None => (setting, None),
+    };
+    let (table, index): (&str, Option<usize>) = match head.split_once('[') {
+        Some((table, index)) => (table, index.trim_end_matches(']').parse().ok()),
+        None => (head, None),
+    };
+    let lines: Vec<&str> = raw.lines().collect();
+    let key_of = |line: &str| -> Option<String> {
+        let line: &str = line.trim_start().trim_start_matches("- ").trim_start();
+        let end: usize = line.find([':', '='])?;
+        Some(line[..end].trim().trim_matches(|c| c == '"' || c == '\'').to_string())
+    };
+    // Where the key (or header, or list item) starts on the 0-based line `i`.
+    let at = |i: usize, item: bool| -> Location {
+        let line: &str = lines[i];
+        let skipped: &str = if item { line.trim_start() } else { line.trim_start().trim_start_matches("- ").trim_start() };
+        Location { line: i + 1, column: line[..line.len() - skipped.len()].chars().count() + 1 }
+    };
+
+    // The range of lines holding `table` (or its `index`th item), and where it starts.
+    let (start, end): (usize, usize) = if yaml {
+        let indent = |line: &str| line.len() - line.trim_start().len();
+        let is_content = |line: &&str| !line.trim().is_empty() && !line.trim_start().starts_with('#');
+        if key.is_none() && index.is_none() {
+            return lines
+                .iter()
+                .position(|line| indent(line) == 0 && key_of(line).as_deref() == Some(table))
+                .map(|i| at(i, false));
+        }
+        let header: usize = lines.iter().position(|line| indent(line) == 0 && key_of(line).as_deref() == Some(table))?;
+        let end: usize = (header + 1..lines.len())
+            .find(|i| is_content(&lines[*i]) && indent(lines[*i]) == 0)
+            .unwrap_or(lines.len());
+        match index {
+            None => (header, end),
+            Some(index) => {
+                let items: Vec<usize> = (header + 1..end)
+                    .filter(|i| lines[*i].trim_start().starts_with("- "))
+                    .collect();
+                let item_indent: usize = items.first().map(|i| indent(lines[*i]))?;
+                let items: Vec<usize> = items.into_iter().filter(|i| indent(lines[*i]) == item_indent).collect();
+                let start: usize = *items.get(index)?;
+                (start, items.get(index + 1).copied().unwrap_or(end))
+            }
+        }
+    } else {
+        let is_header = |line: &str| line.trim_start().starts_with('[');
+        let header_name = |line: &str| line.trim().trim_matches(|c| c == '[' || c == ']').trim().to_string();
+        if key.is_none() && index.is_none() {
+            // A top-level key, before the first table.
+            let first_table: usize = lines.iter().position(|line| is_header(line)).unwrap_or(lines.len());
+            return lines[..first_table]
+                .iter()
+                .position(|line| key_of(line).as_deref() == Some(table))
+                .map(|i| at(i, false));
+        }
+        let headers: Vec<usize> = (0..lines.len())
+            .filter(|i| is_header(lines[*i]) && header_name(lines[*i]) == table)
+            .collect();
+        let start: usize = *headers.get(index.unwrap_or(0))?;
+        let end: usize = (start + 1..lines.len()).find(|i| is_header(lines[*i])).unwrap_or(lines.len());
+        (start, end)
+    };
+
+    let found: Option<usize> = key.and_then(|key| (start..end).find(|i| key_of(lines[*i]).as_deref() == Some(key)));
+    Some(found.map_or_else(|| at(start, true), |i| at(i, false)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn described(raw: &str, yaml: bool) -> Vec<String> {
+        let path: &Path = Path::new(if yaml { "depth.yaml" } else { "depth.toml" });
+        Config::check_str(raw, yaml).iter().map(|problem| problem.describe(path)).collect()
+    }
+
+    fn pair(ladder: Option<&str>, slippages: Option<Vec<f64>>) -> PairConfig {
+        PairConfig {
+            token_in: "WETH".to_string(),
+            token_out: "USDC".to_string(),
+            ladder: ladder.map(str::to_string),
+            slippages,
+            reference: None,
+            usd_price: None,
+        }
+    }
+
+    #[test]
+    fn resolves_targets_by_precedence() {
+        let ladders: HashMap<String, Vec<f64>> = HashMap::from([("tight".to_string(), vec![0.002, 0.0005, 0.001])]);
+        let with_default: Config = Config { ladders: ladders.clone(), slippages: Some(vec![0.05, 0.01]), ..Default::default() };
+        let without_default: Config = Config { ladders, ..Default::default() };
+        let cli: &[f64] = &[0.03, 0.003, 0.03];
+        let own: PairConfig = pair(None, Some(vec![0.04]));
+        let laddered: PairConfig = pair(Some("tight"), None);
+        let plain: PairConfig = pair(None, None);
+
+        // (config, pair, --slippage, targets)
+        type Case<'a> = (&'a Config, Option<&'a PairConfig>, Option<&'a [f64]>, Vec<f64>);
+        let cases: [Case; 8] = [
+            (&with_default, Some(&own), Some(cli), vec![0.003, 0.03]),
+            (&with_default, Some(&laddered), Some(cli), vec![0.003, 0.03]),
+            (&with_default, None, Some(cli), vec![0.003, 0.03]),
+            (&with_default, Some(&own), None, vec![0.04]),
+            (&with_default, Some(&laddered), None, vec![0.0005, 0.001, 0.002]),
+            (&with_default, Some(&plain), None, vec![0.01, 0.05]),
+            (&with_default, None, None, vec![0.01, 0.05]),
+            (&without_default, Some(&plain), None, DEFAULT_SLIPPAGE_TARGETS.to_vec()),
+        ];
+        for (i, (config, pair, cli, targets)) in cases.into_iter().enumerate() {
+            assert_eq!(config.resolve_targets(pair, cli).unwrap(), targets, "case {}", i);
+        }
+    }
+
+    #[test]
+    fn rejects_targets_outside_of_zero_and_one() {
+        let ladders: HashMap<String, Vec<f64>> = HashMap::from([("bad".to_string(), vec![0.01, 1.0])]);
+        let config: Config = Config { ladders, slippages: Some(vec![-0.01]), ..Default::default() };
+        let rejected = |pair: Option<&PairConfig>, cli: Option<&[f64]>| config.resolve_targets(pair, cli).unwrap_err().to_string();
+
+        assert_eq!(rejected(None, Some(&[0.0])), "--slippage has slippage target 0 outside of (0, 1)");
+        assert_eq!(rejected(Some(&pair(None, Some(vec![1.5]))), None), "pair WETH/USDC has slippage target 1.5 outside of (0, 1)");
+        assert_eq!(rejected(Some(&pair(Some("bad"), None)), None), "ladder \"bad\" has slippage target 1 outside of (0, 1)");
+        assert_eq!(rejected(None, None), "top-level `slippages` has slippage target -0.01 outside of (0, 1)");
+        assert_eq!(rejected(None, Some(&[])), "--slippage has no slippage targets");
+        assert_eq!(rejected(Some(&pair(Some("tight"), None)), None), "pair WETH/USDC references unknown ladder \"tight\"");
+        assert_eq!(
+            rejected(Some(&pair(Some("bad"), Some(vec![0.01]))), Some(&[0.01])),
+            "pair WETH/USDC sets both `ladder` and `slippages`, pick one"
+        );
+        // A good --slippage still wins over a bad config.
+        assert_eq!(config.resolve_targets(Some(&pair(Some("bad"), None)), Some(&[0.01])).unwrap(), vec![0.01]);
+    }
+
+    const TOML: &str = r#"
+chain = ["ethereum", "base"]
+precision = 0.0001
+tvl_min = 100
+every_n_blocks = 5
+slippages = [0.01]
+
+[endpoints]
+base = ["tycho-base-beta.propellerheads.xyz", "tycho-base-backup.example.com"]
+
+[output]
+results = "depth.jsonl"
+plain = true
+
+[concurrency]
+"vm:curve" = 2
+
+[labels]
+experiment = "fee-change-study"
+
+[ladders]
+tight = [0.0005, 0.001, 0.002]
+
+[[pairs]]
+token_in = "WETH"
+token_out = "USDC"
+ladder = "tight"
+
+[[pairs]]
+token_in = "base:0x4200000000000000000000000000000000000006"
+token_out = "base:USDC"
+slippages = [0.02, 0.05]
+reference = { url = "https://prices.example.com/eth", price_pointer = "/data/price", max_age_secs = 30 }
+
+[display]
+"0xd9aAEc86B65D86f6A7B5B1b0c42FFA531710b6CA" = { symbol = "USDbC" }
+"#;
+
+    const YAML: &str = r#"
+chain: [ethereum, base]
+precision: 0.0001
+tvl_min: 100
+every_n_blocks: 5
+slippages: [0.01]
+endpoints:
+  base:
+    - tycho-base-beta.propellerheads.xyz
+    - tycho-base-backup.example.com
+output:
+  results: depth.jsonl
+  plain: true
+concurrency:
+  "vm:curve": 2
+labels:
+  experiment: fee-change-study
+ladders:
+  tight: [0.0005, 0.001, 0.002]
+pairs:
+  - token_in: WETH
+    token_out: USDC
+    ladder: tight
+  - token_in: "base:0x4200000000000000000000000000000000000006"
+    token_out: "base:USDC"
+    slippages: [0.02, 0.05]
+    reference:
+      url: https://prices.example.com/eth
+      price_pointer: /data/price
+      max_age_secs: 30
+display:
+  "0xd9aAEc86B65D86f6A7B5B1b0c42FFA531710b6CA":
+    symbol: USDbC
+"#;
+
+    #[test]
+    fn toml_and_yaml_read_the_same() {
+        let toml: Config = Config::from_toml_str(TOML).unwrap();
+        let yaml: Config = Config::from_yaml_str(YAML).unwrap();
+        assert_eq!(serde_json::to_value(&toml).unwrap(), serde_json::to_value(&yaml).unwrap());
+        assert_eq!(toml.chains().unwrap(), [Chain::Ethereum, Chain::Base]);
+        assert_eq!(toml.endpoint_urls(&Chain::Base).unwrap().len(), 2);
+        assert_eq!(toml.slippage_targets(&toml.pairs[0]).unwrap(), [0.0005, 0.001, 0.002]);
+        assert_eq!(toml.pairs[1].reference.as_ref().unwrap().max_age_secs, 30);
+    }
+
+    #[test]
+    fn round_trips_through_either_format() {
+        let config: Config = Config::from_toml_str(TOML).unwrap();
+        let expected: serde_json::Value = serde_json::to_value(&config).unwrap();
+        let through_toml: Config = Config::from_toml_str(&toml::to_string(&config).unwrap()).unwrap();
+        let through_yaml: Config = Config::from_yaml_str(&serde_yaml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(serde_json::to_value(&through_toml).unwrap(), expected);
+        assert_eq!(serde_json::to_value(&through_yaml).unwrap(), expected);
+    }
+
+    #[test]
+    fn locates_a_bad_value_in_a_nested_table() {
+        let raw: &str = "precision = 0.0001\n\n[ladders]\n  tight = [0.0005, 0.001]\n  wide = [0.01, 1.5]\n";
+        assert_eq!(described(raw, false), ["depth.toml:5:3: ladder \"wide\" has slippage target 1.5 outside of (0, 1)"]);
+    }
+
+    #[test]
+    fn locates_a_key_in_the_second_table_of_an_array() {
+        let raw: &str = "[ladders]\ntight = [0.001]\n\n[[pairs]]\ntoken_in = \"WETH\"\ntoken_out = \"USDC\"\n\n\
+                         [[pairs]]\ntoken_in = \"PEPE\"\ntoken_out = \"WETH\"\nladder = \"loose\"\n";
+        assert_eq!(described(raw, false), ["depth.toml:11:1: pair PEPE/WETH references unknown ladder \"loose\""]);
+    }
+
+    #[test]
+    fn locates_an_unknown_key() {
+        let raw: &str = "chain = \"base\"\n\n[output]\ncsv = \"depth.csv\"\ncolour = true\n";
+        let problems: Vec<String> = described(raw, false);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("depth.toml:5:1: unknown field `colour`"), "{}", problems[0]);
+    }
+
+    #[test]
+    fn locates_problems_in_yaml() {
+        let raw: &str = "chain: base\nladders:\n  tight: [0.0005, 0.001]\npairs:\n  - token_in: WETH\n    token_out: USDC\n    \
+                         ladder: tight\n  - token_in: PEPE\n    token_out: WETH\n    ladder: loose\n";
+        assert_eq!(described(raw, true), ["depth.yaml:10:5: pair PEPE/WETH references unknown ladder \"loose\""]);
+
+        let raw: &str = "chain: base\npairs:\n  - token_in: WETH\n    token_out: USDC\n    ladders: tight\n";
+        let problems: Vec<String> = described(raw, true);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("depth.yaml:5:5: pairs[0]: unknown field `ladders`"), "{}", problems[0]);
+        assert!(!problems[0].contains(" at line "), "{}", problems[0]);
+    }
+}
@@ -0,0 +1,481 @@
+use std::{collections::HashMap, fmt};
+use crate::{
+    chart::{DepthChart, ASCII_GLYPHS, UNICODE_GLYPHS},
+    report::ImpactTable,
+};
+
+/// The rows to print for one block.
+#[derive(Debug, Default)]
+pub struct ConsoleFrame {
+    /// True if this frame is a full table rather than a diff.
+    pub full: bool,
+    /// Rendered rows to print, sorted by key.
+    pub rows: Vec<String>,
+    /// Rows left out because they're unchanged since they were last printed.
+    pub suppressed: usize,
+}
+
+/// Throttles per-block console output when watching many pools: a full table every `full_every`
+/// blocks and only the rows whose rendering changed in between.
+///
+/// This only affects the console; callers should keep feeding machine sinks every result.
+pub struct ConsoleThrottle {
+    full_every: u64,
+    blocks_since_full: Option<u64>,
+    last_printed: HashMap<String, String>,
+}
+
+impl ConsoleThrottle {
+    /// Args:
+    /// - full_every: Print the full table every this many blocks. 0 and 1 both mean every block.
+    pub fn new(full_every: u64) -> Self {
+        Self {
+            full_every: full_every.max(1),
+            blocks_since_full: None,
+            last_printed: HashMap::new(),
+        }
+    }
+
+    /// A function to pick which of this block's rows get printed.
+    ///
+    /// Args:
+    /// - rows: (key, rendered row) pairs for the block, e.g. keyed by pool id
+    ///
+    /// Returns:
+    /// - The frame to print
+    pub fn frame(&mut self, mut rows: Vec<(String, String)>) -> ConsoleFrame {
+        rows.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let full: bool = match self.blocks_since_full {
+            None => true,
+            Some(n) => n + 1 >= self.full_every,
+        };
+        self.blocks_since_full = Some(if full { 0 } else { self.blocks_since_full.unwrap_or(0) + 1 });
+
+        let mut frame: ConsoleFrame = ConsoleFrame { full, ..Default::default() };
+        let mut printed: HashMap<String, String> = HashMap::with_capacity(rows.len());
+        for (key, row) in rows.into_iter() {
+            let changed: bool = self.last_printed.get(&key) != Some(&row);
+            if full || changed {
+                frame.rows.push(row.clone());
+            } else {
+                frame.suppressed += 1;
+            }
+            printed.insert(key, row);
+        }
+        // Keys that disappeared are dropped, so they show up as changes if they come back.
+        self.last_printed = printed;
+        frame
+    }
+}
+
+/// Human-readable lines, sent to stderr when stdout is reserved for JSON.
+pub struct Console {
+    pub to_stderr: bool,
+    /// True while `--tui` has the terminal.
+    pub muted: bool,
+    /// Starts every line, e.g. "[base] " when several chains share the console.
+    pub prefix: Option<String>,
+}
+
+impl Console {
+    pub fn line(&self, line: impl fmt::Display) {
+        if self.muted {
+            return;
+        }
+        let line: String = self.prefixed(line);
+        if self.to_stderr {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    }
+
+    /// A function to start every line of a possibly multi-line message with the prefix.
+    fn prefixed(&self, line: impl fmt::Display) -> String {
+        match &self.prefix {
+            Some(prefix) => line.to_string().lines().map(|l| format!("{}{}", prefix, l)).collect::<Vec<_>>().join("\n"),
+            None => line.to_string(),
+        }
+    }
+}
+
+/// Formats everything the block loop prints. The default renderer uses emoji; `--plain` swaps in
+/// `PlainRenderer` for terminals and log collectors that mangle Unicode.
+pub trait ConsoleRenderer {
+    fn waiting_for_first_block(&self) -> String;
+    fn block_header(&self, block_number: u64, states: usize, new_pairs: usize, removed_pairs: usize) -> String;
+    /// The block's native token price, read from the deepest native/stable pool.
+    fn native_price(&self, native: &str, price: f64, stable: &str, pool_id: &str) -> String;
+    /// A pair's base token in dollars, used to give depth in USD.
+    fn usd_price(&self, token: &str, price: f64, source: &str) -> String;
+    /// One status line per tracked pair: how many pools matched and how many were skipped.
+    fn pair_summary(&self, pair: &str, matched_pools: usize, skipped_pools: usize) -> String;
+    fn quote(&self, amount_in: &str, token_in: &str, amount_out: &str, token_out: &str) -> String;
+    fn reference_shift(&self, shift: &str, spot: f64, shifted: f64, token_in: &str, token_out: &str) -> String;
+    /// Slippage is measured against the pair's price oracle instead of pool spot.
+    fn reference_mark(&self, spot: f64, mark: f64, token_in: &str, token_out: &str) -> String;
+    /// The pair's price oracle had no usable mark, so the pair is skipped this block.
+    fn reference_unavailable(&self, pair: &str, reason: &str) -> String;
+    fn depth(&self, target_slippage: f64, depth: &str) -> String;
+    /// Depth of one order split across all of a pair's pools.
+    fn aggregate_depth(&self, pair: &str, pools: usize, target_slippage: f64, depth: &str) -> String;
+    /// Depth along a pair's deepest two-hop route, with `--two-hop`.
+    fn route_depth(&self, pair: &str, via: &str, target_slippage: f64, depth: &str) -> String;
+    /// Two of a pair's pools priced further apart than `--arb-bps`, and what closes the gap.
+    fn divergence(&self, pair: &str, divergence_bps: f64, detail: &str) -> String;
+    /// What a fixed-size trade costs in one pool, with `--notional`.
+    fn fill(&self, notional: &str, fill: &str) -> String;
+    /// The same trade split across all of a pair's pools.
+    fn aggregate_fill(&self, pair: &str, pools: usize, notional: &str, fill: &str) -> String;
+    /// Slippage at each trade size, per pool and split across them, with `--impact-table`.
+    fn impact_table(&self, table: &ImpactTable) -> String;
+    /// The pair's pools' slippage-vs-size curves as a text plot, with `--chart`.
+    fn depth_chart(&self, chart: &DepthChart) -> String;
+    fn skipped_pool(&self, token_a: &str, token_b: &str) -> String;
+    fn hidden_rows(&self, count: usize) -> String;
+    fn wrote_file(&self, what: &str, path: &str) -> String;
+    fn unreliable_pool(&self, pool_id: &str, evidence: &str) -> String;
+    /// The pool couldn't be priced at all this block, so it's left out.
+    fn failed_pool(&self, pool_id: &str, error: &str) -> String;
+    /// Native and VM simulations of the same pool gave different outputs.
+    fn simulator_disagreement(&self, pool_id: &str, evidence: &str) -> String;
+    /// The stream came back after dropping; `restarts` counts every restart so far.
+    fn stream_reconnected(&self, block_number: u64, restarts: u64) -> String;
+    /// The stream moved to another Tycho endpoint.
+    fn endpoint_failover(&self, from: &str, to: &str, reason: &str) -> String;
+    /// A signal arrived and the run is wrapping up.
+    fn shutting_down(&self, signal: &str) -> String;
+    /// The run is over. `labels` are its labels as `key=value,key=value`, empty for none.
+    fn run_summary(&self, blocks_seen: usize, unreliable_pools: usize, stream_restarts: u64, labels: &str) -> String;
+}
+
+/// A function to lay out a price-impact table in fixed-width columns: the size, each pool, then
+/// all pools together.
+///
+/// Args:
+/// - table: The table to lay out
+/// - ellipsis: Marks a shortened pool id
+/// - indent: Starts every line
+/// - clean: Makes caller-supplied text printable, e.g. `PlainRenderer::ascii`
+fn impact_table_lines(table: &ImpactTable, ellipsis: &str, indent: &str, clean: fn(&str) -> String) -> Vec<String> {
+    // Pool ids run to 66 characters; the first few tell them apart.
+    let short = |id: &str| -> String {
+        match id.char_indices().nth(10) {
+            Some((end, _)) => format!("{}{}", &id[..end], ellipsis),
+            None => id.to_string(),
+        }
+    };
+    let percent = |slippage: Option<f64>| -> String {
+        slippage.map_or_else(|| "-".to_string(), |slippage| format!("{:.2}%", slippage * 100.0))
+    };
+    let mut header: String = format!("{}{:>10}", indent, "size");
+    for id in table.pools.iter() {
+        header.push_str(&format!("  {:>13}", short(id)));
+    }
+    header.push_str(&format!("  {:>13}", "all pools"));
+    let mut lines: Vec<String> = vec![header];
+    for row in table.rows.iter() {
+        let mut line: String = format!("{}{:>10}", indent, clean(&row.notional));
+        for slippage in row.slippage.iter() {
+            line.push_str(&format!("  {:>13}", percent(*slippage)));
+        }
+        line.push_str(&format!("  {:>13}", percent(row.aggregate)));
+        lines.push(line);
+    }
+    lines
+}
+
+pub struct EmojiRenderer;
+
+impl ConsoleRenderer for EmojiRenderer {
+    fn waiting_for_first_block(&self) -> String {
+        "🛰  waiting for first block …".to_string()
+    }
+
+    fn block_header(&self, block_number: u64, states: usize, new_pairs: usize, removed_pairs: usize) -> String {
+        format!(
+            "Block #{}\n   → {} states\n   → {} new pairs\n   → {} removed pairs",
+            block_number, states, new_pairs, removed_pairs
+        )
+    }
+
+    fn native_price(&self, native: &str, price: f64, stable: &str, pool_id: &str) -> String {
+        format!("⛽ 1 {} = {:.2} {} (from {})", native, price, stable, pool_id)
+    }
+
+    fn usd_price(&self, token: &str, price: f64, source: &str) -> String {
+        format!("💵 1 {} = ${:.4} ({})", token, price, source)
+    }
+
+    fn pair_summary(&self, pair: &str, matched_pools: usize, skipped_pools: usize) -> String {
+        format!("📊 {}: {} matching pools, {} skipped", pair, matched_pools, skipped_pools)
+    }
+
+    fn quote(&self, amount_in: &str, token_in: &str, amount_out: &str, token_out: &str) -> String {
+        format!("✅ {} {} = {} {}", amount_in, token_in, amount_out, token_out)
+    }
+
+    fn reference_shift(&self, shift: &str, spot: f64, shifted: f64, token_in: &str, token_out: &str) -> String {
+        format!(
+            "📐 reference shifted {}: {:.6} → {:.6} {} per {}",
+            shift, spot, shifted, token_out, token_in
+        )
+    }
+
+    fn reference_mark(&self, spot: f64, mark: f64, token_in: &str, token_out: &str) -> String {
+        format!("🏷️ reference is the oracle mark: spot {:.6} vs mark {:.6} {} per {}", spot, mark, token_out, token_in)
+    }
+
+    fn reference_unavailable(&self, pair: &str, reason: &str) -> String {
+        format!("⚠️  {} skipped this block: {}", pair, reason)
+    }
+
+    fn depth(&self, target_slippage: f64, depth: &str) -> String {
+        format!("Output for {}% slippage: {}", target_slippage * 100.0, depth)
+    }
+
+    fn aggregate_depth(&self, pair: &str, pools: usize, target_slippage: f64, depth: &str) -> String {
+        format!("🌊 {} across {} pools, {}% slippage: {}", pair, pools, target_slippage * 100.0, depth)
+    }
+
+    fn route_depth(&self, pair: &str, via: &str, target_slippage: f64, depth: &str) -> String {
+        format!("🔀 {} via {}, {}% slippage: {}", pair, via, target_slippage * 100.0, depth)
+    }
+
+    fn divergence(&self, pair: &str, divergence_bps: f64, detail: &str) -> String {
+        format!("⚖️  {} pools {:.1} bps apart: {}", pair, divergence_bps, detail)
+    }
+
+    fn fill(&self, notional: &str, fill: &str) -> String {
+        format!("Slippage for {}: {}", notional, fill)
+    }
+
+    fn aggregate_fill(&self, pair: &str, pools: usize, notional: &str, fill: &str) -> String {
+        format!("🌊 {} across {} pools, {}: {}", pair, pools, notional, fill)
+    }
+
+    fn impact_table(&self, table: &ImpactTable) -> String {
+        let mut lines: Vec<String> = vec![format!("📋 {} price impact", table.pair)];
+        lines.extend(impact_table_lines(table, "…", "   ", |s| s.to_string()));
+        lines.join("\n")
+    }
+
+    fn depth_chart(&self, chart: &DepthChart) -> String {
+        let mut lines: Vec<String> = vec![format!("📈 {} slippage by size", chart.pair)];
+        lines.extend(chart.lines(&UNICODE_GLYPHS, "…", "   "));
+        lines.join("\n")
+    }
+
+    fn skipped_pool(&self, token_a: &str, token_b: &str) -> String {
+        format!("🔴 skipping pair {} - {}", token_a, token_b)
+    }
+
+    fn hidden_rows(&self, count: usize) -> String {
+        format!("   … {} unchanged pools hidden", count)
+    }
+
+    fn wrote_file(&self, what: &str, path: &str) -> String {
+        format!("📦 wrote {} to {}", what, path)
+    }
+
+    fn unreliable_pool(&self, pool_id: &str, evidence: &str) -> String {
+        format!("⚠️  excluding unreliable pool {}: {}", pool_id, evidence)
+    }
+
+    fn failed_pool(&self, pool_id: &str, error: &str) -> String {
+        format!("❌ skipping pool {} this block: {}", pool_id, error)
+    }
+
+    fn simulator_disagreement(&self, pool_id: &str, evidence: &str) -> String {
+        format!("🔀 native and VM simulations disagree for {}: {}", pool_id, evidence)
+    }
+
+    fn stream_reconnected(&self, block_number: u64, restarts: u64) -> String {
+        format!("🔌 stream reconnected at block #{} (restart #{})", block_number, restarts)
+    }
+
+    fn endpoint_failover(&self, from: &str, to: &str, reason: &str) -> String {
+        format!("🔁 failed over from {} to {}: {}", from, to, reason)
+    }
+
+    fn shutting_down(&self, signal: &str) -> String {
+        format!("🛑 {} received, flushing sinks …", signal)
+    }
+
+    fn run_summary(&self, blocks_seen: usize, unreliable_pools: usize, stream_restarts: u64, labels: &str) -> String {
+        let labels: String = if labels.is_empty() { String::new() } else { format!(" 🏷️ {}", labels) };
+        format!(
+            "🏁 run summary: {} blocks, {} unreliable pools, {} stream restarts{}",
+            blocks_seen, unreliable_pools, stream_restarts, labels
+        )
+    }
+}
+
+/// ASCII-only, fixed-width columns, one record per line.
+pub struct PlainRenderer;
+
+impl PlainRenderer {
+    /// Token symbols come from on-chain metadata and can contain anything.
+    fn ascii(s: &str) -> String {
+        s.chars().map(|c| if c.is_ascii() && !c.is_ascii_control() { c } else { '?' }).collect()
+    }
+}
+
+impl ConsoleRenderer for PlainRenderer {
+    fn waiting_for_first_block(&self) -> String {
+        "waiting for first block ...".to_string()
+    }
+
+    fn block_header(&self, block_number: u64, states: usize, new_pairs: usize, removed_pairs: usize) -> String {
+        format!(
+            "BLOCK   {:>12}  states {:>6}  new {:>6}  removed {:>6}",
+            block_number, states, new_pairs, removed_pairs
+        )
+    }
+
+    fn native_price(&self, native: &str, price: f64, stable: &str, pool_id: &str) -> String {
+        format!(
+            "NATIVE  {:<8}  {:>14.2} {:<8}  pool {}",
+            Self::ascii(native), price, Self::ascii(stable), pool_id
+        )
+    }
+
+    fn usd_price(&self, token: &str, price: f64, source: &str) -> String {
+        format!("USD     {:<8}  {:>14.4}  {}", Self::ascii(token), price, Self::ascii(source))
+    }
+
+    fn pair_summary(&self, pair: &str, matched_pools: usize, skipped_pools: usize) -> String {
+        format!(
+            "PAIR    {:<20}  matched {:>4}  skipped {:>6}",
+            Self::ascii(pair), matched_pools, skipped_pools
+        )
+    }
+
+    fn quote(&self, amount_in: &str, token_in: &str, amount_out: &str, token_out: &str) -> String {
+        format!(
+            "QUOTE   {:>24} {:<8} = {:>24} {:<8}",
+            amount_in, Self::ascii(token_in), amount_out, Self::ascii(token_out)
+        )
+    }
+
+    fn reference_shift(&self, shift: &str, spot: f64, shifted: f64, token_in: &str, token_out: &str) -> String {
+        format!(
+            "SHIFT   {:>10}  spot {:>18.6}  shifted {:>18.6}  {}/{}",
+            shift, spot, shifted, Self::ascii(token_out), Self::ascii(token_in)
+        )
+    }
+
+    fn reference_mark(&self, spot: f64, mark: f64, token_in: &str, token_out: &str) -> String {
+        format!(
+            "MARK    {:>10}  spot {:>18.6}  mark    {:>18.6}  {}/{}",
+            "oracle", spot, mark, Self::ascii(token_out), Self::ascii(token_in)
+        )
+    }
+
+    fn reference_unavailable(&self, pair: &str, reason: &str) -> String {
+        format!("NOMARK  {:<20}  {}", Self::ascii(pair), Self::ascii(reason))
+    }
+
+    fn depth(&self, target_slippage: f64, depth: &str) -> String {
+        format!("DEPTH   {:>9.4}%  {}", target_slippage * 100.0, Self::ascii(depth))
+    }
+
+    fn aggregate_depth(&self, pair: &str, pools: usize, target_slippage: f64, depth: &str) -> String {
+        format!(
+            "TOTAL   {:<20}  pools {:>4}  {:>9.4}%  {}",
+            Self::ascii(pair), pools, target_slippage * 100.0, Self::ascii(depth)
+        )
+    }
+
+    fn route_depth(&self, pair: &str, via: &str, target_slippage: f64, depth: &str) -> String {
+        format!(
+            "ROUTE   {:<20}  via {:<6}  {:>9.4}%  {}",
+            Self::ascii(pair), Self::ascii(via), target_slippage * 100.0, Self::ascii(depth)
+        )
+    }
+
+    fn divergence(&self, pair: &str, divergence_bps: f64, detail: &str) -> String {
+        format!("ARB     {:<20}  {:>9.1} bps  {}", Self::ascii(pair), divergence_bps, Self::ascii(detail))
+    }
+
+    fn fill(&self, notional: &str, fill: &str) -> String {
+        format!("FILL    {:>10}  {}", Self::ascii(notional), Self::ascii(fill))
+    }
+
+    fn aggregate_fill(&self, pair: &str, pools: usize, notional: &str, fill: &str) -> String {
+        format!(
+            "TOTAL   {:<20}  pools {:>4}  {:>10}  {}",
+            Self::ascii(pair), pools, Self::ascii(notional), Self::ascii(fill)
+        )
+    }
+
+    fn impact_table(&self, table: &ImpactTable) -> String {
+        let mut lines: Vec<String> = vec![format!("IMPACT  {}", Self::ascii(&table.pair))];
+        lines.extend(impact_table_lines(table, "...", "        ", Self::ascii));
+        lines.join("\n")
+    }
+
+    fn depth_chart(&self, chart: &DepthChart) -> String {
+        let mut lines: Vec<String> = vec![format!("CHART   {}", Self::ascii(&chart.pair))];
+        lines.extend(chart.lines(&ASCII_GLYPHS, "...", "        ").iter().map(|line| Self::ascii(line)));
+        lines.join("\n")
+    }
+
+    fn skipped_pool(&self, token_a: &str, token_b: &str) -> String {
+        format!("SKIP    {:<8} - {:<8}", Self::ascii(token_a), Self::ascii(token_b))
+    }
+
+    fn hidden_rows(&self, count: usize) -> String {
+        format!("HIDDEN  {:>6} unchanged pools", count)
+    }
+
+    fn wrote_file(&self, what: &str, path: &str) -> String {
+        format!("WROTE   {} {}", what, Self::ascii(path))
+    }
+
+    fn unreliable_pool(&self, pool_id: &str, evidence: &str) -> String {
+        format!("UNRELIABLE {:<44}  {}", Self::ascii(pool_id), Self::ascii(evidence))
+    }
+
+    fn failed_pool(&self, pool_id: &str, error: &str) -> String {
+        format!("FAILED     {:<44}  {}", Self::ascii(pool_id), Self::ascii(error))
+    }
+
+    fn simulator_disagreement(&self, pool_id: &str, evidence: &str) -> String {
+        format!("DISAGREE   {:<44}  {}", Self::ascii(pool_id), Self::ascii(evidence))
+    }
+
+    fn stream_reconnected(&self, block_number: u64, restarts: u64) -> String {
+        format!("RECONNECT block {:>12}  restart {:>4}", block_number, restarts)
+    }
+
+    fn endpoint_failover(&self, from: &str, to: &str, reason: &str) -> String {
+        format!("FAILOVER  {} -> {}  {}", from, to, reason)
+    }
+
+    fn shutting_down(&self, signal: &str) -> String {
+        format!("SHUTDOWN {}", signal)
+    }
+
+    fn run_summary(&self, blocks_seen: usize, unreliable_pools: usize, stream_restarts: u64, labels: &str) -> String {
+        let labels: String = if labels.is_empty() { String::new() } else { format!("  labels {}", Self::ascii(labels)) };
+        format!(
+            "SUMMARY blocks {:>8}  unreliable {:>6}  restarts {:>4}{}",
+            blocks_seen, unreliable_pools, stream_restarts, labels
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefixes_every_line_of_a_message() {
+        let console: Console = Console { to_stderr: true, muted: false, prefix: Some("[base] ".to_string()) };
+        assert_eq!(console.prefixed("WETH/USDC\n   2 pools"), "[base] WETH/USDC\n[base]    2 pools");
+        let console: Console = Console { prefix: None, ..console };
+        assert_eq!(console.prefixed("WETH/USDC"), "WETH/USDC");
+    }
+}
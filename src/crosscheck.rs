@@ -0,0 +1,107 @@
+use std::fmt;
+use num_bigint::BigUint;
+use serde::Serialize;
+use tycho_simulation::{models::Token, protocol::state::ProtocolSim};
+use crate::error::guard_simulation;
+
+/// Native and VM simulations of the same pool are expected to match to the wei; anything past this
+/// is worth a look.
+pub const DEFAULT_DISAGREEMENT_BPS: u32 = 1;
+
+/// Both implementations' answer for one input size.
+#[derive(Debug, Clone, Serialize)]
+pub struct Comparison {
+    pub amount_in: String,
+    /// None if that implementation failed to simulate.
+    pub native_out: Option<String>,
+    pub vm_out: Option<String>,
+    pub disagrees: bool,
+}
+
+/// The outcome of comparing one pool's native and VM simulations at several sizes.
+#[derive(Debug, Clone, Serialize)]
+pub struct CrossCheck {
+    pub threshold_bps: u32,
+    pub comparisons: Vec<Comparison>,
+}
+
+/// Renders each size as "amount_in: native vs vm", marking the ones that disagree.
+impl fmt::Display for CrossCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |out: &Option<String>| out.clone().unwrap_or_else(|| "failed".to_string());
+        let comparisons: Vec<String> = self
+            .comparisons
+            .iter()
+            .map(|c| {
+                format!(
+                    "{}{}: native {} vs vm {}",
+                    if c.disagrees { "!" } else { "" },
+                    c.amount_in,
+                    show(&c.native_out),
+                    show(&c.vm_out)
+                )
+            })
+            .collect();
+        write!(f, "{} (threshold {} bps)", comparisons.join(", "), self.threshold_bps)
+    }
+}
+
+impl CrossCheck {
+    pub fn agrees(&self) -> bool {
+        !self.comparisons.iter().any(|c| c.disagrees)
+    }
+}
+
+/// A function to compare `get_amount_out` between a pool's native state (e.g. `UniswapV3State`)
+/// and its VM state, to catch indexer or simulator bugs that would silently corrupt depth.
+///
+/// One side failing while the other succeeds counts as a disagreement; both failing doesn't.
+///
+/// Args:
+/// - native: The pool's native state
+/// - vm: The same pool's VM state, at the same block
+/// - token_in: The token being sold
+/// - token_out: The token being bought
+/// - amounts: Input sizes in base units, e.g. `probe::default_probe_amounts`
+/// - threshold_bps: How far apart the outputs may be, relative to the larger one
+///
+/// Returns:
+/// - Both outputs at every size, with disagreements flagged
+pub fn compare_implementations(
+    native: &dyn ProtocolSim,
+    vm: &dyn ProtocolSim,
+    token_in: &Token,
+    token_out: &Token,
+    amounts: &[BigUint],
+    threshold_bps: u32,
+) -> CrossCheck {
+    let comparisons: Vec<Comparison> = amounts
+        .iter()
+        .map(|amount_in| {
+            let native_out: Option<BigUint> =
+                guard_simulation(|| native.get_amount_out(amount_in.clone(), token_in, token_out))
+                    .ok()
+                    .map(|result| result.amount);
+            let vm_out: Option<BigUint> = guard_simulation(|| vm.get_amount_out(amount_in.clone(), token_in, token_out))
+                .ok()
+                .map(|result| result.amount);
+            let disagrees: bool = match (&native_out, &vm_out) {
+                // |a - b| / max(a, b) > threshold
+                (Some(a), Some(b)) => {
+                    let (low, high): (&BigUint, &BigUint) = if a <= b { (a, b) } else { (b, a) };
+                    (high - low) * 10_000u32 > high * threshold_bps
+                }
+                (None, None) => false,
+                _ => true,
+            };
+            Comparison {
+                amount_in: amount_in.to_string(),
+                native_out: native_out.map(|out| out.to_string()),
+                vm_out: vm_out.map(|out| out.to_string()),
+                disagrees,
+            }
+        })
+        .collect();
+
+    CrossCheck { threshold_bps, comparisons }
+}
@@ -0,0 +1,139 @@
+use std::fmt;
+use alloy_primitives::U256;
+use serde::Serialize;
+use tycho_simulation::{models::Token, protocol::state::ProtocolSim};
+use crate::{amount::one_token, binary_search::Simulator, error::{guard_simulation, DepthError}};
+
+/// One sampled swap on the price-impact curve.
+#[derive(Debug, Clone, Serialize)]
+pub struct CurvePoint {
+    /// In token_in base units.
+    pub amount_in: String,
+    /// In token_out base units.
+    pub amount_out: String,
+    /// Relative to the reference price, as a decimal. Lossy, for plotting only.
+    pub slippage: f64,
+}
+
+/// The price-impact curve of one pool: (amount_in, amount_out, slippage) at log-spaced sizes.
+#[derive(Debug, Clone, Serialize)]
+pub struct DepthCurve {
+    pub token_in: String,
+    pub token_out: String,
+    pub reference_price: f64,
+    pub points: Vec<CurvePoint>,
+    /// Why sampling stopped early, e.g. the pool couldn't fill the next size.
+    pub truncated: Option<String>,
+}
+
+/// Renders one point per line, e.g. for pasting into a plotting tool.
+impl fmt::Display for DepthCurve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:>32}  {:>32}  {:>10}", "amount_in", "amount_out", "slippage")?;
+        for point in self.points.iter() {
+            writeln!(f, "{:>32}  {:>32}  {:>9.4}%", point.amount_in, point.amount_out, point.slippage * 100.0)?;
+        }
+        if let Some(reason) = &self.truncated {
+            writeln!(f, "(stopped early: {})", reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// A function to pick a default sampling range: 0.01 to 1,000,000 whole tokens.
+pub fn default_curve_range(token_in: &Token) -> (U256, U256) {
+    let one: U256 = one_token(token_in.decimals).unwrap_or(U256::MAX);
+    ((one / U256::from(100u64)).max(U256::from(1u64)), one.saturating_mul(U256::from(1_000_000u64)))
+}
+
+/// A function to pick `samples` log-spaced input sizes between `min_in` and `max_in`, inclusive.
+///
+/// Sizes that round to the same base unit are only returned once.
+pub fn log_spaced_amounts(min_in: U256, max_in: U256, samples: usize) -> Vec<U256> {
+    if samples == 0 || min_in.is_zero() || max_in < min_in {
+        return Vec::new();
+    }
+    if samples == 1 {
+        return vec![min_in];
+    }
+    let (lo, hi): (f64, f64) = (f64::from(min_in).ln(), f64::from(max_in).ln());
+    let mut amounts: Vec<U256> = Vec::with_capacity(samples);
+    for i in 0..samples {
+        let amount: U256 = if i == 0 {
+            min_in
+        } else if i == samples - 1 {
+            max_in
+        } else {
+            let x: f64 = (lo + (hi - lo) * i as f64 / (samples - 1) as f64).exp();
+            U256::from(x.round() as u128).clamp(min_in, max_in)
+        };
+        if amounts.last() != Some(&amount) {
+            amounts.push(amount);
+        }
+    }
+    amounts
+}
+
+impl DepthCurve {
+    /// A function to sample a pool's price-impact curve against its own spot price.
+    ///
+    /// Args:
+    /// - state: The pool state
+    /// - token_in: The token being sold
+    /// - token_out: The token being bought
+    /// - min_in: Smallest input size, in token_in base units
+    /// - max_in: Largest input size, in token_in base units
+    /// - samples: How many sizes to sample, log-spaced
+    ///
+    /// Returns:
+    /// - The curve, stopping early if a size fails to simulate
+    pub fn sample(
+        state: &dyn ProtocolSim,
+        token_in: &Token,
+        token_out: &Token,
+        min_in: U256,
+        max_in: U256,
+        samples: usize,
+    ) -> Result<Self, DepthError> {
+        let spot_price: f64 = guard_simulation(|| state.spot_price(token_in, token_out))?;
+        Self::sample_against_reference(state, token_in, token_out, min_in, max_in, samples, spot_price)
+    }
+
+    /// Like `sample`, but slippage is measured against a caller-supplied reference price, e.g. a
+    /// scenario-shifted spot.
+    pub fn sample_against_reference(
+        state: &dyn ProtocolSim,
+        token_in: &Token,
+        token_out: &Token,
+        min_in: U256,
+        max_in: U256,
+        samples: usize,
+        reference_price: f64,
+    ) -> Result<Self, DepthError> {
+        let mut sim: Simulator = Simulator::new(state, token_in, token_out, reference_price)?;
+        let mut points: Vec<CurvePoint> = Vec::with_capacity(samples);
+        let mut truncated: Option<String> = None;
+        for amount_in in log_spaced_amounts(min_in, max_in, samples).into_iter() {
+            match sim.simulate(amount_in) {
+                Ok((amount_out, slippage)) => points.push(CurvePoint {
+                    amount_in: amount_in.to_string(),
+                    amount_out: amount_out.to_string(),
+                    slippage: slippage.to_f64(),
+                }),
+                // Most pools refuse sizes past their limits; everything below is still useful.
+                Err(e) => {
+                    truncated = Some(format!("amount_in {}: {}", amount_in, e));
+                    break;
+                }
+            }
+        }
+
+        Ok(Self {
+            token_in: token_in.symbol.clone(),
+            token_out: token_out.symbol.clone(),
+            reference_price,
+            points,
+            truncated,
+        })
+    }
+}
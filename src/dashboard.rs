@@ -0,0 +1,349 @@
+use std::{collections::VecDeque, io, time::Duration};
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, Cell, Paragraph, Row, Sparkline, Table, TableState},
+    Frame, Terminal,
+};
+use tokio::sync::mpsc;
+use crate::report::{BlockReport, TradeAction};
+
+/// How many blocks of aggregate depth each pair's sparkline keeps.
+pub const DASHBOARD_HISTORY: usize = 120;
+
+/// How far behind the chain the dashboard is: blocks already waiting behind the one shown, and how
+/// long the one shown took to measure.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BlockLag {
+    pub queued: usize,
+    pub measured_in: Duration,
+}
+
+/// One pool's row: its spot and its own depth at each of the pair's targets.
+#[derive(Debug, Clone, PartialEq)]
+struct PoolRow {
+    pool_id: String,
+    protocol: String,
+    spot: Option<f64>,
+    /// Whole base tokens sold, per target; None where the search failed.
+    depths: Vec<(f64, Option<String>)>,
+    /// Why the pool was left out, or the block its results are from when re-emitted.
+    note: Option<String>,
+}
+
+/// One tracked pair's latest block and its depth history.
+#[derive(Debug, Clone, PartialEq)]
+struct PairPanel {
+    pair: String,
+    pools: Vec<PoolRow>,
+    /// Aggregate depth in whole base tokens, per target.
+    aggregates: Vec<(f64, Option<String>)>,
+    /// Aggregate depth at the pair's tightest target, oldest first; failed blocks are gaps.
+    history: VecDeque<Option<f64>>,
+}
+
+/// What `--tui` shows: a row per tracked pair with its aggregate depth at each target and a
+/// sparkline of recent blocks, and the selected pair's pools underneath. Fed one `BlockReport` per
+/// block, so it shows exactly what the other outputs write.
+#[derive(Debug, Clone, Default)]
+pub struct Dashboard {
+    chain: String,
+    block_number: Option<u64>,
+    lag: BlockLag,
+    pairs: Vec<PairPanel>,
+    selected: usize,
+}
+
+impl Dashboard {
+    pub fn new(chain: &str) -> Self {
+        Self { chain: chain.to_string(), ..Default::default() }
+    }
+
+    /// A function to take in a measured block. Pairs keep the order they were first seen in.
+    ///
+    /// Args:
+    /// - report: The block's report, as `--output json` prints it
+    /// - lag: How far behind the stream the block was measured
+    pub fn observe(&mut self, report: &BlockReport, lag: BlockLag) {
+        self.block_number = Some(report.block_number);
+        self.lag = lag;
+        for panel in self.pairs.iter_mut() {
+            panel.pools.clear();
+            panel.aggregates.clear();
+        }
+        for pool in report.pools.iter() {
+            let depths: Vec<(f64, Option<String>)> = pool
+                .depths
+                .iter()
+                .filter(|depth| depth.action == TradeAction::Sell)
+                .map(|depth| (depth.target_slippage, depth.conversions.base_tokens.clone()))
+                .collect();
+            let note: Option<String> = pool
+                .excluded
+                .clone()
+                .or_else(|| pool.stale_since_block.map(|block| format!("unchanged since #{}", block)));
+            self.panel(&pool.pair).pools.push(PoolRow {
+                pool_id: pool.pool_id.clone(),
+                protocol: pool.protocol.clone(),
+                spot: pool.spot_price,
+                depths,
+                note,
+            });
+        }
+        for aggregate in report.aggregates.iter().filter(|aggregate| aggregate.action == TradeAction::Sell) {
+            let tokens: Option<String> = aggregate.conversions.base_tokens.clone();
+            self.panel(&aggregate.pair).aggregates.push((aggregate.target_slippage, tokens));
+        }
+        for panel in self.pairs.iter_mut() {
+            let tightest: Option<f64> = panel
+                .aggregates
+                .iter()
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+                .and_then(|(_, tokens)| tokens.as_ref()?.parse::<f64>().ok());
+            if panel.history.len() == DASHBOARD_HISTORY {
+                panel.history.pop_front();
+            }
+            panel.history.push_back(tightest);
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.pairs.is_empty() {
+            self.selected = (self.selected + 1) % self.pairs.len();
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        if !self.pairs.is_empty() {
+            self.selected = (self.selected + self.pairs.len() - 1) % self.pairs.len();
+        }
+    }
+
+    /// A function to draw the whole dashboard into a frame.
+    pub fn render(&self, frame: &mut Frame) {
+        let [header, pairs, history, pools, footer]: [Rect; 5] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(self.pairs.len() as u16 + 3),
+            Constraint::Length(6),
+            Constraint::Min(4),
+            Constraint::Length(1),
+        ])
+        .areas(frame.size());
+
+        let block: String = self.block_number.map_or("waiting for the first block".to_string(), |b| format!("#{}", b));
+        frame.render_widget(
+            Paragraph::new(format!(
+                "{} {}  measured in {:.1}s, {} blocks queued",
+                self.chain,
+                block,
+                self.lag.measured_in.as_secs_f64(),
+                self.lag.queued
+            ))
+            .style(Style::new().add_modifier(Modifier::BOLD)),
+            header,
+        );
+
+        let targets: Vec<f64> = self.targets();
+        let target_header = || targets.iter().map(|target| Cell::from(format!("{}%", target * 100.0)));
+        let depth_cells = |depths: &[(f64, Option<String>)]| -> Vec<Cell> {
+            targets
+                .iter()
+                .map(|target| {
+                    let depth: Option<&Option<String>> =
+                        depths.iter().find(|(t, _)| (t - target).abs() < 1e-12).map(|(_, tokens)| tokens);
+                    Cell::from(match depth {
+                        Some(Some(tokens)) => tokens.clone(),
+                        Some(None) => "failed".to_string(),
+                        None => String::new(),
+                    })
+                })
+                .collect()
+        };
+        let depth_widths = || targets.iter().map(|_| Constraint::Length(14));
+
+        let rows: Vec<Row> = self
+            .pairs
+            .iter()
+            .map(|panel| {
+                let cells: Vec<Cell> = [Cell::from(panel.pair.clone()), Cell::from(panel.pools.len().to_string())]
+                    .into_iter()
+                    .chain(depth_cells(&panel.aggregates))
+                    .collect();
+                Row::new(cells)
+            })
+            .collect();
+        let header_row: Row = Row::new([Cell::from("pair"), Cell::from("pools")].into_iter().chain(target_header()))
+            .style(Style::new().add_modifier(Modifier::BOLD));
+        let widths = [Constraint::Length(20), Constraint::Length(6)].into_iter().chain(depth_widths());
+        let table: Table = Table::new(rows, widths)
+            .header(header_row)
+            .block(Block::bordered().title("aggregate depth, base sold"))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+            .highlight_symbol("> ");
+        let mut state: TableState = TableState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(table, pairs, &mut state);
+
+        let Some(panel) = self.pairs.get(self.selected) else {
+            frame.render_widget(Paragraph::new("q quits"), footer);
+            return;
+        };
+        // Scaled to the window's deepest block, so a sparkline shows change rather than size.
+        let deepest: f64 = panel.history.iter().flatten().copied().fold(0.0, f64::max);
+        let scaled: Vec<u64> = panel
+            .history
+            .iter()
+            .map(|depth| match depth {
+                Some(depth) if deepest > 0.0 => (depth / deepest * 1_000.0).round() as u64,
+                _ => 0,
+            })
+            .collect();
+        let tightest: String = panel
+            .aggregates
+            .iter()
+            .map(|(target, _)| *target)
+            .reduce(f64::min)
+            .map_or(String::new(), |target| format!(" at {}%", target * 100.0));
+        frame.render_widget(
+            Sparkline::default()
+                .data(&scaled)
+                .max(1_000)
+                .block(Block::bordered().title(format!("{} depth{}, last {} blocks", panel.pair, tightest, scaled.len()))),
+            history,
+        );
+
+        let rows: Vec<Row> = panel
+            .pools
+            .iter()
+            .map(|pool| {
+                let spot: String = pool.spot.map_or("-".to_string(), |spot| format!("{:.6}", spot));
+                let cells: Vec<Cell> =
+                    [Cell::from(pool.pool_id.clone()), Cell::from(pool.protocol.clone()), Cell::from(spot)]
+                        .into_iter()
+                        .chain(depth_cells(&pool.depths))
+                        .chain([Cell::from(pool.note.clone().unwrap_or_default())])
+                        .collect();
+                Row::new(cells)
+            })
+            .collect();
+        let header_row: Row = Row::new(
+            [Cell::from("pool"), Cell::from("protocol"), Cell::from("spot")]
+                .into_iter()
+                .chain(target_header())
+                .chain([Cell::from("")]),
+        )
+        .style(Style::new().add_modifier(Modifier::BOLD));
+        let widths = [Constraint::Length(42), Constraint::Length(14), Constraint::Length(14)]
+            .into_iter()
+            .chain(depth_widths())
+            .chain([Constraint::Min(10)]);
+        frame.render_widget(
+            Table::new(rows, widths).header(header_row).block(Block::bordered().title(format!("{} pools", panel.pair))),
+            pools,
+        );
+        frame.render_widget(Paragraph::new(Line::from("↑/↓ pair   q quit")), footer);
+    }
+
+    fn panel(&mut self, pair: &str) -> &mut PairPanel {
+        let position: Option<usize> = self.pairs.iter().position(|panel| panel.pair == pair);
+        let i: usize = position.unwrap_or_else(|| {
+            self.pairs.push(PairPanel {
+                pair: pair.to_string(),
+                pools: Vec::new(),
+                aggregates: Vec::new(),
+                history: VecDeque::with_capacity(DASHBOARD_HISTORY),
+            });
+            self.pairs.len() - 1
+        });
+        &mut self.pairs[i]
+    }
+
+    /// A function to list every target any pair has, tightest first, as the tables' columns.
+    fn targets(&self) -> Vec<f64> {
+        let mut targets: Vec<f64> = self
+            .pairs
+            .iter()
+            .flat_map(|panel| panel.aggregates.iter().chain(panel.pools.iter().flat_map(|pool| pool.depths.iter())))
+            .map(|(target, _)| *target)
+            .collect();
+        targets.sort_by(|a, b| a.total_cmp(b));
+        targets.dedup_by(|a, b| (*a - *b).abs() < 1e-12);
+        targets
+    }
+}
+
+/// The `--tui` dashboard and the terminal it's drawn on, which is given back when it's dropped,
+/// however the run ends.
+pub struct Tui {
+    terminal: Terminal<CrosstermBackend<io::Stdout>>,
+    pub dashboard: Dashboard,
+}
+
+impl Tui {
+    /// A function to take over the terminal and start reading keys, on a thread of their own since
+    /// crossterm's reads block.
+    ///
+    /// Returns:
+    /// - The dashboard, and the key presses and resizes to redraw on
+    pub fn start(chain: &str) -> io::Result<(Self, mpsc::Receiver<Event>)> {
+        enable_raw_mode()?;
+        crossterm::execute!(io::stdout(), EnterAlternateScreen)?;
+        let terminal: Terminal<CrosstermBackend<io::Stdout>> = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+        let mut tui: Tui = Tui { terminal, dashboard: Dashboard::new(chain) };
+        tui.draw()?;
+        let (events_tx, events_rx) = mpsc::channel::<Event>(16);
+        std::thread::spawn(move || {
+            while !events_tx.is_closed() {
+                if !event::poll(Duration::from_millis(250)).unwrap_or(false) {
+                    continue;
+                }
+                match event::read() {
+                    Ok(event @ (Event::Key(_) | Event::Resize(..))) => {
+                        if events_tx.blocking_send(event).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(_) => break,
+                }
+            }
+        });
+        Ok((tui, events_rx))
+    }
+
+    pub fn draw(&mut self) -> io::Result<()> {
+        self.terminal.draw(|frame| self.dashboard.render(frame))?;
+        Ok(())
+    }
+
+    /// A function to act on a key press or resize.
+    ///
+    /// Returns:
+    /// - True to quit: q, Esc or Ctrl-C, which raw mode turns into a key press rather than SIGINT
+    pub fn handle(&mut self, event: &Event) -> io::Result<bool> {
+        if let Event::Key(KeyEvent { code, modifiers, kind: KeyEventKind::Press, .. }) = event {
+            match code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(true),
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return Ok(true),
+                KeyCode::Down | KeyCode::Char('j') | KeyCode::Tab => self.dashboard.select_next(),
+                KeyCode::Up | KeyCode::Char('k') | KeyCode::BackTab => self.dashboard.select_previous(),
+                _ => {}
+            }
+        }
+        self.draw()?;
+        Ok(false)
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+        let _ = crossterm::execute!(self.terminal.backend_mut(), LeaveAlternateScreen);
+        let _ = self.terminal.show_cursor();
+    }
+}
@@ -0,0 +1,286 @@
+use std::str::FromStr;
+use alloy_primitives::U256;
+use tycho_simulation::models::Token;
+use crate::{
+    amount::format_amount,
+    binary_search::SearchResult,
+    display::DisplayNames,
+    divergence::Divergence,
+    error::DepthError,
+    report::{
+        BlockReport, DepthReport, FillReport, Leg, OrganicDepth, ProtocolComparison, ProtocolShare, SetDepth,
+        TwoSidedReport,
+    },
+    stability::DepthStability,
+};
+
+/// A function to describe one leg's depth the way a trader would say it, e.g. "sell 12 WETH for
+/// 36,000 USDC".
+pub fn describe_depth(
+    names: &DisplayNames,
+    leg: &Leg,
+    depth: Result<&SearchResult, &DepthError>,
+    report: &DepthReport,
+) -> anyhow::Result<String> {
+    Ok(match depth {
+        Ok(result) => {
+            let (base_amount, quote_amount) = leg.base_quote(result.amount_in, result.amount_out);
+            let usd: String = report.usd_value.map(|usd| format!(" ≈ ${:.0}", usd)).unwrap_or_default();
+            let attribution: String = describe_attribution(report.fee_slippage, report.impact_slippage);
+            let converged: &str = if result.converged { "" } else { ", not converged" };
+            // Depth the pool cut short is a floor, not where slippage reaches the target.
+            let (at_least, limited): (&str, String) = match &result.limit {
+                Some(reason) => ("at least ", format!(", pool-limited: {}", reason)),
+                None => ("", String::new()),
+            };
+            format!(
+                "{} {}{} {}{} for {} {} (slippage {:+.4}%{}, {} simulations in {:.1}ms{}{})",
+                leg.action,
+                at_least,
+                format_amount(base_amount, leg.base.decimals),
+                names.symbol(leg.base),
+                usd,
+                format_amount(quote_amount, leg.quote.decimals),
+                names.symbol(leg.quote),
+                report.slippage.unwrap_or(0.0) * 100.0,
+                attribution,
+                result.simulations,
+                result.elapsed.as_secs_f64() * 1000.0,
+                converged,
+                limited,
+            )
+        }
+        Err(e) => format!("{} failed: {}", leg.action, e),
+    })
+}
+
+/// A function to format a report's amount (a decimal string in base units) in whole tokens.
+pub fn format_report_amount(amount: &Option<String>, token: &Token) -> anyhow::Result<String> {
+    Ok(match amount {
+        Some(amount) => format_amount(U256::from_str(amount)?, token.decimals),
+        None => "n/a".to_string(),
+    })
+}
+
+/// A function to describe a fixed-size fill, e.g. "sell 100 WETH for 295,000 USDC (slippage +1.6667%)".
+pub fn describe_fill(names: &DisplayNames, leg: &Leg, report: &FillReport) -> anyhow::Result<String> {
+    if let Some(error) = &report.error {
+        return Ok(format!("{} failed: {}", leg.action, error));
+    }
+    let usd: String = report.usd_value.map(|usd| format!(" ≈ ${:.0}", usd)).unwrap_or_default();
+    Ok(format!(
+        "{} {} {}{} for {} {} (slippage {:+.4}%{})",
+        leg.action,
+        format_report_amount(&report.base_amount, leg.base)?,
+        names.symbol(leg.base),
+        usd,
+        format_report_amount(&report.quote_amount, leg.quote)?,
+        names.symbol(leg.quote),
+        report.slippage.unwrap_or(0.0) * 100.0,
+        describe_attribution(report.fee_slippage, report.impact_slippage),
+    ))
+}
+
+/// A function to describe depth within ±target of mid, e.g. "±2% of 3000.00: bid 10 WETH + ask 9.8 WETH
+/// = 19.8 WETH (59,400 USDC)".
+pub fn describe_two_sided(
+    names: &DisplayNames,
+    base: &Token,
+    quote: &Token,
+    report: &TwoSidedReport,
+) -> anyhow::Result<String> {
+    let mut text: String = format!(
+        "±{}% of {:.6}: bid {} + ask {} = {} {} ({} {})",
+        report.target_slippage * 100.0,
+        report.mid_price,
+        format_report_amount(&report.bid_base_amount, base)?,
+        format_report_amount(&report.ask_base_amount, base)?,
+        format_report_amount(&report.base_amount, base)?,
+        names.symbol(base),
+        format_report_amount(&report.quote_amount, quote)?,
+        names.symbol(quote),
+    );
+    if let Some(usd) = report.usd_value {
+        text.push_str(&format!(" ≈ ${:.0}", usd));
+    }
+    if let Some(error) = &report.error {
+        text.push_str(&format!(", {}", error));
+    }
+    Ok(text)
+}
+
+/// A function to describe how slippage splits into fee and impact, e.g. ": fee 0.3000%, impact
+/// +1.0030%", or nothing if it couldn't be split.
+pub fn describe_attribution(fee_slippage: Option<f64>, impact_slippage: Option<f64>) -> String {
+    match (fee_slippage, impact_slippage) {
+        (Some(fee), Some(impact)) => format!(": fee {:.4}%, impact {:+.4}%", fee * 100.0, impact * 100.0),
+        _ => String::new(),
+    }
+}
+
+/// A function to describe how steady a pair's depth has been, e.g. "; depth CV 3.10% over 20 blocks".
+pub fn describe_stability(stability: Option<&DepthStability>) -> String {
+    match stability {
+        Some(DepthStability { cv: Some(cv), blocks, .. }) => format!("; depth CV {:.2}% over {} blocks", cv * 100.0, blocks),
+        _ => String::new(),
+    }
+}
+
+/// A function to describe the depth left without protocol-owned pools, for the aggregate line.
+pub fn describe_organic(organic: Option<&OrganicDepth>, token_in: &Token, names: &DisplayNames) -> anyhow::Result<String> {
+    let Some(organic) = organic else { return Ok(String::new()) };
+    let pools: String = format!(
+        "{} protocol-owned pool{}",
+        organic.protocol_owned_pools,
+        if organic.protocol_owned_pools == 1 { "" } else { "s" }
+    );
+    Ok(match (&organic.base_amount, &organic.error) {
+        (Some(base_amount), _) => format!(
+            "; {} {} without {}",
+            format_amount(base_amount.parse::<U256>()?, token_in.decimals),
+            names.symbol(token_in),
+            pools
+        ),
+        (None, Some(e)) => format!("; without {}: {}", pools, e),
+        (None, None) => String::new(),
+    })
+}
+
+/// A function to describe a block's aggregate depth for a chat message, one line per pair and
+/// target under the block, e.g. "WETH/USDC at 2.00%: 34 WETH across 2 pools ($102417)".
+pub fn describe_block_summary(report: &BlockReport) -> String {
+    let mut lines: Vec<String> = vec![format!("{} block #{}", report.chain, report.block_number)];
+    for aggregate in report.aggregates.iter() {
+        let base: &str = aggregate.pair.split('/').next().unwrap_or_default();
+        let depth: String = match (&aggregate.conversions.base_tokens, &aggregate.error) {
+            (Some(tokens), _) => format!("{} {} across {} pools", tokens, base, aggregate.pools),
+            (None, Some(e)) => format!("failed: {}", e),
+            (None, None) => "unknown".to_string(),
+        };
+        let usd: String = aggregate.usd_value.map(|usd| format!(" (${:.0})", usd)).unwrap_or_default();
+        lines.push(format!("{} at {:.2}%: {}{}", aggregate.pair, aggregate.target_slippage * 100.0, depth, usd));
+    }
+    lines.join("\n")
+}
+
+/// A function to describe a divergence, e.g. "3003.2 in 0xaaa vs 3016 in 0xccc; buy 3.1 WETH for
+/// 9,301.2 USDC and sell it in the other for 11.4 USDC profit", or "within fees" if no size pays.
+pub fn describe_divergence(divergence: &Divergence, base: &Token, quote: &Token, names: &DisplayNames) -> String {
+    let spots: String = format!(
+        "{:.6} in {} vs {:.6} in {}",
+        divergence.cheap_spot, divergence.cheap_pool, divergence.rich_spot, divergence.rich_pool
+    );
+    if divergence.base_amount == "0" {
+        return format!("{}; within fees", spots);
+    }
+    format!(
+        "{}; buy {} {} for {} {} and sell it in the other for {} {} profit",
+        spots,
+        divergence.base_tokens,
+        names.symbol(base),
+        divergence.quote_tokens,
+        names.symbol(quote),
+        divergence.profit_tokens,
+        names.symbol(quote),
+    )
+}
+
+/// A function to describe how an aggregate splits across protocols, e.g. "; uniswap_v3 62.1%,
+/// uniswap_v4 30.2%, curve 7.7%". Empty when it all goes through one protocol.
+pub fn describe_protocol_shares(shares: &[ProtocolShare]) -> String {
+    if shares.len() < 2 {
+        return String::new();
+    }
+    let shares: Vec<String> = shares
+        .iter()
+        .map(|share| {
+            let protocol: &str = if share.protocol.is_empty() { "unknown" } else { &share.protocol };
+            match share.share {
+                Some(share) => format!("{} {:.1}%", protocol, share * 100.0),
+                None => protocol.to_string(),
+            }
+        })
+        .collect();
+    format!("; {}", shares.join(", "))
+}
+
+/// A function to describe a protocol-set comparison, e.g. "; with-v4 34 WETH vs without-v4 30 WETH
+/// (+4 WETH, +13.33%)".
+pub fn describe_comparison(
+    comparison: Option<&ProtocolComparison>,
+    token_in: &Token,
+    names: &DisplayNames,
+) -> anyhow::Result<String> {
+    let Some(comparison) = comparison else { return Ok(String::new()) };
+    let describe = |set: &SetDepth| -> anyhow::Result<String> {
+        Ok(match (&set.base_amount, &set.error) {
+            (Some(base_amount), _) => format!(
+                "{} {} {}",
+                set.name,
+                format_amount(base_amount.parse::<U256>()?, token_in.decimals),
+                names.symbol(token_in)
+            ),
+            (None, Some(e)) => format!("{} failed: {}", set.name, e),
+            (None, None) => set.name.clone(),
+        })
+    };
+    let delta: String = match &comparison.delta_base_tokens {
+        Some(tokens) => {
+            let sign: &str = if tokens.starts_with('-') { "" } else { "+" };
+            let ratio: String = comparison.delta_ratio.map(|ratio| format!(", {:+.2}%", ratio * 100.0)).unwrap_or_default();
+            format!(" ({}{} {}{})", sign, tokens, names.symbol(token_in), ratio)
+        }
+        None => String::new(),
+    };
+    Ok(format!("; {} vs {}{}", describe(&comparison.a)?, describe(&comparison.b)?, delta))
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigUint;
+    use super::*;
+
+    fn tokens() -> (Token, Token) {
+        (
+            Token::new("0x0000000000000000000000000000000000000001", 18, "WETH", BigUint::from(0u32)),
+            Token::new("0x0000000000000000000000000000000000000002", 6, "USDC", BigUint::from(0u32)),
+        )
+    }
+
+    #[test]
+    fn describes_a_fill_in_whole_tokens() {
+        let (weth, usdc) = tokens();
+        let sell: Leg = Leg::sell(&weth, &usdc, 3000.0).with_fee(Some(0.003));
+        let one_weth: U256 = U256::from(10u64).pow(U256::from(18u64));
+        let report: FillReport = FillReport::new("1", &sell, Ok((one_weth, U256::from(2_970_000_000u64))));
+        assert_eq!(
+            describe_fill(&DisplayNames::default(), &sell, &report).unwrap(),
+            "sell 1 WETH for 2970 USDC (slippage +1.0000%: fee 0.3000%, impact +0.7021%)"
+        );
+        let failed: FillReport = FillReport::new("$1M", &sell, Err("no dollar price".to_string()));
+        assert_eq!(describe_fill(&DisplayNames::default(), &sell, &failed).unwrap(), "sell failed: no dollar price");
+    }
+
+    #[test]
+    fn leaves_out_what_there_is_nothing_to_say_about() {
+        assert_eq!(describe_attribution(Some(0.003), None), "");
+        assert_eq!(describe_stability(None), "");
+        let share = |protocol: &str, share: f64| ProtocolShare {
+            protocol: protocol.to_string(),
+            pools: 1,
+            base_amount: "0".to_string(),
+            quote_amount: "0".to_string(),
+            share: Some(share),
+        };
+        assert_eq!(describe_protocol_shares(&[share("uniswap_v3", 1.0)]), "");
+        assert_eq!(
+            describe_protocol_shares(&[share("uniswap_v3", 0.621), share("", 0.379)]),
+            "; uniswap_v3 62.1%, unknown 37.9%"
+        );
+        let (weth, _) = tokens();
+        assert_eq!(
+            describe_organic(Some(&OrganicDepth::empty(1)), &weth, &DisplayNames::default()).unwrap(),
+            "; 0 WETH without 1 protocol-owned pool"
+        );
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use tycho_common::Bytes;
+use tycho_simulation::models::Token;
+
+/// How a token is shown in reports instead of its registry symbol, e.g. `USDbC` as
+/// "Bridged USDC (Base)".
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TokenDisplay {
+    /// Replaces the symbol wherever a report names the token.
+    #[serde(default)]
+    pub symbol: Option<String>,
+    /// A longer name, shown where the token is introduced.
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// Display overrides by token address.
+///
+/// Presentation only: resolving `--pair` symbols and matching pools always use the registry's
+/// symbols and addresses, so an override can never change which pools are measured.
+#[derive(Debug, Clone, Default)]
+pub struct DisplayNames {
+    by_address: HashMap<Bytes, TokenDisplay>,
+}
+
+impl DisplayNames {
+    pub fn new(by_address: HashMap<Bytes, TokenDisplay>) -> Self {
+        Self { by_address }
+    }
+
+    /// A function to get the symbol a report should show for a token.
+    ///
+    /// Returns:
+    /// - The override if there is one, else the registry symbol
+    pub fn symbol<'a>(&'a self, token: &'a Token) -> &'a str {
+        self.by_address
+            .get(&token.address)
+            .and_then(|display| display.symbol.as_deref())
+            .unwrap_or(&token.symbol)
+    }
+
+    /// A function to get a token's long name, if one is configured.
+    pub fn name(&self, token: &Token) -> Option<&str> {
+        self.by_address.get(&token.address).and_then(|display| display.name.as_deref())
+    }
+
+    /// A function to label a pair for reports, e.g. "WETH/Bridged USDC (Base)".
+    pub fn pair(&self, base: &Token, quote: &Token) -> String {
+        format!("{}/{}", self.symbol(base), self.symbol(quote))
+    }
+}
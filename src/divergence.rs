@@ -0,0 +1,106 @@
+use alloy_primitives::U256;
+use serde::Serialize;
+use tycho_simulation::models::Token;
+use crate::{
+    amount::format_amount,
+    error::DepthError,
+    orderbook::{fill_to_price, quote},
+    provider::{AmountOutProvider, Pool},
+    route::TwoHop,
+};
+
+/// Two pools of a pair whose spots are further apart than `--arb-bps`, and what it takes to bring
+/// them back together: quote spent buying base in the cheaper pool and sold straight back into
+/// the richer one, until the round trip stops paying.
+#[derive(Debug, Clone, Serialize)]
+pub struct Divergence {
+    /// e.g. "WETH/USDC"
+    pub pair: String,
+    /// The pool with the lowest spot, where base is bought.
+    pub cheap_pool: String,
+    /// The pool with the highest spot, where it's sold.
+    pub rich_pool: String,
+    /// Spots in quote per base.
+    pub cheap_spot: f64,
+    pub rich_spot: f64,
+    /// How far the richer spot is above the cheaper one, in basis points.
+    pub divergence_bps: f64,
+    /// Base moved from one pool to the other before their marginal prices meet, in base units. Zero
+    /// when the pools' fees eat the whole divergence.
+    pub base_amount: String,
+    /// Quote spent in the cheaper pool for it, in base units.
+    pub quote_amount: String,
+    /// Quote the round trip returns over what it spent, before gas, in base units.
+    pub profit_amount: String,
+    pub base_tokens: String,
+    pub quote_tokens: String,
+    pub profit_tokens: String,
+}
+
+/// A function to find a pair's widest spot divergence and the size tradable across it.
+///
+/// Only the cheapest and richest pools are compared: any other two diverge by less, and trading
+/// the widest gap is what closes the others. The size is where the round trip quote → base in the
+/// cheap pool → quote in the rich pool stops returning more quote than it takes, found as the
+/// order book finds a level: by the round trip's marginal price over a small step, doubling and
+/// then bisecting. Fees are in the quotes, so a divergence inside them has no size.
+///
+/// Args:
+/// - pair: The pair's label, e.g. "WETH/USDC"
+/// - pools: (id, pool) for every pool of the pair, Tycho states or any other `Pool`
+/// - base: The pair's base token, the one spots price
+/// - quote_token: The pair's quote token, the one spots are in
+/// - min_bps: The smallest divergence worth flagging, in basis points
+///
+/// Returns:
+/// - The divergence, None if fewer than two pools could be priced or they're within `min_bps` of
+///   each other, or an error if a quote fails outright
+pub fn widest_divergence<'a, P: Into<Pool<'a>> + Copy>(
+    pair: &str,
+    pools: &[(String, P)],
+    base: &Token,
+    quote_token: &Token,
+    min_bps: f64,
+) -> Result<Option<Divergence>, DepthError> {
+    let spots: Vec<(&str, Pool, f64)> = pools
+        .iter()
+        .filter_map(|(id, pool)| {
+            let pool: Pool = (*pool).into();
+            let spot: f64 = pool.spot(base, quote_token).ok().filter(|spot| spot.is_finite() && *spot > 0.0)?;
+            Some((id.as_str(), pool, spot))
+        })
+        .collect();
+    let by_spot = |a: &&(&str, Pool, f64), b: &&(&str, Pool, f64)| a.2.total_cmp(&b.2);
+    let (Some(cheap), Some(rich)) = (spots.iter().min_by(by_spot), spots.iter().max_by(by_spot)) else {
+        return Ok(None);
+    };
+    let divergence_bps: f64 = (rich.2 / cheap.2 - 1.0) * 10_000.0;
+    if cheap.0 == rich.0 || divergence_bps <= min_bps {
+        return Ok(None);
+    }
+
+    let round_trip: TwoHop = TwoHop { first: cheap.1, via: base, second: rich.1 };
+    let round_trip: Pool = Pool::Provider(&round_trip);
+    let quote_amount: U256 = fill_to_price(round_trip, quote_token, quote_token, 1.0, U256::ZERO)?;
+    let (base_amount, quote_out): (U256, U256) = if quote_amount.is_zero() {
+        (U256::ZERO, U256::ZERO)
+    } else {
+        (quote(cheap.1, quote_amount, quote_token, base)?, quote(round_trip, quote_amount, quote_token, quote_token)?)
+    };
+    let profit_amount: U256 = quote_out.saturating_sub(quote_amount);
+
+    Ok(Some(Divergence {
+        pair: pair.to_string(),
+        cheap_pool: cheap.0.to_string(),
+        rich_pool: rich.0.to_string(),
+        cheap_spot: cheap.2,
+        rich_spot: rich.2,
+        divergence_bps,
+        base_amount: base_amount.to_string(),
+        quote_amount: quote_amount.to_string(),
+        profit_amount: profit_amount.to_string(),
+        base_tokens: format_amount(base_amount, base.decimals),
+        quote_tokens: format_amount(quote_amount, quote_token.decimals),
+        profit_tokens: format_amount(profit_amount, quote_token.decimals),
+    }))
+}
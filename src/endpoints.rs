@@ -0,0 +1,200 @@
+use std::fmt;
+use serde::Serialize;
+
+/// How long a stream may go without a block before its endpoint counts as failed. Long enough
+/// for a slow chain's block time several times over.
+pub const DEFAULT_STALL_TIMEOUT_SECS: u64 = 60;
+
+/// How much one success or failure moves an endpoint's health, which is a moving average of
+/// outcomes between 0 (failing) and 1 (healthy).
+const HEALTH_WEIGHT: f64 = 0.3;
+
+/// A switch from one Tycho endpoint to another, recorded so gaps in the data can be explained.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Failover {
+    pub from: String,
+    pub to: String,
+    /// Why `from` was dropped, e.g. "no block for 60s".
+    pub reason: String,
+    /// The last block `from` delivered, if any; blocks after it until `to`'s snapshot are missing.
+    pub last_block: Option<u64>,
+}
+
+impl fmt::Display for Failover {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {} after {}", self.from, self.to, self.reason)?;
+        if let Some(last_block) = self.last_block {
+            write!(f, " (last block #{})", last_block)?;
+        }
+        Ok(())
+    }
+}
+
+/// The Tycho endpoints a stream can use, in order of preference, with a health score each.
+///
+/// Selection is sticky: the stream stays on an endpoint for as long as it works, and only moves
+/// when it errors or stalls, to whichever other endpoint is healthiest (the earliest on a tie). A
+/// single endpoint never fails over; it's just reconnected.
+#[derive(Debug, Clone)]
+pub struct Endpoints {
+    urls: Vec<String>,
+    health: Vec<f64>,
+    current: usize,
+    last_block: Option<u64>,
+    /// A failover the next connection hasn't reported yet.
+    pending: Option<Failover>,
+}
+
+impl Endpoints {
+    /// Args:
+    /// - urls: The endpoints, primary first; must not be empty
+    pub fn new(urls: Vec<String>) -> Self {
+        assert!(!urls.is_empty(), "at least one Tycho endpoint is needed");
+        let health: Vec<f64> = vec![1.0; urls.len()];
+        Self { urls, health, current: 0, last_block: None, pending: None }
+    }
+
+    /// The endpoint to connect to.
+    pub fn current(&self) -> &str {
+        &self.urls[self.current]
+    }
+
+    pub fn urls(&self) -> &[String] {
+        &self.urls
+    }
+
+    pub fn health(&self) -> &[f64] {
+        &self.health
+    }
+
+    /// A function to credit the current endpoint with a block it delivered.
+    pub fn record_success(&mut self, block_number: u64) {
+        self.health[self.current] += (1.0 - self.health[self.current]) * HEALTH_WEIGHT;
+        self.last_block = Some(block_number);
+    }
+
+    /// A function to mark the current endpoint as failed and move to the healthiest other one.
+    ///
+    /// Args:
+    /// - reason: Why it failed, for the failover record
+    ///
+    /// Returns:
+    /// - The failover, or None if there's no other endpoint to move to
+    pub fn record_failure(&mut self, reason: &str) -> Option<Failover> {
+        self.health[self.current] *= 1.0 - HEALTH_WEIGHT;
+        let next: usize = (0..self.urls.len())
+            .filter(|&i| i != self.current)
+            .fold(None, |best: Option<usize>, i| match best {
+                Some(best) if self.health[best] >= self.health[i] => Some(best),
+                _ => Some(i),
+            })?;
+        let failover: Failover = Failover {
+            from: self.urls[self.current].clone(),
+            to: self.urls[next].clone(),
+            reason: reason.to_string(),
+            last_block: self.last_block,
+        };
+        self.current = next;
+        self.pending = Some(failover.clone());
+        Some(failover)
+    }
+
+    /// A function to take the failover that led to the current endpoint, once, so the first block
+    /// from it can carry it.
+    pub fn take_failover(&mut self) -> Option<Failover> {
+        self.pending.take()
+    }
+}
+
+/// A function to split a comma-separated endpoint list, e.g. the TYCHO_URL variable.
+pub fn parse_endpoint_list(list: &str) -> Vec<String> {
+    list.split(',').map(str::trim).filter(|url| !url.is_empty()).map(str::to_string).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoints(urls: &[&str]) -> Endpoints {
+        Endpoints::new(urls.iter().map(|url| url.to_string()).collect())
+    }
+
+    #[test]
+    fn moves_health_by_a_moving_average() {
+        let mut endpoints: Endpoints = endpoints(&["a", "b"]);
+        endpoints.record_failure("stalled");
+        assert!((endpoints.health()[0] - 0.7).abs() < 1e-12);
+        assert_eq!(endpoints.current(), "b");
+        // Back on "a", which recovers a share of what it lost with each block.
+        endpoints.record_failure("stalled");
+        endpoints.record_success(10);
+        assert!((endpoints.health()[0] - (0.7 + 0.3 * HEALTH_WEIGHT)).abs() < 1e-12);
+        assert!((endpoints.health()[1] - 0.7).abs() < 1e-12);
+        for block in 11..100 {
+            endpoints.record_success(block);
+        }
+        assert!(endpoints.health()[0] < 1.0 && endpoints.health()[0] > 0.999);
+    }
+
+    #[test]
+    fn fails_over_to_the_healthiest_other_endpoint() {
+        let mut endpoints: Endpoints = endpoints(&["primary", "second", "third"]);
+        // Ties go to the earliest.
+        assert_eq!(endpoints.record_failure("error").map(|f| f.to), Some("second".to_string()));
+        assert_eq!(endpoints.record_failure("error").map(|f| f.to), Some("third".to_string()));
+        // "primary" and "second" have failed once each; the tie goes to the earliest again.
+        assert_eq!(endpoints.record_failure("error").map(|f| f.to), Some("primary".to_string()));
+        endpoints.record_failure("error");
+        assert_eq!(endpoints.current(), "second");
+        // "primary" and "second" have failed twice each, "third" only once.
+        assert_eq!(endpoints.record_failure("error").map(|f| f.to), Some("third".to_string()));
+    }
+
+    #[test]
+    fn sticks_to_a_working_endpoint() {
+        let mut endpoints: Endpoints = endpoints(&["primary", "backup"]);
+        endpoints.record_failure("no block for 60s");
+        for block in 1..50 {
+            endpoints.record_success(block);
+        }
+        // The primary has long been the healthier one, but the stream only moves on failure.
+        assert!(endpoints.health()[0] < endpoints.health()[1]);
+        assert_eq!(endpoints.current(), "backup");
+    }
+
+    #[test]
+    fn records_each_failover_once_with_the_last_block() {
+        let mut endpoints: Endpoints = endpoints(&["primary", "backup"]);
+        assert_eq!(endpoints.take_failover(), None);
+        endpoints.record_success(41);
+        endpoints.record_success(42);
+        let failover: Failover = endpoints.record_failure("no block for 60s").unwrap();
+        assert_eq!(
+            failover,
+            Failover {
+                from: "primary".to_string(),
+                to: "backup".to_string(),
+                reason: "no block for 60s".to_string(),
+                last_block: Some(42),
+            }
+        );
+        assert_eq!(failover.to_string(), "primary -> backup after no block for 60s (last block #42)");
+        assert_eq!(endpoints.take_failover(), Some(failover));
+        assert_eq!(endpoints.take_failover(), None);
+    }
+
+    #[test]
+    fn never_fails_over_from_a_single_endpoint() {
+        let mut endpoints: Endpoints = endpoints(&["only"]);
+        assert_eq!(endpoints.record_failure("error"), None);
+        assert_eq!(endpoints.current(), "only");
+        assert!((endpoints.health()[0] - 0.7).abs() < 1e-12);
+        assert_eq!(endpoints.take_failover(), None);
+    }
+
+    #[test]
+    fn splits_an_endpoint_list() {
+        assert_eq!(parse_endpoint_list(" https://a , ,https://b,"), vec!["https://a", "https://b"]);
+        assert!(parse_endpoint_list("").is_empty());
+    }
+}
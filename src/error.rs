@@ -0,0 +1,58 @@
+use std::{fmt, panic::AssertUnwindSafe};
+use alloy_primitives::U256;
+use thiserror::Error;
+use crate::supervisor::panic_message;
+
+/// Everything that can go wrong measuring depth, from the stream down to a single simulation.
+///
+/// Errors are per pool and per search: the block loop records them against the pool and moves on,
+/// so one bad pool doesn't take down the run.
+#[derive(Debug, Error)]
+pub enum DepthError {
+    #[error("arithmetic overflow")]
+    Overflow,
+    /// The reference price was zero, negative, or not finite.
+    #[error("invalid spot price {0}")]
+    InvalidSpotPrice(f64),
+    /// `spot_price` or `get_amount_out` failed for the pool.
+    #[error("simulation failed: {0}")]
+    Simulation(String),
+    /// The protocol stream failed to build or dropped a message it couldn't decode.
+    #[error("stream failed: {0}")]
+    Stream(String),
+    /// Bracketing never got past the target, e.g. a pool whose price barely moves with size.
+    #[error("no convergence at {target_slippage} slippage after {simulations} simulations")]
+    NotConverged { target_slippage: f64, simulations: u32 },
+    /// A slippage or precision that isn't a finite, non-negative decimal small enough to convert.
+    #[error("invalid slippage {0}")]
+    InvalidSlippage(f64),
+    /// An exact-output search passed the slippage limit before the pool returned enough.
+    #[error("receiving {amount_out} takes more than {max_slippage} slippage")]
+    OutputOutOfReach { amount_out: U256, max_slippage: f64 },
+    /// A token with more decimals than a whole token fits in 256 bits, see `amount::MAX_DECIMALS`.
+    #[error("{0} decimals is more than a 256-bit amount can hold one whole token of")]
+    UnsupportedDecimals(usize),
+    /// Bracketing reached the pool's cap still under the target, see `binary_search::bracket_cap`.
+    #[error("{target_slippage} slippage is out of reach: still under it selling {max_in}")]
+    InsufficientLiquidity { target_slippage: f64, max_in: U256 },
+    /// An unwind notional that isn't a finite, non-negative amount.
+    #[error("invalid notional {0}; expected a non-negative amount of token_out")]
+    InvalidNotional(f64),
+    #[error("depth is at least {amount_in}, but the pool won't quote more in one trade: {reason}")]
+    PoolLimited { amount_in: U256, reason: String },
+}
+
+/// A function to call into a pool's simulation, turning both its errors and its panics (VM pools
+/// can panic on state they don't expect) into `DepthError::Simulation`, so the pool fails alone.
+///
+/// Args:
+/// - simulation: The call, e.g. `|| state.get_amount_out(amount, token_in, token_out)`
+///
+/// Returns:
+/// - What the call returned, or why it failed
+pub fn guard_simulation<T, E: fmt::Debug>(simulation: impl FnOnce() -> Result<T, E>) -> Result<T, DepthError> {
+    match std::panic::catch_unwind(AssertUnwindSafe(simulation)) {
+        Ok(result) => result.map_err(|e| DepthError::Simulation(format!("{:?}", e))),
+        Err(payload) => Err(DepthError::Simulation(format!("panicked: {}", panic_message(payload.as_ref())))),
+    }
+}
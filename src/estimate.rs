@@ -0,0 +1,120 @@
+use std::time::{Duration, Instant};
+use alloy_primitives::U256;
+use tycho_simulation::{
+    evm::protocol::u256_num::u256_to_f64,
+    models::Token,
+    protocol::state::ProtocolSim,
+};
+use crate::{
+    amount::one_token,
+    binary_search::{Simulator, DEFAULT_BRACKET_TVL_MULTIPLE},
+    error::{guard_simulation, DepthError},
+    provider::{AmountOutProvider, Pool},
+};
+
+/// How much larger the second probe is than the first. Far enough apart that integer rounding
+/// doesn't dominate the slope, close enough that the curve is still roughly linear between them.
+pub const PROBE_RATIO: u64 = 1_000;
+/// The large probe is this fraction of the pool's bracket cap (see `binary_search::bracket_cap`):
+/// a tenth of the reserve in a v2 pool, around 2% of either side in others, so it lands near the
+/// usual targets instead of at a fixed size that is noise in a deep pool and past a shallow one.
+pub const PROBE_CAP_DIVISOR: u64 = 1_000;
+
+/// A coarse depth estimate from two probes. Always approximate: callers should label it as such.
+#[derive(Debug, Clone, Copy)]
+pub struct DepthEstimate {
+    /// Estimated amount of token_in that can be swapped before slippage reaches the target.
+    pub amount_in: U256,
+    /// (amount_in, slippage) for each probe.
+    pub probes: [(U256, f64); 2],
+    /// True if the probes saw no impact to interpolate through, so `amount_in` is the larger
+    /// probe: the pool is at least this deep, but how much deeper wasn't measured.
+    pub lower_bound: bool,
+    pub elapsed: Duration,
+}
+
+/// A function to estimate depth at the target slippage from two probes instead of a full search,
+/// for scans over thousands of pairs.
+///
+/// Slippage levels off toward 100% with size, but the extra cost it implies, `s / (1 - s)` (what
+/// the fill costs over the spot price), grows close to linearly with size for constant-product
+/// pools and within a concentrated-liquidity range. So the target crossing is found by linear
+/// interpolation (or extrapolation) of that cost through the two probes. The probes are sized from
+/// the pool's bracket cap, falling back to one and `PROBE_RATIO` whole tokens for pools without
+/// one. Within roughly 10% of a full search on typical pools (`tests/estimate.rs` checks v2-style
+/// and concentrated-liquidity mocks), and worse where liquidity is very uneven across ticks.
+///
+/// Args:
+/// - target_slippage: The slippage tolerance, as a decimal (e.g., 2% slippage = 0.02)
+/// - state: The pool state
+/// - token_in: The token being sold
+/// - token_out: The token being bought
+///
+/// Returns:
+/// - The estimate and the probes it came from
+pub fn estimate_depth(
+    target_slippage: f64,
+    state: &dyn ProtocolSim,
+    token_in: &Token,
+    token_out: &Token,
+) -> Result<DepthEstimate, DepthError> {
+    let spot_price: f64 = guard_simulation(|| state.spot_price(token_in, token_out))?;
+
+    estimate_depth_against_reference(target_slippage, state, token_in, token_out, spot_price)
+}
+
+/// Like `estimate_depth`, but against a caller-supplied reference price, e.g. a scenario-shifted
+/// spot.
+pub fn estimate_depth_against_reference(
+    target_slippage: f64,
+    state: &dyn ProtocolSim,
+    token_in: &Token,
+    token_out: &Token,
+    reference_price: f64,
+) -> Result<DepthEstimate, DepthError> {
+    estimate_depth_in(target_slippage, Pool::Tycho(state), token_in, token_out, reference_price)
+}
+
+/// `estimate_depth_against_reference` over any `Pool`.
+pub(crate) fn estimate_depth_in(
+    target_slippage: f64,
+    pool: Pool,
+    token_in: &Token,
+    token_out: &Token,
+    reference_price: f64,
+) -> Result<DepthEstimate, DepthError> {
+    let started: Instant = Instant::now();
+    let mut sim: Simulator = Simulator::new(pool, token_in, token_out, reference_price)?;
+
+    let one: U256 = one_token(token_in.decimals).ok_or(DepthError::UnsupportedDecimals(token_in.decimals))?;
+    let cap: Option<U256> = pool.max_in(token_in, token_out, reference_price, DEFAULT_BRACKET_TVL_MULTIPLE);
+    let (small_in, large_in): (U256, U256) = match cap.map(|cap| cap / U256::from(PROBE_CAP_DIVISOR)) {
+        Some(large_in) if large_in >= U256::from(PROBE_RATIO) => (large_in / U256::from(PROBE_RATIO), large_in),
+        _ => (one, one.checked_mul(U256::from(PROBE_RATIO)).ok_or(DepthError::Overflow)?),
+    };
+    let (_, small_slippage) = sim.simulate(small_in)?;
+    let (_, large_slippage) = sim.simulate(large_in)?;
+    let (x1, y1): (f64, f64) = (u256_to_f64(small_in), cost_over_spot(small_slippage.to_f64()));
+    let (x2, y2): (f64, f64) = (u256_to_f64(large_in), cost_over_spot(large_slippage.to_f64()));
+    let target: f64 = cost_over_spot(target_slippage);
+
+    let (amount_in, lower_bound): (f64, bool) = if y2 <= y1 {
+        // No measurable impact between the probes: the pool is at least this deep.
+        if y2 < target { (x2, true) } else { (0.0, false) }
+    } else {
+        ((x1 + (target - y1) * (x2 - x1) / (y2 - y1)).max(0.0), false)
+    };
+
+    Ok(DepthEstimate {
+        amount_in: U256::from(amount_in.min(u128::MAX as f64) as u128),
+        probes: [(small_in, small_slippage.to_f64()), (large_in, large_slippage.to_f64())],
+        lower_bound,
+        elapsed: started.elapsed(),
+    })
+}
+
+/// A function to turn a slippage into what the fill costs over the spot price, e.g. 50% slippage
+/// pays twice spot, 100% over it. Infinite for a fill that returns nothing.
+fn cost_over_spot(slippage: f64) -> f64 {
+    slippage / (1.0 - slippage)
+}
@@ -0,0 +1,189 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use plotters::{coord::Shift, prelude::*};
+use crate::{
+    chart::{compact, DepthChart, FigureFormat},
+    report::{BlockReport, TradeAction},
+    sink::partition_slug,
+};
+
+/// Every figure's size in pixels.
+pub const FIGURE_SIZE: (u32, u32) = (1024, 640);
+/// How many blocks of aggregate depth each pair's time series keeps; a day of mainnet blocks.
+pub const FIGURE_HISTORY: usize = 7_200;
+
+/// (block, whole base tokens sold, None where the search failed), oldest first.
+type BlockDepths = Vec<(u64, Option<f64>)>;
+
+/// One pair's aggregate depth at each target, block by block.
+#[derive(Debug, Clone, PartialEq)]
+struct PairHistory {
+    pair: String,
+    targets: Vec<(f64, BlockDepths)>,
+}
+
+/// Writes report-ready figures for `--charts-dir`, one file per pair and kind, rewritten every block
+/// so the directory always holds the latest:
+/// - `<pair>-curves.<ext>`: each pool's slippage against size, as `--chart` prints it
+/// - `<pair>-depth.<ext>`: aggregate depth at each target over the run's blocks
+#[derive(Debug, Clone)]
+pub struct FigureWriter {
+    dir: PathBuf,
+    format: FigureFormat,
+    pairs: Vec<PairHistory>,
+}
+
+impl FigureWriter {
+    /// A function to set up the writer, creating the directory if it's missing.
+    pub fn create(dir: &Path, format: FigureFormat) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self { dir: dir.to_path_buf(), format, pairs: Vec::new() })
+    }
+
+    /// A function to take in a measured block and redraw its pairs' figures.
+    ///
+    /// Args:
+    /// - report: The block's report, for the aggregates
+    /// - charts: The block's sampled curves per pair; empty ones are skipped
+    ///
+    /// Returns:
+    /// - The files written
+    pub fn write_block(&mut self, report: &BlockReport, charts: &[DepthChart]) -> io::Result<Vec<PathBuf>> {
+        self.observe(report);
+        let mut written: Vec<PathBuf> = Vec::new();
+        for chart in charts.iter().filter(|chart| !chart.is_empty()) {
+            written.push(self.render(&chart.pair, "curves", Figure::Curves(chart))?);
+        }
+        for history in self.pairs.iter() {
+            written.push(self.render(&history.pair, "depth", Figure::History(history))?);
+        }
+        Ok(written)
+    }
+
+    fn observe(&mut self, report: &BlockReport) {
+        for aggregate in report.aggregates.iter().filter(|aggregate| aggregate.action == TradeAction::Sell) {
+            let depth: Option<f64> =
+                aggregate.conversions.base_tokens.as_ref().and_then(|tokens| tokens.parse::<f64>().ok());
+            let i: usize = match self.pairs.iter().position(|history| history.pair == aggregate.pair) {
+                Some(i) => i,
+                None => {
+                    self.pairs.push(PairHistory { pair: aggregate.pair.clone(), targets: Vec::new() });
+                    self.pairs.len() - 1
+                }
+            };
+            let targets: &mut Vec<(f64, BlockDepths)> = &mut self.pairs[i].targets;
+            let j: usize = match targets.iter().position(|(t, _)| (t - aggregate.target_slippage).abs() < 1e-12) {
+                Some(j) => j,
+                None => {
+                    targets.push((aggregate.target_slippage, Vec::new()));
+                    targets.len() - 1
+                }
+            };
+            let blocks: &mut BlockDepths = &mut targets[j].1;
+            if blocks.len() == FIGURE_HISTORY {
+                blocks.remove(0);
+            }
+            blocks.push((report.block_number, depth));
+        }
+    }
+
+    /// A function to draw one figure with whichever backend the format needs.
+    ///
+    /// Returns:
+    /// - The file written, e.g. `out/weth-usdc-curves.svg`
+    fn render(&self, pair: &str, kind: &str, figure: Figure) -> io::Result<PathBuf> {
+        let path: PathBuf = self.dir.join(format!("{}-{}.{}", partition_slug(pair), kind, self.format.extension()));
+        let result: Result<(), String> = match self.format {
+            FigureFormat::Svg => draw(SVGBackend::new(&path, FIGURE_SIZE).into_drawing_area(), figure),
+            FigureFormat::Png => draw(BitMapBackend::new(&path, FIGURE_SIZE).into_drawing_area(), figure),
+        };
+        result.map_err(|e| io::Error::other(format!("{}: {}", path.display(), e)))?;
+        Ok(path)
+    }
+}
+
+enum Figure<'a> {
+    Curves(&'a DepthChart),
+    History(&'a PairHistory),
+}
+
+/// A function to draw a figure onto any backend and finish the file.
+fn draw<DB: DrawingBackend>(root: DrawingArea<DB, Shift>, figure: Figure) -> Result<(), String> {
+    root.fill(&WHITE).map_err(|e| e.to_string())?;
+    match figure {
+        Figure::Curves(chart) => draw_curves(&root, chart),
+        Figure::History(history) => draw_history(&root, history),
+    }
+    .map_err(|e| e.to_string())?;
+    root.present().map_err(|e| e.to_string())
+}
+
+/// A function to plot each pool's slippage against size, sizes on a log scale.
+fn draw_curves<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    chart: &DepthChart,
+) -> Result<(), DrawingAreaErrorKind<DB::ErrorType>> {
+    let points = || chart.series.iter().flat_map(|series| series.points.iter());
+    let smallest: f64 = points().map(|(size, _)| *size).fold(f64::INFINITY, f64::min);
+    let largest: f64 = points().map(|(size, _)| *size).fold(smallest * 10.0, f64::max);
+    let highest: f64 = points().map(|(_, slippage)| *slippage).fold(0.0, f64::max).max(1e-4);
+    let mut plot = ChartBuilder::on(root)
+        .caption(format!("{} slippage by size", chart.pair), ("sans-serif", 24))
+        .margin(16)
+        .x_label_area_size(48)
+        .y_label_area_size(72)
+        .build_cartesian_2d((smallest..largest).log_scale(), 0.0..highest * 1.05)?;
+    plot.configure_mesh()
+        .x_desc(format!("{} sold", chart.base))
+        .x_label_formatter(&|size| compact(*size))
+        .y_desc("slippage")
+        .y_label_formatter(&|slippage| format!("{:.2}%", slippage * 100.0))
+        .draw()?;
+    for (i, series) in chart.series.iter().enumerate() {
+        let style: ShapeStyle = Palette99::pick(i).stroke_width(2);
+        plot.draw_series(LineSeries::new(series.points.iter().copied(), style))?
+            .label(series.label.clone())
+            .legend(move |(x, y)| PathElement::new([(x, y), (x + 20, y)], style));
+    }
+    plot.configure_series_labels().background_style(WHITE.mix(0.8)).border_style(BLACK).draw()
+}
+
+/// A function to plot a pair's aggregate depth at each target over blocks. Failed blocks break the
+/// line rather than being drawn as zero.
+fn draw_history<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    history: &PairHistory,
+) -> Result<(), DrawingAreaErrorKind<DB::ErrorType>> {
+    let blocks = || history.targets.iter().flat_map(|(_, blocks)| blocks.iter());
+    let first: u64 = blocks().map(|(block, _)| *block).min().unwrap_or_default();
+    let last: u64 = blocks().map(|(block, _)| *block).max().unwrap_or_default().max(first + 1);
+    let deepest: f64 = blocks().filter_map(|(_, depth)| *depth).fold(0.0, f64::max).max(1e-9);
+    let base: &str = history.pair.split('/').next().unwrap_or(&history.pair);
+    let mut plot = ChartBuilder::on(root)
+        .caption(format!("{} aggregate depth", history.pair), ("sans-serif", 24))
+        .margin(16)
+        .x_label_area_size(48)
+        .y_label_area_size(72)
+        .build_cartesian_2d(first..last, 0.0..deepest * 1.05)?;
+    plot.configure_mesh().x_desc("block").y_desc(format!("{} sold", base)).draw()?;
+    for (i, (target, blocks)) in history.targets.iter().enumerate() {
+        let style: ShapeStyle = Palette99::pick(i).stroke_width(2);
+        let mut runs = blocks.split(|(_, depth)| depth.is_none()).filter(|run| !run.is_empty());
+        let Some(run) = runs.next() else {
+            continue;
+        };
+        let line = |run: &[(u64, Option<f64>)]| {
+            // Dotted, so a run of one block still shows.
+            LineSeries::new(run.iter().filter_map(|(block, depth)| Some((*block, (*depth)?))), style).point_size(3)
+        };
+        plot.draw_series(line(run))?
+            .label(format!("{}%", target * 100.0))
+            .legend(move |(x, y)| PathElement::new([(x, y), (x + 20, y)], style));
+        for run in runs {
+            plot.draw_series(line(run))?;
+        }
+    }
+    plot.configure_series_labels().background_style(WHITE.mix(0.8)).border_style(BLACK).draw()
+}
@@ -0,0 +1,122 @@
+use std::path::Path;
+use rusqlite::{params, Connection, OpenFlags};
+use serde::Serialize;
+
+/// Bucket width when a history request doesn't say, in seconds.
+pub const DEFAULT_HISTORY_RESOLUTION_SECS: u64 = 3_600;
+
+/// One bucket of a pair's aggregate sell depth at one target.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryPoint {
+    /// Where the bucket starts, in Unix seconds.
+    pub start: u64,
+    pub first_block: u64,
+    pub last_block: u64,
+    /// Blocks measured in the bucket, failed searches included.
+    pub blocks: u64,
+    /// Mean depth in whole base tokens over the blocks that found one; None if none did.
+    pub base_tokens: Option<f64>,
+    pub min_base_tokens: Option<f64>,
+    pub max_base_tokens: Option<f64>,
+    /// Mean dollar value of the depth, with `--usd`.
+    pub usd_value: Option<f64>,
+}
+
+/// A pair's downsampled history at one slippage target, oldest bucket first.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TargetHistory {
+    pub target_slippage: f64,
+    pub points: Vec<HistoryPoint>,
+}
+
+/// A pair's aggregate depth between two times, as `/depth/history` answers it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PairHistory {
+    /// e.g. "WETH/USDC"
+    pub pair: String,
+    pub from: u64,
+    pub to: u64,
+    pub resolution_secs: u64,
+    pub targets: Vec<TargetHistory>,
+}
+
+/// A function to parse a bucket width such as "30s", "15m", "1h" or "1d".
+///
+/// Returns:
+/// - The width in seconds, or why it isn't one
+pub fn parse_resolution(text: &str) -> Result<u64, String> {
+    let text: &str = text.trim();
+    let split: usize = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (count, unit) = text.split_at(split);
+    let unit_secs: u64 = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3_600,
+        "d" => 86_400,
+        _ => return Err(format!("resolution \"{}\" should be a number and s, m, h or d, e.g. 1h", text)),
+    };
+    match count.parse::<u64>() {
+        Ok(count) if count > 0 => count.checked_mul(unit_secs).ok_or_else(|| format!("resolution \"{}\" is too long", text)),
+        _ => Err(format!("resolution \"{}\" should be a positive number and s, m, h or d, e.g. 1h", text)),
+    }
+}
+
+/// A function to read a pair's aggregate sell depth from a `--sqlite` history, averaged into
+/// buckets of `resolution_secs` by each block's timestamp.
+///
+/// Blocks written before the database recorded block times have none and are left out.
+///
+/// Args:
+/// - path: The database, opened read-only
+/// - pair: The pair's label as the run wrote it, e.g. "WETH/USDC", in any case
+/// - from: The earliest block time to include, in Unix seconds
+/// - to: The latest block time to include, in Unix seconds
+/// - resolution_secs: Bucket width in seconds; buckets start at multiples of it
+///
+/// Returns:
+/// - One series per slippage target, with only the buckets that have blocks
+pub fn read_depth_history(
+    path: &Path,
+    pair: &str,
+    from: u64,
+    to: u64,
+    resolution_secs: u64,
+) -> rusqlite::Result<PairHistory> {
+    let connection: Connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let resolution: i64 = resolution_secs.clamp(1, i64::MAX as u64) as i64;
+    let mut statement = connection.prepare(
+        "SELECT a.target_slippage, (b.measured_at / ?4) * ?4 AS start, MIN(a.block_number), MAX(a.block_number),
+             COUNT(*), AVG(CAST(a.base_tokens AS REAL)), MIN(CAST(a.base_tokens AS REAL)),
+             MAX(CAST(a.base_tokens AS REAL)), AVG(a.usd_value)
+         FROM aggregates a JOIN blocks b ON b.block_number = a.block_number
+         WHERE a.pair = ?1 COLLATE NOCASE AND a.action = 'sell' AND b.measured_at BETWEEN ?2 AND ?3
+         GROUP BY a.target_slippage, start
+         ORDER BY a.target_slippage, start",
+    )?;
+    let mut history: PairHistory =
+        PairHistory { pair: pair.to_string(), from, to, resolution_secs, targets: Vec::new() };
+    let mut rows = statement.query(params![
+        pair,
+        from.min(i64::MAX as u64) as i64,
+        to.min(i64::MAX as u64) as i64,
+        resolution
+    ])?;
+    while let Some(row) = rows.next()? {
+        let target_slippage: f64 = row.get(0)?;
+        let point: HistoryPoint = HistoryPoint {
+            start: row.get::<_, i64>(1)? as u64,
+            first_block: row.get::<_, i64>(2)? as u64,
+            last_block: row.get::<_, i64>(3)? as u64,
+            blocks: row.get::<_, i64>(4)? as u64,
+            base_tokens: row.get(5)?,
+            min_base_tokens: row.get(6)?,
+            max_base_tokens: row.get(7)?,
+            usd_value: row.get(8)?,
+        };
+        match history.targets.last_mut() {
+            Some(target) if target.target_slippage == target_slippage => target.points.push(point),
+            _ => history.targets.push(TargetHistory { target_slippage, points: vec![point] }),
+        }
+    }
+    Ok(history)
+}
@@ -0,0 +1,464 @@
+use std::{fmt::Write, path::Path};
+use rusqlite::{params, Connection, OpenFlags};
+use crate::report::{BlockReport, TradeAction};
+
+/// Series colours, one per slippage target, repeated if a pair has more targets.
+const COLORS: [&str; 6] = ["#1f6fb4", "#d1495b", "#2a9d5c", "#e07a1f", "#7b4fb0", "#7a5c45"];
+/// The depth chart's size in SVG units; it scales to the page width.
+const CHART_WIDTH: f64 = 720.0;
+const CHART_HEIGHT: f64 = 260.0;
+
+const STYLE: &str = "
+body { font: 15px/1.5 system-ui, sans-serif; color: #222; max-width: 980px; margin: 2em auto; padding: 0 1em; }
+h1 { margin-bottom: 0; }
+.meta { color: #666; margin-top: 0.2em; }
+section { margin: 2.5em 0; }
+table { border-collapse: collapse; width: 100%; margin: 0.8em 0; }
+th, td { text-align: right; padding: 0.3em 0.6em; border-bottom: 1px solid #e4e4e4; }
+th:first-child, td:first-child { text-align: left; }
+th { background: #f5f5f5; font-weight: 600; }
+td.note { text-align: left; color: #a33; }
+code { font-size: 0.9em; }
+figure { margin: 1em 0; }
+figcaption { color: #666; font-size: 0.9em; }
+svg { width: 100%; height: auto; }
+";
+
+/// One block's aggregate depth at a target: whole base tokens sold and their value in dollars,
+/// None where the search failed or there was no dollar price.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct BlockDepth {
+    block_number: u64,
+    base_tokens: Option<f64>,
+    usd_value: Option<f64>,
+}
+
+/// A pool as of the pair's latest block: its own depth per target, or why it was left out.
+#[derive(Debug, Clone, PartialEq)]
+struct PoolLine {
+    pool_id: String,
+    protocol: String,
+    spot: Option<f64>,
+    depths: Vec<(f64, Option<f64>)>,
+    excluded: Option<String>,
+}
+
+/// Everything the report says about one pair.
+#[derive(Debug, Clone, PartialEq)]
+struct PairSection {
+    pair: String,
+    /// Aggregate depth per target, tightest target first, oldest block first.
+    targets: Vec<(f64, Vec<BlockDepth>)>,
+    /// The last block the pair's pools were measured at, and those pools.
+    latest_block: Option<u64>,
+    pools: Vec<PoolLine>,
+}
+
+impl PairSection {
+    fn base(&self) -> &str {
+        self.pair.split('/').next().unwrap_or(&self.pair)
+    }
+
+    fn target(&mut self, target_slippage: f64) -> &mut Vec<BlockDepth> {
+        let i: usize = match self.targets.iter().position(|(t, _)| (t - target_slippage).abs() < 1e-12) {
+            Some(i) => i,
+            None => {
+                self.targets.push((target_slippage, Vec::new()));
+                self.targets.sort_by(|a, b| a.0.total_cmp(&b.0));
+                self.targets.iter().position(|(t, _)| (t - target_slippage).abs() < 1e-12).expect("just pushed")
+            }
+        };
+        &mut self.targets[i].1
+    }
+}
+
+/// A standalone HTML page summarising a run or a `--sqlite` history per pair, for sharing with
+/// people who won't read JSON: how much can be sold at each slippage target now and over the
+/// blocks covered, a chart of it, and the pools behind it. Styles and charts are inline, so the
+/// file can be mailed or attached as is.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HtmlReport {
+    title: String,
+    chain: Option<String>,
+    pairs: Vec<PairSection>,
+}
+
+impl HtmlReport {
+    pub fn new(title: &str) -> Self {
+        Self { title: title.to_string(), ..Default::default() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// A function to take in a measured block. Only sell-side depth is reported; pairs keep the
+    /// order they were first seen in.
+    pub fn observe(&mut self, report: &BlockReport) {
+        if self.chain.is_none() && !report.chain.is_empty() {
+            self.chain = Some(report.chain.clone());
+        }
+        for aggregate in report.aggregates.iter().filter(|aggregate| aggregate.action == TradeAction::Sell) {
+            let base_tokens: Option<f64> =
+                aggregate.conversions.base_tokens.as_ref().and_then(|tokens| tokens.parse::<f64>().ok());
+            self.section(&aggregate.pair).target(aggregate.target_slippage).push(BlockDepth {
+                block_number: report.block_number,
+                base_tokens,
+                usd_value: aggregate.usd_value,
+            });
+        }
+        let mut cleared: Vec<String> = Vec::new();
+        for pool in report.pools.iter() {
+            let section: &mut PairSection = self.section(&pool.pair);
+            if !cleared.contains(&pool.pair) {
+                section.pools.clear();
+                section.latest_block = Some(report.block_number);
+                cleared.push(pool.pair.clone());
+            }
+            section.pools.push(PoolLine {
+                pool_id: pool.pool_id.clone(),
+                protocol: pool.protocol.clone(),
+                spot: pool.spot_price,
+                depths: pool
+                    .depths
+                    .iter()
+                    .filter(|depth| depth.action == TradeAction::Sell)
+                    .map(|depth| {
+                        (depth.target_slippage, depth.conversions.base_tokens.as_ref().and_then(|t| t.parse().ok()))
+                    })
+                    .collect(),
+                excluded: pool.excluded.clone(),
+            });
+        }
+    }
+
+    /// A function to build the report from a history `--sqlite` wrote. Rows written before the
+    /// database had whole-token columns show as unknown.
+    ///
+    /// Args:
+    /// - path: The database, opened read-only
+    /// - title: The page's heading
+    pub fn from_sqlite(path: &Path, title: &str) -> rusqlite::Result<Self> {
+        let connection: Connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let mut report: HtmlReport = HtmlReport::new(title);
+        let mut aggregates = connection.prepare(
+            "SELECT pair, target_slippage, block_number, base_tokens, usd_value FROM aggregates
+             WHERE action = 'sell' ORDER BY pair, block_number",
+        )?;
+        let mut rows = aggregates.query([])?;
+        while let Some(row) = rows.next()? {
+            let base_tokens: Option<String> = row.get(3)?;
+            report.section(&row.get::<_, String>(0)?).target(row.get(1)?).push(BlockDepth {
+                block_number: row.get::<_, i64>(2)? as u64,
+                base_tokens: base_tokens.and_then(|tokens| tokens.parse().ok()),
+                usd_value: row.get(4)?,
+            });
+        }
+
+        let mut latest = connection.prepare("SELECT pair, MAX(block_number) FROM depths GROUP BY pair ORDER BY pair")?;
+        let latest: Vec<(String, i64)> =
+            latest.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<rusqlite::Result<_>>()?;
+        let mut depths = connection.prepare(
+            "SELECT d.pool_id, p.protocol, d.spot_price, d.target_slippage, d.base_tokens
+             FROM depths d JOIN pools p ON p.pool_id = d.pool_id
+             WHERE d.pair = ?1 AND d.block_number = ?2 AND d.action = 'sell'
+             ORDER BY d.pool_id, d.target_slippage",
+        )?;
+        let mut exclusions = connection.prepare(
+            "SELECT e.pool_id, p.protocol, e.reason FROM exclusions e JOIN pools p ON p.pool_id = e.pool_id
+             WHERE e.pair = ?1 AND e.block_number = ?2 ORDER BY e.pool_id",
+        )?;
+        for (pair, block_number) in latest {
+            let section: &mut PairSection = report.section(&pair);
+            section.latest_block = Some(block_number as u64);
+            let mut rows = depths.query(params![pair, block_number])?;
+            while let Some(row) = rows.next()? {
+                let pool_id: String = row.get(0)?;
+                let base_tokens: Option<String> = row.get(4)?;
+                let depth: (f64, Option<f64>) = (row.get(3)?, base_tokens.and_then(|tokens| tokens.parse().ok()));
+                match section.pools.iter_mut().find(|pool| pool.pool_id == pool_id) {
+                    Some(pool) => pool.depths.push(depth),
+                    None => section.pools.push(PoolLine {
+                        pool_id,
+                        protocol: row.get(1)?,
+                        spot: row.get(2)?,
+                        depths: vec![depth],
+                        excluded: None,
+                    }),
+                }
+            }
+            let mut rows = exclusions.query(params![pair, block_number])?;
+            while let Some(row) = rows.next()? {
+                section.pools.push(PoolLine {
+                    pool_id: row.get(0)?,
+                    protocol: row.get(1)?,
+                    spot: None,
+                    depths: Vec::new(),
+                    excluded: Some(row.get(2)?),
+                });
+            }
+        }
+        Ok(report)
+    }
+
+    /// A function to write out the whole page.
+    pub fn render(&self) -> String {
+        let mut html: String = String::new();
+        let blocks = || self.pairs.iter().flat_map(|section| section.targets.iter()).flat_map(|(_, d)| d.iter());
+        let first: Option<u64> = blocks().map(|depth| depth.block_number).min();
+        let last: Option<u64> = blocks().map(|depth| depth.block_number).max();
+        let mut meta: Vec<String> = Vec::new();
+        if let Some(chain) = &self.chain {
+            meta.push(escape(chain));
+        }
+        match (first, last) {
+            (Some(first), Some(last)) if first < last => meta.push(format!("blocks {} to {}", first, last)),
+            (Some(first), _) => meta.push(format!("block {}", first)),
+            _ => meta.push("no blocks measured".to_string()),
+        }
+        meta.push(match self.pairs.len() {
+            1 => "1 pair".to_string(),
+            pairs => format!("{} pairs", pairs),
+        });
+
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+             <style>{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n<p class=\"meta\">{meta}</p>\n\
+             <p>Depth is how much of a token can be sold into the pools before the price it fetches falls by \
+             the given slippage. Higher is better: larger trades can go through without moving the price.</p>\n",
+            title = escape(&self.title),
+            meta = meta.join(" · "),
+        );
+        for section in self.pairs.iter() {
+            section.render(&mut html);
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+
+    fn section(&mut self, pair: &str) -> &mut PairSection {
+        let i: usize = match self.pairs.iter().position(|section| section.pair == pair) {
+            Some(i) => i,
+            None => {
+                self.pairs.push(PairSection {
+                    pair: pair.to_string(),
+                    targets: Vec::new(),
+                    latest_block: None,
+                    pools: Vec::new(),
+                });
+                self.pairs.len() - 1
+            }
+        };
+        &mut self.pairs[i]
+    }
+}
+
+impl PairSection {
+    fn render(&self, html: &mut String) {
+        let base: String = escape(self.base());
+        let _ = writeln!(html, "<section>\n<h2>{}</h2>", escape(&self.pair));
+        if self.targets.is_empty() {
+            html.push_str("<p>No aggregate depth was measured for this pair.</p>\n");
+        } else {
+            let _ = writeln!(
+                html,
+                "<table>\n<thead><tr><th>Price moves by</th><th>{base} sellable now</th><th>Worth</th>\
+                 <th>Lowest</th><th>Median</th><th>Highest</th><th>Blocks</th></tr></thead>\n<tbody>",
+            );
+            for (target, depths) in self.targets.iter() {
+                let latest: Option<&BlockDepth> = depths.last();
+                let mut measured: Vec<f64> = depths.iter().filter_map(|depth| depth.base_tokens).collect();
+                measured.sort_by(|a, b| a.total_cmp(b));
+                let failed: usize = depths.len() - measured.len();
+                let blocks: String = match failed {
+                    0 => depths.len().to_string(),
+                    failed => format!("{} ({} failed)", depths.len(), failed),
+                };
+                let _ = writeln!(
+                    html,
+                    "<tr><td>{}%</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    target * 100.0,
+                    tokens(latest.and_then(|depth| depth.base_tokens)),
+                    dollars(latest.and_then(|depth| depth.usd_value)),
+                    tokens(measured.first().copied()),
+                    tokens(median(&measured)),
+                    tokens(measured.last().copied()),
+                    blocks,
+                );
+            }
+            html.push_str("</tbody>\n</table>\n");
+            let _ = writeln!(
+                html,
+                "<figure>\n{}\n<figcaption>{} sellable within each slippage, block by block.</figcaption>\n</figure>",
+                self.chart(),
+                base
+            );
+        }
+
+        if let Some(block_number) = self.latest_block {
+            let targets: Vec<f64> = self.targets.iter().map(|(target, _)| *target).collect();
+            let _ = write!(
+                html,
+                "<h3>Pools at block {}</h3>\n<table>\n<thead><tr><th>Pool</th><th>Protocol</th><th>Spot price</th>",
+                block_number
+            );
+            for target in targets.iter() {
+                let _ = write!(html, "<th>{}%</th>", target * 100.0);
+            }
+            html.push_str("<th></th></tr></thead>\n<tbody>\n");
+            for pool in self.pools.iter() {
+                let _ = write!(
+                    html,
+                    "<tr><td><code>{}</code></td><td>{}</td><td>{}</td>",
+                    escape(&pool.pool_id),
+                    escape(&pool.protocol),
+                    pool.spot.map_or("-".to_string(), |spot| format!("{:.6}", spot)),
+                );
+                for target in targets.iter() {
+                    let depth: Option<f64> = pool
+                        .depths
+                        .iter()
+                        .find(|(t, _)| (t - target).abs() < 1e-12)
+                        .and_then(|(_, depth)| *depth);
+                    let cell: String = if pool.excluded.is_some() { String::new() } else { tokens(depth) };
+                    let _ = write!(html, "<td>{}</td>", cell);
+                }
+                let _ = writeln!(
+                    html,
+                    "<td class=\"note\">{}</td></tr>",
+                    pool.excluded.as_deref().map(escape).unwrap_or_default()
+                );
+            }
+            html.push_str("</tbody>\n</table>\n");
+        }
+        html.push_str("</section>\n");
+    }
+
+    /// A function to draw aggregate depth over blocks as an inline SVG line chart, a line per
+    /// target. Failed blocks break the line; every block gets a dot, so a lone block still shows.
+    fn chart(&self) -> String {
+        let (left, right, top, bottom): (f64, f64, f64, f64) = (72.0, 16.0, 12.0, 36.0);
+        let depths = || self.targets.iter().flat_map(|(_, depths)| depths.iter());
+        let first: u64 = depths().map(|depth| depth.block_number).min().unwrap_or_default();
+        let last: u64 = depths().map(|depth| depth.block_number).max().unwrap_or_default().max(first + 1);
+        let deepest: f64 = depths().filter_map(|depth| depth.base_tokens).fold(0.0, f64::max).max(1e-9) * 1.05;
+        let x = |block: u64| left + (block - first) as f64 / (last - first) as f64 * (CHART_WIDTH - left - right);
+        let y = |tokens: f64| top + (1.0 - tokens / deepest) * (CHART_HEIGHT - top - bottom);
+
+        let mut svg: String = format!(
+            "<svg viewBox=\"0 0 {w} {h}\" xmlns=\"http://www.w3.org/2000/svg\" font-size=\"11\" font-family=\"sans-serif\">",
+            w = CHART_WIDTH,
+            h = CHART_HEIGHT
+        );
+        for step in 0..=4 {
+            let tokens: f64 = deepest * step as f64 / 4.0;
+            let _ = write!(
+                svg,
+                "<line x1=\"{left}\" x2=\"{x2}\" y1=\"{y:.1}\" y2=\"{y:.1}\" stroke=\"#e4e4e4\"/>\
+                 <text x=\"{tx}\" y=\"{ty:.1}\" text-anchor=\"end\">{label}</text>",
+                x2 = CHART_WIDTH - right,
+                y = y(tokens),
+                tx = left - 6.0,
+                ty = y(tokens) + 4.0,
+                label = tokens_short(tokens),
+            );
+        }
+        let _ = write!(
+            svg,
+            "<text x=\"{left}\" y=\"{ly}\">{first}</text><text x=\"{rx}\" y=\"{ly}\" text-anchor=\"end\">{last}</text>\
+             <text x=\"{mx}\" y=\"{ly}\" text-anchor=\"middle\">block</text>",
+            ly = CHART_HEIGHT - 8.0,
+            rx = CHART_WIDTH - right,
+            mx = (left + CHART_WIDTH - right) / 2.0,
+        );
+        for (i, (target, depths)) in self.targets.iter().enumerate() {
+            let color: &str = COLORS[i % COLORS.len()];
+            for run in depths.split(|depth| depth.base_tokens.is_none()).filter(|run| run.len() > 1) {
+                let points: Vec<String> = run
+                    .iter()
+                    .filter_map(|depth| Some(format!("{:.1},{:.1}", x(depth.block_number), y(depth.base_tokens?))))
+                    .collect();
+                let _ = write!(
+                    svg,
+                    "<polyline fill=\"none\" stroke=\"{}\" stroke-width=\"2\" points=\"{}\"/>",
+                    color,
+                    points.join(" ")
+                );
+            }
+            for depth in depths.iter() {
+                if let Some(tokens) = depth.base_tokens {
+                    let _ = write!(
+                        svg,
+                        "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"2.5\" fill=\"{}\"/>",
+                        x(depth.block_number),
+                        y(tokens),
+                        color
+                    );
+                }
+            }
+            let legend_y: f64 = top + 14.0 * (i + 1) as f64;
+            let _ = write!(
+                svg,
+                "<rect x=\"{lx}\" y=\"{ry:.1}\" width=\"14\" height=\"3\" fill=\"{color}\"/>\
+                 <text x=\"{tx}\" y=\"{legend_y:.1}\">{target}%</text>",
+                lx = CHART_WIDTH - right - 64.0,
+                ry = legend_y - 4.0,
+                tx = CHART_WIDTH - right - 44.0,
+                target = target * 100.0,
+            );
+        }
+        svg.push_str("</svg>");
+        svg
+    }
+}
+
+fn median(sorted: &[f64]) -> Option<f64> {
+    match sorted.len() {
+        0 => None,
+        n if n % 2 == 1 => Some(sorted[n / 2]),
+        n => Some((sorted[n / 2 - 1] + sorted[n / 2]) / 2.0),
+    }
+}
+
+/// A function to write a token amount for people, e.g. 1,204.5 or 0.0312; "-" when unknown.
+fn tokens(amount: Option<f64>) -> String {
+    match amount {
+        Some(amount) if amount >= 1_000.0 => thousands(amount.round()),
+        Some(amount) if amount >= 1.0 => format!("{:.2}", amount).trim_end_matches('0').trim_end_matches('.').to_string(),
+        Some(amount) => format!("{:.4}", amount).trim_end_matches('0').trim_end_matches('.').to_string(),
+        None => "-".to_string(),
+    }
+}
+
+/// A function to label a chart axis briefly, e.g. 12.5k.
+fn tokens_short(amount: f64) -> String {
+    let (value, suffix): (f64, &str) = match amount {
+        a if a >= 1e6 => (a / 1e6, "M"),
+        a if a >= 1e3 => (a / 1e3, "k"),
+        a => (a, ""),
+    };
+    let digits: String = format!("{:.2}", value);
+    format!("{}{}", digits.trim_end_matches('0').trim_end_matches('.'), suffix)
+}
+
+fn dollars(usd: Option<f64>) -> String {
+    usd.map_or("-".to_string(), |usd| format!("${}", thousands(usd.round())))
+}
+
+/// A function to group a whole number's digits in threes, e.g. 1,242,180.
+fn thousands(value: f64) -> String {
+    let digits: String = format!("{:.0}", value.abs());
+    let mut grouped: String = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    if value < 0.0 { format!("-{}", grouped) } else { grouped }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,178 @@
+use serde_json::{json, Map, Value};
+use crate::{hub::BlockView, rpc::RpcHandler};
+
+/// Where `serve` listens unless `--listen` says otherwise; loopback only, since there's no auth.
+pub const DEFAULT_LISTEN_ADDR: &str = "127.0.0.1:8080";
+
+/// The most `serve` reads of a request before giving up on it; a GET needs far less.
+pub const MAX_REQUEST_BYTES: usize = 16 * 1024;
+
+/// How long `serve` waits for a request's headers before answering 408, so a client that connects
+/// and goes quiet doesn't hold a connection open.
+pub const HEADER_TIMEOUT_SECS: u64 = 10;
+
+/// One HTTP response: a status and a JSON body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl HttpResponse {
+    pub fn error(status: u16, message: impl Into<String>) -> Self {
+        Self { status, body: json!({ "error": { "message": message.into() } }) }
+    }
+
+    /// A function to write the response out as HTTP/1.1, closing the connection after it.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let body: String = self.body.to_string();
+        format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            reason(self.status),
+            body.len(),
+            body
+        )
+        .into_bytes()
+    }
+}
+
+/// A function to answer one HTTP request from the latest block view.
+///
+/// Endpoints, all GET, with the pair written `TOKEN_IN-TOKEN_OUT` (e.g. `WETH-USDC`):
+/// - `/depth?pair=…&slippage=0.02`: depth per pool and split across pools, as the `depth` method;
+///   `slippage` takes a comma-separated list or may be repeated
+/// - `/quote?pair=…&amount=100`: what selling `amount` whole tokens returns, as `quote`
+/// - `/curve?pair=…&samples=20`: each pool's price-impact curve, as `curve`
+/// - `/pools?pair=…`: the pools trading the pair with their spot prices and fees, as `pools`
+/// - `/depth/history?pair=…&from=…&to=…&resolution=1h`: the pair's aggregate sell depth from the
+///   `--sqlite` history, bucketed by write time (Unix seconds), as `history`
+///
+/// Args:
+/// - handler: Answers the call once the request is translated into one
+/// - head: The request line and headers, e.g. "GET /depth?pair=WETH-USDC HTTP/1.1\r\n…"
+/// - view: The latest block view; held for the duration of the call
+///
+/// Returns:
+/// - The response: 200 with the method's result, 400 for a bad query, 404/405 for anything but
+///   the endpoints above, or 503 until the stream's first block arrives (history excepted, since it
+///   reads the database rather than the view)
+pub fn route(handler: &RpcHandler, head: &str, view: &BlockView) -> HttpResponse {
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else {
+        return HttpResponse::error(400, "malformed request line");
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let endpoint: &str = match path {
+        "/depth" | "/quote" | "/curve" | "/pools" => &path[1..],
+        "/depth/history" => "history",
+        _ => {
+            return HttpResponse::error(
+                404,
+                format!("no endpoint at {}, expected /depth, /quote, /curve, /pools or /depth/history", path),
+            )
+        }
+    };
+    if method != "GET" {
+        return HttpResponse::error(405, format!("{} takes GET, not {}", path, method));
+    }
+    let params: Value = match query_params(endpoint, path, query) {
+        Ok(params) => params,
+        Err(message) => return HttpResponse::error(400, message),
+    };
+    if view.block_number.is_none() && endpoint != "history" {
+        return HttpResponse::error(503, "waiting for the stream's first block");
+    }
+    match handler.call(endpoint, params, view) {
+        Ok(body) => HttpResponse { status: 200, body },
+        Err(message) => HttpResponse::error(400, message),
+    }
+}
+
+/// A function to translate an endpoint's query string into its method's params.
+fn query_params(endpoint: &str, path: &str, query: &str) -> Result<Value, String> {
+    let mut params: Map<String, Value> = Map::new();
+    let mut slippages: Vec<Value> = Vec::new();
+    for (key, value) in query.split('&').filter(|p| !p.is_empty()).map(|p| p.split_once('=').unwrap_or((p, ""))) {
+        let value: String = percent_decode(value)?;
+        match (endpoint, key) {
+            (_, "pair") => {
+                let Some((token_in, token_out)) = value.split_once('-') else {
+                    return Err(format!("pair \"{}\" should be TOKEN_IN-TOKEN_OUT, e.g. WETH-USDC", value));
+                };
+                params.insert("token_in".to_string(), json!(token_in));
+                params.insert("token_out".to_string(), json!(token_out));
+            }
+            ("depth", "slippage") => {
+                for target in value.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+                    let target: f64 = target.parse().map_err(|_| format!("slippage \"{}\" is not a number", target))?;
+                    slippages.push(json!(target));
+                }
+            }
+            ("quote", "amount") => {
+                params.insert("amount".to_string(), json!(value));
+            }
+            ("history", "from" | "to") => {
+                let time: u64 = value.parse().map_err(|_| format!("{} \"{}\" is not a Unix time in seconds", key, value))?;
+                params.insert(key.to_string(), json!(time));
+            }
+            ("history", "resolution") => {
+                params.insert("resolution".to_string(), json!(value));
+            }
+            ("curve", "samples") => {
+                let samples: usize = value.parse().map_err(|_| format!("samples \"{}\" is not a whole number", value))?;
+                params.insert("samples".to_string(), json!(samples));
+            }
+            (_, other) => return Err(format!("{} doesn't take \"{}\"", path, other)),
+        }
+    }
+    if !params.contains_key("token_in") {
+        return Err(format!("{} needs a pair, e.g. ?pair=WETH-USDC", path));
+    }
+    if !slippages.is_empty() {
+        params.insert("slippages".to_string(), Value::Array(slippages));
+    }
+    Ok(Value::Object(params))
+}
+
+/// A function to undo `%XX` escapes and `+` for spaces in one query value.
+fn percent_decode(value: &str) -> Result<String, String> {
+    let bytes: &[u8] = value.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i: usize = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let byte: u8 = value
+                    .get(i + 1..i + 3)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| format!("\"{}\" has a malformed % escape", value))?;
+                decoded.push(byte);
+                i += 3;
+            }
+            b'+' => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| format!("\"{}\" doesn't decode to UTF-8", value))
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
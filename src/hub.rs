@@ -0,0 +1,252 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
+use tycho_simulation::{
+    models::Token,
+    protocol::{models::BlockUpdate, state::ProtocolSim},
+};
+
+/// The latest state of every tracked pool, as of one block.
+#[derive(Debug, Clone, Default)]
+pub struct BlockView {
+    /// None until the first block arrives.
+    pub block_number: Option<u64>,
+    /// Tokens by pool id.
+    pub pools: HashMap<String, Vec<Token>>,
+    /// Protocol system by pool id, e.g. "uniswap_v3".
+    pub protocols: HashMap<String, String>,
+    /// Shared rather than cloned, so a query can hold on to a state while the next block lands.
+    pub states: HashMap<String, Arc<dyn ProtocolSim>>,
+}
+
+impl BlockView {
+    /// A function to apply one block's changes.
+    pub fn apply(&mut self, update: BlockUpdate) {
+        for (id, component) in update.new_pairs.into_iter() {
+            self.pools.insert(id.clone(), component.tokens);
+            self.protocols.insert(id, component.protocol_system);
+        }
+        for id in update.removed_pairs.keys() {
+            self.pools.remove(id);
+            self.protocols.remove(id);
+            self.states.remove(id);
+        }
+        for (id, state) in update.states.into_iter() {
+            self.states.insert(id, Arc::from(state));
+        }
+        self.block_number = Some(update.block_number);
+    }
+
+    /// A function to list the pools trading exactly these tokens, with their states.
+    ///
+    /// Args:
+    /// - tokens: Both tokens sorted by address, as pools list them
+    pub fn pools_for(&self, tokens: &[Token]) -> Vec<(String, Arc<dyn ProtocolSim>)> {
+        self.pools
+            .iter()
+            .filter(|(_, pool_tokens)| pool_tokens.as_slice() == tokens)
+            .filter_map(|(id, _)| self.states.get(id).map(|state| (id.clone(), state.clone())))
+            .collect()
+    }
+}
+
+/// Opens the one stream the hub reads from, e.g. a supervised Tycho `ProtocolStream`.
+pub type Connect = Box<dyn Fn() -> mpsc::Receiver<BlockUpdate> + Send + Sync>;
+
+struct HubInner {
+    connect: Connect,
+    /// The latest block view. Each block publishes a new one, so a query keeps the view it started
+    /// with and the stream never waits on a reader.
+    views: watch::Sender<Arc<BlockView>>,
+    /// The task feeding `views`, while anyone is subscribed.
+    feed: Mutex<Option<JoinHandle<()>>>,
+    subscribers: Mutex<usize>,
+}
+
+/// One live stream and block view shared by every query, so a REPL or server answering many
+/// ad-hoc requests never opens a stream per request.
+///
+/// The stream is opened on the first subscription and closed when the last one is dropped. Cheap
+/// to clone; every clone is the same hub.
+#[derive(Clone)]
+pub struct StateHub {
+    inner: Arc<HubInner>,
+}
+
+impl StateHub {
+    /// Args:
+    /// - connect: Opens the stream; called again whenever the hub goes from zero subscribers to one
+    pub fn new(connect: Connect) -> Self {
+        let (views, _) = watch::channel(Arc::new(BlockView::default()));
+        Self {
+            inner: Arc::new(HubInner {
+                connect,
+                views,
+                feed: Mutex::new(None),
+                subscribers: Mutex::new(0),
+            }),
+        }
+    }
+
+    /// A function to start using the shared stream, opening it if nobody else is.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub fn subscribe(&self) -> Subscription {
+        let mut subscribers = self.inner.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        *subscribers += 1;
+        if *subscribers == 1 {
+            // A fresh stream starts with a full snapshot, so nothing from the last one carries over.
+            self.inner.views.send_replace(Arc::new(BlockView::default()));
+            let mut updates: mpsc::Receiver<BlockUpdate> = (self.inner.connect)();
+            let inner: Arc<HubInner> = self.inner.clone();
+            let feed: JoinHandle<()> = tokio::spawn(async move {
+                // The next view is built on a copy, sharing the states, while readers keep theirs.
+                let mut view: BlockView = BlockView::default();
+                while let Some(update) = updates.recv().await {
+                    view.apply(update);
+                    inner.views.send_replace(Arc::new(view.clone()));
+                }
+            });
+            *self.inner.feed.lock().unwrap_or_else(|e| e.into_inner()) = Some(feed);
+        }
+        Subscription {
+            hub: self.clone(),
+            views: self.inner.views.subscribe(),
+        }
+    }
+
+    /// How many subscriptions are open.
+    pub fn subscribers(&self) -> usize {
+        *self.inner.subscribers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// True while the stream is open.
+    pub fn is_connected(&self) -> bool {
+        self.inner
+            .feed
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+            .is_some_and(|feed| !feed.is_finished())
+    }
+
+    fn unsubscribe(&self) {
+        let mut subscribers = self.inner.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        *subscribers -= 1;
+        if *subscribers == 0 {
+            if let Some(feed) = self.inner.feed.lock().unwrap_or_else(|e| e.into_inner()).take() {
+                feed.abort();
+            }
+        }
+    }
+}
+
+/// A handle on the hub's stream; it stays open while any subscription is alive.
+pub struct Subscription {
+    hub: StateHub,
+    views: watch::Receiver<Arc<BlockView>>,
+}
+
+impl Subscription {
+    /// A function to take the latest block view. It never changes, so it can be held for as long
+    /// as a query runs, on any thread; later blocks arrive as new views.
+    pub fn view(&self) -> Arc<BlockView> {
+        self.views.borrow().clone()
+    }
+
+    /// A function to wait for the next block.
+    ///
+    /// Returns:
+    /// - The new block number, or None if the hub is gone
+    pub async fn next_block(&mut self) -> Option<u64> {
+        self.views.changed().await.ok()?;
+        self.views.borrow_and_update().block_number
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.hub.unsubscribe();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(block_number: u64) -> BlockUpdate {
+        BlockUpdate {
+            block_number,
+            states: HashMap::new(),
+            new_pairs: HashMap::new(),
+            removed_pairs: HashMap::new(),
+        }
+    }
+
+    /// The sending end of the hub's current stream.
+    type Feed = Arc<Mutex<Option<mpsc::Sender<BlockUpdate>>>>;
+
+    /// A hub whose stream is fed by hand, with a count of how often it was opened.
+    fn hub() -> (StateHub, Feed, Arc<Mutex<usize>>) {
+        let feed: Feed = Arc::new(Mutex::new(None));
+        let opened: Arc<Mutex<usize>> = Arc::new(Mutex::new(0));
+        let (connect_feed, connect_opened) = (feed.clone(), opened.clone());
+        let hub: StateHub = StateHub::new(Box::new(move || {
+            let (tx, rx) = mpsc::channel(8);
+            *connect_feed.lock().unwrap() = Some(tx);
+            *connect_opened.lock().unwrap() += 1;
+            rx
+        }));
+        (hub, feed, opened)
+    }
+
+    fn sender(feed: &Feed) -> mpsc::Sender<BlockUpdate> {
+        feed.lock().unwrap().clone().expect("the stream is open")
+    }
+
+    #[tokio::test]
+    async fn keeps_a_taken_view_while_later_blocks_arrive() {
+        let (hub, feed, _) = hub();
+        let mut subscription: Subscription = hub.subscribe();
+        assert_eq!(subscription.view().block_number, None);
+
+        sender(&feed).send(block(10)).await.unwrap();
+        assert_eq!(subscription.next_block().await, Some(10));
+        let held: Arc<BlockView> = subscription.view();
+
+        sender(&feed).send(block(11)).await.unwrap();
+        assert_eq!(subscription.next_block().await, Some(11));
+        // The stream moved on without waiting for the held view to be dropped.
+        assert_eq!(held.block_number, Some(10));
+        assert_eq!(subscription.view().block_number, Some(11));
+    }
+
+    #[tokio::test]
+    async fn shares_one_stream_and_closes_it_with_the_last_subscription() {
+        let (hub, feed, opened) = hub();
+        let mut first: Subscription = hub.subscribe();
+        let mut second: Subscription = hub.clone().subscribe();
+        assert_eq!((hub.subscribers(), *opened.lock().unwrap()), (2, 1));
+
+        sender(&feed).send(block(5)).await.unwrap();
+        assert_eq!(first.next_block().await, Some(5));
+        assert_eq!(second.next_block().await, Some(5));
+
+        drop(first);
+        assert!(hub.is_connected());
+        drop(second);
+        assert_eq!(hub.subscribers(), 0);
+        tokio::task::yield_now().await;
+        assert!(!hub.is_connected());
+
+        // A new stream starts from a blank view, not the last stream's.
+        let reopened: Subscription = hub.subscribe();
+        assert_eq!(*opened.lock().unwrap(), 2);
+        assert_eq!(reopened.view().block_number, None);
+    }
+}
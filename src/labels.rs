@@ -0,0 +1,74 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+use serde::Serialize;
+
+/// One free-form tag for a run, written `key=value` as `--label` takes it, e.g.
+/// `experiment=fee-change-study`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub key: String,
+    pub value: String,
+}
+
+impl FromStr for Label {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((key, value)) = s.split_once('=') else {
+            return Err(format!("label \"{}\" should be KEY=VALUE, e.g. analyst=zw", s));
+        };
+        check_key(key)?;
+        Ok(Label { key: key.to_string(), value: value.to_string() })
+    }
+}
+
+/// A function to check a label key: non-empty, and without whitespace, `=` or `,`, so labels
+/// read back unambiguously from `Labels`' `key=value,key=value` form.
+pub fn check_key(key: &str) -> Result<(), String> {
+    if key.is_empty() {
+        return Err("a label needs a key before the =".to_string());
+    }
+    if key.contains(|c: char| c.is_whitespace() || c == '=' || c == ',') {
+        return Err(format!("label key \"{}\" can't contain whitespace, = or ,", key));
+    }
+    Ok(())
+}
+
+/// A run's labels, attached to every record and summary it writes so archived datasets from many
+/// runs can be told apart. Serialized as a JSON object, keys sorted.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct Labels(BTreeMap<String, String>);
+
+impl Labels {
+    /// A function to merge labels in order of precedence: a later label overrides an earlier one
+    /// with the same key, e.g. the command line's over the config's.
+    pub fn merge(labels: impl IntoIterator<Item = Label>) -> Self {
+        Self(labels.into_iter().map(|label| (label.key, label.value)).collect())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// A function to tag one result record with the labels, as a `labels` object.
+    ///
+    /// Returns:
+    /// - The record as JSON, unchanged if there are no labels or it isn't an object
+    pub fn attach(&self, record: impl Serialize) -> serde_json::Result<serde_json::Value> {
+        let mut record: serde_json::Value = serde_json::to_value(record)?;
+        if !self.is_empty() {
+            if let Some(object) = record.as_object_mut() {
+                object.insert("labels".to_string(), serde_json::to_value(self)?);
+            }
+        }
+        Ok(record)
+    }
+}
+
+/// `key=value,key=value`, e.g. for a CSV column; empty with no labels.
+impl fmt::Display for Labels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pairs: Vec<String> = self.0.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+        write!(f, "{}", pairs.join(","))
+    }
+}
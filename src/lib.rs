@@ -1 +1,71 @@
-// pub mod binary_search;
\ No newline at end of file
+//! Liquidity depth for Tycho pools: how much can be swapped before slippage reaches a target.
+//!
+//! `DepthCalculator` is the entry point for embedding the engine; the `binary_search` binary is a
+//! CLI over the same modules.
+pub mod aggregate;
+pub mod ahead;
+pub mod alert;
+pub mod amount;
+pub mod attribution;
+pub mod backfill;
+pub mod binary_search;
+pub mod bundle;
+pub mod calculator;
+pub mod chain;
+pub mod chart;
+pub mod checkpoint;
+pub mod columnar;
+pub mod compare;
+pub mod config;
+pub mod console;
+pub mod crosscheck;
+pub mod curve;
+pub mod dashboard;
+pub mod describe;
+pub mod display;
+pub mod divergence;
+pub mod endpoints;
+pub mod error;
+pub mod estimate;
+#[cfg(feature = "charts")]
+pub mod figures;
+pub mod history;
+pub mod html;
+pub mod http;
+pub mod hub;
+pub mod labels;
+pub mod measure;
+pub mod mock;
+pub mod native;
+pub mod notional;
+pub mod orderbook;
+pub mod oracle;
+pub mod parallel;
+pub mod preflight;
+pub mod probe;
+pub mod protocols;
+pub mod provider;
+pub mod ranges;
+pub mod recording;
+pub mod report;
+pub mod results;
+pub mod route;
+pub mod rpc;
+pub mod run;
+pub mod scenario;
+pub mod schedule;
+pub mod serve;
+pub mod sink;
+pub mod soak;
+pub mod stability;
+pub mod stream;
+pub mod supervisor;
+pub mod token_id;
+pub mod unwind;
+pub mod usd;
+pub mod webhook;
+
+pub use binary_search::{SearchResult, Slippage};
+pub use calculator::DepthCalculator;
+pub use error::DepthError;
+pub use provider::AmountOutProvider;
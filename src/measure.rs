@@ -0,0 +1,850 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use alloy_primitives::U256;
+use num_bigint::BigUint;
+use tycho_common::{models::Chain, Bytes};
+use tycho_simulation::{models::Token, protocol::state::ProtocolSim};
+use crate::{
+    amount::{format_amount, parse_amount},
+    attribution::pool_fee,
+    binary_search::{
+        PoolLimitPolicy, SearchResult, WarmStarts, DEFAULT_BRACKET_TVL_MULTIPLE, DEFAULT_MAX_ITERATIONS,
+        DEFAULT_PRECISION,
+    },
+    calculator::DepthCalculator,
+    chart::DEFAULT_CHART_SAMPLES,
+    console::ConsoleRenderer,
+    crosscheck::{compare_implementations, CrossCheck, DEFAULT_DISAGREEMENT_BPS},
+    curve::{default_curve_range, DepthCurve},
+    describe::{describe_depth, describe_fill, describe_two_sided},
+    display::DisplayNames,
+    error::{guard_simulation, DepthError},
+    estimate::DepthEstimate,
+    notional::Notional,
+    oracle::{OracleConfig, PriceOracle},
+    probe::{default_probe_amounts, probe_monotonicity, MonotonicityCheck, DEFAULT_TOLERANCE_BPS},
+    ranges::{is_concentrated_liquidity, liquidity_by_price_range, RangeReport, DEFAULT_PRICE_BANDS},
+    report::{
+        DepthReport, FillReport, Leg, PoolReport, PoolStatus, SimulationInputs, TradeAction, TwoSidedReport,
+    },
+    scenario::PriceShift,
+    token_id::TokenId,
+};
+
+/// (id, state) for pools of one pair.
+pub type PoolList<'a> = Vec<(String, &'a dyn ProtocolSim)>;
+
+/// How every pool of the run is measured, as resolved from the flags and the config.
+#[derive(Debug, Clone)]
+pub struct MeasureSettings {
+    pub precision: f64,
+    pub max_iterations: u32,
+    pub bracket_tvl_multiple: f64,
+    /// Whether pool-limited depth is reported or fails the pool.
+    pub pool_limits: PoolLimitPolicy,
+    pub adaptive_precision: bool,
+    /// Attach each depth's final `get_amount_out` call to its report.
+    pub sim_inputs: bool,
+    /// Flag native/VM outputs further apart than this, in basis points.
+    pub cross_check_bps: u32,
+    /// A hypothetical move applied to every reference price.
+    pub price_shift: Option<PriceShift>,
+    /// Solve for the token_in that returns exactly this much token_out, in whole tokens, instead
+    /// of searching for depth.
+    pub exact_output: Option<String>,
+    /// Estimate depth from two probes instead of a full search.
+    pub coarse: bool,
+    /// Also measure the reverse leg.
+    pub both_directions: bool,
+    /// Measure both legs against the sell leg's mid and report their sum.
+    pub two_sided: bool,
+    /// Log-spaced sizes each sampled curve has; 0 for the chart's default.
+    pub curve_samples: usize,
+    /// For concentrated-liquidity pools, also measure liquidity by price band.
+    pub price_ranges: bool,
+}
+
+impl Default for MeasureSettings {
+    fn default() -> Self {
+        Self {
+            precision: DEFAULT_PRECISION,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            bracket_tvl_multiple: DEFAULT_BRACKET_TVL_MULTIPLE,
+            pool_limits: PoolLimitPolicy::default(),
+            adaptive_precision: false,
+            sim_inputs: false,
+            cross_check_bps: DEFAULT_DISAGREEMENT_BPS,
+            price_shift: None,
+            exact_output: None,
+            coarse: false,
+            both_directions: false,
+            two_sided: false,
+            curve_samples: 0,
+            price_ranges: false,
+        }
+    }
+}
+
+/// One pool to measure this block.
+pub struct PoolJob<'a> {
+    /// Index into the tracked pairs.
+    pub pair: usize,
+    pub id: &'a String,
+    pub state: &'a dyn ProtocolSim,
+    /// The pool's VM twin under --cross-check, when both streams are at the same block.
+    pub vm_state: Option<&'a dyn ProtocolSim>,
+    /// Results from an earlier block to re-emit instead, if nothing they depend on changed.
+    pub replay: Option<PoolOutcome>,
+}
+
+/// What every pool of a block is measured with, shared read-only by the workers. The per-pair
+/// slices are in the order of `pairs`.
+pub struct BlockContext<'a> {
+    pub settings: &'a MeasureSettings,
+    pub names: &'a DisplayNames,
+    pub renderer: &'a (dyn ConsoleRenderer + Sync),
+    pub pairs: &'a [TrackedPair],
+    pub pool_protocols: &'a HashMap<String, String>,
+    /// Pool ids tagged as protocol-owned, lowercased.
+    pub protocol_owned: &'a HashSet<String>,
+    pub block_number: u64,
+    /// Where each pool converged last block; empty with --cold-start.
+    pub warm_starts: &'a WarmStarts,
+    pub notionals: &'a [Notional],
+    pub notional_amounts: &'a [Vec<Result<U256, String>>],
+    pub base_usd: &'a [Option<f64>],
+    pub marks: &'a [Option<f64>],
+    /// Sample each measured pool's curve into the bundle.
+    pub sample_curves: bool,
+    /// Sample each measured pool's curve for --chart or --charts-dir.
+    pub chart: bool,
+}
+
+/// Everything measuring one pool produced, gathered on a worker and emitted in pool order.
+#[derive(Clone)]
+pub struct PoolOutcome {
+    pub report: PoolReport,
+    /// Console rows, keyed for the throttle.
+    pub rows: Vec<(String, String)>,
+    /// Result records, in the order they're written.
+    pub records: Vec<serde_json::Value>,
+    /// Records only the bundle keeps (sampled curves).
+    pub bundle_records: Vec<serde_json::Value>,
+    /// The sampled curve, for --chart.
+    pub curve: Option<DepthCurve>,
+    /// The cross-check, when the VM twin disagreed.
+    pub disagreement: Option<CrossCheck>,
+    /// The probe evidence, when the pool was excluded as non-monotone.
+    pub unreliable: Option<MonotonicityCheck>,
+    /// Probed and priced, so it counts toward the aggregate and the bundle keeps its state.
+    pub reliable: bool,
+    /// Depth searches that converged, by token sold, for the next block's warm start.
+    pub converged: Vec<(Bytes, Vec<SearchResult>)>,
+}
+
+/// What a pool's results depend on besides its state. Results are only reused while these hold.
+#[derive(PartialEq)]
+pub struct PoolInputs {
+    pub mark: Option<f64>,
+    pub base_usd: Option<f64>,
+    pub notional_amounts: Vec<Result<U256, String>>,
+}
+
+/// A pool's last measured results, re-emitted while its state and inputs are unchanged.
+pub struct CachedPool {
+    /// The block they were measured at.
+    pub block_number: u64,
+    pub inputs: PoolInputs,
+    pub outcome: PoolOutcome,
+}
+
+/// A pool measured ahead of its block, used once the loop reaches that block if it tracks the
+/// same state with the same inputs.
+pub struct MeasuredAhead {
+    pub state: Arc<dyn ProtocolSim>,
+    pub inputs: PoolInputs,
+    pub outcome: PoolOutcome,
+}
+
+impl MeasuredAhead {
+    pub fn matches(&self, state: &Arc<dyn ProtocolSim>, inputs: &PoolInputs) -> bool {
+        std::ptr::addr_eq(Arc::as_ptr(&self.state), Arc::as_ptr(state)) && self.inputs == *inputs
+    }
+}
+
+impl PoolOutcome {
+    /// A function to start a pool's outcome from its report, with nothing measured yet.
+    pub fn new(report: PoolReport) -> Self {
+        Self {
+            report,
+            rows: Vec::new(),
+            records: Vec::new(),
+            bundle_records: Vec::new(),
+            curve: None,
+            disagreement: None,
+            unreliable: None,
+            reliable: false,
+            converged: Vec::new(),
+        }
+    }
+
+    /// A function to mark every result record of a protocol-owned pool as such, so they can be
+    /// told apart without the block report.
+    pub fn tag_protocol_owned(mut self) -> Self {
+        if self.report.protocol_owned {
+            for record in self.records.iter_mut() {
+                if let Some(record) = record.as_object_mut() {
+                    record.insert("protocol_owned".to_string(), true.into());
+                }
+            }
+        }
+        self
+    }
+}
+
+impl CachedPool {
+    /// A function to re-emit the results for a later block, marked with the block they're from.
+    pub fn replay(&self, block_number: u64) -> PoolOutcome {
+        let mut outcome: PoolOutcome = self.outcome.clone();
+        outcome.report.stale_since_block = Some(self.block_number);
+        for record in outcome.records.iter_mut().chain(outcome.bundle_records.iter_mut()) {
+            if let Some(record) = record.as_object_mut() {
+                record.insert("block_number".to_string(), block_number.into());
+                record.insert("stale_since_block".to_string(), self.block_number.into());
+            }
+        }
+        outcome
+    }
+}
+
+/// A pair as given on the command line or in the config, before its tokens are resolved.
+pub struct PairSpec {
+    pub token_in: TokenId,
+    pub token_out: TokenId,
+    pub slippages: Vec<f64>,
+    pub reference: Option<OracleConfig>,
+    pub usd_price: Option<OracleConfig>,
+}
+
+impl PairSpec {
+    /// A function to look the pair's tokens up in the chain's token list.
+    ///
+    /// Args:
+    /// - chain: The chain being streamed
+    /// - tokens: Its tokens by address
+    /// - names: Display overrides, for the pair's label
+    pub fn resolve(self, chain: Chain, tokens: &HashMap<Bytes, Token>, names: &DisplayNames) -> anyhow::Result<TrackedPair> {
+        let mut pair: TrackedPair = TrackedPair::new(
+            self.token_in.resolve(chain, tokens)?,
+            self.token_out.resolve(chain, tokens)?,
+            self.slippages,
+            self.reference.map(PriceOracle::new),
+            names,
+        )?;
+        pair.usd_oracle = self.usd_price.map(PriceOracle::new);
+        Ok(pair)
+    }
+}
+
+/// A pair being tracked, with the pool token set it matches.
+pub struct TrackedPair {
+    /// e.g. "WETH/USDC"; results are keyed by it.
+    pub label: String,
+    pub token_in: Token,
+    pub token_out: Token,
+    /// Both tokens sorted by address, as pools list them.
+    pub tokens: Vec<Token>,
+    pub slippages: Vec<f64>,
+    /// Where the slippage reference comes from when it isn't pool spot.
+    pub oracle: Option<PriceOracle>,
+    /// Where token_in's dollar price comes from when it isn't the pools.
+    pub usd_oracle: Option<PriceOracle>,
+}
+
+impl TrackedPair {
+    pub fn new(
+        token_in: Token,
+        token_out: Token,
+        slippages: Vec<f64>,
+        oracle: Option<PriceOracle>,
+        names: &DisplayNames,
+    ) -> anyhow::Result<Self> {
+        if token_in.address == token_out.address {
+            anyhow::bail!("{} is paired with itself ({})", token_in.symbol, token_in.address);
+        }
+        let mut tokens: Vec<Token> = vec![token_out.clone(), token_in.clone()];
+        tokens.sort_unstable_by_key(|t: &Token| t.address.clone());
+        Ok(Self {
+            label: names.pair(&token_in, &token_out),
+            token_in,
+            token_out,
+            tokens,
+            slippages,
+            oracle,
+            usd_oracle: None,
+        })
+    }
+}
+
+/// A function to measure one pool for the block: cross-check, monotonicity probing, pricing, and
+/// then whichever of the depth search, coarse estimate or fixed-size fills the run asked for.
+///
+/// Runs on a worker, so nothing is written here; the records and console rows come back in the
+/// order they're emitted.
+///
+/// Args:
+/// - context: What every pool of the block is measured with
+/// - job: The pool, its pair and its VM twin
+///
+/// Returns:
+/// - The pool's report, console rows and records, or an error formatting an amount
+pub fn measure_pool(context: &BlockContext, job: &PoolJob) -> anyhow::Result<PoolOutcome> {
+    let names: &DisplayNames = context.names;
+    let renderer: &(dyn ConsoleRenderer + Sync) = context.renderer;
+    let pair: &TrackedPair = &context.pairs[job.pair];
+    let (token_in, token_out): (&Token, &Token) = (&pair.token_in, &pair.token_out);
+    let (id, state): (&String, &dyn ProtocolSim) = (job.id, job.state);
+    let mut outcome: PoolOutcome = PoolOutcome::new(PoolReport {
+        pool_id: id.clone(),
+        protocol: context.pool_protocols.get(id).cloned().unwrap_or_default(),
+        pair: pair.label.clone(),
+        base: token_in.address.to_string(),
+        quote: token_out.address.to_string(),
+        status: PoolStatus::Ok,
+        base_usd_price: context.base_usd[job.pair],
+        fee: pool_fee(state),
+        spot_price: None,
+        reference_price: None,
+        excluded: None,
+        depths: Vec::new(),
+        two_sided: Vec::new(),
+        fills: Vec::new(),
+        stale_since_block: None,
+        protocol_owned: context.protocol_owned.contains(&id.to_lowercase()),
+    });
+
+    if let Some(vm_state) = job.vm_state {
+        cross_check(context, job, vm_state, &mut outcome);
+    }
+
+    // Don't trust a pool for bracketing until a few probes behave sanely.
+    let check: MonotonicityCheck = probe_monotonicity(
+        state,
+        token_in,
+        token_out,
+        &default_probe_amounts(token_in),
+        DEFAULT_TOLERANCE_BPS,
+    );
+    if !check.is_reliable() {
+        outcome.rows.push((format!("{} {}", pair.label, id), renderer.unreliable_pool(id, &check.to_string())));
+        outcome.records.push(serde_json::json!({
+            "block_number": context.block_number,
+            "pair": &pair.label,
+            "pool_id": id,
+            "status": PoolStatus::Unreliable,
+            "unreliable": &check,
+        }));
+        outcome.report.status = PoolStatus::Unreliable;
+        outcome.report.excluded = Some(format!("unreliable: {}", check));
+        outcome.unreliable = Some(check);
+        return Ok(outcome);
+    }
+    let priced: Result<(BigUint, f64), DepthError> =
+        guard_simulation(|| state.get_amount_out(token_in.one(), token_in, token_out)).and_then(|quote| {
+            let spot: f64 = guard_simulation(|| state.spot_price(token_in, token_out))?;
+            Ok((quote.amount, spot))
+        });
+    let (out, spot): (BigUint, f64) = match priced {
+        Ok(priced) => priced,
+        Err(error) => {
+            outcome.rows.push((format!("{} {}", pair.label, id), renderer.failed_pool(id, &error.to_string())));
+            outcome.records.push(serde_json::json!({
+                "block_number": context.block_number,
+                "pair": &pair.label,
+                "pool_id": id,
+                "status": PoolStatus::Failed,
+                "error": error.to_string(),
+            }));
+            outcome.report.status = PoolStatus::Failed;
+            outcome.report.excluded = Some(format!("failed: {}", error));
+            return Ok(outcome);
+        }
+    };
+    let mut row: String = renderer.quote("1", names.symbol(token_in), &out.to_string(), names.symbol(token_out));
+    let reference: f64 = reference_price(context, job, spot, &mut row);
+    outcome.report.spot_price = Some(spot);
+    outcome.report.reference_price = Some(reference);
+    outcome.reliable = true;
+    let sell: Leg = Leg::sell(token_in, token_out, reference)
+        .with_base_usd(context.base_usd[job.pair])
+        .with_fee(outcome.report.fee);
+
+    let settings: &MeasureSettings = context.settings;
+    let calculator: DepthCalculator = DepthCalculator::new(state, token_in, token_out)
+        .with_reference_price(reference)
+        .with_precision(settings.precision)
+        .with_max_iterations(settings.max_iterations)
+        .with_bracket_tvl_multiple(settings.bracket_tvl_multiple)
+        .with_adaptive_precision(settings.adaptive_precision);
+
+    if !context.notionals.is_empty() || settings.exact_output.is_some() {
+        measure_fills(context, job, &calculator, &sell, &mut outcome, &mut row)?;
+    } else if settings.coarse {
+        measure_coarse(context, job, &calculator, &sell, &mut outcome, &mut row);
+    } else {
+        measure_depths(context, job, &calculator, &sell, &mut outcome, &mut row)?;
+        sample_curve(context, job, reference, &mut outcome);
+        measure_price_ranges(context, job, &mut outcome, &mut row);
+    }
+    if outcome.report.all_failed() {
+        outcome.report.status = PoolStatus::Failed;
+    }
+    outcome.rows.push((format!("{} {}", pair.label, id), row));
+    Ok(outcome)
+}
+
+/// A function to compare the pool's quotes against its VM twin's, keeping the result and
+/// flagging a disagreement.
+fn cross_check(context: &BlockContext, job: &PoolJob, vm_state: &dyn ProtocolSim, outcome: &mut PoolOutcome) {
+    let pair: &TrackedPair = &context.pairs[job.pair];
+    let cross_check: CrossCheck = compare_implementations(
+        job.state,
+        vm_state,
+        &pair.token_in,
+        &pair.token_out,
+        &default_probe_amounts(&pair.token_in),
+        context.settings.cross_check_bps,
+    );
+    outcome.records.push(serde_json::json!({
+        "block_number": context.block_number,
+        "pair": &pair.label,
+        "pool_id": job.id,
+        "cross_check": &cross_check,
+    }));
+    if !cross_check.agrees() {
+        outcome.rows.push((
+            format!("{} {} cross-check", pair.label, job.id),
+            context.renderer.simulator_disagreement(job.id, &cross_check.to_string()),
+        ));
+        outcome.disagreement = Some(cross_check);
+    }
+}
+
+/// A function to pick the price the pool is measured against: the pair's mark if it has one,
+/// else spot, moved by any price shift. Each substitution is noted on the pool's row.
+fn reference_price(context: &BlockContext, job: &PoolJob, spot: f64, row: &mut String) -> f64 {
+    let pair: &TrackedPair = &context.pairs[job.pair];
+    let (token_in, token_out): (&str, &str) =
+        (context.names.symbol(&pair.token_in), context.names.symbol(&pair.token_out));
+    let base: f64 = match context.marks[job.pair] {
+        Some(mark) => {
+            row.push('\n');
+            row.push_str(&context.renderer.reference_mark(spot, mark, token_in, token_out));
+            mark
+        }
+        None => spot,
+    };
+    match context.settings.price_shift {
+        Some(shift) => {
+            row.push('\n');
+            row.push_str(&context.renderer.reference_shift(&shift.to_string(), base, shift.apply(base), token_in, token_out));
+            shift.apply(base)
+        }
+        None => base,
+    }
+}
+
+/// A function to price fixed-size trades: the input given (--notional, --impact-table) or the
+/// input solved for an exact output (--exact-output), labeled as given.
+fn measure_fills(
+    context: &BlockContext,
+    job: &PoolJob,
+    calculator: &DepthCalculator,
+    sell: &Leg,
+    outcome: &mut PoolOutcome,
+    row: &mut String,
+) -> anyhow::Result<()> {
+    let names: &DisplayNames = context.names;
+    let pair: &TrackedPair = &context.pairs[job.pair];
+    let (token_in, token_out): (&Token, &Token) = (&pair.token_in, &pair.token_out);
+    let fills: Vec<(String, Result<SearchResult, String>)> = match &context.settings.exact_output {
+        Some(amount) => {
+            let max_slippage: f64 = pair.slippages.iter().copied().fold(0.0, f64::max);
+            let fill: Result<SearchResult, String> = parse_amount(amount, token_out.decimals as u8)
+                .map_err(|e| e.to_string())
+                .and_then(|amount_out| calculator.input_for_output(amount_out, max_slippage).map_err(|e| e.to_string()));
+            vec![(format!("{} {}", amount, names.symbol(token_out)), fill)]
+        }
+        None => context
+            .notionals
+            .iter()
+            .zip(context.notional_amounts[job.pair].iter())
+            .map(|(notional, amount_in)| {
+                let fill: Result<SearchResult, String> = amount_in
+                    .clone()
+                    .and_then(|amount_in| calculator.slippage_for(amount_in).map_err(|e| e.to_string()));
+                (notional.to_string(), fill)
+            })
+            .collect(),
+    };
+    for (notional, fill) in fills.into_iter() {
+        let report: FillReport =
+            FillReport::new(&notional, sell, fill.as_ref().map(|r| (r.amount_in, r.amount_out)).map_err(Clone::clone));
+        outcome.records.push(serde_json::json!({
+            "block_number": context.block_number,
+            "pair": &pair.label,
+            "pool_id": job.id,
+            "action": sell.action,
+            "base": names.symbol(token_in),
+            "quote": names.symbol(token_out),
+            "reference_price": sell.reference,
+            "notional": &report.notional,
+            "base_amount": &report.base_amount,
+            "quote_amount": &report.quote_amount,
+            "usd_value": report.usd_value,
+            "base_tokens": &report.conversions.base_tokens,
+            "quote_tokens": &report.conversions.quote_tokens,
+            "quote_usd_value": report.conversions.quote_usd_value,
+            "slippage": report.slippage,
+            "fee_slippage": report.fee_slippage,
+            "impact_slippage": report.impact_slippage,
+            "error": &report.error,
+            "simulations": fill.as_ref().ok().map(|r| r.simulations),
+            "elapsed_ms": fill.as_ref().ok().map(|r| r.elapsed.as_secs_f64() * 1000.0),
+        }));
+        row.push('\n');
+        row.push_str(&context.renderer.fill(&report.notional, &describe_fill(names, sell, &report)?));
+        outcome.report.fills.push(report);
+    }
+    // The top of a ladder can be past what a small pool can take; that alone doesn't fail the pool.
+    if outcome.report.fills.iter().all(|fill| fill.error.is_some()) {
+        outcome.report.status = PoolStatus::Failed;
+    }
+    Ok(())
+}
+
+/// A function to estimate depth at every target from two probes, for --coarse.
+fn measure_coarse(
+    context: &BlockContext,
+    job: &PoolJob,
+    calculator: &DepthCalculator,
+    sell: &Leg,
+    outcome: &mut PoolOutcome,
+    row: &mut String,
+) {
+    let names: &DisplayNames = context.names;
+    let pair: &TrackedPair = &context.pairs[job.pair];
+    let (token_in, token_out): (&Token, &Token) = (&pair.token_in, &pair.token_out);
+    for &slippage in pair.slippages.iter() {
+        let estimate: Result<DepthEstimate, DepthError> = calculator.estimate_at(slippage);
+        outcome.records.push(serde_json::json!({
+            "block_number": context.block_number,
+            "pair": &pair.label,
+            "pool_id": job.id,
+            "action": TradeAction::Sell,
+            "base": names.symbol(token_in),
+            "quote": names.symbol(token_out),
+            "reference_price": sell.reference,
+            "target_slippage": slippage,
+            "approximate": true,
+            "lower_bound": estimate.as_ref().ok().map(|e| e.lower_bound),
+            "base_amount": estimate.as_ref().ok().map(|e| e.amount_in.to_string()),
+            "usd_value": estimate.as_ref().ok().and_then(|e| sell.usd_value(e.amount_in)),
+            "base_tokens": estimate.as_ref().ok().map(|e| format_amount(e.amount_in, token_in.decimals)),
+            "error": estimate.as_ref().err().map(|e| e.to_string()),
+            "simulations": 2,
+            "elapsed_ms": estimate.as_ref().ok().map(|e| e.elapsed.as_secs_f64() * 1000.0),
+        }));
+        let estimate_text: String = match &estimate {
+            Ok(estimate) if estimate.lower_bound => format!(
+                "sell ≥ {} {} (no impact across 2 probes)",
+                format_amount(estimate.amount_in, token_in.decimals),
+                names.symbol(token_in),
+            ),
+            Ok(estimate) => format!(
+                "sell ≈ {} {} (approximate, 2 probes)",
+                format_amount(estimate.amount_in, token_in.decimals),
+                names.symbol(token_in),
+            ),
+            Err(e) => format!("failed: {}", e),
+        };
+        row.push('\n');
+        row.push_str(&context.renderer.depth(slippage, &estimate_text));
+        outcome.report.depths.push(DepthReport::from_estimate(slippage, sell, estimate.as_ref()));
+    }
+}
+
+/// A function to search for depth at every target, on the sell leg and, with --both-directions or
+/// --two-sided, the buy leg.
+fn measure_depths(
+    context: &BlockContext,
+    job: &PoolJob,
+    calculator: &DepthCalculator,
+    sell: &Leg,
+    outcome: &mut PoolOutcome,
+    row: &mut String,
+) -> anyhow::Result<()> {
+    let settings: &MeasureSettings = context.settings;
+    let names: &DisplayNames = context.names;
+    let pair: &TrackedPair = &context.pairs[job.pair];
+    let (token_in, token_out): (&Token, &Token) = (&pair.token_in, &pair.token_out);
+    let (id, reference): (&String, f64) = (job.id, sell.reference);
+    // One search covers every target, reusing evaluations between them, and starts from where
+    // each target converged last block.
+    let depths: Result<Vec<SearchResult>, DepthError> =
+        calculator.depths_from_hints(&pair.slippages, &context.warm_starts.hints(id, &token_in.address, &pair.slippages));
+    // The buy leg spends quote on base against the same (possibly shifted) market. Its
+    // reference is kept in quote per base, like the sell leg's.
+    let buy_depths: Option<(f64, Result<Vec<SearchResult>, DepthError>)> =
+        (settings.both_directions || settings.two_sided).then(|| {
+            // Two-sided depth measures both legs from the sell leg's mid, so they add up.
+            let reverse_reference: Result<f64, DepthError> = if settings.two_sided {
+                Ok(1.0 / reference)
+            } else {
+                let reverse_spot: Result<f64, DepthError> = match context.marks[job.pair] {
+                    Some(mark) => Ok(1.0 / mark),
+                    None => guard_simulation(|| job.state.spot_price(token_out, token_in)),
+                };
+                reverse_spot.map(|reverse_spot| match settings.price_shift {
+                    Some(shift) => shift.apply_inverse(reverse_spot),
+                    None => reverse_spot,
+                })
+            };
+            match reverse_reference {
+                Ok(reverse_reference) => {
+                    let depths: Result<Vec<SearchResult>, DepthError> =
+                        calculator.reversed().with_reference_price(reverse_reference).depths_from_hints(
+                            &pair.slippages,
+                            &context.warm_starts.hints(id, &token_out.address, &pair.slippages),
+                        );
+                    (1.0 / reverse_reference, depths)
+                }
+                // Nothing gets measured, so the reference doesn't matter.
+                Err(e) => (reference, Err(e)),
+            }
+        });
+    if let Ok(depths) = &depths {
+        outcome.converged.push((token_in.address.clone(), depths.clone()));
+    }
+    if let Some((_, Ok(depths))) = &buy_depths {
+        outcome.converged.push((token_out.address.clone(), depths.clone()));
+    }
+    let buy: Option<Leg> = buy_depths.as_ref().map(|(buy_reference, _)| {
+        Leg::buy(token_in, token_out, *buy_reference)
+            .with_base_usd(context.base_usd[job.pair])
+            .with_fee(outcome.report.fee)
+    });
+
+    for (i, &slippage) in pair.slippages.iter().enumerate() {
+        let depth: Result<&SearchResult, &DepthError> = depths.as_ref().map(|d| &d[i]);
+        let buy_depth: Option<Result<&SearchResult, &DepthError>> =
+            buy_depths.as_ref().map(|(_, r)| r.as_ref().map(|d| &d[i]));
+        let legs = std::iter::once((sell, depth)).chain(buy.as_ref().zip(buy_depth));
+        for (leg, depth) in legs {
+            // With --pool-limits fail, depth the pool cut short counts as a failure.
+            let rejected: Option<DepthError> = depth.ok().and_then(|d| settings.pool_limits.rejects(d));
+            let depth: Result<&SearchResult, &DepthError> = match &rejected {
+                Some(e) => Err(e),
+                None => depth,
+            };
+            let mut report: DepthReport = DepthReport::from_search(slippage, leg, depth);
+            if settings.sim_inputs {
+                report.simulation = depth.ok().map(|depth| SimulationInputs::new(leg, depth));
+            }
+            outcome.records.push(serde_json::json!({
+                "block_number": context.block_number,
+                "pair": &pair.label,
+                "pool_id": id,
+                "action": leg.action,
+                "base": names.symbol(leg.base),
+                "quote": names.symbol(leg.quote),
+                "reference_price": leg.reference,
+                "target_slippage": slippage,
+                "base_amount": &report.base_amount,
+                "quote_amount": &report.quote_amount,
+                "usd_value": report.usd_value,
+                "base_tokens": &report.conversions.base_tokens,
+                "quote_tokens": &report.conversions.quote_tokens,
+                "quote_usd_value": report.conversions.quote_usd_value,
+                "slippage": report.slippage,
+                "fee_slippage": report.fee_slippage,
+                "impact_slippage": report.impact_slippage,
+                "error": &report.error,
+                "simulations": depth.ok().map(|d| d.simulations),
+                "iterations": report.iterations,
+                "converged": report.converged,
+                "pool_limited": &report.pool_limited,
+                "elapsed_ms": report.elapsed_ms,
+                "simulation": &report.simulation,
+            }));
+            row.push('\n');
+            row.push_str(&context.renderer.depth(slippage, &describe_depth(names, leg, depth, &report)?));
+            outcome.report.depths.push(report);
+        }
+        if let Some((buy, buy_depth)) = buy.as_ref().zip(buy_depth).filter(|_| settings.two_sided) {
+            let report: TwoSidedReport = TwoSidedReport::new(slippage, sell, depth, buy, buy_depth);
+            outcome.records.push(serde_json::json!({
+                "block_number": context.block_number,
+                "pair": &pair.label,
+                "pool_id": id,
+                "base": names.symbol(token_in),
+                "quote": names.symbol(token_out),
+                "two_sided": &report,
+            }));
+            row.push('\n');
+            row.push_str(&context.renderer.depth(slippage, &describe_two_sided(names, token_in, token_out, &report)?));
+            outcome.report.two_sided.push(report);
+        }
+    }
+    Ok(())
+}
+
+/// A function to sample the pool's slippage-by-size curve, into the bundle and for the charts.
+fn sample_curve(context: &BlockContext, job: &PoolJob, reference: f64, outcome: &mut PoolOutcome) {
+    if !(context.sample_curves || context.chart) {
+        return;
+    }
+    let pair: &TrackedPair = &context.pairs[job.pair];
+    let (min_in, max_in) = default_curve_range(&pair.token_in);
+    let samples: usize =
+        if context.settings.curve_samples > 0 { context.settings.curve_samples } else { DEFAULT_CHART_SAMPLES };
+    let curve: Result<DepthCurve, DepthError> = DepthCurve::sample_against_reference(
+        job.state,
+        &pair.token_in,
+        &pair.token_out,
+        min_in,
+        max_in,
+        samples,
+        reference,
+    );
+    if context.sample_curves {
+        outcome.bundle_records.push(serde_json::json!({
+            "block_number": context.block_number,
+            "pair": &pair.label,
+            "pool_id": job.id,
+            "curve": curve.as_ref().ok(),
+            "error": curve.as_ref().err().map(|e| e.to_string()),
+        }));
+    }
+    outcome.curve = curve.ok().filter(|_| context.chart);
+}
+
+/// A function to measure a concentrated-liquidity pool's liquidity by price band, for --price-ranges.
+fn measure_price_ranges(context: &BlockContext, job: &PoolJob, outcome: &mut PoolOutcome, row: &mut String) {
+    if !(context.settings.price_ranges && is_concentrated_liquidity(job.state)) {
+        return;
+    }
+    let names: &DisplayNames = context.names;
+    let pair: &TrackedPair = &context.pairs[job.pair];
+    let ranges: Result<RangeReport, DepthError> =
+        liquidity_by_price_range(job.state, &pair.token_in, &pair.token_out, &DEFAULT_PRICE_BANDS).map(|ranges| {
+            RangeReport {
+                base: names.symbol(&pair.token_in).to_string(),
+                quote: names.symbol(&pair.token_out).to_string(),
+                ..ranges
+            }
+        });
+    outcome.records.push(serde_json::json!({
+        "block_number": context.block_number,
+        "pair": &pair.label,
+        "pool_id": job.id,
+        "price_ranges": ranges.as_ref().ok(),
+        "error": ranges.as_ref().err().map(|e| e.to_string()),
+    }));
+    row.push('\n');
+    match &ranges {
+        Ok(ranges) => row.push_str(ranges.to_string().trim_end()),
+        Err(e) => row.push_str(&format!("   price ranges failed: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tycho_simulation::evm::protocol::uniswap_v2::state::UniswapV2State;
+    use crate::console::PlainRenderer;
+    use super::*;
+
+    const POOL: &str = "0x0000000000000000000000000000000000000abc";
+
+    fn pair() -> TrackedPair {
+        let weth = Token::new("0x0000000000000000000000000000000000000001", 18, "WETH", BigUint::from(0u32));
+        let usdc = Token::new("0x0000000000000000000000000000000000000002", 6, "USDC", BigUint::from(0u32));
+        TrackedPair::new(weth, usdc, vec![0.01, 0.02], None, &DisplayNames::default()).unwrap()
+    }
+
+    /// 1,000 WETH against 3,000,000 USDC.
+    fn pool() -> UniswapV2State {
+        UniswapV2State::new(U256::from(10u64).pow(U256::from(21u64)), U256::from(3_000_000_000_000u64))
+    }
+
+    /// A function to measure the pool on its own at block 100, with the given settings.
+    fn measure(settings: &MeasureSettings, protocol_owned: &HashSet<String>) -> PoolOutcome {
+        let pairs: Vec<TrackedPair> = vec![pair()];
+        let id: String = POOL.to_string();
+        let state: UniswapV2State = pool();
+        let context: BlockContext = BlockContext {
+            settings,
+            names: &DisplayNames::default(),
+            renderer: &PlainRenderer,
+            pairs: &pairs,
+            pool_protocols: &HashMap::from([(id.clone(), "uniswap_v2".to_string())]),
+            protocol_owned,
+            block_number: 100,
+            warm_starts: &WarmStarts::default(),
+            notionals: &[],
+            notional_amounts: &[Vec::new()],
+            base_usd: &[None],
+            marks: &[None],
+            sample_curves: false,
+            chart: false,
+        };
+        let job: PoolJob = PoolJob { pair: 0, id: &id, state: &state, vm_state: None, replay: None };
+        measure_pool(&context, &job).unwrap()
+    }
+
+    #[test]
+    fn measures_every_target_of_a_reliable_pool() {
+        let outcome: PoolOutcome = measure(&MeasureSettings::default(), &HashSet::new());
+        assert!(outcome.reliable);
+        assert_eq!(outcome.report.status, PoolStatus::Ok);
+        assert_eq!(outcome.report.protocol, "uniswap_v2");
+        assert_eq!(outcome.report.depths.len(), 2);
+        assert_eq!(outcome.records.len(), 2);
+        // A 2% target on an x*y=k pool with a 0.3% fee clears 17.5 WETH, as in the committed recording.
+        assert_eq!(outcome.records[1]["target_slippage"], serde_json::json!(0.02));
+        assert_eq!(outcome.records[1]["base_amount"], serde_json::json!("17500000000000000000"));
+        assert_eq!(outcome.converged.len(), 1);
+        assert_eq!(outcome.rows.len(), 1);
+    }
+
+    #[test]
+    fn measures_both_legs_and_only_tags_protocol_owned_pools() {
+        let settings: MeasureSettings = MeasureSettings { both_directions: true, ..MeasureSettings::default() };
+        let outcome: PoolOutcome = measure(&settings, &HashSet::new()).tag_protocol_owned();
+        let actions: Vec<&serde_json::Value> = outcome.records.iter().map(|record| &record["action"]).collect();
+        assert_eq!(actions.len(), 4);
+        assert!(outcome.records.iter().all(|record| record.get("protocol_owned").is_none()));
+        assert_eq!(outcome.converged.len(), 2);
+
+        let owned: HashSet<String> = HashSet::from([POOL.to_string()]);
+        let outcome: PoolOutcome = measure(&MeasureSettings::default(), &owned).tag_protocol_owned();
+        assert!(outcome.report.protocol_owned);
+        assert!(outcome.records.iter().all(|record| record["protocol_owned"] == serde_json::json!(true)));
+    }
+
+    #[test]
+    fn replays_cached_results_marked_with_the_block_they_are_from() {
+        let outcome: PoolOutcome = measure(&MeasureSettings::default(), &HashSet::new());
+        let inputs: PoolInputs = PoolInputs { mark: None, base_usd: None, notional_amounts: Vec::new() };
+        let cached: CachedPool = CachedPool { block_number: 100, inputs, outcome };
+        let replayed: PoolOutcome = cached.replay(103);
+        assert_eq!(replayed.report.stale_since_block, Some(100));
+        for record in replayed.records.iter() {
+            assert_eq!((record["block_number"].clone(), record["stale_since_block"].clone()), (103.into(), 100.into()));
+        }
+        // The cache itself still holds the results as measured.
+        assert_eq!(cached.outcome.records[0]["block_number"], serde_json::json!(100));
+    }
+}
@@ -0,0 +1,105 @@
+use alloy_primitives::U256;
+use num_bigint::BigUint;
+use tycho_common::Bytes;
+use tycho_simulation::{
+    evm::protocol::u256_num::{u256_to_biguint, u256_to_f64},
+    models::Token,
+};
+use crate::{
+    binary_search::V2_RESERVE_MULTIPLE,
+    error::DepthError,
+    provider::AmountOutProvider,
+};
+
+/// The fee a `MockXykPool` charges unless `with_fee_bps` says otherwise: Uniswap v2's 0.3%.
+pub const DEFAULT_MOCK_FEE_BPS: u32 = 30;
+
+/// A constant-product (x * y = k) pool quoted from its reserves alone, for testing the search,
+/// slippage math and aggregation offline, without Tycho:
+///
+/// ```ignore
+/// let pool: MockXykPool = MockXykPool::new(&weth, reserve_weth, &usdc, reserve_usdc).with_fee_bps(5);
+/// let depth: SearchResult = DepthCalculator::from_provider(&pool, &weth, &usdc).depth_at(0.01)?;
+/// ```
+///
+/// Quotes follow Uniswap v2's `getAmountOut`, rounding down, so a mock with the default fee quotes
+/// what a `UniswapV2State` with the same reserves would.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockXykPool {
+    token_a: Bytes,
+    reserve_a: U256,
+    token_b: Bytes,
+    reserve_b: U256,
+    fee_bps: u32,
+}
+
+impl MockXykPool {
+    /// A pool holding `reserve_a` of token_a against `reserve_b` of token_b, in base units, with the
+    /// default fee.
+    pub fn new(token_a: &Token, reserve_a: U256, token_b: &Token, reserve_b: U256) -> Self {
+        Self {
+            token_a: token_a.address.clone(),
+            reserve_a,
+            token_b: token_b.address.clone(),
+            reserve_b,
+            fee_bps: DEFAULT_MOCK_FEE_BPS,
+        }
+    }
+
+    /// Charge this fee on every input, in basis points (e.g. 30 for 0.3%), at most 10,000.
+    pub fn with_fee_bps(self, fee_bps: u32) -> Self {
+        Self { fee_bps: fee_bps.min(10_000), ..self }
+    }
+
+    /// The fee as a decimal, e.g. 0.003, as `ProtocolSim::fee` gives it.
+    pub fn fee(&self) -> f64 {
+        self.fee_bps as f64 / 10_000.0
+    }
+
+    /// A function to get the reserves facing a sale of token_in.
+    ///
+    /// Returns:
+    /// - (reserve of token_in, reserve of token_out), in base units
+    pub fn reserves(&self, token_in: &Token, token_out: &Token) -> Result<(U256, U256), DepthError> {
+        if token_in.address == self.token_a && token_out.address == self.token_b {
+            Ok((self.reserve_a, self.reserve_b))
+        } else if token_in.address == self.token_b && token_out.address == self.token_a {
+            Ok((self.reserve_b, self.reserve_a))
+        } else {
+            Err(DepthError::Simulation(format!(
+                "the mock pool doesn't trade {} for {}",
+                token_in.address, token_out.address
+            )))
+        }
+    }
+}
+
+impl AmountOutProvider for MockXykPool {
+    fn amount_out(&self, amount_in: BigUint, token_in: &Token, token_out: &Token) -> Result<BigUint, DepthError> {
+        let (reserve_in, reserve_out) = self.reserves(token_in, token_out)?;
+        let amount_in_with_fee: BigUint = amount_in * (10_000u32 - self.fee_bps);
+        let denominator: BigUint = u256_to_biguint(reserve_in) * 10_000u32 + &amount_in_with_fee;
+        if denominator == BigUint::from(0u32) {
+            return Err(DepthError::Simulation("the mock pool has no reserves".to_string()));
+        }
+        Ok(amount_in_with_fee * u256_to_biguint(reserve_out) / denominator)
+    }
+
+    fn spot(&self, token_in: &Token, token_out: &Token) -> Result<f64, DepthError> {
+        let (reserve_in, reserve_out) = self.reserves(token_in, token_out)?;
+        let whole_in: f64 = u256_to_f64(reserve_in) / 10f64.powi(token_in.decimals as i32);
+        let whole_out: f64 = u256_to_f64(reserve_out) / 10f64.powi(token_out.decimals as i32);
+        let spot: f64 = whole_out / whole_in;
+        if spot.is_finite() && spot > 0.0 {
+            Ok(spot)
+        } else {
+            Err(DepthError::InvalidSpotPrice(spot))
+        }
+    }
+
+    /// Bracketed like a `UniswapV2State`, up to `V2_RESERVE_MULTIPLE` times the token_in reserve.
+    fn max_in(&self, token_in: &Token, token_out: &Token, _reference_price: f64, _tvl_multiple: f64) -> Option<U256> {
+        let (reserve_in, _) = self.reserves(token_in, token_out).ok()?;
+        Some(reserve_in.saturating_mul(U256::from(V2_RESERVE_MULTIPLE))).filter(|cap| !cap.is_zero())
+    }
+}
@@ -0,0 +1,70 @@
+use alloy_primitives::U256;
+use serde::Serialize;
+use tycho_common::Bytes;
+use tycho_simulation::{models::Token, protocol::state::ProtocolSim};
+use crate::{error::guard_simulation, estimate::estimate_depth};
+
+/// Native/stable pools are ranked by their estimated depth at this slippage.
+pub const NATIVE_PRICE_DEPTH_SLIPPAGE: f64 = 0.01;
+
+/// The chain's native token price for one block, in dollars.
+#[derive(Debug, Clone, Serialize)]
+pub struct NativePrice {
+    /// Spot price of the native token in the stablecoin, taken as dollars.
+    pub price: f64,
+    pub native: String,
+    pub stable: String,
+    /// The pool the price was read from.
+    pub pool_id: String,
+}
+
+/// A function to price the native token from the deepest native/stable pool, once per block, for
+/// gas adjustment and USD conversions.
+///
+/// Pools are ranked by a two-probe estimate of how much native they take before
+/// `NATIVE_PRICE_DEPTH_SLIPPAGE`, so a thin pool with a stale price can't win on its own quote.
+///
+/// Args:
+/// - pools: (id, tokens, state) for every tracked pool; the ones holding both a native and a
+///   stable token are candidates
+/// - natives: Addresses counted as the native token, e.g. WETH and the zero address for pools
+///   holding native ETH directly
+/// - stables: Addresses of dollar stablecoins, see `chain::stablecoin_addresses`
+///
+/// Returns:
+/// - The price, or None if no candidate pool could be priced
+pub fn native_token_price<'a>(
+    pools: impl IntoIterator<Item = (&'a str, &'a [Token], &'a dyn ProtocolSim)>,
+    natives: &[Bytes],
+    stables: &[Bytes],
+) -> Option<NativePrice> {
+    let mut best: Option<(U256, NativePrice)> = None;
+    for (id, tokens, state) in pools.into_iter() {
+        let native: Option<&Token> = tokens.iter().find(|t| natives.contains(&t.address));
+        let stable: Option<&Token> = tokens.iter().find(|t| stables.contains(&t.address));
+        let (Some(native), Some(stable)) = (native, stable) else {
+            continue;
+        };
+        let Ok(price) = guard_simulation(|| state.spot_price(native, stable)) else {
+            continue;
+        };
+        let Ok(estimate) = estimate_depth(NATIVE_PRICE_DEPTH_SLIPPAGE, state, native, stable) else {
+            continue;
+        };
+        if !price.is_finite() || price <= 0.0 {
+            continue;
+        }
+        if best.as_ref().is_none_or(|(depth, _)| estimate.amount_in > *depth) {
+            best = Some((
+                estimate.amount_in,
+                NativePrice {
+                    price,
+                    native: native.symbol.clone(),
+                    stable: stable.symbol.clone(),
+                    pool_id: id.to_string(),
+                },
+            ));
+        }
+    }
+    best.map(|(_, price)| price)
+}
@@ -0,0 +1,87 @@
+use std::{fmt, str::FromStr};
+use alloy_primitives::U256;
+use tycho_simulation::models::Token;
+use crate::amount::parse_amount;
+
+/// Trade sizes for `--impact-table` when none are configured: the ladder listings are usually
+/// judged by.
+pub const DEFAULT_IMPACT_SIZES: [Notional; 4] =
+    [Notional::Usd(10_000.0), Notional::Usd(100_000.0), Notional::Usd(1_000_000.0), Notional::Usd(10_000_000.0)];
+
+/// A fixed trade size for `--notional`: whole tokens of the pair's token_in, or dollars.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Notional {
+    /// e.g. "100" or "2_500.5", kept as written so it's parsed at the token's own decimals.
+    Tokens(String),
+    /// e.g. "$1M", converted with the token's dollar price each block.
+    Usd(f64),
+}
+
+impl Notional {
+    /// A function to turn the notional into an amount of token_in.
+    ///
+    /// Args:
+    /// - token: The token being sold
+    /// - usd_price: Price of one token in dollars, needed for dollar notionals
+    ///
+    /// Returns:
+    /// - The amount in base units, or why it can't be sized this block
+    pub fn amount_in(&self, token: &Token, usd_price: Option<f64>) -> Result<U256, String> {
+        let whole: String = match self {
+            Notional::Tokens(amount) => amount.clone(),
+            Notional::Usd(usd) => {
+                let price: f64 = usd_price
+                    .filter(|price| *price > 0.0 && price.is_finite())
+                    .ok_or_else(|| format!("no dollar price for {} to size {}", token.symbol, self))?;
+                // As many fractional digits as the token has, so nothing is rounded away twice.
+                format!("{:.*}", token.decimals, usd / price)
+            }
+        };
+        parse_amount(&whole, token.decimals as u8).map_err(|e| e.to_string())
+    }
+}
+
+impl fmt::Display for Notional {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Notional::Tokens(amount) => write!(f, "{}", amount),
+            Notional::Usd(usd) => write!(f, "${}", usd),
+        }
+    }
+}
+
+/// Parses whole tokens ("100", "0.5") or dollars with an optional k/M/B suffix ("$1M", "$250k",
+/// "$1,000,000").
+impl FromStr for Notional {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed: &str = s.trim();
+        let Some(dollars) = trimmed.strip_prefix('$') else {
+            // Checked at as many decimals as it's written with, since the token (and its decimals,
+            // anything from 0 to 24 or more) is only known once it's resolved.
+            let written: usize = trimmed.split_once('.').map_or(0, |(_, fraction)| fraction.len());
+            let amount: U256 = parse_amount(trimmed, written.min(u8::MAX as usize) as u8)
+                .map_err(|e| format!("invalid notional \"{}\": {}", s, e))?;
+            if amount.is_zero() {
+                return Err(format!("notional \"{}\" must be a positive amount", s));
+            }
+            return Ok(Notional::Tokens(trimmed.to_string()));
+        };
+        let digits: String = dollars.trim().chars().filter(|c| *c != ',' && *c != '_').collect();
+        let (number, multiplier): (&str, f64) = match digits.char_indices().last() {
+            Some((i, 'k' | 'K')) => (&digits[..i], 1e3),
+            Some((i, 'm' | 'M')) => (&digits[..i], 1e6),
+            Some((i, 'b' | 'B')) => (&digits[..i], 1e9),
+            _ => (digits.as_str(), 1.0),
+        };
+        let usd: f64 = number
+            .parse::<f64>()
+            .map_err(|e| format!("invalid notional \"{}\": {}", s, e))?
+            * multiplier;
+        if !(usd.is_finite() && usd > 0.0) {
+            return Err(format!("notional \"{}\" must be a positive amount", s));
+        }
+        Ok(Notional::Usd(usd))
+    }
+}
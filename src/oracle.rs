@@ -0,0 +1,167 @@
+use std::{
+    fmt,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use serde::{Deserialize, Serialize};
+
+/// Marks are refetched at most this often unless configured otherwise.
+pub const DEFAULT_CACHE_SECS: u64 = 5;
+/// Marks older than this are refused unless configured otherwise.
+pub const DEFAULT_MAX_AGE_SECS: u64 = 60;
+
+/// An HTTP price source for a pair, used as the slippage reference instead of pool spot.
+///
+/// The endpoint is expected to answer a GET with JSON holding the price of token_in in token_out,
+/// in whole-token units, e.g. `{"price": 3012.5, "timestamp": 1717171717}`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct OracleConfig {
+    pub url: String,
+    /// JSON pointer to the price in the response.
+    #[serde(default = "default_price_pointer")]
+    pub price_pointer: String,
+    /// JSON pointer to when the mark was taken, in unix seconds. Without one, a mark is as old as
+    /// the request that fetched it.
+    #[serde(default)]
+    pub timestamp_pointer: Option<String>,
+    /// Reuse a fetched mark for this long before asking again.
+    #[serde(default = "default_cache_secs")]
+    pub cache_secs: u64,
+    /// Refuse marks older than this, rather than measure against a stale price.
+    #[serde(default = "default_max_age_secs")]
+    pub max_age_secs: u64,
+}
+
+fn default_price_pointer() -> String {
+    "/price".to_string()
+}
+
+fn default_cache_secs() -> u64 {
+    DEFAULT_CACHE_SECS
+}
+
+fn default_max_age_secs() -> u64 {
+    DEFAULT_MAX_AGE_SECS
+}
+
+#[derive(Debug)]
+pub enum OracleError {
+    Http(reqwest::Error),
+    /// The response didn't hold a positive price (or timestamp) where the pointer says.
+    BadResponse(String),
+    /// The newest mark available is older than `max_age_secs`.
+    Stale { age: Duration, max_age: Duration },
+}
+
+impl fmt::Display for OracleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OracleError::Http(e) => write!(f, "price oracle request failed: {}", e),
+            OracleError::BadResponse(e) => write!(f, "price oracle response unusable: {}", e),
+            OracleError::Stale { age, max_age } => write!(
+                f,
+                "price oracle mark is {}s old, more than the {}s allowed",
+                age.as_secs(),
+                max_age.as_secs()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OracleError {}
+
+/// A price and when it was taken.
+#[derive(Debug, Clone, Copy)]
+struct Mark {
+    price: f64,
+    observed_at: SystemTime,
+    fetched_at: Instant,
+}
+
+/// A cached client for one `OracleConfig`.
+pub struct PriceOracle {
+    config: OracleConfig,
+    client: reqwest::Client,
+    mark: Option<Mark>,
+}
+
+impl PriceOracle {
+    pub fn new(config: OracleConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+            mark: None,
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.config.url
+    }
+
+    /// A function to get the current mark, from cache if it's recent enough.
+    ///
+    /// A failed fetch falls back to the cached mark as long as that's within `max_age_secs`, so
+    /// one dropped request doesn't cost a block.
+    ///
+    /// Returns:
+    /// - The price of token_in in token_out, or why there's no usable one
+    pub async fn price(&mut self) -> Result<f64, OracleError> {
+        let cache_for: Duration = Duration::from_secs(self.config.cache_secs);
+        let fresh: Option<Mark> = self.mark.filter(|mark| mark.fetched_at.elapsed() < cache_for);
+        let mark: Mark = match fresh {
+            Some(mark) => mark,
+            None => match self.fetch().await {
+                Ok(mark) => {
+                    self.mark = Some(mark);
+                    mark
+                }
+                Err(error) => match self.mark {
+                    Some(mark) => {
+                        tracing::warn!("{}, using the cached mark", error);
+                        mark
+                    }
+                    None => return Err(error),
+                },
+            },
+        };
+
+        let age: Duration = SystemTime::now().duration_since(mark.observed_at).unwrap_or_default();
+        let max_age: Duration = Duration::from_secs(self.config.max_age_secs);
+        if age > max_age {
+            return Err(OracleError::Stale { age, max_age });
+        }
+        Ok(mark.price)
+    }
+
+    async fn fetch(&self) -> Result<Mark, OracleError> {
+        let body: serde_json::Value = self
+            .client
+            .get(&self.config.url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(OracleError::Http)?
+            .json()
+            .await
+            .map_err(OracleError::Http)?;
+
+        let price: f64 = body
+            .pointer(&self.config.price_pointer)
+            .and_then(serde_json::Value::as_f64)
+            .filter(|price| price.is_finite() && *price > 0.0)
+            .ok_or_else(|| OracleError::BadResponse(format!("no positive number at {}", self.config.price_pointer)))?;
+        let observed_at: SystemTime = match &self.config.timestamp_pointer {
+            Some(pointer) => body
+                .pointer(pointer)
+                .and_then(serde_json::Value::as_u64)
+                .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+                .ok_or_else(|| OracleError::BadResponse(format!("no unix timestamp at {}", pointer)))?,
+            None => SystemTime::now(),
+        };
+        Ok(Mark {
+            price,
+            observed_at,
+            fetched_at: Instant::now(),
+        })
+    }
+}
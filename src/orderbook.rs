@@ -0,0 +1,345 @@
+use std::{fmt, io, path::Path};
+use alloy_primitives::U256;
+use serde::Serialize;
+use tycho_simulation::{
+    evm::protocol::u256_num::{biguint_to_u256, u256_to_biguint},
+    models::Token,
+};
+use crate::{
+    amount::{format_amount, one_token},
+    error::DepthError,
+    provider::{AmountOutProvider, Pool},
+    sink::{Compression, CsvSink, FileSink},
+};
+
+/// How far apart the ladder's price levels are unless `--step-bps` says otherwise.
+pub const DEFAULT_BOOK_STEP_BPS: u32 = 10;
+
+/// How many levels each side of the ladder has unless `--levels` says otherwise.
+pub const DEFAULT_BOOK_LEVELS: usize = 20;
+
+/// How close to a level's price a pool's fill is solved, relative to the fill.
+const BOOK_PRECISION: u64 = 10_000;
+
+/// The most times a fill is doubled looking past a level's price; a pool still above it after
+/// that is reported at the last size tried.
+const MAX_BOOK_DOUBLINGS: u32 = 128;
+
+/// Which side of the book a level is on: bids buy base from the seller, asks sell it to the buyer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BookSide {
+    Bid,
+    Ask,
+}
+
+impl fmt::Display for BookSide {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookSide::Bid => write!(f, "bid"),
+            BookSide::Ask => write!(f, "ask"),
+        }
+    }
+}
+
+/// One price level: everything the pools trade before their marginal price reaches it, summed
+/// across pools as an order split to keep marginal prices equal would.
+#[derive(Debug, Clone, Serialize)]
+pub struct BookLevel {
+    pub side: BookSide,
+    /// How far the level is from mid, in basis points.
+    pub offset_bps: u32,
+    /// One whole base in quote.
+    pub price: f64,
+    /// Cumulative base sold (bids) or bought (asks) down or up to the level, in base units.
+    pub base_amount: String,
+    /// The quote received (bids) or spent (asks) for it, in base units.
+    pub quote_amount: String,
+    pub base_tokens: String,
+    pub quote_tokens: String,
+}
+
+/// A pair's pools as a CEX-style ladder of cumulative size at evenly spaced prices either side
+/// of mid, for `orderbook`.
+#[derive(Debug, Clone, Serialize)]
+pub struct OrderBook {
+    /// e.g. "WETH/USDC"
+    pub pair: String,
+    pub pools: usize,
+    /// Halfway between the best bid and best ask spot among the pools, in quote per base.
+    pub mid_price: f64,
+    pub step_bps: u32,
+    /// Nearest mid first.
+    pub bids: Vec<BookLevel>,
+    /// Nearest mid first.
+    pub asks: Vec<BookLevel>,
+    #[serde(skip)]
+    base: String,
+    #[serde(skip)]
+    quote: String,
+}
+
+/// Renders the ladder as exchanges do: asks from the furthest down to mid, then bids away from it.
+impl fmt::Display for OrderBook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Order book for {} across {} pools, {} bps levels", self.pair, self.pools, self.step_bps)?;
+        writeln!(f, "   {:>4}  {:>7}  {:>16}  {:>24}  {:>24}", "side", "bps", "price", self.base, self.quote)?;
+        let row = |f: &mut fmt::Formatter<'_>, level: &BookLevel| {
+            writeln!(
+                f,
+                "   {:>4}  {:>7}  {:>16.6}  {:>24}  {:>24}",
+                level.side, level.offset_bps, level.price, level.base_tokens, level.quote_tokens
+            )
+        };
+        for level in self.asks.iter().rev() {
+            row(f, level)?;
+        }
+        writeln!(f, "   {:>4}  {:>7}  {:>16.6}", "mid", 0, self.mid_price)?;
+        for level in self.bids.iter() {
+            row(f, level)?;
+        }
+        Ok(())
+    }
+}
+
+/// One level as a CSV row, with the pair it belongs to.
+#[derive(Debug, Serialize)]
+struct BookRow<'a> {
+    pair: &'a str,
+    side: BookSide,
+    offset_bps: u32,
+    price: f64,
+    base_amount: &'a str,
+    quote_amount: &'a str,
+    base_tokens: &'a str,
+    quote_tokens: &'a str,
+}
+
+/// A function to build a pair's order book from its pools.
+///
+/// Each level's price is `step_bps` further from mid than the last. A pool contributes what it
+/// fills before its marginal price, measured over a small step past the fill, crosses the level;
+/// pools already past it contribute nothing. Fees are in the quotes, so the first levels inside
+/// the pools' fee are usually empty.
+///
+/// Args:
+/// - pair: The pair's label, e.g. "WETH/USDC"
+/// - pools: (id, pool) for every pool of the pair, Tycho states or any other `Pool`
+/// - base: The pair's base token, the one whose size the ladder gives
+/// - quote: The pair's quote token, the one prices are in
+/// - step_bps: Distance between levels, in basis points
+/// - levels: Levels per side
+///
+/// Returns:
+/// - The ladder, or an error if no pool can be priced or a quote fails outright
+pub fn order_book<'a, P: Into<Pool<'a>> + Copy>(
+    pair: &str,
+    pools: &[(String, P)],
+    base: &Token,
+    quote: &Token,
+    step_bps: u32,
+    levels: usize,
+) -> Result<OrderBook, DepthError> {
+    let pools: Vec<Pool> = pools.iter().map(|(_, pool)| (*pool).into()).collect();
+    let best_bid: Option<f64> = pools.iter().filter_map(|pool| pool.spot(base, quote).ok()).reduce(f64::max);
+    let best_ask: Option<f64> = pools
+        .iter()
+        .filter_map(|pool| pool.spot(quote, base).ok())
+        .filter(|spot| *spot > 0.0)
+        .map(|spot| 1.0 / spot)
+        .reduce(f64::min);
+    let mid_price: f64 = match (best_bid, best_ask) {
+        (Some(bid), Some(ask)) => (bid + ask) / 2.0,
+        (Some(price), None) | (None, Some(price)) => price,
+        (None, None) => return Err(DepthError::Simulation("no pool of the pair could be priced".to_string())),
+    };
+    if !mid_price.is_finite() || mid_price <= 0.0 {
+        return Err(DepthError::InvalidSpotPrice(mid_price));
+    }
+
+    let mut bids: Vec<BookLevel> = Vec::with_capacity(levels);
+    let mut asks: Vec<BookLevel> = Vec::with_capacity(levels);
+    // Levels only move further out, so each pool's fill at one is where the next starts from.
+    let mut bid_fills: Vec<U256> = vec![U256::ZERO; pools.len()];
+    let mut ask_fills: Vec<U256> = vec![U256::ZERO; pools.len()];
+    for level in 1..=levels {
+        let offset_bps: u32 = step_bps.saturating_mul(level as u32);
+        let offset: f64 = f64::from(offset_bps) / 10_000.0;
+        if offset < 1.0 {
+            let price: f64 = mid_price * (1.0 - offset);
+            let (base_amount, quote_amount) = side_total(&pools, &mut bid_fills, base, quote, price)?;
+            bids.push(level_at(BookSide::Bid, offset_bps, price, base_amount, quote_amount, base, quote));
+        }
+        let price: f64 = mid_price * (1.0 + offset);
+        let (quote_amount, base_amount) = side_total(&pools, &mut ask_fills, quote, base, 1.0 / price)?;
+        asks.push(level_at(BookSide::Ask, offset_bps, price, base_amount, quote_amount, base, quote));
+    }
+
+    Ok(OrderBook {
+        pair: pair.to_string(),
+        pools: pools.len(),
+        mid_price,
+        step_bps,
+        bids,
+        asks,
+        base: base.symbol.clone(),
+        quote: quote.symbol.clone(),
+    })
+}
+
+impl OrderBook {
+    /// A function to write one row per level, bids then asks, nearest mid first.
+    pub fn write_csv(&self, sink: &mut CsvSink) -> io::Result<()> {
+        let rows: Vec<BookRow> = self
+            .bids
+            .iter()
+            .chain(self.asks.iter())
+            .map(|level| BookRow {
+                pair: &self.pair,
+                side: level.side,
+                offset_bps: level.offset_bps,
+                price: level.price,
+                base_amount: &level.base_amount,
+                quote_amount: &level.quote_amount,
+                base_tokens: &level.base_tokens,
+                quote_tokens: &level.quote_tokens,
+            })
+            .collect();
+        sink.write_rows(&rows)
+    }
+}
+
+/// A function to export order books to a file: CSV for a `.csv` path (optionally `.csv.gz` or
+/// `.csv.zst`), one JSON document per line otherwise. Appends, like the other sinks.
+pub fn export_order_books(path: &Path, books: &[OrderBook]) -> io::Result<()> {
+    let is_csv: bool = match Compression::from_path(path) {
+        Compression::None => path.extension().is_some_and(|ext| ext == "csv"),
+        _ => path.file_stem().is_some_and(|stem| Path::new(stem).extension().is_some_and(|ext| ext == "csv")),
+    };
+    if is_csv {
+        let mut sink: CsvSink = CsvSink::open(path, None)?;
+        for book in books.iter() {
+            book.write_csv(&mut sink)?;
+        }
+        sink.flush()
+    } else {
+        let mut sink: FileSink = FileSink::open(path, None)?;
+        for book in books.iter() {
+            sink.write_json_line(book)?;
+        }
+        io::Write::flush(&mut sink)
+    }
+}
+
+fn level_at(
+    side: BookSide,
+    offset_bps: u32,
+    price: f64,
+    base_amount: U256,
+    quote_amount: U256,
+    base: &Token,
+    quote: &Token,
+) -> BookLevel {
+    BookLevel {
+        side,
+        offset_bps,
+        price,
+        base_amount: base_amount.to_string(),
+        quote_amount: quote_amount.to_string(),
+        base_tokens: format_amount(base_amount, base.decimals),
+        quote_tokens: format_amount(quote_amount, quote.decimals),
+    }
+}
+
+/// A function to sum every pool's fill down to one level on one side.
+///
+/// Args:
+/// - fills: Each pool's fill at the previous level, updated to this one
+/// - limit: The level as whole token_out per whole token_in
+///
+/// Returns:
+/// - (token_in sold, token_out bought), summed across pools
+fn side_total(
+    pools: &[Pool],
+    fills: &mut [U256],
+    token_in: &Token,
+    token_out: &Token,
+    limit: f64,
+) -> Result<(U256, U256), DepthError> {
+    let mut total_in: U256 = U256::ZERO;
+    let mut total_out: U256 = U256::ZERO;
+    for (pool, fill) in pools.iter().zip(fills.iter_mut()) {
+        *fill = fill_to_price(*pool, token_in, token_out, limit, *fill)?;
+        if !fill.is_zero() {
+            total_in += *fill;
+            total_out += quote(*pool, *fill, token_in, token_out)?;
+        }
+    }
+    Ok((total_in, total_out))
+}
+
+/// A function to find how much of token_in a pool takes before its marginal price falls to
+/// `limit`, bracketing up from `start` by doubling and then bisecting.
+///
+/// Returns:
+/// - The fill in token_in base units, zero if the pool's first marginal price is already under
+///   the limit
+pub(crate) fn fill_to_price(
+    pool: Pool,
+    token_in: &Token,
+    token_out: &Token,
+    limit: f64,
+    start: U256,
+) -> Result<U256, DepthError> {
+    let smallest: U256 = (one_token(token_in.decimals).ok_or(DepthError::UnsupportedDecimals(token_in.decimals))?
+        / U256::from(BOOK_PRECISION))
+    .max(U256::from(1u64));
+    let above = |amount_in: U256| -> bool {
+        marginal_price(pool, amount_in, smallest, token_in, token_out).is_ok_and(|price| price >= limit)
+    };
+    let mut left: U256 = start.max(smallest);
+    if !above(left) {
+        return Ok(start);
+    }
+    let mut right: U256 = left;
+    for _ in 0..MAX_BOOK_DOUBLINGS {
+        right = right.checked_mul(U256::from(2u64)).ok_or(DepthError::Overflow)?;
+        if !above(right) {
+            break;
+        }
+        left = right;
+    }
+    if left == right {
+        return Ok(left);
+    }
+    while right - left > (left / U256::from(BOOK_PRECISION)).max(U256::from(1u64)) {
+        let middle: U256 = (left + right) / U256::from(2u64);
+        if above(middle) {
+            left = middle;
+        } else {
+            right = middle;
+        }
+    }
+    Ok(left)
+}
+
+/// A function to price the next bit of a fill: whole token_out per whole token_in over a step of
+/// a ten-thousandth of the fill past it, or `min_step` if that's more, so the output of a step
+/// isn't lost to rounding.
+fn marginal_price(
+    pool: Pool,
+    amount_in: U256,
+    min_step: U256,
+    token_in: &Token,
+    token_out: &Token,
+) -> Result<f64, DepthError> {
+    let step: U256 = (amount_in / U256::from(BOOK_PRECISION)).max(min_step);
+    let before: U256 = quote(pool, amount_in, token_in, token_out)?;
+    let after: U256 = quote(pool, amount_in + step, token_in, token_out)?;
+    let scale: f64 = 10f64.powi(token_in.decimals as i32 - token_out.decimals as i32);
+    Ok(f64::from(after.saturating_sub(before)) / f64::from(step) * scale)
+}
+
+pub(crate) fn quote(pool: Pool, amount_in: U256, token_in: &Token, token_out: &Token) -> Result<U256, DepthError> {
+    Ok(biguint_to_u256(&pool.amount_out(u256_to_biguint(amount_in), token_in, token_out)?))
+}
@@ -0,0 +1,276 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Condvar, Mutex,
+    },
+    thread,
+};
+
+/// A function to pick a worker count when none is configured: one per available core.
+pub fn default_workers() -> usize {
+    thread::available_parallelism().map(NonZeroUsize::get).unwrap_or(1)
+}
+
+/// A function to map over items on a bounded number of threads, keeping the input order.
+///
+/// Workers pull the next unclaimed item, so one slow pool doesn't hold up a fixed share of the
+/// others. Everything is borrowed for the duration of the call, so states don't need cloning.
+///
+/// Args:
+/// - items: The work, one item per call of `f`
+/// - workers: The most threads to run at once; 0 and 1 both run inline on the calling thread
+/// - f: The work for one item
+///
+/// Returns:
+/// - The results of `f`, in the order of `items`
+pub fn map_bounded<T, R, F>(items: &[T], workers: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    map_limited(items, workers, |_: &T| None, f)
+}
+
+/// A counting semaphore for the worker threads of `map_limited`.
+#[derive(Debug)]
+pub struct Semaphore {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+impl Semaphore {
+    pub fn new(permits: usize) -> Self {
+        Self { available: Mutex::new(permits), released: Condvar::new() }
+    }
+
+    /// A function to take a permit if one is free, without waiting.
+    pub fn try_acquire(&self) -> Option<Permit<'_>> {
+        let mut available = self.available.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if *available == 0 {
+            return None;
+        }
+        *available -= 1;
+        Some(Permit { semaphore: self })
+    }
+
+    /// A function to take a permit, waiting for one to be released if none is free.
+    pub fn acquire(&self) -> Permit<'_> {
+        let mut available = self.available.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        while *available == 0 {
+            available = self.released.wait(available).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        *available -= 1;
+        Permit { semaphore: self }
+    }
+}
+
+/// A permit from a `Semaphore`, given back when dropped.
+#[derive(Debug)]
+pub struct Permit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut available = self.semaphore.available.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        *available += 1;
+        self.semaphore.released.notify_one();
+    }
+}
+
+/// How many simulations of each protocol system may run at once, e.g. two for "vm:curve" so the
+/// VM engine doesn't thrash. Protocols without a limit run on every worker.
+#[derive(Debug, Default)]
+pub struct ConcurrencyLimits {
+    semaphores: HashMap<String, Semaphore>,
+}
+
+impl ConcurrencyLimits {
+    /// Args:
+    /// - limits: The most concurrent simulations by protocol system, e.g. {"vm:curve": 2}
+    pub fn new(limits: &HashMap<String, usize>) -> Self {
+        let semaphores: HashMap<String, Semaphore> =
+            limits.iter().map(|(protocol, limit)| (protocol.clone(), Semaphore::new(*limit))).collect();
+        Self { semaphores }
+    }
+
+    /// The semaphore for a protocol system, or None if it's unlimited.
+    pub fn get(&self, protocol: &str) -> Option<&Semaphore> {
+        self.semaphores.get(protocol)
+    }
+}
+
+/// A function to map like `map_bounded`, but with some items limited in how many of them run at
+/// once, e.g. VM pools of one protocol.
+///
+/// A worker that claims an item whose semaphore is exhausted sets it aside and moves on, so
+/// unlimited items keep every worker busy; the set-aside items are taken up again as permits come
+/// back, and waited on once nothing else is left.
+///
+/// Args:
+/// - items: The work, one item per call of `f`
+/// - workers: The most threads to run at once; 0 and 1 both run inline on the calling thread
+/// - limit: The semaphore an item needs a permit from, or None if it's unlimited
+/// - f: The work for one item, called while holding the item's permit
+///
+/// Returns:
+/// - The results of `f`, in the order of `items`
+pub fn map_limited<'s, T, R, L, F>(items: &[T], workers: usize, limit: L, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    L: Fn(&T) -> Option<&'s Semaphore> + Sync,
+    F: Fn(&T) -> R + Sync,
+{
+    let workers: usize = workers.min(items.len());
+    if workers <= 1 {
+        return items.iter().map(&f).collect();
+    }
+    let next: AtomicUsize = AtomicUsize::new(0);
+    let deferred: Mutex<VecDeque<usize>> = Mutex::new(VecDeque::new());
+    // A function to take the first set-aside item whose permit is free, or with `wait`, the first
+    // one at all and its permit once one is released.
+    let take_deferred = |wait: bool| -> Option<(usize, Option<Permit<'_>>)> {
+        let mut queue = deferred.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        for position in 0..queue.len() {
+            if let Some(permit) = limit(&items[queue[position]]).and_then(Semaphore::try_acquire) {
+                return queue.remove(position).map(|i| (i, Some(permit)));
+            }
+        }
+        if !wait {
+            return None;
+        }
+        let i: usize = queue.pop_front()?;
+        // Waited on outside the queue's lock, so other workers can still set items aside.
+        drop(queue);
+        Some((i, limit(&items[i]).map(Semaphore::acquire)))
+    };
+
+    let mut results: Vec<(usize, R)> = thread::scope(|scope| {
+        let handles: Vec<thread::ScopedJoinHandle<'_, Vec<(usize, R)>>> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done: Vec<(usize, R)> = Vec::new();
+                    loop {
+                        let (i, _permit): (usize, Option<Permit<'_>>) = match take_deferred(false) {
+                            Some(claimed) => claimed,
+                            None => {
+                                let i: usize = next.fetch_add(1, Ordering::Relaxed);
+                                match items.get(i) {
+                                    Some(item) => match limit(item) {
+                                        None => (i, None),
+                                        Some(semaphore) => match semaphore.try_acquire() {
+                                            Some(permit) => (i, Some(permit)),
+                                            None => {
+                                                deferred
+                                                    .lock()
+                                                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                                                    .push_back(i);
+                                                continue;
+                                            }
+                                        },
+                                    },
+                                    None => match take_deferred(true) {
+                                        Some(claimed) => claimed,
+                                        None => break,
+                                    },
+                                }
+                            }
+                        };
+                        done.push((i, f(&items[i])));
+                    }
+                    done
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+            .collect()
+    });
+    results.sort_unstable_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Counts the simulations of one protocol in flight, remembering the most seen at once.
+    #[derive(Default)]
+    struct InFlight {
+        now: AtomicUsize,
+        most: AtomicUsize,
+    }
+
+    impl InFlight {
+        fn run<R>(&self, f: impl FnOnce() -> R) -> R {
+            let now: usize = self.now.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most.fetch_max(now, Ordering::SeqCst);
+            let result: R = f();
+            self.now.fetch_sub(1, Ordering::SeqCst);
+            result
+        }
+    }
+
+    fn protocol(i: usize) -> &'static str {
+        ["vm:curve", "uniswap_v2", "vm:balancer_v2", "uniswap_v3"][i % 4]
+    }
+
+    #[test]
+    fn keeps_the_input_order_inline_and_on_workers() {
+        let items: Vec<u64> = (0..100).collect();
+        for workers in [0, 1, 3, 16, 200] {
+            let squares: Vec<u64> = map_bounded(&items, workers, |n: &u64| {
+                // Later items finish first, so the order has to be restored.
+                thread::sleep(Duration::from_micros(100 - *n));
+                n * n
+            });
+            assert_eq!(squares, items.iter().map(|n| n * n).collect::<Vec<u64>>(), "{} workers", workers);
+        }
+    }
+
+    #[test]
+    fn limits_how_many_of_each_protocol_run_at_once() {
+        let limits: ConcurrencyLimits =
+            ConcurrencyLimits::new(&HashMap::from([("vm:curve".to_string(), 2), ("vm:balancer_v2".to_string(), 1)]));
+        let in_flight: HashMap<&str, InFlight> = (0..4).map(|i| (protocol(i), InFlight::default())).collect();
+        let everything: InFlight = InFlight::default();
+        let items: Vec<usize> = (0..64).collect();
+
+        let outputs: Vec<String> = map_limited(
+            &items,
+            8,
+            |i: &usize| limits.get(protocol(*i)),
+            |i: &usize| {
+                everything.run(|| {
+                    in_flight[protocol(*i)].run(|| {
+                        thread::sleep(Duration::from_millis(2));
+                        format!("{}:{}", protocol(*i), i)
+                    })
+                })
+            },
+        );
+
+        assert_eq!(outputs, items.iter().map(|i| format!("{}:{}", protocol(*i), i)).collect::<Vec<String>>());
+        assert!(in_flight["vm:curve"].most.load(Ordering::SeqCst) <= 2);
+        assert_eq!(in_flight["vm:balancer_v2"].most.load(Ordering::SeqCst), 1);
+        // Unlimited protocols keep the other workers busy meanwhile.
+        assert!(everything.most.load(Ordering::SeqCst) > 3);
+    }
+
+    #[test]
+    fn finishes_when_every_item_waits_on_one_permit() {
+        let semaphore: Semaphore = Semaphore::new(1);
+        let in_flight: InFlight = InFlight::default();
+        let items: Vec<usize> = (0..20).collect();
+        let outputs: Vec<usize> =
+            map_limited(&items, 4, |_: &usize| Some(&semaphore), |i: &usize| in_flight.run(|| i * 10));
+        assert_eq!(outputs, items.iter().map(|i| i * 10).collect::<Vec<usize>>());
+        assert_eq!(in_flight.most.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,124 @@
+use std::{collections::{BTreeMap, HashMap}, fmt};
+use serde::Serialize;
+use tycho_simulation::{
+    evm::protocol::u256_num::u256_to_f64,
+    models::Token,
+    protocol::state::ProtocolSim,
+};
+use crate::{aggregate::best_spot_price, error::guard_simulation, estimate::estimate_depth_against_reference};
+
+/// A token as it was resolved, so a wrong symbol match is caught before a long run.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedToken {
+    pub symbol: String,
+    pub address: String,
+    pub decimals: usize,
+}
+
+impl From<&Token> for ResolvedToken {
+    fn from(token: &Token) -> Self {
+        Self {
+            symbol: token.symbol.clone(),
+            address: token.address.to_string(),
+            decimals: token.decimals,
+        }
+    }
+}
+
+/// What a pair looks like on the first block, shown before committing to a long run.
+#[derive(Debug, Clone, Serialize)]
+pub struct PairPreflight {
+    pub pair: String,
+    pub token_in: ResolvedToken,
+    pub token_out: ResolvedToken,
+    /// Matching pools per protocol system.
+    pub pools_by_protocol: BTreeMap<String, usize>,
+    /// Best spot price of token_in in token_out among the pools. None if there are none or it failed.
+    pub spot_price: Option<f64>,
+    pub target_slippage: f64,
+    /// Two-probe depth estimates summed over pools, in whole token_in. None if no pool could be
+    /// estimated.
+    pub approximate_depth: Option<f64>,
+}
+
+impl fmt::Display for PairPreflight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Pre-flight {}", self.pair)?;
+        for (side, token) in [("token_in", &self.token_in), ("token_out", &self.token_out)] {
+            writeln!(f, "   {:<10} {} {} ({} decimals)", side, token.symbol, token.address, token.decimals)?;
+        }
+        let total: usize = self.pools_by_protocol.values().sum();
+        let by_protocol: Vec<String> = self
+            .pools_by_protocol
+            .iter()
+            .map(|(protocol, count)| format!("{}: {}", protocol, count))
+            .collect();
+        writeln!(f, "   {:<10} {} ({})", "pools", total, by_protocol.join(", "))?;
+        match self.spot_price {
+            Some(spot) => writeln!(
+                f,
+                "   {:<10} {:.6} {} per {}",
+                "spot", spot, self.token_out.symbol, self.token_in.symbol
+            )?,
+            None => writeln!(f, "   {:<10} n/a", "spot")?,
+        }
+        match self.approximate_depth {
+            Some(depth) => write!(
+                f,
+                "   {:<10} ~{:.4} {} at {}% (two-probe estimate, summed over pools)",
+                "depth",
+                depth,
+                self.token_in.symbol,
+                self.target_slippage * 100.0
+            ),
+            None => write!(f, "   {:<10} n/a", "depth"),
+        }
+    }
+}
+
+/// A function to summarize a pair before a long run: resolved tokens, matching pools, spot price
+/// and an instant depth estimate.
+///
+/// Args:
+/// - pair: The pair's label, e.g. "WETH/USDC"
+/// - pools: (id, state) for every pool of the pair
+/// - protocols: Protocol system by pool id
+/// - token_in: The token being sold
+/// - token_out: The token being bought
+/// - target_slippage: The slippage the depth is estimated at, as a decimal
+///
+/// Returns:
+/// - The summary; pools that fail to price or estimate are counted but left out of the numbers
+pub fn preflight_pair(
+    pair: &str,
+    pools: &[(String, &dyn ProtocolSim)],
+    protocols: &HashMap<String, String>,
+    token_in: &Token,
+    token_out: &Token,
+    target_slippage: f64,
+) -> PairPreflight {
+    let mut pools_by_protocol: BTreeMap<String, usize> = BTreeMap::new();
+    for (id, _) in pools.iter() {
+        let protocol: String = protocols.get(id).cloned().unwrap_or_else(|| "unknown".to_string());
+        *pools_by_protocol.entry(protocol).or_default() += 1;
+    }
+    let spot_price: Option<f64> = best_spot_price(pools, token_in, token_out).ok();
+    let estimates: Vec<f64> = pools
+        .iter()
+        .filter_map(|(_, state)| {
+            let reference: f64 = guard_simulation(|| state.spot_price(token_in, token_out)).ok()?;
+            estimate_depth_against_reference(target_slippage, *state, token_in, token_out, reference).ok()
+        })
+        .map(|estimate| u256_to_f64(estimate.amount_in) / 10f64.powi(token_in.decimals as i32))
+        .collect();
+
+    PairPreflight {
+        pair: pair.to_string(),
+        token_in: token_in.into(),
+        token_out: token_out.into(),
+        pools_by_protocol,
+        spot_price,
+        target_slippage,
+        approximate_depth: (!estimates.is_empty()).then(|| estimates.iter().sum()),
+    }
+}
@@ -0,0 +1,161 @@
+use std::fmt;
+use num_bigint::BigUint;
+use serde::Serialize;
+use tycho_simulation::{models::Token, protocol::state::ProtocolSim};
+use crate::error::guard_simulation;
+
+/// Execution price is allowed to improve by this much between probes before we call it a
+/// violation. Integer truncation on small probes (especially low-decimal tokens) makes the
+/// smallest probe look slightly worse than it really is.
+pub const DEFAULT_TOLERANCE_BPS: u32 = 1;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Probe {
+    pub amount_in: String,
+    pub amount_out: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Violation {
+    /// A larger input produced less output.
+    OutputDecreased { smaller_in: String, larger_in: String },
+    /// A larger input got a better execution price, i.e. slippage went down as size went up.
+    SlippageDecreased { smaller_in: String, larger_in: String },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::OutputDecreased { smaller_in, larger_in } => {
+                write!(f, "output decreased from amount_in {} to {}", smaller_in, larger_in)
+            }
+            Violation::SlippageDecreased { smaller_in, larger_in } => {
+                write!(f, "slippage decreased from amount_in {} to {}", smaller_in, larger_in)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "verdict", rename_all = "snake_case")]
+pub enum Verdict {
+    Monotone,
+    NonMonotone { violation: Violation },
+    /// A probe failed to simulate, so we have no evidence either way.
+    Inconclusive { error: String },
+}
+
+/// The outcome of probing one pool, with the raw probes kept as evidence.
+#[derive(Debug, Clone, Serialize)]
+pub struct MonotonicityCheck {
+    pub probes: Vec<Probe>,
+    #[serde(flatten)]
+    pub verdict: Verdict,
+}
+
+/// Renders the verdict followed by the raw probes, e.g. for the run summary.
+impl fmt::Display for MonotonicityCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.verdict {
+            Verdict::Monotone => write!(f, "monotone")?,
+            Verdict::NonMonotone { violation } => write!(f, "{}", violation)?,
+            Verdict::Inconclusive { error } => write!(f, "inconclusive, {}", error)?,
+        }
+        let probes: Vec<String> = self
+            .probes
+            .iter()
+            .map(|p| format!("{} -> {}", p.amount_in, p.amount_out))
+            .collect();
+        write!(f, " (probes: {})", probes.join(", "))
+    }
+}
+
+impl MonotonicityCheck {
+    /// Only pools with positive evidence of a broken simulation are unreliable.
+    pub fn is_reliable(&self) -> bool {
+        !matches!(self.verdict, Verdict::NonMonotone { .. })
+    }
+}
+
+/// A function to pick three increasing probe sizes: 0.01, 1, and 100 whole tokens.
+pub fn default_probe_amounts(token_in: &Token) -> [BigUint; 3] {
+    let one: BigUint = BigUint::from(10u32).pow(token_in.decimals as u32);
+    let small: BigUint = (&one / 100u32).max(BigUint::from(1u32));
+    let large: BigUint = &one * 100u32;
+    [small, one, large]
+}
+
+/// A function to check that a pool's simulated output and slippage are monotone in the input.
+///
+/// Args:
+/// - state: The pool state to probe
+/// - token_in: The token being sold
+/// - token_out: The token being bought
+/// - amounts: Probe sizes in base units, strictly increasing
+/// - tolerance_bps: How much execution price may improve between probes before it's a violation
+///
+/// Returns:
+/// - The probes and a verdict
+pub fn probe_monotonicity(
+    state: &dyn ProtocolSim,
+    token_in: &Token,
+    token_out: &Token,
+    amounts: &[BigUint],
+    tolerance_bps: u32,
+) -> MonotonicityCheck {
+    let mut outputs: Vec<(BigUint, BigUint)> = Vec::with_capacity(amounts.len());
+    for amount_in in amounts.iter() {
+        match guard_simulation(|| state.get_amount_out(amount_in.clone(), token_in, token_out)) {
+            Ok(result) => outputs.push((amount_in.clone(), result.amount)),
+            Err(e) => {
+                return MonotonicityCheck {
+                    probes: to_probes(&outputs),
+                    verdict: Verdict::Inconclusive {
+                        error: format!("amount_in {}: {}", amount_in, e),
+                    },
+                };
+            }
+        }
+    }
+
+    let bps: BigUint = BigUint::from(10_000u32);
+    let bps_with_tolerance: BigUint = BigUint::from(10_000u32 + tolerance_bps);
+    let mut verdict: Verdict = Verdict::Monotone;
+    for window in outputs.windows(2) {
+        let (small_in, small_out) = &window[0];
+        let (large_in, large_out) = &window[1];
+        if large_out < small_out {
+            verdict = Verdict::NonMonotone {
+                violation: Violation::OutputDecreased {
+                    smaller_in: small_in.to_string(),
+                    larger_in: large_in.to_string(),
+                },
+            };
+            break;
+        }
+        // Execution price must not improve with size:
+        // large_out / large_in <= small_out / small_in * (1 + tolerance)
+        if large_out * small_in * &bps > small_out * large_in * &bps_with_tolerance {
+            verdict = Verdict::NonMonotone {
+                violation: Violation::SlippageDecreased {
+                    smaller_in: small_in.to_string(),
+                    larger_in: large_in.to_string(),
+                },
+            };
+            break;
+        }
+    }
+
+    MonotonicityCheck { probes: to_probes(&outputs), verdict }
+}
+
+fn to_probes(outputs: &[(BigUint, BigUint)]) -> Vec<Probe> {
+    outputs
+        .iter()
+        .map(|(amount_in, amount_out)| Probe {
+            amount_in: amount_in.to_string(),
+            amount_out: amount_out.to_string(),
+        })
+        .collect()
+}
@@ -0,0 +1,82 @@
+use std::{fmt, str::FromStr};
+
+/// Which protocol systems to keep, written as a comma-separated list, e.g.
+/// `uniswap_v3,uniswap_v4`. A system prefixed with `!` is left out instead, and a filter of only
+/// exclusions keeps everything else, e.g. `!vm:curve`. An empty filter keeps every protocol.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProtocolFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl ProtocolFilter {
+    /// A filter keeping `include` (or everything, if it's empty) except `exclude`, as
+    /// `--protocols` and `--exclude-protocols` give them.
+    pub fn new(include: impl IntoIterator<Item = String>, exclude: impl IntoIterator<Item = String>) -> Self {
+        let clean = |protocols: Vec<String>| -> Vec<String> {
+            protocols.into_iter().map(|p| p.trim().to_string()).filter(|p| !p.is_empty()).collect()
+        };
+        Self { include: clean(include.into_iter().collect()), exclude: clean(exclude.into_iter().collect()) }
+    }
+
+    /// A function to check whether a pool of this protocol system passes the filter.
+    pub fn matches(&self, protocol: &str) -> bool {
+        let included: bool = self.include.is_empty() || self.include.iter().any(|p| p == protocol);
+        included && !self.exclude.iter().any(|p| p == protocol)
+    }
+
+    /// True if the filter keeps every protocol.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+}
+
+/// A function to build the filter `--protocols` and `--exclude-protocols` describe, checking that
+/// each names a protocol system once.
+///
+/// Args:
+/// - include: Protocol systems to keep, e.g. ["uniswap_v3", "uniswap_v4"]; empty keeps every one
+/// - exclude: Protocol systems to leave out, e.g. ["vm:curve"]
+///
+/// Returns:
+/// - The filter, or why the lists don't make one: an empty or `!`-prefixed name, or a system
+///   that's both kept and left out
+pub fn protocol_filter(include: &[String], exclude: &[String]) -> Result<ProtocolFilter, String> {
+    for protocol in include.iter().chain(exclude.iter()) {
+        if protocol.trim().is_empty() {
+            return Err("a protocol system can't be empty".to_string());
+        }
+        if protocol.starts_with('!') {
+            return Err(format!("\"{}\": list protocols to leave out with --exclude-protocols instead of !", protocol));
+        }
+    }
+    if let Some(protocol) = include.iter().find(|p| exclude.contains(p)) {
+        return Err(format!("{} is both included and excluded", protocol));
+    }
+    Ok(ProtocolFilter::new(include.to_vec(), exclude.to_vec()))
+}
+
+impl FromStr for ProtocolFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut include: Vec<String> = Vec::new();
+        let mut exclude: Vec<String> = Vec::new();
+        for protocol in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            match protocol.strip_prefix('!') {
+                Some("") => return Err(format!("protocol filter \"{}\" has a ! without a protocol", s)),
+                Some(excluded) => exclude.push(excluded.to_string()),
+                None => include.push(protocol.to_string()),
+            }
+        }
+        Ok(ProtocolFilter { include, exclude })
+    }
+}
+
+impl fmt::Display for ProtocolFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let filter: Vec<String> =
+            self.include.iter().cloned().chain(self.exclude.iter().map(|p| format!("!{}", p))).collect();
+        write!(f, "{}", filter.join(","))
+    }
+}
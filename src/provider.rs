@@ -0,0 +1,111 @@
+use alloy_primitives::U256;
+use num_bigint::BigUint;
+use tycho_simulation::{models::Token, protocol::state::ProtocolSim};
+use crate::{binary_search::bracket_cap, error::{guard_simulation, DepthError}};
+
+/// What the depth search needs from a pool: a quote for selling an exact amount, and the marginal
+/// price slippage is measured from. Tycho's `ProtocolSim` states provide both; implement it to run
+/// the same search over another source of quotes, e.g. an RFQ endpoint or a constant-product mock
+/// in tests.
+pub trait AmountOutProvider {
+    /// A function to quote selling an exact amount.
+    ///
+    /// Args:
+    /// - amount_in: The amount of token_in to sell, in base units
+    /// - token_in: The token being sold
+    /// - token_out: The token being bought
+    ///
+    /// Returns:
+    /// - What the sale pays out, in token_out base units
+    fn amount_out(&self, amount_in: BigUint, token_in: &Token, token_out: &Token) -> Result<BigUint, DepthError>;
+
+    /// A function to get the price of one whole token_in in token_out before fees and impact, as
+    /// `ProtocolSim::spot_price` gives it.
+    fn spot(&self, token_in: &Token, token_out: &Token) -> Result<f64, DepthError>;
+
+    /// A function to quote a sale along with the gas it costs. Providers that can't tell report
+    /// zero gas.
+    ///
+    /// Returns:
+    /// - (amount_out, gas)
+    fn quote(&self, amount_in: BigUint, token_in: &Token, token_out: &Token) -> Result<(BigUint, BigUint), DepthError> {
+        Ok((self.amount_out(amount_in, token_in, token_out)?, BigUint::from(0u32)))
+    }
+
+    /// A function to get the largest input worth bracketing up to, see `binary_search::bracket_cap`.
+    ///
+    /// Returns:
+    /// - The cap in token_in base units. None by default, so only `MAX_BRACKETING_DOUBLINGS` stops
+    ///   the bracketing.
+    fn max_in(&self, _token_in: &Token, _token_out: &Token, _reference_price: f64, _tvl_multiple: f64) -> Option<U256> {
+        None
+    }
+}
+
+impl AmountOutProvider for dyn ProtocolSim {
+    fn amount_out(&self, amount_in: BigUint, token_in: &Token, token_out: &Token) -> Result<BigUint, DepthError> {
+        Ok(guard_simulation(|| self.get_amount_out(amount_in, token_in, token_out))?.amount)
+    }
+
+    fn spot(&self, token_in: &Token, token_out: &Token) -> Result<f64, DepthError> {
+        guard_simulation(|| self.spot_price(token_in, token_out))
+    }
+
+    fn quote(&self, amount_in: BigUint, token_in: &Token, token_out: &Token) -> Result<(BigUint, BigUint), DepthError> {
+        let simulated = guard_simulation(|| self.get_amount_out(amount_in, token_in, token_out))?;
+        Ok((simulated.amount, simulated.gas))
+    }
+
+    fn max_in(&self, token_in: &Token, token_out: &Token, reference_price: f64, tvl_multiple: f64) -> Option<U256> {
+        bracket_cap(self, token_in, token_out, reference_price, tvl_multiple)
+    }
+}
+
+/// Where a search gets its quotes: a Tycho pool state, or any other `AmountOutProvider`.
+#[derive(Clone, Copy)]
+pub enum Pool<'a> {
+    Tycho(&'a dyn ProtocolSim),
+    Provider(&'a dyn AmountOutProvider),
+}
+
+impl AmountOutProvider for Pool<'_> {
+    fn amount_out(&self, amount_in: BigUint, token_in: &Token, token_out: &Token) -> Result<BigUint, DepthError> {
+        match self {
+            Pool::Tycho(state) => state.amount_out(amount_in, token_in, token_out),
+            Pool::Provider(provider) => provider.amount_out(amount_in, token_in, token_out),
+        }
+    }
+
+    fn spot(&self, token_in: &Token, token_out: &Token) -> Result<f64, DepthError> {
+        match self {
+            Pool::Tycho(state) => state.spot(token_in, token_out),
+            Pool::Provider(provider) => provider.spot(token_in, token_out),
+        }
+    }
+
+    fn quote(&self, amount_in: BigUint, token_in: &Token, token_out: &Token) -> Result<(BigUint, BigUint), DepthError> {
+        match self {
+            Pool::Tycho(state) => state.quote(amount_in, token_in, token_out),
+            Pool::Provider(provider) => provider.quote(amount_in, token_in, token_out),
+        }
+    }
+
+    fn max_in(&self, token_in: &Token, token_out: &Token, reference_price: f64, tvl_multiple: f64) -> Option<U256> {
+        match self {
+            Pool::Tycho(state) => state.max_in(token_in, token_out, reference_price, tvl_multiple),
+            Pool::Provider(provider) => provider.max_in(token_in, token_out, reference_price, tvl_multiple),
+        }
+    }
+}
+
+impl<'a> From<&'a dyn ProtocolSim> for Pool<'a> {
+    fn from(state: &'a dyn ProtocolSim) -> Self {
+        Pool::Tycho(state)
+    }
+}
+
+impl<'a> From<&'a dyn AmountOutProvider> for Pool<'a> {
+    fn from(provider: &'a dyn AmountOutProvider) -> Self {
+        Pool::Provider(provider)
+    }
+}
@@ -0,0 +1,189 @@
+use std::fmt;
+use alloy_primitives::U256;
+use serde::Serialize;
+use tycho_simulation::{
+    evm::protocol::{
+        u256_num::{u256_to_biguint, u256_to_f64},
+        uniswap_v3::state::UniswapV3State,
+        uniswap_v4::state::UniswapV4State,
+    },
+    models::Token,
+    protocol::state::ProtocolSim,
+};
+use crate::{
+    amount::one_token,
+    error::{guard_simulation, DepthError},
+};
+
+/// Price bands around spot for the range report: ±0.1%, ±0.5%, ±1%, ±2%.
+pub const DEFAULT_PRICE_BANDS: [f64; 4] = [0.001, 0.005, 0.01, 0.02];
+
+/// Searches stop once the bracket is within this fraction of the amount.
+const RELATIVE_PRECISION: u64 = 10_000;
+
+/// Liquidity between spot and one band edge on each side, in whole quote tokens.
+#[derive(Debug, Clone, Serialize)]
+pub struct PriceBand {
+    /// Distance from spot, as a decimal, e.g. 0.01 for ±1%.
+    pub band: f64,
+    /// Quote it takes to push the price of base up by `band`. None if the pool runs out first.
+    pub above: Option<f64>,
+    /// Base it takes to push the price of base down by `band`, valued at spot.
+    pub below: Option<f64>,
+}
+
+/// How a concentrated-liquidity pool's liquidity is distributed around spot, as a structural view
+/// to go with the behavioral depth number.
+#[derive(Debug, Clone, Serialize)]
+pub struct RangeReport {
+    pub base: String,
+    pub quote: String,
+    pub spot_price: f64,
+    /// Cumulative from spot, in ascending band order.
+    pub bands: Vec<PriceBand>,
+}
+
+/// Renders cumulative liquidity per band and what each band adds over the previous one.
+impl fmt::Display for RangeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |v: Option<f64>| v.map_or_else(|| "n/a".to_string(), |v| format!("{:.2}", v));
+        writeln!(
+            f,
+            "   liquidity around spot {:.6} {} per {} (in {}):",
+            self.spot_price, self.quote, self.base, self.quote
+        )?;
+        writeln!(f, "   {:>8}  {:>18}  {:>18}  {:>18}  {:>18}", "band", "below", "below bucket", "above", "above bucket")?;
+        let mut previous: Option<&PriceBand> = None;
+        for band in self.bands.iter() {
+            let bucket = |now: Option<f64>, before: Option<f64>| match (now, before) {
+                (Some(now), Some(before)) => Some(now - before),
+                (Some(now), None) if previous.is_none() => Some(now),
+                _ => None,
+            };
+            writeln!(
+                f,
+                "   +/-{:>4.1}%  {:>18}  {:>18}  {:>18}  {:>18}",
+                band.band * 100.0,
+                show(band.below),
+                show(bucket(band.below, previous.and_then(|p| p.below))),
+                show(band.above),
+                show(bucket(band.above, previous.and_then(|p| p.above))),
+            )?;
+            previous = Some(band);
+        }
+        Ok(())
+    }
+}
+
+/// A function to check whether a state is a concentrated-liquidity pool (Uniswap v3/v4), the only
+/// kind the range report means something for.
+pub fn is_concentrated_liquidity(state: &dyn ProtocolSim) -> bool {
+    state.as_any().is::<UniswapV3State>() || state.as_any().is::<UniswapV4State>()
+}
+
+/// A function to bucket a pool's liquidity by price range around spot.
+///
+/// Tick data isn't exposed by the pool states, so it's read through the simulator instead: the
+/// liquidity within a band is what it takes to push the post-swap spot price to the band edge,
+/// which is exactly what the ticks in that range hold.
+///
+/// Args:
+/// - state: The pool state
+/// - base: The token whose price is being moved
+/// - quote: The token liquidity is valued in
+/// - bands: Distances from spot, as decimals, e.g. `DEFAULT_PRICE_BANDS`
+///
+/// Returns:
+/// - Cumulative liquidity from spot to each band edge, on both sides
+pub fn liquidity_by_price_range(
+    state: &dyn ProtocolSim,
+    base: &Token,
+    quote: &Token,
+    bands: &[f64],
+) -> Result<RangeReport, DepthError> {
+    let spot: f64 = spot_price(state, base, quote)?;
+    let mut bands: Vec<f64> = bands.to_vec();
+    bands.sort_by(|a, b| a.total_cmp(b));
+
+    let mut report: RangeReport = RangeReport {
+        base: base.symbol.clone(),
+        quote: quote.symbol.clone(),
+        spot_price: spot,
+        bands: Vec::with_capacity(bands.len()),
+    };
+    // Wider bands need at least as much as narrower ones, so each search starts from the last.
+    let mut below_from: U256 = U256::ZERO;
+    let mut above_from: U256 = U256::ZERO;
+    for band in bands.into_iter() {
+        // Selling base pushes its price down; selling quote pushes the price of quote down, i.e.
+        // the price of base up by `band`.
+        let below: Option<U256> = amount_to_push_price(state, base, quote, spot * (1.0 - band), below_from)?;
+        let above: Option<U256> = amount_to_push_price(state, quote, base, 1.0 / (spot * (1.0 + band)), above_from)?;
+        below_from = below.unwrap_or(below_from);
+        above_from = above.unwrap_or(above_from);
+        report.bands.push(PriceBand {
+            band,
+            below: below.map(|amount| whole(amount, base) * spot),
+            above: above.map(|amount| whole(amount, quote)),
+        });
+    }
+    Ok(report)
+}
+
+fn spot_price(state: &dyn ProtocolSim, base: &Token, quote: &Token) -> Result<f64, DepthError> {
+    guard_simulation(|| state.spot_price(base, quote))
+}
+
+fn whole(amount: U256, token: &Token) -> f64 {
+    u256_to_f64(amount) / 10f64.powi(token.decimals as i32)
+}
+
+/// A function to find the smallest amount of `sell` that leaves the pool's spot price of `sell`
+/// in `buy` at or below `threshold`.
+///
+/// Returns:
+/// - The amount, or None if the pool can't be pushed that far (the swap fails first)
+fn amount_to_push_price(
+    state: &dyn ProtocolSim,
+    sell: &Token,
+    buy: &Token,
+    threshold: f64,
+    start_from: U256,
+) -> Result<Option<U256>, DepthError> {
+    // Some(true) if the swap reaches the threshold, None if it fails.
+    let reaches = |amount_in: U256| -> Result<Option<bool>, DepthError> {
+        match guard_simulation(|| state.get_amount_out(u256_to_biguint(amount_in), sell, buy)) {
+            Ok(result) => Ok(Some(spot_price(result.new_state.as_ref(), sell, buy)? <= threshold)),
+            Err(_) => Ok(None),
+        }
+    };
+
+    // Double until the threshold is crossed.
+    let mut left: U256 = start_from;
+    let mut right: U256 = if start_from.is_zero() {
+        one_token(sell.decimals).ok_or(DepthError::UnsupportedDecimals(sell.decimals))?
+    } else {
+        start_from
+    };
+    loop {
+        match reaches(right)? {
+            Some(true) => break,
+            Some(false) => {
+                left = right;
+                right = right.checked_mul(U256::from(2u64)).ok_or(DepthError::Overflow)?;
+            }
+            None => return Ok(None),
+        }
+    }
+
+    // Then bisect to a relative precision; the exact base unit doesn't matter for a report.
+    while right - left > (right / U256::from(RELATIVE_PRECISION)).max(U256::from(1u64)) {
+        let mid: U256 = (left + right) / U256::from(2u64);
+        match reaches(mid)? {
+            Some(true) => right = mid,
+            Some(false) => left = mid,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(right))
+}
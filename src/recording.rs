@@ -0,0 +1,239 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::{Path, PathBuf},
+};
+use alloy_primitives::U256;
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use tycho_common::{models::Chain, Bytes};
+use tycho_simulation::{
+    evm::protocol::uniswap_v2::state::UniswapV2State,
+    models::Token,
+    protocol::{
+        models::{BlockUpdate, ProtocolComponent},
+        state::ProtocolSim,
+    },
+};
+
+/// A pool state as recorded. States aren't serializable in general, so only state types whose
+/// fields are public are captured, exactly; the rest are listed in `RecordedBlock::unrecorded`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RecordedState {
+    /// A Uniswap v2-style pool's reserves, in base units and token address order.
+    UniswapV2 { reserve0: String, reserve1: String },
+}
+
+impl RecordedState {
+    /// A function to capture a state, if its type can be.
+    pub fn capture(state: &dyn ProtocolSim) -> Option<Self> {
+        let v2: &UniswapV2State = state.as_any().downcast_ref::<UniswapV2State>()?;
+        Some(RecordedState::UniswapV2 { reserve0: v2.reserve0.to_string(), reserve1: v2.reserve1.to_string() })
+    }
+
+    /// A function to rebuild the state for simulation.
+    pub fn restore(&self) -> Result<Box<dyn ProtocolSim>, String> {
+        match self {
+            RecordedState::UniswapV2 { reserve0, reserve1 } => {
+                let reserve = |value: &str| value.parse::<U256>().map_err(|e| format!("reserve \"{}\": {}", value, e));
+                Ok(Box::new(UniswapV2State::new(reserve(reserve0)?, reserve(reserve1)?)))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedToken {
+    pub address: String,
+    pub symbol: String,
+    pub decimals: usize,
+}
+
+/// A pool as it was announced, with enough of its component to track and label it again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedPool {
+    pub id: String,
+    pub protocol_system: String,
+    pub protocol_type_name: String,
+    pub tokens: Vec<RecordedToken>,
+}
+
+/// One block of a stream as written by `--record`: the pools it announced and removed, and the
+/// states it updated.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedBlock {
+    pub block_number: u64,
+    /// True for a block that lists every pool, as the first after the stream (re)connects.
+    pub snapshot: bool,
+    /// The block's time, in Unix seconds; recordings from before it was kept have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    pub new_pairs: Vec<RecordedPool>,
+    pub removed_pairs: Vec<String>,
+    pub states: BTreeMap<String, RecordedState>,
+    /// Pools whose state changed in the block but whose state type can't be recorded. On replay
+    /// they have no state, so they're never measured.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unrecorded: Vec<String>,
+}
+
+impl RecordedBlock {
+    /// A function to capture a stream block for writing.
+    ///
+    /// Args:
+    /// - update: The block
+    /// - snapshot: Whether it lists every pool
+    /// - timestamp: The block's time, in Unix seconds
+    pub fn capture(update: &BlockUpdate, snapshot: bool, timestamp: u64) -> Self {
+        let mut states: BTreeMap<String, RecordedState> = BTreeMap::new();
+        let mut unrecorded: Vec<String> = Vec::new();
+        for (id, state) in update.states.iter() {
+            match RecordedState::capture(state.as_ref()) {
+                Some(recorded) => {
+                    states.insert(id.clone(), recorded);
+                }
+                None => unrecorded.push(id.clone()),
+            }
+        }
+        unrecorded.sort_unstable();
+        let mut new_pairs: Vec<RecordedPool> = update
+            .new_pairs
+            .iter()
+            .map(|(id, component)| RecordedPool {
+                id: id.clone(),
+                protocol_system: component.protocol_system.clone(),
+                protocol_type_name: component.protocol_type_name.clone(),
+                tokens: component
+                    .tokens
+                    .iter()
+                    .map(|token| RecordedToken {
+                        address: token.address.to_string(),
+                        symbol: token.symbol.clone(),
+                        decimals: token.decimals,
+                    })
+                    .collect(),
+            })
+            .collect();
+        new_pairs.sort_unstable_by(|a, b| a.id.cmp(&b.id));
+        let mut removed_pairs: Vec<String> = update.removed_pairs.keys().cloned().collect();
+        removed_pairs.sort_unstable();
+        Self {
+            block_number: update.block_number,
+            snapshot,
+            timestamp: Some(timestamp),
+            new_pairs,
+            removed_pairs,
+            states,
+            unrecorded,
+        }
+    }
+
+    /// A function to turn the record back into a stream block.
+    ///
+    /// Args:
+    /// - chain: The chain the recording is from, for the rebuilt components
+    pub fn into_update(self, chain: Chain) -> Result<BlockUpdate, String> {
+        let states: HashMap<String, Box<dyn ProtocolSim>> = self
+            .states
+            .iter()
+            .map(|(id, state)| Ok((id.clone(), state.restore().map_err(|e| format!("{}: {}", id, e))?)))
+            .collect::<Result<_, String>>()?;
+        let new_pairs: HashMap<String, ProtocolComponent> = self
+            .new_pairs
+            .into_iter()
+            .map(|pool| {
+                let component: ProtocolComponent = pool.component(chain)?;
+                Ok((pool.id, component))
+            })
+            .collect::<Result<_, String>>()?;
+        // Only the ids of removed pools are used.
+        let removed_pairs: HashMap<String, ProtocolComponent> = self
+            .removed_pairs
+            .into_iter()
+            .map(|id| {
+                let pool: RecordedPool = RecordedPool {
+                    id: id.clone(),
+                    protocol_system: String::new(),
+                    protocol_type_name: String::new(),
+                    tokens: Vec::new(),
+                };
+                Ok((id, pool.component(chain)?))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(BlockUpdate { block_number: self.block_number, states, new_pairs, removed_pairs })
+    }
+
+    /// Every token the block's pools trade, by address.
+    pub fn tokens(&self) -> impl Iterator<Item = Token> + '_ {
+        self.new_pairs.iter().flat_map(|pool| pool.tokens.iter()).map(RecordedToken::token)
+    }
+}
+
+impl RecordedToken {
+    pub fn token(&self) -> Token {
+        Token::new(&self.address, self.decimals, &self.symbol, BigUint::from(0u32))
+    }
+}
+
+impl RecordedPool {
+    fn component(&self, chain: Chain) -> Result<ProtocolComponent, String> {
+        let id: Bytes = self.id.parse().map_err(|_| format!("pool id \"{}\" isn't hex", self.id))?;
+        Ok(ProtocolComponent {
+            address: id.clone(),
+            id,
+            tokens: self.tokens.iter().map(RecordedToken::token).collect(),
+            protocol_system: self.protocol_system.clone(),
+            protocol_type_name: self.protocol_type_name.clone(),
+            chain,
+            contract_ids: Vec::new(),
+            static_attributes: HashMap::new(),
+            creation_tx: Bytes::from(Vec::new()),
+        })
+    }
+}
+
+/// A function to get where a chain's blocks are recorded: one JSON file per block under a
+/// directory named after the chain, e.g. `recordings/ethereum/000021000000.json`.
+pub fn block_path(dir: &Path, chain: &str, block_number: u64) -> PathBuf {
+    dir.join(chain).join(format!("{:012}.json", block_number))
+}
+
+/// A function to write one block of a recording, creating the chain's directory if needed.
+pub fn write_block(dir: &Path, chain: &str, block: &RecordedBlock) -> io::Result<()> {
+    let path: PathBuf = block_path(dir, chain, block.block_number);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_vec(block)?)
+}
+
+/// A function to list a chain's recorded blocks up to `to_block`, oldest first.
+///
+/// Returns:
+/// - (block number, path) for every block file, or an error if the chain has none recorded
+pub fn recorded_blocks(dir: &Path, chain: &str, to_block: Option<u64>) -> io::Result<Vec<(u64, PathBuf)>> {
+    let chain_dir: PathBuf = dir.join(chain);
+    let mut blocks: Vec<(u64, PathBuf)> = Vec::new();
+    for entry in fs::read_dir(&chain_dir)? {
+        let path: PathBuf = entry?.path();
+        let block_number: Option<u64> = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".json"))
+            .and_then(|number| number.parse().ok());
+        if let Some(block_number) = block_number.filter(|block| to_block.is_none_or(|to| *block <= to)) {
+            blocks.push((block_number, path));
+        }
+    }
+    if blocks.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("no recorded blocks in {}", chain_dir.display())));
+    }
+    blocks.sort_unstable_by_key(|(block_number, _)| *block_number);
+    Ok(blocks)
+}
+
+/// A function to read one recorded block.
+pub fn read_block(path: &Path) -> io::Result<RecordedBlock> {
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
@@ -0,0 +1,952 @@
+use std::{collections::BTreeMap, fmt};
+use alloy_primitives::U256;
+use serde::Serialize;
+use tycho_simulation::models::Token;
+use crate::{
+    aggregate::{AggregateDepth, PoolAllocation},
+    amount::format_amount,
+    attribution::SlippageAttribution,
+    binary_search::SearchResult,
+    divergence::Divergence,
+    error::DepthError,
+    estimate::DepthEstimate,
+    labels::Labels,
+    native::NativePrice,
+    stability::DepthStability,
+};
+
+/// Which way an order trades a pair's base token. For "WETH/USDC", base is WETH and quote is USDC:
+/// selling 1000 WETH for USDC is a sell, spending USDC on WETH is a buy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TradeAction {
+    Sell,
+    Buy,
+}
+
+impl fmt::Display for TradeAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TradeAction::Sell => write!(f, "sell"),
+            TradeAction::Buy => write!(f, "buy"),
+        }
+    }
+}
+
+/// One direction of a pair in the pair's own terms, used to turn token_in/token_out search results
+/// into base/quote records.
+#[derive(Debug, Clone, Copy)]
+pub struct Leg<'a> {
+    pub action: TradeAction,
+    pub base: &'a Token,
+    pub quote: &'a Token,
+    /// Price of one base in quote that the leg is measured against.
+    pub reference: f64,
+    /// Price of one base in dollars, when results are also given in USD.
+    pub base_usd: Option<f64>,
+    /// The fee fills pay, to split slippage into fee and impact. For a split order, the routed fee.
+    pub fee: Option<f64>,
+}
+
+impl<'a> Leg<'a> {
+    /// Selling base for quote: base is token_in.
+    pub fn sell(base: &'a Token, quote: &'a Token, reference: f64) -> Self {
+        Self { action: TradeAction::Sell, base, quote, reference, base_usd: None, fee: None }
+    }
+
+    /// Spending quote on base: quote is token_in.
+    pub fn buy(base: &'a Token, quote: &'a Token, reference: f64) -> Self {
+        Self { action: TradeAction::Buy, base, quote, reference, base_usd: None, fee: None }
+    }
+
+    /// Also value the leg's base amounts in dollars.
+    pub fn with_base_usd(self, base_usd: Option<f64>) -> Self {
+        Self { base_usd, ..self }
+    }
+
+    /// Also split the leg's slippage into fee and price impact.
+    pub fn with_fee(self, fee: Option<f64>) -> Self {
+        Self { fee, ..self }
+    }
+
+    /// A function to split a fill's slippage into fee and price impact.
+    ///
+    /// Returns:
+    /// - None unless the leg knows its fee and the fill has a slippage
+    pub fn attribute(&self, slippage: Option<f64>) -> Option<SlippageAttribution> {
+        SlippageAttribution::split(slippage?, self.fee?)
+    }
+
+    /// A function to value a base amount in dollars.
+    ///
+    /// Returns:
+    /// - None unless the leg has a dollar price for base
+    pub fn usd_value(&self, base_amount: U256) -> Option<f64> {
+        self.base_usd
+            .map(|price| f64::from(base_amount) / 10f64.powi(self.base.decimals as i32) * price)
+    }
+
+    /// A function to value a quote amount in dollars, at base's dollar price over the leg's
+    /// reference. Both sides of a fill are then valued through the same reference, so they differ
+    /// by exactly the fill's slippage.
+    ///
+    /// Returns:
+    /// - None unless the leg has a dollar price for base and a positive reference
+    pub fn quote_usd_value(&self, quote_amount: U256) -> Option<f64> {
+        let quote_usd: f64 = self.base_usd? / self.reference;
+        quote_usd
+            .is_finite()
+            .then(|| f64::from(quote_amount) / 10f64.powi(self.quote.decimals as i32) * quote_usd)
+    }
+
+    /// A function to convert a fill's (base, quote) amounts for consumers, see `Conversions`.
+    pub fn conversions(&self, amounts: Option<(U256, U256)>) -> Conversions {
+        Conversions {
+            base_tokens: amounts.map(|(base, _)| format_amount(base, self.base.decimals)),
+            quote_tokens: amounts.map(|(_, quote)| format_amount(quote, self.quote.decimals)),
+            quote_usd_value: amounts.and_then(|(_, quote)| self.quote_usd_value(quote)),
+        }
+    }
+
+    /// A function to get the tokens the leg's swap sells and buys, in that order.
+    pub fn swap_tokens(&self) -> (&'a Token, &'a Token) {
+        match self.action {
+            TradeAction::Sell => (self.base, self.quote),
+            TradeAction::Buy => (self.quote, self.base),
+        }
+    }
+
+    /// A function to map a swap's (amount_in, amount_out) onto (base, quote) amounts.
+    pub fn base_quote(&self, amount_in: U256, amount_out: U256) -> (U256, U256) {
+        match self.action {
+            TradeAction::Sell => (amount_in, amount_out),
+            TradeAction::Buy => (amount_out, amount_in),
+        }
+    }
+
+    /// A function to compare a fill's execution price with the reference.
+    ///
+    /// Args:
+    /// - base_amount: Base sold or bought, in base units
+    /// - quote_amount: Quote received or spent, in base units
+    ///
+    /// Returns:
+    /// - The signed slippage as a decimal: positive when the fill is worse than the reference for
+    ///   the side taking it (a sell receives less, a buy pays more), negative for price improvement.
+    ///   None if nothing was traded.
+    pub fn signed_slippage(&self, base_amount: U256, quote_amount: U256) -> Option<f64> {
+        let base: f64 = f64::from(base_amount) / 10f64.powi(self.base.decimals as i32);
+        let quote: f64 = f64::from(quote_amount) / 10f64.powi(self.quote.decimals as i32);
+        if base == 0.0 || self.reference <= 0.0 {
+            return None;
+        }
+        let execution: f64 = quote / base;
+        Some(match self.action {
+            TradeAction::Sell => (self.reference - execution) / self.reference,
+            TradeAction::Buy => (execution - self.reference) / self.reference,
+        })
+    }
+}
+
+/// A fill's amounts in whole tokens and its quote side in dollars, next to the base units and
+/// `usd_value` of the record it's flattened into. All of them come from the same converged amounts
+/// and the leg's one reference, so consumers never convert with a spot of their own.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Conversions {
+    /// Base sold or bought, in whole tokens, e.g. "17.5".
+    pub base_tokens: Option<String>,
+    /// Quote received or spent, in whole tokens.
+    pub quote_tokens: Option<String>,
+    /// Quote in dollars, with `--usd`, see `Leg::quote_usd_value`.
+    pub quote_usd_value: Option<f64>,
+}
+
+/// What became of a pool in a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PoolStatus {
+    /// Measured; individual targets may still carry errors.
+    Ok,
+    /// Left out after failing the monotonicity probe.
+    Unreliable,
+    /// Couldn't be quoted or priced, or every search failed. Other pools are measured as usual.
+    Failed,
+}
+
+impl fmt::Display for PoolStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolStatus::Ok => write!(f, "ok"),
+            PoolStatus::Unreliable => write!(f, "unreliable"),
+            PoolStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// One block's results as a single structured document, for consumers that would otherwise scrape
+/// console lines.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BlockReport {
+    pub block_number: u64,
+    /// The chain the block is from, e.g. "base". Left out when a report isn't from a run.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub chain: String,
+    /// The chain's native token in dollars, if a native/stable pool is tracked.
+    pub native_price: Option<NativePrice>,
+    pub pools: Vec<PoolReport>,
+    /// Depth of one order split across every reliable pool of a pair.
+    pub aggregates: Vec<AggregateReport>,
+    /// A fixed-size order split across every reliable pool of a pair, with `--notional`.
+    pub aggregate_fills: Vec<AggregateFillReport>,
+    /// Depth along each pair's deepest two-hop route, with `--two-hop`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<RouteReport>,
+    /// Pairs whose pools' spots are further apart than `--arb-bps`, with the size tradable across.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub divergences: Vec<Divergence>,
+    /// The run's `--label`s, left out when there are none.
+    #[serde(skip_serializing_if = "Labels::is_empty")]
+    pub labels: Labels,
+    /// True when a chain reorganization brought the stream back to a height already measured: this
+    /// block's records supersede the ones written for it, and for every height after it, before.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub reorg: bool,
+}
+
+/// Everything measured for one pool of one pair in a block.
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolReport {
+    pub pool_id: String,
+    /// The Tycho protocol system, e.g. "uniswap_v3".
+    pub protocol: String,
+    /// e.g. "WETH/USDC"
+    pub pair: String,
+    /// Address of the pair's base token, the first in `pair`.
+    pub base: String,
+    /// Address of the pair's quote token, the second in `pair`.
+    pub quote: String,
+    pub status: PoolStatus,
+    /// Price of one base in dollars, with `--usd`.
+    pub base_usd_price: Option<f64>,
+    /// The pool's fee as a decimal, if the protocol reports one.
+    pub fee: Option<f64>,
+    /// Price of one base in quote. None if the pool was excluded before it was priced.
+    pub spot_price: Option<f64>,
+    /// What sells are measured against, in quote per base: the spot price or oracle mark, possibly
+    /// shifted by a scenario.
+    pub reference_price: Option<f64>,
+    /// Why the pool was left out, e.g. it failed the monotonicity probe. `depths` is empty then.
+    pub excluded: Option<String>,
+    pub depths: Vec<DepthReport>,
+    /// Depth within ±target of mid, with `--two-sided`.
+    pub two_sided: Vec<TwoSidedReport>,
+    /// The slippage of fixed-size sells, with `--notional` (one) or `--impact-table` (one per
+    /// size). Replaces `depths`.
+    pub fills: Vec<FillReport>,
+    /// The block these results were measured at, when they're re-emitted because the pool's state
+    /// hasn't changed since. None when measured this block.
+    pub stale_since_block: Option<u64>,
+    /// Tagged in the config as protocol-owned or locked liquidity.
+    pub protocol_owned: bool,
+}
+
+/// Depth at one slippage target, for one side of the pair.
+#[derive(Debug, Clone, Serialize)]
+pub struct DepthReport {
+    pub target_slippage: f64,
+    /// Sell base for quote, or buy base with quote.
+    pub action: TradeAction,
+    /// True for coarse two-probe estimates.
+    pub approximate: bool,
+    /// Base sold or bought, in base units.
+    pub base_amount: Option<String>,
+    /// Quote received or spent, in base units. None for estimates.
+    pub quote_amount: Option<String>,
+    /// `base_amount` in dollars, with `--usd`.
+    pub usd_value: Option<f64>,
+    /// The same amounts in whole tokens and dollars. Estimates only have base.
+    #[serde(flatten)]
+    pub conversions: Conversions,
+    /// The fill against the leg's reference as a decimal, see `Leg::signed_slippage`. None for
+    /// estimates.
+    pub slippage: Option<f64>,
+    /// The part of `slippage` that is the pool's fee, see `SlippageAttribution`.
+    pub fee_slippage: Option<f64>,
+    /// The part of `slippage` that is price impact.
+    pub impact_slippage: Option<f64>,
+    /// False if the search settled for the best bracket it found, see `SearchResult::converged`.
+    /// None for estimates and failed searches.
+    pub converged: Option<bool>,
+    /// Why the amounts are only a lower bound on depth, see `SearchResult::limit`.
+    pub pool_limited: Option<String>,
+    /// Bisection steps the search took for this target.
+    pub iterations: Option<u32>,
+    /// Wall-clock time of the search, up to this target.
+    pub elapsed_ms: Option<f64>,
+    pub error: Option<String>,
+    /// The converged point exactly as simulated, with `--sim-inputs`.
+    pub simulation: Option<SimulationInputs>,
+}
+
+/// The `get_amount_out` call behind a depth result, for re-verifying it with another simulator.
+/// Amounts are in the swap's own direction (token_in to token_out), unlike base and quote.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SimulationInputs {
+    pub token_in: String,
+    pub token_out: String,
+    /// In token_in base units.
+    pub amount_in: String,
+    /// In token_out base units, as `get_amount_out` returned it.
+    pub amount_out: String,
+    /// As `get_amount_out` returned it.
+    pub gas: String,
+}
+
+impl SimulationInputs {
+    pub fn new(leg: &Leg, result: &SearchResult) -> Self {
+        let (token_in, token_out): (&Token, &Token) = leg.swap_tokens();
+        Self {
+            token_in: token_in.address.to_string(),
+            token_out: token_out.address.to_string(),
+            amount_in: result.amount_in.to_string(),
+            amount_out: result.amount_out.to_string(),
+            gas: result.gas.to_string(),
+        }
+    }
+}
+
+/// What one fixed-size trade costs: the inverse of a depth result, with the amount given and the
+/// slippage measured.
+#[derive(Debug, Clone, Serialize)]
+pub struct FillReport {
+    pub action: TradeAction,
+    /// The notional as given, e.g. "100" or "$1000000".
+    pub notional: String,
+    /// Base sold, in base units. None if the notional couldn't be sized.
+    pub base_amount: Option<String>,
+    /// Quote received, in base units.
+    pub quote_amount: Option<String>,
+    pub usd_value: Option<f64>,
+    #[serde(flatten)]
+    pub conversions: Conversions,
+    /// As for `DepthReport::slippage`.
+    pub slippage: Option<f64>,
+    pub fee_slippage: Option<f64>,
+    pub impact_slippage: Option<f64>,
+    pub error: Option<String>,
+}
+
+impl FillReport {
+    /// Args:
+    /// - notional: The notional as given
+    /// - leg: The leg the trade is on
+    /// - fill: (amount_in, amount_out) of the trade, or why it couldn't be priced
+    pub fn new(notional: &str, leg: &Leg, fill: Result<(U256, U256), String>) -> Self {
+        let amounts: Option<(U256, U256)> = fill.as_ref().ok().map(|(amount_in, amount_out)| {
+            leg.base_quote(*amount_in, *amount_out)
+        });
+        let slippage: Option<f64> = amounts.and_then(|(base, quote)| leg.signed_slippage(base, quote));
+        let attribution: Option<SlippageAttribution> = leg.attribute(slippage);
+        Self {
+            action: leg.action,
+            notional: notional.to_string(),
+            base_amount: amounts.map(|(base, _)| base.to_string()),
+            quote_amount: amounts.map(|(_, quote)| quote.to_string()),
+            usd_value: amounts.and_then(|(base, _)| leg.usd_value(base)),
+            conversions: leg.conversions(amounts),
+            slippage,
+            fee_slippage: attribution.map(|a| a.fee),
+            impact_slippage: attribution.map(|a| a.impact),
+            error: fill.err(),
+        }
+    }
+}
+
+/// A fixed-size order split across a pair's pools.
+#[derive(Debug, Clone, Serialize)]
+pub struct AggregateFillReport {
+    pub pair: String,
+    /// How many pools the order could be split across.
+    pub pools: usize,
+    #[serde(flatten)]
+    pub fill: FillReport,
+}
+
+/// Depth of a pair along its deepest two-hop route at one slippage target, e.g. WETH/DAI through
+/// USDC, with slippage measured against the product of the two pools' spots.
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteReport {
+    pub pair: String,
+    /// The intermediate token's symbol, e.g. "USDC". None if no route was found.
+    pub via: Option<String>,
+    pub via_address: Option<String>,
+    /// The pool trading token_in for the intermediate.
+    pub first_pool: Option<String>,
+    /// The pool trading the intermediate for token_out.
+    pub second_pool: Option<String>,
+    #[serde(flatten)]
+    pub depth: DepthReport,
+}
+
+/// Slippage at a ladder of trade sizes for one pair, with `--impact-table`: a row per size and a
+/// column per pool, plus the order split across all of them. The table exchanges and market makers
+/// ask for when evaluating a listing.
+#[derive(Debug, Clone, Serialize)]
+pub struct ImpactTable {
+    pub pair: String,
+    /// Pool ids, in the order of each row's `slippage`.
+    pub pools: Vec<String>,
+    pub rows: Vec<ImpactRow>,
+}
+
+/// One trade size of an `ImpactTable`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ImpactRow {
+    /// The size as given, e.g. "$1M".
+    pub notional: String,
+    /// Per pool; None where the trade couldn't be priced.
+    pub slippage: Vec<Option<f64>>,
+    /// Split across the pair's pools.
+    pub aggregate: Option<f64>,
+}
+
+/// Depth within ±target of the mid price, quoted the way order-book depth is: what can be sold
+/// down to `mid * (1 - target)` (the bid side) plus what can be bought up to `mid * (1 + target)`
+/// (the ask side). Both legs are measured against the same mid, so the sides add up.
+#[derive(Debug, Clone, Serialize)]
+pub struct TwoSidedReport {
+    pub target_slippage: f64,
+    /// Price of one base in quote both sides are measured against.
+    pub mid_price: f64,
+    /// Base that can be sold within the target, in base units.
+    pub bid_base_amount: Option<String>,
+    /// Quote that selling it returns.
+    pub bid_quote_amount: Option<String>,
+    /// Base that can be bought within the target, in base units.
+    pub ask_base_amount: Option<String>,
+    /// Quote that buying it costs.
+    pub ask_quote_amount: Option<String>,
+    /// Bid plus ask, in base units. None unless both sides were measured.
+    pub base_amount: Option<String>,
+    /// Bid plus ask, in quote units.
+    pub quote_amount: Option<String>,
+    /// `base_amount` in dollars, with `--usd`.
+    pub usd_value: Option<f64>,
+    /// The totals in whole tokens and dollars.
+    #[serde(flatten)]
+    pub conversions: Conversions,
+    /// The first side's error, if either failed.
+    pub error: Option<String>,
+}
+
+impl TwoSidedReport {
+    /// A function to combine a sell and a buy search at the same target into one two-sided result.
+    ///
+    /// Args:
+    /// - target_slippage: How far from mid each side goes
+    /// - bid: The sell leg, whose reference is the mid
+    /// - bid_result: The sell leg's search
+    /// - ask: The buy leg, with the same reference
+    /// - ask_result: The buy leg's search
+    pub fn new(
+        target_slippage: f64,
+        bid: &Leg,
+        bid_result: Result<&SearchResult, &DepthError>,
+        ask: &Leg,
+        ask_result: Result<&SearchResult, &DepthError>,
+    ) -> Self {
+        let bid_amounts: Option<(U256, U256)> = bid_result.ok().map(|r| bid.base_quote(r.amount_in, r.amount_out));
+        let ask_amounts: Option<(U256, U256)> = ask_result.ok().map(|r| ask.base_quote(r.amount_in, r.amount_out));
+        let total: Option<(U256, U256)> = bid_amounts
+            .zip(ask_amounts)
+            .and_then(|((bid_base, bid_quote), (ask_base, ask_quote))| {
+                Some((bid_base.checked_add(ask_base)?, bid_quote.checked_add(ask_quote)?))
+            });
+        Self {
+            target_slippage,
+            mid_price: bid.reference,
+            bid_base_amount: bid_amounts.map(|(base, _)| base.to_string()),
+            bid_quote_amount: bid_amounts.map(|(_, quote)| quote.to_string()),
+            ask_base_amount: ask_amounts.map(|(base, _)| base.to_string()),
+            ask_quote_amount: ask_amounts.map(|(_, quote)| quote.to_string()),
+            base_amount: total.map(|(base, _)| base.to_string()),
+            quote_amount: total.map(|(_, quote)| quote.to_string()),
+            usd_value: total.and_then(|(base, _)| bid.usd_value(base)),
+            conversions: bid.conversions(total),
+            error: bid_result.err().or(ask_result.err()).map(|e| e.to_string()),
+        }
+    }
+}
+
+/// One depth result flattened into a table row, one per (block, pool, slippage target, direction).
+#[derive(Debug, Clone, Serialize)]
+pub struct DepthRow {
+    pub block_number: u64,
+    pub chain: String,
+    pub native_price: Option<f64>,
+    pub pair: String,
+    pub pool_id: String,
+    pub protocol: String,
+    pub base: String,
+    pub quote: String,
+    pub status: PoolStatus,
+    pub spot_price: Option<f64>,
+    pub reference_price: Option<f64>,
+    /// None for excluded pools, which get a single row.
+    pub target_slippage: Option<f64>,
+    /// None for excluded pools.
+    pub action: Option<TradeAction>,
+    /// The trade size, for `--notional` fills, which have no target.
+    pub notional: Option<String>,
+    pub approximate: bool,
+    pub base_amount: Option<String>,
+    pub quote_amount: Option<String>,
+    pub usd_value: Option<f64>,
+    /// Spelled out rather than a flattened `Conversions`, which CSV can't write.
+    pub base_tokens: Option<String>,
+    pub quote_tokens: Option<String>,
+    pub quote_usd_value: Option<f64>,
+    pub slippage: Option<f64>,
+    pub fee_slippage: Option<f64>,
+    pub impact_slippage: Option<f64>,
+    /// The depth error, or why the pool was excluded.
+    pub error: Option<String>,
+    /// As for `PoolReport::stale_since_block`.
+    pub stale_since_block: Option<u64>,
+    /// As for `DepthReport::converged`.
+    pub converged: Option<bool>,
+    /// As for `DepthReport::pool_limited`.
+    pub pool_limited: Option<String>,
+    pub iterations: Option<u32>,
+    pub elapsed_ms: Option<f64>,
+    /// As for `PoolReport::protocol_owned`.
+    pub protocol_owned: bool,
+    /// As for `BlockReport::reorg`.
+    pub reorg: bool,
+    /// The run's labels as `key=value,key=value`, see `Labels`.
+    pub labels: String,
+}
+
+impl BlockReport {
+    /// A function to flatten the report into one row per pool and depth result (or `--notional`
+    /// fill), for tabular sinks.
+    /// Aggregates are left out; they don't belong to a pool.
+    pub fn rows(&self) -> Vec<DepthRow> {
+        let mut rows: Vec<DepthRow> = Vec::new();
+        for pool in self.pools.iter() {
+            let row = |depth: Option<&DepthReport>| DepthRow {
+                block_number: self.block_number,
+                chain: self.chain.clone(),
+                native_price: self.native_price.as_ref().map(|p| p.price),
+                pair: pool.pair.clone(),
+                pool_id: pool.pool_id.clone(),
+                protocol: pool.protocol.clone(),
+                base: pool.base.clone(),
+                quote: pool.quote.clone(),
+                status: pool.status,
+                spot_price: pool.spot_price,
+                reference_price: pool.reference_price,
+                target_slippage: depth.map(|d| d.target_slippage),
+                action: depth.map(|d| d.action),
+                notional: None,
+                approximate: depth.is_some_and(|d| d.approximate),
+                base_amount: depth.and_then(|d| d.base_amount.clone()),
+                quote_amount: depth.and_then(|d| d.quote_amount.clone()),
+                usd_value: depth.and_then(|d| d.usd_value),
+                base_tokens: depth.and_then(|d| d.conversions.base_tokens.clone()),
+                quote_tokens: depth.and_then(|d| d.conversions.quote_tokens.clone()),
+                quote_usd_value: depth.and_then(|d| d.conversions.quote_usd_value),
+                slippage: depth.and_then(|d| d.slippage),
+                fee_slippage: depth.and_then(|d| d.fee_slippage),
+                impact_slippage: depth.and_then(|d| d.impact_slippage),
+                error: depth.and_then(|d| d.error.clone()).or_else(|| pool.excluded.clone()),
+                stale_since_block: pool.stale_since_block,
+                converged: depth.and_then(|d| d.converged),
+                pool_limited: depth.and_then(|d| d.pool_limited.clone()),
+                iterations: depth.and_then(|d| d.iterations),
+                elapsed_ms: depth.and_then(|d| d.elapsed_ms),
+                protocol_owned: pool.protocol_owned,
+                reorg: self.reorg,
+                labels: self.labels.to_string(),
+            };
+            for fill in pool.fills.iter() {
+                rows.push(DepthRow {
+                    action: Some(fill.action),
+                    notional: Some(fill.notional.clone()),
+                    base_amount: fill.base_amount.clone(),
+                    quote_amount: fill.quote_amount.clone(),
+                    usd_value: fill.usd_value,
+                    base_tokens: fill.conversions.base_tokens.clone(),
+                    quote_tokens: fill.conversions.quote_tokens.clone(),
+                    quote_usd_value: fill.conversions.quote_usd_value,
+                    slippage: fill.slippage,
+                    fee_slippage: fill.fee_slippage,
+                    impact_slippage: fill.impact_slippage,
+                    error: fill.error.clone().or_else(|| pool.excluded.clone()),
+                    ..row(None)
+                });
+            }
+            if pool.depths.is_empty() && pool.fills.is_empty() {
+                rows.push(row(None));
+            }
+            rows.extend(pool.depths.iter().map(|depth| row(Some(depth))));
+        }
+        rows
+    }
+}
+
+impl BlockReport {
+    /// A function to arrange one pair's fixed-size fills as a price-impact table.
+    ///
+    /// Args:
+    /// - pair: The pair's label, as in `PoolReport::pair`
+    ///
+    /// Returns:
+    /// - The table, with sizes in the order they were measured and only the pools that have fills
+    pub fn impact_table(&self, pair: &str) -> ImpactTable {
+        let pools: Vec<&PoolReport> = self.pools.iter().filter(|p| p.pair == pair && !p.fills.is_empty()).collect();
+        let aggregates: Vec<&FillReport> =
+            self.aggregate_fills.iter().filter(|a| a.pair == pair).map(|a| &a.fill).collect();
+        let mut notionals: Vec<&str> = Vec::new();
+        for fill in pools.iter().flat_map(|p| p.fills.iter()).chain(aggregates.iter().copied()) {
+            if !notionals.contains(&fill.notional.as_str()) {
+                notionals.push(&fill.notional);
+            }
+        }
+        let slippage_at = |fills: &[FillReport], notional: &str| -> Option<f64> {
+            fills.iter().find(|f| f.notional == notional).and_then(|f| f.slippage)
+        };
+        ImpactTable {
+            pair: pair.to_string(),
+            pools: pools.iter().map(|p| p.pool_id.clone()).collect(),
+            rows: notionals
+                .into_iter()
+                .map(|notional| ImpactRow {
+                    notional: notional.to_string(),
+                    slippage: pools.iter().map(|p| slippage_at(&p.fills, notional)).collect(),
+                    aggregate: aggregates.iter().find(|f| f.notional == notional).and_then(|f| f.slippage),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl PoolReport {
+    /// True if the pool was measured but every target failed, e.g. the VM simulation errors on any
+    /// size.
+    pub fn all_failed(&self) -> bool {
+        !self.depths.is_empty() && self.depths.iter().all(|depth| depth.error.is_some())
+    }
+}
+
+impl DepthReport {
+    pub fn from_search(target_slippage: f64, leg: &Leg, result: Result<&SearchResult, &DepthError>) -> Self {
+        let amounts: Option<(U256, U256)> = result.ok().map(|r| leg.base_quote(r.amount_in, r.amount_out));
+        let slippage: Option<f64> = amounts.and_then(|(base, quote)| leg.signed_slippage(base, quote));
+        let attribution: Option<SlippageAttribution> = leg.attribute(slippage);
+        Self {
+            target_slippage,
+            action: leg.action,
+            approximate: false,
+            base_amount: amounts.map(|(base, _)| base.to_string()),
+            quote_amount: amounts.map(|(_, quote)| quote.to_string()),
+            usd_value: amounts.and_then(|(base, _)| leg.usd_value(base)),
+            conversions: leg.conversions(amounts),
+            slippage,
+            fee_slippage: attribution.map(|a| a.fee),
+            impact_slippage: attribution.map(|a| a.impact),
+            converged: result.ok().map(|r| r.converged),
+            pool_limited: result.ok().and_then(|r| r.limit.clone()),
+            iterations: result.ok().map(|r| r.iterations),
+            elapsed_ms: result.ok().map(|r| r.elapsed.as_secs_f64() * 1000.0),
+            error: result.err().map(|e| e.to_string()),
+            simulation: None,
+        }
+    }
+
+    /// Estimates only cover sells, where the estimated amount is base.
+    pub fn from_estimate(target_slippage: f64, leg: &Leg, estimate: Result<&DepthEstimate, &DepthError>) -> Self {
+        Self {
+            target_slippage,
+            action: TradeAction::Sell,
+            approximate: true,
+            base_amount: estimate.ok().map(|e| e.amount_in.to_string()),
+            quote_amount: None,
+            usd_value: estimate.ok().and_then(|e| leg.usd_value(e.amount_in)),
+            conversions: Conversions {
+                base_tokens: estimate.ok().map(|e| format_amount(e.amount_in, leg.base.decimals)),
+                ..Conversions::default()
+            },
+            slippage: None,
+            fee_slippage: None,
+            impact_slippage: None,
+            converged: None,
+            pool_limited: None,
+            iterations: None,
+            elapsed_ms: estimate.ok().map(|e| e.elapsed.as_secs_f64() * 1000.0),
+            error: estimate.err().map(|e| e.to_string()),
+            simulation: None,
+        }
+    }
+}
+
+/// Aggregate depth of a pair at one slippage target.
+#[derive(Debug, Clone, Serialize)]
+pub struct AggregateReport {
+    pub pair: String,
+    pub target_slippage: f64,
+    pub action: TradeAction,
+    /// How many pools the order could be split across.
+    pub pools: usize,
+    pub base_amount: Option<String>,
+    pub quote_amount: Option<String>,
+    pub usd_value: Option<f64>,
+    #[serde(flatten)]
+    pub conversions: Conversions,
+    /// As for `DepthReport::slippage`.
+    pub slippage: Option<f64>,
+    /// Split by the fee each pool charges, weighted by how much of the order it takes.
+    pub fee_slippage: Option<f64>,
+    pub impact_slippage: Option<f64>,
+    pub error: Option<String>,
+    /// How steady this depth has been over the last blocks; set by whoever keeps the history.
+    pub stability: Option<DepthStability>,
+    /// The same depth without the pools tagged as protocol-owned. None when none of them are among
+    /// `pools`.
+    pub organic: Option<OrganicDepth>,
+    /// The same depth under two protocol sets, with `--compare-protocols`.
+    pub comparison: Option<ProtocolComparison>,
+    /// How much of the depth each protocol's pools take, largest first; empty if it failed.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub by_protocol: Vec<ProtocolShare>,
+}
+
+/// The part of an aggregate's split order one protocol's pools take, e.g. uniswap_v3's.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProtocolShare {
+    pub protocol: String,
+    /// How many of the protocol's pools the order is routed through.
+    pub pools: usize,
+    pub base_amount: String,
+    pub quote_amount: String,
+    /// `base_amount` as a fraction of the aggregate's, e.g. 0.62; None if the aggregate has none.
+    pub share: Option<f64>,
+}
+
+impl ProtocolShare {
+    /// A function to total an aggregate's allocations by the protocol of the pool they go to.
+    ///
+    /// Args:
+    /// - allocations: The split order, as `AggregateDepth::allocations`
+    /// - protocol_of: The protocol system of a pool id; "" for one that isn't known
+    /// - leg: The aggregate's leg, to tell base from quote
+    ///
+    /// Returns:
+    /// - One share per protocol, largest first
+    pub fn breakdown(allocations: &[PoolAllocation], protocol_of: impl Fn(&str) -> String, leg: &Leg) -> Vec<Self> {
+        let mut totals: BTreeMap<String, (usize, U256, U256)> = BTreeMap::new();
+        for allocation in allocations {
+            let amount_in: U256 = allocation.amount_in.parse().unwrap_or(U256::ZERO);
+            let amount_out: U256 = allocation.amount_out.parse().unwrap_or(U256::ZERO);
+            let (base, quote): (U256, U256) = leg.base_quote(amount_in, amount_out);
+            let total: &mut (usize, U256, U256) = totals.entry(protocol_of(&allocation.pool_id)).or_default();
+            total.0 += 1;
+            total.1 += base;
+            total.2 += quote;
+        }
+        let total_base: U256 = totals.values().map(|(_, base, _)| *base).fold(U256::ZERO, |sum, base| sum + base);
+        let mut shares: Vec<Self> = totals
+            .into_iter()
+            .map(|(protocol, (pools, base, quote))| Self {
+                protocol,
+                pools,
+                base_amount: base.to_string(),
+                quote_amount: quote.to_string(),
+                share: (!total_base.is_zero()).then(|| f64::from(base) / f64::from(total_base)),
+            })
+            .collect();
+        shares.sort_by(|a, b| b.share.partial_cmp(&a.share).unwrap_or(std::cmp::Ordering::Equal));
+        shares
+    }
+}
+
+/// Aggregate depth left once protocol-owned or locked liquidity is taken out, i.e. what the market
+/// itself provides.
+#[derive(Debug, Clone, Serialize)]
+pub struct OrganicDepth {
+    /// How many of the aggregate's pools were left out.
+    pub protocol_owned_pools: usize,
+    pub base_amount: Option<String>,
+    pub quote_amount: Option<String>,
+    pub usd_value: Option<f64>,
+    #[serde(flatten)]
+    pub conversions: Conversions,
+    pub slippage: Option<f64>,
+    /// Also set when every pool is protocol-owned, as there's nothing left to measure.
+    pub error: Option<String>,
+}
+
+impl OrganicDepth {
+    /// A function to keep the amounts of an aggregate measured without the protocol-owned pools.
+    ///
+    /// Args:
+    /// - protocol_owned_pools: How many pools were left out
+    /// - aggregate: The aggregate over the remaining pools
+    pub fn new(protocol_owned_pools: usize, aggregate: AggregateReport) -> Self {
+        Self {
+            protocol_owned_pools,
+            base_amount: aggregate.base_amount,
+            quote_amount: aggregate.quote_amount,
+            usd_value: aggregate.usd_value,
+            conversions: aggregate.conversions,
+            slippage: aggregate.slippage,
+            error: aggregate.error,
+        }
+    }
+
+    /// A function for a pair whose pools are all protocol-owned: no organic depth at all.
+    pub fn empty(protocol_owned_pools: usize) -> Self {
+        Self {
+            protocol_owned_pools,
+            base_amount: Some("0".to_string()),
+            quote_amount: Some("0".to_string()),
+            usd_value: None,
+            conversions: Conversions {
+                base_tokens: Some("0".to_string()),
+                quote_tokens: Some("0".to_string()),
+                quote_usd_value: None,
+            },
+            slippage: None,
+            error: None,
+        }
+    }
+}
+
+/// Aggregate depth over the pools of one protocol set, see `compare::ProtocolSet`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SetDepth {
+    /// The set's name, e.g. "with-v4".
+    pub name: String,
+    /// How many of the pair's pools belong to the set.
+    pub pools: usize,
+    pub base_amount: Option<String>,
+    pub quote_amount: Option<String>,
+    pub usd_value: Option<f64>,
+    #[serde(flatten)]
+    pub conversions: Conversions,
+    pub slippage: Option<f64>,
+    pub error: Option<String>,
+}
+
+impl SetDepth {
+    /// A function to keep the amounts of an aggregate measured over one protocol set.
+    pub fn new(name: &str, aggregate: AggregateReport) -> Self {
+        Self {
+            name: name.to_string(),
+            pools: aggregate.pools,
+            base_amount: aggregate.base_amount,
+            quote_amount: aggregate.quote_amount,
+            usd_value: aggregate.usd_value,
+            conversions: aggregate.conversions,
+            slippage: aggregate.slippage,
+            error: aggregate.error,
+        }
+    }
+
+    /// A function for a set none of the pair's pools belong to: no depth at all.
+    pub fn empty(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            pools: 0,
+            base_amount: Some("0".to_string()),
+            quote_amount: Some("0".to_string()),
+            usd_value: None,
+            conversions: Conversions {
+                base_tokens: Some("0".to_string()),
+                quote_tokens: Some("0".to_string()),
+                quote_usd_value: None,
+            },
+            slippage: None,
+            error: None,
+        }
+    }
+}
+
+/// A pair's depth under two protocol sets measured against the same block and reference price,
+/// so the difference is exactly what the venues in one and not the other contribute.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProtocolComparison {
+    pub a: SetDepth,
+    pub b: SetDepth,
+    /// `a`'s depth minus `b`'s, in base units, negative if `b` is deeper. None if either failed.
+    pub delta_base_amount: Option<String>,
+    /// The same in whole base tokens.
+    pub delta_base_tokens: Option<String>,
+    pub delta_usd_value: Option<f64>,
+    /// The delta as a fraction of `b`'s depth, e.g. 0.25 for a quarter more; None if `b` has none.
+    pub delta_ratio: Option<f64>,
+}
+
+impl ProtocolComparison {
+    /// A function to compare two sets' depths.
+    ///
+    /// Args:
+    /// - a: The set whose extra depth is measured, e.g. "with-v4"
+    /// - b: The set it's measured against, e.g. "without-v4"
+    /// - base_decimals: The base token's decimals, for the delta in whole tokens
+    pub fn new(a: SetDepth, b: SetDepth, base_decimals: usize) -> Self {
+        let amounts: Option<(U256, U256)> = a
+            .base_amount
+            .as_deref()
+            .zip(b.base_amount.as_deref())
+            .and_then(|(a, b)| Some((a.parse::<U256>().ok()?, b.parse::<U256>().ok()?)));
+        let delta: Option<(&str, U256)> = amounts.map(|(a, b)| if a >= b { ("", a - b) } else { ("-", b - a) });
+        let delta_usd_value: Option<f64> = match (a.usd_value, b.usd_value) {
+            (Some(a), Some(b)) => Some(a - b),
+            _ => None,
+        };
+        let delta_ratio: Option<f64> = amounts
+            .filter(|(_, b)| !b.is_zero())
+            .map(|(a, b)| (f64::from(a) - f64::from(b)) / f64::from(b));
+        Self {
+            delta_base_amount: delta.map(|(sign, delta)| format!("{}{}", sign, delta)),
+            delta_base_tokens: delta.map(|(sign, delta)| format!("{}{}", sign, format_amount(delta, base_decimals))),
+            delta_usd_value,
+            delta_ratio,
+            a,
+            b,
+        }
+    }
+}
+
+impl AggregateReport {
+    pub fn new(
+        pair: &str,
+        target_slippage: f64,
+        pools: usize,
+        leg: &Leg,
+        result: Result<&AggregateDepth, &DepthError>,
+    ) -> Self {
+        let amounts: Option<(U256, U256)> = result.ok().map(|r| leg.base_quote(r.amount_in, r.amount_out));
+        let slippage: Option<f64> = amounts.and_then(|(base, quote)| leg.signed_slippage(base, quote));
+        let attribution: Option<SlippageAttribution> = leg.attribute(slippage);
+        Self {
+            pair: pair.to_string(),
+            target_slippage,
+            action: leg.action,
+            pools,
+            base_amount: amounts.map(|(base, _)| base.to_string()),
+            quote_amount: amounts.map(|(_, quote)| quote.to_string()),
+            usd_value: amounts.and_then(|(base, _)| leg.usd_value(base)),
+            conversions: leg.conversions(amounts),
+            slippage,
+            fee_slippage: attribution.map(|a| a.fee),
+            impact_slippage: attribution.map(|a| a.impact),
+            error: result.err().map(|e| e.to_string()),
+            stability: None,
+            organic: None,
+            comparison: None,
+            by_protocol: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    time::SystemTime,
+};
+use serde::Serialize;
+use crate::{
+    bundle::RunBundle,
+    columnar::ParquetSink,
+    labels::Labels,
+    report::BlockReport,
+    sink::{CsvSink, FileSink, Partition, PartitionedSink, SqliteSink},
+};
+
+/// Where result records go: the bundle, written at the end, and a results file and stdout,
+/// streamed. The CSV file, the database and the Parquet files get block reports instead.
+pub struct ResultSinks {
+    pub bundle: Option<RunBundle>,
+    pub results: Option<PartitionedSink<FileSink>>,
+    pub csv: Option<PartitionedSink<CsvSink>>,
+    pub sqlite: Option<SqliteSink>,
+    pub parquet: Option<ParquetSink>,
+    /// NDJSON on stdout, flushed per line so pipes see records as they come.
+    pub stdout: bool,
+    /// How the results and CSV files are split, if they are.
+    pub partition: Option<Partition>,
+    pub chain: String,
+    /// Pool id to protocol system, kept up to date only when partitioning by protocol.
+    pub pool_protocols: HashMap<String, String>,
+    /// Attached to every result record.
+    pub labels: Labels,
+    /// Whether the block being written replaces heights already written, see `BlockReport::reorg`;
+    /// its result records are flagged `"reorg": true`.
+    pub reorg: bool,
+}
+
+impl ResultSinks {
+    /// A function to set up sinks that write nothing, for the run to add to.
+    pub fn new(chain: &str, labels: Labels) -> Self {
+        Self {
+            bundle: None,
+            results: None,
+            csv: None,
+            sqlite: None,
+            parquet: None,
+            stdout: false,
+            partition: None,
+            chain: chain.to_string(),
+            pool_protocols: HashMap::new(),
+            labels,
+            reorg: false,
+        }
+    }
+
+    pub fn add_result(&mut self, result: impl Serialize) -> io::Result<()> {
+        let mut result: serde_json::Value = self.labels.attach(result)?;
+        if let Some(record) = result.as_object_mut() {
+            record.insert("chain".to_string(), self.chain.clone().into());
+            if self.reorg {
+                record.insert("reorg".to_string(), true.into());
+            }
+        }
+        if let Some(bundle) = self.bundle.as_mut() {
+            bundle.add_result(&result)?;
+        }
+        if self.results.is_some() {
+            let value: Option<String> = self.partition_value(&result)?;
+            if let Some(results) = self.results.as_mut() {
+                results.sink(value.as_deref())?.write_json_line(&result)?;
+            }
+        }
+        if self.stdout {
+            let mut stdout: io::StdoutLock = io::stdout().lock();
+            serde_json::to_writer(&mut stdout, &result)?;
+            writeln!(stdout)?;
+            stdout.flush()?;
+        }
+        Ok(())
+    }
+
+    /// A function to find which partition a result record belongs to, from its `pair` or
+    /// `pool_id`.
+    ///
+    /// Returns:
+    /// - The partition value, or None for records that belong to no single partition
+    fn partition_value(&self, result: &impl Serialize) -> io::Result<Option<String>> {
+        let record: serde_json::Value = match self.partition {
+            None => return Ok(None),
+            Some(Partition::Chain) => return Ok(Some(self.chain.clone())),
+            Some(_) => serde_json::to_value(result)?,
+        };
+        Ok(match self.partition {
+            Some(Partition::Pair) => record.get("pair").and_then(|pair| pair.as_str()).map(str::to_string),
+            _ => record
+                .get("pool_id")
+                .and_then(|id| id.as_str())
+                .and_then(|id| self.pool_protocols.get(id))
+                .cloned(),
+        })
+    }
+
+    /// A function to write a block report to the CSV file, the database and the Parquet files.
+    ///
+    /// Args:
+    /// - report: The block's results
+    /// - block_time: The block's timestamp, kept as `measured_at` in the database, for history, and
+    ///   in the Parquet files, which are partitioned by its date
+    pub fn add_block(&mut self, report: &BlockReport, block_time: SystemTime) -> anyhow::Result<()> {
+        if let Some(csv) = self.csv.as_mut() {
+            for row in report.rows().into_iter() {
+                let value: Option<&str> = match self.partition {
+                    None => None,
+                    Some(Partition::Pair) => Some(&row.pair),
+                    Some(Partition::Chain) => Some(&self.chain),
+                    Some(Partition::Protocol) => Some(&row.protocol),
+                };
+                csv.sink(value)?.write_rows(std::slice::from_ref(&row))?;
+            }
+        }
+        if let Some(sqlite) = self.sqlite.as_mut() {
+            sqlite.write_block(report, block_time)?;
+        }
+        if let Some(parquet) = self.parquet.as_mut() {
+            parquet.write_block(report, block_time)?;
+        }
+        Ok(())
+    }
+
+    /// A function to mark heights already written as orphaned by a chain reorganization: an
+    /// `"event": "reorg"` record for the append-only sinks, whose rows for those heights the
+    /// block's own (flagged) records then supersede, and a delete in SQLite.
+    ///
+    /// Args:
+    /// - from_block: The block the stream went back to
+    /// - to_block: The last block written before it
+    pub fn orphan(&mut self, from_block: u64, to_block: u64) -> anyhow::Result<()> {
+        self.add_result(serde_json::json!({
+            "block_number": from_block,
+            "event": "reorg",
+            "orphaned_from": from_block,
+            "orphaned_to": to_block,
+        }))?;
+        if let Some(sqlite) = self.sqlite.as_mut() {
+            sqlite.orphan_blocks(from_block, to_block)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        for results in self.results.iter_mut().flat_map(|results| results.sinks_mut()) {
+            results.flush()?;
+        }
+        for csv in self.csv.iter_mut().flat_map(|csv| csv.sinks_mut()) {
+            csv.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::{Path, PathBuf}};
+    use serde_json::{json, Value};
+    use crate::labels::Label;
+    use super::*;
+
+    fn read_records(path: &Path) -> Vec<Value> {
+        fs::read_to_string(path).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir: PathBuf = env::temp_dir().join(format!("liquidity-depth-results-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn tags_every_record_with_the_chain_labels_and_reorg() {
+        let dir: PathBuf = scratch_dir("tags");
+        let labels: Labels = Labels::merge(["experiment=fee-study".parse::<Label>().unwrap()]);
+        let mut sinks: ResultSinks = ResultSinks::new("base", labels);
+        sinks.results = Some(PartitionedSink::open(dir.join("results.jsonl"), None, |path| FileSink::open(path, None)).unwrap());
+
+        sinks.add_result(json!({ "block_number": 100, "pair": "WETH/USDC" })).unwrap();
+        sinks.reorg = true;
+        sinks.orphan(99, 100).unwrap();
+        sinks.flush().unwrap();
+
+        let records: Vec<Value> = read_records(&dir.join("results.jsonl"));
+        assert_eq!(records.len(), 2);
+        assert_eq!((records[0]["chain"].clone(), records[0]["labels"].clone()), (json!("base"), json!({ "experiment": "fee-study" })));
+        assert_eq!(records[0].get("reorg"), None);
+        assert_eq!(records[1]["event"], json!("reorg"));
+        assert_eq!((records[1]["orphaned_from"].clone(), records[1]["orphaned_to"].clone()), (json!(99), json!(100)));
+        assert_eq!(records[1]["reorg"], json!(true));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn files_records_by_pair_or_by_the_pools_protocol() {
+        let dir: PathBuf = scratch_dir("partitions");
+        let mut sinks: ResultSinks = ResultSinks::new("ethereum", Labels::default());
+        let open = |partition: Partition| {
+            PartitionedSink::open(dir.join(format!("{}.jsonl", partition)), Some(partition), |path| FileSink::open(path, None))
+        };
+        sinks.partition = Some(Partition::Pair);
+        sinks.results = Some(open(Partition::Pair).unwrap());
+        sinks.add_result(json!({ "pair": "WETH/USDC" })).unwrap();
+        sinks.add_result(json!({ "pair": "WBTC/USDC" })).unwrap();
+        sinks.add_result(json!({ "native_price": 3000.0 })).unwrap();
+
+        sinks.partition = Some(Partition::Protocol);
+        sinks.results = Some(open(Partition::Protocol).unwrap());
+        sinks.pool_protocols.insert("0xabc".to_string(), "uniswap_v3".to_string());
+        sinks.add_result(json!({ "pool_id": "0xabc" })).unwrap();
+        sinks.flush().unwrap();
+
+        assert_eq!(read_records(&dir.join("pair-weth-usdc.jsonl")).len(), 1);
+        assert_eq!(read_records(&dir.join("pair-wbtc-usdc.jsonl")).len(), 1);
+        // A record for no one pair goes to the shared file.
+        assert_eq!(read_records(&dir.join("pair-all.jsonl"))[0]["native_price"], json!(3000.0));
+        assert_eq!(read_records(&dir.join("protocol-uniswap-v3.jsonl"))[0]["pool_id"], json!("0xabc"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
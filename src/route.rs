@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+use alloy_primitives::U256;
+use num_bigint::BigUint;
+use tycho_common::Bytes;
+use tycho_simulation::models::Token;
+use crate::{
+    binary_search::SearchResult,
+    calculator::DepthCalculator,
+    error::DepthError,
+    provider::{AmountOutProvider, Pool},
+};
+
+/// How many of each leg's pools, deepest first, are combined into candidate routes per
+/// intermediate token, so a token with dozens of pools doesn't cost dozens squared searches.
+pub const LEG_CANDIDATES: usize = 3;
+
+/// Selling through two pools in a row: token_in for `via` in `first`, then all of that `via` for
+/// token_out in `second`. Quotes and spot compose, so the depth search runs over it as over one
+/// pool, measuring slippage against the product of the two spots.
+#[derive(Clone, Copy)]
+pub struct TwoHop<'a> {
+    pub first: Pool<'a>,
+    pub via: &'a Token,
+    pub second: Pool<'a>,
+}
+
+impl AmountOutProvider for TwoHop<'_> {
+    fn amount_out(&self, amount_in: BigUint, token_in: &Token, token_out: &Token) -> Result<BigUint, DepthError> {
+        let via_amount: BigUint = self.first.amount_out(amount_in, token_in, self.via)?;
+        self.second.amount_out(via_amount, self.via, token_out)
+    }
+
+    fn spot(&self, token_in: &Token, token_out: &Token) -> Result<f64, DepthError> {
+        Ok(self.first.spot(token_in, self.via)? * self.second.spot(self.via, token_out)?)
+    }
+
+    fn quote(&self, amount_in: BigUint, token_in: &Token, token_out: &Token) -> Result<(BigUint, BigUint), DepthError> {
+        let (via_amount, first_gas) = self.first.quote(amount_in, token_in, self.via)?;
+        let (amount_out, second_gas) = self.second.quote(via_amount, self.via, token_out)?;
+        Ok((amount_out, first_gas + second_gas))
+    }
+
+    /// The first leg's cap: the route can't take more than its first pool can.
+    fn max_in(&self, token_in: &Token, _token_out: &Token, _reference_price: f64, tvl_multiple: f64) -> Option<U256> {
+        let first_spot: f64 = self.first.spot(token_in, self.via).ok()?;
+        self.first.max_in(token_in, self.via, first_spot, tvl_multiple)
+    }
+}
+
+/// A two-pool path from token_in to token_out through an intermediate token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    pub via: Token,
+    /// Trades token_in for `via`.
+    pub first_pool: String,
+    /// Trades `via` for token_out.
+    pub second_pool: String,
+}
+
+/// The deepest route at a target, as `best_two_hop` finds it.
+#[derive(Debug, Clone)]
+pub struct RouteDepth {
+    pub route: Route,
+    pub depth: SearchResult,
+    /// The route's spot price of token_in in token_out, the two pools' spots multiplied; what the
+    /// depth's slippage is measured against.
+    pub spot: f64,
+}
+
+/// A function to find every two-hop path between two tokens over the tracked pools.
+///
+/// Args:
+/// - pools: (id, tokens) for every tracked pool, of any pair
+/// - token_in: The token being sold
+/// - token_out: The token being bought
+///
+/// Returns:
+/// - Each intermediate token with the pools trading it against token_in and against token_out,
+///   keyed by its address. Tokens missing either leg are left out.
+pub fn two_hop_legs<'a>(
+    pools: impl IntoIterator<Item = (&'a str, &'a [Token])>,
+    token_in: &Token,
+    token_out: &Token,
+) -> BTreeMap<Bytes, (Token, Vec<String>, Vec<String>)> {
+    let mut legs: BTreeMap<Bytes, (Token, Vec<String>, Vec<String>)> = BTreeMap::new();
+    for (id, tokens) in pools {
+        let has = |token: &Token| tokens.iter().any(|t| t.address == token.address);
+        let (sells_in, buys_out): (bool, bool) = (has(token_in), has(token_out));
+        for via in tokens.iter().filter(|t| t.address != token_in.address && t.address != token_out.address) {
+            let entry = legs.entry(via.address.clone()).or_insert_with(|| (via.clone(), Vec::new(), Vec::new()));
+            if sells_in {
+                entry.1.push(id.to_string());
+            }
+            if buys_out {
+                entry.2.push(id.to_string());
+            }
+        }
+    }
+    legs.retain(|_, (_, first, second)| !first.is_empty() && !second.is_empty());
+    legs
+}
+
+/// A function to find the deepest two-hop route at a slippage target.
+///
+/// Each leg's pools are ranked by their own depth at the target and the deepest `LEG_CANDIDATES`
+/// of each are paired up; every pairing is searched as a `TwoHop` and the one that takes the most
+/// token_in wins.
+///
+/// Args:
+/// - legs: As `two_hop_legs` finds them
+/// - pool_of: The pool to quote for an id, None for one without a state yet
+/// - token_in: The token being sold
+/// - token_out: The token being bought
+/// - target_slippage: The target, as a decimal
+/// - precision: As for `DepthCalculator::with_precision`
+///
+/// Returns:
+/// - The best route with its depth and spot, or the last error if no route could be searched, or
+///   `DepthError::Simulation` if there's no route at all
+pub fn best_two_hop<'a>(
+    legs: &BTreeMap<Bytes, (Token, Vec<String>, Vec<String>)>,
+    pool_of: impl Fn(&str) -> Option<Pool<'a>>,
+    token_in: &Token,
+    token_out: &Token,
+    target_slippage: f64,
+    precision: f64,
+) -> Result<RouteDepth, DepthError> {
+    let mut best: Option<RouteDepth> = None;
+    let mut last_error: Option<DepthError> = None;
+    for (via, first_ids, second_ids) in legs.values() {
+        let first: Vec<(&str, Pool)> = deepest(first_ids, &pool_of, token_in, via, target_slippage, precision);
+        let second: Vec<(&str, Pool)> = deepest(second_ids, &pool_of, via, token_out, target_slippage, precision);
+        for ((first_id, first_pool), (second_id, second_pool)) in
+            first.iter().flat_map(|first| second.iter().map(move |second| (first, second)))
+        {
+            if first_id == second_id {
+                continue;
+            }
+            let hop: TwoHop = TwoHop { first: *first_pool, via, second: *second_pool };
+            let spot: f64 = match hop.spot(token_in, token_out) {
+                Ok(spot) => spot,
+                Err(e) => {
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+            let depth: Result<SearchResult, DepthError> = DepthCalculator::from_provider(&hop, token_in, token_out)
+                .with_reference_price(spot)
+                .with_precision(precision)
+                .depth_at(target_slippage);
+            match depth {
+                Ok(depth) if best.as_ref().is_none_or(|best| depth.amount_in > best.depth.amount_in) => {
+                    let route: Route =
+                        Route { via: via.clone(), first_pool: first_id.to_string(), second_pool: second_id.to_string() };
+                    best = Some(RouteDepth { route, depth, spot });
+                }
+                Ok(_) => {}
+                Err(e) => last_error = Some(e),
+            }
+        }
+    }
+    match (best, last_error) {
+        (Some(best), _) => Ok(best),
+        (None, Some(e)) => Err(e),
+        (None, None) => Err(DepthError::Simulation("no two-hop route between the pair's tokens".to_string())),
+    }
+}
+
+/// A function to rank one leg's pools by their own depth at the target, keeping the deepest
+/// `LEG_CANDIDATES`. Pools that can't be searched are dropped.
+fn deepest<'a, 'b>(
+    ids: &'b [String],
+    pool_of: &impl Fn(&str) -> Option<Pool<'a>>,
+    token_in: &Token,
+    token_out: &Token,
+    target_slippage: f64,
+    precision: f64,
+) -> Vec<(&'b str, Pool<'a>)> {
+    let mut ranked: Vec<(&str, Pool, U256)> = ids
+        .iter()
+        .filter_map(|id| {
+            let pool: Pool = pool_of(id)?;
+            let depth: SearchResult = DepthCalculator::from_provider(&pool, token_in, token_out)
+                .with_precision(precision)
+                .depth_at(target_slippage)
+                .ok()?;
+            Some((id.as_str(), pool, depth.amount_in))
+        })
+        .collect();
+    ranked.sort_by_key(|(_, _, depth)| std::cmp::Reverse(*depth));
+    ranked.into_iter().take(LEG_CANDIDATES).map(|(id, pool, _)| (id, pool)).collect()
+}
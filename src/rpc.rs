@@ -0,0 +1,475 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use alloy_primitives::U256;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use tycho_common::{models::Chain, Bytes};
+use tycho_simulation::{models::Token, protocol::state::ProtocolSim};
+use crate::{
+    aggregate::{aggregate_depth_against_reference, aggregate_fill_against_reference, best_spot_price, DEFAULT_SPLIT_CHUNKS},
+    amount::parse_amount,
+    attribution::{pool_fee, routed_fee},
+    binary_search::{
+        calculate_outputs_for_reference_price, calculate_slippage_for_amount, SearchResult, DEFAULT_BRACKET_TVL_MULTIPLE,
+        DEFAULT_MAX_ITERATIONS,
+    },
+    config::DEFAULT_SLIPPAGE_TARGETS,
+    curve::{default_curve_range, DepthCurve},
+    error::{guard_simulation, DepthError},
+    history::{parse_resolution, read_depth_history, PairHistory, DEFAULT_HISTORY_RESOLUTION_SECS},
+    hub::BlockView,
+    report::{
+        AggregateFillReport, AggregateReport, BlockReport, DepthReport, FillReport, Leg, PoolReport, PoolStatus,
+        ProtocolShare,
+    },
+    token_id::TokenId,
+};
+
+/// Sizes sampled per curve when a request doesn't say.
+pub const DEFAULT_RPC_CURVE_SAMPLES: usize = 20;
+
+/// One request line, JSON-RPC style: `{"id": 1, "method": "depth", "params": {...}}`.
+///
+/// Methods:
+/// - `depth`: `{"token_in", "token_out", "slippages"?}`, depth per pool and split across pools
+/// - `quote`: `{"token_in", "token_out", "amount"}`, what selling `amount` whole tokens returns
+/// - `curve`: `{"token_in", "token_out", "samples"?}`, each pool's price-impact curve
+/// - `pools`: `{"token_in", "token_out"}`, the pools trading the pair with their spot prices and fees
+/// - `history`: `{"token_in", "token_out", "from"?, "to"?, "resolution"?}`, the pair's aggregate sell
+///   depth from the `--sqlite` history, bucketed by block time (Unix seconds; `resolution` like "1h")
+#[derive(Debug, Deserialize)]
+pub struct RpcRequest {
+    /// Echoed back so callers can match responses to requests.
+    #[serde(default)]
+    pub id: Value,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+/// One response line: `result` on success, `error` otherwise.
+#[derive(Debug, Serialize)]
+pub struct RpcResponse {
+    pub id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RpcError {
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DepthParams {
+    token_in: String,
+    token_out: String,
+    #[serde(default)]
+    slippages: Option<Vec<f64>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PairParams {
+    token_in: String,
+    token_out: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QuoteParams {
+    token_in: String,
+    token_out: String,
+    /// Whole tokens of token_in, e.g. "100" or "0.5".
+    amount: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HistoryParams {
+    token_in: String,
+    token_out: String,
+    /// Unix seconds; from the start of the history when absent.
+    #[serde(default)]
+    from: Option<u64>,
+    /// Unix seconds; up to now when absent.
+    #[serde(default)]
+    to: Option<u64>,
+    /// Bucket width, e.g. "15m" or "1h".
+    #[serde(default)]
+    resolution: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CurveParams {
+    token_in: String,
+    token_out: String,
+    #[serde(default)]
+    samples: Option<usize>,
+}
+
+/// One pool's curve in a `curve` response.
+#[derive(Debug, Serialize)]
+struct PoolCurve {
+    pool_id: String,
+    curve: Option<DepthCurve>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct CurveResult {
+    block_number: Option<u64>,
+    pair: String,
+    pools: Vec<PoolCurve>,
+}
+
+/// One pool in a `pools` response.
+#[derive(Debug, Serialize)]
+struct PoolListing {
+    pool_id: String,
+    protocol: String,
+    spot_price: Option<f64>,
+    fee: Option<f64>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct PoolsResult {
+    block_number: Option<u64>,
+    pair: String,
+    pools: Vec<PoolListing>,
+}
+
+/// Answers `--stdio` and `serve` requests against whatever block the caller's view is at.
+pub struct RpcHandler {
+    chain: Chain,
+    tokens: HashMap<Bytes, Token>,
+    precision: f64,
+    /// The `--sqlite` database `history` reads, if the run writes one.
+    history: Option<PathBuf>,
+}
+
+impl RpcHandler {
+    /// Args:
+    /// - chain: The chain being streamed, for resolving token identifiers
+    /// - tokens: The token list, e.g. from `load_all_tokens`
+    /// - precision: How close to a target counts as converged, as for the depth search
+    pub fn new(chain: Chain, tokens: HashMap<Bytes, Token>, precision: f64) -> Self {
+        Self { chain, tokens, precision, history: None }
+    }
+
+    /// A function to answer `history` from a `--sqlite` database; without one it errors.
+    pub fn with_history(mut self, path: PathBuf) -> Self {
+        self.history = Some(path);
+        self
+    }
+
+    /// A function to answer one request line.
+    ///
+    /// Args:
+    /// - line: The request, as JSON
+    /// - view: The latest block view; held for the duration of the call
+    ///
+    /// Returns:
+    /// - The response. Malformed requests get an error response rather than ending the session.
+    pub fn handle_line(&self, line: &str, view: &BlockView) -> RpcResponse {
+        let request: RpcRequest = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(e) => return RpcResponse::error(Value::Null, format!("invalid request: {}", e)),
+        };
+        match self.call(&request.method, request.params, view) {
+            Ok(result) => RpcResponse { id: request.id, result: Some(result), error: None },
+            Err(message) => RpcResponse::error(request.id, message),
+        }
+    }
+
+    /// A function to answer one method call, however it arrived.
+    ///
+    /// Args:
+    /// - method: "depth", "quote", "curve", "pools" or "history"
+    /// - params: The method's parameters, as a JSON object
+    /// - view: The latest block view
+    ///
+    /// Returns:
+    /// - The result as JSON, or why the call couldn't be answered
+    pub fn call(&self, method: &str, params_json: Value, view: &BlockView) -> Result<Value, String> {
+        match method {
+            "depth" => params(params_json).and_then(|params| self.depth(params, view)),
+            "quote" => params(params_json).and_then(|params| self.quote(params, view)),
+            "curve" => params(params_json).and_then(|params| self.curve(params, view)),
+            "pools" => params(params_json).and_then(|params| self.pools(params, view)),
+            "history" => params(params_json).and_then(|params| self.history(params)),
+            other => Err(format!("unknown method \"{}\", expected depth, quote, curve, pools or history", other)),
+        }
+    }
+
+    fn depth(&self, params: DepthParams, view: &BlockView) -> Result<Value, String> {
+        let (token_in, token_out) = self.resolve(&params.token_in, &params.token_out)?;
+        let mut targets: Vec<f64> = params.slippages.unwrap_or_else(|| DEFAULT_SLIPPAGE_TARGETS.to_vec());
+        if targets.is_empty() || targets.iter().any(|target| !(*target > 0.0 && *target < 1.0)) {
+            return Err("slippages must be decimals in (0, 1), e.g. [0.005, 0.02]".to_string());
+        }
+        targets.sort_by(|a, b| a.total_cmp(b));
+        targets.dedup();
+        let label: String = format!("{}/{}", token_in.symbol, token_out.symbol);
+        let pools: Vec<(String, Arc<dyn ProtocolSim>)> = pools_of(view, &token_in, &token_out);
+
+        let mut report: BlockReport = BlockReport { block_number: view.block_number.unwrap_or_default(), ..Default::default() };
+        for (id, state) in pools.iter() {
+            let mut pool: PoolReport = pool_report(view, id, state.as_ref(), &label, &token_in, &token_out);
+            let Some(spot) = pool.spot_price else {
+                report.pools.push(pool);
+                continue;
+            };
+            let sell: Leg = Leg::sell(&token_in, &token_out, spot).with_fee(pool.fee);
+            let depths: Result<Vec<SearchResult>, DepthError> =
+                calculate_outputs_for_reference_price(&targets, self.precision, state.as_ref(), &token_in, &token_out, spot);
+            for (i, target) in targets.iter().enumerate() {
+                pool.depths.push(DepthReport::from_search(*target, &sell, depths.as_ref().map(|d| &d[i])));
+            }
+            if pool.all_failed() {
+                pool.status = PoolStatus::Failed;
+            }
+            report.pools.push(pool);
+        }
+
+        let measured: Vec<(String, &dyn ProtocolSim)> = measured_pools(&pools, &report.pools);
+        if !measured.is_empty() {
+            for target in targets.iter() {
+                let reference: Result<f64, DepthError> = best_spot_price(&measured, &token_in, &token_out);
+                let placeholder: f64 = *reference.as_ref().unwrap_or(&f64::NAN);
+                let aggregate = reference.and_then(|reference| {
+                    aggregate_depth_against_reference(
+                        &measured,
+                        *target,
+                        self.precision,
+                        &token_in,
+                        &token_out,
+                        DEFAULT_SPLIT_CHUNKS,
+                        reference,
+                        DEFAULT_MAX_ITERATIONS,
+                        DEFAULT_BRACKET_TVL_MULTIPLE,
+                    )
+                });
+                let fee: Option<f64> = aggregate.as_ref().ok().and_then(|a| routed_fee(&a.allocations, |id| fee_of(&measured, id)));
+                let sell: Leg = Leg::sell(&token_in, &token_out, placeholder).with_fee(fee);
+                let mut aggregate_report: AggregateReport =
+                    AggregateReport::new(&label, *target, measured.len(), &sell, aggregate.as_ref());
+                if let Ok(aggregate) = &aggregate {
+                    aggregate_report.by_protocol = ProtocolShare::breakdown(
+                        &aggregate.allocations,
+                        |id| view.protocols.get(id).cloned().unwrap_or_default(),
+                        &sell,
+                    );
+                }
+                report.aggregates.push(aggregate_report);
+            }
+        }
+        serde_json::to_value(&report).map_err(|e| e.to_string())
+    }
+
+    fn quote(&self, params: QuoteParams, view: &BlockView) -> Result<Value, String> {
+        let (token_in, token_out) = self.resolve(&params.token_in, &params.token_out)?;
+        let amount_in: U256 = parse_amount(&params.amount, token_in.decimals as u8).map_err(|e| e.to_string())?;
+        let label: String = format!("{}/{}", token_in.symbol, token_out.symbol);
+        let pools: Vec<(String, Arc<dyn ProtocolSim>)> = pools_of(view, &token_in, &token_out);
+
+        let mut report: BlockReport = BlockReport { block_number: view.block_number.unwrap_or_default(), ..Default::default() };
+        for (id, state) in pools.iter() {
+            let mut pool: PoolReport = pool_report(view, id, state.as_ref(), &label, &token_in, &token_out);
+            let Some(spot) = pool.spot_price else {
+                report.pools.push(pool);
+                continue;
+            };
+            let sell: Leg = Leg::sell(&token_in, &token_out, spot).with_fee(pool.fee);
+            let fill: Result<(U256, U256), String> =
+                calculate_slippage_for_amount(amount_in, state.as_ref(), &token_in, &token_out, spot)
+                    .map(|r| (r.amount_in, r.amount_out))
+                    .map_err(|e| e.to_string());
+            let fill: FillReport = FillReport::new(&params.amount, &sell, fill);
+            if fill.error.is_some() {
+                pool.status = PoolStatus::Failed;
+            }
+            pool.fills.push(fill);
+            report.pools.push(pool);
+        }
+
+        let measured: Vec<(String, &dyn ProtocolSim)> = measured_pools(&pools, &report.pools);
+        if !measured.is_empty() {
+            let reference: Result<f64, DepthError> = best_spot_price(&measured, &token_in, &token_out);
+            let placeholder: f64 = *reference.as_ref().unwrap_or(&f64::NAN);
+            let aggregate = reference.and_then(|reference| {
+                aggregate_fill_against_reference(&measured, amount_in, &token_in, &token_out, DEFAULT_SPLIT_CHUNKS, reference)
+            });
+            let fee: Option<f64> = aggregate.as_ref().ok().and_then(|a| routed_fee(&a.allocations, |id| fee_of(&measured, id)));
+            let sell: Leg = Leg::sell(&token_in, &token_out, placeholder).with_fee(fee);
+            report.aggregate_fills.push(AggregateFillReport {
+                pair: label,
+                pools: measured.len(),
+                fill: FillReport::new(
+                    &params.amount,
+                    &sell,
+                    aggregate.map(|a| (a.amount_in, a.amount_out)).map_err(|e| e.to_string()),
+                ),
+            });
+        }
+        serde_json::to_value(&report).map_err(|e| e.to_string())
+    }
+
+    fn curve(&self, params: CurveParams, view: &BlockView) -> Result<Value, String> {
+        let (token_in, token_out) = self.resolve(&params.token_in, &params.token_out)?;
+        let samples: usize = params.samples.unwrap_or(DEFAULT_RPC_CURVE_SAMPLES);
+        if samples < 2 {
+            return Err("samples must be at least 2".to_string());
+        }
+        let (min_in, max_in) = default_curve_range(&token_in);
+        let pools: Vec<PoolCurve> = pools_of(view, &token_in, &token_out)
+            .into_iter()
+            .map(|(pool_id, state)| {
+                let curve: Result<DepthCurve, DepthError> = guard_simulation(|| {
+                    DepthCurve::sample(state.as_ref(), &token_in, &token_out, min_in, max_in, samples)
+                });
+                PoolCurve { pool_id, error: curve.as_ref().err().map(|e| e.to_string()), curve: curve.ok() }
+            })
+            .collect();
+        let result: CurveResult = CurveResult {
+            block_number: view.block_number,
+            pair: format!("{}/{}", token_in.symbol, token_out.symbol),
+            pools,
+        };
+        serde_json::to_value(&result).map_err(|e| e.to_string())
+    }
+
+    fn pools(&self, params: PairParams, view: &BlockView) -> Result<Value, String> {
+        let (token_in, token_out) = self.resolve(&params.token_in, &params.token_out)?;
+        let pools: Vec<PoolListing> = pools_of(view, &token_in, &token_out)
+            .into_iter()
+            .map(|(pool_id, state)| {
+                let spot: Result<f64, DepthError> = guard_simulation(|| state.spot_price(&token_in, &token_out));
+                PoolListing {
+                    protocol: view.protocols.get(&pool_id).cloned().unwrap_or_default(),
+                    pool_id,
+                    spot_price: spot.as_ref().ok().copied(),
+                    fee: pool_fee(state.as_ref()),
+                    error: spot.err().map(|e| e.to_string()),
+                }
+            })
+            .collect();
+        let result: PoolsResult = PoolsResult {
+            block_number: view.block_number,
+            pair: format!("{}/{}", token_in.symbol, token_out.symbol),
+            pools,
+        };
+        serde_json::to_value(&result).map_err(|e| e.to_string())
+    }
+
+    fn history(&self, params: HistoryParams) -> Result<Value, String> {
+        let Some(path) = &self.history else {
+            return Err("history needs the run to write a --sqlite database".to_string());
+        };
+        let resolution_secs: u64 = match &params.resolution {
+            Some(resolution) => parse_resolution(resolution)?,
+            None => DEFAULT_HISTORY_RESOLUTION_SECS,
+        };
+        let now: u64 = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        let (from, to): (u64, u64) = (params.from.unwrap_or(0), params.to.unwrap_or(now));
+        if from > to {
+            return Err(format!("from {} is after to {}", from, to));
+        }
+        // Rows are labelled by symbol; a token the list doesn't know is looked up as written.
+        let pair: String = match self.resolve(&params.token_in, &params.token_out) {
+            Ok((token_in, token_out)) => format!("{}/{}", token_in.symbol, token_out.symbol),
+            Err(_) => format!("{}/{}", params.token_in, params.token_out),
+        };
+        let history: PairHistory =
+            read_depth_history(path, &pair, from, to, resolution_secs).map_err(|e| format!("reading history: {}", e))?;
+        serde_json::to_value(&history).map_err(|e| e.to_string())
+    }
+
+    fn resolve(&self, token_in: &str, token_out: &str) -> Result<(Token, Token), String> {
+        let resolve = |raw: &str| -> Result<Token, String> {
+            raw.parse::<TokenId>()
+                .and_then(|id| id.resolve(self.chain, &self.tokens))
+                .map_err(|e| e.to_string())
+        };
+        let (token_in, token_out): (Token, Token) = (resolve(token_in)?, resolve(token_out)?);
+        if token_in.address == token_out.address {
+            return Err(format!("{} is paired with itself", token_in.symbol));
+        }
+        Ok((token_in, token_out))
+    }
+}
+
+impl RpcResponse {
+    fn error(id: Value, message: String) -> Self {
+        Self { id, result: None, error: Some(RpcError { message }) }
+    }
+}
+
+fn params<T: DeserializeOwned>(params: Value) -> Result<T, String> {
+    serde_json::from_value(params).map_err(|e| format!("invalid params: {}", e))
+}
+
+/// A function to list the pools trading exactly this pair, in a stable order.
+fn pools_of(view: &BlockView, token_in: &Token, token_out: &Token) -> Vec<(String, Arc<dyn ProtocolSim>)> {
+    let mut tokens: Vec<Token> = vec![token_in.clone(), token_out.clone()];
+    tokens.sort_unstable_by_key(|t: &Token| t.address.clone());
+    let mut pools: Vec<(String, Arc<dyn ProtocolSim>)> = view.pools_for(&tokens);
+    pools.sort_by(|a, b| a.0.cmp(&b.0));
+    pools
+}
+
+/// A function to start a pool's report with its spot price, or mark it failed if it has none.
+fn pool_report(
+    view: &BlockView,
+    id: &str,
+    state: &dyn ProtocolSim,
+    label: &str,
+    token_in: &Token,
+    token_out: &Token,
+) -> PoolReport {
+    let spot: Result<f64, DepthError> = guard_simulation(|| state.spot_price(token_in, token_out));
+    PoolReport {
+        pool_id: id.to_string(),
+        protocol: view.protocols.get(id).cloned().unwrap_or_default(),
+        pair: label.to_string(),
+        base: token_in.address.to_string(),
+        quote: token_out.address.to_string(),
+        status: if spot.is_ok() { PoolStatus::Ok } else { PoolStatus::Failed },
+        base_usd_price: None,
+        fee: pool_fee(state),
+        spot_price: spot.as_ref().ok().copied(),
+        reference_price: spot.as_ref().ok().copied(),
+        excluded: spot.err().map(|e| format!("failed: {}", e)),
+        depths: Vec::new(),
+        two_sided: Vec::new(),
+        fills: Vec::new(),
+        stale_since_block: None,
+        protocol_owned: false,
+    }
+}
+
+/// A function to pick the pools that could be measured, for splitting an order across them.
+fn measured_pools<'a>(
+    pools: &'a [(String, Arc<dyn ProtocolSim>)],
+    reports: &[PoolReport],
+) -> Vec<(String, &'a dyn ProtocolSim)> {
+    pools
+        .iter()
+        .zip(reports.iter())
+        .filter(|(_, report)| report.status == PoolStatus::Ok)
+        .map(|((id, state), _)| (id.clone(), state.as_ref()))
+        .collect()
+}
+
+fn fee_of(pools: &[(String, &dyn ProtocolSim)], pool_id: &str) -> Option<f64> {
+    pools.iter().find(|(id, _)| id == pool_id).and_then(|(_, state)| pool_fee(*state))
+}
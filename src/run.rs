@@ -0,0 +1,1684 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fs,
+    future::Future,
+    io::{self, IsTerminal, Write},
+    path::PathBuf,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use alloy_primitives::U256;
+use crossterm::event::Event;
+use tokio::sync::mpsc;
+use tycho_common::{models::Chain, Bytes};
+use tycho_simulation::{
+    models::Token,
+    protocol::{models::BlockUpdate, state::ProtocolSim},
+};
+#[cfg(feature = "charts")]
+use crate::figures::FigureWriter;
+use crate::{
+    ahead::{measure_ahead, next_block, read_ahead, AheadBlock, MeasuredBlocks},
+    aggregate::{
+        aggregate_depth_against_reference, aggregate_fill_against_reference, best_spot_price, AggregateDepth,
+        DEFAULT_SPLIT_CHUNKS,
+    },
+    alert::{AlertMonitor, AlertRule, Notifier},
+    amount::{format_amount, parse_amount},
+    attribution::{pool_fee, routed_fee},
+    backfill::{BackfillSeries, Replay, SeriesPoint},
+    binary_search::WarmStarts,
+    bundle::RunBundle,
+    chain::{stablecoin_addresses, wrapped_native_address},
+    chart::{DepthChart, FigureFormat},
+    checkpoint::Checkpoint,
+    columnar::ParquetSink,
+    compare::ProtocolSet,
+    config::Config,
+    console::{Console, ConsoleRenderer, ConsoleThrottle, EmojiRenderer, PlainRenderer},
+    crosscheck::CrossCheck,
+    dashboard::{BlockLag, Tui},
+    describe::{
+        describe_attribution, describe_block_summary, describe_comparison, describe_divergence, describe_fill,
+        describe_organic, describe_protocol_shares, describe_stability,
+    },
+    display::DisplayNames,
+    divergence::widest_divergence,
+    error::DepthError,
+    html::HtmlReport,
+    labels::Labels,
+    measure::{
+        measure_pool, BlockContext, CachedPool, MeasureSettings, MeasuredAhead, PoolInputs, PoolJob, PoolList,
+        PoolOutcome, TrackedPair,
+    },
+    native::{native_token_price, NativePrice},
+    notional::Notional,
+    oracle::OracleError,
+    orderbook::{export_order_books, order_book, OrderBook},
+    parallel::{default_workers, map_limited, ConcurrencyLimits},
+    preflight::{preflight_pair, PairPreflight},
+    probe::MonotonicityCheck,
+    provider::Pool,
+    recording::{write_block, RecordedBlock},
+    report::{
+        AggregateFillReport, AggregateReport, BlockReport, DepthReport, FillReport, Leg, OrganicDepth,
+        ProtocolComparison, ProtocolShare, RouteReport, SetDepth,
+    },
+    route::{best_two_hop, two_hop_legs, RouteDepth},
+    schedule::SampleSchedule,
+    sink::{Compression, CsvSink, FileSink, Partition, PartitionedSink, SqliteSink},
+    soak::SoakMonitor,
+    results::ResultSinks,
+    stability::{DepthHistory, DEFAULT_STABILITY_WINDOW},
+    stream::StreamBlock,
+    supervisor::RestartCounter,
+    unwind::estimate_unwind,
+    usd::{token_usd_price, UsdPrice},
+};
+
+/// What goes to stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    /// One document per block, written once the block is done.
+    Json,
+    /// Every result record on its own line as soon as it's measured.
+    Ndjson,
+    /// Nothing; block reports go to Parquet files instead.
+    Parquet,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" | "jsonl" => Ok(OutputFormat::Ndjson),
+            "parquet" => Ok(OutputFormat::Parquet),
+            _ => Err(format!("unknown output format \"{}\", expected text, json, ndjson or parquet", s)),
+        }
+    }
+}
+
+/// What the block loop does with the blocks it's given.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunMode {
+    /// Measure every due block.
+    Measure,
+    /// Estimate each pair's depth after a notional of liquidity is withdrawn, on the first block.
+    Unwind { notional: f64, targets: Vec<f64> },
+    /// Ladder each pair's pools on the first block, and write the ladders to `export` if given.
+    Orderbook { step_bps: u32, levels: usize, export: Option<PathBuf> },
+}
+
+/// One chain's run as the flags and the config resolved it.
+pub struct RunSettings {
+    pub chain: Chain,
+    /// Records and console lines are told apart by chain.
+    pub multi_chain: bool,
+    pub mode: RunMode,
+    pub measure: MeasureSettings,
+    pub names: DisplayNames,
+    pub output: OutputFormat,
+    pub plain: bool,
+    /// Draw the dashboard instead of console lines.
+    pub tui: bool,
+    /// Also price token_in in dollars from the pools.
+    pub usd: bool,
+    /// Print each pair's impact table after its fills.
+    pub impact_table: bool,
+    /// Fixed trade sizes that replace the depth search; empty to search.
+    pub notionals: Vec<Notional>,
+    pub two_hop: bool,
+    /// Report pools whose spots are further apart than this, in basis points.
+    pub arb_bps: Option<f64>,
+    pub stability_window: usize,
+    /// Blocks measured side by side under `backfill --parallel-blocks`.
+    pub parallel_blocks: usize,
+    /// Start each pool's searches from where they converged last block.
+    pub warm_start: bool,
+    /// With `--pools`, the only pools measured, lowercased.
+    pub pools: Vec<String>,
+    /// Pool ids tagged as protocol-owned, lowercased.
+    pub protocol_owned: HashSet<String>,
+    pub labels: Labels,
+    pub protocol_sets: Option<[ProtocolSet; 2]>,
+    pub alert_rules: Vec<AlertRule>,
+    pub notifiers: Vec<Box<dyn Notifier>>,
+    /// Stop after this many measured blocks; 0 runs until the stream ends or a signal.
+    pub max_blocks: usize,
+    /// Show what's about to be measured on the first block, and ask before going on unless `yes`.
+    pub preflight: bool,
+    pub yes: bool,
+    /// With `--soak`, how long to run and how often to sample.
+    pub soak: Option<(Duration, Duration)>,
+    pub full_every: u64,
+    pub workers: usize,
+    pub concurrency: ConcurrencyLimits,
+    pub schedule: SampleSchedule,
+    /// Print each pair's depth chart.
+    pub chart: bool,
+    pub charts_dir: Option<PathBuf>,
+    pub chart_format: FigureFormat,
+    pub bundle_path: Option<PathBuf>,
+    pub results_path: Option<PathBuf>,
+    pub csv_path: Option<PathBuf>,
+    pub sqlite_path: Option<PathBuf>,
+    pub parquet_dir: PathBuf,
+    pub compression: Option<Compression>,
+    pub partition: Option<Partition>,
+    pub checkpoint_path: Option<PathBuf>,
+    /// The checkpoint to resume after, loaded from `checkpoint_path`.
+    pub checkpoint: Option<Checkpoint>,
+    pub record_dir: Option<PathBuf>,
+    /// The recording read in place of the stream, for `backfill` and `replay`.
+    pub replay: Option<Replay>,
+    /// The HTML report's title and path, for `report`.
+    pub report: Option<(String, PathBuf)>,
+    /// Every setting as the run resolved it, and the endpoint streamed from, for the bundle.
+    pub resolved_config: Config,
+    pub endpoint: String,
+}
+
+impl RunSettings {
+    /// A function to start from the defaults: five blocks measured and printed, nothing written.
+    pub fn new(chain: Chain) -> Self {
+        Self {
+            chain,
+            multi_chain: false,
+            mode: RunMode::Measure,
+            measure: MeasureSettings::default(),
+            names: DisplayNames::default(),
+            output: OutputFormat::Text,
+            plain: false,
+            tui: false,
+            usd: false,
+            impact_table: false,
+            notionals: Vec::new(),
+            two_hop: false,
+            arb_bps: None,
+            stability_window: DEFAULT_STABILITY_WINDOW,
+            parallel_blocks: 1,
+            warm_start: true,
+            pools: Vec::new(),
+            protocol_owned: HashSet::new(),
+            labels: Labels::default(),
+            protocol_sets: None,
+            alert_rules: Vec::new(),
+            notifiers: Vec::new(),
+            max_blocks: 5,
+            preflight: false,
+            yes: false,
+            soak: None,
+            full_every: 1,
+            workers: default_workers(),
+            concurrency: ConcurrencyLimits::new(&HashMap::new()),
+            schedule: SampleSchedule::new(1, 0),
+            chart: false,
+            charts_dir: None,
+            chart_format: FigureFormat::default(),
+            bundle_path: None,
+            results_path: None,
+            csv_path: None,
+            sqlite_path: None,
+            parquet_dir: PathBuf::from("parquet"),
+            compression: None,
+            partition: None,
+            checkpoint_path: None,
+            checkpoint: None,
+            record_dir: None,
+            replay: None,
+            report: None,
+            resolved_config: Config::default(),
+            endpoint: String::new(),
+        }
+    }
+}
+
+/// One chain's block loop: the pools it tracks, what it remembers between blocks, and where the
+/// results go.
+pub struct BlockLoop {
+    pub settings: RunSettings,
+    pub pairs: Vec<TrackedPair>,
+    pub console: Console,
+    renderer: Box<dyn ConsoleRenderer + Sync>,
+    pub sinks: ResultSinks,
+    alerts: AlertMonitor,
+    html: Option<(HtmlReport, PathBuf)>,
+    #[cfg(feature = "charts")]
+    figures: Option<FigureWriter>,
+    throttle: ConsoleThrottle,
+    soak: Option<SoakMonitor>,
+    tui: Option<Tui>,
+    /// Native ETH (the zero address) counts as native too, for pools that hold it directly.
+    native_addresses: Vec<Bytes>,
+    stable_addresses: Vec<Bytes>,
+    tracked_pairs: HashMap<String, Vec<Token>>,
+    pool_protocols: HashMap<String, String>,
+    /// Shared rather than cloned: the stream's states move in, and compute tasks borrow them.
+    tracked_states: HashMap<String, Arc<dyn ProtocolSim>>,
+    /// VM states by pool id, and the block the VM stream last reached.
+    vm_states: HashMap<String, Arc<dyn ProtocolSim>>,
+    vm_block: Option<u64>,
+    /// Pools whose state changed since they were last measured, across skipped blocks too, and the
+    /// last results of every pool (keyed by pair index and pool id) for the ones that didn't.
+    changed_pools: HashSet<String>,
+    pool_cache: HashMap<(usize, String), CachedPool>,
+    /// Each pair's recent aggregate depth, for the stability metric.
+    depth_history: DepthHistory,
+    /// Where each pool's searches converged, to start the next block from.
+    warm_starts: WarmStarts,
+    /// Latest evidence for every pool excluded as non-monotone, and every disagreement seen, for
+    /// the run summary.
+    unreliable_pools: BTreeMap<String, MonotonicityCheck>,
+    disagreeing_pools: BTreeMap<String, CrossCheck>,
+    series: BackfillSeries,
+    /// Under --parallel-blocks, blocks read off the stream before their turn, and pools measured
+    /// before their block's turn, by block.
+    ahead: VecDeque<AheadBlock>,
+    measured_ahead: MeasuredBlocks,
+    /// Restarts already reported on the console.
+    reported_restarts: u64,
+    /// Blocks up to a checkpoint were measured before a restart; the first block after it says
+    /// what changed while the run was stopped.
+    resume_after: Option<u64>,
+    resumed: Option<Checkpoint>,
+    /// The last block whose results went to the sinks, to tell a reorg by.
+    last_written: Option<u64>,
+    blocks_seen: usize,
+    announced_schedule: bool,
+}
+
+/// Each tracked pair's prices for one block, fetched once so every pool of the pair agrees on them.
+struct BlockPrices {
+    native_price: Option<NativePrice>,
+    /// Dollar price of each pair's token_in, when depth is also given in USD.
+    base_usd: Vec<Option<f64>>,
+    /// With --notional or --impact-table, each pair's trade sizes in token_in.
+    notional_amounts: Vec<Vec<Result<U256, String>>>,
+    /// Oracle marks; None for pairs measured against spot.
+    marks: Vec<Option<f64>>,
+    /// Pairs whose mark couldn't be fetched, which measure nothing this block.
+    unmarked: Vec<bool>,
+}
+
+impl BlockLoop {
+    /// A function to set up the loop: check the pairs against the settings, open every sink and
+    /// say what's tracked.
+    ///
+    /// Args:
+    /// - settings: The chain's resolved settings
+    /// - pairs: The pairs to track, their tokens resolved
+    pub fn new(mut settings: RunSettings, pairs: Vec<TrackedPair>) -> anyhow::Result<Self> {
+        let chain: Chain = settings.chain;
+        // A rule for a pair or target that isn't measured would never fire, which looks like all's well.
+        // Side by side, a rule may be for another chain's pair.
+        for rule in settings.alert_rules.iter() {
+            let covered: bool = pairs
+                .iter()
+                .any(|pair| pair.slippages.iter().any(|target| rule.applies_to(&pair.label, *target)));
+            if !covered && !settings.multi_chain {
+                anyhow::bail!("alert {}: no tracked pair {} with a {} slippage target", rule, rule.pair, rule.target_slippage);
+            }
+        }
+        if let Some(amount) = &settings.measure.exact_output {
+            for pair in pairs.iter() {
+                let amount_out: U256 = parse_amount(amount, pair.token_out.decimals as u8)
+                    .map_err(|e| anyhow::anyhow!("--exact-output {} for {}: {}", amount, pair.label, e))?;
+                if amount_out.is_zero() {
+                    anyhow::bail!("--exact-output must be a positive amount");
+                }
+            }
+        }
+
+        let console: Console = Console {
+            to_stderr: matches!(settings.output, OutputFormat::Json | OutputFormat::Ndjson),
+            muted: settings.tui,
+            prefix: settings.multi_chain.then(|| format!("[{}] ", chain)),
+        };
+        let renderer: Box<dyn ConsoleRenderer + Sync> =
+            if settings.plain { Box::new(PlainRenderer) } else { Box::new(EmojiRenderer) };
+        let compression: Option<Compression> = settings.compression;
+        let mut sinks: ResultSinks = ResultSinks::new(&chain.to_string(), settings.labels.clone());
+        sinks.bundle = settings.bundle_path.as_ref().map(|_| {
+            RunBundle::new(&chain.to_string(), &settings.endpoint)
+                .with_labels(settings.labels.clone())
+                .with_config(&settings.resolved_config)
+        });
+        sinks.results = settings
+            .results_path
+            .as_ref()
+            .map(|path| PartitionedSink::open(path, settings.partition, move |path| FileSink::open(path, compression)))
+            .transpose()?;
+        sinks.csv = settings
+            .csv_path
+            .as_ref()
+            .map(|path| PartitionedSink::open(path, settings.partition, move |path| CsvSink::open(path, compression)))
+            .transpose()?;
+        sinks.sqlite = settings.sqlite_path.as_ref().map(SqliteSink::open).transpose()?;
+        sinks.parquet = (settings.output == OutputFormat::Parquet)
+            .then(|| ParquetSink::new(&settings.parquet_dir, &chain.to_string()));
+        sinks.stdout = settings.output == OutputFormat::Ndjson;
+        sinks.partition = settings.partition;
+        #[cfg(feature = "charts")]
+        let figures: Option<FigureWriter> = settings
+            .charts_dir
+            .as_ref()
+            .map(|dir| FigureWriter::create(dir, settings.chart_format))
+            .transpose()
+            .map_err(|e| anyhow::anyhow!("--charts-dir: {}", e))?;
+
+        console.line(renderer.waiting_for_first_block());
+        for pair in pairs.iter() {
+            console.line(format!("tracking {} ({} → {})", pair.label, pair.token_in.address, pair.token_out.address));
+            for token in [&pair.token_in, &pair.token_out] {
+                if let Some(name) = settings.names.name(token) {
+                    console.line(format!("   {} is {}", settings.names.symbol(token), name));
+                }
+            }
+        }
+        if settings.schedule.every() > 1 {
+            console.line(format!("sampling {}", settings.schedule));
+            tracing::info!(every = settings.schedule.every(), phase = settings.schedule.phase(), "sample schedule");
+        }
+
+        let resume_after: Option<u64> = settings.checkpoint.as_ref().map(|checkpoint| checkpoint.block_number);
+        Ok(Self {
+            alerts: AlertMonitor::new(std::mem::take(&mut settings.alert_rules)),
+            html: settings.report.clone().map(|(title, path)| (HtmlReport::new(&title), path)),
+            #[cfg(feature = "charts")]
+            figures,
+            throttle: ConsoleThrottle::new(settings.full_every),
+            soak: settings.soak.map(|(duration, interval)| SoakMonitor::new(duration, interval)),
+            tui: None,
+            native_addresses: wrapped_native_address(&chain)
+                .into_iter()
+                .chain([Bytes::from("0x0000000000000000000000000000000000000000")])
+                .collect(),
+            stable_addresses: stablecoin_addresses(&chain),
+            tracked_pairs: HashMap::new(),
+            pool_protocols: HashMap::new(),
+            tracked_states: HashMap::new(),
+            vm_states: HashMap::new(),
+            vm_block: None,
+            changed_pools: HashSet::new(),
+            pool_cache: HashMap::new(),
+            depth_history: DepthHistory::new(settings.stability_window),
+            warm_starts: WarmStarts::default(),
+            unreliable_pools: BTreeMap::new(),
+            disagreeing_pools: BTreeMap::new(),
+            series: BackfillSeries::default(),
+            ahead: VecDeque::new(),
+            measured_ahead: HashMap::new(),
+            reported_restarts: 0,
+            resume_after,
+            resumed: settings.checkpoint.take(),
+            last_written: resume_after,
+            blocks_seen: 0,
+            announced_schedule: false,
+            settings,
+            pairs,
+            console,
+            renderer,
+            sinks,
+        })
+    }
+
+    /// A function to measure blocks until the stream ends, the run is done or it's stopped, then
+    /// print the run summary and close every sink.
+    ///
+    /// Args:
+    /// - block_rx: The protocol stream, or a recording read in its place
+    /// - vm_rx: With --cross-check, the VM twins' stream
+    /// - restarts: The streams' restart counter, for the console and the summary
+    /// - shutdown: Resolves to the name of what stopped the run, e.g. "SIGTERM"; the block in
+    ///   progress is finished first
+    pub async fn run(
+        mut self,
+        mut block_rx: mpsc::Receiver<StreamBlock>,
+        mut vm_rx: Option<mpsc::Receiver<StreamBlock>>,
+        restarts: RestartCounter,
+        shutdown: impl Future<Output = anyhow::Result<&'static str>>,
+    ) -> anyhow::Result<()> {
+        tokio::pin!(shutdown);
+        let mut tui_events: Option<mpsc::Receiver<Event>> = None;
+        if self.settings.tui {
+            let (tui, events) = Tui::start(self.settings.chain.to_string().as_str())?;
+            self.tui = Some(tui);
+            tui_events = Some(events);
+        }
+
+        loop {
+            // In soak mode a quiet stream must not keep us past the deadline.
+            let soak_deadline: Option<tokio::time::Instant> =
+                self.soak.as_ref().map(|monitor| tokio::time::Instant::from_std(monitor.deadline()));
+            let block: Option<AheadBlock> = tokio::select! {
+                block = next_block(&mut self.ahead, &mut block_rx) => block,
+                _ = async {
+                    match soak_deadline {
+                        Some(deadline) => tokio::time::sleep_until(deadline).await,
+                        None => std::future::pending().await,
+                    }
+                } => break,
+                signal = &mut shutdown => {
+                    self.console.line(self.renderer.shutting_down(signal?));
+                    break;
+                }
+                event = async {
+                    match tui_events.as_mut() {
+                        Some(events) => events.recv().await,
+                        None => std::future::pending().await,
+                    }
+                } => {
+                    match (self.tui.as_mut(), event) {
+                        (Some(tui), Some(event)) => {
+                            if tui.handle(&event)? {
+                                break;
+                            }
+                        }
+                        // The key reader stopped; the dashboard still redraws every block.
+                        _ => tui_events = None,
+                    }
+                    continue;
+                }
+            };
+            // The supervisor gave up on the stream, or the recording is out of blocks.
+            let Some((StreamBlock { snapshot, failover, timestamp, update: mut block }, states_ahead)) = block else {
+                break;
+            };
+            let received: Instant = Instant::now();
+            if restarts.get() > self.reported_restarts {
+                self.reported_restarts = restarts.get();
+                self.console.line(self.renderer.stream_reconnected(block.block_number, self.reported_restarts));
+            }
+            if let Some(failover) = failover {
+                self.console.line(self.renderer.endpoint_failover(&failover.from, &failover.to, &failover.reason));
+                self.sinks.add_result(serde_json::json!({
+                    "block_number": block.block_number,
+                    "event": "failover",
+                    "from": &failover.from,
+                    "to": &failover.to,
+                    "reason": &failover.reason,
+                    "last_block": failover.last_block,
+                }))?;
+            }
+            let updated_states: usize = self.track(&mut block, snapshot, timestamp, states_ahead)?;
+            if let Some(vm_rx) = vm_rx.as_mut() {
+                self.track_vm(vm_rx);
+            }
+            if !self.is_due(block.block_number, snapshot) {
+                continue;
+            }
+            self.blocks_seen += 1;
+
+            // A long run starts with a look at what it's about to measure, in case a symbol resolved
+            // to the wrong token or the pair barely has pools.
+            if self.blocks_seen == 1 && self.settings.preflight {
+                self.preflight();
+                if !self.settings.yes && !confirm("Start the run?")? {
+                    break;
+                }
+            }
+            if self.settings.mode != RunMode::Measure {
+                self.run_once()?;
+                break;
+            }
+
+            // A block at or below one already written replaces it: the chain reorganized. Append-only
+            // files can't take back what they have, so the orphaned heights are marked instead.
+            let orphaned_to: Option<u64> = self.last_written.filter(|last| block.block_number <= *last);
+            if let Some(last) = orphaned_to {
+                self.console.line(format!(
+                    "reorg: #{} replaces blocks #{} to #{} already written; their records are superseded",
+                    block.block_number, block.block_number, last
+                ));
+                tracing::warn!(block = block.block_number, orphaned_to = last, "chain reorganization");
+                self.sinks.orphan(block.block_number, last)?;
+            }
+            self.sinks.reorg = orphaned_to.is_some();
+            self.last_written = Some(block.block_number);
+
+            self.console.line(self.renderer.block_header(
+                block.block_number,
+                updated_states,
+                block.new_pairs.len(),
+                block.removed_pairs.len(),
+            ));
+            let prices: BlockPrices = self.price_block(block.block_number).await?;
+            let mut block_report: BlockReport = BlockReport {
+                block_number: block.block_number,
+                chain: self.settings.chain.to_string(),
+                native_price: prices.native_price.clone(),
+                labels: self.settings.labels.clone(),
+                reorg: self.sinks.reorg,
+                ..Default::default()
+            };
+            let charts: Vec<DepthChart> = self.measure_block(&block, &prices, &mut block_rx, &mut block_report).await?;
+            let lag: BlockLag = BlockLag { queued: block_rx.len(), measured_in: received.elapsed() };
+            self.write_block(&block_report, &charts, timestamp, lag)?;
+
+            if let Some(monitor) = self.soak.as_mut() {
+                monitor.maybe_sample(
+                    block.block_number,
+                    [
+                        ("tracked_pairs", self.tracked_pairs.len()),
+                        ("tracked_states", self.tracked_states.len()),
+                        ("pool_cache", self.pool_cache.len()),
+                    ],
+                );
+                if monitor.is_finished() {
+                    break;
+                }
+            } else if self.settings.max_blocks > 0 && self.blocks_seen >= self.settings.max_blocks {
+                self.console.line(format!("Seen {} blocks", self.blocks_seen));
+                break;
+            }
+        }
+        self.finish(&restarts)
+    }
+
+    /// A function to take a block's pools and states in: record the block, follow pools added and
+    /// removed (all of them on a resync snapshot), say what changed since a checkpoint, and share
+    /// the new states.
+    ///
+    /// Returns:
+    /// - How many states the block updated, counting those read ahead
+    fn track(
+        &mut self,
+        block: &mut BlockUpdate,
+        snapshot: bool,
+        timestamp: SystemTime,
+        states_ahead: HashMap<String, Arc<dyn ProtocolSim>>,
+    ) -> anyhow::Result<usize> {
+        let chain: Chain = self.settings.chain;
+        if let Some(dir) = &self.settings.record_dir {
+            let secs: u64 = timestamp.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+            let recorded: RecordedBlock = RecordedBlock::capture(block, snapshot, secs);
+            if snapshot && !recorded.unrecorded.is_empty() {
+                self.console.line(format!(
+                    "recording #{}: {} pools have states that can't be recorded, and won't be measured on backfill",
+                    block.block_number,
+                    recorded.unrecorded.len()
+                ));
+            }
+            write_block(dir, &chain.to_string(), &recorded)
+                .map_err(|e| anyhow::anyhow!("--record {}: {}", dir.display(), e))?;
+        }
+        // The stream is subscribed to the allowlist only, but a pool outside it is never measured.
+        let pools: &[String] = &self.settings.pools;
+        if !pools.is_empty() {
+            block.new_pairs.retain(|id, _| pools.contains(&id.to_lowercase()));
+            block.states.retain(|id, _| pools.contains(&id.to_lowercase()));
+        }
+        // A reconnected stream starts over with a full snapshot. Everything else carries over, but
+        // pools removed while it was down never get a removal, so drop whatever the snapshot lacks.
+        if snapshot && !self.tracked_pairs.is_empty() {
+            let before: usize = self.tracked_pairs.len();
+            self.tracked_pairs.retain(|id, _| block.new_pairs.contains_key(id));
+            self.pool_protocols.retain(|id, _| block.new_pairs.contains_key(id));
+            self.tracked_states.retain(|id, _| block.new_pairs.contains_key(id));
+            self.warm_starts.retain(|id| block.new_pairs.contains_key(id));
+            self.pool_cache.retain(|(_, id), _| block.new_pairs.contains_key(id));
+            if self.tracked_pairs.len() < before {
+                tracing::info!("dropped {} pools missing from the resync snapshot", before - self.tracked_pairs.len());
+            }
+        }
+        for (id, pool) in block.new_pairs.iter() {
+            self.tracked_pairs.insert(id.clone(), pool.tokens.clone());
+            self.pool_protocols.insert(id.clone(), pool.protocol_system.clone());
+            if let Some(bundle) = self.sinks.bundle.as_mut() {
+                bundle.add_pool(id, pool);
+            }
+        }
+        for id in block.removed_pairs.keys() {
+            self.tracked_pairs.remove(id);
+            self.pool_protocols.remove(id);
+        }
+        if snapshot {
+            for id in pools.iter().filter(|id| !self.tracked_pairs.keys().any(|tracked| tracked.to_lowercase() == **id)) {
+                tracing::warn!("--pools: {} isn't in the {} snapshot, check the id and chain", id, chain);
+            }
+        }
+        if let Some(checkpoint) = self.resumed.take() {
+            match checkpoint.gap(block.block_number) {
+                Some((from_block, to_block)) => {
+                    self.console.line(format!(
+                        "resuming after #{}: blocks #{} to #{} were missed while stopped and can't be streamed again",
+                        checkpoint.block_number, from_block, to_block
+                    ));
+                    self.sinks.add_result(serde_json::json!({
+                        "block_number": block.block_number,
+                        "event": "gap",
+                        "from_block": from_block,
+                        "to_block": to_block,
+                        "missed_blocks": to_block - from_block + 1,
+                    }))?;
+                }
+                None => self.console.line(format!("resuming after #{}", checkpoint.block_number)),
+            }
+            let (gone, new) = checkpoint.pool_changes(self.tracked_pairs.keys().map(String::as_str));
+            if !gone.is_empty() || !new.is_empty() {
+                self.console.line(format!("   {} pools gone and {} new since the checkpoint", gone.len(), new.len()));
+                tracing::info!(?gone, ?new, "pools changed since the checkpoint");
+            }
+        }
+        self.warm_starts.retain(|id| !block.removed_pairs.contains_key(id));
+        self.pool_cache.retain(|(_, id), _| !block.removed_pairs.contains_key(id));
+        if self.sinks.partition == Some(Partition::Protocol) {
+            self.sinks.pool_protocols.clone_from(&self.pool_protocols);
+        }
+
+        let updated_states: usize = block.states.len() + states_ahead.len();
+        let arrived = std::mem::take(&mut block.states).into_iter().map(|(id, state)| (id, Arc::from(state)));
+        for (id, state) in arrived.chain(states_ahead) {
+            self.changed_pools.insert(id.clone());
+            self.tracked_states.insert(id, state);
+        }
+        Ok(updated_states)
+    }
+
+    /// A function to take in every VM block streamed so far, for --cross-check.
+    fn track_vm(&mut self, vm_rx: &mut mpsc::Receiver<StreamBlock>) {
+        while let Ok(StreamBlock { snapshot, failover, update: vm_update, .. }) = vm_rx.try_recv() {
+            if let Some(failover) = failover {
+                tracing::warn!("vm stream failed over: {}", failover);
+            }
+            if snapshot {
+                self.vm_states.clear();
+            }
+            self.vm_block = Some(vm_update.block_number);
+            for (id, state) in vm_update.states.into_iter() {
+                self.vm_states.insert(id, Arc::from(state));
+            }
+        }
+    }
+
+    /// A function to decide whether a block is measured, or only builds up state: it's before a
+    /// backfill's range, already written, or off the sample schedule.
+    fn is_due(&mut self, block_number: u64, snapshot: bool) -> bool {
+        // Recorded blocks before a backfill's range only build up state.
+        if self.settings.replay.as_ref().is_some_and(|replay| replay.is_before_range(block_number)) {
+            return false;
+        }
+        // A new connection, after a failover or a reconnect, can start on an endpoint a few blocks
+        // behind the last one written. Those heights are delivered again, not reorganized, so they
+        // only build up state too, and nothing already written is orphaned.
+        if snapshot && self.resume_after.is_none() {
+            if let Some(last) = self.last_written.filter(|last| block_number <= *last) {
+                self.console.line(format!(
+                    "the new connection starts at #{}, behind #{} already written; catching up",
+                    block_number, last
+                ));
+                tracing::info!(block = block_number, last_written = last, "new connection is behind");
+                self.resume_after = Some(last);
+            }
+        }
+        // So do blocks a resumed run already measured.
+        if self.resume_after.is_some_and(|last| block_number <= last) {
+            return false;
+        }
+        self.resume_after = None;
+        // Off-schedule blocks only update state.
+        let schedule: SampleSchedule = self.settings.schedule;
+        if !self.announced_schedule && schedule.every() > 1 {
+            self.announced_schedule = true;
+            let next: u64 = schedule.next_due(block_number);
+            self.console.line(format!("first sampled block: #{} (stream is at #{})", next, block_number));
+            tracing::info!(block = next, "first sampled block");
+        }
+        schedule.is_due(block_number)
+    }
+
+    /// A function to collect the pools of one pair that have a state, as (id, state).
+    fn pair_pools(&self, pair: &TrackedPair) -> PoolList<'_> {
+        self.tracked_pairs
+            .iter()
+            .filter(|(_, tokens)| *tokens == &pair.tokens)
+            .filter_map(|(id, _)| self.tracked_states.get(id).map(|state| (id.clone(), state.as_ref())))
+            .collect()
+    }
+
+    /// A function to print each pair's pre-flight report.
+    fn preflight(&self) {
+        for pair in self.pairs.iter() {
+            let preflight: PairPreflight = preflight_pair(
+                &pair.label,
+                &self.pair_pools(pair),
+                &self.pool_protocols,
+                &pair.token_in,
+                &pair.token_out,
+                pair.slippages[0],
+            );
+            self.console.line(&preflight);
+        }
+    }
+
+    /// A function to do what `unwind` and `orderbook` do, once, on the first block.
+    fn run_once(&mut self) -> anyhow::Result<()> {
+        let mut books: Vec<OrderBook> = Vec::with_capacity(self.pairs.len());
+        for pair in self.pairs.iter() {
+            let pools: PoolList = self.pair_pools(pair);
+            match &self.settings.mode {
+                RunMode::Unwind { notional, targets } => {
+                    let mut report = estimate_unwind(
+                        &pools,
+                        &pair.token_in,
+                        &pair.token_out,
+                        *notional,
+                        targets,
+                        self.settings.measure.precision,
+                    )?;
+                    report.token_in = self.settings.names.symbol(&pair.token_in).to_string();
+                    report.token_out = self.settings.names.symbol(&pair.token_out).to_string();
+                    self.console.line(&report);
+                    self.sinks.add_result(&report)?;
+                }
+                RunMode::Orderbook { step_bps, levels, .. } => {
+                    match order_book(&pair.label, &pools, &pair.token_in, &pair.token_out, *step_bps, *levels) {
+                        Ok(book) => {
+                            if self.settings.output == OutputFormat::Json {
+                                println!("{}", serde_json::to_string(&book)?);
+                            }
+                            self.console.line(&book);
+                            self.sinks.add_result(&book)?;
+                            books.push(book);
+                        }
+                        Err(e) => self.console.line(format!("{}: no order book: {}", pair.label, e)),
+                    }
+                }
+                RunMode::Measure => {}
+            }
+        }
+        if let RunMode::Orderbook { export: Some(path), .. } = &self.settings.mode {
+            export_order_books(path, &books).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+            self.console.line(self.renderer.wrote_file("order books", &path.display().to_string()));
+        }
+        Ok(())
+    }
+
+    /// A function to price the block once, so gas and USD conversions downstream agree on it: the
+    /// native token, each pair's token_in in dollars, its trade sizes and its oracle mark.
+    async fn price_block(&mut self, block_number: u64) -> anyhow::Result<BlockPrices> {
+        let priced_pools: Vec<(&str, &[Token], &dyn ProtocolSim)> = self
+            .tracked_pairs
+            .iter()
+            .filter_map(|(id, tokens): (&String, &Vec<Token>)| {
+                self.tracked_states.get(id).map(|state| (id.as_str(), tokens.as_slice(), state.as_ref()))
+            })
+            .collect();
+        let native_price: Option<NativePrice> =
+            native_token_price(priced_pools.iter().copied(), &self.native_addresses, &self.stable_addresses);
+        if let Some(native_price) = &native_price {
+            self.console.line(self.renderer.native_price(
+                &native_price.native,
+                native_price.price,
+                &native_price.stable,
+                &native_price.pool_id,
+            ));
+            self.sinks.add_result(serde_json::json!({
+                "block_number": block_number,
+                "native_price": native_price,
+            }))?;
+        }
+
+        let mut base_usd: Vec<Option<f64>> = Vec::with_capacity(self.pairs.len());
+        for pair in self.pairs.iter_mut() {
+            let price: Option<UsdPrice> = match pair.usd_oracle.as_mut() {
+                Some(oracle) => match oracle.price().await {
+                    Ok(price) => Some(UsdPrice { price, source: "oracle".to_string() }),
+                    Err(error) => {
+                        tracing::warn!("{}: no dollar price this block: {}", pair.label, error);
+                        None
+                    }
+                },
+                None if self.settings.usd => token_usd_price(
+                    &pair.token_in,
+                    &priced_pools,
+                    &self.native_addresses,
+                    &self.stable_addresses,
+                    native_price.as_ref(),
+                ),
+                None => None,
+            };
+            if let Some(price) = &price {
+                self.console.line(self.renderer.usd_price(self.settings.names.symbol(&pair.token_in), price.price, &price.source));
+            }
+            base_usd.push(price.map(|price| price.price));
+        }
+        let notional_amounts: Vec<Vec<Result<U256, String>>> = self
+            .pairs
+            .iter()
+            .zip(base_usd.iter())
+            .map(|(pair, usd_price)| {
+                self.settings.notionals.iter().map(|notional| notional.amount_in(&pair.token_in, *usd_price)).collect()
+            })
+            .collect();
+
+        let mut marks: Vec<Option<f64>> = Vec::with_capacity(self.pairs.len());
+        let mut unmarked: Vec<bool> = Vec::with_capacity(self.pairs.len());
+        for pair in self.pairs.iter_mut() {
+            let mark: Option<Result<f64, OracleError>> = match pair.oracle.as_mut() {
+                Some(oracle) => Some(oracle.price().await),
+                None => None,
+            };
+            if let Some(Err(error)) = &mark {
+                self.sinks.add_result(serde_json::json!({
+                    "block_number": block_number,
+                    "pair": &pair.label,
+                    "reference_error": error.to_string(),
+                }))?;
+            }
+            unmarked.push(matches!(mark, Some(Err(_))));
+            marks.push(mark.and_then(|mark| mark.ok()));
+        }
+        Ok(BlockPrices { native_price, base_usd, notional_amounts, marks, unmarked })
+    }
+
+    /// A function to measure every pool of every tracked pair and then each pair as a whole, writing
+    /// the records as they're emitted and filling in the block report.
+    ///
+    /// Returns:
+    /// - Each pair's depth chart, for --charts-dir
+    async fn measure_block(
+        &mut self,
+        block: &BlockUpdate,
+        prices: &BlockPrices,
+        block_rx: &mut mpsc::Receiver<StreamBlock>,
+        block_report: &mut BlockReport,
+    ) -> anyhow::Result<Vec<DepthChart>> {
+        let settings: &RunSettings = &self.settings;
+        let names: &DisplayNames = &settings.names;
+        let renderer: &(dyn ConsoleRenderer + Sync) = self.renderer.as_ref();
+        let mut rows: Vec<(String, String)> = Vec::new();
+        for (pair, unmarked) in self.pairs.iter().zip(prices.unmarked.iter()) {
+            if *unmarked {
+                let error: String = "no mark this block".to_string();
+                rows.push((pair.label.clone(), renderer.reference_unavailable(&pair.label, &error)));
+            }
+        }
+
+        // Every pool of every tracked pair, in the order of `pairs`. Pairs missing their mark
+        // measure nothing: desks that measure against their own marks don't want spot substituted.
+        let pool_inputs = |i: usize| PoolInputs {
+            mark: prices.marks[i],
+            base_usd: prices.base_usd[i],
+            notional_amounts: prices.notional_amounts[i].clone(),
+        };
+        let context: BlockContext = BlockContext {
+            settings: &settings.measure,
+            names,
+            renderer,
+            pairs: &self.pairs,
+            pool_protocols: &self.pool_protocols,
+            protocol_owned: &settings.protocol_owned,
+            block_number: block.block_number,
+            warm_starts: &self.warm_starts,
+            notionals: &settings.notionals,
+            notional_amounts: &prices.notional_amounts,
+            base_usd: &prices.base_usd,
+            marks: &prices.marks,
+            sample_curves: self.sinks.bundle.is_some() && settings.measure.curve_samples > 0,
+            chart: settings.chart || settings.charts_dir.is_some(),
+        };
+        // Under --parallel-blocks, the first block of a batch reads the next ones ahead and measures
+        // them all side by side, each against its own copy of the states; later blocks of the batch
+        // pick their pools up here, and everything is still written in block order below.
+        if settings.parallel_blocks > 1 && !self.measured_ahead.contains_key(&block.block_number) {
+            let due_ahead = |ahead: &VecDeque<AheadBlock>| {
+                ahead.iter().filter(|(next, _)| settings.schedule.is_due(next.update.block_number)).count()
+            };
+            while due_ahead(&self.ahead) + 1 < settings.parallel_blocks {
+                let Some(next) = block_rx.recv().await else { break };
+                self.ahead.push_back(read_ahead(next, &settings.pools));
+            }
+            self.measured_ahead = tokio::task::block_in_place(|| {
+                measure_ahead(
+                    &context,
+                    &self.tracked_pairs,
+                    &self.tracked_states,
+                    &self.ahead,
+                    &settings.schedule,
+                    settings.usd.then_some((self.native_addresses.as_slice(), self.stable_addresses.as_slice())),
+                    settings.workers,
+                    &settings.concurrency,
+                )
+            });
+        }
+        let mut block_ahead: HashMap<(usize, String), MeasuredAhead> =
+            self.measured_ahead.remove(&block.block_number).unwrap_or_default();
+        // States from different blocks would disagree for no reason, so only compare in lockstep.
+        let vm_in_sync: bool = self.vm_block == Some(block.block_number);
+        let mut jobs: Vec<PoolJob> = Vec::new();
+        for (i, pair) in self.pairs.iter().enumerate().filter(|(i, _)| !prices.unmarked[*i]) {
+            // In id order, so the same blocks always give the same records in the same order.
+            let mut ids: Vec<&String> =
+                self.tracked_pairs.iter().filter(|(_, tokens)| *tokens == &pair.tokens).map(|(id, _)| id).collect();
+            ids.sort_unstable();
+            for id in ids.into_iter() {
+                // Announced but not yet given a state; it'll be measured once it has one.
+                let Some(state) = self.tracked_states.get(id) else { continue };
+                let vm_state: Option<&dyn ProtocolSim> =
+                    self.vm_states.get(id).filter(|_| vm_in_sync).map(|state| state.as_ref());
+                // Cross-checks compare against a VM state that moves on its own, so they always rerun.
+                let replay: Option<PoolOutcome> = block_ahead
+                    .remove(&(i, id.clone()))
+                    .filter(|ahead| ahead.matches(state, &pool_inputs(i)))
+                    .map(|ahead| ahead.outcome)
+                    .or_else(|| {
+                        self.pool_cache
+                            .get(&(i, id.clone()))
+                            .filter(|_| !self.changed_pools.contains(id) && vm_state.is_none())
+                            .filter(|cached| cached.inputs == pool_inputs(i))
+                            .map(|cached| cached.replay(block.block_number))
+                    });
+                jobs.push(PoolJob { pair: i, id, state: state.as_ref(), vm_state, replay });
+            }
+        }
+        // The searches are most of the block's time, so pools are measured side by side and
+        // emitted in order once they're all done.
+        let outcomes: Vec<anyhow::Result<PoolOutcome>> = tokio::task::block_in_place(|| {
+            map_limited(
+                &jobs,
+                settings.workers,
+                // Replays don't simulate, so only fresh measurements count against a protocol's limit.
+                |job| match &job.replay {
+                    Some(_) => None,
+                    None => self.pool_protocols.get(job.id).and_then(|protocol| settings.concurrency.get(protocol)),
+                },
+                |job| match &job.replay {
+                    Some(replay) => Ok(replay.clone()),
+                    None => measure_pool(&context, job).map(PoolOutcome::tag_protocol_owned),
+                },
+            )
+        });
+        self.changed_pools.clear();
+
+        // (matched, reliable) pools per tracked pair, in the order of `pairs`.
+        let mut pair_pools: Vec<(usize, PoolList)> = self.pairs.iter().map(|_| (0, Vec::new())).collect();
+        let mut charts: Vec<DepthChart> =
+            self.pairs.iter().map(|pair| DepthChart::new(&pair.label, names.symbol(&pair.token_in))).collect();
+        for (job, outcome) in jobs.iter().zip(outcomes) {
+            let outcome: PoolOutcome = outcome?;
+            if job.replay.is_none() {
+                self.pool_cache.insert(
+                    (job.pair, job.id.clone()),
+                    CachedPool { block_number: block.block_number, inputs: pool_inputs(job.pair), outcome: outcome.clone() },
+                );
+            }
+            rows.extend(outcome.rows);
+            for record in outcome.records.iter() {
+                self.sinks.add_result(record)?;
+            }
+            let (matched_pools, reliable_pools) = &mut pair_pools[job.pair];
+            *matched_pools += 1;
+            if outcome.reliable {
+                if let Some(curve) = outcome.curve.as_ref() {
+                    charts[job.pair].add_curve(job.id, curve, self.pairs[job.pair].token_in.decimals);
+                }
+                reliable_pools.push((job.id.clone(), job.state));
+                if let Some(bundle) = self.sinks.bundle.as_mut() {
+                    bundle.add_state(block.block_number, job.id, job.state);
+                    for record in outcome.bundle_records.iter() {
+                        bundle.add_result(self.sinks.labels.attach(record)?)?;
+                    }
+                }
+            }
+            if let Some(cross_check) = outcome.disagreement {
+                self.disagreeing_pools.insert(job.id.clone(), cross_check);
+            }
+            if let Some(check) = outcome.unreliable {
+                self.unreliable_pools.insert(job.id.clone(), check);
+            }
+            if settings.warm_start {
+                let targets: &[f64] = &self.pairs[job.pair].slippages;
+                for (token_in, results) in outcome.converged.iter() {
+                    self.warm_starts.record(job.id, token_in, targets, results);
+                }
+            }
+            block_report.pools.push(outcome.report);
+        }
+        let mut skipped_pools: usize = 0;
+        for (id, tokens) in self.tracked_pairs.iter() {
+            if !self.pairs.iter().any(|pair| &pair.tokens == tokens) {
+                rows.push((id.clone(), renderer.skipped_pool(names.symbol(&tokens[0]), names.symbol(&tokens[1]))));
+                skipped_pools += 1;
+            }
+        }
+
+        let frame = self.throttle.frame(rows);
+        for row in frame.rows.iter() {
+            self.console.line(row);
+        }
+        if frame.suppressed > 0 {
+            self.console.line(renderer.hidden_rows(frame.suppressed));
+        }
+        let precision: f64 = settings.measure.precision;
+        let (max_iterations, bracket_tvl_multiple): (u32, f64) =
+            (settings.measure.max_iterations, settings.measure.bracket_tvl_multiple);
+        for (i, ((pair, (matched_pools, reliable_pools)), mark)) in
+            self.pairs.iter().zip(pair_pools.iter()).zip(prices.marks.iter()).enumerate()
+        {
+            let (token_in, token_out): (&Token, &Token) = (&pair.token_in, &pair.token_out);
+            self.console.line(renderer.pair_summary(&pair.label, *matched_pools, skipped_pools));
+            if settings.chart && !charts[i].is_empty() {
+                self.console.line(renderer.depth_chart(&charts[i]));
+            }
+
+            // Total market depth: one order split across every reliable pool of the pair.
+            let aggregate_reference = || -> Result<f64, DepthError> {
+                mark.map_or_else(|| best_spot_price(reliable_pools, token_in, token_out), Ok)
+                    .map(|base| match settings.measure.price_shift {
+                        Some(shift) => shift.apply(base),
+                        None => base,
+                    })
+            };
+            if !settings.notionals.is_empty() {
+                if reliable_pools.is_empty() {
+                    continue;
+                }
+                for (notional, amount_in) in settings.notionals.iter().zip(prices.notional_amounts[i].iter()) {
+                    let reference: Result<f64, DepthError> = aggregate_reference();
+                    let fill: Result<AggregateDepth, String> = amount_in.clone().and_then(|amount_in| {
+                        reference
+                            .as_ref()
+                            .map_err(|e| e.to_string())
+                            .and_then(|reference| {
+                                aggregate_fill_against_reference(
+                                    reliable_pools,
+                                    amount_in,
+                                    token_in,
+                                    token_out,
+                                    DEFAULT_SPLIT_CHUNKS,
+                                    *reference,
+                                )
+                                .map_err(|e| e.to_string())
+                            })
+                    });
+                    let fee: Option<f64> = fill.as_ref().ok().and_then(|fill| {
+                        routed_fee(&fill.allocations, |pool_id| {
+                            reliable_pools.iter().find(|(id, _)| id == pool_id).and_then(|(_, state)| pool_fee(*state))
+                        })
+                    });
+                    let sell: Leg = Leg::sell(token_in, token_out, *reference.as_ref().unwrap_or(&f64::NAN))
+                        .with_base_usd(prices.base_usd[i])
+                        .with_fee(fee);
+                    let report: AggregateFillReport = AggregateFillReport {
+                        pair: pair.label.clone(),
+                        pools: reliable_pools.len(),
+                        fill: FillReport::new(
+                            &notional.to_string(),
+                            &sell,
+                            fill.as_ref().map(|f| (f.amount_in, f.amount_out)).map_err(Clone::clone),
+                        ),
+                    };
+                    self.console.line(renderer.aggregate_fill(
+                        &pair.label,
+                        reliable_pools.len(),
+                        &report.fill.notional,
+                        &describe_fill(names, &sell, &report.fill)?,
+                    ));
+                    self.sinks.add_result(serde_json::json!({
+                        "block_number": block.block_number,
+                        "pair": &pair.label,
+                        "action": sell.action,
+                        "base": names.symbol(token_in),
+                        "quote": names.symbol(token_out),
+                        "notional": &report.fill.notional,
+                        "aggregate_base_amount": &report.fill.base_amount,
+                        "aggregate_quote_amount": &report.fill.quote_amount,
+                        "aggregate_usd_value": report.fill.usd_value,
+                        "aggregate_base_tokens": &report.fill.conversions.base_tokens,
+                        "aggregate_quote_tokens": &report.fill.conversions.quote_tokens,
+                        "aggregate_quote_usd_value": report.fill.conversions.quote_usd_value,
+                        "slippage": report.fill.slippage,
+                        "fee_slippage": report.fill.fee_slippage,
+                        "impact_slippage": report.fill.impact_slippage,
+                        "allocations": fill.as_ref().ok().map(|f| &f.allocations),
+                        "error": &report.fill.error,
+                    }))?;
+                    block_report.aggregate_fills.push(report);
+                }
+                if settings.impact_table {
+                    self.console.line(renderer.impact_table(&block_report.impact_table(&pair.label)));
+                }
+                continue;
+            }
+            // An exact output is solved per pool only.
+            if settings.measure.exact_output.is_some() {
+                continue;
+            }
+            for &slippage in pair.slippages.iter().filter(|_| !reliable_pools.is_empty()) {
+                let reference: Result<f64, DepthError> = aggregate_reference();
+                // Without a reference there's no aggregate either, so any placeholder does.
+                let placeholder: f64 = *reference.as_ref().unwrap_or(&f64::NAN);
+                let aggregate_over = |pools: &[(String, &dyn ProtocolSim)], reference: f64| {
+                    aggregate_depth_against_reference(
+                        pools,
+                        slippage,
+                        precision,
+                        token_in,
+                        token_out,
+                        DEFAULT_SPLIT_CHUNKS,
+                        reference,
+                        max_iterations,
+                        bracket_tvl_multiple,
+                    )
+                };
+                let aggregate: Result<AggregateDepth, DepthError> =
+                    reference.and_then(|reference| aggregate_over(reliable_pools, reference));
+                // The split order pays each pool's fee on the share it routes there.
+                let fee: Option<f64> = aggregate.as_ref().ok().and_then(|aggregate| {
+                    routed_fee(&aggregate.allocations, |pool_id| {
+                        reliable_pools.iter().find(|(id, _)| id == pool_id).and_then(|(_, state)| pool_fee(*state))
+                    })
+                });
+                let sell: Leg = Leg::sell(token_in, token_out, placeholder).with_base_usd(prices.base_usd[i]).with_fee(fee);
+                let mut report: AggregateReport =
+                    AggregateReport::new(&pair.label, slippage, reliable_pools.len(), &sell, aggregate.as_ref());
+                if let Ok(aggregate) = &aggregate {
+                    report.stability = Some(self.depth_history.add(&pair.label, slippage, f64::from(aggregate.amount_in)));
+                    report.by_protocol = ProtocolShare::breakdown(
+                        &aggregate.allocations,
+                        |id| self.pool_protocols.get(id).cloned().unwrap_or_default(),
+                        &sell,
+                    );
+                }
+                // Depth again without protocol-owned liquidity, if the pair has any.
+                let organic_pools: Vec<(String, &dyn ProtocolSim)> = reliable_pools
+                    .iter()
+                    .filter(|(id, _)| !settings.protocol_owned.contains(&id.to_lowercase()))
+                    .cloned()
+                    .collect();
+                let protocol_owned_pools: usize = reliable_pools.len() - organic_pools.len();
+                if protocol_owned_pools > 0 && organic_pools.is_empty() {
+                    report.organic = Some(OrganicDepth::empty(protocol_owned_pools));
+                } else if protocol_owned_pools > 0 {
+                    let organic: Result<AggregateDepth, DepthError> =
+                        aggregate_reference().and_then(|reference| aggregate_over(&organic_pools, reference));
+                    report.organic = Some(OrganicDepth::new(
+                        protocol_owned_pools,
+                        AggregateReport::new(&pair.label, slippage, organic_pools.len(), &sell, organic.as_ref()),
+                    ));
+                }
+                // Depth again over each protocol set, against the same reference, to tell what the
+                // venues in one and not the other add.
+                if let Some(sets) = &settings.protocol_sets {
+                    let [a, b]: [SetDepth; 2] = sets.each_ref().map(|set| {
+                        let set_pools: Vec<(String, &dyn ProtocolSim)> = reliable_pools
+                            .iter()
+                            .filter(|(id, _)| self.pool_protocols.get(id).is_some_and(|protocol| set.matches(protocol)))
+                            .cloned()
+                            .collect();
+                        if set_pools.is_empty() {
+                            return SetDepth::empty(&set.name);
+                        }
+                        let depth: Result<AggregateDepth, DepthError> =
+                            aggregate_reference().and_then(|reference| aggregate_over(&set_pools, reference));
+                        SetDepth::new(
+                            &set.name,
+                            AggregateReport::new(&pair.label, slippage, set_pools.len(), &sell, depth.as_ref()),
+                        )
+                    });
+                    report.comparison = Some(ProtocolComparison::new(a, b, token_in.decimals));
+                }
+                let aggregate_text: String = match &aggregate {
+                    Ok(aggregate) => format!(
+                        "{} {} → {} {} ({} simulations{}{}{}{}{})",
+                        format_amount(aggregate.amount_in, token_in.decimals),
+                        names.symbol(token_in),
+                        format_amount(aggregate.amount_out, token_out.decimals),
+                        names.symbol(token_out),
+                        aggregate.simulations,
+                        describe_attribution(report.fee_slippage, report.impact_slippage),
+                        describe_protocol_shares(&report.by_protocol),
+                        describe_stability(report.stability.as_ref()),
+                        describe_organic(report.organic.as_ref(), token_in, names)?,
+                        describe_comparison(report.comparison.as_ref(), token_in, names)?,
+                    ),
+                    Err(e) => format!("failed: {}", e),
+                };
+                self.console.line(renderer.aggregate_depth(&pair.label, reliable_pools.len(), slippage, &aggregate_text));
+                self.sinks.add_result(serde_json::json!({
+                    "block_number": block.block_number,
+                    "pair": &pair.label,
+                    "action": sell.action,
+                    "base": names.symbol(token_in),
+                    "quote": names.symbol(token_out),
+                    "target_slippage": slippage,
+                    "aggregate_base_amount": &report.base_amount,
+                    "aggregate_quote_amount": &report.quote_amount,
+                    "aggregate_usd_value": report.usd_value,
+                    "aggregate_base_tokens": &report.conversions.base_tokens,
+                    "aggregate_quote_tokens": &report.conversions.quote_tokens,
+                    "aggregate_quote_usd_value": report.conversions.quote_usd_value,
+                    "slippage": report.slippage,
+                    "fee_slippage": report.fee_slippage,
+                    "impact_slippage": report.impact_slippage,
+                    "allocations": aggregate.as_ref().ok().map(|a| &a.allocations),
+                    "converged": aggregate.as_ref().ok().map(|a| a.converged),
+                    "iterations": aggregate.as_ref().ok().map(|a| a.iterations),
+                    "stability": &report.stability,
+                    "organic": &report.organic,
+                    "comparison": &report.comparison,
+                    "by_protocol": &report.by_protocol,
+                    "error": &report.error,
+                }))?;
+                if settings.replay.as_ref().is_some_and(|replay| replay.range.is_some()) {
+                    let point: SeriesPoint = SeriesPoint {
+                        block_number: block.block_number,
+                        amount_in: aggregate.as_ref().ok().map(|aggregate| aggregate.amount_in),
+                        usd_value: report.usd_value,
+                    };
+                    self.series.push(&pair.label, names.symbol(token_in), token_in.decimals, slippage, point);
+                }
+                let base_tokens: Option<f64> = aggregate
+                    .as_ref()
+                    .ok()
+                    .map(|aggregate| f64::from(aggregate.amount_in) / 10f64.powi(token_in.decimals as i32));
+                let fired = self.alerts.observe(
+                    &pair.label,
+                    slippage,
+                    block.block_number,
+                    base_tokens,
+                    report.usd_value,
+                    names.symbol(token_in),
+                );
+                for alert in fired.into_iter() {
+                    self.console.line(&alert);
+                    self.sinks.add_result(serde_json::json!({
+                        "block_number": block.block_number,
+                        "event": "alert",
+                        "alert": &alert,
+                    }))?;
+                    for notifier in settings.notifiers.iter() {
+                        if let Err(e) = notifier.notify(&alert) {
+                            tracing::warn!("{} notifier failed: {}", notifier.name(), e);
+                        }
+                    }
+                }
+                block_report.aggregates.push(report);
+            }
+            // The widest gap between the pair's pools' spots, and what trading across it takes.
+            if let Some(min_bps) = settings.arb_bps.filter(|_| reliable_pools.len() > 1) {
+                match widest_divergence(&pair.label, reliable_pools, token_in, token_out, min_bps) {
+                    Ok(Some(divergence)) => {
+                        self.console.line(renderer.divergence(
+                            &pair.label,
+                            divergence.divergence_bps,
+                            &describe_divergence(&divergence, token_in, token_out, names),
+                        ));
+                        self.sinks.add_result(serde_json::json!({
+                            "block_number": block.block_number,
+                            "event": "divergence",
+                            "divergence": &divergence,
+                        }))?;
+                        block_report.divergences.push(divergence);
+                    }
+                    Ok(None) => {}
+                    Err(e) => tracing::warn!("{}: no divergence check this block: {}", pair.label, e),
+                }
+            }
+            // Depth along the deepest route through another token, measured against the route's
+            // own spot, since the direct pools may be too thin to price the pair at all.
+            if settings.two_hop {
+                let legs = two_hop_legs(
+                    self.tracked_pairs.iter().map(|(id, tokens): (&String, &Vec<Token>)| (id.as_str(), tokens.as_slice())),
+                    token_in,
+                    token_out,
+                );
+                let pool_of = |id: &str| self.tracked_states.get(id).map(|state| Pool::Tycho(state.as_ref()));
+                for &slippage in pair.slippages.iter() {
+                    let route: Result<RouteDepth, DepthError> =
+                        best_two_hop(&legs, pool_of, token_in, token_out, slippage, precision);
+                    let via: Option<&Token> = route.as_ref().ok().map(|r| &r.route.via);
+                    let sell: Leg = Leg::sell(token_in, token_out, route.as_ref().map_or(f64::NAN, |r| r.spot))
+                        .with_base_usd(prices.base_usd[i]);
+                    let report: RouteReport = RouteReport {
+                        pair: pair.label.clone(),
+                        via: via.map(|via| names.symbol(via).to_string()),
+                        via_address: via.map(|via| via.address.to_string()),
+                        first_pool: route.as_ref().ok().map(|r| r.route.first_pool.clone()),
+                        second_pool: route.as_ref().ok().map(|r| r.route.second_pool.clone()),
+                        depth: DepthReport::from_search(slippage, &sell, route.as_ref().map(|r| &r.depth)),
+                    };
+                    let route_text: String = match &route {
+                        Ok(route) => format!(
+                            "{} {} → {} {} (pools {} → {})",
+                            format_amount(route.depth.amount_in, token_in.decimals),
+                            names.symbol(token_in),
+                            format_amount(route.depth.amount_out, token_out.decimals),
+                            names.symbol(token_out),
+                            route.route.first_pool,
+                            route.route.second_pool,
+                        ),
+                        Err(e) => format!("failed: {}", e),
+                    };
+                    let via: &str = report.via.as_deref().unwrap_or("-");
+                    self.console.line(renderer.route_depth(&pair.label, via, slippage, &route_text));
+                    self.sinks.add_result(serde_json::json!({
+                        "block_number": block.block_number,
+                        "pair": &pair.label,
+                        "action": sell.action,
+                        "base": names.symbol(token_in),
+                        "quote": names.symbol(token_out),
+                        "target_slippage": slippage,
+                        "via": &report.via,
+                        "via_address": &report.via_address,
+                        "first_pool": &report.first_pool,
+                        "second_pool": &report.second_pool,
+                        "route_base_amount": &report.depth.base_amount,
+                        "route_quote_amount": &report.depth.quote_amount,
+                        "route_usd_value": report.depth.usd_value,
+                        "route_base_tokens": &report.depth.conversions.base_tokens,
+                        "route_quote_tokens": &report.depth.conversions.quote_tokens,
+                        "slippage": report.depth.slippage,
+                        "error": &report.depth.error,
+                    }))?;
+                    block_report.routes.push(report);
+                }
+            }
+        }
+        Ok(charts)
+    }
+
+    /// A function to hand a measured block to everything that keeps it: notifiers, stdout, the
+    /// sinks, the figures, the HTML report, the dashboard and the checkpoint, in that order.
+    ///
+    /// Args:
+    /// - block_time: The block's timestamp, which the database and Parquet files keep it under
+    fn write_block(
+        &mut self,
+        block_report: &BlockReport,
+        charts: &[DepthChart],
+        block_time: SystemTime,
+        lag: BlockLag,
+    ) -> anyhow::Result<()> {
+        if !block_report.aggregates.is_empty() {
+            let summary: String = describe_block_summary(block_report);
+            for notifier in self.settings.notifiers.iter() {
+                if let Err(e) = notifier.block_summary(&summary) {
+                    tracing::warn!("{} notifier failed: {}", notifier.name(), e);
+                }
+            }
+        }
+        if self.settings.output == OutputFormat::Json {
+            println!("{}", serde_json::to_string(block_report)?);
+        }
+        self.sinks.add_block(block_report, block_time)?;
+        // A crash loses at most the block in progress.
+        self.sinks.flush()?;
+        #[cfg(feature = "charts")]
+        if let Some(figures) = self.figures.as_mut() {
+            figures.write_block(block_report, charts).map_err(|e| anyhow::anyhow!("--charts-dir {}", e))?;
+        }
+        #[cfg(not(feature = "charts"))]
+        let _ = charts;
+        if let Some((html, _)) = self.html.as_mut() {
+            html.observe(block_report);
+        }
+        if let Some(tui) = self.tui.as_mut() {
+            tui.dashboard.observe(block_report, lag);
+            tui.draw()?;
+        }
+        // Saved only once the block is in every sink, so a restart never skips an unwritten block.
+        if let Some(path) = &self.settings.checkpoint_path {
+            let mut pools: Vec<String> = self.tracked_pairs.keys().cloned().collect();
+            pools.sort_unstable();
+            Checkpoint { chain: self.settings.chain.to_string(), block_number: block_report.block_number, pools }
+                .save(path)
+                .map_err(|e| anyhow::anyhow!("--checkpoint {}: {}", path.display(), e))?;
+        }
+        Ok(())
+    }
+
+    /// A function to print the run summary and close every sink.
+    fn finish(mut self, restarts: &RestartCounter) -> anyhow::Result<()> {
+        // The summary goes back to the terminal the dashboard was drawn over.
+        if self.tui.take().is_some() {
+            self.console.muted = false;
+        }
+        self.console.line(self.renderer.run_summary(
+            self.blocks_seen,
+            self.unreliable_pools.len(),
+            restarts.get(),
+            &self.settings.labels.to_string(),
+        ));
+        for (id, check) in self.unreliable_pools.iter() {
+            self.console.line(self.renderer.unreliable_pool(id, &check.to_string()));
+        }
+        for (id, cross_check) in self.disagreeing_pools.iter() {
+            self.console.line(self.renderer.simulator_disagreement(id, &cross_check.to_string()));
+        }
+        if let Some(monitor) = self.soak.take() {
+            self.console.line(monitor.report());
+        }
+        for depth_series in self.series.iter() {
+            self.console.line(depth_series);
+        }
+
+        self.sinks.flush()?;
+        if let Some(mut parquet) = self.sinks.parquet.take() {
+            let parquet_dir: &PathBuf = &self.settings.parquet_dir;
+            parquet.close().map_err(|e| anyhow::anyhow!("--parquet-dir {}: {}", parquet_dir.display(), e))?;
+            self.console.line(self.renderer.wrote_file("Parquet files", &parquet_dir.display().to_string()));
+        }
+        if let Some((html, path)) = self.html.take() {
+            fs::write(&path, html.render()).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+            self.console.line(self.renderer.wrote_file("HTML report", &path.display().to_string()));
+        }
+        if let (Some(bundle), Some(path)) = (self.sinks.bundle.take(), self.settings.bundle_path.as_ref()) {
+            bundle.write(path)?;
+            self.console.line(self.renderer.wrote_file("reproducibility bundle", &path.display().to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// A function to ask a yes/no question on the terminal. Without one, there's nobody to ask, so it
+/// fails and points at --yes instead of hanging or assuming yes.
+pub fn confirm(question: &str) -> anyhow::Result<bool> {
+    if !io::stdin().is_terminal() {
+        anyhow::bail!("stdin isn't a terminal, pass --yes to start a long run without confirming");
+    }
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush()?;
+    let mut answer: String = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// A function to wait for SIGINT or, on unix, SIGTERM.
+///
+/// Returns:
+/// - The name of the signal that arrived
+pub async fn shutdown_signal() -> anyhow::Result<&'static str> {
+    #[cfg(unix)]
+    {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result.map(|_| "SIGINT"),
+            _ = terminate.recv() => Ok("SIGTERM"),
+        }
+        .map_err(Into::into)
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await?;
+        Ok("SIGINT")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, path::Path};
+    use serde_json::Value;
+    use crate::{
+        backfill::{recording_tokens, replay_recording},
+        columnar::partition_dir,
+        history::{read_depth_history, PairHistory},
+        recording::write_block,
+    };
+    use super::*;
+
+    const POOL: &str = "0x0000000000000000000000000000000000000abc";
+
+    /// A three-block recording of one WETH/USDC pool, WETH getting cheaper every block. Blocks 100
+    /// and 101 are 12 seconds apart on 2023-11-14; 102 comes a day later.
+    fn recording(name: &str) -> PathBuf {
+        let dir: PathBuf = env::temp_dir().join(format!("liquidity-depth-run-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (block_number, timestamp) in [(100u64, 1_700_000_000u64), (101, 1_700_000_012), (102, 1_700_086_400)] {
+            let block: RecordedBlock = serde_json::from_value(serde_json::json!({
+                "block_number": block_number,
+                "snapshot": block_number == 100,
+                "timestamp": timestamp,
+                "new_pairs": [{
+                    "id": POOL,
+                    "protocol_system": "uniswap_v2",
+                    "protocol_type_name": "uniswap_v2_pool",
+                    "tokens": [
+                        { "address": "0x0000000000000000000000000000000000000001", "symbol": "WETH", "decimals": 18 },
+                        { "address": "0x0000000000000000000000000000000000000002", "symbol": "USDC", "decimals": 6 },
+                    ],
+                }],
+                "removed_pairs": [],
+                "states": {
+                    POOL: {
+                        "kind": "uniswap_v2",
+                        "reserve0": "1000000000000000000000",
+                        "reserve1": (3_000_000_000_000u64 - (block_number - 100) * 10_000_000_000).to_string(),
+                    },
+                },
+            }))
+            .unwrap();
+            write_block(&dir, "ethereum", &block).unwrap();
+        }
+        dir
+    }
+
+    /// A function to run the loop over a recording, measuring WETH/USDC at 1% and 2%.
+    ///
+    /// Returns:
+    /// - Every record written to the results file, in order
+    async fn run_recording(dir: &Path, adjust: impl FnOnce(&mut RunSettings)) -> Vec<Value> {
+        let replay: Replay = Replay::new(dir, None).unwrap();
+        let blocks: Vec<(u64, PathBuf)> = replay.blocks(Chain::Ethereum).unwrap();
+        let tokens: HashMap<Bytes, Token> = recording_tokens(&blocks).unwrap();
+        let token = |symbol: &str| tokens.values().find(|token| token.symbol == symbol).unwrap().clone();
+        let mut settings: RunSettings = RunSettings::new(Chain::Ethereum);
+        settings.plain = true;
+        settings.max_blocks = 0;
+        settings.replay = Some(replay);
+        settings.results_path = Some(dir.join("results.jsonl"));
+        adjust(&mut settings);
+        let results_path: PathBuf = settings.results_path.clone().unwrap();
+        let pair: TrackedPair =
+            TrackedPair::new(token("WETH"), token("USDC"), vec![0.01, 0.02], None, &settings.names).unwrap();
+
+        BlockLoop::new(settings, vec![pair])
+            .unwrap()
+            .run(replay_recording(blocks, Chain::Ethereum), None, RestartCounter::default(), std::future::pending())
+            .await
+            .unwrap();
+        fs::read_to_string(results_path).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+
+    /// The block number of every aggregate record, in order.
+    fn aggregate_blocks(records: &[Value]) -> Vec<u64> {
+        records
+            .iter()
+            .filter(|record| record.get("aggregate_base_amount").is_some())
+            .map(|record| record["block_number"].as_u64().unwrap())
+            .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn measures_every_block_of_a_recording_in_order() {
+        let dir: PathBuf = recording("all");
+        let checkpoint_path: PathBuf = dir.join("checkpoint.json");
+        let records: Vec<Value> =
+            run_recording(&dir, |settings| settings.checkpoint_path = Some(checkpoint_path.clone())).await;
+        assert_eq!(aggregate_blocks(&records), vec![100, 100, 101, 101, 102, 102]);
+        assert_eq!(Checkpoint::load(&checkpoint_path).unwrap().map(|checkpoint| checkpoint.block_number), Some(102));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn resumes_after_the_checkpoint_and_stops_after_max_blocks() {
+        let dir: PathBuf = recording("resume");
+        let records: Vec<Value> = run_recording(&dir, |settings| {
+            settings.checkpoint =
+                Some(Checkpoint { chain: "ethereum".to_string(), block_number: 100, pools: vec![POOL.to_string()] });
+            settings.max_blocks = 1;
+        })
+        .await;
+        assert_eq!(aggregate_blocks(&records), vec![101, 101]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn backfills_the_same_records_in_block_order_with_parallel_blocks() {
+        let dir: PathBuf = recording("parallel");
+        let backfill = |results: &'static str, parallel_blocks: usize| {
+            let results_path: PathBuf = dir.join(results);
+            move |settings: &mut RunSettings| {
+                settings.replay.as_mut().unwrap().range = Some((100, 102));
+                settings.results_path = Some(results_path);
+                settings.parallel_blocks = parallel_blocks;
+                // Blocks measured side by side start cold, so the sequential run does too.
+                settings.warm_start = false;
+            }
+        };
+        let sequential: Vec<Value> = run_recording(&dir, backfill("sequential.jsonl", 1)).await;
+        // Two at a time: 101 is read ahead and measured alongside 100, then 102 on its own.
+        let parallel: Vec<Value> = run_recording(&dir, backfill("parallel.jsonl", 2)).await;
+
+        assert_eq!(aggregate_blocks(&parallel), vec![100, 100, 101, 101, 102, 102]);
+        let blocks: Vec<u64> = parallel.iter().filter_map(|record| record["block_number"].as_u64()).collect();
+        assert!(blocks.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", blocks);
+        // Identical but for how long each search took.
+        let untimed = |mut records: Vec<Value>| {
+            for record in records.iter_mut().filter_map(Value::as_object_mut) {
+                record.remove("elapsed_ms");
+            }
+            records
+        };
+        assert_eq!(untimed(parallel), untimed(sequential));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn keeps_history_by_block_time() {
+        let dir: PathBuf = recording("history");
+        let sqlite_path: PathBuf = dir.join("depth.db");
+        run_recording(&dir, |settings| settings.sqlite_path = Some(sqlite_path.clone())).await;
+
+        // Hourly buckets by when the blocks were made, not when the run got to them.
+        let history: PairHistory = read_depth_history(&sqlite_path, "WETH/USDC", 0, u64::MAX, 3_600).unwrap();
+        let buckets: Vec<(u64, u64, u64)> =
+            history.targets[0].points.iter().map(|point| (point.start, point.first_block, point.last_block)).collect();
+        assert_eq!(buckets, vec![(1_699_999_200, 100, 101), (1_700_085_600, 102, 102)]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn partitions_parquet_by_block_date() {
+        let dir: PathBuf = recording("parquet");
+        let parquet_dir: PathBuf = dir.join("parquet");
+        run_recording(&dir, |settings| {
+            settings.output = OutputFormat::Parquet;
+            settings.parquet_dir = parquet_dir.clone();
+        })
+        .await;
+
+        // 100 and 101 share 2023-11-14's file; 102, a day later, starts one under the next date.
+        let partition = |date: &str| partition_dir(&parquet_dir, date, "ethereum", "WETH/USDC");
+        assert!(partition("2023-11-14").join("part-000000000100.parquet").is_file());
+        assert!(!partition("2023-11-14").join("part-000000000102.parquet").exists());
+        assert!(partition("2023-11-15").join("part-000000000102.parquet").is_file());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
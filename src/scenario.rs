@@ -0,0 +1,64 @@
+use std::{fmt, str::FromStr};
+
+/// A hypothetical external market move applied to the reference price only. Pool state is left
+/// untouched, so depth measured against the shifted reference approximates the liquidity left
+/// after the market moves but before arbitrageurs re-align the pool.
+///
+/// A positive shift means token_in got more valuable in token_out terms: the pool is now
+/// underpricing token_in, every fill starts out with slippage of `shift / (1 + shift)`, and depth
+/// shrinks. A negative shift means the pool is overpricing token_in and depth grows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PriceShift {
+    /// The move as a decimal, e.g. 0.05 for +5%.
+    pub fraction: f64,
+}
+
+impl PriceShift {
+    /// A function to build a shift, rejecting moves that would make the reference non-positive.
+    ///
+    /// Args:
+    /// - fraction: The move as a decimal, e.g. -0.1 for -10%
+    pub fn new(fraction: f64) -> Result<Self, String> {
+        if !fraction.is_finite() || fraction <= -1.0 {
+            return Err(format!("price shift {} must be finite and greater than -100%", fraction));
+        }
+        Ok(Self { fraction })
+    }
+
+    /// A function to apply the shift to a reference price.
+    pub fn apply(&self, reference_price: f64) -> f64 {
+        reference_price * (1.0 + self.fraction)
+    }
+
+    /// A function to apply the same market move to a price quoted the other way round, e.g.
+    /// token_in per token_out for the reverse leg of a pair.
+    pub fn apply_inverse(&self, inverse_reference_price: f64) -> f64 {
+        inverse_reference_price / (1.0 + self.fraction)
+    }
+}
+
+impl fmt::Display for PriceShift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:+}%", self.fraction * 100.0)
+    }
+}
+
+/// Parses either a percentage ("+5%", "-2.5%") or a bare decimal ("0.05").
+impl FromStr for PriceShift {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed: &str = s.trim();
+        let fraction: f64 = match trimmed.strip_suffix('%') {
+            Some(pct) => pct
+                .trim()
+                .parse::<f64>()
+                .map_err(|e| format!("invalid price shift \"{}\": {}", s, e))?
+                / 100.0,
+            None => trimmed
+                .parse::<f64>()
+                .map_err(|e| format!("invalid price shift \"{}\": {}", s, e))?,
+        };
+        Self::new(fraction)
+    }
+}
@@ -0,0 +1,125 @@
+use std::fmt;
+use rand::Rng;
+
+/// Which blocks get measured with `--every-n-blocks`: block numbers that leave `phase` when
+/// divided by `every`.
+///
+/// Keyed by block number rather than by count, so a reconnect doesn't shift the schedule and two
+/// instances with the same phase measure the same blocks. Instances started together would all
+/// pick phase 0, which is what `jittered` is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleSchedule {
+    every: u64,
+    phase: u64,
+}
+
+impl SampleSchedule {
+    /// Args:
+    /// - every: Measure one block in this many; 0 and 1 both mean every block
+    /// - phase: Which block of each `every` to measure, taken modulo `every`
+    pub fn new(every: u64, phase: u64) -> Self {
+        let every: u64 = every.max(1);
+        Self { every, phase: phase % every }
+    }
+
+    /// A function to pick a random phase, so instances watching the same chain spread their
+    /// simulations (and their load on the Tycho endpoint) across the interval.
+    pub fn jittered(every: u64) -> Self {
+        Self::jittered_with(every, &mut rand::thread_rng())
+    }
+
+    /// A function to pick a random phase from a given random number generator, e.g. a seeded
+    /// one.
+    pub fn jittered_with<R: Rng + ?Sized>(every: u64, rng: &mut R) -> Self {
+        let every: u64 = every.max(1);
+        Self::new(every, rng.gen_range(0..every))
+    }
+
+    pub fn every(&self) -> u64 {
+        self.every
+    }
+
+    pub fn phase(&self) -> u64 {
+        self.phase
+    }
+
+    /// A function to check whether a block gets measured.
+    pub fn is_due(&self, block_number: u64) -> bool {
+        block_number % self.every == self.phase
+    }
+
+    /// A function to find the first block at or after `block_number` that gets measured.
+    pub fn next_due(&self, block_number: u64) -> u64 {
+        let offset: u64 = (self.phase + self.every - block_number % self.every) % self.every;
+        block_number.saturating_add(offset)
+    }
+}
+
+impl fmt::Display for SampleSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.every == 1 {
+            return write!(f, "every block");
+        }
+        write!(f, "every {} blocks, phase {} (block number mod {} = {})", self.every, self.phase, self.every, self.phase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn measures_every_block_when_every_is_zero_or_one() {
+        for every in [0, 1] {
+            let schedule: SampleSchedule = SampleSchedule::new(every, 7);
+            assert_eq!((schedule.every(), schedule.phase()), (1, 0));
+            assert!((0..20).all(|block| schedule.is_due(block)));
+            assert_eq!(schedule.to_string(), "every block");
+        }
+    }
+
+    #[test]
+    fn measures_one_block_in_every_n() {
+        let schedule: SampleSchedule = SampleSchedule::new(5, 0);
+        let due: Vec<u64> = (0..23).filter(|block| schedule.is_due(*block)).collect();
+        assert_eq!(due, vec![0, 5, 10, 15, 20]);
+    }
+
+    #[test]
+    fn offsets_the_schedule_by_its_phase() {
+        let schedule: SampleSchedule = SampleSchedule::new(4, 6);
+        assert_eq!(schedule.phase(), 2);
+        let due: Vec<u64> = (100..116).filter(|block| schedule.is_due(*block)).collect();
+        assert_eq!(due, vec![102, 106, 110, 114]);
+        assert_eq!(schedule.next_due(100), 102);
+        assert_eq!(schedule.next_due(102), 102);
+        assert_eq!(schedule.next_due(103), 106);
+        assert_eq!(schedule.next_due(u64::MAX), u64::MAX);
+        assert_eq!(schedule.to_string(), "every 4 blocks, phase 2 (block number mod 4 = 2)");
+    }
+
+    #[test]
+    fn keeps_the_jittered_phase_within_the_interval() {
+        let mut rng: StdRng = StdRng::seed_from_u64(42);
+        let phases: Vec<u64> = (0..200).map(|_| SampleSchedule::jittered_with(10, &mut rng).phase()).collect();
+        assert!(phases.iter().all(|phase| *phase < 10));
+        let mut seen: Vec<u64> = phases.clone();
+        seen.sort_unstable();
+        seen.dedup();
+        assert_eq!(seen, (0..10).collect::<Vec<u64>>());
+        assert_eq!(SampleSchedule::jittered_with(0, &mut rng), SampleSchedule::new(1, 0));
+    }
+
+    #[test]
+    fn jitters_the_same_way_for_the_same_seed() {
+        let pick = |seed: u64| -> Vec<SampleSchedule> {
+            let mut rng: StdRng = StdRng::seed_from_u64(seed);
+            (0..16).map(|_| SampleSchedule::jittered_with(100, &mut rng)).collect()
+        };
+        assert_eq!(pick(7), pick(7));
+        let schedule: SampleSchedule = pick(7)[0];
+        let due: Vec<u64> = (0..300).filter(|block| schedule.is_due(*block)).collect();
+        assert_eq!(due, vec![schedule.phase(), schedule.phase() + 100, schedule.phase() + 200]);
+    }
+}
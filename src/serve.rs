@@ -0,0 +1,241 @@
+use std::{future::Future, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use crate::{
+    http::{route, HttpResponse, HEADER_TIMEOUT_SECS, MAX_REQUEST_BYTES},
+    hub::{BlockView, StateHub, Subscription},
+    rpc::{RpcHandler, RpcResponse},
+};
+
+/// A function to answer `--stdio` requests, one JSON line in and one out, until stdin closes.
+pub async fn serve_stdio(hub: StateHub, handler: RpcHandler) -> anyhow::Result<()> {
+    serve_lines(&hub, handler, tokio::io::BufReader::new(tokio::io::stdin()), tokio::io::stdout()).await
+}
+
+/// A function to answer JSON line requests until the input closes.
+///
+/// Requests are answered from a shared block view, so each sees the latest block and nothing is
+/// re-simulated between them. The first request waits for the stream's first block. Each is
+/// worked out on a blocking thread from the view as of its arrival, so the stream keeps up.
+///
+/// Args:
+/// - hub: The stream to answer from; subscribed to for as long as requests come in
+/// - handler: Answers each request
+/// - input: One request per line; blank lines are skipped
+/// - output: One response per line, flushed after each
+pub async fn serve_lines<R, W>(hub: &StateHub, handler: RpcHandler, input: R, mut output: W) -> anyhow::Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let handler: Arc<RpcHandler> = Arc::new(handler);
+    let mut subscription: Subscription = hub.subscribe();
+    let mut lines = input.lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        while subscription.view().block_number.is_none() {
+            if subscription.next_block().await.is_none() {
+                anyhow::bail!("the stream closed before its first block");
+            }
+        }
+        let view: Arc<BlockView> = subscription.view();
+        let handler: Arc<RpcHandler> = handler.clone();
+        let response: RpcResponse = tokio::task::spawn_blocking(move || handler.handle_line(&line, &view)).await?;
+        let mut json: String = serde_json::to_string(&response)?;
+        json.push('\n');
+        output.write_all(json.as_bytes()).await?;
+        output.flush().await?;
+    }
+    Ok(())
+}
+
+/// A function to answer `serve` requests over HTTP until shut down.
+///
+/// Every connection reads the shared block view, so a request sees the latest block and costs no
+/// stream of its own. Requests before the first block get a 503 rather than waiting.
+///
+/// Args:
+/// - listener: Where requests arrive
+/// - hub: The stream to answer from; subscribed to for the server's lifetime
+/// - handler: Answers each request
+/// - shutdown: Resolves to the name of what stopped the server, e.g. "SIGTERM"
+pub async fn serve_http(
+    listener: TcpListener,
+    hub: StateHub,
+    handler: RpcHandler,
+    shutdown: impl Future<Output = anyhow::Result<&'static str>>,
+) -> anyhow::Result<()> {
+    // Held for the server's lifetime, so the stream stays open between requests.
+    let _subscription: Subscription = hub.subscribe();
+    let handler: Arc<RpcHandler> = Arc::new(handler);
+    tracing::info!("serving depth on http://{}", listener.local_addr()?);
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            signal = &mut shutdown => {
+                tracing::info!("{}, stopping the server", signal?);
+                return Ok(());
+            }
+            accepted = listener.accept() => {
+                let (connection, peer) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        tracing::warn!("accepting a connection failed: {}", e);
+                        continue;
+                    }
+                };
+                let (hub, handler) = (hub.clone(), handler.clone());
+                tokio::spawn(async move {
+                    if let Err(e) = answer_http(connection, &hub, &handler).await {
+                        tracing::warn!("request from {} failed: {}", peer, e);
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// A function to read one request off a connection, answer it and close it.
+///
+/// The answer is worked out on a blocking thread from the block view as of the request, so a slow
+/// search holds up neither the runtime's other connections nor the stream.
+pub async fn answer_http(mut connection: TcpStream, hub: &StateHub, handler: &Arc<RpcHandler>) -> anyhow::Result<()> {
+    // The request's head, or the response it already earned; None if the client sent nothing.
+    let read_head = async {
+        let mut head: Vec<u8> = Vec::new();
+        let mut buffer: [u8; 1024] = [0; 1024];
+        loop {
+            let read: usize = connection.read(&mut buffer).await?;
+            head.extend_from_slice(&buffer[..read]);
+            if head.windows(4).any(|w| w == b"\r\n\r\n") || (read == 0 && !head.is_empty()) {
+                return anyhow::Ok(Some(Ok(head)));
+            }
+            if read == 0 {
+                return Ok(None);
+            }
+            if head.len() > MAX_REQUEST_BYTES {
+                return Ok(Some(Err(HttpResponse::error(413, "request too large"))));
+            }
+        }
+    };
+    let response: HttpResponse = match tokio::time::timeout(Duration::from_secs(HEADER_TIMEOUT_SECS), read_head).await {
+        Err(_) => HttpResponse::error(408, format!("no request headers within {}s", HEADER_TIMEOUT_SECS)),
+        Ok(read) => match read? {
+            None => return Ok(()),
+            Some(Err(response)) => response,
+            Some(Ok(head)) => {
+                let view: Arc<BlockView> = hub.subscribe().view();
+                let handler: Arc<RpcHandler> = handler.clone();
+                tokio::task::spawn_blocking(move || route(&handler, &String::from_utf8_lossy(&head), &view))
+                    .await
+                    .unwrap_or_else(|e| {
+                        tracing::warn!("answering a request failed: {}", e);
+                        HttpResponse::error(500, "the request failed")
+                    })
+            }
+        },
+    };
+    connection.write_all(&response.to_bytes()).await?;
+    connection.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::Mutex,
+    };
+    use num_bigint::BigUint;
+    use serde_json::Value;
+    use tokio::sync::{mpsc, oneshot};
+    use tycho_common::{models::Chain, Bytes};
+    use tycho_simulation::{models::Token, protocol::models::BlockUpdate};
+    use super::*;
+
+    fn handler() -> RpcHandler {
+        let tokens: HashMap<Bytes, Token> = [
+            Token::new("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2", 18, "WETH", BigUint::from(0u32)),
+            Token::new("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", 6, "USDC", BigUint::from(0u32)),
+        ]
+        .into_iter()
+        .map(|token| (token.address.clone(), token))
+        .collect();
+        RpcHandler::new(Chain::Ethereum, tokens, 1e-4)
+    }
+
+    fn block(block_number: u64) -> BlockUpdate {
+        BlockUpdate { block_number, states: HashMap::new(), new_pairs: HashMap::new(), removed_pairs: HashMap::new() }
+    }
+
+    /// A hub whose stream sends these blocks and then stays open.
+    fn hub(blocks: Vec<BlockUpdate>) -> StateHub {
+        let blocks: Mutex<Vec<BlockUpdate>> = Mutex::new(blocks);
+        StateHub::new(Box::new(move || {
+            let (tx, rx) = mpsc::channel(8);
+            let blocks: Vec<BlockUpdate> = std::mem::take(&mut *blocks.lock().unwrap());
+            tokio::spawn(async move {
+                for block in blocks.into_iter() {
+                    tx.send(block).await.unwrap();
+                }
+                tx.closed().await;
+            });
+            rx
+        }))
+    }
+
+    const POOLS: &str = r#"{"id": 7, "method": "pools", "params": {"token_in": "WETH", "token_out": "USDC"}}"#;
+
+    #[tokio::test]
+    async fn answers_each_line_once_the_first_block_is_in() {
+        let input: String = format!("{}\n\n{{\"id\": 8, \"method\": \"liquidity\"}}\n", POOLS);
+        let mut output: Vec<u8> = Vec::new();
+        serve_lines(&hub(vec![block(21_000_000)]), handler(), input.as_bytes(), &mut output).await.unwrap();
+
+        let responses: Vec<Value> =
+            String::from_utf8(output).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(responses.len(), 2);
+        assert_eq!((responses[0]["id"].clone(), responses[0]["result"]["block_number"].clone()), (7.into(), 21_000_000.into()));
+        // A bad request is answered with an error, and the session goes on.
+        assert_eq!(responses[1]["id"], Value::from(8));
+        assert!(responses[1]["error"]["message"].as_str().unwrap().contains("unknown method"));
+    }
+
+    /// A function to send one raw request to the server and read the whole response.
+    async fn request(address: std::net::SocketAddr, raw: &[u8]) -> String {
+        let mut connection: TcpStream = TcpStream::connect(address).await.unwrap();
+        connection.write_all(raw).await.unwrap();
+        let mut response: String = String::new();
+        connection.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn answers_http_requests_from_the_latest_block_until_shut_down() {
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address: std::net::SocketAddr = listener.local_addr().unwrap();
+        let hub: StateHub = hub(vec![block(21_000_000)]);
+        let mut subscription: Subscription = hub.subscribe();
+        let (stop_tx, stop_rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(serve_http(listener, hub, handler(), async move {
+            let _ = stop_rx.await;
+            Ok("stopped")
+        }));
+        subscription.next_block().await;
+
+        let response: String = request(address, b"GET /pools?pair=WETH-USDC HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.ends_with(r#""block_number":21000000,"pair":"WETH/USDC","pools":[]}"#), "{}", response);
+        let response: String = request(address, b"GET /liquidity HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 404 "), "{}", response);
+        let oversized: Vec<u8> = vec![b'a'; MAX_REQUEST_BYTES + 1];
+        assert!(request(address, &oversized).await.starts_with("HTTP/1.1 413 "));
+
+        stop_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+}
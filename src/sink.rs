@@ -0,0 +1,555 @@
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use flate2::{write::GzEncoder, Compression as GzLevel};
+use rusqlite::{params, Connection, Transaction};
+use serde::Serialize;
+use crate::report::BlockReport;
+
+/// Buffered output is compressed and written out at least this often, even between flushes.
+const MAX_BUFFERED_BYTES: usize = 1 << 20;
+
+/// How a file sink is compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// A function to pick compression from a file extension: `.gz` is gzip, `.zst` is zstd, and
+    /// anything else is uncompressed.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compression::None => write!(f, "none"),
+            Compression::Gzip => write!(f, "gzip"),
+            Compression::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            _ => Err(format!("unknown compression \"{}\", expected one of: none, gzip, zstd", s)),
+        }
+    }
+}
+
+/// How `--partition-by` splits file sinks into one file per value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Partition {
+    /// One file per pair, e.g. `weth-usdc`.
+    Pair,
+    /// One file per chain, e.g. `unichain`.
+    Chain,
+    /// One file per protocol system, e.g. `uniswap-v3`.
+    Protocol,
+}
+
+impl fmt::Display for Partition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Partition::Pair => write!(f, "pair"),
+            Partition::Chain => write!(f, "chain"),
+            Partition::Protocol => write!(f, "protocol"),
+        }
+    }
+}
+
+impl FromStr for Partition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pair" => Ok(Partition::Pair),
+            "chain" => Ok(Partition::Chain),
+            "protocol" => Ok(Partition::Protocol),
+            _ => Err(format!("unknown partition \"{}\", expected one of: pair, chain, protocol", s)),
+        }
+    }
+}
+
+/// What records that don't belong to any one partition (e.g. a block's native price when
+/// partitioning by pair) are filed under.
+pub const SHARED_PARTITION: &str = "all";
+
+/// A function to turn a partition value into something safe in a file name: lowercase, with every
+/// run of other characters as one dash, e.g. "WETH/USDC" to "weth-usdc".
+pub fn partition_slug(value: &str) -> String {
+    let mut slug: String = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug: &str = slug.trim_end_matches('-');
+    if slug.is_empty() { SHARED_PARTITION.to_string() } else { slug.to_string() }
+}
+
+/// A function to name a partition's file.
+///
+/// Args:
+/// - path: The sink's path as configured. A `{partition}` placeholder is replaced by the slug;
+///   otherwise the slug goes before the extensions, so compression is still picked by extension
+/// - slug: The partition, see `partition_slug`
+///
+/// Returns:
+/// - e.g. `runs/{partition}.csv` to `runs/weth-usdc.csv`, or `runs/depth.csv.zst` to
+///   `runs/depth-weth-usdc.csv.zst`
+pub fn partition_path(path: &Path, slug: &str) -> PathBuf {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return path.join(slug);
+    };
+    let name: String = if name.contains("{partition}") {
+        name.replace("{partition}", slug)
+    } else {
+        // Skip a leading dot, so ".csv" is a name rather than an extension.
+        match name.char_indices().skip(1).find(|(_, c)| *c == '.') {
+            Some((dot, _)) => format!("{}-{}{}", &name[..dot], slug, &name[dot..]),
+            None => format!("{}-{}", name, slug),
+        }
+    };
+    path.with_file_name(name)
+}
+
+/// A function to name one chain's file when a run streams several chains side by side, so each
+/// chain writes its own.
+///
+/// Args:
+/// - path: The sink's path as configured. A `{partition}` placeholder is kept for `--partition-by`
+/// - chain: The chain's name, e.g. "base"
+///
+/// Returns:
+/// - e.g. `runs/depth.csv.zst` to `runs/depth-base.csv.zst`, or `runs/{partition}.csv` to
+///   `runs/base-{partition}.csv`
+pub fn chain_path(path: &Path, chain: &str) -> PathBuf {
+    let slug: String = partition_slug(chain);
+    match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) if name.contains("{partition}") => {
+            path.with_file_name(name.replace("{partition}", &format!("{}-{{partition}}", slug)))
+        }
+        _ => partition_path(path, &slug),
+    }
+}
+
+/// Opens one partition's file.
+pub type OpenSink<S> = Box<dyn Fn(&Path) -> io::Result<S>>;
+
+/// A file sink split by `--partition-by`: each partition's file is opened the first time a record
+/// for it arrives. Unpartitioned, everything goes to the configured path.
+pub struct PartitionedSink<S> {
+    path: PathBuf,
+    partition: Option<Partition>,
+    open: OpenSink<S>,
+    sinks: BTreeMap<String, S>,
+}
+
+impl<S> PartitionedSink<S> {
+    /// A function to set up a partitioned sink. Unpartitioned, the file is opened right away, so a
+    /// bad path fails before the run starts.
+    ///
+    /// Args:
+    /// - path: The sink's path as configured, see `partition_path`
+    /// - partition: How to split it, or None for a single file
+    /// - open: Opens one file, e.g. `|path| CsvSink::open(path, compression)`
+    pub fn open(
+        path: impl Into<PathBuf>,
+        partition: Option<Partition>,
+        open: impl Fn(&Path) -> io::Result<S> + 'static,
+    ) -> io::Result<Self> {
+        let mut sink: Self = Self { path: path.into(), partition, open: Box::new(open), sinks: BTreeMap::new() };
+        if partition.is_none() {
+            sink.sink(None)?;
+        }
+        Ok(sink)
+    }
+
+    pub fn partition(&self) -> Option<Partition> {
+        self.partition
+    }
+
+    /// A function to get the sink for a partition value, opening its file if needed.
+    ///
+    /// Args:
+    /// - value: e.g. the record's pair label; None for records that belong to no single partition
+    pub fn sink(&mut self, value: Option<&str>) -> io::Result<&mut S> {
+        let slug: String = match self.partition {
+            Some(_) => partition_slug(value.unwrap_or(SHARED_PARTITION)),
+            None => String::new(),
+        };
+        match self.sinks.entry(slug) {
+            Entry::Occupied(sink) => Ok(sink.into_mut()),
+            Entry::Vacant(entry) => {
+                let path: PathBuf = match self.partition {
+                    Some(_) => partition_path(&self.path, entry.key()),
+                    None => self.path.clone(),
+                };
+                Ok(entry.insert((self.open)(&path)?))
+            }
+        }
+    }
+
+    /// Every file opened so far, e.g. to flush them.
+    pub fn sinks_mut(&mut self) -> impl Iterator<Item = &mut S> {
+        self.sinks.values_mut()
+    }
+}
+
+/// An append-only file that stays readable if the process dies mid-run.
+///
+/// Compressed output is written as a sequence of complete gzip members or zstd frames, one per
+/// `flush`. Both formats decode concatenated members/frames as one stream, so everything up to the
+/// last flush survives a crash, and re-opening an existing file just appends more of them.
+pub struct FileSink {
+    file: File,
+    compression: Compression,
+    buffer: Vec<u8>,
+}
+
+impl FileSink {
+    /// A function to open a file sink for appending, creating the file if needed.
+    ///
+    /// Args:
+    /// - path: The file to write
+    /// - compression: Overrides the compression implied by the extension, see
+    ///   `Compression::from_path`
+    pub fn open(path: impl AsRef<Path>, compression: Option<Compression>) -> io::Result<Self> {
+        let path: &Path = path.as_ref();
+        let file: File = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file,
+            compression: compression.unwrap_or_else(|| Compression::from_path(path)),
+            buffer: Vec::new(),
+        })
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// A function to append a record as one line of JSON.
+    pub fn write_json_line(&mut self, record: &impl Serialize) -> io::Result<()> {
+        serde_json::to_writer(&mut *self, record)?;
+        self.write_all(b"\n")
+    }
+}
+
+/// A CSV file sink with a header row, appended to across runs.
+///
+/// The header is only written when the file starts out empty, so re-running with the same path
+/// keeps adding rows under the first header.
+pub struct CsvSink {
+    writer: csv::Writer<FileSink>,
+}
+
+impl CsvSink {
+    /// A function to open a CSV sink for appending, creating the file if needed.
+    ///
+    /// Args:
+    /// - path: The file to write
+    /// - compression: As for `FileSink::open`
+    pub fn open(path: impl AsRef<Path>, compression: Option<Compression>) -> io::Result<Self> {
+        let path: &Path = path.as_ref();
+        let is_empty: bool = std::fs::metadata(path).map(|m| m.len() == 0).unwrap_or(true);
+        let writer: csv::Writer<FileSink> = csv::WriterBuilder::new()
+            .has_headers(is_empty)
+            .from_writer(FileSink::open(path, compression)?);
+        Ok(Self { writer })
+    }
+
+    /// A function to append one row per record, with columns named after its fields.
+    pub fn write_rows<T: Serialize>(&mut self, rows: &[T]) -> io::Result<()> {
+        for row in rows.iter() {
+            self.writer.serialize(row).map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+
+    /// A function to write out buffered rows, see `FileSink::flush`.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Tables for `SqliteSink`. Amounts are decimal strings, since they don't fit SQLite integers; the
+/// `_tokens` columns are the same in whole tokens. `measured_at` is the block's timestamp, in
+/// Unix seconds, for querying history by time.
+const SQLITE_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS blocks (
+    block_number INTEGER PRIMARY KEY,
+    native_price REAL,
+    measured_at INTEGER
+);
+CREATE TABLE IF NOT EXISTS pools (
+    pool_id TEXT PRIMARY KEY,
+    protocol TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS depths (
+    block_number INTEGER NOT NULL REFERENCES blocks (block_number),
+    pool_id TEXT NOT NULL REFERENCES pools (pool_id),
+    pair TEXT NOT NULL,
+    target_slippage REAL NOT NULL,
+    action TEXT NOT NULL,
+    approximate INTEGER NOT NULL,
+    spot_price REAL,
+    reference_price REAL,
+    base_amount TEXT,
+    quote_amount TEXT,
+    usd_value REAL,
+    slippage REAL,
+    fee_slippage REAL,
+    impact_slippage REAL,
+    error TEXT,
+    base_tokens TEXT,
+    quote_tokens TEXT,
+    PRIMARY KEY (block_number, pool_id, pair, target_slippage, action)
+);
+CREATE TABLE IF NOT EXISTS exclusions (
+    block_number INTEGER NOT NULL REFERENCES blocks (block_number),
+    pool_id TEXT NOT NULL REFERENCES pools (pool_id),
+    pair TEXT NOT NULL,
+    status TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    PRIMARY KEY (block_number, pool_id, pair)
+);
+CREATE TABLE IF NOT EXISTS aggregates (
+    block_number INTEGER NOT NULL REFERENCES blocks (block_number),
+    pair TEXT NOT NULL,
+    target_slippage REAL NOT NULL,
+    action TEXT NOT NULL,
+    pools INTEGER NOT NULL,
+    base_amount TEXT,
+    quote_amount TEXT,
+    usd_value REAL,
+    slippage REAL,
+    fee_slippage REAL,
+    impact_slippage REAL,
+    error TEXT,
+    base_tokens TEXT,
+    quote_tokens TEXT,
+    PRIMARY KEY (block_number, pair, target_slippage, action)
+);
+CREATE INDEX IF NOT EXISTS depths_by_pair ON depths (pair, block_number);
+";
+
+/// A local SQLite history of every block's results, queryable with plain SQL.
+///
+/// Each block is written in one transaction and every row is upserted, so re-running over the same
+/// blocks (or a crash mid-block) never leaves duplicates or half a block behind.
+pub struct SqliteSink {
+    connection: Connection,
+}
+
+impl SqliteSink {
+    /// A function to open a database, creating it and its tables if needed.
+    pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        let connection: Connection = Connection::open(path)?;
+        connection.execute_batch(SQLITE_SCHEMA)?;
+        // Whole-token columns and write times came later; older databases get them, empty for
+        // blocks already written.
+        let added: [(&str, &[&str], &str); 3] = [
+            ("depths", &["base_tokens", "quote_tokens"], "TEXT"),
+            ("aggregates", &["base_tokens", "quote_tokens"], "TEXT"),
+            ("blocks", &["measured_at"], "INTEGER"),
+        ];
+        for (table, new_columns, column_type) in added {
+            let columns: Vec<String> = connection
+                .prepare(&format!("PRAGMA table_info({})", table))?
+                .query_map([], |row| row.get(1))?
+                .collect::<rusqlite::Result<_>>()?;
+            for column in new_columns.iter() {
+                if !columns.iter().any(|name| name == column) {
+                    connection.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, column_type), [])?;
+                }
+            }
+        }
+        Ok(Self { connection })
+    }
+
+    /// A function to upsert one block's results: the block, its pools, each pool's depth per
+    /// target and direction, excluded pools, and aggregate depth per pair.
+    ///
+    /// Args:
+    /// - report: The block's results
+    /// - measured_at: The block's timestamp, stored for `history::read_depth_history`
+    pub fn write_block(&mut self, report: &BlockReport, measured_at: SystemTime) -> rusqlite::Result<()> {
+        let tx: Transaction = self.connection.transaction()?;
+        let block_number: i64 = report.block_number as i64;
+        let measured_at: i64 = measured_at.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs() as i64);
+        tx.execute(
+            "INSERT INTO blocks (block_number, native_price, measured_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT (block_number) DO UPDATE SET
+                 native_price = excluded.native_price,
+                 measured_at = excluded.measured_at",
+            params![block_number, report.native_price.as_ref().map(|p| p.price), measured_at],
+        )?;
+        for pool in report.pools.iter() {
+            tx.execute(
+                "INSERT INTO pools (pool_id, protocol) VALUES (?1, ?2)
+                 ON CONFLICT (pool_id) DO UPDATE SET protocol = excluded.protocol",
+                params![pool.pool_id, pool.protocol],
+            )?;
+            if let Some(reason) = &pool.excluded {
+                tx.execute(
+                    "INSERT INTO exclusions (block_number, pool_id, pair, status, reason) VALUES (?1, ?2, ?3, ?4, ?5)
+                     ON CONFLICT (block_number, pool_id, pair) DO UPDATE SET
+                         status = excluded.status,
+                         reason = excluded.reason",
+                    params![block_number, pool.pool_id, pool.pair, pool.status.to_string(), reason],
+                )?;
+            }
+            for depth in pool.depths.iter() {
+                tx.execute(
+                    "INSERT INTO depths (block_number, pool_id, pair, target_slippage, action, approximate,
+                         spot_price, reference_price, base_amount, quote_amount, usd_value, slippage, fee_slippage,
+                         impact_slippage, error, base_tokens, quote_tokens)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)
+                     ON CONFLICT (block_number, pool_id, pair, target_slippage, action) DO UPDATE SET
+                         approximate = excluded.approximate,
+                         spot_price = excluded.spot_price,
+                         reference_price = excluded.reference_price,
+                         base_amount = excluded.base_amount,
+                         quote_amount = excluded.quote_amount,
+                         usd_value = excluded.usd_value,
+                         slippage = excluded.slippage,
+                         fee_slippage = excluded.fee_slippage,
+                         impact_slippage = excluded.impact_slippage,
+                         error = excluded.error,
+                         base_tokens = excluded.base_tokens,
+                         quote_tokens = excluded.quote_tokens",
+                    params![
+                        block_number,
+                        pool.pool_id,
+                        pool.pair,
+                        depth.target_slippage,
+                        depth.action.to_string(),
+                        depth.approximate,
+                        pool.spot_price,
+                        pool.reference_price,
+                        depth.base_amount,
+                        depth.quote_amount,
+                        depth.usd_value,
+                        depth.slippage,
+                        depth.fee_slippage,
+                        depth.impact_slippage,
+                        depth.error,
+                        depth.conversions.base_tokens,
+                        depth.conversions.quote_tokens,
+                    ],
+                )?;
+            }
+        }
+        for aggregate in report.aggregates.iter() {
+            tx.execute(
+                "INSERT INTO aggregates (block_number, pair, target_slippage, action, pools, base_amount, quote_amount,
+                     usd_value, slippage, fee_slippage, impact_slippage, error, base_tokens, quote_tokens)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+                 ON CONFLICT (block_number, pair, target_slippage, action) DO UPDATE SET
+                     pools = excluded.pools,
+                     base_amount = excluded.base_amount,
+                     quote_amount = excluded.quote_amount,
+                     usd_value = excluded.usd_value,
+                     slippage = excluded.slippage,
+                     fee_slippage = excluded.fee_slippage,
+                     impact_slippage = excluded.impact_slippage,
+                     error = excluded.error,
+                     base_tokens = excluded.base_tokens,
+                     quote_tokens = excluded.quote_tokens",
+                params![
+                    block_number,
+                    aggregate.pair,
+                    aggregate.target_slippage,
+                    aggregate.action.to_string(),
+                    aggregate.pools as i64,
+                    aggregate.base_amount,
+                    aggregate.quote_amount,
+                    aggregate.usd_value,
+                    aggregate.slippage,
+                    aggregate.fee_slippage,
+                    aggregate.impact_slippage,
+                    aggregate.error,
+                    aggregate.conversions.base_tokens,
+                    aggregate.conversions.quote_tokens,
+                ],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// A function to delete every row of a range of blocks that a chain reorganization orphaned,
+    /// so a pool or pair the new chain doesn't measure at those heights isn't left behind.
+    pub fn orphan_blocks(&mut self, from_block: u64, to_block: u64) -> rusqlite::Result<()> {
+        let tx: Transaction = self.connection.transaction()?;
+        for table in ["depths", "exclusions", "aggregates", "blocks"] {
+            tx.execute(
+                &format!("DELETE FROM {} WHERE block_number BETWEEN ?1 AND ?2", table),
+                params![from_block as i64, to_block as i64],
+            )?;
+        }
+        tx.commit()
+    }
+}
+
+impl Write for FileSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= MAX_BUFFERED_BYTES {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    /// Compresses everything buffered so far into one self-contained member/frame and writes it.
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk: Vec<u8> = match self.compression {
+            Compression::None => std::mem::take(&mut self.buffer),
+            Compression::Gzip => {
+                let mut encoder: GzEncoder<Vec<u8>> = GzEncoder::new(Vec::new(), GzLevel::default());
+                encoder.write_all(&self.buffer)?;
+                self.buffer.clear();
+                encoder.finish()?
+            }
+            Compression::Zstd => {
+                let chunk: Vec<u8> = zstd::stream::encode_all(self.buffer.as_slice(), 0)?;
+                self.buffer.clear();
+                chunk
+            }
+        };
+        self.file.write_all(&chunk)?;
+        self.file.flush()
+    }
+}
+
+impl Drop for FileSink {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
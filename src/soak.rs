@@ -0,0 +1,201 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs,
+    time::{Duration, Instant},
+};
+use serde::Serialize;
+
+/// A function to read the resident set size of the current process.
+///
+/// Returns:
+/// - RSS in bytes, or None on platforms without `/proc/self/status`
+pub fn current_rss_bytes() -> Option<u64> {
+    let status: String = fs::read_to_string("/proc/self/status").ok()?;
+    let line: &str = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    // Format is "VmRSS:     123456 kB"
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SoakSample {
+    pub elapsed_secs: f64,
+    pub block_number: u64,
+    pub rss_bytes: Option<u64>,
+    pub map_sizes: BTreeMap<String, usize>,
+}
+
+/// Samples memory and tracked-map sizes over a bounded run so leaks show up as a growth rate
+/// rather than an OOM three days later.
+pub struct SoakMonitor {
+    started: Instant,
+    duration: Duration,
+    interval: Duration,
+    last_sample: Option<Instant>,
+    samples: Vec<SoakSample>,
+}
+
+impl SoakMonitor {
+    pub fn new(duration: Duration, interval: Duration) -> Self {
+        Self {
+            started: Instant::now(),
+            duration,
+            interval,
+            last_sample: None,
+            samples: Vec::new(),
+        }
+    }
+
+    /// When the soak run should stop.
+    pub fn deadline(&self) -> Instant {
+        self.started + self.duration
+    }
+
+    pub fn is_finished(&self) -> bool {
+        Instant::now() >= self.deadline()
+    }
+
+    /// A function to record a sample if at least `interval` has passed since the last one.
+    ///
+    /// Args:
+    /// - block_number: The block the caller just processed
+    /// - map_sizes: Name and length of every map the caller wants watched, e.g. tracked states
+    pub fn maybe_sample<'a>(
+        &mut self,
+        block_number: u64,
+        map_sizes: impl IntoIterator<Item = (&'a str, usize)>,
+    ) {
+        let now: Instant = Instant::now();
+        if let Some(last) = self.last_sample {
+            if now.duration_since(last) < self.interval {
+                return;
+            }
+        }
+        self.last_sample = Some(now);
+        self.samples.push(SoakSample {
+            elapsed_secs: now.duration_since(self.started).as_secs_f64(),
+            block_number,
+            rss_bytes: current_rss_bytes(),
+            map_sizes: map_sizes
+                .into_iter()
+                .map(|(name, len)| (name.to_string(), len))
+                .collect(),
+        });
+    }
+
+    pub fn samples(&self) -> &[SoakSample] {
+        &self.samples
+    }
+
+    /// A function to summarize the samples into per-minute growth rates.
+    pub fn report(&self) -> SoakReport {
+        let rss: Vec<(f64, f64)> = self
+            .samples
+            .iter()
+            .filter_map(|s| s.rss_bytes.map(|b| (s.elapsed_secs, b as f64)))
+            .collect();
+
+        let mut names: Vec<&String> = self
+            .samples
+            .iter()
+            .flat_map(|s| s.map_sizes.keys())
+            .collect();
+        names.sort();
+        names.dedup();
+
+        let maps: Vec<SeriesGrowth> = names
+            .into_iter()
+            .map(|name| {
+                let points: Vec<(f64, f64)> = self
+                    .samples
+                    .iter()
+                    .filter_map(|s| s.map_sizes.get(name).map(|len| (s.elapsed_secs, *len as f64)))
+                    .collect();
+                SeriesGrowth::from_points(name, &points)
+            })
+            .collect();
+
+        SoakReport {
+            elapsed_secs: self.started.elapsed().as_secs_f64(),
+            samples: self.samples.len(),
+            rss: (!rss.is_empty()).then(|| SeriesGrowth::from_points("rss_bytes", &rss)),
+            maps,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SeriesGrowth {
+    pub name: String,
+    pub first: f64,
+    pub last: f64,
+    pub max: f64,
+    /// Least-squares slope of the series, per minute.
+    pub growth_per_min: f64,
+}
+
+impl SeriesGrowth {
+    fn from_points(name: &str, points: &[(f64, f64)]) -> Self {
+        let first: f64 = points.first().map(|p| p.1).unwrap_or(0.0);
+        let last: f64 = points.last().map(|p| p.1).unwrap_or(0.0);
+        let max: f64 = points.iter().map(|p| p.1).fold(0.0, f64::max);
+        Self {
+            name: name.to_string(),
+            first,
+            last,
+            max,
+            growth_per_min: slope(points) * 60.0,
+        }
+    }
+}
+
+/// Least-squares slope of y over x. Zero for fewer than two distinct x values.
+fn slope(points: &[(f64, f64)]) -> f64 {
+    let n: f64 = points.len() as f64;
+    if points.len() < 2 {
+        return 0.0;
+    }
+    let mean_x: f64 = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y: f64 = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let cov: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+    let var: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+    if var == 0.0 {
+        0.0
+    } else {
+        cov / var
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SoakReport {
+    pub elapsed_secs: f64,
+    pub samples: usize,
+    pub rss: Option<SeriesGrowth>,
+    pub maps: Vec<SeriesGrowth>,
+}
+
+impl fmt::Display for SoakReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Soak report: {:.0}s, {} samples", self.elapsed_secs, self.samples)?;
+        match &self.rss {
+            Some(rss) => writeln!(
+                f,
+                "   rss: {:.1} MiB -> {:.1} MiB (max {:.1} MiB), {:+.3} MiB/min",
+                rss.first / 1_048_576.0,
+                rss.last / 1_048_576.0,
+                rss.max / 1_048_576.0,
+                rss.growth_per_min / 1_048_576.0,
+            )?,
+            None => writeln!(f, "   rss: unavailable on this platform")?,
+        }
+        for map in self.maps.iter() {
+            writeln!(
+                f,
+                "   {}: {} -> {} (max {}), {:+.2}/min",
+                map.name, map.first, map.last, map.max, map.growth_per_min,
+            )?;
+        }
+        Ok(())
+    }
+}
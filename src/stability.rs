@@ -0,0 +1,71 @@
+use std::collections::{HashMap, VecDeque};
+use serde::Serialize;
+
+/// Blocks of aggregate depth the stability metric looks back over by default.
+pub const DEFAULT_STABILITY_WINDOW: usize = 20;
+
+/// How steady a pair's depth has been over the last blocks.
+///
+/// The coefficient of variation is the standard deviation over the mean, so it doesn't depend on
+/// the token or its decimals: a few percent is liquidity that stays put, while JIT or transient
+/// positions that appear and vanish block to block show up as a large one.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct DepthStability {
+    /// Blocks in the window so far, this one included.
+    pub blocks: usize,
+    /// Mean depth over the window, in token_in base units.
+    pub mean: f64,
+    /// Population standard deviation over the window, in token_in base units.
+    pub std_dev: f64,
+    /// `std_dev / mean`; None until there are two blocks, or if the mean is zero.
+    pub cv: Option<f64>,
+}
+
+/// The last few blocks of aggregate depth per pair and slippage target.
+#[derive(Debug, Clone)]
+pub struct DepthHistory {
+    window: usize,
+    /// Keyed by pair label and the target's bits, oldest block first.
+    depths: HashMap<(String, u64), VecDeque<f64>>,
+}
+
+impl DepthHistory {
+    /// Args:
+    /// - window: How many blocks to keep per pair and target; at least two are kept
+    pub fn new(window: usize) -> Self {
+        Self { window: window.max(2), depths: HashMap::new() }
+    }
+
+    /// A function to add one block's depth and get the stability over the window including it.
+    ///
+    /// Args:
+    /// - pair: The pair's label, e.g. "WETH/USDC"
+    /// - target_slippage: The slippage target the depth was measured at
+    /// - depth: The depth in token_in base units; only the ratio to other blocks matters
+    ///
+    /// Returns:
+    /// - The stability over the last `window` blocks
+    pub fn add(&mut self, pair: &str, target_slippage: f64, depth: f64) -> DepthStability {
+        let depths: &mut VecDeque<f64> =
+            self.depths.entry((pair.to_string(), target_slippage.to_bits())).or_default();
+        if depths.len() == self.window {
+            depths.pop_front();
+        }
+        depths.push_back(depth);
+        stability(depths)
+    }
+}
+
+/// A function to compute the mean, standard deviation and coefficient of variation of some depths.
+fn stability(depths: &VecDeque<f64>) -> DepthStability {
+    let blocks: usize = depths.len();
+    let mean: f64 = depths.iter().sum::<f64>() / blocks.max(1) as f64;
+    let variance: f64 = depths.iter().map(|depth| (depth - mean).powi(2)).sum::<f64>() / blocks.max(1) as f64;
+    let std_dev: f64 = variance.sqrt();
+    DepthStability {
+        blocks,
+        mean,
+        std_dev,
+        cv: (blocks >= 2 && mean > 0.0).then(|| std_dev / mean),
+    }
+}
@@ -0,0 +1,279 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+use futures::StreamExt;
+use tokio::sync::mpsc;
+use tycho_common::{models::Chain, Bytes};
+use tycho_simulation::{
+    evm::{
+        engine_db::tycho_db::PreCachedDB,
+        protocol::{
+            ekubo::state::EkuboState,
+            filters::{balancer_pool_filter, curve_pool_filter, uniswap_v4_pool_with_hook_filter},
+            pancakeswap_v2::state::PancakeswapV2State,
+            uniswap_v2::state::UniswapV2State,
+            uniswap_v3::state::UniswapV3State,
+            uniswap_v4::state::UniswapV4State,
+            vm::state::EVMPoolState,
+        },
+        stream::ProtocolStreamBuilder,
+    },
+    models::Token,
+    protocol::models::BlockUpdate,
+    tycho_client::feed::component_tracker::ComponentFilter,
+    utils::load_all_tokens,
+};
+use crate::{
+    endpoints::{Endpoints, Failover},
+    error::DepthError,
+    hub::StateHub,
+    protocols::ProtocolFilter,
+    supervisor::{supervise, Backoff, RestartCounter},
+};
+
+/// A block as handed over by a supervised stream.
+pub struct StreamBlock {
+    /// True for the first block after the stream (re)connects, which lists every pool.
+    pub snapshot: bool,
+    /// On the first block from a new endpoint, why and from where the stream moved.
+    pub failover: Option<Failover>,
+    /// The block's time. A decoded update doesn't carry its header's, so live blocks are stamped
+    /// as they arrive, within a block time of it; replayed ones keep the time they were recorded with.
+    pub timestamp: SystemTime,
+    pub update: BlockUpdate,
+}
+
+/// What every protocol stream of a run shares.
+#[derive(Clone)]
+pub struct StreamSettings {
+    /// In order of preference.
+    pub tycho_urls: Vec<String>,
+    /// How long the stream may go without a block before the endpoint counts as failed.
+    pub stall_timeout: Duration,
+    pub tycho_api_key: String,
+    pub chain: Chain,
+    /// TVL a pool needs to be added, in ETH.
+    pub tvl_min: f64,
+    /// TVL below which a tracked pool is removed, in ETH; at most `tvl_min`.
+    pub tvl_remove: f64,
+    /// With `--pools`, the only components subscribed to, lowercased; the TVL bounds don't apply.
+    pub pools: Vec<String>,
+    pub tokens: HashMap<Bytes, Token>,
+    /// Which exchanges are registered.
+    pub protocols: ProtocolFilter,
+}
+
+/// A function to load the chain's full token list from the first endpoint that answers.
+///
+/// Args:
+/// - tycho_urls: The endpoints, in order of preference
+/// - tycho_api_key: Sent to each
+/// - chain: The chain whose tokens to load
+///
+/// Returns:
+/// - Tokens by address; empty if no endpoint had any
+pub async fn load_tokens(tycho_urls: &[String], tycho_api_key: &str, chain: Chain) -> HashMap<Bytes, Token> {
+    for tycho_url in tycho_urls.iter() {
+        let tokens: HashMap<Bytes, Token> = load_all_tokens(tycho_url, false, Some(tycho_api_key), chain, None, None).await;
+        if !tokens.is_empty() {
+            return tokens;
+        }
+        tracing::warn!("no tokens from {}, trying the next endpoint", tycho_url);
+    }
+    HashMap::new()
+}
+
+/// A function to run a protocol stream on its own supervised task and hand its blocks over a channel.
+///
+/// A dropped connection, stall or failed build rebuilds the stream from scratch after an
+/// exponential, jittered backoff, on the healthiest other endpoint if there is one; the receiver
+/// keeps going and sees the new stream's snapshot flagged, along with any failover.
+///
+/// Args:
+/// - task: A name for logs
+/// - settings: Endpoints, chain and token list
+/// - restarts: Incremented whenever the stream is restarted
+/// - register: Adds the exchanges to stream
+///
+/// Returns:
+/// - The receiving end; it closes if the supervisor gives up
+pub fn spawn_stream<F>(
+    task: &'static str,
+    settings: StreamSettings,
+    restarts: RestartCounter,
+    register: F,
+) -> mpsc::Receiver<StreamBlock>
+where
+    F: Fn(ProtocolStreamBuilder, ComponentFilter) -> ProtocolStreamBuilder + Clone + Send + Sync + 'static,
+{
+    let (block_tx, block_rx) = mpsc::channel::<StreamBlock>(64);
+    let endpoints: Arc<Mutex<Endpoints>> = Arc::new(Mutex::new(Endpoints::new(settings.tycho_urls.clone())));
+    tokio::spawn(async move {
+        let supervised = supervise(task, Backoff::default(), restarts, || {
+            let settings: StreamSettings = settings.clone();
+            let register = register.clone();
+            let block_tx = block_tx.clone();
+            let endpoints: Arc<Mutex<Endpoints>> = endpoints.clone();
+            async move {
+                let (tycho_url, mut failover): (String, Option<Failover>) = {
+                    let mut endpoints = endpoints.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                    (endpoints.current().to_string(), endpoints.take_failover())
+                };
+                let streamed: Result<(), DepthError> = async {
+                    let component_filter: ComponentFilter = if settings.pools.is_empty() {
+                        ComponentFilter::with_tvl_range(settings.tvl_remove, settings.tvl_min)
+                    } else {
+                        ComponentFilter::ids(settings.pools.iter().map(String::as_str).collect())
+                    };
+                    let mut stream = register(ProtocolStreamBuilder::new(&tycho_url, settings.chain), component_filter)
+                        .auth_key(Some(settings.tycho_api_key))
+                        .skip_state_decode_failures(true)
+                        .set_tokens(settings.tokens)
+                        .await
+                        .build()
+                        .await
+                        .map_err(|e| DepthError::Stream(format!("failed to build: {:?}", e)))?;
+                    let mut snapshot: bool = true;
+                    loop {
+                        let msg = match tokio::time::timeout(settings.stall_timeout, stream.next()).await {
+                            Ok(Some(msg)) => msg,
+                            // The server hung up; a clean end is still a dropped connection to a long run.
+                            Ok(None) => return Err(DepthError::Stream("stream ended".to_string())),
+                            Err(_) => {
+                                return Err(DepthError::Stream(format!(
+                                    "no block for {}s",
+                                    settings.stall_timeout.as_secs()
+                                )))
+                            }
+                        };
+                        let update: BlockUpdate = msg.map_err(|e| DepthError::Stream(format!("{:?}", e)))?;
+                        endpoints
+                            .lock()
+                            .unwrap_or_else(|poisoned| poisoned.into_inner())
+                            .record_success(update.block_number);
+                        let timestamp: SystemTime = SystemTime::now();
+                        let block: StreamBlock = StreamBlock { snapshot, failover: failover.take(), timestamp, update };
+                        if block_tx.send(block).await.is_err() {
+                            // The block loop is done with us.
+                            return Ok(());
+                        }
+                        snapshot = false;
+                    }
+                }
+                .await;
+                if let Err(e) = &streamed {
+                    let moved: Option<Failover> = endpoints
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .record_failure(&e.to_string());
+                    if let Some(moved) = moved {
+                        tracing::warn!("{} failing over: {}", task, moved);
+                    }
+                }
+                streamed
+            }
+        });
+        if let Err(e) = supervised.await {
+            tracing::error!("{}", e);
+        }
+    });
+    block_rx
+}
+
+/// A function to stream the chain's exchanges, those the protocol filter keeps.
+///
+/// Returns:
+/// - The receiving end, as for `spawn_stream`
+pub fn spawn_protocol_stream(settings: StreamSettings, restarts: RestartCounter) -> mpsc::Receiver<StreamBlock> {
+    let (chain, protocols): (Chain, ProtocolFilter) = (settings.chain, settings.protocols.clone());
+    spawn_stream("protocol stream", settings, restarts, move |builder, filter| {
+        register_exchanges(builder, &chain, &protocols, filter)
+    })
+}
+
+/// A function to stream VM twins of native pools for `--cross-check`, separately since a pool id
+/// can only hold one state per stream.
+///
+/// Args:
+/// - systems: The VM protocol systems, e.g. "vm:uniswap_v3"
+///
+/// Returns:
+/// - The receiving end, as for `spawn_stream`
+pub fn spawn_vm_stream(
+    settings: StreamSettings,
+    restarts: RestartCounter,
+    systems: Vec<String>,
+) -> mpsc::Receiver<StreamBlock> {
+    spawn_stream("vm stream", settings, restarts, move |mut builder, filter| {
+        for system in systems.iter() {
+            builder = builder.exchange::<EVMPoolState<PreCachedDB>>(system, filter.clone(), None);
+        }
+        builder
+    })
+}
+
+/// A function to build a hub over a supervised protocol stream, opened while anyone's subscribed.
+pub fn stream_hub(settings: StreamSettings, restarts: RestartCounter) -> StateHub {
+    StateHub::new(Box::new(move || {
+        let mut blocks: mpsc::Receiver<StreamBlock> = spawn_protocol_stream(settings.clone(), restarts.clone());
+        let (update_tx, update_rx) = mpsc::channel::<BlockUpdate>(64);
+        tokio::spawn(async move {
+            while let Some(block) = blocks.recv().await {
+                if update_tx.send(block.update).await.is_err() {
+                    break;
+                }
+            }
+        });
+        update_rx
+    }))
+}
+
+/// A function to register the chain's exchanges on the stream, those the protocol filter keeps.
+pub fn register_exchanges(
+    mut builder: ProtocolStreamBuilder,
+    chain: &Chain,
+    protocols: &ProtocolFilter,
+    component_filter: ComponentFilter,
+) -> ProtocolStreamBuilder {
+    let mut registered: usize = 0;
+    macro_rules! exchange {
+        ($state:ty, $system:expr, $pool_filter:expr) => {
+            if protocols.matches($system) {
+                builder = builder.exchange::<$state>($system, component_filter.clone(), $pool_filter);
+                registered += 1;
+            }
+        };
+    }
+    match chain {
+        Chain::Ethereum => {
+            exchange!(UniswapV2State, "uniswap_v2", None);
+            exchange!(UniswapV3State, "uniswap_v3", None);
+            exchange!(EVMPoolState<PreCachedDB>, "vm:balancer_v2", Some(balancer_pool_filter));
+            exchange!(EVMPoolState<PreCachedDB>, "vm:curve", Some(curve_pool_filter));
+            exchange!(EkuboState, "ekubo_v2", None);
+            exchange!(UniswapV4State, "uniswap_v4", Some(uniswap_v4_pool_with_hook_filter));
+        }
+        Chain::Base | Chain::Unichain | Chain::Arbitrum => {
+            exchange!(UniswapV2State, "uniswap_v2", None);
+            exchange!(UniswapV3State, "uniswap_v3", None);
+            exchange!(UniswapV4State, "uniswap_v4", Some(uniswap_v4_pool_with_hook_filter));
+        }
+        Chain::Bsc => {
+            exchange!(UniswapV2State, "uniswap_v2", None);
+            exchange!(UniswapV3State, "uniswap_v3", None);
+            exchange!(UniswapV4State, "uniswap_v4", Some(uniswap_v4_pool_with_hook_filter));
+            exchange!(PancakeswapV2State, "pancakeswap_v2", None);
+            // PancakeSwap v3 is a Uniswap v3 fork and decodes as one.
+            exchange!(UniswapV3State, "pancakeswap_v3", None);
+        }
+        // `parse_supported_chain` keeps every other chain out, so this is only reached if
+        // SUPPORTED_CHAINS and this match drift apart.
+        other => tracing::error!("no exchanges registered for {}, the stream will carry no pools", other),
+    }
+    if registered == 0 {
+        tracing::error!("no exchange on {} passes the protocol filter {}, the stream will carry no pools", chain, protocols);
+    }
+    builder
+}
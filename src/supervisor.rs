@@ -0,0 +1,221 @@
+use std::{
+    any::Any,
+    fmt,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use rand::Rng;
+use tracing::{error, warn};
+
+/// How long to wait before restarting a failed task. The delay doubles after every failure up to
+/// `max`, and resets once a restarted task has stayed up for at least `max`.
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    /// Give up after this many restarts in a row. None retries forever.
+    pub max_restarts: Option<u64>,
+    /// Spread every delay by up to this fraction either way, so clients dropped by the same outage
+    /// don't all reconnect at once.
+    pub jitter: f64,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(60),
+            max_restarts: None,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl Backoff {
+    /// A function to randomize a delay by up to `jitter` either way.
+    ///
+    /// Args:
+    /// - delay: The delay before jitter
+    ///
+    /// Returns:
+    /// - The delay to actually wait
+    pub fn jittered(&self, delay: Duration) -> Duration {
+        let jitter: f64 = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return delay;
+        }
+        delay.mul_f64(rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter))
+    }
+}
+
+/// Counts restarts of a supervised task. Cheap to clone; every clone shares the count.
+#[derive(Debug, Clone, Default)]
+pub struct RestartCounter(Arc<AtomicU64>);
+
+impl RestartCounter {
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn increment(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug)]
+pub enum SupervisorError {
+    GaveUp { task: String, restarts: u64 },
+}
+
+impl fmt::Display for SupervisorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SupervisorError::GaveUp { task, restarts } => {
+                write!(f, "gave up on {} after {} restarts in a row", task, restarts)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SupervisorError {}
+
+/// A function to run a task on its own tokio task and restart it whenever it panics or returns an
+/// error, so one bad message can't take the whole process down or leave it hanging. It's what
+/// reconnects the protocol streams; sinks aren't run under it.
+///
+/// Args:
+/// - task: A name for logs, e.g. "stream"
+/// - backoff: How long to wait between restarts
+/// - restarts: Incremented on every restart
+/// - start: Builds a fresh instance of the task; called once per (re)start
+///
+/// Returns:
+/// - Ok once the task finishes cleanly, or an error if `backoff.max_restarts` runs out
+pub async fn supervise<F, Fut, E>(
+    task: &str,
+    backoff: Backoff,
+    restarts: RestartCounter,
+    mut start: F,
+) -> Result<(), SupervisorError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(), E>> + Send + 'static,
+    E: fmt::Display + Send + 'static,
+{
+    let mut delay: Duration = backoff.initial;
+    let mut in_a_row: u64 = 0;
+    loop {
+        let started: Instant = Instant::now();
+        match tokio::spawn(start()).await {
+            Ok(Ok(())) => return Ok(()),
+            Ok(Err(e)) => error!("{} failed after {:?}: {}", task, started.elapsed(), e),
+            Err(join_error) if join_error.is_panic() => error!(
+                "{} panicked after {:?}: {}",
+                task,
+                started.elapsed(),
+                panic_message(join_error.into_panic().as_ref())
+            ),
+            // Cancelled from outside, e.g. runtime shutdown: nothing to restart.
+            Err(_) => return Ok(()),
+        }
+
+        if started.elapsed() >= backoff.max {
+            delay = backoff.initial;
+            in_a_row = 0;
+        }
+        in_a_row += 1;
+        if backoff.max_restarts.is_some_and(|max| in_a_row > max) {
+            return Err(SupervisorError::GaveUp { task: task.to_string(), restarts: in_a_row - 1 });
+        }
+        restarts.increment();
+        let wait: Duration = backoff.jittered(delay);
+        warn!("restarting {} in {:?} (restart #{})", task, wait, restarts.get());
+        tokio::time::sleep(wait).await;
+        delay = (delay * 2).min(backoff.max);
+    }
+}
+
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "non-string panic payload".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Mutex, MutexGuard};
+    use tokio::time::Instant;
+    use super::*;
+
+    /// Starts at 10ms and doubles up to 30ms, without jitter.
+    fn backoff(max_restarts: Option<u64>) -> Backoff {
+        Backoff { initial: Duration::from_millis(10), max: Duration::from_millis(30), max_restarts, jitter: 0.0 }
+    }
+
+    /// A function to start a task that fails its first `failures` runs, noting when each run starts.
+    fn flaky(failures: usize, starts: &Arc<Mutex<Vec<Instant>>>) -> impl Future<Output = Result<(), String>> {
+        let starts: Arc<Mutex<Vec<Instant>>> = starts.clone();
+        async move {
+            let mut starts: MutexGuard<Vec<Instant>> = starts.lock().unwrap();
+            starts.push(Instant::now());
+            if starts.len() <= failures {
+                return Err(format!("failure {}", starts.len()));
+            }
+            Ok(())
+        }
+    }
+
+    fn gaps(starts: &Mutex<Vec<Instant>>) -> Vec<Duration> {
+        starts.lock().unwrap().windows(2).map(|pair| pair[1] - pair[0]).collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn restarts_a_failing_task_with_doubling_backoff() {
+        let starts: Arc<Mutex<Vec<Instant>>> = Arc::default();
+        let restarts: RestartCounter = RestartCounter::default();
+        supervise("stream", backoff(None), restarts.clone(), || flaky(4, &starts)).await.unwrap();
+
+        assert_eq!(restarts.get(), 4);
+        let millis: Vec<u64> = gaps(&starts).into_iter().map(|gap| gap.as_millis() as u64).collect();
+        assert_eq!(millis, vec![10, 20, 30, 30]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_after_max_restarts_in_a_row() {
+        let starts: Arc<Mutex<Vec<Instant>>> = Arc::default();
+        let restarts: RestartCounter = RestartCounter::default();
+        let error: SupervisorError =
+            supervise("stream", backoff(Some(2)), restarts.clone(), || flaky(usize::MAX, &starts)).await.unwrap_err();
+
+        assert!(matches!(&error, SupervisorError::GaveUp { restarts: 2, .. }));
+        assert_eq!(error.to_string(), "gave up on stream after 2 restarts in a row");
+        assert_eq!(restarts.get(), 2);
+        // The first run and two restarts; there's no wait after the last failure.
+        assert_eq!(starts.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn restarts_a_panicking_task() {
+        let runs: Arc<AtomicU64> = Arc::default();
+        let restarts: RestartCounter = RestartCounter::default();
+        let supervised = supervise("stream", backoff(Some(3)), restarts.clone(), || {
+            let runs: Arc<AtomicU64> = runs.clone();
+            async move {
+                if runs.fetch_add(1, Ordering::Relaxed) == 0 {
+                    panic!("bad message");
+                }
+                Ok::<(), String>(())
+            }
+        });
+        supervised.await.unwrap();
+        assert_eq!((runs.load(Ordering::Relaxed), restarts.get()), (2, 1));
+    }
+}
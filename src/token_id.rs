@@ -0,0 +1,350 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+use tycho_common::{models::Chain, Bytes};
+use tycho_simulation::models::Token;
+
+/// How a token is named: by address, or by symbol to be looked up in the chain's token list.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenRef {
+    Address(Bytes),
+    Symbol(String),
+    /// A symbol narrowed down by the start of its address, e.g. `USDC@0x8335`, for symbols that
+    /// several tokens share.
+    SymbolAt { symbol: String, address_prefix: String },
+}
+
+/// A token identifier as written on the command line or in a config file, optionally prefixed
+/// with the chain it lives on, e.g. `base:0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913`,
+/// `ethereum:WETH`, or just `USDC`.
+///
+/// The prefix makes multi-chain config files unambiguous: the same symbol (or even address) can
+/// name different tokens on different chains. A symbol shared by several tokens on one chain is
+/// narrowed down with the start of the address, e.g. `USDC@0x8335`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenId {
+    pub chain: Option<Chain>,
+    pub token: TokenRef,
+}
+
+#[derive(Debug)]
+pub enum TokenIdError {
+    Empty,
+    UnknownChain { id: String, chain: String },
+    InvalidAddress { id: String },
+    /// The part after `@` isn't the start of a hex address.
+    InvalidAddressPrefix { id: String },
+    /// The prefix names a chain the run isn't connected to.
+    ChainMismatch { id: String, active: Vec<Chain> },
+    NotFound { id: String },
+    /// More than one token in the list has this symbol. Candidates are written as `SYMBOL@0x…`
+    /// with the shortest address prefix that tells them apart.
+    AmbiguousSymbol { id: String, candidates: Vec<String> },
+}
+
+impl fmt::Display for TokenIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenIdError::Empty => write!(f, "empty token identifier"),
+            TokenIdError::UnknownChain { id, chain } => {
+                write!(f, "token \"{}\" has unknown chain prefix \"{}\"", id, chain)
+            }
+            TokenIdError::InvalidAddress { id } => {
+                write!(f, "token \"{}\" is not a valid 20-byte hex address", id)
+            }
+            TokenIdError::InvalidAddressPrefix { id } => {
+                write!(f, "token \"{}\" should be SYMBOL@0x followed by the start of the address", id)
+            }
+            TokenIdError::ChainMismatch { id, active } => {
+                let active: Vec<String> = active.iter().map(|c| c.to_string()).collect();
+                write!(f, "token \"{}\" is prefixed with a chain that isn't active (active: {})", id, active.join(", "))
+            }
+            TokenIdError::NotFound { id } => write!(f, "token \"{}\" not found in the token list", id),
+            TokenIdError::AmbiguousSymbol { id, candidates } => {
+                write!(f, "token \"{}\" matches several tokens, pick one: {}", id, candidates.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for TokenIdError {}
+
+impl fmt::Display for TokenId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(chain) = &self.chain {
+            write!(f, "{}:", chain)?;
+        }
+        match &self.token {
+            TokenRef::Address(address) => write!(f, "{}", address),
+            TokenRef::Symbol(symbol) => write!(f, "{}", symbol),
+            TokenRef::SymbolAt { symbol, address_prefix } => write!(f, "{}@{}", symbol, address_prefix),
+        }
+    }
+}
+
+/// Parses `[chain:]address`, `[chain:]SYMBOL` or `[chain:]SYMBOL@0xPREFIX`. Anything starting with
+/// `0x` is an address.
+impl FromStr for TokenId {
+    type Err = TokenIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed: &str = s.trim();
+        let (chain, token): (Option<Chain>, &str) = match trimmed.split_once(':') {
+            Some((prefix, rest)) => {
+                let chain: Chain = prefix
+                    .trim()
+                    .to_lowercase()
+                    .parse::<Chain>()
+                    .map_err(|_| TokenIdError::UnknownChain { id: s.to_string(), chain: prefix.to_string() })?;
+                (Some(chain), rest.trim())
+            }
+            None => (None, trimmed),
+        };
+        if token.is_empty() {
+            return Err(TokenIdError::Empty);
+        }
+
+        let token: TokenRef = if token.starts_with("0x") || token.starts_with("0X") {
+            let address: Bytes = token
+                .parse::<Bytes>()
+                .map_err(|_| TokenIdError::InvalidAddress { id: s.to_string() })?;
+            if address.len() != 20 {
+                return Err(TokenIdError::InvalidAddress { id: s.to_string() });
+            }
+            TokenRef::Address(address)
+        } else if let Some((symbol, address_prefix)) = token.split_once('@') {
+            let hex: &str = address_prefix
+                .strip_prefix("0x")
+                .or_else(|| address_prefix.strip_prefix("0X"))
+                .unwrap_or("");
+            if symbol.is_empty() || hex.is_empty() || hex.len() > 40 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(TokenIdError::InvalidAddressPrefix { id: s.to_string() });
+            }
+            TokenRef::SymbolAt { symbol: symbol.to_string(), address_prefix: format!("0x{}", hex.to_lowercase()) }
+        } else {
+            TokenRef::Symbol(token.to_string())
+        };
+        Ok(Self { chain, token })
+    }
+}
+
+impl TokenId {
+    /// A function to check that the chain prefix, if any, is one of the chains being streamed.
+    ///
+    /// Args:
+    /// - active: The chains this run is connected to
+    pub fn check_chain(&self, active: &[Chain]) -> Result<(), TokenIdError> {
+        match &self.chain {
+            Some(chain) if !active.contains(chain) => Err(TokenIdError::ChainMismatch {
+                id: self.to_string(),
+                active: active.to_vec(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// A function to look the token up in a chain's token list.
+    ///
+    /// Addresses match exactly. Symbols match case-insensitively and must be unique in the list,
+    /// or be narrowed down to one token with `SYMBOL@0xPREFIX`.
+    ///
+    /// Args:
+    /// - chain: The chain the token list belongs to
+    /// - tokens: The token list, e.g. from `load_all_tokens`
+    ///
+    /// Returns:
+    /// - The token, or an error if the prefix names another chain or the lookup fails. An
+    ///   ambiguous symbol lists every candidate in a form that resolves uniquely.
+    pub fn resolve(&self, chain: Chain, tokens: &HashMap<Bytes, Token>) -> Result<Token, TokenIdError> {
+        self.check_chain(&[chain])?;
+        let (symbol, address_prefix): (&str, Option<&str>) = match &self.token {
+            TokenRef::Address(address) => {
+                return tokens
+                    .get(address)
+                    .cloned()
+                    .ok_or_else(|| TokenIdError::NotFound { id: self.to_string() });
+            }
+            TokenRef::Symbol(symbol) => (symbol, None),
+            TokenRef::SymbolAt { symbol, address_prefix } => (symbol, Some(address_prefix)),
+        };
+        let mut matches: Vec<&Token> = tokens
+            .values()
+            .filter(|t| t.symbol.eq_ignore_ascii_case(symbol))
+            .filter(|t| address_prefix.is_none_or(|prefix| t.address.to_string().to_lowercase().starts_with(prefix)))
+            .collect();
+        match matches.len() {
+            0 => Err(TokenIdError::NotFound { id: self.to_string() }),
+            1 => Ok(matches[0].clone()),
+            _ => {
+                matches.sort_by(|a, b| a.address.cmp(&b.address));
+                let addresses: Vec<String> = matches.iter().map(|t| t.address.to_string().to_lowercase()).collect();
+                let hex_digits: usize = distinguishing_hex_digits(&addresses);
+                Err(TokenIdError::AmbiguousSymbol {
+                    id: self.to_string(),
+                    candidates: matches
+                        .iter()
+                        .zip(addresses.iter())
+                        .map(|(t, address)| format!("{}@{}", t.symbol, &address[..2 + hex_digits]))
+                        .collect(),
+                })
+            }
+        }
+    }
+}
+
+/// Never suggest fewer hex digits than this, so suggestions stay recognisable next to a block
+/// explorer.
+const MIN_PREFIX_HEX_DIGITS: usize = 4;
+
+/// A function to find how many hex digits of `0x…` addresses it takes to tell them all apart.
+fn distinguishing_hex_digits(addresses: &[String]) -> usize {
+    let longest: usize = addresses.iter().map(|a| a.len().saturating_sub(2)).max().unwrap_or(0);
+    (MIN_PREFIX_HEX_DIGITS..longest)
+        .find(|digits| {
+            let mut prefixes: Vec<&str> = addresses.iter().map(|a| &a[..(2 + digits).min(a.len())]).collect();
+            prefixes.sort_unstable();
+            prefixes.dedup();
+            prefixes.len() == addresses.len()
+        })
+        .unwrap_or(longest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_bigint::BigUint;
+
+    const USDC_BASE: &str = "0x833589fcd6edb6e08f4c7c32d4f71b54bda02913";
+
+    fn parse(id: &str) -> TokenId {
+        id.parse::<TokenId>().unwrap_or_else(|error| panic!("{}: {}", id, error))
+    }
+
+    #[test]
+    fn parses_an_address_with_a_chain() {
+        let id: TokenId = parse(" base:0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913 ");
+        assert_eq!(id.chain, Some(Chain::Base));
+        assert_eq!(id.token, TokenRef::Address(USDC_BASE.parse::<Bytes>().unwrap()));
+        assert_eq!(id.to_string(), format!("base:{}", USDC_BASE));
+    }
+
+    #[test]
+    fn parses_a_symbol_with_and_without_a_chain() {
+        assert_eq!(parse("USDC"), TokenId { chain: None, token: TokenRef::Symbol("USDC".to_string()) });
+        assert_eq!(
+            parse("Ethereum:WETH"),
+            TokenId { chain: Some(Chain::Ethereum), token: TokenRef::Symbol("WETH".to_string()) }
+        );
+    }
+
+    #[test]
+    fn parses_a_symbol_narrowed_by_an_address_prefix() {
+        let id: TokenId = parse("base:USDC@0X8335AB");
+        assert_eq!(id.chain, Some(Chain::Base));
+        assert_eq!(
+            id.token,
+            TokenRef::SymbolAt { symbol: "USDC".to_string(), address_prefix: "0x8335ab".to_string() }
+        );
+        assert_eq!(id.to_string(), "base:USDC@0x8335ab");
+    }
+
+    #[test]
+    fn rejects_malformed_identifiers() {
+        assert!(matches!("".parse::<TokenId>(), Err(TokenIdError::Empty)));
+        assert!(matches!("base: ".parse::<TokenId>(), Err(TokenIdError::Empty)));
+        assert!(matches!(
+            "mars:USDC".parse::<TokenId>(),
+            Err(TokenIdError::UnknownChain { chain, .. }) if chain == "mars"
+        ));
+        assert!(matches!("0x8335".parse::<TokenId>(), Err(TokenIdError::InvalidAddress { .. })));
+        assert!(matches!("0xzz".parse::<TokenId>(), Err(TokenIdError::InvalidAddress { .. })));
+        for id in ["USDC@", "USDC@0x", "USDC@8335", "USDC@0x83g5", "@0x8335"] {
+            assert!(
+                matches!(id.parse::<TokenId>(), Err(TokenIdError::InvalidAddressPrefix { .. })),
+                "{} should be rejected",
+                id
+            );
+        }
+        let too_long: String = format!("USDC@0x{}", "a".repeat(41));
+        assert!(matches!(too_long.parse::<TokenId>(), Err(TokenIdError::InvalidAddressPrefix { .. })));
+    }
+
+    #[test]
+    fn checks_the_chain_prefix_against_the_active_chains() {
+        let active: [Chain; 2] = [Chain::Ethereum, Chain::Base];
+        assert!(parse("USDC").check_chain(&active).is_ok());
+        assert!(parse("base:USDC").check_chain(&active).is_ok());
+        match parse("unichain:USDC").check_chain(&active) {
+            Err(TokenIdError::ChainMismatch { id, active: listed }) => {
+                assert_eq!(id, "unichain:USDC");
+                assert_eq!(listed, active.to_vec());
+            }
+            other => panic!("expected a chain mismatch, got {:?}", other),
+        }
+    }
+
+    fn token_list(tokens: &[(&str, &str)]) -> HashMap<Bytes, Token> {
+        tokens
+            .iter()
+            .map(|(address, symbol)| {
+                let token: Token = Token::new(address, 6, symbol, BigUint::from(0u32));
+                (token.address.clone(), token)
+            })
+            .collect()
+    }
+
+    const USDC_BRIDGED: &str = "0xd9aaec86b65d86f6a7b5b1b0c42ffa531710b6ca";
+    const USDC_LOOKALIKE: &str = "0x833589fcd6edb6e08f4c7c32d4f71b54bda0ffff";
+
+    #[test]
+    fn resolves_addresses_and_unique_symbols() {
+        let tokens: HashMap<Bytes, Token> = token_list(&[(USDC_BASE, "USDC"), (USDC_BRIDGED, "USDbC")]);
+        let by_address: Token = parse(USDC_BRIDGED).resolve(Chain::Base, &tokens).unwrap();
+        assert_eq!(by_address.symbol, "USDbC");
+        let by_symbol: Token = parse("base:usdc").resolve(Chain::Base, &tokens).unwrap();
+        assert_eq!(by_symbol.address.to_string(), USDC_BASE);
+        assert!(matches!(parse("WETH").resolve(Chain::Base, &tokens), Err(TokenIdError::NotFound { .. })));
+        assert!(matches!(
+            parse("ethereum:USDC").resolve(Chain::Base, &tokens),
+            Err(TokenIdError::ChainMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn lists_every_candidate_for_a_shared_symbol() {
+        let tokens: HashMap<Bytes, Token> = token_list(&[(USDC_BASE, "USDC"), (USDC_BRIDGED, "USDC")]);
+        match parse("USDC").resolve(Chain::Base, &tokens) {
+            Err(TokenIdError::AmbiguousSymbol { id, candidates }) => {
+                assert_eq!(id, "USDC");
+                assert_eq!(candidates, vec!["USDC@0x8335", "USDC@0xd9aa"]);
+            }
+            other => panic!("expected an ambiguous symbol, got {:?}", other),
+        }
+        for candidate in ["USDC@0x8335", "USDC@0xd9aa"] {
+            let token: Token = parse(candidate).resolve(Chain::Base, &tokens).unwrap();
+            assert!(token.address.to_string().starts_with(&candidate[5..]));
+        }
+    }
+
+    #[test]
+    fn suggests_prefixes_long_enough_to_tell_candidates_apart() {
+        let tokens: HashMap<Bytes, Token> = token_list(&[(USDC_BASE, "USDC"), (USDC_LOOKALIKE, "USDC")]);
+        match parse("USDC@0x8335").resolve(Chain::Base, &tokens) {
+            Err(TokenIdError::AmbiguousSymbol { candidates, .. }) => assert_eq!(
+                candidates,
+                vec![
+                    "USDC@0x833589fcd6edb6e08f4c7c32d4f71b54bda02",
+                    "USDC@0x833589fcd6edb6e08f4c7c32d4f71b54bda0f",
+                ]
+            ),
+            other => panic!("expected an ambiguous symbol, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn finds_the_shortest_distinguishing_prefix() {
+        let addresses = |list: &[&str]| -> Vec<String> { list.iter().map(|a| a.to_string()).collect() };
+        assert_eq!(distinguishing_hex_digits(&addresses(&["0x1111aa", "0x2222bb"])), MIN_PREFIX_HEX_DIGITS);
+        assert_eq!(distinguishing_hex_digits(&addresses(&["0x1234a0", "0x1234b0"])), 5);
+        assert_eq!(distinguishing_hex_digits(&addresses(&["0x123456a", "0x123456b", "0x1234560"])), 7);
+        assert_eq!(distinguishing_hex_digits(&addresses(&[USDC_BASE, USDC_LOOKALIKE])), 37);
+    }
+}
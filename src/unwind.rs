@@ -0,0 +1,207 @@
+use std::fmt;
+use alloy_primitives::U256;
+use serde::Serialize;
+use tycho_simulation::{
+    evm::protocol::{u256_num::u256_to_f64, uniswap_v2::state::UniswapV2State},
+    models::Token,
+    protocol::state::ProtocolSim,
+};
+use crate::{
+    amount::format_amount,
+    binary_search::{calculate_outputs_for_slippage_targets, SearchResult},
+    error::{guard_simulation, DepthError},
+};
+
+/// Fractions are applied to reserves in millionths.
+const FRACTION_SCALE: u64 = 1_000_000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UnwindPool {
+    pub id: String,
+    /// Pool value in whole token_out, or None if the state type can't be modified synthetically.
+    pub tvl_out: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UnwindPoint {
+    pub target_slippage: f64,
+    /// Summed depth across pools before the removal, in token_in base units.
+    pub depth_before: String,
+    /// Summed depth across pools after the removal, in token_in base units.
+    pub depth_after: String,
+    pub change: f64,
+}
+
+/// Before/after depth for a proportional liquidity removal across a pair's pools.
+#[derive(Debug, Clone, Serialize)]
+pub struct UnwindReport {
+    pub token_in: String,
+    pub token_out: String,
+    /// The notional asked to be removed, in whole token_out.
+    pub notional: f64,
+    /// The share of every supported pool's liquidity that was removed.
+    pub fraction_removed: f64,
+    pub pools: Vec<UnwindPool>,
+    pub curve: Vec<UnwindPoint>,
+    #[serde(skip)]
+    token_in_decimals: u8,
+}
+
+impl fmt::Display for UnwindReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let supported: usize = self.pools.iter().filter(|p| p.tvl_out.is_some()).count();
+        writeln!(
+            f,
+            "Unwind of {} {} across {}/{} modifiable pools ({:.2}% of their liquidity)",
+            self.notional,
+            self.token_out,
+            supported,
+            self.pools.len(),
+            self.fraction_removed * 100.0,
+        )?;
+        for pool in self.pools.iter().filter(|p| p.tvl_out.is_none()) {
+            writeln!(f, "   {} left unchanged: state type doesn't support synthetic modification", pool.id)?;
+        }
+        writeln!(f, "   {:>10}  {:>24}  {:>24}  {:>8}", "target", "before", "after", "change")?;
+        for point in self.curve.iter() {
+            let before: String = self.human(&point.depth_before);
+            let after: String = self.human(&point.depth_after);
+            writeln!(
+                f,
+                "   {:>9.3}%  {:>24}  {:>24}  {:>+7.2}%",
+                point.target_slippage * 100.0,
+                before,
+                after,
+                point.change * 100.0,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl UnwindReport {
+    fn human(&self, base_units: &str) -> String {
+        base_units
+            .parse::<U256>()
+            .map(|v| format_amount(v, self.token_in_decimals as usize))
+            .unwrap_or_else(|_| base_units.to_string())
+    }
+}
+
+/// A function to value a pool in whole token_out and build a copy with `fraction` of its
+/// liquidity removed.
+///
+/// Only Uniswap v2-style states expose their reserves, so other state types return None.
+fn scale_liquidity(
+    state: &dyn ProtocolSim,
+    token_in: &Token,
+    token_out: &Token,
+    spot_price: f64,
+    fraction: f64,
+) -> Option<(f64, Box<dyn ProtocolSim>)> {
+    let v2: &UniswapV2State = state.as_any().downcast_ref::<UniswapV2State>()?;
+    // Uniswap sorts pool tokens by address, so reserve0 belongs to the lower address.
+    let (reserve_in, reserve_out): (U256, U256) = if token_in.address < token_out.address {
+        (v2.reserve0, v2.reserve1)
+    } else {
+        (v2.reserve1, v2.reserve0)
+    };
+    let tvl_out: f64 = u256_to_f64(reserve_out) / 10f64.powi(token_out.decimals as i32)
+        + u256_to_f64(reserve_in) / 10f64.powi(token_in.decimals as i32) * spot_price;
+
+    let keep: U256 = U256::from(FRACTION_SCALE - (fraction * FRACTION_SCALE as f64).round() as u64);
+    let scale = |reserve: U256| reserve * keep / U256::from(FRACTION_SCALE);
+    let mut modified: UniswapV2State = v2.clone();
+    modified.reserve0 = scale(v2.reserve0);
+    modified.reserve1 = scale(v2.reserve1);
+    Some((tvl_out, Box::new(modified)))
+}
+
+/// A function to estimate how depth decays when a notional of liquidity is withdrawn, e.g. when
+/// LP incentives end.
+///
+/// The notional is removed proportionally: every modifiable pool loses the same share of its
+/// liquidity, sized so the total removed matches `notional`. Pools whose state type can't be
+/// modified are left as they are and listed in the report.
+///
+/// Args:
+/// - pools: (id, state) for every pool of the pair
+/// - token_in: The token being sold
+/// - token_out: The token being bought; the notional is denominated in it
+/// - notional: Liquidity to remove, in whole token_out
+/// - targets: Slippage targets to report depth at
+/// - precision: Search precision, see `calculate_output_for_slippage_tolerance`
+///
+/// Returns:
+/// - Summed depth per target before and after the removal, or `DepthError::InvalidNotional` if the
+///   notional is negative, NaN, or infinite
+pub fn estimate_unwind(
+    pools: &[(String, &dyn ProtocolSim)],
+    token_in: &Token,
+    token_out: &Token,
+    notional: f64,
+    targets: &[f64],
+    precision: f64,
+) -> Result<UnwindReport, DepthError> {
+    if !(notional.is_finite() && notional >= 0.0) {
+        return Err(DepthError::InvalidNotional(notional));
+    }
+    // Value every pool first so the removal can be spread proportionally.
+    let mut valued: Vec<(String, &dyn ProtocolSim, f64, Option<f64>)> = Vec::with_capacity(pools.len());
+    for (id, state) in pools.iter() {
+        let spot: f64 = guard_simulation(|| state.spot_price(token_in, token_out))?;
+        let tvl: Option<f64> = scale_liquidity(*state, token_in, token_out, spot, 0.0).map(|(tvl, _)| tvl);
+        valued.push((id.clone(), *state, spot, tvl));
+    }
+    let total_tvl: f64 = valued.iter().filter_map(|(_, _, _, tvl)| *tvl).sum();
+    let fraction: f64 = if total_tvl > 0.0 { (notional / total_tvl).clamp(0.0, 1.0) } else { 0.0 };
+
+    // Only pools that lose liquidity need a state of their own; the rest are searched as they are.
+    let after: Vec<Option<Box<dyn ProtocolSim>>> = valued
+        .iter()
+        .map(|(_, state, spot, _)| {
+            scale_liquidity(*state, token_in, token_out, *spot, fraction).map(|(_, modified)| modified)
+        })
+        .collect();
+
+    // One multi-target search per pool and side, so evaluations are shared across the curve.
+    let mut depth_before: Vec<U256> = vec![U256::ZERO; targets.len()];
+    let mut depth_after: Vec<U256> = vec![U256::ZERO; targets.len()];
+    for ((_, state, _, _), modified) in valued.iter().zip(after.iter()) {
+        let results_before: Vec<SearchResult> = calculate_outputs_for_slippage_targets(targets, precision, *state, token_in, token_out)?;
+        let modified: &dyn ProtocolSim = modified.as_deref().unwrap_or(*state);
+        let results_after: Vec<SearchResult> = calculate_outputs_for_slippage_targets(targets, precision, modified, token_in, token_out)?;
+        for (i, (b, a)) in results_before.iter().zip(results_after.iter()).enumerate() {
+            depth_before[i] += b.amount_in;
+            depth_after[i] += a.amount_in;
+        }
+    }
+
+    let mut curve: Vec<UnwindPoint> = Vec::with_capacity(targets.len());
+    for ((target, depth_before), depth_after) in targets.iter().zip(depth_before).zip(depth_after) {
+        let change: f64 = if depth_before.is_zero() {
+            0.0
+        } else {
+            u256_to_f64(depth_after) / u256_to_f64(depth_before) - 1.0
+        };
+        curve.push(UnwindPoint {
+            target_slippage: *target,
+            depth_before: depth_before.to_string(),
+            depth_after: depth_after.to_string(),
+            change,
+        });
+    }
+
+    Ok(UnwindReport {
+        token_in: token_in.symbol.clone(),
+        token_out: token_out.symbol.clone(),
+        notional,
+        fraction_removed: fraction,
+        pools: valued
+            .into_iter()
+            .map(|(id, _, _, tvl_out)| UnwindPool { id, tvl_out })
+            .collect(),
+        curve,
+        token_in_decimals: token_in.decimals as u8,
+    })
+}
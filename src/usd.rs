@@ -0,0 +1,56 @@
+use serde::Serialize;
+use tycho_common::Bytes;
+use tycho_simulation::{models::Token, protocol::state::ProtocolSim};
+use crate::native::{native_token_price, NativePrice};
+
+/// A token's dollar price for one block, and where it came from.
+#[derive(Debug, Clone, Serialize)]
+pub struct UsdPrice {
+    pub price: f64,
+    /// e.g. "stablecoin", "pool 0xabc…", "pool 0xabc… via native", "oracle"
+    pub source: String,
+}
+
+/// A function to price a token in dollars from the tracked pools, for comparing depth across pairs
+/// with different base tokens.
+///
+/// Stablecoins count as one dollar. Anything else is read from its deepest pool against a
+/// stablecoin, ranked the same way as `native_token_price`, or failing that from its deepest pool
+/// against the native token, converted with the block's native price.
+///
+/// Args:
+/// - token: The token to price
+/// - pools: (id, tokens, state) for every tracked pool
+/// - natives: Addresses counted as the native token
+/// - stables: Addresses of dollar stablecoins, see `chain::stablecoin_addresses`
+/// - native_price: The block's native token price, if there is one
+///
+/// Returns:
+/// - The price, or None if the token trades against neither
+pub fn token_usd_price(
+    token: &Token,
+    pools: &[(&str, &[Token], &dyn ProtocolSim)],
+    natives: &[Bytes],
+    stables: &[Bytes],
+    native_price: Option<&NativePrice>,
+) -> Option<UsdPrice> {
+    if stables.contains(&token.address) {
+        return Some(UsdPrice { price: 1.0, source: "stablecoin".to_string() });
+    }
+    if natives.contains(&token.address) {
+        return native_price.map(|native| UsdPrice {
+            price: native.price,
+            source: format!("pool {}", native.pool_id),
+        });
+    }
+    let token_address: [Bytes; 1] = [token.address.clone()];
+    if let Some(direct) = native_token_price(pools.iter().copied(), &token_address, stables) {
+        return Some(UsdPrice { price: direct.price, source: format!("pool {}", direct.pool_id) });
+    }
+    let native: &NativePrice = native_price?;
+    let in_native: NativePrice = native_token_price(pools.iter().copied(), &token_address, natives)?;
+    Some(UsdPrice {
+        price: in_native.price * native.price,
+        source: format!("pool {} via native", in_native.pool_id),
+    })
+}
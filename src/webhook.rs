@@ -0,0 +1,132 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use serde::Deserialize;
+use crate::alert::{Alert, Notifier};
+
+/// How long a webhook post may take before it's given up on.
+pub const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Discord refuses messages longer than this.
+const DISCORD_MAX_CHARS: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookKind {
+    Slack,
+    Discord,
+    Telegram,
+}
+
+impl fmt::Display for WebhookKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebhookKind::Slack => write!(f, "slack"),
+            WebhookKind::Discord => write!(f, "discord"),
+            WebhookKind::Telegram => write!(f, "telegram"),
+        }
+    }
+}
+
+/// A chat webhook to send alerts (and optionally block summaries) to, as a `[[notifiers]]` entry.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub kind: WebhookKind,
+    /// The incoming webhook URL for Slack and Discord; for Telegram, the bot's sendMessage URL,
+    /// `https://api.telegram.org/bot<TOKEN>/sendMessage`.
+    pub url: String,
+    /// The chat to post to, for Telegram only.
+    #[serde(default)]
+    pub chat_id: Option<String>,
+    /// Also post every Nth measured block's aggregate depth.
+    #[serde(default)]
+    pub summary_every: Option<u64>,
+}
+
+impl WebhookConfig {
+    /// A function to check the entry without sending anything.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.url.starts_with("https://") || self.url.starts_with("http://")) {
+            return Err(format!("the {} webhook url should start with https://", self.kind));
+        }
+        match (self.kind, &self.chat_id) {
+            (WebhookKind::Telegram, None) => return Err("a telegram notifier needs a chat_id".to_string()),
+            (WebhookKind::Slack | WebhookKind::Discord, Some(_)) => {
+                return Err(format!("chat_id is for telegram, not {}", self.kind))
+            }
+            _ => {}
+        }
+        if self.summary_every == Some(0) {
+            return Err("summary_every must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Posts to a Slack, Discord or Telegram webhook. Posts go out on the async runtime in the
+/// background, so a slow or failing chat service never holds up a block; failures are logged,
+/// without the URL, which holds the webhook's secret.
+pub struct WebhookNotifier {
+    config: WebhookConfig,
+    client: reqwest::Client,
+    /// Block summaries offered so far, to post every `summary_every`th.
+    summaries: AtomicU64,
+}
+
+impl WebhookNotifier {
+    pub fn new(config: WebhookConfig) -> Result<Self, String> {
+        config.validate()?;
+        Ok(Self { config, client: reqwest::Client::new(), summaries: AtomicU64::new(0) })
+    }
+
+    /// A function to build the JSON body each service expects for a plain-text message.
+    pub fn payload(&self, text: &str) -> serde_json::Value {
+        match self.config.kind {
+            WebhookKind::Slack => serde_json::json!({ "text": text }),
+            WebhookKind::Discord => {
+                let content: String = text.chars().take(DISCORD_MAX_CHARS).collect();
+                serde_json::json!({ "content": content })
+            }
+            WebhookKind::Telegram => serde_json::json!({ "chat_id": &self.config.chat_id, "text": text }),
+        }
+    }
+
+    fn post(&self, text: &str) -> Result<(), String> {
+        let runtime = tokio::runtime::Handle::try_current()
+            .map_err(|_| format!("the {} webhook needs an async runtime to post from", self.config.kind))?;
+        let request = self.client.post(&self.config.url).timeout(WEBHOOK_TIMEOUT).json(&self.payload(text));
+        let kind: WebhookKind = self.config.kind;
+        runtime.spawn(async move {
+            if let Err(e) = request.send().await.and_then(|response| response.error_for_status()) {
+                tracing::warn!("{} webhook post failed: {}", kind, e.without_url());
+            }
+        });
+        Ok(())
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &str {
+        match self.config.kind {
+            WebhookKind::Slack => "slack",
+            WebhookKind::Discord => "discord",
+            WebhookKind::Telegram => "telegram",
+        }
+    }
+
+    fn notify(&self, alert: &Alert) -> Result<(), String> {
+        self.post(&alert.to_string())
+    }
+
+    fn block_summary(&self, summary: &str) -> Result<(), String> {
+        let Some(every) = self.config.summary_every else {
+            return Ok(());
+        };
+        if !self.summaries.fetch_add(1, Ordering::Relaxed).is_multiple_of(every) {
+            return Ok(());
+        }
+        self.post(summary)
+    }
+}
@@ -0,0 +1,48 @@
+//! Alert rules: how they're written, and that they fire and resolve once per streak.
+
+use liquidity_depth_cli::{
+    alert::{Alert, AlertKind, AlertMonitor, AlertRule},
+    notional::Notional,
+};
+
+#[test]
+fn parses_rules() {
+    let rule: AlertRule = "WETH/USDC@0.02<500 for 3".parse().unwrap();
+    assert_eq!(rule.pair, "WETH/USDC");
+    assert_eq!(rule.target_slippage, 0.02);
+    assert_eq!(rule.below, Notional::Tokens("500".to_string()));
+    assert_eq!(rule.blocks, 3);
+    assert_eq!(rule.to_string(), "WETH/USDC@0.02<500 for 3");
+
+    let rule: AlertRule = "WETH/USDC@0.01<$1M".parse().unwrap();
+    assert_eq!((rule.below, rule.blocks), (Notional::Usd(1_000_000.0), 1));
+
+    for bad in ["WETH/USDC<500", "WETH/USDC@2<500", "WETH/USDC@0.02<500 for 0", "WETH@0.02<500"] {
+        assert!(bad.parse::<AlertRule>().is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn fires_once_per_streak_and_resolves() {
+    let mut monitor: AlertMonitor = AlertMonitor::new(vec!["weth/usdc@0.02<500 for 3".parse().unwrap()]);
+    let mut observe = |block: u64, depth: Option<f64>| -> Vec<Alert> {
+        monitor.observe("WETH/USDC", 0.02, block, depth, None, "WETH")
+    };
+
+    assert!(observe(1, Some(400.0)).is_empty());
+    // A failed search neither counts nor breaks the streak.
+    assert!(observe(2, None).is_empty());
+    assert!(observe(3, Some(450.0)).is_empty());
+    let fired: Vec<Alert> = observe(4, Some(420.0));
+    assert_eq!(fired.len(), 1);
+    assert_eq!((fired[0].kind, fired[0].since_block, fired[0].block_number), (AlertKind::Firing, 1, 4));
+    assert!(observe(5, Some(100.0)).is_empty());
+
+    let resolved: Vec<Alert> = observe(6, Some(600.0));
+    assert_eq!((resolved[0].kind, resolved[0].since_block), (AlertKind::Resolved, 1));
+    assert!(observe(7, Some(700.0)).is_empty());
+
+    // Other targets and pairs aren't covered.
+    assert!(monitor.observe("WETH/USDC", 0.01, 8, Some(1.0), None, "WETH").is_empty());
+    assert!(monitor.observe("WBTC/USDC", 0.02, 8, Some(1.0), None, "WBTC").is_empty());
+}
@@ -0,0 +1,55 @@
+//! `--bundle`: the resolved config written so it can be re-run, and state fingerprints that don't
+//! depend on hash map order.
+
+use std::{collections::HashMap, env, fs::File, io::Read, path::PathBuf};
+use liquidity_depth_cli::{
+    bundle::{canonical_debug, RunBundle},
+    config::{Config, PairConfig},
+};
+
+#[test]
+fn canonical_debug_sorts_maps_only() {
+    let forward: HashMap<u32, &str> = (0..64).map(|i| (i, "tick")).collect();
+    let backward: HashMap<u32, &str> = (0..64).rev().map(|i| (i, "tick")).collect();
+    assert_eq!(canonical_debug(&format!("{:?}", forward)), canonical_debug(&format!("{:?}", backward)));
+
+    assert_eq!(canonical_debug("Pool { ticks: {3: [2, 1], -5: (9, 8)} }"), "Pool {ticks: {-5: (9, 8), 3: [2, 1]}}");
+    // Braces and commas inside strings are text.
+    assert_eq!(canonical_debug(r#"{"b": 1, "a, {\"}": 2}"#), r#"{"a, {\"}": 2, "b": 1}"#);
+}
+
+#[test]
+fn writes_the_resolved_config_for_replay() {
+    let config: Config = Config {
+        precision: Some(0.0005),
+        tvl_min: Some(50.0),
+        pairs: vec![PairConfig {
+            token_in: "WETH".to_string(),
+            token_out: "USDC".to_string(),
+            ladder: None,
+            slippages: Some(vec![0.005, 0.02]),
+            reference: None,
+            usd_price: None,
+        }],
+        ..Default::default()
+    };
+    let path: PathBuf = env::temp_dir().join(format!("liquidity-depth-bundle-{}.tar", std::process::id()));
+    RunBundle::new("ethereum", "tycho.example").with_config(&config).write(&path).unwrap();
+
+    let mut archive = tar::Archive::new(File::open(&path).unwrap());
+    let mut written: Option<String> = None;
+    for entry in archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        if entry.path().unwrap().to_str() == Some("config.toml") {
+            let mut raw: String = String::new();
+            entry.read_to_string(&mut raw).unwrap();
+            written = Some(raw);
+        }
+    }
+    let replayed: Config = Config::from_toml_str(&written.expect("config.toml in the bundle")).unwrap();
+    assert_eq!(replayed.precision, Some(0.0005));
+    assert_eq!(replayed.tvl_min, Some(50.0));
+    assert_eq!(replayed.pairs[0].slippages, Some(vec![0.005, 0.02]));
+
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,59 @@
+//! The embedding API, used from outside the crate as another project would.
+
+use alloy_primitives::U256;
+use num_bigint::BigUint;
+use tycho_simulation::{evm::protocol::uniswap_v2::state::UniswapV2State, models::Token};
+use liquidity_depth_cli::{
+    binary_search::{calculate_output_for_slippage_tolerance, DEFAULT_PRECISION},
+    DepthCalculator, DepthError, SearchResult,
+};
+
+/// A function to build a WETH/USDC pool at 3,000 USDC per WETH, 1,000 WETH deep.
+fn pool() -> (UniswapV2State, Token, Token) {
+    let weth: Token = Token::new("0x0000000000000000000000000000000000000001", 18, "WETH", BigUint::from(0u32));
+    let usdc: Token = Token::new("0x0000000000000000000000000000000000000002", 6, "USDC", BigUint::from(0u32));
+    let reserve_weth: U256 = U256::from(1_000u64) * U256::from(10u64).pow(U256::from(18u64));
+    let reserve_usdc: U256 = U256::from(3_000_000u64) * U256::from(10u64).pow(U256::from(6u64));
+    (UniswapV2State::new(reserve_weth, reserve_usdc), weth, usdc)
+}
+
+#[test]
+fn depth_matches_the_search() {
+    let (state, weth, usdc) = pool();
+    let depth: SearchResult = DepthCalculator::new(&state, &weth, &usdc).depth_at(0.01).unwrap();
+    let search: SearchResult =
+        calculate_output_for_slippage_tolerance(0.01, DEFAULT_PRECISION, &state, &weth, &usdc).unwrap();
+    assert_eq!((depth.amount_in, depth.amount_out), (search.amount_in, search.amount_out));
+    assert!(depth.converged);
+
+    // Pricing the depth it found gives back its own fill.
+    let fill: SearchResult = DepthCalculator::new(&state, &weth, &usdc).slippage_for(depth.amount_in).unwrap();
+    assert_eq!((fill.amount_out, fill.slippage), (depth.amount_out, depth.slippage));
+}
+
+#[test]
+fn targets_directions_and_references() {
+    let (state, weth, usdc) = pool();
+    let calculator: DepthCalculator = DepthCalculator::new(&state, &weth, &usdc).with_precision(1e-6);
+
+    // Deeper at looser targets, in the order asked for.
+    let depths: Vec<SearchResult> = calculator.depths_at(&[0.02, 0.005]).unwrap();
+    assert!(depths[0].amount_in > depths[1].amount_in);
+
+    // Both ways is the calculator and its reverse.
+    let (forward, reverse): (SearchResult, SearchResult) = calculator.depth_both_ways(0.01).unwrap();
+    assert_eq!(forward.amount_in, calculator.depth_at(0.01).unwrap().amount_in);
+    assert_eq!(reverse.amount_in, calculator.reversed().depth_at(0.01).unwrap().amount_in);
+
+    // A reference under spot makes every fill look better, so more fits under the same target.
+    let spot: f64 = calculator.reference_price().unwrap();
+    let marked: DepthCalculator = calculator.with_reference_price(spot * 0.99);
+    assert!(marked.depth_at(0.01).unwrap().amount_in > forward.amount_in);
+
+    // An exact output is reached, and one past the pool isn't.
+    let amount_out: U256 = U256::from(10_000u64) * U256::from(1_000_000u64);
+    let fill: SearchResult = calculator.input_for_output(amount_out, 0.05).unwrap();
+    assert!(fill.amount_out >= amount_out);
+    let past: U256 = U256::from(3_000_000u64) * U256::from(1_000_000u64);
+    assert!(matches!(calculator.input_for_output(past, 0.05), Err(DepthError::OutputOutOfReach { .. })));
+}
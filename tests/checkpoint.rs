@@ -0,0 +1,35 @@
+//! `--checkpoint`: saved and loaded back, and what a restarted run missed.
+
+use std::{env, fs, path::PathBuf};
+use liquidity_depth_cli::checkpoint::Checkpoint;
+
+fn checkpoint() -> Checkpoint {
+    Checkpoint { chain: "ethereum".to_string(), block_number: 100, pools: vec!["0xa".to_string(), "0xb".to_string()] }
+}
+
+#[test]
+fn saves_and_loads_back() {
+    let path: PathBuf = env::temp_dir().join(format!("liquidity-depth-checkpoint-{}.json", std::process::id()));
+    let _ = fs::remove_file(&path);
+    assert_eq!(Checkpoint::load(&path).unwrap(), None);
+
+    checkpoint().save(&path).unwrap();
+    let mut later: Checkpoint = checkpoint();
+    later.block_number = 101;
+    later.save(&path).unwrap();
+    assert_eq!(Checkpoint::load(&path).unwrap(), Some(later));
+
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn finds_the_blocks_and_pools_missed_while_stopped() {
+    let checkpoint: Checkpoint = checkpoint();
+    // Picking up at the next block, or at one already measured, misses nothing.
+    assert_eq!(checkpoint.gap(101), None);
+    assert_eq!(checkpoint.gap(99), None);
+    assert_eq!(checkpoint.gap(105), Some((101, 104)));
+
+    let (gone, new) = checkpoint.pool_changes(["0xc", "0xb"]);
+    assert_eq!((gone, new), (vec!["0xa"], vec!["0xc"]));
+}
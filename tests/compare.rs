@@ -0,0 +1,105 @@
+//! Protocol filters for `--protocols`, sets for `--compare-protocols`, the delta between two
+//! sets' depths, and an aggregate's split by protocol.
+
+use num_bigint::BigUint;
+use tycho_simulation::models::Token;
+use liquidity_depth_cli::{
+    aggregate::PoolAllocation,
+    compare::{comparison_pair, ProtocolSet},
+    protocols::{protocol_filter, ProtocolFilter},
+    report::{Conversions, Leg, ProtocolComparison, ProtocolShare, SetDepth},
+};
+
+fn set_depth(name: &str, base_amount: &str) -> SetDepth {
+    SetDepth {
+        name: name.to_string(),
+        pools: 1,
+        base_amount: Some(base_amount.to_string()),
+        quote_amount: None,
+        usd_value: None,
+        conversions: Conversions::default(),
+        slippage: None,
+        error: None,
+    }
+}
+
+#[test]
+fn filters_include_and_exclude() {
+    let with_v4: ProtocolSet = "with-v4=uniswap_v3, uniswap_v4".parse().unwrap();
+    assert!(with_v4.matches("uniswap_v4") && with_v4.matches("uniswap_v3"));
+    assert!(!with_v4.matches("vm:curve"));
+
+    // Only exclusions keep everything else.
+    let without_v4: ProtocolSet = "without-v4=!uniswap_v4".parse().unwrap();
+    assert!(without_v4.matches("vm:curve") && !without_v4.matches("uniswap_v4"));
+    assert_eq!(without_v4.to_string(), "without-v4=!uniswap_v4");
+
+    let everything: ProtocolSet = "all=".parse().unwrap();
+    assert!(everything.matches("uniswap_v2"));
+
+    for bad in ["uniswap_v4", "=uniswap_v4", "no v4=!uniswap_v4", "x=!"] {
+        assert!(bad.parse::<ProtocolSet>().is_err(), "{}", bad);
+    }
+    assert!(comparison_pair(&[with_v4.clone(), without_v4.clone()]).is_ok());
+    assert!(comparison_pair(std::slice::from_ref(&with_v4)).is_err());
+    assert!(comparison_pair(&[with_v4.clone(), with_v4]).is_err());
+}
+
+#[test]
+fn delta_is_signed() {
+    let more: ProtocolComparison =
+        ProtocolComparison::new(set_depth("a", "3000000000000000000"), set_depth("b", "2000000000000000000"), 18);
+    assert_eq!(more.delta_base_amount.as_deref(), Some("1000000000000000000"));
+    assert_eq!(more.delta_base_tokens.as_deref(), Some("1"));
+    assert_eq!(more.delta_ratio, Some(0.5));
+
+    let less: ProtocolComparison =
+        ProtocolComparison::new(set_depth("a", "1500000000000000000"), set_depth("b", "2000000000000000000"), 18);
+    assert_eq!(less.delta_base_tokens.as_deref(), Some("-0.5"));
+    assert_eq!(less.delta_ratio, Some(-0.25));
+
+    // Against nothing there's a delta but no ratio.
+    let from_nothing: ProtocolComparison = ProtocolComparison::new(set_depth("a", "5"), SetDepth::empty("b"), 0);
+    assert_eq!(from_nothing.delta_base_tokens.as_deref(), Some("5"));
+    assert_eq!(from_nothing.delta_ratio, None);
+}
+
+#[test]
+fn filters_the_stream() {
+    let strings = |protocols: &[&str]| -> Vec<String> { protocols.iter().map(|p| p.to_string()).collect() };
+
+    let only: ProtocolFilter = protocol_filter(&strings(&["uniswap_v3", "uniswap_v4"]), &[]).unwrap();
+    assert!(only.matches("uniswap_v3") && !only.matches("vm:curve"));
+    let without: ProtocolFilter = protocol_filter(&[], &strings(&["vm:curve"])).unwrap();
+    assert!(without.matches("uniswap_v3") && !without.matches("vm:curve"));
+    assert_eq!(without.to_string(), "!vm:curve");
+    assert!(protocol_filter(&[], &[]).unwrap().is_empty());
+
+    assert!(protocol_filter(&strings(&["uniswap_v3"]), &strings(&["uniswap_v3"])).is_err());
+    assert!(protocol_filter(&strings(&["!vm:curve"]), &[]).is_err());
+    assert!(protocol_filter(&strings(&[" "]), &[]).is_err());
+}
+
+#[test]
+fn breaks_the_split_down_by_protocol() {
+    let weth: Token = Token::new("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2", 18, "WETH", BigUint::from(0u32));
+    let usdc: Token = Token::new("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", 6, "USDC", BigUint::from(0u32));
+    let allocation = |pool_id: &str, amount_in: u64, amount_out: u64| PoolAllocation {
+        pool_id: pool_id.to_string(),
+        amount_in: amount_in.to_string(),
+        amount_out: amount_out.to_string(),
+    };
+    let allocations: Vec<PoolAllocation> =
+        vec![allocation("0xa", 300, 9), allocation("0xb", 100, 3), allocation("0xc", 600, 18)];
+    let protocol_of = |id: &str| if id == "0xb" { "uniswap_v4".to_string() } else { "uniswap_v3".to_string() };
+
+    let shares: Vec<ProtocolShare> = ProtocolShare::breakdown(&allocations, protocol_of, &Leg::sell(&weth, &usdc, 3.0));
+    assert_eq!(shares.len(), 2);
+    assert_eq!((shares[0].protocol.as_str(), shares[0].pools, shares[0].base_amount.as_str()), ("uniswap_v3", 2, "900"));
+    assert_eq!(shares[0].share, Some(0.9));
+    assert_eq!((shares[1].protocol.as_str(), shares[1].base_amount.as_str()), ("uniswap_v4", "100"));
+
+    // Buying base, what the pools pay out is the base side.
+    let shares: Vec<ProtocolShare> = ProtocolShare::breakdown(&allocations, protocol_of, &Leg::buy(&weth, &usdc, 3.0));
+    assert_eq!((shares[0].base_amount.as_str(), shares[0].quote_amount.as_str()), ("27", "900"));
+}
@@ -0,0 +1,122 @@
+//! Tokens with unusual decimals (0 for NFT-like ERC20s, 2 for GUSD, 24 for some synths) must give
+//! the same depth, in whole tokens, as the same pool written with the usual 6 and 18.
+
+use alloy_primitives::U256;
+use num_bigint::BigUint;
+use tycho_simulation::{evm::protocol::uniswap_v2::state::UniswapV2State, models::Token};
+use liquidity_depth_cli::{
+    amount::{format_amount, one_token, parse_amount, MAX_DECIMALS},
+    binary_search::{calc_slippage, calculate_output_for_slippage_tolerance, check_slippage_vs_target_within_tolerance},
+    notional::Notional,
+};
+
+/// Every decimals value the matrix covers.
+const DECIMALS: [usize; 6] = [0, 2, 6, 8, 18, 24];
+
+fn token(address: &str, decimals: usize, symbol: &str) -> Token {
+    Token::new(address, decimals, symbol, BigUint::from(0u32))
+}
+
+/// A function to build the same pool whatever the decimals: a million of token_in against three
+/// billion of token_out, i.e. a price of 3,000.
+fn pool(decimals_in: usize, decimals_out: usize) -> (UniswapV2State, Token, Token) {
+    // token_in has the lower address, so it's reserve0.
+    let token_in: Token = token("0x0000000000000000000000000000000000000001", decimals_in, "IN");
+    let token_out: Token = token("0x0000000000000000000000000000000000000002", decimals_out, "OUT");
+    let reserve_in: U256 = U256::from(1_000_000u64) * one_token(decimals_in).unwrap();
+    let reserve_out: U256 = U256::from(3_000_000_000u64) * one_token(decimals_out).unwrap();
+    (UniswapV2State::new(reserve_in, reserve_out), token_in, token_out)
+}
+
+/// A function to turn base units into whole tokens, for comparing depths across decimals.
+fn whole(amount: U256, decimals: usize) -> f64 {
+    format_amount(amount, decimals).parse().unwrap()
+}
+
+#[test]
+fn amounts_round_trip() {
+    for decimals in DECIMALS {
+        for written in ["0", "1", "12.5", "0.01", "1000000"] {
+            let Ok(amount) = parse_amount(written, decimals as u8) else {
+                // Only fractions finer than the token can't be parsed, e.g. 0.01 of a 0-decimal token.
+                assert!(written.contains('.') && decimals < 2, "{} at {} decimals", written, decimals);
+                continue;
+            };
+            assert_eq!(format_amount(amount, decimals), written, "at {} decimals", decimals);
+        }
+    }
+    // A whole amount has no fraction to write, and a 0-decimal token has no fractions at all.
+    assert_eq!(format_amount(U256::from(12u64), 0), "12");
+    assert_eq!(format_amount(U256::from(1205u64), 2), "12.05");
+    assert_eq!(format_amount(U256::from(5u64), 24), "0.000000000000000000000005");
+    assert_eq!(format_amount(U256::ZERO, 0), "0");
+}
+
+#[test]
+fn one_whole_token() {
+    assert_eq!(one_token(0), Some(U256::from(1u64)));
+    assert_eq!(one_token(2), Some(U256::from(100u64)));
+    assert_eq!(one_token(24), Some(U256::from(10u64).pow(U256::from(24u64))));
+    assert!(one_token(MAX_DECIMALS).is_some());
+    assert_eq!(one_token(MAX_DECIMALS + 1), None);
+}
+
+#[test]
+fn slippage_ignores_decimals() {
+    // Sell 10 tokens for 29,850 against a spot of 3,000: 0.5% whatever either side's decimals.
+    for decimals_in in DECIMALS {
+        for decimals_out in DECIMALS {
+            let amount_in: U256 = U256::from(10u64) * one_token(decimals_in).unwrap();
+            let amount_out: U256 = U256::from(29_850u64) * one_token(decimals_out).unwrap();
+            let slippage = calc_slippage(amount_in, amount_out, decimals_in, decimals_out, 3000.0).unwrap();
+            assert!(
+                check_slippage_vs_target_within_tolerance(&slippage, 0.005, 1e-12).unwrap(),
+                "{}/{} decimals: {:?}",
+                decimals_in,
+                decimals_out,
+                slippage
+            );
+        }
+    }
+}
+
+#[test]
+fn depth_ignores_decimals() {
+    let (state, token_in, token_out) = pool(18, 18);
+    let reference: f64 =
+        whole(calculate_output_for_slippage_tolerance(0.01, 1e-6, &state, &token_in, &token_out).unwrap().amount_in, 18);
+    for decimals_in in DECIMALS {
+        for decimals_out in DECIMALS {
+            let (state, token_in, token_out) = pool(decimals_in, decimals_out);
+            let result = calculate_output_for_slippage_tolerance(0.01, 1e-6, &state, &token_in, &token_out).unwrap();
+            let depth: f64 = whole(result.amount_in, decimals_in);
+            // A 0-decimal token_in can only be off by the one unit it can't split.
+            let tolerance: f64 = (1e-4 * reference).max(1.0 / 10f64.powi(decimals_in as i32));
+            assert!(
+                (depth - reference).abs() <= tolerance,
+                "{}/{} decimals: {} vs {} tokens",
+                decimals_in,
+                decimals_out,
+                depth,
+                reference
+            );
+        }
+    }
+}
+
+#[test]
+fn notionals_at_any_decimals() {
+    // 24 fractional digits are fine until the token turns out to have fewer.
+    let notional: Notional = "1.000000000000000000000001".parse().unwrap();
+    let synth: Token = token("0x0000000000000000000000000000000000000003", 24, "SYN");
+    let amount: U256 = notional.amount_in(&synth, None).unwrap();
+    assert_eq!(amount, one_token(24).unwrap() + U256::from(1u64));
+    let gusd: Token = token("0x0000000000000000000000000000000000000004", 2, "GUSD");
+    assert!(notional.amount_in(&gusd, None).is_err());
+
+    // Dollar notionals round to what the token can hold.
+    let dollars: Notional = "$1,000".parse().unwrap();
+    assert_eq!(dollars.amount_in(&gusd, Some(1.0)).unwrap(), U256::from(100_000u64));
+    let nft: Token = token("0x0000000000000000000000000000000000000005", 0, "NFT");
+    assert_eq!(dollars.amount_in(&nft, Some(300.0)).unwrap(), U256::from(3u64));
+}
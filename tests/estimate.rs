@@ -0,0 +1,125 @@
+//! The two-probe estimate behind `--coarse`, checked against the full search it stands in for.
+
+use alloy_primitives::U256;
+use num_bigint::BigUint;
+use tycho_simulation::{evm::protocol::u256_num::u256_to_f64, models::Token};
+use liquidity_depth_cli::{
+    estimate::DepthEstimate, mock::MockXykPool, AmountOutProvider, DepthCalculator, DepthError, SearchResult,
+};
+
+fn tokens() -> (Token, Token) {
+    (
+        Token::new("0x0000000000000000000000000000000000000001", 18, "WETH", BigUint::from(0u32)),
+        Token::new("0x0000000000000000000000000000000000000002", 6, "USDC", BigUint::from(0u32)),
+    )
+}
+
+/// A WETH/USDC pool with Uniswap v3-style concentrated liquidity: `inner` liquidity down to 3%
+/// under the starting price, then half that below it. Selling WETH moves `1/sqrt(price)` by
+/// `amount_in / liquidity`, with a 0.05% fee taken from the input.
+struct Concentrated {
+    price: f64,
+    inner: f64,
+}
+
+impl Concentrated {
+    const FEE: f64 = 0.0005;
+    const BAND: f64 = 0.97;
+
+    fn whole_out(&self, whole_in: f64) -> f64 {
+        let start: f64 = self.price.sqrt();
+        let edge: f64 = (self.price * Self::BAND).sqrt();
+        let to_edge: f64 = self.inner * (1.0 / edge - 1.0 / start);
+        let sold: f64 = whole_in * (1.0 - Self::FEE);
+        if sold <= to_edge {
+            return self.inner * (start - 1.0 / (1.0 / start + sold / self.inner));
+        }
+        let outer: f64 = self.inner / 2.0;
+        self.inner * (start - edge) + outer * (edge - 1.0 / (1.0 / edge + (sold - to_edge) / outer))
+    }
+}
+
+impl AmountOutProvider for Concentrated {
+    fn amount_out(&self, amount_in: BigUint, token_in: &Token, token_out: &Token) -> Result<BigUint, DepthError> {
+        let whole_in: f64 = amount_in.to_string().parse::<f64>().unwrap() / 10f64.powi(token_in.decimals as i32);
+        let out: f64 = self.whole_out(whole_in) * 10f64.powi(token_out.decimals as i32);
+        Ok(BigUint::from(out.floor() as u128))
+    }
+
+    fn spot(&self, _token_in: &Token, _token_out: &Token) -> Result<f64, DepthError> {
+        Ok(self.price)
+    }
+
+    /// Valued like `bracket_cap` values a pool from its limits: the band's WETH, twice over for
+    /// both sides, times the multiple.
+    fn max_in(&self, token_in: &Token, _token_out: &Token, _reference_price: f64, tvl_multiple: f64) -> Option<U256> {
+        let to_edge: f64 = self.inner * (1.0 / (self.price * Self::BAND).sqrt() - 1.0 / self.price.sqrt());
+        Some(U256::from((2.0 * to_edge * tvl_multiple * 10f64.powi(token_in.decimals as i32)) as u128))
+    }
+}
+
+/// A stable pair quoted one for one, which no probe moves.
+struct Pegged;
+
+impl AmountOutProvider for Pegged {
+    fn amount_out(&self, amount_in: BigUint, _token_in: &Token, _token_out: &Token) -> Result<BigUint, DepthError> {
+        Ok(amount_in)
+    }
+
+    fn spot(&self, _token_in: &Token, _token_out: &Token) -> Result<f64, DepthError> {
+        Ok(1.0)
+    }
+}
+
+/// A function to check the estimate lands within 10% of the full search at every target.
+fn assert_close(calculator: &DepthCalculator, targets: &[f64]) {
+    for &target in targets {
+        let searched: SearchResult = calculator.depth_at(target).unwrap();
+        let estimate: DepthEstimate = calculator.estimate_at(target).unwrap();
+        let error: f64 = (u256_to_f64(estimate.amount_in) / u256_to_f64(searched.amount_in) - 1.0).abs();
+        assert!(error < 0.10, "{}% off at {} slippage", error * 100.0, target);
+        assert!(!estimate.lower_bound);
+    }
+}
+
+#[test]
+fn tracks_the_search_on_v2_pools() {
+    let (weth, usdc) = tokens();
+    let one_weth: U256 = U256::from(10u64).pow(U256::from(18u64));
+    let pool: MockXykPool = MockXykPool::new(
+        &weth,
+        U256::from(1_000u64) * one_weth,
+        &usdc,
+        U256::from(3_000_000_000_000u64),
+    );
+    let calculator: DepthCalculator = DepthCalculator::from_provider(&pool, &weth, &usdc);
+    assert_close(&calculator, &[0.005, 0.01, 0.02, 0.05]);
+
+    // The probes scale with the pool instead of starting at one token: a tenth of the reserve.
+    let estimate: DepthEstimate = calculator.estimate_at(0.01).unwrap();
+    assert_eq!(estimate.probes[1].0, U256::from(100u64) * one_weth);
+    assert_eq!(estimate.probes[0].0, one_weth / U256::from(10u64));
+}
+
+#[test]
+fn tracks_the_search_on_concentrated_liquidity() {
+    let (weth, usdc) = tokens();
+    // About 150 WETH sells down to the edge of the band.
+    let pool: Concentrated = Concentrated { price: 3_000.0, inner: 550_000.0 };
+    let calculator: DepthCalculator = DepthCalculator::from_provider(&pool, &weth, &usdc);
+    assert_close(&calculator, &[0.005, 0.01, 0.015, 0.02]);
+
+    // Well past the band the probes never saw the thinner liquidity, so the estimate overshoots.
+    let searched: SearchResult = calculator.depth_at(0.03).unwrap();
+    assert!(calculator.estimate_at(0.03).unwrap().amount_in > searched.amount_in);
+}
+
+#[test]
+fn marks_a_pool_the_probes_cannot_move_as_a_lower_bound() {
+    let usdc: Token = Token::new("0x0000000000000000000000000000000000000002", 6, "USDC", BigUint::from(0u32));
+    let usdt: Token = Token::new("0x0000000000000000000000000000000000000003", 6, "USDT", BigUint::from(0u32));
+    let estimate: DepthEstimate = DepthCalculator::from_provider(&Pegged, &usdc, &usdt).estimate_at(0.01).unwrap();
+    assert!(estimate.lower_bound);
+    // With no cap to size from, the probes are one and a thousand whole tokens.
+    assert_eq!(estimate.amount_in, U256::from(1_000_000_000u64));
+}
@@ -0,0 +1,65 @@
+//! `--charts-dir`: every pair's figures written, and redrawn as blocks come in. Only built with
+//! `--features charts`.
+#![cfg(feature = "charts")]
+
+use std::{env, fs, path::PathBuf};
+use liquidity_depth_cli::{
+    chart::{ChartSeries, DepthChart, FigureFormat},
+    figures::FigureWriter,
+    report::{AggregateReport, BlockReport, Conversions, TradeAction},
+};
+
+fn block(block_number: u64, base_tokens: Option<&str>) -> BlockReport {
+    let aggregate = AggregateReport {
+        pair: "WETH/USDC".to_string(),
+        target_slippage: 0.02,
+        action: TradeAction::Sell,
+        pools: 2,
+        base_amount: None,
+        quote_amount: None,
+        usd_value: None,
+        conversions: Conversions {
+            base_tokens: base_tokens.map(str::to_string),
+            quote_tokens: None,
+            quote_usd_value: None,
+        },
+        slippage: None,
+        fee_slippage: None,
+        impact_slippage: None,
+        error: None,
+        stability: None,
+        organic: None,
+        comparison: None,
+        by_protocol: Vec::new(),
+    };
+    BlockReport { block_number, chain: "ethereum".to_string(), aggregates: vec![aggregate], ..Default::default() }
+}
+
+#[test]
+fn writes_curves_and_depth_history_per_pair() {
+    let dir: PathBuf = env::temp_dir().join(format!("liquidity-depth-figures-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let mut figures: FigureWriter = FigureWriter::create(&dir, FigureFormat::Svg).unwrap();
+    let chart = DepthChart {
+        pair: "WETH/USDC".to_string(),
+        base: "WETH".to_string(),
+        series: vec![ChartSeries {
+            label: "0xaaa".to_string(),
+            points: vec![(0.01, 0.003), (1.0, 0.0031), (100.0, 0.012), (10_000.0, 0.4)],
+        }],
+    };
+
+    let written: Vec<PathBuf> = figures.write_block(&block(100, Some("34")), &[chart]).unwrap();
+    assert_eq!(written, vec![dir.join("weth-usdc-curves.svg"), dir.join("weth-usdc-depth.svg")]);
+    let curves: String = fs::read_to_string(&written[0]).unwrap();
+    assert!(curves.starts_with("<svg"));
+    assert!(curves.contains("WETH/USDC slippage by size"));
+
+    // A failed block and a block without sampled curves still redraw the history.
+    figures.write_block(&block(101, None), &[]).unwrap();
+    let written: Vec<PathBuf> = figures.write_block(&block(102, Some("31.5")), &[]).unwrap();
+    assert_eq!(written, vec![dir.join("weth-usdc-depth.svg")]);
+    assert!(fs::read_to_string(&written[0]).unwrap().contains("WETH/USDC aggregate depth"));
+
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,447 @@
+//! Golden-file tests for every output renderer: the emoji and plain consoles, the JSON block
+//! document, CSV rows and the `--tui` dashboard. Each renderer's output for a fixed block is compared with its file under
+//! `tests/goldens/`.
+//!
+//! After an intended formatting change, regenerate the files and review the diff:
+//!
+//! ```sh
+//! cargo test --test goldens -- --update-goldens
+//! ```
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
+};
+use ratatui::{backend::TestBackend, Terminal};
+use liquidity_depth_cli::{
+    chart::{ChartSeries, DepthChart},
+    console::{ConsoleRenderer, EmojiRenderer, PlainRenderer},
+    dashboard::{BlockLag, Dashboard},
+    divergence::Divergence,
+    html::HtmlReport,
+    labels::{Label, Labels},
+    native::NativePrice,
+    report::{
+        AggregateFillReport, AggregateReport, BlockReport, Conversions, DepthReport, FillReport, OrganicDepth, PoolReport,
+        PoolStatus, ProtocolComparison, ProtocolShare, RouteReport, SetDepth, SimulationInputs, TradeAction,
+        TwoSidedReport,
+    },
+    sink::{Compression, CsvSink},
+    stability::DepthStability,
+};
+
+/// One renderer's output and the file it's checked against.
+struct Golden {
+    file: &'static str,
+    rendered: String,
+}
+
+fn main() -> ExitCode {
+    let update: bool = env::args().any(|arg| arg == "--update-goldens");
+    let dir: PathBuf = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("goldens");
+    let report: BlockReport = fixture();
+    let goldens: Vec<Golden> = vec![
+        Golden { file: "console_emoji.txt", rendered: render_console(&EmojiRenderer, &report) },
+        Golden { file: "console_plain.txt", rendered: render_console(&PlainRenderer, &report) },
+        Golden { file: "block.json", rendered: render_json(&report) },
+        Golden { file: "depth.csv", rendered: render_csv(&report) },
+        Golden { file: "dashboard.txt", rendered: render_dashboard(&report) },
+        Golden { file: "report.html", rendered: render_html(&report) },
+    ];
+
+    let mut failed: usize = 0;
+    for golden in goldens.iter() {
+        let path: PathBuf = dir.join(golden.file);
+        if update {
+            fs::create_dir_all(&dir).expect("create tests/goldens");
+            fs::write(&path, &golden.rendered).expect("write golden");
+            println!("updated {}", golden.file);
+            continue;
+        }
+        let expected: String = match fs::read_to_string(&path) {
+            Ok(expected) => expected,
+            Err(e) => {
+                println!("FAIL {}: {} (run with --update-goldens to create it)", golden.file, e);
+                failed += 1;
+                continue;
+            }
+        };
+        match first_difference(&expected, &golden.rendered) {
+            None => println!("ok   {}", golden.file),
+            Some((line, expected, actual)) => {
+                println!("FAIL {} at line {}", golden.file, line);
+                println!("  expected: {}", expected);
+                println!("  actual:   {}", actual);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        println!("{} of {} goldens differ; if the change is intended, run with --update-goldens", failed, goldens.len());
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+/// A function to find the first line where two renderings part ways.
+///
+/// Returns:
+/// - The 1-based line number and both lines (empty past the end), or None if they're identical
+fn first_difference(expected: &str, actual: &str) -> Option<(usize, String, String)> {
+    if expected == actual {
+        return None;
+    }
+    let expected_lines: Vec<&str> = expected.lines().collect();
+    let actual_lines: Vec<&str> = actual.lines().collect();
+    let lines: usize = expected_lines.len().max(actual_lines.len());
+    // Identical lines but different bytes means a trailing newline changed.
+    let line: usize = (0..lines)
+        .find(|i| expected_lines.get(*i) != actual_lines.get(*i))
+        .unwrap_or(lines.saturating_sub(1));
+    Some((
+        line + 1,
+        expected_lines.get(line).unwrap_or(&"").to_string(),
+        actual_lines.get(line).unwrap_or(&"").to_string(),
+    ))
+}
+
+/// A function to build two pools' curves: a deep pool and a shallow one whose slippage climbs
+/// sooner.
+fn fixture_chart() -> DepthChart {
+    let sizes: [f64; 7] = [0.01, 0.1, 1.0, 10.0, 100.0, 1_000.0, 10_000.0];
+    let series = |label: &str, depth: f64| ChartSeries {
+        label: label.to_string(),
+        points: sizes.iter().map(|size| (*size, size / (size + depth))).collect(),
+    };
+    DepthChart {
+        pair: "WETH/USDC".to_string(),
+        base: "WETH".to_string(),
+        series: vec![
+            series("0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", 5_000.0),
+            series("0xcccccccccccccccccccccccccccccccccccccccc", 400.0),
+        ],
+    }
+}
+
+/// A function to render every console line once, in the order a run prints them.
+fn render_console(renderer: &dyn ConsoleRenderer, report: &BlockReport) -> String {
+    let lines: Vec<String> = vec![
+        renderer.waiting_for_first_block(),
+        renderer.block_header(21_000_000, 412, 3, 1),
+        renderer.native_price("WETH", 3012.25, "USDC", "0xaaa"),
+        renderer.usd_price("WETH", 3012.25, "pool 0xaaa"),
+        renderer.pair_summary("WETH/USDC", 2, 1),
+        renderer.depth_chart(&fixture_chart()),
+        renderer.quote("1", "WETH", "3003.21", "USDC"),
+        renderer.reference_shift("+5%", 3012.25, 3162.8625, "WETH", "USDC"),
+        renderer.reference_mark(3012.25, 3010.0, "WETH", "USDC"),
+        renderer.reference_unavailable("WETH/USDT", "mark is 95s old"),
+        renderer.depth(0.02, "17.5 WETH for 51,892.4 USDC"),
+        renderer.aggregate_depth("WETH/USDC", 2, 0.02, "34 WETH for 99,999.11 USDC"),
+        renderer.route_depth("WETH/USDC", "DAI", 0.02, "12 WETH → 35,421.8 USDC (pools 0xeee → 0xfff)"),
+        renderer.divergence(
+            "WETH/USDC",
+            42.5,
+            "3003.21 in 0xaaa vs 3016 in 0xccc; buy 3.1 WETH for 9,301.2 USDC and sell it in the other for 11.4 USDC profit",
+        ),
+        renderer.fill("$1M", "332 WETH for 982,010.5 USDC, slippage 1.96%"),
+        renderer.aggregate_fill("WETH/USDC", 2, "$1M", "332 WETH for 990,100.2 USDC, slippage 1.14%"),
+        renderer.impact_table(&report.impact_table("WETH/USDC")),
+        renderer.skipped_pool("WETH", "DAI"),
+        renderer.hidden_rows(4),
+        renderer.wrote_file("bundle", "out.tar.zst"),
+        renderer.unreliable_pool("0xddd", "output fell from 10 to 9 between 1e18 and 2e18"),
+        renderer.failed_pool("0xbbb", "simulation failed: out of gas"),
+        renderer.simulator_disagreement("0xaaa", "native 3003.21 vs vm 3003.19 (0.7 bps)"),
+        renderer.stream_reconnected(21_000_005, 2),
+        renderer.endpoint_failover("tycho-beta.propellerheads.xyz", "tycho-backup.example.com", "no block for 60s"),
+        renderer.shutting_down("SIGINT"),
+        renderer.run_summary(120, 1, 2, &report.labels.to_string()),
+    ];
+    let mut rendered: String = lines.join("\n");
+    rendered.push('\n');
+    rendered
+}
+
+fn render_json(report: &BlockReport) -> String {
+    let mut rendered: String = serde_json::to_string_pretty(report).expect("serialize block report");
+    rendered.push('\n');
+    rendered
+}
+
+/// A function to write the block through `CsvSink`, as `--csv` does, and read the file back.
+fn render_csv(report: &BlockReport) -> String {
+    let path: PathBuf = env::temp_dir().join(format!("liquidity-depth-goldens-{}.csv", std::process::id()));
+    let _ = fs::remove_file(&path);
+    {
+        let mut sink: CsvSink = CsvSink::open(&path, Some(Compression::None)).expect("open csv sink");
+        sink.write_rows(&report.rows()).expect("write csv rows");
+        sink.flush().expect("flush csv sink");
+    }
+    let rendered: String = fs::read_to_string(&path).expect("read csv back");
+    let _ = fs::remove_file(&path);
+    rendered
+}
+
+/// A function to draw the dashboard after a few blocks on a fixed-size screen, one line per row.
+fn render_dashboard(report: &BlockReport) -> String {
+    let mut dashboard: Dashboard = Dashboard::new("ethereum");
+    for (i, scale) in [1.0, 0.8, 0.4, 0.9].into_iter().enumerate() {
+        let mut block: BlockReport = report.clone();
+        block.block_number += i as u64;
+        for aggregate in block.aggregates.iter_mut() {
+            let tokens: f64 = aggregate.conversions.base_tokens.as_deref().unwrap_or("0").parse().unwrap();
+            aggregate.conversions.base_tokens = Some((tokens * scale).to_string());
+        }
+        dashboard.observe(&block, BlockLag { queued: 1, measured_in: Duration::from_millis(1_250) });
+    }
+    let mut terminal: Terminal<TestBackend> = Terminal::new(TestBackend::new(120, 24)).expect("test terminal");
+    terminal.draw(|frame| dashboard.render(frame)).expect("draw dashboard");
+    let buffer = terminal.backend().buffer();
+    let mut rendered: String = String::new();
+    for row in buffer.content().chunks(buffer.area.width as usize) {
+        let line: String = row.iter().map(|cell| cell.symbol()).collect();
+        rendered.push_str(line.trim_end());
+        rendered.push('\n');
+    }
+    rendered
+}
+
+/// A function to render the HTML report over a few blocks of the fixture, one with the aggregate
+/// failed.
+fn render_html(report: &BlockReport) -> String {
+    let mut html: HtmlReport = HtmlReport::new("WETH/USDC depth <weekly>");
+    for (i, scale) in [Some(1.0), Some(0.8), None, Some(0.9)].into_iter().enumerate() {
+        let mut block: BlockReport = report.clone();
+        block.block_number += i as u64;
+        for aggregate in block.aggregates.iter_mut() {
+            let tokens: f64 = aggregate.conversions.base_tokens.as_deref().unwrap_or("0").parse().unwrap();
+            aggregate.conversions.base_tokens = scale.map(|scale| (tokens * scale).to_string());
+        }
+        html.observe(&block);
+    }
+    html.render()
+}
+
+/// A block that exercises every kind of record: measured depth with a failed target, a failed
+/// pool, two-sided depth, a fixed-size fill, and aggregates.
+fn fixture() -> BlockReport {
+    // WETH is $3012.25 and so is the reference, so a USDC is exactly a dollar.
+    let conversions = |base_tokens: &str, quote_tokens: &str, quote_usd_value: f64| Conversions {
+        base_tokens: Some(base_tokens.to_string()),
+        quote_tokens: Some(quote_tokens.to_string()),
+        quote_usd_value: Some(quote_usd_value),
+    };
+    let depth = |target_slippage: f64, base_amount: &str, quote_amount: &str, slippage: f64| DepthReport {
+        conversions: Conversions::default(),
+        target_slippage,
+        action: TradeAction::Sell,
+        approximate: false,
+        base_amount: Some(base_amount.to_string()),
+        quote_amount: Some(quote_amount.to_string()),
+        usd_value: Some(52_714.375),
+        slippage: Some(slippage),
+        fee_slippage: Some(0.003),
+        impact_slippage: Some(1.0 - (1.0 - slippage) / 0.997),
+        converged: Some(true),
+        iterations: Some(14),
+        elapsed_ms: Some(3.5),
+        pool_limited: None,
+        error: None,
+        simulation: None,
+    };
+    let pool = |pool_id: &str, status: PoolStatus| PoolReport {
+        pool_id: pool_id.to_string(),
+        protocol: "uniswap_v2".to_string(),
+        pair: "WETH/USDC".to_string(),
+        base: "0x4200000000000000000000000000000000000006".to_string(),
+        quote: "0x078d782b760474a361dda0af3839290b0ef57ad6".to_string(),
+        status,
+        base_usd_price: Some(3012.25),
+        fee: Some(0.003),
+        spot_price: Some(3012.25),
+        reference_price: Some(3012.25),
+        excluded: None,
+        depths: Vec::new(),
+        two_sided: Vec::new(),
+        fills: Vec::new(),
+        stale_since_block: None,
+        protocol_owned: false,
+    };
+    let fill = |notional: &str, quote_amount: &str, quote_tokens: &str, slippage: f64| FillReport {
+        action: TradeAction::Sell,
+        notional: notional.to_string(),
+        base_amount: Some("332000000000000000000".to_string()),
+        quote_amount: Some(quote_amount.to_string()),
+        usd_value: Some(1_000_067.0),
+        conversions: conversions("332", quote_tokens, quote_tokens.parse().unwrap()),
+        slippage: Some(slippage),
+        fee_slippage: Some(0.003),
+        impact_slippage: Some(1.0 - (1.0 - slippage) / 0.997),
+        error: None,
+    };
+
+    let mut measured: PoolReport = pool("0xaaa", PoolStatus::Ok);
+    measured.depths = vec![
+        DepthReport {
+            simulation: Some(SimulationInputs {
+                token_in: "0x4200000000000000000000000000000000000006".to_string(),
+                token_out: "0x078d782b760474a361dda0af3839290b0ef57ad6".to_string(),
+                amount_in: "4375000000000000000".to_string(),
+                amount_out: "13114300000".to_string(),
+                gas: "120000".to_string(),
+            }),
+            conversions: conversions("4.375", "13114.3", 13_114.3),
+            ..depth(0.005, "4375000000000000000", "13114300000", 0.0049)
+        },
+        DepthReport {
+            error: Some("search did not converge after 64 simulations".to_string()),
+            base_amount: None,
+            quote_amount: None,
+            usd_value: None,
+            slippage: None,
+            fee_slippage: None,
+            impact_slippage: None,
+            converged: None,
+            iterations: None,
+            elapsed_ms: None,
+            ..depth(0.02, "0", "0", 0.0)
+        },
+        DepthReport {
+            pool_limited: Some("quotes 1000 but not 1000.9765625: gas limit exceeded".to_string()),
+            usd_value: Some(3_012_250.0),
+            conversions: conversions("1000", "1495000", 1_495_000.0),
+            ..depth(0.5, "1000000000000000000000", "1495000000000", 0.3187)
+        },
+    ];
+    measured.two_sided = vec![TwoSidedReport {
+        target_slippage: 0.02,
+        mid_price: 3012.25,
+        bid_base_amount: Some("17500000000000000000".to_string()),
+        bid_quote_amount: Some("51892400000".to_string()),
+        ask_base_amount: Some("17100000000000000000".to_string()),
+        ask_quote_amount: Some("52564000000".to_string()),
+        base_amount: Some("34600000000000000000".to_string()),
+        quote_amount: Some("104456400000".to_string()),
+        usd_value: Some(104_223.85),
+        conversions: conversions("34.6", "104456.4", 104_456.4),
+        error: None,
+    }];
+
+    let mut failed: PoolReport = pool("0xbbb", PoolStatus::Failed);
+    failed.protocol = "vm:curve".to_string();
+    failed.fee = None;
+    failed.spot_price = None;
+    failed.reference_price = None;
+    failed.excluded = Some("failed: simulation failed: out of gas".to_string());
+
+    let mut filled: PoolReport = pool("0xccc", PoolStatus::Ok);
+    filled.fills = vec![fill("$1M", "982010500000", "982010.5", 0.0196)];
+    filled.stale_since_block = Some(20_999_998);
+    filled.protocol_owned = true;
+
+    BlockReport {
+        block_number: 21_000_000,
+        chain: "ethereum".to_string(),
+        native_price: Some(NativePrice {
+            price: 3012.25,
+            native: "WETH".to_string(),
+            stable: "USDC".to_string(),
+            pool_id: "0xaaa".to_string(),
+        }),
+        pools: vec![measured, failed, filled],
+        aggregates: vec![AggregateReport {
+            pair: "WETH/USDC".to_string(),
+            target_slippage: 0.02,
+            action: TradeAction::Sell,
+            pools: 2,
+            base_amount: Some("34000000000000000000".to_string()),
+            quote_amount: Some("99999114998".to_string()),
+            usd_value: Some(102_416.5),
+            conversions: conversions("34", "99999.114998", 99_999.114_998),
+            slippage: Some(0.0196),
+            fee_slippage: Some(0.003),
+            impact_slippage: Some(1.0 - (1.0 - 0.0196) / 0.997),
+            error: None,
+            stability: Some(DepthStability { blocks: 20, mean: 3.2e19, std_dev: 1.6e18, cv: Some(0.05) }),
+            organic: Some(OrganicDepth {
+                protocol_owned_pools: 1,
+                base_amount: Some("17500000000000000000".to_string()),
+                quote_amount: Some("51892400000".to_string()),
+                usd_value: Some(52_714.375),
+                conversions: conversions("17.5", "51892.4", 51_892.4),
+                slippage: Some(0.0199),
+                error: None,
+            }),
+            comparison: Some(ProtocolComparison::new(
+                SetDepth {
+                    name: "with-v4".to_string(),
+                    pools: 2,
+                    base_amount: Some("34000000000000000000".to_string()),
+                    quote_amount: Some("99999114998".to_string()),
+                    usd_value: Some(102_416.5),
+                    conversions: conversions("34", "99999.114998", 99_999.114_998),
+                    slippage: Some(0.0196),
+                    error: None,
+                },
+                SetDepth {
+                    name: "without-v4".to_string(),
+                    pools: 1,
+                    base_amount: Some("17500000000000000000".to_string()),
+                    quote_amount: Some("51892400000".to_string()),
+                    usd_value: Some(52_714.375),
+                    conversions: conversions("17.5", "51892.4", 51_892.4),
+                    slippage: Some(0.0199),
+                    error: None,
+                },
+                18,
+            )),
+            by_protocol: vec![
+                ProtocolShare {
+                    protocol: "uniswap_v3".to_string(),
+                    pools: 1,
+                    base_amount: "20000000000000000000".to_string(),
+                    quote_amount: "58823000000".to_string(),
+                    share: Some(20.0 / 34.0),
+                },
+                ProtocolShare {
+                    protocol: "uniswap_v4".to_string(),
+                    pools: 1,
+                    base_amount: "14000000000000000000".to_string(),
+                    quote_amount: "41176114998".to_string(),
+                    share: Some(14.0 / 34.0),
+                },
+            ],
+        }],
+        aggregate_fills: vec![AggregateFillReport {
+            pair: "WETH/USDC".to_string(),
+            pools: 2,
+            fill: fill("$1M", "990100200000", "990100.2", 0.0114),
+        }],
+        routes: vec![RouteReport {
+            pair: "WETH/USDC".to_string(),
+            via: Some("DAI".to_string()),
+            via_address: Some("0x50c5725949a6f0c72e6c4a641f24049a917db0cb".to_string()),
+            first_pool: Some("0xeee".to_string()),
+            second_pool: Some("0xfff".to_string()),
+            depth: DepthReport { usd_value: Some(36_147.0), ..depth(0.02, "12000000000000000000", "35421800000", 0.0199) },
+        }],
+        divergences: vec![Divergence {
+            pair: "WETH/USDC".to_string(),
+            cheap_pool: "0xaaa".to_string(),
+            rich_pool: "0xccc".to_string(),
+            cheap_spot: 3003.21,
+            rich_spot: 3016.0,
+            divergence_bps: 42.5,
+            base_amount: "3100000000000000000".to_string(),
+            quote_amount: "9301200000".to_string(),
+            profit_amount: "11400000".to_string(),
+            base_tokens: "3.1".to_string(),
+            quote_tokens: "9301.2".to_string(),
+            profit_tokens: "11.4".to_string(),
+        }],
+        labels: Labels::merge(["experiment=fee-change-study", "analyst=zw"].map(|label| label.parse::<Label>().unwrap())),
+        reorg: false,
+    }
+}
@@ -0,0 +1,129 @@
+//! `serve`'s routing, against block views with no pools so nothing needs a stream.
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::PathBuf,
+    time::{Duration, UNIX_EPOCH},
+};
+use num_bigint::BigUint;
+use serde_json::json;
+use tycho_common::{models::Chain, Bytes};
+use tycho_simulation::models::Token;
+use liquidity_depth_cli::{
+    http::{route, HttpResponse},
+    hub::BlockView,
+    report::{AggregateReport, BlockReport, Conversions, TradeAction},
+    rpc::RpcHandler,
+    sink::SqliteSink,
+};
+
+fn handler() -> RpcHandler {
+    let tokens: HashMap<Bytes, Token> = [
+        Token::new("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2", 18, "WETH", BigUint::from(0u32)),
+        Token::new("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48", 6, "USDC", BigUint::from(0u32)),
+    ]
+    .into_iter()
+    .map(|token| (token.address.clone(), token))
+    .collect();
+    RpcHandler::new(Chain::Ethereum, tokens, 1e-4)
+}
+
+fn get(target: &str, view: &BlockView) -> HttpResponse {
+    route(&handler(), &format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", target), view)
+}
+
+#[test]
+fn routes_queries_to_methods() {
+    let view: BlockView = BlockView { block_number: Some(21_000_000), ..Default::default() };
+
+    let pools: HttpResponse = get("/pools?pair=WETH-USDC", &view);
+    assert_eq!(pools.status, 200, "{}", pools.body);
+    assert_eq!(pools.body, json!({ "block_number": 21_000_000, "pair": "WETH/USDC", "pools": [] }));
+
+    let depth: HttpResponse = get("/depth?pair=WETH-USDC&slippage=0.005,0.02", &view);
+    assert_eq!(depth.status, 200, "{}", depth.body);
+    assert_eq!(depth.body["block_number"], json!(21_000_000));
+
+    // Bad values reach the method and come back as its error.
+    let depth: HttpResponse = get("/depth?pair=WETH-USDC&slippage=2", &view);
+    assert_eq!(depth.status, 400);
+    assert!(depth.body["error"]["message"].as_str().unwrap().contains("slippages must be decimals"));
+}
+
+#[test]
+fn rejects_what_it_cant_answer() {
+    let view: BlockView = BlockView { block_number: Some(1), ..Default::default() };
+    assert_eq!(get("/liquidity?pair=WETH-USDC", &view).status, 404);
+    assert_eq!(route(&handler(), "POST /depth?pair=WETH-USDC HTTP/1.1\r\n\r\n", &view).status, 405);
+    assert_eq!(get("/depth", &view).status, 400);
+    assert_eq!(get("/depth?pair=WETH", &view).status, 400);
+    assert_eq!(get("/pools?pair=WETH-USDC&samples=3", &view).status, 400);
+    assert_eq!(get("/pools?pair=WETH-DOGE", &view).status, 400);
+
+    // Nothing to answer from until the first block.
+    let response: HttpResponse = get("/pools?pair=WETH-USDC", &BlockView::default());
+    assert_eq!(response.status, 503);
+    assert!(String::from_utf8(response.to_bytes()).unwrap().starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+}
+
+/// A block whose only record is WETH/USDC's aggregate sell depth at 2%.
+fn aggregate_block(block_number: u64, base_tokens: &str) -> BlockReport {
+    let aggregate = AggregateReport {
+        pair: "WETH/USDC".to_string(),
+        target_slippage: 0.02,
+        action: TradeAction::Sell,
+        pools: 1,
+        base_amount: None,
+        quote_amount: None,
+        usd_value: None,
+        conversions: Conversions { base_tokens: Some(base_tokens.to_string()), quote_tokens: None, quote_usd_value: None },
+        slippage: None,
+        fee_slippage: None,
+        impact_slippage: None,
+        error: None,
+        stability: None,
+        organic: None,
+        comparison: None,
+        by_protocol: Vec::new(),
+    };
+    BlockReport { block_number, aggregates: vec![aggregate], ..Default::default() }
+}
+
+#[test]
+fn serves_history_from_the_sqlite_database() {
+    let path: PathBuf = env::temp_dir().join(format!("liquidity-depth-http-{}.db", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let mut sink: SqliteSink = SqliteSink::open(&path).unwrap();
+    // Two blocks in the hour from 7200s, one in the next, one past the window asked for.
+    let blocks: [(u64, u64, &str); 4] = [(100, 7_300, "400"), (101, 9_000, "600"), (102, 11_000, "500"), (103, 20_000, "1")];
+    for (block_number, at, base_tokens) in blocks {
+        sink.write_block(&aggregate_block(block_number, base_tokens), UNIX_EPOCH + Duration::from_secs(at)).unwrap();
+    }
+    drop(sink);
+
+    let handler: RpcHandler = handler().with_history(path.clone());
+    let ask = |target: &str| route(&handler, &format!("GET {} HTTP/1.1\r\n\r\n", target), &BlockView::default());
+
+    // History reads the database, so it answers before the stream's first block.
+    let history: HttpResponse = ask("/depth/history?pair=WETH-USDC&from=7000&to=12000&resolution=1h");
+    assert_eq!(history.status, 200, "{}", history.body);
+    assert_eq!(history.body["pair"], json!("WETH/USDC"));
+    let points = &history.body["targets"][0]["points"];
+    assert_eq!(history.body["targets"][0]["target_slippage"], json!(0.02));
+    assert_eq!(points.as_array().unwrap().len(), 2);
+    assert_eq!(points[0]["start"], json!(7_200));
+    assert_eq!(points[0]["blocks"], json!(2));
+    assert_eq!(points[0]["base_tokens"], json!(500.0));
+    assert_eq!(points[0]["min_base_tokens"], json!(400.0));
+    assert_eq!(points[0]["max_base_tokens"], json!(600.0));
+    assert_eq!((points[1]["start"].clone(), points[1]["first_block"].clone()), (json!(10_800), json!(102)));
+
+    assert_eq!(ask("/depth/history?pair=WETH-USDC&resolution=1w").status, 400);
+    assert_eq!(ask("/depth/history?pair=WETH-USDC&from=yesterday").status, 400);
+    // Without a database there's no history to read.
+    let no_history: HttpResponse = get("/depth/history?pair=WETH-USDC", &BlockView::default());
+    assert_eq!(no_history.status, 400);
+
+    let _ = fs::remove_file(&path);
+}
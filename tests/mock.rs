@@ -0,0 +1,419 @@
+//! The offline constant-product pool, checked against the Uniswap v2 state it stands in for and
+//! used to test the search, signed slippage math, exact tolerances, adaptive precision, the
+//! bracketing cap, attribution, aggregation, routing and divergence sizing without Tycho.
+
+use alloy_primitives::U256;
+use num_bigint::BigUint;
+use tycho_simulation::{evm::protocol::uniswap_v2::state::UniswapV2State, models::Token, protocol::state::ProtocolSim};
+use liquidity_depth_cli::{
+    aggregate::{
+        aggregate_depth, aggregate_depth_against_reference, aggregate_fill_against_reference, best_spot_price,
+        AggregateDepth, DEFAULT_SPLIT_CHUNKS,
+    },
+    attribution::{routed_fee, SlippageAttribution},
+    binary_search::{
+        adaptive_precision, calc_slippage, check_slippage_vs_target_within_tolerance, PoolLimitPolicy,
+        DEFAULT_BRACKET_TVL_MULTIPLE, DEFAULT_MAX_ITERATIONS, DEFAULT_PRECISION,
+    },
+    divergence::{widest_divergence, Divergence},
+    mock::MockXykPool,
+    provider::Pool,
+    route::{best_two_hop, two_hop_legs, RouteDepth},
+    AmountOutProvider, DepthCalculator, DepthError, SearchResult, Slippage,
+};
+
+fn tokens() -> (Token, Token) {
+    (
+        Token::new("0x0000000000000000000000000000000000000001", 18, "WETH", BigUint::from(0u32)),
+        Token::new("0x0000000000000000000000000000000000000002", 6, "USDC", BigUint::from(0u32)),
+    )
+}
+
+fn weth(amount: u64) -> U256 {
+    U256::from(amount) * U256::from(10u64).pow(U256::from(18u64))
+}
+
+fn usdc(amount: u64) -> U256 {
+    U256::from(amount) * U256::from(1_000_000u64)
+}
+
+/// A mock that won't quote past `ceiling`, like a pool that runs out of initialized ticks.
+struct Ceilinged {
+    pool: MockXykPool,
+    ceiling: U256,
+}
+
+impl AmountOutProvider for Ceilinged {
+    fn amount_out(&self, amount_in: BigUint, token_in: &Token, token_out: &Token) -> Result<BigUint, DepthError> {
+        if amount_in > self.ceiling.to_string().parse::<BigUint>().unwrap() {
+            return Err(DepthError::Simulation("no liquidity past the last tick".to_string()));
+        }
+        self.pool.amount_out(amount_in, token_in, token_out)
+    }
+
+    fn spot(&self, token_in: &Token, token_out: &Token) -> Result<f64, DepthError> {
+        self.pool.spot(token_in, token_out)
+    }
+}
+
+#[test]
+fn quotes_like_uniswap_v2() {
+    let (weth_token, usdc_token) = tokens();
+    let mock: MockXykPool = MockXykPool::new(&weth_token, weth(1_000), &usdc_token, usdc(3_000_000));
+    // WETH has the lower address, so it's reserve0.
+    let state: UniswapV2State = UniswapV2State::new(weth(1_000), usdc(3_000_000));
+
+    for amount_in in [weth(1), weth(37), weth(500)] {
+        let mocked: BigUint = mock.amount_out(amount_in.to_string().parse().unwrap(), &weth_token, &usdc_token).unwrap();
+        let simulated: BigUint =
+            state.get_amount_out(amount_in.to_string().parse().unwrap(), &weth_token, &usdc_token).unwrap().amount;
+        assert_eq!(mocked, simulated, "selling {}", amount_in);
+    }
+    let depth: SearchResult = DepthCalculator::from_provider(&mock, &weth_token, &usdc_token).depth_at(0.01).unwrap();
+    let v2_depth: SearchResult = DepthCalculator::new(&state, &weth_token, &usdc_token).depth_at(0.01).unwrap();
+    assert_eq!((depth.amount_in, depth.amount_out), (v2_depth.amount_in, v2_depth.amount_out));
+}
+
+#[test]
+fn fees_and_directions() {
+    let (weth_token, usdc_token) = tokens();
+    let fee_free: MockXykPool = MockXykPool::new(&weth_token, weth(1_000), &usdc_token, usdc(3_000_000)).with_fee_bps(0);
+    let mock: MockXykPool = fee_free.clone().with_fee_bps(100);
+    assert_eq!(mock.fee(), 0.01);
+
+    // A tiny sale slips by about the fee and no more.
+    let amount_in: U256 = weth(1) / U256::from(1_000u64);
+    let amount_out: BigUint = mock.amount_out(amount_in.to_string().parse().unwrap(), &weth_token, &usdc_token).unwrap();
+    let slippage: f64 =
+        calc_slippage(amount_in, amount_out.to_string().parse().unwrap(), 18, 6, 3000.0).unwrap().to_f64();
+    assert!((slippage - 0.01).abs() < 1e-5, "{}", slippage);
+
+    // A fee eats into depth: a 2% target leaves 1% for impact.
+    let calculator: DepthCalculator = DepthCalculator::from_provider(&mock, &weth_token, &usdc_token).with_precision(1e-9);
+    let depth: f64 = f64::from(calculator.depth_at(0.02).unwrap().amount_in) / 1e18;
+    let fee_free_depth: f64 =
+        f64::from(DepthCalculator::from_provider(&fee_free, &weth_token, &usdc_token).with_precision(1e-9).depth_at(0.02).unwrap().amount_in)
+            / 1e18;
+    assert!(depth < fee_free_depth / 1.9, "{} vs {}", depth, fee_free_depth);
+
+    // The same pool sells the other way, from the inverse spot.
+    assert_eq!(mock.spot(&usdc_token, &weth_token).unwrap(), 1.0 / 3000.0);
+    let reverse: SearchResult = calculator.reversed().depth_at(0.02).unwrap();
+    assert!((f64::from(reverse.amount_in) / 1e6 / 3000.0 - depth).abs() / depth < 1e-3);
+}
+
+#[test]
+fn attributes_slippage_to_fee_and_impact() {
+    let (weth_token, usdc_token) = tokens();
+    let pool: MockXykPool = MockXykPool::new(&weth_token, weth(1_000), &usdc_token, usdc(3_000_000));
+    let depth: SearchResult =
+        DepthCalculator::from_provider(&pool, &weth_token, &usdc_token).with_precision(1e-7).depth_at(0.01).unwrap();
+    let split: SlippageAttribution = SlippageAttribution::split(depth.slippage.to_f64(), pool.fee()).unwrap();
+    assert_eq!(split.fee, 0.003);
+    // What's left is the constant-product impact of what the fee lets through: x / (reserve + x).
+    let sold: f64 = f64::from(depth.amount_in) * 0.997;
+    let impact: f64 = sold / (f64::from(weth(1_000)) + sold);
+    assert!((split.impact - impact).abs() < 1e-6, "{} vs {}", split.impact, impact);
+
+    // A fee-free pool's slippage is all impact.
+    let fee_free: MockXykPool = pool.clone().with_fee_bps(0);
+    let depth: SearchResult = DepthCalculator::from_provider(&fee_free, &weth_token, &usdc_token).depth_at(0.01).unwrap();
+    let split: SlippageAttribution = SlippageAttribution::split(depth.slippage.to_f64(), fee_free.fee()).unwrap();
+    assert_eq!(split.fee, 0.0);
+    assert!((split.impact - depth.slippage.to_f64()).abs() < 1e-15);
+
+    // Split across a 5 bps and a 30 bps pool, the fee paid is each one's weighted by its share.
+    let cheap: MockXykPool = pool.clone().with_fee_bps(5);
+    let pools: Vec<(String, Pool)> =
+        vec![("cheap".to_string(), Pool::Provider(&cheap)), ("pool".to_string(), Pool::Provider(&pool))];
+    let aggregate: AggregateDepth = aggregate_depth(&pools, 0.01, 1e-6, &weth_token, &usdc_token, 50).unwrap();
+    let fee_of = |id: &str| Some(if id == "cheap" { cheap.fee() } else { pool.fee() });
+    let cheap_in: f64 = aggregate.allocations.iter().find(|a| a.pool_id == "cheap").unwrap().amount_in.parse().unwrap();
+    let share: f64 = cheap_in / f64::from(aggregate.amount_in);
+    let fee: f64 = routed_fee(&aggregate.allocations, fee_of).unwrap();
+    assert!((fee - (share * 0.0005 + (1.0 - share) * 0.003)).abs() < 1e-12);
+    assert!(share > 0.5, "the cheaper pool takes more of the order, got {}", share);
+    assert_eq!(routed_fee(&aggregate.allocations, |_| None), None);
+}
+
+#[test]
+fn the_limit_policy_reports_or_fails_a_pool_limited_depth() {
+    let (weth_token, usdc_token) = tokens();
+    let pool: MockXykPool = MockXykPool::new(&weth_token, weth(1_000), &usdc_token, usdc(3_000_000)).with_fee_bps(5);
+    let capped: Ceilinged = Ceilinged { pool, ceiling: weth(2) };
+    let depths: Vec<SearchResult> =
+        DepthCalculator::from_provider(&capped, &weth_token, &usdc_token).depths_at(&[0.002, 0.02]).unwrap();
+
+    // About 1.5 WETH reaches 0.2%, under the ceiling, so that depth stands either way.
+    assert!(depths[0].converged && depths[0].limit.is_none());
+    for policy in [PoolLimitPolicy::Report, PoolLimitPolicy::Fail] {
+        assert!(policy.rejects(&depths[0]).is_none());
+    }
+
+    // 2% would take about 15 WETH: depth is reported as at least what's just under the ceiling.
+    let limited: &SearchResult = &depths[1];
+    assert!(!limited.converged);
+    assert!(limited.limit.as_deref().unwrap().contains("last tick"));
+    assert!(limited.amount_in <= weth(2) && limited.amount_in >= weth(2) - weth(2) / U256::from(512u64));
+    assert!(PoolLimitPolicy::Report.rejects(limited).is_none());
+    match PoolLimitPolicy::Fail.rejects(limited) {
+        Some(DepthError::PoolLimited { amount_in, .. }) => assert_eq!(amount_in, limited.amount_in),
+        other => panic!("expected PoolLimited, got {:?}", other),
+    }
+}
+
+#[test]
+fn improvement_counts_as_under_the_target() {
+    let (weth_token, usdc_token) = tokens();
+    let pool: MockXykPool = MockXykPool::new(&weth_token, weth(1_000), &usdc_token, usdc(3_000_000));
+    // Against a reference 2% under spot, small fills beat it: negative slippage, not an error.
+    let shifted: DepthCalculator =
+        DepthCalculator::from_provider(&pool, &weth_token, &usdc_token).with_reference_price(3_000.0 * 0.98);
+    let small: SearchResult = shifted.slippage_for(weth(1)).unwrap();
+    assert!(small.slippage.is_improvement());
+    assert!((small.slippage.to_f64() + 0.0167).abs() < 1e-3, "{:?}", small.slippage);
+
+    // The search reads improvement as under the target and keeps going: 1% against the shifted
+    // reference is 1 - 0.99 * 0.98 = 2.98% against spot.
+    let depth: f64 = f64::from(shifted.depth_at(0.01).unwrap().amount_in);
+    let spot: DepthCalculator = DepthCalculator::from_provider(&pool, &weth_token, &usdc_token);
+    let at_spot: f64 = f64::from(spot.depth_at(0.0298).unwrap().amount_in);
+    assert!((depth - at_spot).abs() / at_spot < 1e-3, "{} vs {}", depth, at_spot);
+}
+
+#[test]
+fn stops_at_the_bracketing_cap() {
+    let (weth_token, usdc_token) = tokens();
+    let pool: MockXykPool = MockXykPool::new(&weth_token, weth(1_000), &usdc_token, usdc(3_000_000));
+    // Even 100 times the reserve, where bracketing stops, only slips about 99%.
+    let depth: SearchResult = DepthCalculator::from_provider(&pool, &weth_token, &usdc_token).depth_at(0.995).unwrap();
+    assert_eq!(depth.amount_in, weth(100_000));
+    assert!(!depth.converged);
+    assert_eq!(depth.limit.as_deref(), Some("still under the target at the bracketing cap"));
+    assert!(depth.slippage.to_f64() < 0.995);
+}
+
+#[test]
+fn checks_the_tolerance_exactly() {
+    // 101/10000 is exactly 0.01 + 0.0001, so it's in, whichever way the f64 difference rounds.
+    let edge: Slippage = Slippage::new(U256::from(101u64), U256::from(10_000u64));
+    assert!(check_slippage_vs_target_within_tolerance(&edge, 0.01, 0.0001).unwrap());
+    let past: Slippage = Slippage::new(U256::from(1_010_001u64), U256::from(100_000_000u64));
+    assert!(!check_slippage_vs_target_within_tolerance(&past, 0.01, 0.0001).unwrap());
+    // Improvement is just further from the target.
+    let improvement: Slippage = Slippage::improvement(U256::from(1u64), U256::from(10_000u64));
+    assert!(!check_slippage_vs_target_within_tolerance(&improvement, 0.0, 0.00005).unwrap());
+
+    // Tolerances far below f64's resolution of the slippage still converge, a wei at a time.
+    let (weth_token, usdc_token) = tokens();
+    let pool: MockXykPool = MockXykPool::new(&weth_token, weth(1_000), &usdc_token, usdc(3_000_000));
+    let depth: SearchResult =
+        DepthCalculator::from_provider(&pool, &weth_token, &usdc_token).with_precision(1e-12).depth_at(0.01).unwrap();
+    assert!(depth.converged);
+    assert!(check_slippage_vs_target_within_tolerance(&depth.slippage, 0.01, 1e-12).unwrap(), "{:?}", depth.slippage);
+}
+
+#[test]
+fn adapts_the_precision_to_the_pool() {
+    let one: U256 = weth(1);
+    assert_eq!(adaptive_precision(1e-4, weth(2), one, 0.005), 5e-4);
+    assert_eq!(adaptive_precision(1e-4, weth(2), one, 0.02), 1e-3);
+    assert_eq!(adaptive_precision(1e-4, weth(1_024), one, 0.02), 1e-4);
+    assert_eq!(adaptive_precision(1e-4, weth(1 << 22), one, 0.02), 1e-5);
+
+    // A pool a few tokens deep settles sooner, at the looser tolerance.
+    let (weth_token, usdc_token) = tokens();
+    let tiny: MockXykPool = MockXykPool::new(&weth_token, weth(10), &usdc_token, usdc(30_000));
+    let fixed: SearchResult = DepthCalculator::from_provider(&tiny, &weth_token, &usdc_token).depth_at(0.05).unwrap();
+    let adapted: SearchResult = DepthCalculator::from_provider(&tiny, &weth_token, &usdc_token)
+        .with_adaptive_precision(true)
+        .depth_at(0.05)
+        .unwrap();
+    assert!(adapted.simulations < fixed.simulations, "{} vs {}", adapted.simulations, fixed.simulations);
+    assert!(check_slippage_vs_target_within_tolerance(&adapted.slippage, 0.05, 1e-3).unwrap());
+
+    // A pool millions of tokens deep is held to a tenth of the tolerance.
+    let deep: MockXykPool = MockXykPool::new(&weth_token, weth(1_000_000_000), &usdc_token, usdc(3_000_000_000_000));
+    let adapted: SearchResult = DepthCalculator::from_provider(&deep, &weth_token, &usdc_token)
+        .with_adaptive_precision(true)
+        .depth_at(0.01)
+        .unwrap();
+    assert!(adapted.converged);
+    let tightened: f64 = DEFAULT_PRECISION / 10.0;
+    let within: bool = check_slippage_vs_target_within_tolerance(&adapted.slippage, 0.01, tightened).unwrap();
+    assert!(within, "{:?}", adapted.slippage);
+}
+
+#[test]
+fn aggregates_across_mocks() {
+    let (weth_token, usdc_token) = tokens();
+    let half: MockXykPool = MockXykPool::new(&weth_token, weth(500), &usdc_token, usdc(1_500_000));
+    let whole: MockXykPool = MockXykPool::new(&weth_token, weth(1_000), &usdc_token, usdc(3_000_000));
+
+    // Two halves at the same price split an order evenly and are as deep as the whole.
+    let pools: Vec<(String, Pool)> = vec![("a".to_string(), Pool::Provider(&half)), ("b".to_string(), Pool::Provider(&half))];
+    let aggregate: AggregateDepth = aggregate_depth(&pools, 0.01, 1e-6, &weth_token, &usdc_token, 50).unwrap();
+    let single: SearchResult =
+        DepthCalculator::from_provider(&whole, &weth_token, &usdc_token).with_precision(1e-6).depth_at(0.01).unwrap();
+    let (aggregate_in, single_in): (f64, f64) = (f64::from(aggregate.amount_in), f64::from(single.amount_in));
+    assert!((aggregate_in - single_in).abs() / single_in < 1e-3, "{} vs {}", aggregate_in, single_in);
+    assert_eq!(aggregate.allocations.len(), 2);
+    assert_eq!(aggregate.allocations[0].amount_in, aggregate.allocations[1].amount_in);
+    assert!(aggregate.converged && aggregate.iterations > 0);
+}
+
+#[test]
+fn bounds_the_aggregate_search() {
+    let (weth_token, usdc_token) = tokens();
+    let small: MockXykPool = MockXykPool::new(&weth_token, weth(500), &usdc_token, usdc(1_500_000));
+    let large: MockXykPool = MockXykPool::new(&weth_token, weth(2_000), &usdc_token, usdc(6_000_000));
+    let pools: Vec<(String, Pool)> =
+        vec![("small".to_string(), Pool::Provider(&small)), ("large".to_string(), Pool::Provider(&large))];
+    let reference: f64 = best_spot_price(&pools, &weth_token, &usdc_token).unwrap();
+    let search = |target: f64, precision: f64, max_iterations: u32| {
+        aggregate_depth_against_reference(
+            &pools,
+            target,
+            precision,
+            &weth_token,
+            &usdc_token,
+            DEFAULT_SPLIT_CHUNKS,
+            reference,
+            max_iterations,
+            DEFAULT_BRACKET_TVL_MULTIPLE,
+        )
+    };
+
+    // Out of iterations short of a tolerance it can't meet: the best amount under the target.
+    let capped: AggregateDepth = search(0.01, 1e-15, 3).unwrap();
+    assert!(!capped.converged);
+    assert_eq!(capped.iterations, 3);
+    assert!(capped.slippage.to_f64() < 0.01);
+
+    // Bracketing stops at the pools' caps added up, 100 times each reserve.
+    match search(0.999, 1e-6, DEFAULT_MAX_ITERATIONS) {
+        Err(DepthError::InsufficientLiquidity { max_in, .. }) => assert_eq!(max_in, weth(250_000)),
+        other => panic!("expected InsufficientLiquidity, got {:?}", other.map(|a| a.amount_in)),
+    }
+}
+
+#[test]
+fn best_spot_price_skips_pools_that_fail() {
+    let (weth_token, usdc_token) = tokens();
+    let dai_token: Token = Token::new("0x0000000000000000000000000000000000000003", 18, "DAI", BigUint::from(0u32));
+    let priced: MockXykPool = MockXykPool::new(&weth_token, weth(1_000), &usdc_token, usdc(3_000_000));
+    // Doesn't trade WETH for USDC, so it can't give a spot price.
+    let broken: MockXykPool = MockXykPool::new(&weth_token, weth(1_000), &dai_token, weth(3_000_000));
+
+    let pools: Vec<(String, Pool)> =
+        vec![("broken".to_string(), Pool::Provider(&broken)), ("priced".to_string(), Pool::Provider(&priced))];
+    let spot: f64 = best_spot_price(&pools, &weth_token, &usdc_token).unwrap();
+    assert!((spot - 3_000.0).abs() < 1e-9);
+
+    let none_priced: Vec<(String, Pool)> = vec![("broken".to_string(), Pool::Provider(&broken))];
+    let error: String = best_spot_price(&none_priced, &weth_token, &usdc_token).unwrap_err().to_string();
+    assert!(error.contains("broken"), "{}", error);
+}
+
+#[test]
+fn refines_a_coarse_split() {
+    let (weth_token, usdc_token) = tokens();
+    let small: MockXykPool = MockXykPool::new(&weth_token, weth(1_000), &usdc_token, usdc(3_000_000));
+    let large: MockXykPool = MockXykPool::new(&weth_token, weth(3_000), &usdc_token, usdc(9_000_000));
+    let pools: Vec<(String, Pool)> =
+        vec![("small".to_string(), Pool::Provider(&small)), ("large".to_string(), Pool::Provider(&large))];
+    let reference: f64 = best_spot_price(&pools, &weth_token, &usdc_token).unwrap();
+
+    // Two chunks of 200 WETH can only split 0/400 or 200/200; refining gets to the 100/300 that
+    // keeps both pools at the same marginal price.
+    let fill: AggregateDepth =
+        aggregate_fill_against_reference(&pools, weth(400), &weth_token, &usdc_token, 2, reference).unwrap();
+    let small_in: f64 = fill.allocations.iter().find(|a| a.pool_id == "small").unwrap().amount_in.parse().unwrap();
+    let optimal: f64 = f64::from(weth(100));
+    assert!((small_in - optimal).abs() / optimal < 0.05, "{} vs {}", small_in, optimal);
+}
+
+#[test]
+fn refined_split_beats_the_greedy_one() {
+    let (weth_token, usdc_token) = tokens();
+    let shallow: MockXykPool = MockXykPool::new(&weth_token, weth(800), &usdc_token, usdc(2_400_000)).with_fee_bps(5);
+    let deep: MockXykPool = MockXykPool::new(&weth_token, weth(5_000), &usdc_token, usdc(15_000_000));
+    let pools: Vec<(String, Pool)> =
+        vec![("shallow".to_string(), Pool::Provider(&shallow)), ("deep".to_string(), Pool::Provider(&deep))];
+    let reference: f64 = best_spot_price(&pools, &weth_token, &usdc_token).unwrap();
+    let fill: AggregateDepth =
+        aggregate_fill_against_reference(&pools, weth(600), &weth_token, &usdc_token, 3, reference).unwrap();
+
+    // Three chunks of 200 WETH can only split in steps of 200, so that's the best greedy can do.
+    let quote = |pool: &MockXykPool, amount: U256| -> U256 {
+        let out: BigUint = pool.amount_out(amount.to_string().parse().unwrap(), &weth_token, &usdc_token).unwrap();
+        out.to_string().parse().unwrap()
+    };
+    let greedy_best: U256 = (0..=3u64)
+        .map(|to_shallow| quote(&shallow, weth(200 * to_shallow)) + quote(&deep, weth(600 - 200 * to_shallow)))
+        .max()
+        .unwrap();
+    assert!(fill.amount_out > greedy_best, "{} vs {}", fill.amount_out, greedy_best);
+    let outputs: U256 = fill.allocations.iter().map(|a| a.amount_out.parse::<U256>().unwrap()).fold(U256::ZERO, |a, b| a + b);
+    assert_eq!(outputs, fill.amount_out);
+}
+
+#[test]
+fn routes_through_an_intermediate() {
+    let (weth_token, usdc_token) = tokens();
+    let dai_token: Token = Token::new("0x0000000000000000000000000000000000000003", 18, "DAI", BigUint::from(0u32));
+    let dai = |amount: u64| U256::from(amount) * U256::from(10u64).pow(U256::from(18u64));
+    let weth_usdc: MockXykPool = MockXykPool::new(&weth_token, weth(1_000), &usdc_token, usdc(3_000_000));
+    let usdc_dai: MockXykPool = MockXykPool::new(&usdc_token, usdc(30_000_000), &dai_token, dai(30_000_000));
+    let tokens: Vec<(&str, Vec<Token>)> = vec![
+        ("0xa", vec![weth_token.clone(), usdc_token.clone()]),
+        ("0xb", vec![usdc_token.clone(), dai_token.clone()]),
+    ];
+
+    let legs = two_hop_legs(tokens.iter().map(|(id, tokens)| (*id, tokens.as_slice())), &weth_token, &dai_token);
+    assert_eq!(legs.len(), 1);
+    let pool_of = |id: &str| match id {
+        "0xa" => Some(Pool::Provider(&weth_usdc as &dyn AmountOutProvider)),
+        "0xb" => Some(Pool::Provider(&usdc_dai as &dyn AmountOutProvider)),
+        _ => None,
+    };
+    let route: RouteDepth = best_two_hop(&legs, pool_of, &weth_token, &dai_token, 0.01, 1e-6).unwrap();
+    assert_eq!(route.route.via.symbol, "USDC");
+    assert_eq!((route.route.first_pool.as_str(), route.route.second_pool.as_str()), ("0xa", "0xb"));
+    assert!((route.spot - 3000.0).abs() < 1e-6, "{}", route.spot);
+
+    // The route is shallower than its first pool alone, since the second adds its own impact.
+    let first: SearchResult =
+        DepthCalculator::from_provider(&weth_usdc, &weth_token, &usdc_token).with_precision(1e-6).depth_at(0.01).unwrap();
+    assert!(route.depth.amount_in < first.amount_in);
+
+    // No route without a pool on both sides of the intermediate.
+    let legs = two_hop_legs(tokens[..1].iter().map(|(id, tokens)| (*id, tokens.as_slice())), &weth_token, &dai_token);
+    assert!(best_two_hop(&legs, pool_of, &weth_token, &dai_token, 0.01, 1e-6).is_err());
+}
+
+#[test]
+fn sizes_a_divergence_until_prices_meet() {
+    let (weth_token, usdc_token) = tokens();
+    let cheap: MockXykPool = MockXykPool::new(&weth_token, weth(1_000), &usdc_token, usdc(3_000_000)).with_fee_bps(0);
+    let rich: MockXykPool = MockXykPool::new(&weth_token, weth(1_000), &usdc_token, usdc(3_300_000)).with_fee_bps(0);
+    let pools: Vec<(String, Pool)> =
+        vec![("cheap".to_string(), Pool::Provider(&cheap)), ("rich".to_string(), Pool::Provider(&rich))];
+
+    let divergence: Divergence = widest_divergence("WETH/USDC", &pools, &weth_token, &usdc_token, 50.0).unwrap().unwrap();
+    assert_eq!((divergence.cheap_pool.as_str(), divergence.rich_pool.as_str()), ("cheap", "rich"));
+    assert!((divergence.divergence_bps - 1_000.0).abs() < 1e-6, "{}", divergence.divergence_bps);
+    // Fee-free pools meet when (1000 + x) / (1000 - x) = sqrt(3.3 / 3), at x = 23.82 WETH.
+    let moved: f64 = divergence.base_amount.parse::<f64>().unwrap() / 1e18;
+    assert!((moved - 23.82).abs() / 23.82 < 0.01, "{}", moved);
+    assert!(divergence.profit_amount.parse::<u64>().unwrap() > 0);
+
+    // A gap narrower than the threshold isn't flagged, and one inside the fees has no size.
+    assert!(widest_divergence("WETH/USDC", &pools, &weth_token, &usdc_token, 1_500.0).unwrap().is_none());
+    let cheap: MockXykPool = cheap.with_fee_bps(100);
+    let rich: MockXykPool = MockXykPool::new(&weth_token, weth(1_000), &usdc_token, usdc(3_030_000)).with_fee_bps(100);
+    let pools: Vec<(String, Pool)> =
+        vec![("cheap".to_string(), Pool::Provider(&cheap)), ("rich".to_string(), Pool::Provider(&rich))];
+    let divergence: Divergence = widest_divergence("WETH/USDC", &pools, &weth_token, &usdc_token, 50.0).unwrap().unwrap();
+    assert_eq!(divergence.base_amount, "0");
+}
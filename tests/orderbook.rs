@@ -0,0 +1,68 @@
+//! `orderbook`'s ladder, over constant-product mocks whose marginal price has a closed form.
+
+use alloy_primitives::U256;
+use num_bigint::BigUint;
+use tycho_simulation::models::Token;
+use liquidity_depth_cli::{
+    mock::MockXykPool,
+    orderbook::{order_book, BookLevel, OrderBook},
+    provider::Pool,
+};
+
+fn tokens() -> (Token, Token) {
+    (
+        Token::new("0x0000000000000000000000000000000000000001", 18, "WETH", BigUint::from(0u32)),
+        Token::new("0x0000000000000000000000000000000000000002", 6, "USDC", BigUint::from(0u32)),
+    )
+}
+
+fn tokens_of(amount: &str) -> f64 {
+    amount.replace(',', "").parse().unwrap()
+}
+
+#[test]
+fn ladders_a_constant_product_pool() {
+    let (weth, usdc) = tokens();
+    let reserve_weth: U256 = U256::from(1_000u64) * U256::from(10u64).pow(U256::from(18u64));
+    let reserve_usdc: U256 = U256::from(3_000_000_000_000u64);
+    let pool: MockXykPool = MockXykPool::new(&weth, reserve_weth, &usdc, reserve_usdc).with_fee_bps(0);
+    let pools: Vec<(String, Pool)> = vec![("0xa".to_string(), Pool::Provider(&pool))];
+
+    let book: OrderBook = order_book("WETH/USDC", &pools, &weth, &usdc, 50, 4).unwrap();
+    assert!((book.mid_price - 3000.0).abs() < 1e-6, "{}", book.mid_price);
+    assert_eq!((book.bids.len(), book.asks.len()), (4, 4));
+
+    // Selling x WETH moves the marginal price to mid * (R / (R + x))^2, so at 1% below mid
+    // x = R * (1 / sqrt(0.99) - 1).
+    let bid: &BookLevel = &book.bids[1];
+    assert_eq!(bid.offset_bps, 100);
+    let expected: f64 = 1_000.0 * (1.0 / 0.99f64.sqrt() - 1.0);
+    assert!((tokens_of(&bid.base_tokens) - expected).abs() / expected < 1e-3, "{} vs {}", bid.base_tokens, expected);
+
+    // Buying is the same on the USDC side, so at 1% above mid the pool has sold
+    // R * (1 - 1 / sqrt(1.01)) WETH.
+    let ask: &BookLevel = &book.asks[1];
+    let expected: f64 = 1_000.0 * (1.0 - 1.0 / 1.01f64.sqrt());
+    assert!((tokens_of(&ask.base_tokens) - expected).abs() / expected < 1e-3, "{} vs {}", ask.base_tokens, expected);
+
+    // Cumulative: every level further out has at least as much.
+    for side in [&book.bids, &book.asks] {
+        for levels in side.windows(2) {
+            assert!(tokens_of(&levels[1].base_tokens) >= tokens_of(&levels[0].base_tokens));
+        }
+    }
+}
+
+#[test]
+fn fees_leave_the_first_levels_empty() {
+    let (weth, usdc) = tokens();
+    let reserve_weth: U256 = U256::from(1_000u64) * U256::from(10u64).pow(U256::from(18u64));
+    let pool: MockXykPool = MockXykPool::new(&weth, reserve_weth, &usdc, U256::from(3_000_000_000_000u64));
+    let pools: Vec<(String, Pool)> = vec![("0xa".to_string(), Pool::Provider(&pool))];
+
+    // The default 0.3% fee is paid on the first unit, so nothing trades within 20 bps of mid.
+    let book: OrderBook = order_book("WETH/USDC", &pools, &weth, &usdc, 10, 5).unwrap();
+    assert_eq!(book.bids[1].base_amount, "0");
+    assert_eq!(book.asks[1].base_amount, "0");
+    assert_ne!(book.bids[4].base_amount, "0");
+}
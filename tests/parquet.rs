@@ -0,0 +1,105 @@
+//! `--output parquet`: block reports written as Hive-partitioned Parquet files and read back.
+
+use std::{
+    env,
+    fs::{self, File},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use arrow_array::{Array, Float64Array, RecordBatch, StringArray, UInt64Array};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use liquidity_depth_cli::{
+    columnar::{depth_schema, partition_dir, utc_date, ParquetSink},
+    report::{BlockReport, Conversions, DepthReport, PoolReport, PoolStatus, TradeAction},
+};
+
+fn block(block_number: u64, pair: &str) -> BlockReport {
+    let depth = DepthReport {
+        conversions: Conversions { base_tokens: Some("12.5".to_string()), quote_tokens: None, quote_usd_value: None },
+        target_slippage: 0.02,
+        action: TradeAction::Sell,
+        approximate: false,
+        base_amount: Some("12500000000000000000".to_string()),
+        quote_amount: None,
+        usd_value: None,
+        slippage: Some(0.0199),
+        fee_slippage: None,
+        impact_slippage: None,
+        converged: Some(true),
+        iterations: Some(14),
+        elapsed_ms: None,
+        pool_limited: None,
+        error: None,
+        simulation: None,
+    };
+    let pool = PoolReport {
+        pool_id: "0xaaa".to_string(),
+        protocol: "uniswap_v3".to_string(),
+        pair: pair.to_string(),
+        base: "WETH".to_string(),
+        quote: "USDC".to_string(),
+        status: PoolStatus::Ok,
+        base_usd_price: None,
+        fee: None,
+        spot_price: Some(3012.25),
+        reference_price: None,
+        excluded: None,
+        depths: vec![depth],
+        two_sided: Vec::new(),
+        fills: Vec::new(),
+        stale_since_block: None,
+        protocol_owned: false,
+    };
+    BlockReport { block_number, chain: "ethereum".to_string(), pools: vec![pool], ..Default::default() }
+}
+
+fn read(path: &PathBuf) -> Vec<RecordBatch> {
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap()).unwrap().build().unwrap();
+    reader.collect::<Result<Vec<RecordBatch>, _>>().unwrap()
+}
+
+#[test]
+fn utc_dates() {
+    assert_eq!(utc_date(UNIX_EPOCH), "1970-01-01");
+    assert_eq!(utc_date(UNIX_EPOCH + Duration::from_secs(951_782_400)), "2000-02-29");
+    assert_eq!(utc_date(UNIX_EPOCH + Duration::from_secs(1_791_935_999)), "2026-10-13");
+}
+
+#[test]
+fn writes_one_file_per_partition_with_the_stable_schema() {
+    let dir: PathBuf = env::temp_dir().join(format!("liquidity-depth-parquet-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let first_day: SystemTime = UNIX_EPOCH + Duration::from_secs(1_791_849_600);
+    let next_day: SystemTime = first_day + Duration::from_secs(86_400);
+
+    let mut sink: ParquetSink = ParquetSink::new(&dir, "ethereum");
+    sink.write_block(&block(100, "WETH/USDC"), first_day).unwrap();
+    sink.write_block(&block(100, "WBTC/USDC"), first_day).unwrap();
+    sink.write_block(&block(101, "WETH/USDC"), first_day).unwrap();
+    // A new day starts a new file in its own partition.
+    sink.write_block(&block(102, "WETH/USDC"), next_day).unwrap();
+    sink.close().unwrap();
+
+    let weth: PathBuf = partition_dir(&dir, "2026-10-13", "ethereum", "WETH/USDC").join("part-000000000100.parquet");
+    assert!(weth.ends_with("date=2026-10-13/chain=ethereum/pair=weth-usdc/part-000000000100.parquet"));
+    assert!(partition_dir(&dir, "2026-10-13", "ethereum", "WBTC/USDC").join("part-000000000100.parquet").is_file());
+    assert!(partition_dir(&dir, "2026-10-14", "ethereum", "WETH/USDC").join("part-000000000102.parquet").is_file());
+
+    let batches: Vec<RecordBatch> = read(&weth);
+    assert_eq!(batches[0].schema().as_ref(), &depth_schema());
+    let blocks: Vec<u64> = batches
+        .iter()
+        .flat_map(|batch| {
+            let column = batch.column_by_name("block_number").unwrap();
+            column.as_any().downcast_ref::<UInt64Array>().unwrap().values().to_vec()
+        })
+        .collect();
+    assert_eq!(blocks, vec![100, 101]);
+    let pools = batches[0].column_by_name("pool_id").unwrap();
+    assert_eq!(pools.as_any().downcast_ref::<StringArray>().unwrap().value(0), "0xaaa");
+    let tokens = batches[0].column_by_name("base_tokens").unwrap();
+    assert_eq!(tokens.as_any().downcast_ref::<Float64Array>().unwrap().value(0), 12.5);
+    assert!(batches[0].column_by_name("quote_tokens").unwrap().is_null(0));
+
+    let _ = fs::remove_dir_all(&dir);
+}
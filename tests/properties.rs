@@ -0,0 +1,90 @@
+//! Property-based tests for the search's invariants, over randomized `MockXykPool`s with every
+//! decimals combination `tests/decimals.rs` covers.
+
+use alloy_primitives::U256;
+use num_bigint::BigUint;
+use proptest::prelude::*;
+use tycho_simulation::models::Token;
+use liquidity_depth_cli::{
+    amount::one_token,
+    binary_search::{check_slippage_under_target, check_slippage_vs_target_within_tolerance},
+    mock::MockXykPool,
+    DepthCalculator, SearchResult,
+};
+
+const DECIMALS: [usize; 6] = [0, 2, 6, 8, 18, 24];
+const PRECISION: f64 = 1e-4;
+
+/// A pool and the tokens it trades, built from whole-token reserves so every decimals combination
+/// is sized alike.
+#[derive(Debug)]
+struct Case {
+    pool: MockXykPool,
+    token_in: Token,
+    token_out: Token,
+}
+
+fn case(decimals_in: usize, decimals_out: usize, reserve_in: u64, reserve_out: u64, fee_bps: u32) -> Case {
+    let token_in: Token = Token::new("0x0000000000000000000000000000000000000001", decimals_in, "IN", BigUint::from(0u32));
+    let token_out: Token = Token::new("0x0000000000000000000000000000000000000002", decimals_out, "OUT", BigUint::from(0u32));
+    let reserve_in: U256 = U256::from(reserve_in) * one_token(decimals_in).unwrap();
+    let reserve_out: U256 = U256::from(reserve_out) * one_token(decimals_out).unwrap();
+    let pool: MockXykPool = MockXykPool::new(&token_in, reserve_in, &token_out, reserve_out).with_fee_bps(fee_bps);
+    Case { pool, token_in, token_out }
+}
+
+/// Pools from a thousand to a billion tokens a side, any decimals, and a fee under every target.
+fn cases() -> impl Strategy<Value = Case> {
+    (
+        prop::sample::select(DECIMALS.to_vec()),
+        prop::sample::select(DECIMALS.to_vec()),
+        1_000u64..1_000_000_000,
+        1_000u64..1_000_000_000,
+        0u32..=50,
+    )
+        .prop_map(|(decimals_in, decimals_out, reserve_in, reserve_out, fee_bps)| {
+            case(decimals_in, decimals_out, reserve_in, reserve_out, fee_bps)
+        })
+}
+
+proptest! {
+    // Every case runs whole searches, so fewer than proptest's default 256 keeps this quick.
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    /// A converged depth is within the precision of its target; one that isn't converged is the
+    /// largest amount found under it.
+    #[test]
+    fn depth_is_within_tolerance(case in cases(), target in 0.01f64..0.5) {
+        let depth: SearchResult = DepthCalculator::from_provider(&case.pool, &case.token_in, &case.token_out)
+            .with_precision(PRECISION)
+            .depth_at(target)
+            .unwrap();
+        if depth.converged {
+            prop_assert!(check_slippage_vs_target_within_tolerance(&depth.slippage, target, PRECISION).unwrap(), "{:?}", depth);
+        } else {
+            prop_assert!(check_slippage_under_target(&depth.slippage, target), "{:?}", depth);
+        }
+    }
+
+    /// A looser target never finds less depth, as long as the two are further apart than the
+    /// tolerance either may land within.
+    #[test]
+    fn depth_is_monotone_in_the_target(case in cases(), target in 0.01f64..0.4, gap in 0.001f64..0.1) {
+        let depths: Vec<SearchResult> = DepthCalculator::from_provider(&case.pool, &case.token_in, &case.token_out)
+            .with_precision(PRECISION)
+            .depths_at(&[target + gap, target])
+            .unwrap();
+        prop_assert!(depths[0].amount_in >= depths[1].amount_in, "{:?} at {} vs {:?} at {}", depths[0], target + gap, depths[1], target);
+    }
+
+    /// No target takes more bisection steps than the cap.
+    #[test]
+    fn iterations_stay_under_the_cap(case in cases(), target in 0.01f64..0.5, max_iterations in 1u32..64) {
+        let depth: SearchResult = DepthCalculator::from_provider(&case.pool, &case.token_in, &case.token_out)
+            .with_precision(1e-12)
+            .with_max_iterations(max_iterations)
+            .depth_at(target)
+            .unwrap();
+        prop_assert!(depth.iterations <= max_iterations, "{} iterations against a cap of {}", depth.iterations, max_iterations);
+    }
+}
@@ -0,0 +1,132 @@
+//! The search over quotes that don't come from Tycho, through `AmountOutProvider`.
+
+use alloy_primitives::U256;
+use num_bigint::BigUint;
+use tycho_simulation::models::Token;
+use liquidity_depth_cli::{binary_search::PoolLimitPolicy, AmountOutProvider, DepthCalculator, DepthError, SearchResult};
+
+/// A fee-free constant-product pool, whose depth has a closed form: selling `dx` into reserves
+/// `(x, y)` slips by `dx / (x + dx)`, so depth at slippage `s` is `s * x / (1 - s)`.
+struct ConstantProduct {
+    reserve_in: BigUint,
+    reserve_out: BigUint,
+}
+
+impl AmountOutProvider for ConstantProduct {
+    fn amount_out(&self, amount_in: BigUint, _token_in: &Token, _token_out: &Token) -> Result<BigUint, DepthError> {
+        Ok(&self.reserve_out * &amount_in / (&self.reserve_in + &amount_in))
+    }
+
+    fn spot(&self, token_in: &Token, token_out: &Token) -> Result<f64, DepthError> {
+        let reserve_in: f64 = self.reserve_in.to_string().parse::<f64>().unwrap() / 10f64.powi(token_in.decimals as i32);
+        let reserve_out: f64 = self.reserve_out.to_string().parse::<f64>().unwrap() / 10f64.powi(token_out.decimals as i32);
+        Ok(reserve_out / reserve_in)
+    }
+}
+
+/// A `ConstantProduct` that won't quote sales above `max_in`, like a pool past its gas limit.
+struct Capped {
+    pool: ConstantProduct,
+    max_in: BigUint,
+}
+
+impl AmountOutProvider for Capped {
+    fn amount_out(&self, amount_in: BigUint, token_in: &Token, token_out: &Token) -> Result<BigUint, DepthError> {
+        if amount_in > self.max_in {
+            return Err(DepthError::Simulation("gas limit exceeded".to_string()));
+        }
+        self.pool.amount_out(amount_in, token_in, token_out)
+    }
+
+    fn spot(&self, token_in: &Token, token_out: &Token) -> Result<f64, DepthError> {
+        self.pool.spot(token_in, token_out)
+    }
+}
+
+fn tokens() -> (Token, Token) {
+    (
+        Token::new("0x0000000000000000000000000000000000000001", 18, "WETH", BigUint::from(0u32)),
+        Token::new("0x0000000000000000000000000000000000000002", 6, "USDC", BigUint::from(0u32)),
+    )
+}
+
+/// 1,000 WETH against 3,000,000 USDC.
+fn pool() -> ConstantProduct {
+    ConstantProduct {
+        reserve_in: BigUint::from(1_000u32) * BigUint::from(10u32).pow(18),
+        reserve_out: BigUint::from(3_000_000u32) * BigUint::from(10u32).pow(6),
+    }
+}
+
+#[test]
+fn depth_has_the_closed_form() {
+    let (weth, usdc) = tokens();
+    let pool: ConstantProduct = pool();
+    let calculator: DepthCalculator = DepthCalculator::from_provider(&pool, &weth, &usdc).with_precision(1e-9);
+    assert_eq!(calculator.reference_price().unwrap(), 3000.0);
+
+    for target in [0.001, 0.01, 0.05] {
+        let depth: SearchResult = calculator.depth_at(target).unwrap();
+        let expected: f64 = target * 1_000.0 / (1.0 - target);
+        let found: f64 = f64::from(depth.amount_in) / 1e18;
+        assert!((found - expected).abs() / expected < 1e-4, "{} WETH at {} vs {}", found, target, expected);
+        // Providers that don't report gas report zero.
+        assert_eq!(depth.gas, U256::ZERO);
+    }
+}
+
+#[test]
+fn every_query_runs_over_the_provider() {
+    let (weth, usdc) = tokens();
+    let pool: ConstantProduct = pool();
+    let calculator: DepthCalculator = DepthCalculator::from_provider(&pool, &weth, &usdc);
+
+    // Selling 10 WETH into 1,000 slips by 10/1010.
+    let fill: SearchResult = calculator.slippage_for(U256::from(10u64) * U256::from(10u64).pow(U256::from(18u64))).unwrap();
+    assert!((fill.slippage.to_f64() - 10.0 / 1010.0).abs() < 1e-9);
+
+    // The same pool quoted the other way round, from its own spot.
+    let reversed: ConstantProduct = ConstantProduct { reserve_in: pool.reserve_out.clone(), reserve_out: pool.reserve_in.clone() };
+    let reverse: SearchResult =
+        DepthCalculator::from_provider(&reversed, &usdc, &weth).with_precision(1e-9).depth_at(0.01).unwrap();
+    assert!((f64::from(reverse.amount_in) / 1e6 - 0.01 * 3_000_000.0 / 0.99).abs() < 1.0);
+
+    assert!(calculator.estimate_at(0.01).unwrap().amount_in > U256::ZERO);
+    assert!(calculator.input_for_output(U256::from(1_000_000_000u64), 0.05).unwrap().amount_out >= U256::from(1_000_000_000u64));
+}
+
+#[test]
+fn a_pool_that_stops_quoting_gives_a_lower_bound() {
+    let (weth, usdc) = tokens();
+    let five: U256 = U256::from(5u64) * U256::from(10u64).pow(U256::from(18u64));
+    let capped: Capped = Capped { pool: pool(), max_in: BigUint::from(5u32) * BigUint::from(10u32).pow(18) };
+    let depths: Vec<SearchResult> = DepthCalculator::from_provider(&capped, &weth, &usdc).depths_at(&[0.001, 0.05]).unwrap();
+
+    // Under the ceiling the search converges as usual.
+    assert_eq!(depths[0].limit, None);
+    assert!(PoolLimitPolicy::Fail.rejects(&depths[0]).is_none());
+
+    // Past it, depth is what the pool still quotes, flagged with why it stopped.
+    let limited: &SearchResult = &depths[1];
+    assert!(limited.limit.as_deref().unwrap().contains("gas limit exceeded"), "{:?}", limited.limit);
+    assert!(limited.amount_in <= five && limited.amount_in >= five - five / U256::from(500u64), "{}", limited.amount_in);
+    assert!(limited.slippage.to_f64() < 0.05);
+    assert!(PoolLimitPolicy::Report.rejects(limited).is_none());
+    assert!(matches!(PoolLimitPolicy::Fail.rejects(limited), Some(DepthError::PoolLimited { .. })));
+}
+
+#[test]
+fn hints_the_pool_refuses_fall_back_to_a_cold_search() {
+    let (weth, usdc) = tokens();
+    let one_weth: U256 = U256::from(10u64).pow(U256::from(18u64));
+    // Last block's depth, but the pool now stops quoting at 5 WETH.
+    let capped: Capped = Capped { pool: pool(), max_in: BigUint::from(5u32) * BigUint::from(10u32).pow(18) };
+    let calculator: DepthCalculator = DepthCalculator::from_provider(&capped, &weth, &usdc);
+    let hints: Vec<Option<U256>> = vec![Some(U256::from(10u64) * one_weth), Some(U256::from(50u64) * one_weth)];
+
+    let warm: Vec<SearchResult> = calculator.depths_from_hints(&[0.001, 0.05], &hints).unwrap();
+    let cold: Vec<SearchResult> = calculator.depths_at(&[0.001, 0.05]).unwrap();
+    assert!(warm[0].amount_in > U256::ZERO && warm[0].converged);
+    assert_eq!(warm[0].amount_in, cold[0].amount_in);
+    assert!(warm[1].limit.is_some());
+}
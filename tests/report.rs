@@ -0,0 +1,93 @@
+//! `report --from-sqlite`: a history written by the SQLite sink read back into the HTML report.
+
+use std::{env, fs, path::PathBuf, time::SystemTime};
+use liquidity_depth_cli::{
+    html::HtmlReport,
+    report::{AggregateReport, BlockReport, Conversions, DepthReport, PoolReport, PoolStatus, TradeAction},
+    sink::SqliteSink,
+};
+
+fn conversions(base_tokens: Option<&str>) -> Conversions {
+    Conversions { base_tokens: base_tokens.map(str::to_string), quote_tokens: None, quote_usd_value: None }
+}
+
+fn block(block_number: u64, base_tokens: Option<&str>) -> BlockReport {
+    let depth = DepthReport {
+        conversions: conversions(Some("12.5")),
+        target_slippage: 0.02,
+        action: TradeAction::Sell,
+        approximate: false,
+        base_amount: Some("12500000000000000000".to_string()),
+        quote_amount: None,
+        usd_value: None,
+        slippage: Some(0.0199),
+        fee_slippage: None,
+        impact_slippage: None,
+        converged: Some(true),
+        iterations: None,
+        elapsed_ms: None,
+        pool_limited: None,
+        error: None,
+        simulation: None,
+    };
+    let pool = PoolReport {
+        pool_id: "0xaaa".to_string(),
+        protocol: "uniswap_v3".to_string(),
+        pair: "WETH/USDC".to_string(),
+        base: "WETH".to_string(),
+        quote: "USDC".to_string(),
+        status: PoolStatus::Ok,
+        base_usd_price: None,
+        fee: None,
+        spot_price: Some(3012.25),
+        reference_price: None,
+        excluded: None,
+        depths: vec![depth],
+        two_sided: Vec::new(),
+        fills: Vec::new(),
+        stale_since_block: None,
+        protocol_owned: false,
+    };
+    let aggregate = AggregateReport {
+        pair: "WETH/USDC".to_string(),
+        target_slippage: 0.02,
+        action: TradeAction::Sell,
+        pools: 1,
+        base_amount: None,
+        quote_amount: None,
+        usd_value: Some(1_242_180.0),
+        conversions: conversions(base_tokens),
+        slippage: None,
+        fee_slippage: None,
+        impact_slippage: None,
+        error: None,
+        stability: None,
+        organic: None,
+        comparison: None,
+        by_protocol: Vec::new(),
+    };
+    BlockReport { block_number, pools: vec![pool], aggregates: vec![aggregate], ..Default::default() }
+}
+
+#[test]
+fn summarises_a_stored_history_per_pair() {
+    let path: PathBuf = env::temp_dir().join(format!("liquidity-depth-report-{}.db", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let mut sink: SqliteSink = SqliteSink::open(&path).unwrap();
+    for (block_number, base_tokens) in [(100, Some("412.5")), (101, None), (102, Some("1204.5"))] {
+        sink.write_block(&block(block_number, base_tokens), SystemTime::now()).unwrap();
+    }
+    drop(sink);
+
+    let html: String = HtmlReport::from_sqlite(&path, "Weekly depth").unwrap().render();
+    assert!(html.contains("<h1>Weekly depth</h1>"));
+    assert!(html.contains("blocks 100 to 102 · 1 pair"));
+    // Latest, worth, lowest, median, highest, and the failed block counted.
+    assert!(html.contains(
+        "<tr><td>2%</td><td>1,205</td><td>$1,242,180</td><td>412.5</td><td>808.5</td><td>1,205</td><td>3 (1 failed)</td></tr>"
+    ));
+    assert!(html.contains("<h3>Pools at block 102</h3>"));
+    assert!(html.contains("<td><code>0xaaa</code></td><td>uniswap_v3</td><td>3012.250000</td><td>12.5</td>"));
+
+    let _ = fs::remove_file(&path);
+}
@@ -0,0 +1,110 @@
+//! Slippage is execution price against spot in whole tokens, so it must come out the same whatever
+//! the decimals of the pair.
+
+use alloy_primitives::U256;
+use liquidity_depth_cli::{
+    binary_search::{calc_slippage, check_slippage_under_target, check_slippage_vs_target_within_tolerance, Slippage},
+    error::DepthError,
+};
+
+/// A function to turn a whole-token amount into base units, e.g. 2970.5 USDC into 2970500000.
+fn units(amount: f64, decimals: usize) -> U256 {
+    U256::from((amount * 10f64.powi(decimals as i32)).round() as u128)
+}
+
+fn assert_slippage(slippage: &Slippage, expected: f64) {
+    assert!(
+        check_slippage_vs_target_within_tolerance(slippage, expected, 1e-9).unwrap(),
+        "expected {} got {}",
+        expected,
+        slippage.to_f64()
+    );
+}
+
+#[test]
+fn six_and_eighteen_decimals() {
+    // Sell 1 WETH for 2,970 USDC against a spot of 3,000 USDC per WETH.
+    let slippage: Slippage = calc_slippage(units(1.0, 18), units(2970.0, 6), 18, 6, 3000.0).unwrap();
+    assert_slippage(&slippage, 0.01);
+
+    // Sell 3,000 USDC for 0.98 WETH against the inverse spot.
+    let slippage: Slippage = calc_slippage(units(3000.0, 6), units(0.98, 18), 6, 18, 1.0 / 3000.0).unwrap();
+    assert_slippage(&slippage, 0.02);
+}
+
+#[test]
+fn eight_and_eighteen_decimals() {
+    // Sell 2 WBTC for 39.6 WETH against a spot of 20 WETH per WBTC.
+    let slippage: Slippage = calc_slippage(units(2.0, 8), units(39.6, 18), 8, 18, 20.0).unwrap();
+    assert_slippage(&slippage, 0.01);
+
+    // Sell 100 WETH for 4.95 WBTC against a spot of 0.05 WBTC per WETH.
+    let slippage: Slippage = calc_slippage(units(100.0, 18), units(4.95, 8), 18, 8, 0.05).unwrap();
+    assert_slippage(&slippage, 0.01);
+}
+
+#[test]
+fn same_fill_same_slippage_whatever_the_decimals() {
+    // The same 0.5% fill, written with every combination of decimals.
+    for (decimals_in, decimals_out) in [(6, 18), (18, 6), (8, 18), (18, 8), (6, 8), (18, 18)] {
+        let slippage: Slippage =
+            calc_slippage(units(10.0, decimals_in), units(248.75, decimals_out), decimals_in, decimals_out, 25.0)
+                .unwrap();
+        assert_slippage(&slippage, 0.005);
+    }
+}
+
+#[test]
+fn improvement_and_empty_fills() {
+    // Better than spot is negative slippage and under any target, nothing back is all of it.
+    let better: Slippage = calc_slippage(units(1.0, 18), units(3030.0, 6), 18, 6, 3000.0).unwrap();
+    assert!(better.is_improvement());
+    assert!((better.to_f64() + 0.01).abs() < 1e-12);
+    // 2% away from a 1% target, not the 0% that dropping the sign would give.
+    assert!(!check_slippage_vs_target_within_tolerance(&better, 0.01, 0.015).unwrap());
+    assert!(check_slippage_vs_target_within_tolerance(&better, 0.01, 0.02).unwrap());
+    assert!(check_slippage_under_target(&better, 0.0));
+    let empty: Slippage = calc_slippage(units(1.0, 18), U256::ZERO, 18, 6, 3000.0).unwrap();
+    assert_eq!(empty.to_f64(), 1.0);
+}
+
+#[test]
+fn invalid_spot_price() {
+    for spot_price in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+        assert!(matches!(
+            calc_slippage(units(1.0, 18), units(1.0, 6), 18, 6, spot_price),
+            Err(DepthError::InvalidSpotPrice(_))
+        ));
+    }
+}
+
+#[test]
+fn large_amounts_dont_overflow() {
+    // A trillion trillion WETH at 18 decimals for 1% less than spot in USDC: the value at spot in
+    // the common scale is past 256 bits.
+    let amount_in: U256 = U256::from(10u64).pow(U256::from(60u64));
+    let amount_out: U256 = U256::from(2970u64) * U256::from(10u64).pow(U256::from(48u64));
+    let slippage: Slippage = calc_slippage(amount_in, amount_out, 18, 6, 3000.0).unwrap();
+    assert_slippage(&slippage, 0.01);
+
+    // A slippage whose denominator uses all 256 bits.
+    let slippage: Slippage = Slippage::new(U256::MAX / U256::from(50u64), U256::MAX);
+    assert_slippage(&slippage, 0.02);
+    assert!(check_slippage_under_target(&slippage, 0.021));
+}
+
+#[test]
+fn targets_are_exact() {
+    // 1.23456789% and a slippage a billionth of a percent either side of it, which rounding the
+    // target to six decimal places would put on the same side.
+    let target: f64 = 0.0123456789;
+    let exact: Slippage = Slippage::new(U256::from(123456789u64), U256::from(10_000_000_000u64));
+    let over: Slippage = Slippage::new(U256::from(1234567891u64), U256::from(100_000_000_000u64));
+    let under: Slippage = Slippage::new(U256::from(1234567889u64), U256::from(100_000_000_000u64));
+    assert_eq!(Slippage::try_from(target).unwrap(), exact);
+    assert!(check_slippage_under_target(&exact, target));
+    assert!(check_slippage_under_target(&under, target));
+    assert!(!check_slippage_under_target(&over, target));
+    assert!(check_slippage_vs_target_within_tolerance(&over, target, 1e-11).unwrap());
+    assert!(!check_slippage_vs_target_within_tolerance(&over, target, 1e-12).unwrap());
+}
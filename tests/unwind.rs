@@ -0,0 +1,28 @@
+//! The unwind scenario: depth after a notional of liquidity is withdrawn across a pair's pools.
+
+use alloy_primitives::U256;
+use num_bigint::BigUint;
+use tycho_simulation::{
+    evm::protocol::uniswap_v2::state::UniswapV2State,
+    models::Token,
+    protocol::state::ProtocolSim,
+};
+use liquidity_depth_cli::{binary_search::DEFAULT_PRECISION, unwind::estimate_unwind, DepthError};
+
+#[test]
+fn rejects_notionals_that_are_not_a_finite_amount() {
+    let weth: Token = Token::new("0x0000000000000000000000000000000000000001", 18, "WETH", BigUint::from(0u32));
+    let usdc: Token = Token::new("0x0000000000000000000000000000000000000002", 6, "USDC", BigUint::from(0u32));
+    let state: UniswapV2State = UniswapV2State::new(
+        U256::from(1_000u64) * U256::from(10u64).pow(U256::from(18u64)),
+        U256::from(3_000_000u64) * U256::from(10u64).pow(U256::from(6u64)),
+    );
+    let pools: Vec<(String, &dyn ProtocolSim)> = vec![("0xaaa".to_string(), &state)];
+
+    for notional in [f64::NAN, f64::INFINITY, -1.0] {
+        let result = estimate_unwind(&pools, &weth, &usdc, notional, &[0.01], DEFAULT_PRECISION);
+        assert!(matches!(result, Err(DepthError::InvalidNotional(_))), "{} was accepted", notional);
+    }
+    let zero = estimate_unwind(&pools, &weth, &usdc, 0.0, &[0.01], DEFAULT_PRECISION).unwrap();
+    assert_eq!(zero.fraction_removed, 0.0);
+}
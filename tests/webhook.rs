@@ -0,0 +1,41 @@
+//! `[[notifiers]]` entries: each chat service gets the body it expects, and an entry that can't
+//! post is refused before the run starts.
+
+use liquidity_depth_cli::webhook::{WebhookConfig, WebhookKind, WebhookNotifier};
+
+fn config(kind: &str, chat_id: Option<&str>) -> WebhookConfig {
+    let mut entry = serde_json::json!({ "kind": kind, "url": "https://hooks.example.com/T000/B000" });
+    if let Some(chat_id) = chat_id {
+        entry["chat_id"] = serde_json::json!(chat_id);
+    }
+    serde_json::from_value(entry).unwrap()
+}
+
+#[test]
+fn builds_each_services_payload() {
+    let slack = WebhookNotifier::new(config("slack", None)).unwrap();
+    assert_eq!(slack.payload("depth low"), serde_json::json!({ "text": "depth low" }));
+
+    let telegram = WebhookNotifier::new(config("telegram", Some("-100123"))).unwrap();
+    assert_eq!(telegram.payload("depth low"), serde_json::json!({ "chat_id": "-100123", "text": "depth low" }));
+
+    // Discord refuses anything over 2000 characters, so long summaries are cut.
+    let discord = WebhookNotifier::new(config("discord", None)).unwrap();
+    let long: String = "x".repeat(2500);
+    assert_eq!(discord.payload(&long)["content"].as_str().unwrap().len(), 2000);
+}
+
+#[test]
+fn refuses_entries_that_cannot_post() {
+    assert_eq!(config("discord", None).kind, WebhookKind::Discord);
+    assert!(config("telegram", None).validate().is_err());
+    assert!(config("slack", Some("-100123")).validate().is_err());
+
+    let mut every_zero: WebhookConfig = config("slack", None);
+    every_zero.summary_every = Some(0);
+    assert!(every_zero.validate().is_err());
+
+    let mut no_scheme: WebhookConfig = config("slack", None);
+    no_scheme.url = "hooks.example.com".to_string();
+    assert!(WebhookNotifier::new(no_scheme).is_err());
+}